@@ -0,0 +1,88 @@
+/* C interface to the t1 navmesh baker and path queries, built from
+ * src/ffi.rs with the `cdylib` feature. Keep the two in step. */
+#ifndef T1_NAV_H
+#define T1_NAV_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef enum NavStatus {
+    NAV_OK = 0,
+    /* The path leads to the polygon nearest the unreachable end */
+    NAV_PARTIAL_PATH = 1,
+    NAV_NULL_ARGUMENT = -1,
+    NAV_INVALID_PATH = -2,
+    NAV_IO = -3,
+    NAV_UNSUPPORTED_FORMAT = -4,
+    NAV_INVALID_MESH = -5,
+    NAV_INVALID_CONFIG = -6,
+    NAV_BUILD_FAILED = -7,
+    /* The bake produced no polygons */
+    NAV_EMPTY_NAVMESH = -8,
+    /* No polygon within the query box */
+    NAV_NOT_FOUND = -9,
+    /* More path points than the buffer holds; it has the first ones */
+    NAV_BUFFER_TOO_SMALL = -10,
+    NAV_PANIC = -11,
+    /* A value the query can't use, like a negative or non-finite extent */
+    NAV_INVALID_ARGUMENT = -12
+} NavStatus;
+
+/* World units unless noted */
+typedef struct NavBakeConfig {
+    float cell_size;
+    float cell_height;
+    float agent_height;
+    float agent_max_climb;
+    float agent_radius;
+    float agent_max_slope;      /* degrees */
+    int32_t min_region_area;    /* cells */
+    int32_t merge_region_area;  /* cells */
+    float edge_max_len;
+    float edge_max_error;       /* voxels */
+    int32_t verts_per_poly;
+    float detail_sample_dist;
+    float detail_sample_max_error;
+} NavBakeConfig;
+
+typedef struct NavGeometry NavGeometry;
+typedef struct NavMesh NavMesh;
+
+NavStatus nav_bake_config_default(NavBakeConfig* out);
+
+/* OBJ, glTF, STL or PLY */
+NavStatus nav_geometry_load(const char* path, NavGeometry** out);
+/* vert_count xyz positions, tri_count triangles of three indices */
+NavStatus nav_geometry_from_triangles(const float* verts, int32_t vert_count,
+                                      const int32_t* tris, int32_t tri_count,
+                                      NavGeometry** out);
+void nav_geometry_free(NavGeometry* geometry);
+
+/* Single-tile navmesh */
+NavStatus nav_bake(const NavGeometry* geometry, const NavBakeConfig* config,
+                   NavMesh** out);
+
+/* Recast demo navmesh set with 32-bit references, loadable by Detour */
+NavStatus nav_mesh_save(const NavMesh* mesh, const char* path);
+NavStatus nav_mesh_load(const char* path, NavMesh** out);
+NavStatus nav_mesh_poly_count(const NavMesh* mesh, int32_t* out);
+void nav_mesh_free(NavMesh* mesh);
+
+/* pos, half_extents and out_pos are xyz triples */
+NavStatus nav_find_nearest_point(const NavMesh* mesh, const float* pos,
+                                 const float* half_extents, float* out_pos);
+/* Straight path corners: up to max_points xyz triples in out_points and
+ * their DT_STRAIGHTPATH_* flags in out_flags unless it is NULL */
+NavStatus nav_find_path(const NavMesh* mesh, const float* start,
+                        const float* end, const float* half_extents,
+                        float* out_points, uint8_t* out_flags,
+                        int32_t max_points, int32_t* out_count);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
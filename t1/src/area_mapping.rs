@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::debug_draw::RC_WALKABLE_AREA;
+
+// Area ids assigned to OBJ faces by group, object or material name, e.g.
+// every face in group "river" or using material "water" gets area 3.
+//
+// The text form has one `name = area` entry per line; `#` starts a comment:
+//
+//     # swimmable
+//     water = 3
+//     lava pit = 0
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AreaMapping {
+    areas: HashMap<String, u8>,
+}
+
+impl AreaMapping {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.areas.is_empty()
+    }
+
+    pub fn insert(&mut self, name: impl Into<String>, area: u8) {
+        self.areas.insert(name.into(), area);
+    }
+
+    pub fn get(&self, name: &str) -> Option<u8> {
+        self.areas.get(name).copied()
+    }
+
+    // Area for a face with the given names, checked from most to least
+    // specific: group, then object, then material
+    pub fn area_for(
+        &self,
+        group: Option<&str>,
+        object: Option<&str>,
+        material: Option<&str>,
+    ) -> Option<u8> {
+        [group, object, material]
+            .into_iter()
+            .flatten()
+            .find_map(|name| self.get(name))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut mapping = Self::new();
+        for (line_no, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |message: &str| format!("line {}: {}", line_no + 1, message);
+            let (name, area) = line
+                .rsplit_once('=')
+                .ok_or_else(|| error("expected `name = area`"))?;
+            let name = name.trim();
+            if name.is_empty() {
+                return Err(error("missing name"));
+            }
+            let area: u8 = area
+                .trim()
+                .parse()
+                .map_err(|_| error("area must be a number"))?;
+            if area > RC_WALKABLE_AREA {
+                return Err(error("area must be at most 63"));
+            }
+            mapping.insert(name, area);
+        }
+        Ok(mapping)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        Self::parse(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_lookup_order() {
+        let mapping =
+            AreaMapping::parse("# water types\nwater = 3\n\nriver bank = 5  # shallow\nlevel=1\n")
+                .unwrap();
+        assert_eq!(mapping.get("river bank"), Some(5));
+        assert_eq!(
+            mapping.area_for(Some("river bank"), Some("level"), Some("water")),
+            Some(5)
+        );
+        assert_eq!(
+            mapping.area_for(Some("unmapped"), None, Some("water")),
+            Some(3)
+        );
+        assert_eq!(mapping.area_for(None, None, Some("stone")), None);
+
+        assert!(AreaMapping::parse("water 3").is_err());
+        assert!(AreaMapping::parse("water = 64").is_err());
+        assert!(AreaMapping::parse("= 3").is_err());
+    }
+
+    #[test]
+    fn test_obj_faces_get_mapped_areas() {
+        use crate::debug_draw_b::obj_to_input_mesh;
+        use crate::obj_loader::load_obj;
+
+        let file = tempfile::NamedTempFile::new().unwrap();
+        fs::write(
+            file.path(),
+            "v 0 0 0\nv 1 0 0\nv 0 0 1\nv 1 0 1\n\
+             f 1 3 2\ng river\nf 2 3 4\ng\nusemtl water\nf 1 3 4\n",
+        )
+        .unwrap();
+        let obj = load_obj(file.path()).unwrap();
+
+        let mut mapping = AreaMapping::new();
+        mapping.insert("river", 5);
+        mapping.insert("water", 3);
+        let mesh = obj_to_input_mesh(&obj, Some(&mapping)).unwrap();
+        assert_eq!(mesh.areas, vec![RC_WALKABLE_AREA, 5, 3]);
+        assert!(obj_to_input_mesh(&obj, None).unwrap().areas.is_empty());
+    }
+}
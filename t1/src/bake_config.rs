@@ -0,0 +1,306 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::json::{number, Json, JsonParser};
+use crate::navmesh_build::{BuildConfigError, NavMeshBuildConfig, MAX_VERTS_PER_POLY};
+use crate::regions::PartitionType;
+
+// Voxelization and agent parameters for navmesh baking, in world units
+// unless noted otherwise
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BakeConfig {
+    // Horizontal voxel size
+    pub cell_size: f32,
+    // Vertical voxel size
+    pub cell_height: f32,
+    // Minimum floor-to-ceiling clearance for an agent
+    pub walkable_height: f32,
+    // Maximum ledge height an agent can step over
+    pub walkable_climb: f32,
+    // How far the navmesh keeps from walls
+    pub agent_radius: f32,
+    // Steepest walkable slope, in degrees
+    pub walkable_slope_angle: f32,
+    // Regions with fewer cells than this are discarded
+    pub min_region_area: u32,
+    // How far, in voxels, simplified region outlines may stray from the
+    // voxel edges
+    pub edge_max_error: f32,
+    // Region partitioning algorithm
+    pub partition: PartitionType,
+    // Most vertices per navmesh polygon, 3 to MAX_VERTS_PER_POLY
+    pub verts_per_poly: usize,
+}
+
+impl Default for BakeConfig {
+    fn default() -> Self {
+        Self {
+            cell_size: 0.3,
+            cell_height: 0.2,
+            walkable_height: 2.0,
+            walkable_climb: 0.9,
+            agent_radius: 0.6,
+            walkable_slope_angle: 45.0,
+            min_region_area: 8,
+            edge_max_error: 1.3,
+            partition: PartitionType::Watershed,
+            verts_per_poly: MAX_VERTS_PER_POLY,
+        }
+    }
+}
+
+impl BakeConfig {
+    // Location of the persisted config, next to the executable
+    pub fn default_path() -> Option<PathBuf> {
+        std::env::current_exe()
+            .ok()
+            .map(|exe| exe.with_file_name("bake_config.json"))
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut text = String::new();
+        self.to_json_value().write(&mut text, 0);
+        text.push('\n');
+        text
+    }
+
+    pub(crate) fn to_json_value(&self) -> Json {
+        Json::Object(vec![
+            ("cell_size".into(), number(self.cell_size)),
+            ("cell_height".into(), number(self.cell_height)),
+            ("walkable_height".into(), number(self.walkable_height)),
+            ("walkable_climb".into(), number(self.walkable_climb)),
+            ("agent_radius".into(), number(self.agent_radius)),
+            (
+                "walkable_slope_angle".into(),
+                number(self.walkable_slope_angle),
+            ),
+            (
+                "min_region_area".into(),
+                Json::Number(self.min_region_area as f64),
+            ),
+            ("edge_max_error".into(), number(self.edge_max_error)),
+            (
+                "partition".into(),
+                Json::String(self.partition.name().into()),
+            ),
+            (
+                "verts_per_poly".into(),
+                Json::Number(self.verts_per_poly as f64),
+            ),
+        ])
+    }
+
+    // Unknown keys are ignored and missing ones keep their default value
+    pub fn from_json(text: &str) -> Result<Self, String> {
+        Self::from_json_value(&JsonParser::parse(text)?)
+    }
+
+    pub(crate) fn from_json_value(json: &Json) -> Result<Self, String> {
+        if !matches!(json, Json::Object(_)) {
+            return Err("expected a JSON object".into());
+        }
+        let mut config = Self::default();
+        let number = |key: &str| json.get(key).map(|v| v.as_f32(key)).transpose();
+        if let Some(v) = number("cell_size")? {
+            config.cell_size = v;
+        }
+        if let Some(v) = number("cell_height")? {
+            config.cell_height = v;
+        }
+        if let Some(v) = number("walkable_height")? {
+            config.walkable_height = v;
+        }
+        if let Some(v) = number("walkable_climb")? {
+            config.walkable_climb = v;
+        }
+        if let Some(v) = number("agent_radius")? {
+            config.agent_radius = v;
+        }
+        if let Some(v) = number("walkable_slope_angle")? {
+            config.walkable_slope_angle = v;
+        }
+        let whole = |key: &str| match json.get(key) {
+            None => Ok(None),
+            Some(Json::Number(n)) if n.fract() == 0.0 && *n >= 0.0 && *n <= u32::MAX as f64 => {
+                Ok(Some(*n as u32))
+            }
+            Some(_) => Err(format!("{} must be a whole number", key)),
+        };
+        if let Some(v) = whole("min_region_area")? {
+            config.min_region_area = v;
+        }
+        if let Some(v) = whole("verts_per_poly")? {
+            config.verts_per_poly = v as usize;
+        }
+        if let Some(v) = number("edge_max_error")? {
+            config.edge_max_error = v;
+        }
+        if let Some(v) = json.get("partition") {
+            let name = v.as_str("partition")?;
+            config.partition = PartitionType::from_name(name)
+                .ok_or_else(|| format!("unknown partition type {}", name))?;
+        }
+        Ok(config)
+    }
+
+    // Parse flat TOML: one `key = value` per line, `#` comments, quoted
+    // strings. Tables and arrays aren't supported. Keys are handled as in
+    // `from_json`.
+    pub fn from_toml(text: &str) -> Result<Self, String> {
+        let mut config = Self::default();
+        for (line_no, line) in text.lines().enumerate() {
+            let line = strip_toml_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("line {}: expected `key = value`", line_no + 1))?;
+            let key = key.trim().trim_matches('"');
+            let value = value.trim();
+            let number = |v: &str| {
+                v.parse::<f32>()
+                    .map_err(|e| format!("invalid value for {}: {}", key, e))
+            };
+
+            match key {
+                "cell_size" => config.cell_size = number(value)?,
+                "cell_height" => config.cell_height = number(value)?,
+                "walkable_height" => config.walkable_height = number(value)?,
+                "walkable_climb" => config.walkable_climb = number(value)?,
+                "agent_radius" => config.agent_radius = number(value)?,
+                "walkable_slope_angle" => config.walkable_slope_angle = number(value)?,
+                "min_region_area" => {
+                    config.min_region_area = value
+                        .parse()
+                        .map_err(|e| format!("invalid value for {}: {}", key, e))?
+                }
+                "verts_per_poly" => {
+                    config.verts_per_poly = value
+                        .parse()
+                        .map_err(|e| format!("invalid value for {}: {}", key, e))?
+                }
+                "edge_max_error" => config.edge_max_error = number(value)?,
+                "partition" => {
+                    let name = value.trim_matches('"');
+                    config.partition = PartitionType::from_name(name)
+                        .ok_or_else(|| format!("unknown partition type {}", name))?
+                }
+                _ => {}
+            }
+        }
+        Ok(config)
+    }
+
+    // Pipeline settings for these values; the rest keep their defaults
+    pub fn to_build_config(&self) -> Result<NavMeshBuildConfig, BuildConfigError> {
+        NavMeshBuildConfig::builder()
+            .cell_size(self.cell_size)
+            .cell_height(self.cell_height)
+            .agent_height(self.walkable_height)
+            .agent_radius(self.agent_radius)
+            .agent_max_climb(self.walkable_climb)
+            .agent_max_slope(self.walkable_slope_angle)
+            .min_region_area(self.min_region_area as i32)
+            .edge_max_error(self.edge_max_error)
+            .partition(self.partition)
+            .verts_per_poly(self.verts_per_poly)
+            .build()
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_json())
+    }
+
+    // Read JSON, or TOML when the file has a .toml extension
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        let is_toml = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("toml"));
+        let parsed = if is_toml {
+            Self::from_toml(&text)
+        } else {
+            Self::from_json(&text)
+        };
+        parsed.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+// Drop a `#` comment, unless the `#` is inside a quoted string
+fn strip_toml_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_round_trip() {
+        let config = BakeConfig {
+            cell_size: 0.25,
+            cell_height: 0.1,
+            walkable_height: 1.8,
+            walkable_climb: 0.5,
+            agent_radius: 0.4,
+            walkable_slope_angle: 50.0,
+            min_region_area: 12,
+            edge_max_error: 2.5,
+            partition: PartitionType::Monotone,
+            verts_per_poly: 4,
+        };
+
+        let parsed = BakeConfig::from_json(&config.to_json()).unwrap();
+        assert_eq!(parsed, config);
+
+        // Missing keys fall back to defaults
+        let partial = BakeConfig::from_json("{\"cell_size\": 0.5}").unwrap();
+        assert_eq!(partial.cell_size, 0.5);
+        assert_eq!(partial.cell_height, BakeConfig::default().cell_height);
+
+        assert!(BakeConfig::from_json("[1, 2]").is_err());
+        assert!(BakeConfig::from_json("{\"partition\": \"layers\"}").is_err());
+        // Commas inside strings and nested values don't split entries
+        assert!(BakeConfig::from_json("{\"note\": \"a, b\", \"x\": [1, 2]}").is_ok());
+        assert!(BakeConfig::from_json("{\"cell_size\": 0.5").is_err());
+        assert!(BakeConfig::from_json("{\"min_region_area\": 1.5}").is_err());
+    }
+
+    #[test]
+    fn test_from_toml() {
+        let config = BakeConfig::from_toml(
+            "# agent\nwalkable_height = 1.8\nwalkable_climb = 0.5 # stairs\n\n\
+             min_region_area = 12\nedge_max_error = 0.8\npartition = \"monotone\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.walkable_height, 1.8);
+        assert_eq!(config.walkable_climb, 0.5);
+        assert_eq!(config.min_region_area, 12);
+        assert_eq!(config.edge_max_error, 0.8);
+        assert_eq!(config.partition, PartitionType::Monotone);
+        assert_eq!(config.cell_size, BakeConfig::default().cell_size);
+        assert_eq!(
+            BakeConfig::from_toml("verts_per_poly = 3\nagent_radius = 0.25")
+                .unwrap()
+                .to_build_config()
+                .map(|cfg| (cfg.verts_per_poly, cfg.agent_radius)),
+            Ok((3, 0.25))
+        );
+
+        assert!(BakeConfig::from_toml("cell_size 0.3").is_err());
+        assert!(BakeConfig::from_toml("cell_size = big").is_err());
+    }
+}
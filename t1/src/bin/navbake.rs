@@ -0,0 +1,10 @@
+// Bake a navmesh without a window: navbake <input> <config> <output>
+use t1::headless;
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(err) = headless::run_navbake_cli(&args) {
+        eprintln!("navbake failed: {}", err);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,248 @@
+use std::fmt::Write;
+use std::mem::size_of;
+use std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+// std's Instant panics in the browser
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
+
+use crate::compact_heightfield::CompactHeightfield;
+use crate::contours::ContourSet;
+use crate::debug_draw::{PolyMesh, PolyMeshDetail};
+use crate::heightfield::Heightfield;
+
+// Steps of the bake pipeline that get their own timer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildStage {
+    Rasterize,
+    // Span filters, compaction and erosion by the agent radius
+    Filter,
+    Regions,
+    Contours,
+    PolyMesh,
+    Detail,
+}
+
+impl BuildStage {
+    pub const ALL: [BuildStage; 6] = [
+        BuildStage::Rasterize,
+        BuildStage::Filter,
+        BuildStage::Regions,
+        BuildStage::Contours,
+        BuildStage::PolyMesh,
+        BuildStage::Detail,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            BuildStage::Rasterize => "rasterize",
+            BuildStage::Filter => "filter",
+            BuildStage::Regions => "regions",
+            BuildStage::Contours => "contours",
+            BuildStage::PolyMesh => "polymesh",
+            BuildStage::Detail => "detail",
+        }
+    }
+}
+
+// Time spent in each pipeline stage and the size of the largest data each
+// one produced, like the timers of Recast's rcContext, plus the warnings the
+// stages logged. A tiled build adds up the times of all tiles, so with the
+// rayon feature they are CPU time rather than wall time.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BuildContext {
+    times: [Duration; BuildStage::ALL.len()],
+    peak_bytes: [usize; BuildStage::ALL.len()],
+    warnings: Vec<String>,
+}
+
+impl BuildContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Run `f`, adding its duration to `stage`. `f` gets the context back to
+    // log warnings with.
+    pub fn time<T>(&mut self, stage: BuildStage, f: impl FnOnce(&mut Self) -> T) -> T {
+        let start = Instant::now();
+        let result = f(self);
+        self.times[stage as usize] += start.elapsed();
+        result
+    }
+
+    // Note that `stage` had `bytes` of output, keeping the largest seen
+    pub fn record_memory(&mut self, stage: BuildStage, bytes: usize) {
+        let peak = &mut self.peak_bytes[stage as usize];
+        *peak = (*peak).max(bytes);
+    }
+
+    // Note a problem a stage worked around, like Recast's RC_LOG_WARNING
+    pub fn warn(&mut self, message: String) {
+        self.warnings.push(message);
+    }
+
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    pub fn stage_time(&self, stage: BuildStage) -> Duration {
+        self.times[stage as usize]
+    }
+
+    pub fn peak_memory(&self, stage: BuildStage) -> usize {
+        self.peak_bytes[stage as usize]
+    }
+
+    pub fn total_time(&self) -> Duration {
+        self.times.iter().sum()
+    }
+
+    // Largest output of any stage
+    pub fn peak_memory_overall(&self) -> usize {
+        self.peak_bytes.iter().copied().max().unwrap_or(0)
+    }
+
+    // Fold in the timings of another build, e.g. one tile of a tiled build
+    pub fn merge(&mut self, other: &BuildContext) {
+        for stage in BuildStage::ALL {
+            self.times[stage as usize] += other.stage_time(stage);
+            self.record_memory(stage, other.peak_memory(stage));
+        }
+        self.warnings.extend_from_slice(&other.warnings);
+    }
+
+    // One line per stage, then the total and any warnings
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for stage in BuildStage::ALL {
+            let _ = writeln!(
+                text,
+                "  {:<10} {:>9.2} ms {:>10}",
+                stage.name(),
+                self.stage_time(stage).as_secs_f64() * 1000.0,
+                format_bytes(self.peak_memory(stage))
+            );
+        }
+        let _ = writeln!(
+            text,
+            "  {:<10} {:>9.2} ms {:>10}",
+            "total",
+            self.total_time().as_secs_f64() * 1000.0,
+            format_bytes(self.peak_memory_overall())
+        );
+        for warning in &self.warnings {
+            let _ = writeln!(text, "  warning: {}", warning);
+        }
+        text
+    }
+}
+
+// `bytes` in the largest unit that keeps the number at least 1
+pub fn format_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+// Approximate heap size of pipeline data, from the capacity of its buffers
+pub trait MemoryUsage {
+    fn memory_bytes(&self) -> usize;
+}
+
+fn vec_bytes<T>(v: &Vec<T>) -> usize {
+    v.capacity() * size_of::<T>()
+}
+
+impl MemoryUsage for Heightfield {
+    fn memory_bytes(&self) -> usize {
+        vec_bytes(&self.spans) + self.spans.iter().map(vec_bytes).sum::<usize>()
+    }
+}
+
+impl MemoryUsage for CompactHeightfield {
+    fn memory_bytes(&self) -> usize {
+        vec_bytes(&self.cells)
+            + vec_bytes(&self.spans)
+            + vec_bytes(&self.dist)
+            + vec_bytes(&self.areas)
+    }
+}
+
+impl MemoryUsage for ContourSet {
+    fn memory_bytes(&self) -> usize {
+        vec_bytes(&self.contours)
+            + self
+                .contours
+                .iter()
+                .map(|c| vec_bytes(&c.verts) + vec_bytes(&c.rverts))
+                .sum::<usize>()
+    }
+}
+
+impl MemoryUsage for PolyMesh {
+    fn memory_bytes(&self) -> usize {
+        vec_bytes(&self.verts)
+            + vec_bytes(&self.polys)
+            + self.polys.iter().map(vec_bytes).sum::<usize>()
+            + vec_bytes(&self.neighbours)
+            + self.neighbours.iter().map(vec_bytes).sum::<usize>()
+            + vec_bytes(&self.regs)
+            + vec_bytes(&self.flags)
+            + vec_bytes(&self.areas)
+    }
+}
+
+impl MemoryUsage for PolyMeshDetail {
+    fn memory_bytes(&self) -> usize {
+        vec_bytes(&self.meshes) + vec_bytes(&self.verts) + vec_bytes(&self.tris)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timers_and_merge() {
+        let mut ctx = BuildContext::new();
+        let value = ctx.time(BuildStage::Regions, |_| {
+            std::thread::sleep(Duration::from_millis(2));
+            7
+        });
+        assert_eq!(value, 7);
+        assert!(ctx.stage_time(BuildStage::Regions) >= Duration::from_millis(2));
+        assert_eq!(ctx.stage_time(BuildStage::Detail), Duration::ZERO);
+
+        ctx.record_memory(BuildStage::Contours, 100);
+        ctx.record_memory(BuildStage::Contours, 50);
+        let mut other = BuildContext::new();
+        other.record_memory(BuildStage::Contours, 80);
+        other.record_memory(BuildStage::Detail, 300);
+        other.time(BuildStage::Regions, |_| {});
+        ctx.merge(&other);
+        assert_eq!(ctx.peak_memory(BuildStage::Contours), 100);
+        assert_eq!(ctx.peak_memory_overall(), 300);
+        assert!(ctx.total_time() >= ctx.stage_time(BuildStage::Regions));
+
+        assert_eq!(ctx.to_text().lines().count(), BuildStage::ALL.len() + 1);
+        other.warn("bad outline".to_string());
+        ctx.merge(&other);
+        assert_eq!(ctx.warnings(), ["bad outline"]);
+        assert!(ctx.to_text().ends_with(
+            "  warning: bad outline
+"
+        ));
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(3 << 20), "3.0 MiB");
+    }
+}
@@ -0,0 +1,189 @@
+#[cfg(not(target_arch = "wasm32"))]
+use std::panic;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+#[cfg(not(target_arch = "wasm32"))]
+use std::thread::{self, JoinHandle};
+
+use crate::navmesh_build::{BuildProgress, NavMeshBuildError};
+
+// State shared between a build thread and its handle. The build reports
+// how far along it is and checks for cancellation between steps.
+#[derive(Debug, Default)]
+pub struct BuildTask {
+    // f32 bits, so the fraction can be read without a lock
+    progress: AtomicU32,
+    cancelled: AtomicBool,
+}
+
+impl BuildTask {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Fraction of the build done, in 0..=1
+    pub fn progress(&self) -> f32 {
+        f32::from_bits(self.progress.load(Ordering::Relaxed))
+    }
+
+    pub fn set_progress(&self, fraction: f32) {
+        self.progress
+            .store(fraction.clamp(0.0, 1.0).to_bits(), Ordering::Relaxed);
+    }
+
+    // For the progress callback of the tiled builds
+    pub fn set_tile_progress(&self, progress: BuildProgress) {
+        self.set_progress(progress.tiles_done as f32 / progress.tile_count.max(1) as f32);
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    // Err(Cancelled) once the build should stop, for use with `?`
+    pub fn check(&self) -> Result<(), NavMeshBuildError> {
+        if self.is_cancelled() {
+            Err(NavMeshBuildError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+// A build running on its own thread. Dropping the handle cancels the build
+// without waiting for it.
+pub struct BuildHandle<T> {
+    task: Arc<BuildTask>,
+    #[cfg(not(target_arch = "wasm32"))]
+    thread: Option<JoinHandle<T>>,
+    // The browser has no threads, so there the build runs to completion
+    // inside `spawn` and only its result is kept
+    #[cfg(target_arch = "wasm32")]
+    result: Option<T>,
+}
+
+impl<T: Send + 'static> BuildHandle<T> {
+    // Run `build` on a new thread, passing it the task to report through
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn spawn(build: impl FnOnce(&BuildTask) -> T + Send + 'static) -> Self {
+        let task = Arc::new(BuildTask::new());
+        let worker_task = Arc::clone(&task);
+        let thread = thread::spawn(move || build(&worker_task));
+        Self {
+            task,
+            thread: Some(thread),
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn spawn(build: impl FnOnce(&BuildTask) -> T + Send + 'static) -> Self {
+        let task = Arc::new(BuildTask::new());
+        let result = Some(build(&task));
+        Self { task, result }
+    }
+
+    pub fn progress(&self) -> f32 {
+        self.task.progress()
+    }
+
+    // Ask the build to stop at its next check. It still finishes, with
+    // whatever result it returns when cancelled.
+    pub fn cancel(&self) {
+        self.task.cancel();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.task.is_cancelled()
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn is_finished(&self) -> bool {
+        self.thread.as_ref().is_none_or(JoinHandle::is_finished)
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn is_finished(&self) -> bool {
+        true
+    }
+
+    // The result once the build has finished, without blocking. Returns
+    // None before that and after the result has been taken.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn try_take(&mut self) -> Option<T> {
+        if !self.thread.as_ref()?.is_finished() {
+            return None;
+        }
+        Some(join(self.thread.take()?))
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn try_take(&mut self) -> Option<T> {
+        self.result.take()
+    }
+
+    // Block until the build finishes
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn wait(mut self) -> T {
+        join(self.thread.take().expect("build result already taken"))
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn wait(mut self) -> T {
+        self.result.take().expect("build result already taken")
+    }
+}
+
+impl<T> Drop for BuildHandle<T> {
+    fn drop(&mut self) {
+        self.task.cancel();
+    }
+}
+
+// A panic in the build is re-raised on the thread asking for the result
+#[cfg(not(target_arch = "wasm32"))]
+fn join<T>(thread: JoinHandle<T>) -> T {
+    thread
+        .join()
+        .unwrap_or_else(|payload| panic::resume_unwind(payload))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_cancel_stops_build() {
+        let (started, wait_started) = mpsc::channel();
+        let handle = BuildHandle::spawn(move |task| {
+            task.set_progress(0.5);
+            started.send(()).unwrap();
+            loop {
+                task.check()?;
+                thread::yield_now();
+            }
+        });
+        wait_started.recv().unwrap();
+        assert_eq!(handle.progress(), 0.5);
+        handle.cancel();
+        let result: Result<(), NavMeshBuildError> = handle.wait();
+        assert!(matches!(result, Err(NavMeshBuildError::Cancelled)));
+
+        let mut handle = BuildHandle::spawn(|task| {
+            task.set_tile_progress(BuildProgress {
+                tiles_done: 4,
+                tile_count: 4,
+            });
+            task.progress()
+        });
+        while !handle.is_finished() {
+            thread::yield_now();
+        }
+        assert_eq!(handle.try_take(), Some(1.0));
+        assert_eq!(handle.try_take(), None);
+    }
+}
@@ -0,0 +1,405 @@
+use glam::Vec3;
+
+use crate::debug_draw_b::{ray_triangle, InputMesh};
+
+// Triangles per leaf before a node is split further
+const MAX_LEAF_TRIS: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hit {
+    pub t: f32,
+    pub tri_index: usize,
+    pub point: Vec3,
+    pub normal: Vec3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClosestPoint {
+    pub point: Vec3,
+    pub tri_index: usize,
+    pub distance: f32,
+}
+
+#[derive(Debug, Clone)]
+struct BvhNode {
+    bmin: Vec3,
+    bmax: Vec3,
+    // Leaves cover tri_order[start..start + count]; internal nodes have
+    // count == 0, their left child at index + 1 and the right one at `start`
+    start: usize,
+    count: usize,
+}
+
+// Bounding volume hierarchy over the triangles of an InputMesh, median split
+// on the longest axis of the centroid bounds
+#[derive(Debug, Clone, Default)]
+pub struct MeshBvh {
+    nodes: Vec<BvhNode>,
+    tri_order: Vec<usize>,
+    // Corners and face normal per triangle, copied so queries don't need the mesh
+    tris: Vec<[Vec3; 3]>,
+    normals: Vec<Vec3>,
+}
+
+impl MeshBvh {
+    pub fn build(mesh: &InputMesh) -> Self {
+        let tris: Vec<[Vec3; 3]> = mesh
+            .tris
+            .chunks_exact(3)
+            .map(|t| {
+                [
+                    mesh.verts[t[0] as usize],
+                    mesh.verts[t[1] as usize],
+                    mesh.verts[t[2] as usize],
+                ]
+            })
+            .collect();
+        let normals = if mesh.normals.len() == tris.len() {
+            mesh.normals.clone()
+        } else {
+            tris.iter()
+                .map(|[a, b, c]| (*b - *a).cross(*c - *a).normalize_or_zero())
+                .collect()
+        };
+
+        let mut bvh = Self {
+            nodes: Vec::new(),
+            tri_order: (0..tris.len()).collect(),
+            tris,
+            normals,
+        };
+        if !bvh.tris.is_empty() {
+            let centroids: Vec<Vec3> = bvh
+                .tris
+                .iter()
+                .map(|[a, b, c]| (*a + *b + *c) / 3.0)
+                .collect();
+            bvh.build_node(&centroids, 0, bvh.tri_order.len());
+        }
+        bvh
+    }
+
+    fn build_node(&mut self, centroids: &[Vec3], start: usize, end: usize) -> usize {
+        let (bmin, bmax) = self.tri_order[start..end].iter().fold(
+            (Vec3::INFINITY, Vec3::NEG_INFINITY),
+            |(lo, hi), &i| {
+                let [a, b, c] = self.tris[i];
+                (lo.min(a).min(b).min(c), hi.max(a).max(b).max(c))
+            },
+        );
+
+        let index = self.nodes.len();
+        self.nodes.push(BvhNode {
+            bmin,
+            bmax,
+            start,
+            count: end - start,
+        });
+        if end - start <= MAX_LEAF_TRIS {
+            return index;
+        }
+
+        // Split at the median centroid along the longest centroid axis
+        let (cmin, cmax) = self.tri_order[start..end]
+            .iter()
+            .fold((Vec3::INFINITY, Vec3::NEG_INFINITY), |(lo, hi), &i| {
+                (lo.min(centroids[i]), hi.max(centroids[i]))
+            });
+        let extent = cmax - cmin;
+        let axis = if extent.x >= extent.y && extent.x >= extent.z {
+            0
+        } else if extent.y >= extent.z {
+            1
+        } else {
+            2
+        };
+        let mid = (start + end) / 2;
+        self.tri_order[start..end].select_nth_unstable_by(mid - start, |&a, &b| {
+            centroids[a][axis].total_cmp(&centroids[b][axis])
+        });
+
+        self.build_node(centroids, start, mid);
+        let right = self.build_node(centroids, mid, end);
+        self.nodes[index].start = right;
+        self.nodes[index].count = 0;
+        index
+    }
+
+    pub fn tri_count(&self) -> usize {
+        self.tris.len()
+    }
+
+    // Closest intersection along the ray; `dir` need not be normalized, `t`
+    // is measured in multiples of it
+    pub fn raycast(&self, origin: Vec3, dir: Vec3) -> Option<Hit> {
+        let inv_dir = dir.recip();
+        let mut best: Option<(f32, usize)> = None;
+
+        self.traverse(|node, leaf| {
+            let entered = ray_aabb(origin, inv_dir, node.bmin, node.bmax)
+                .is_some_and(|t| best.is_none_or(|(best_t, _)| t <= best_t));
+            if !entered {
+                return false;
+            }
+            for &i in leaf {
+                let [a, b, c] = self.tris[i];
+                if let Some(t) = ray_triangle(origin, dir, a, b, c) {
+                    if best.is_none_or(|(best_t, _)| t < best_t) {
+                        best = Some((t, i));
+                    }
+                }
+            }
+            true
+        });
+
+        best.map(|(t, tri_index)| Hit {
+            t,
+            tri_index,
+            point: origin + dir * t,
+            normal: self.normals[tri_index],
+        })
+    }
+
+    // Nearest point on the mesh surface within `max_dist` of `p`
+    pub fn closest_point(&self, p: Vec3, max_dist: f32) -> Option<ClosestPoint> {
+        let mut best: Option<ClosestPoint> = None;
+        let mut best_dist = max_dist;
+
+        self.traverse(|node, leaf| {
+            if p.clamp(node.bmin, node.bmax).distance(p) > best_dist {
+                return false;
+            }
+            for &i in leaf {
+                let [a, b, c] = self.tris[i];
+                let point = closest_point_on_triangle(p, a, b, c);
+                let distance = point.distance(p);
+                if distance <= best_dist {
+                    best_dist = distance;
+                    best = Some(ClosestPoint {
+                        point,
+                        tri_index: i,
+                        distance,
+                    });
+                }
+            }
+            true
+        });
+
+        best
+    }
+
+    // Triangles whose bounds overlap the box
+    pub fn aabb_query(&self, bmin: Vec3, bmax: Vec3) -> Vec<usize> {
+        let mut result = Vec::new();
+        self.traverse(|node, leaf| {
+            if !aabb_overlap(node.bmin, node.bmax, bmin, bmax) {
+                return false;
+            }
+            for &i in leaf {
+                let [a, b, c] = self.tris[i];
+                if aabb_overlap(a.min(b).min(c), a.max(b).max(c), bmin, bmax) {
+                    result.push(i);
+                }
+            }
+            true
+        });
+        result.sort_unstable();
+        result
+    }
+
+    // Depth-first walk. `visit` gets each reached node with its triangles
+    // (empty for internal nodes) and returns whether to descend into it.
+    fn traverse(&self, mut visit: impl FnMut(&BvhNode, &[usize]) -> bool) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            let leaf = &self.tri_order[node.start..node.start + node.count];
+            if visit(node, leaf) && node.count == 0 {
+                stack.push(node.start);
+                stack.push(index + 1);
+            }
+        }
+    }
+}
+
+// Entry distance of a ray into a box (slab test), 0 if the origin is inside
+fn ray_aabb(origin: Vec3, inv_dir: Vec3, bmin: Vec3, bmax: Vec3) -> Option<f32> {
+    let t0 = (bmin - origin) * inv_dir;
+    let t1 = (bmax - origin) * inv_dir;
+    // NaN from 0 * inf on a slab boundary is ignored by min/max
+    let tmin = t0.min(t1).max_element().max(0.0);
+    let tmax = t0.max(t1).min_element();
+    (tmin <= tmax).then_some(tmin)
+}
+
+fn aabb_overlap(amin: Vec3, amax: Vec3, bmin: Vec3, bmax: Vec3) -> bool {
+    amin.cmple(bmax).all() && bmin.cmple(amax).all()
+}
+
+// Closest point on triangle abc to p (Ericson, Real-Time Collision Detection 5.1.5)
+pub fn closest_point_on_triangle(p: Vec3, a: Vec3, b: Vec3, c: Vec3) -> Vec3 {
+    let ab = b - a;
+    let ac = c - a;
+    let ap = p - a;
+    let d1 = ab.dot(ap);
+    let d2 = ac.dot(ap);
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return a;
+    }
+
+    let bp = p - b;
+    let d3 = ab.dot(bp);
+    let d4 = ac.dot(bp);
+    if d3 >= 0.0 && d4 <= d3 {
+        return b;
+    }
+
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        return a + ab * (d1 / (d1 - d3));
+    }
+
+    let cp = p - c;
+    let d5 = ab.dot(cp);
+    let d6 = ac.dot(cp);
+    if d6 >= 0.0 && d5 <= d6 {
+        return c;
+    }
+
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        return a + ac * (d2 / (d2 - d6));
+    }
+
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && (d4 - d3) >= 0.0 && (d5 - d6) >= 0.0 {
+        return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+    }
+
+    let denom = 1.0 / (va + vb + vc);
+    a + ab * (vb * denom) + ac * (vc * denom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    // Deterministic xorshift so the tests don't need a rand dependency
+    struct Rng(u32);
+
+    impl Rng {
+        fn next(&mut self) -> f32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            self.0 as f32 / u32::MAX as f32
+        }
+
+        fn range(&mut self, lo: f32, hi: f32) -> f32 {
+            lo + (hi - lo) * self.next()
+        }
+    }
+
+    fn grid_mesh(n: usize) -> InputMesh {
+        let mut mesh = InputMesh::new();
+        for z in 0..=n {
+            for x in 0..=n {
+                let y = ((x as f32 * 0.7).sin() + (z as f32 * 0.4).cos()) * 0.5;
+                mesh.verts.push(Vec3::new(x as f32, y, z as f32));
+            }
+        }
+        let row = (n + 1) as i32;
+        for z in 0..n {
+            for x in 0..n {
+                let i = (z * (n + 1) + x) as i32;
+                mesh.tris
+                    .extend_from_slice(&[i, i + row, i + 1, i + 1, i + row, i + row + 1]);
+            }
+        }
+        mesh.compute_normals();
+        mesh
+    }
+
+    #[test]
+    fn test_raycast_matches_brute_force() {
+        let mesh = grid_mesh(8);
+        let bvh = MeshBvh::build(&mesh);
+        let mut rng = Rng(0x1234_5678);
+
+        for _ in 0..500 {
+            let origin = Vec3::new(rng.range(-2.0, 10.0), 5.0, rng.range(-2.0, 10.0));
+            let dir = Vec3::new(rng.range(-0.5, 0.5), -1.0, rng.range(-0.5, 0.5));
+            let brute = mesh.raycast(origin, dir);
+            let hit = bvh.raycast(origin, dir);
+            match (brute, hit) {
+                (None, None) => {}
+                (Some((t, _)), Some(hit)) => {
+                    assert!((t - hit.t).abs() < 1e-5);
+                    assert!((hit.point - (origin + dir * t)).length() < 1e-4);
+                }
+                other => panic!("brute force and BVH disagree: {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_closest_point_and_aabb_query() {
+        let mesh = grid_mesh(6);
+        let bvh = MeshBvh::build(&mesh);
+        let mut rng = Rng(0x9e37_79b9);
+
+        for _ in 0..200 {
+            let p = Vec3::new(
+                rng.range(-1.0, 7.0),
+                rng.range(-2.0, 3.0),
+                rng.range(-1.0, 7.0),
+            );
+            let brute = bvh
+                .tris
+                .iter()
+                .map(|&[a, b, c]| closest_point_on_triangle(p, a, b, c).distance(p))
+                .fold(f32::INFINITY, f32::min);
+            let found = bvh.closest_point(p, f32::INFINITY).unwrap();
+            assert!((found.distance - brute).abs() < 1e-5);
+        }
+        assert!(bvh.closest_point(Vec3::new(50.0, 0.0, 50.0), 1.0).is_none());
+
+        let (bmin, bmax) = (Vec3::new(1.2, -5.0, 2.2), Vec3::new(2.8, 5.0, 3.1));
+        let brute: Vec<usize> = (0..bvh.tri_count())
+            .filter(|&i| {
+                let [a, b, c] = bvh.tris[i];
+                aabb_overlap(a.min(b).min(c), a.max(b).max(c), bmin, bmax)
+            })
+            .collect();
+        assert!(!brute.is_empty());
+        assert_eq!(bvh.aabb_query(bmin, bmax), brute);
+    }
+
+    #[test]
+    fn test_raycast_throughput() {
+        // 20k triangles
+        let mesh = grid_mesh(100);
+        let bvh = MeshBvh::build(&mesh);
+        let mut rng = Rng(42);
+
+        let start = Instant::now();
+        let mut hits = 0;
+        for _ in 0..10_000 {
+            let origin = Vec3::new(rng.range(0.0, 100.0), 10.0, rng.range(0.0, 100.0));
+            let dir = Vec3::new(rng.range(-1.0, 1.0), -1.0, rng.range(-1.0, 1.0));
+            hits += bvh.raycast(origin, dir).is_some() as usize;
+        }
+        let elapsed = start.elapsed();
+
+        assert!(hits > 9_000);
+        assert!(
+            elapsed.as_secs_f32() < 1.0,
+            "10k raycasts took {:?}",
+            elapsed
+        );
+    }
+}
@@ -0,0 +1,282 @@
+use glam::{Vec2, Vec3, Vec3Swizzles};
+
+use crate::debug_draw_b::{ray_triangle, InputMesh};
+
+// Triangles per leaf, as in Recast's sample
+pub const DEFAULT_TRIS_PER_CHUNK: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ChunkyNode {
+    // Bounds of the node's triangles. Splits only look at XZ.
+    bmin: Vec3,
+    bmax: Vec3,
+    // Leaves cover tri_order[start..start + count]; inner nodes have
+    // count 0 and `start` is the index of the node after their subtree
+    start: usize,
+    count: usize,
+}
+
+// AABB tree over the input triangles on the XZ plane, so a tiled build can
+// rasterize only the triangles under each tile. Like Recast's
+// rcChunkyTriMesh, but keeping the heights of each node as well so the
+// viewer can cull chunks against the camera.
+#[derive(Debug, Clone, Default)]
+pub struct ChunkyTriMesh {
+    nodes: Vec<ChunkyNode>,
+    tri_order: Vec<usize>,
+    max_tris_per_chunk: usize,
+}
+
+// One leaf of a ChunkyTriMesh: the bounds of its triangles and their indices
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Chunk<'a> {
+    pub bmin: Vec3,
+    pub bmax: Vec3,
+    pub tris: &'a [usize],
+}
+
+impl ChunkyTriMesh {
+    pub fn build(mesh: &InputMesh, tris_per_chunk: usize) -> Self {
+        let bounds: Vec<(Vec3, Vec3)> = mesh
+            .tris
+            .chunks_exact(3)
+            .map(|t| {
+                let [a, b, c] = [0, 1, 2].map(|k| mesh.verts[t[k] as usize]);
+                (a.min(b).min(c), a.max(b).max(c))
+            })
+            .collect();
+
+        let mut chunky = Self {
+            nodes: Vec::new(),
+            tri_order: (0..bounds.len()).collect(),
+            max_tris_per_chunk: 0,
+        };
+        if !bounds.is_empty() {
+            chunky.build_node(&bounds, 0, bounds.len(), tris_per_chunk.max(1));
+        }
+        chunky
+    }
+
+    fn build_node(&mut self, bounds: &[(Vec3, Vec3)], start: usize, end: usize, per_chunk: usize) {
+        let (bmin, bmax) = self.tri_order[start..end]
+            .iter()
+            .fold((Vec3::INFINITY, Vec3::NEG_INFINITY), |(lo, hi), &i| {
+                (lo.min(bounds[i].0), hi.max(bounds[i].1))
+            });
+
+        let index = self.nodes.len();
+        self.nodes.push(ChunkyNode {
+            bmin,
+            bmax,
+            start,
+            count: end - start,
+        });
+        if end - start <= per_chunk {
+            self.max_tris_per_chunk = self.max_tris_per_chunk.max(end - start);
+            return;
+        }
+
+        // Split in half along the longer side, ordered by triangle minimum
+        let axis = if bmax.x - bmin.x >= bmax.z - bmin.z {
+            0
+        } else {
+            2
+        };
+        let mid = (start + end) / 2;
+        self.tri_order[start..end].select_nth_unstable_by(mid - start, |&a, &b| {
+            bounds[a].0[axis].total_cmp(&bounds[b].0[axis])
+        });
+
+        self.build_node(bounds, start, mid, per_chunk);
+        self.build_node(bounds, mid, end, per_chunk);
+        self.nodes[index].start = self.nodes.len();
+        self.nodes[index].count = 0;
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.nodes.iter().filter(|n| n.count > 0).count()
+    }
+
+    pub fn max_tris_per_chunk(&self) -> usize {
+        self.max_tris_per_chunk
+    }
+
+    // Triangles in the leaves whose XZ bounds overlap the box from `bmin`
+    // to `bmax`, in mesh order. Heights are ignored. Leaves are tested as a
+    // whole, so triangles near the box but outside it may be included.
+    pub fn tris_overlapping(&self, bmin: Vec3, bmax: Vec3) -> Vec<usize> {
+        let (qmin, qmax) = (bmin.xz(), bmax.xz());
+        self.collect_tris(|nmin, nmax| nmin.cmple(qmax).all() && nmax.cmpge(qmin).all())
+    }
+
+    // Triangles in the leaves crossed by the segment from `p` to `q`
+    // projected on the XZ plane, like rcGetChunksOverlappingSegment
+    pub fn tris_overlapping_segment(&self, p: Vec3, q: Vec3) -> Vec<usize> {
+        let (p, q) = (p.xz(), q.xz());
+        self.collect_tris(|nmin, nmax| segment_overlaps_rect(p, q, nmin, nmax))
+    }
+
+    // Nearest triangle of `mesh` hit by the segment from `start` to `end`,
+    // as the triangle index and the hit's fraction of the way along. The
+    // mesh must be the one the tree was built from.
+    pub fn raycast(&self, mesh: &InputMesh, start: Vec3, end: Vec3) -> Option<(usize, f32)> {
+        let dir = end - start;
+        self.tris_overlapping_segment(start, end)
+            .into_iter()
+            .filter_map(|i| {
+                let [a, b, c] = [0, 1, 2].map(|k| mesh.verts[mesh.tris[i * 3 + k] as usize]);
+                ray_triangle(start, dir, a, b, c)
+                    .filter(|&t| t <= 1.0)
+                    .map(|t| (i, t))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    // Leaves whose bounds pass `visible`, e.g. against a view frustum.
+    // Subtrees whose bounds fail are skipped without testing their leaves.
+    pub fn chunks_passing(&self, visible: impl Fn(Vec3, Vec3) -> bool) -> Vec<Chunk<'_>> {
+        let mut chunks = Vec::new();
+        self.visit_leaves(visible, |node| {
+            chunks.push(Chunk {
+                bmin: node.bmin,
+                bmax: node.bmax,
+                tris: &self.tri_order[node.start..node.start + node.count],
+            })
+        });
+        chunks
+    }
+
+    // Triangles of every leaf whose XZ bounds pass `overlaps`, in mesh order
+    fn collect_tris(&self, overlaps: impl Fn(Vec2, Vec2) -> bool) -> Vec<usize> {
+        let mut tris = Vec::new();
+        self.visit_leaves(
+            |bmin, bmax| overlaps(bmin.xz(), bmax.xz()),
+            |node| tris.extend_from_slice(&self.tri_order[node.start..node.start + node.count]),
+        );
+        // Same order as a full-mesh pass, so span merging comes out the same
+        tris.sort_unstable();
+        tris
+    }
+
+    // Call `leaf` for every leaf whose bounds, and whose ancestors' bounds,
+    // pass `test`
+    fn visit_leaves(&self, test: impl Fn(Vec3, Vec3) -> bool, mut leaf: impl FnMut(&ChunkyNode)) {
+        let mut i = 0;
+        while i < self.nodes.len() {
+            let node = &self.nodes[i];
+            let pass = test(node.bmin, node.bmax);
+            if node.count > 0 {
+                if pass {
+                    leaf(node);
+                }
+                i += 1;
+            } else if pass {
+                i += 1;
+            } else {
+                // Skip the whole subtree
+                i = node.start;
+            }
+        }
+    }
+}
+
+// Slab test of the segment from `p` to `q` against a rectangle
+fn segment_overlaps_rect(p: Vec2, q: Vec2, bmin: Vec2, bmax: Vec2) -> bool {
+    let d = q - p;
+    let (mut tmin, mut tmax) = (0.0_f32, 1.0_f32);
+    for axis in 0..2 {
+        if d[axis].abs() < 1e-6 {
+            // Parallel to the slab, so the start must already be inside it
+            if p[axis] < bmin[axis] || p[axis] > bmax[axis] {
+                return false;
+            }
+            continue;
+        }
+        let t1 = (bmin[axis] - p[axis]) / d[axis];
+        let t2 = (bmax[axis] - p[axis]) / d[axis];
+        tmin = tmin.max(t1.min(t2));
+        tmax = tmax.min(t1.max(t2));
+        if tmin > tmax {
+            return false;
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tris_overlapping_finds_local_triangles() {
+        // A 16x16 grid of unit quads
+        let mut mesh = InputMesh::new();
+        for z in 0..=16 {
+            for x in 0..=16 {
+                mesh.verts.push(Vec3::new(x as f32, 0.0, z as f32));
+            }
+        }
+        for z in 0..16 {
+            for x in 0..16 {
+                let v = z * 17 + x;
+                mesh.tris.extend([v, v + 17, v + 18, v, v + 18, v + 1]);
+            }
+        }
+
+        let chunky = ChunkyTriMesh::build(&mesh, 32);
+        assert!(chunky.chunk_count() >= 16);
+        assert!(chunky.max_tris_per_chunk() <= 32);
+
+        let found = chunky.tris_overlapping(Vec3::new(2.5, -1.0, 2.5), Vec3::new(3.5, 1.0, 3.5));
+        // Every triangle touching the box is there, and far fewer than all
+        for quad in [2 * 16 + 2, 2 * 16 + 3, 3 * 16 + 2, 3 * 16 + 3] {
+            assert!(found.contains(&(quad * 2)) && found.contains(&(quad * 2 + 1)));
+        }
+        assert!(found.len() < mesh.tri_count() / 4);
+        assert!(found.windows(2).all(|w| w[0] < w[1]));
+
+        assert!(chunky
+            .tris_overlapping(Vec3::splat(20.0), Vec3::splat(30.0))
+            .is_empty());
+        // Chunks cover every triangle once and keep the floor's height
+        let chunks = chunky.chunks_passing(|_, _| true);
+        assert_eq!(chunks.len(), chunky.chunk_count());
+        let covered: usize = chunks.iter().map(|c| c.tris.len()).sum();
+        assert_eq!(covered, mesh.tri_count());
+        assert!(chunks.iter().all(|c| c.bmin.y == 0.0 && c.bmax.y == 0.0));
+        assert!(chunky.chunks_passing(|_, bmax| bmax.y > 1.0).is_empty());
+    }
+
+    #[test]
+    fn test_raycast_through_chunks() {
+        let mut mesh = InputMesh::new();
+        for z in 0..=16 {
+            for x in 0..=16 {
+                mesh.verts.push(Vec3::new(x as f32, 0.0, z as f32));
+            }
+        }
+        for z in 0..16 {
+            for x in 0..16 {
+                let v = z * 17 + x;
+                mesh.tris.extend([v, v + 17, v + 18, v, v + 18, v + 1]);
+            }
+        }
+        let chunky = ChunkyTriMesh::build(&mesh, 32);
+
+        // Straight down into the first triangle of quad (2, 3), halfway along
+        let start = Vec3::new(2.25, 5.0, 3.75);
+        let hit = chunky.raycast(&mesh, start, start - Vec3::Y * 10.0);
+        assert_eq!(hit, Some(((3 * 16 + 2) * 2, 0.5)));
+
+        // A slanted segment only visits the leaves it crosses
+        let (p, q) = (Vec3::new(-1.0, 4.0, 8.5), Vec3::new(12.0, -4.0, 8.5));
+        assert!(chunky.tris_overlapping_segment(p, q).len() < mesh.tri_count() / 2);
+        let (tri, t) = chunky.raycast(&mesh, p, q).unwrap();
+        let point = p.lerp(q, t);
+        assert!((point.y).abs() < 1e-5 && (point.x - 5.5).abs() < 1e-4);
+        assert_eq!(tri / 32, 8);
+
+        // Ending above the floor misses it
+        assert_eq!(chunky.raycast(&mesh, start, start - Vec3::Y), None);
+    }
+}
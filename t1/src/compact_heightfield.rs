@@ -0,0 +1,314 @@
+use glam::Vec3;
+
+use crate::debug_draw::RC_NULL_AREA;
+use crate::heightfield::Heightfield;
+
+// Neighbour slot value for "no walkable connection in this direction"
+pub const RC_NOT_CONNECTED: u8 = 0x3f;
+// Height of the open space above the topmost span of a column
+const RC_MAX_HEIGHT: i32 = 0xffff;
+
+// Grid steps for directions 0..4: -x, +z, +x, -z
+const DIR_OFFSETS: [(i32, i32); 4] = [(-1, 0), (0, 1), (1, 0), (0, -1)];
+
+pub fn dir_offset(dir: usize) -> (i32, i32) {
+    DIR_OFFSETS[dir & 3]
+}
+
+// Range of `CompactHeightfield::spans` belonging to one column
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CompactCell {
+    pub index: u32,
+    pub count: u32,
+}
+
+// The open space on top of a solid span
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CompactSpan {
+    // Floor height and clearance above it, in cell-height units
+    pub y: u16,
+    pub h: u8,
+    // Region id, 0 until regions are built
+    pub reg: u16,
+    // Per direction, the layer index of the connected span in the
+    // neighbouring column or RC_NOT_CONNECTED
+    pub con: [u8; 4],
+}
+
+impl CompactSpan {
+    pub fn con(&self, dir: usize) -> Option<usize> {
+        let layer = self.con[dir];
+        (layer != RC_NOT_CONNECTED).then_some(layer as usize)
+    }
+}
+
+// Walkable open space of a heightfield with the connectivity between
+// neighbouring spans, the input to region partitioning
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CompactHeightfield {
+    pub width: i32,
+    pub height: i32,
+    pub walkable_height: i32,
+    pub walkable_climb: i32,
+    // Width of the non-navigable border around tiles
+    pub border_size: i32,
+    pub max_distance: u16,
+    pub max_regions: u16,
+    pub bmin: Vec3,
+    pub bmax: Vec3,
+    pub cs: f32,
+    pub ch: f32,
+    pub cells: Vec<CompactCell>,
+    pub spans: Vec<CompactSpan>,
+    // Distance field, filled by region building
+    pub dist: Vec<u16>,
+    // Area id per span
+    pub areas: Vec<u8>,
+}
+
+impl CompactHeightfield {
+    // `walkable_height` is the clearance an agent needs and `walkable_climb`
+    // the step it can take, both in cell-height units
+    pub fn build(hf: &Heightfield, walkable_height: i32, walkable_climb: i32) -> Self {
+        let mut chf = Self {
+            width: hf.width,
+            height: hf.height,
+            walkable_height,
+            walkable_climb,
+            border_size: 0,
+            max_distance: 0,
+            max_regions: 0,
+            // Leave room for an agent standing on the highest span
+            bmin: hf.bmin,
+            bmax: hf.bmax + Vec3::new(0.0, walkable_height as f32 * hf.ch, 0.0),
+            cs: hf.cs,
+            ch: hf.ch,
+            cells: vec![CompactCell::default(); hf.spans.len()],
+            spans: Vec::new(),
+            dist: Vec::new(),
+            areas: Vec::new(),
+        };
+
+        // The open space of a span runs from its top to the bottom of the
+        // next span up
+        for (cell, column) in chf.cells.iter_mut().zip(&hf.spans) {
+            cell.index = chf.spans.len() as u32;
+            for (i, span) in column.iter().enumerate() {
+                if span.area == RC_NULL_AREA {
+                    continue;
+                }
+                let bot = span.smax as i32;
+                let top = column
+                    .get(i + 1)
+                    .map_or(RC_MAX_HEIGHT, |next| next.smin as i32);
+                chf.spans.push(CompactSpan {
+                    y: bot.clamp(0, 0xffff) as u16,
+                    h: (top - bot).clamp(0, 0xff) as u8,
+                    reg: 0,
+                    con: [RC_NOT_CONNECTED; 4],
+                });
+                chf.areas.push(span.area);
+            }
+            cell.count = chf.spans.len() as u32 - cell.index;
+        }
+
+        chf.connect_neighbours();
+        chf
+    }
+
+    fn connect_neighbours(&mut self) {
+        for z in 0..self.height {
+            for x in 0..self.width {
+                for i in self.span_range(x, z) {
+                    let s = self.spans[i];
+                    for dir in 0..4 {
+                        let (dx, dz) = dir_offset(dir);
+                        let (nx, nz) = (x + dx, z + dz);
+                        if nx < 0 || nz < 0 || nx >= self.width || nz >= self.height {
+                            continue;
+                        }
+
+                        // Connect to the first span the agent fits through
+                        // and can step onto
+                        let neighbours = self.span_range(nx, nz);
+                        let first = neighbours.start;
+                        let layer = neighbours.into_iter().find(|&k| {
+                            let ns = &self.spans[k];
+                            let bot = s.y.max(ns.y) as i32;
+                            let top = (s.y as i32 + s.h as i32).min(ns.y as i32 + ns.h as i32);
+                            top - bot >= self.walkable_height
+                                && (ns.y as i32 - s.y as i32).abs() <= self.walkable_climb
+                        });
+                        // Layers past the slot range can't be encoded
+                        if let Some(layer) = layer.map(|k| k - first) {
+                            if layer < RC_NOT_CONNECTED as usize {
+                                self.spans[i].con[dir] = layer as u8;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Unmark walkable spans closer than `radius` cells to an unwalkable
+    // span or a missing neighbour, so agents of that radius stay clear of
+    // walls and ledges
+    pub fn erode_walkable_area(&mut self, radius: i32) {
+        let mut dist = vec![u8::MAX; self.span_count()];
+
+        for z in 0..self.height {
+            for x in 0..self.width {
+                for i in self.span_range(x, z) {
+                    let walkable_neighbours = (0..4)
+                        .filter_map(|dir| self.neighbour(x, z, i, dir))
+                        .filter(|&(_, _, ai)| self.areas[ai] != RC_NULL_AREA)
+                        .count();
+                    if self.areas[i] == RC_NULL_AREA || walkable_neighbours != 4 {
+                        dist[i] = 0;
+                    }
+                }
+            }
+        }
+
+        // Chamfer distance, as for the region distance field
+        let relax = |dist: &mut [u8], x: i32, z: i32, i: usize, dirs: [usize; 2]| {
+            for dir in dirs {
+                let Some((ax, az, ai)) = self.neighbour(x, z, i, dir) else {
+                    continue;
+                };
+                dist[i] = dist[i].min(dist[ai].saturating_add(2));
+                if let Some((_, _, aai)) = self.neighbour(ax, az, ai, (dir + 3) & 3) {
+                    dist[i] = dist[i].min(dist[aai].saturating_add(3));
+                }
+            }
+        };
+        for z in 0..self.height {
+            for x in 0..self.width {
+                for i in self.span_range(x, z) {
+                    relax(&mut dist, x, z, i, [0, 3]);
+                }
+            }
+        }
+        for z in (0..self.height).rev() {
+            for x in (0..self.width).rev() {
+                for i in self.span_range(x, z) {
+                    relax(&mut dist, x, z, i, [2, 1]);
+                }
+            }
+        }
+
+        let threshold = radius * 2;
+        for (area, &d) in self.areas.iter_mut().zip(&dist) {
+            if (d as i32) < threshold {
+                *area = RC_NULL_AREA;
+            }
+        }
+    }
+
+    pub fn span_count(&self) -> usize {
+        self.spans.len()
+    }
+
+    // Indices into `spans` of the column at (x, z)
+    pub fn span_range(&self, x: i32, z: i32) -> std::ops::Range<usize> {
+        let cell = self.cells[(x + z * self.width) as usize];
+        cell.index as usize..(cell.index + cell.count) as usize
+    }
+
+    // Column and span index of the neighbour of span `i` at (x, z) in `dir`
+    pub fn neighbour(&self, x: i32, z: i32, i: usize, dir: usize) -> Option<(i32, i32, usize)> {
+        let layer = self.spans[i].con(dir)?;
+        let (dx, dz) = dir_offset(dir);
+        let (nx, nz) = (x + dx, z + dz);
+        Some((
+            nx,
+            nz,
+            self.cells[(nx + nz * self.width) as usize].index as usize + layer,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debug_draw::RC_WALKABLE_AREA;
+
+    fn connected_dirs(chf: &CompactHeightfield, i: usize) -> usize {
+        (0..4)
+            .filter(|&dir| chf.spans[i].con(dir).is_some())
+            .count()
+    }
+
+    #[test]
+    fn test_build_flat_floor() {
+        let mut hf = Heightfield::new(Vec3::ZERO, Vec3::new(3.0, 4.0, 3.0), 1.0, 0.1);
+        for z in 0..3 {
+            for x in 0..3 {
+                hf.add_span(x, z, 0, 2, RC_WALKABLE_AREA, 1);
+            }
+        }
+        let chf = CompactHeightfield::build(&hf, 10, 4);
+        assert_eq!(chf.span_count(), 9);
+
+        // Corners, edges and the centre have 2, 3 and 4 neighbours
+        let centre = chf.span_range(1, 1).start;
+        assert_eq!(connected_dirs(&chf, centre), 4);
+        assert_eq!(connected_dirs(&chf, chf.span_range(0, 0).start), 2);
+        assert_eq!(connected_dirs(&chf, chf.span_range(1, 0).start), 3);
+
+        let (nx, nz, n) = chf.neighbour(1, 1, centre, 2).unwrap();
+        assert_eq!((nx, nz), (2, 1));
+        assert_eq!(n, chf.span_range(2, 1).start);
+        assert_eq!(chf.spans[n].y, 2);
+    }
+
+    #[test]
+    fn test_build_steps_and_ceilings() {
+        let mut hf = Heightfield::new(Vec3::ZERO, Vec3::new(3.0, 4.0, 1.0), 1.0, 0.1);
+        hf.add_span(0, 0, 0, 2, RC_WALKABLE_AREA, 1);
+        // Too high to climb onto
+        hf.add_span(1, 0, 0, 10, RC_WALKABLE_AREA, 1);
+        // A floor with a second, unwalkable surface above it, and a
+        // walkable one higher still
+        hf.add_span(2, 0, 0, 10, RC_WALKABLE_AREA, 1);
+        hf.add_span(2, 0, 15, 20, RC_NULL_AREA, 1);
+        hf.add_span(2, 0, 40, 42, RC_WALKABLE_AREA, 1);
+
+        let chf = CompactHeightfield::build(&hf, 10, 4);
+        assert_eq!(chf.span_count(), 4);
+        assert_eq!(chf.spans[0].con(2), None);
+
+        // The low ceiling over x = 2 leaves 5 cells of clearance, too little
+        // to walk onto from the step, and the upper floor is out of reach
+        let step = chf.span_range(1, 0).start;
+        let lower = chf.span_range(2, 0).start;
+        assert_eq!(chf.spans[lower].h, 5);
+        assert_eq!(chf.spans[step].con(2), None);
+        assert_eq!(chf.spans[lower + 1].h, 0xff);
+        assert_eq!(chf.areas, vec![RC_WALKABLE_AREA; 4]);
+    }
+
+    #[test]
+    fn test_erode_walkable_area() {
+        let mut hf = Heightfield::new(Vec3::ZERO, Vec3::new(7.0, 4.0, 7.0), 1.0, 0.1);
+        for z in 0..7 {
+            for x in 0..7 {
+                hf.add_span(x, z, 0, 2, RC_WALKABLE_AREA, 1);
+            }
+        }
+        let mut chf = CompactHeightfield::build(&hf, 10, 4);
+        chf.erode_walkable_area(2);
+
+        // Two cells in from the edge of the 7x7 floor is what's left
+        let walkable: Vec<(i32, i32)> = (0..7)
+            .flat_map(|z| (0..7).map(move |x| (x, z)))
+            .filter(|&(x, z)| chf.areas[chf.span_range(x, z).start] != RC_NULL_AREA)
+            .collect();
+        let expected: Vec<(i32, i32)> = (2..5).flat_map(|z| (2..5).map(move |x| (x, z))).collect();
+        assert_eq!(walkable, expected);
+    }
+}
@@ -0,0 +1,735 @@
+use glam::Vec3;
+
+use crate::build_context::BuildContext;
+use crate::compact_heightfield::CompactHeightfield;
+use crate::regions::RC_BORDER_REG;
+
+// Flags in the fourth component of contour vertices
+pub const RC_BORDER_VERTEX: i32 = 0x10000;
+pub const RC_AREA_BORDER: i32 = 0x20000;
+pub const RC_CONTOUR_REG_MASK: i32 = 0xffff;
+
+// Build flags for `build_contours`: split long outer edges, and long edges
+// between areas
+pub const RC_CONTOUR_TESS_WALL_EDGES: i32 = 0x01;
+pub const RC_CONTOUR_TESS_AREA_EDGES: i32 = 0x02;
+
+// Outline of one region. Vertices are (x, y, z, flags) in voxel units,
+// where the flags hold the region on the other side of the following edge.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Contour {
+    pub verts: Vec<[i32; 4]>,
+    // Unsimplified outline, kept for debugging and detail meshes
+    pub rverts: Vec<[i32; 4]>,
+    pub reg: u16,
+    pub area: u8,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ContourSet {
+    pub contours: Vec<Contour>,
+    pub bmin: Vec3,
+    pub bmax: Vec3,
+    pub cs: f32,
+    pub ch: f32,
+    // Grid size without the border
+    pub width: i32,
+    pub height: i32,
+    pub border_size: i32,
+    pub max_error: f32,
+}
+
+// Trace the outline of every region in `chf` and simplify it so no raw
+// vertex is further than `max_error` voxels from the result. Edges longer
+// than `max_edge_len` voxels are split as selected by `build_flags`.
+// Regions whose holes can't be merged are logged to `ctx`.
+pub fn build_contours(
+    chf: &CompactHeightfield,
+    max_error: f32,
+    max_edge_len: i32,
+    build_flags: i32,
+    ctx: &mut BuildContext,
+) -> ContourSet {
+    let (w, h) = (chf.width, chf.height);
+    let border_size = chf.border_size;
+
+    let mut cset = ContourSet {
+        contours: Vec::new(),
+        bmin: chf.bmin,
+        bmax: chf.bmax,
+        cs: chf.cs,
+        ch: chf.ch,
+        width: w - border_size * 2,
+        height: h - border_size * 2,
+        border_size,
+        max_error,
+    };
+    if border_size > 0 {
+        // The contours are offset below, so shrink the bounds to match
+        let pad = border_size as f32 * chf.cs;
+        cset.bmin += Vec3::new(pad, 0.0, pad);
+        cset.bmax -= Vec3::new(pad, 0.0, pad);
+    }
+
+    // Mark the edges of each span that face another region
+    let mut flags = vec![0u8; chf.span_count()];
+    for z in 0..h {
+        for x in 0..w {
+            for i in chf.span_range(x, z) {
+                let reg = chf.spans[i].reg;
+                if reg == 0 || reg & RC_BORDER_REG != 0 {
+                    continue;
+                }
+                let mut connected = 0u8;
+                for dir in 0..4 {
+                    let r = chf
+                        .neighbour(x, z, i, dir)
+                        .map_or(0, |(_, _, ai)| chf.spans[ai].reg);
+                    if r == reg {
+                        connected |= 1 << dir;
+                    }
+                }
+                flags[i] = connected ^ 0xf;
+            }
+        }
+    }
+
+    let mut verts = Vec::new();
+    let mut simplified = Vec::new();
+    for z in 0..h {
+        for x in 0..w {
+            for i in chf.span_range(x, z) {
+                if flags[i] == 0 || flags[i] == 0xf {
+                    flags[i] = 0;
+                    continue;
+                }
+                let reg = chf.spans[i].reg;
+                if reg == 0 || reg & RC_BORDER_REG != 0 {
+                    continue;
+                }
+
+                verts.clear();
+                walk_contour(chf, x, z, i, &mut flags, &mut verts);
+                simplified.clear();
+                simplify_contour(
+                    &verts,
+                    &mut simplified,
+                    max_error,
+                    max_edge_len,
+                    build_flags,
+                );
+                remove_degenerate_segments(&mut simplified);
+
+                if simplified.len() >= 3 {
+                    let unpad = |v: &[i32; 4]| [v[0] - border_size, v[1], v[2] - border_size, v[3]];
+                    cset.contours.push(Contour {
+                        verts: simplified.iter().map(unpad).collect(),
+                        rverts: verts.iter().map(unpad).collect(),
+                        reg,
+                        area: chf.areas[i],
+                    });
+                }
+            }
+        }
+    }
+
+    merge_holes(&mut cset, chf.max_regions, ctx);
+    cset
+}
+
+// Height of the corner at the clockwise end of edge `dir` of span `i`: the
+// highest floor among the four spans around it. Also reports whether the
+// corner lies on the tile border between two interior spans, in which
+// case it can be removed once tiles are stitched.
+fn corner_height(chf: &CompactHeightfield, x: i32, z: i32, i: usize, dir: usize) -> (i32, bool) {
+    let dirp = (dir + 1) & 3;
+    let mut height = chf.spans[i].y as i32;
+    // Region and area combined, so vertices between two areas are kept
+    let code = |i: usize| chf.spans[i].reg as u32 | (chf.areas[i] as u32) << 16;
+    let mut regs = [code(i), 0, 0, 0];
+
+    if let Some((ax, az, ai)) = chf.neighbour(x, z, i, dir) {
+        height = height.max(chf.spans[ai].y as i32);
+        regs[1] = code(ai);
+        if let Some((_, _, ai2)) = chf.neighbour(ax, az, ai, dirp) {
+            height = height.max(chf.spans[ai2].y as i32);
+            regs[2] = code(ai2);
+        }
+    }
+    if let Some((ax, az, ai)) = chf.neighbour(x, z, i, dirp) {
+        height = height.max(chf.spans[ai].y as i32);
+        regs[3] = code(ai);
+        if let Some((_, _, ai2)) = chf.neighbour(ax, az, ai, dir) {
+            height = height.max(chf.spans[ai2].y as i32);
+            regs[2] = code(ai2);
+        }
+    }
+
+    // Two identical border regions in a row followed by two interior
+    // spans of the same area
+    let border = RC_BORDER_REG as u32;
+    let is_border_vertex = (0..4).any(|j| {
+        let (a, b, c, d) = (
+            regs[j],
+            regs[(j + 1) & 3],
+            regs[(j + 2) & 3],
+            regs[(j + 3) & 3],
+        );
+        let two_same_exts = a & b & border != 0 && a == b;
+        let two_ints = (c | d) & border == 0;
+        let ints_same_area = c >> 16 == d >> 16;
+        let no_zeros = a != 0 && b != 0 && c != 0 && d != 0;
+        two_same_exts && two_ints && ints_same_area && no_zeros
+    });
+    (height, is_border_vertex)
+}
+
+// Follow the region boundary clockwise from span `i`, emitting a vertex at
+// every corner along with the region across the edge
+fn walk_contour(
+    chf: &CompactHeightfield,
+    mut x: i32,
+    mut z: i32,
+    mut i: usize,
+    flags: &mut [u8],
+    points: &mut Vec<[i32; 4]>,
+) {
+    // Start at the first edge facing another region
+    let mut dir = (0..4).find(|&dir| flags[i] & (1 << dir) != 0).unwrap_or(0);
+    let start_dir = dir;
+    let start_i = i;
+    let area = chf.areas[i];
+
+    for _ in 1..40000 {
+        if flags[i] & (1 << dir) != 0 {
+            let (py, is_border_vertex) = corner_height(chf, x, z, i, dir);
+            let (px, pz) = match dir {
+                0 => (x, z + 1),
+                1 => (x + 1, z + 1),
+                2 => (x + 1, z),
+                _ => (x, z),
+            };
+
+            let mut r = 0;
+            if let Some((_, _, ai)) = chf.neighbour(x, z, i, dir) {
+                r = chf.spans[ai].reg as i32;
+                if chf.areas[ai] != area {
+                    r |= RC_AREA_BORDER;
+                }
+            }
+            if is_border_vertex {
+                r |= RC_BORDER_VERTEX;
+            }
+            points.push([px, py, pz, r]);
+
+            // Visited, then rotate clockwise
+            flags[i] &= !(1 << dir);
+            dir = (dir + 1) & 3;
+        } else {
+            let Some((nx, nz, ni)) = chf.neighbour(x, z, i, dir) else {
+                // Not a boundary edge, so the neighbour must exist
+                return;
+            };
+            x = nx;
+            z = nz;
+            i = ni;
+            // Rotate counter-clockwise
+            dir = (dir + 3) & 3;
+        }
+
+        if start_i == i && start_dir == dir {
+            break;
+        }
+    }
+}
+
+// Squared distance from (x, z) to the segment p-q
+fn distance_pt_seg(x: i32, z: i32, px: i32, pz: i32, qx: i32, qz: i32) -> f32 {
+    let pqx = (qx - px) as f32;
+    let pqz = (qz - pz) as f32;
+    let dx = (x - px) as f32;
+    let dz = (z - pz) as f32;
+    let d = pqx * pqx + pqz * pqz;
+    let mut t = pqx * dx + pqz * dz;
+    if d > 0.0 {
+        t /= d;
+    }
+    let t = t.clamp(0.0, 1.0);
+
+    let dx = px as f32 + t * pqx - x as f32;
+    let dz = pz as f32 + t * pqz - z as f32;
+    dx * dx + dz * dz
+}
+
+// Douglas-Peucker style simplification of a raw contour. The fourth
+// component of `simplified` temporarily holds the raw vertex index.
+fn simplify_contour(
+    points: &[[i32; 4]],
+    simplified: &mut Vec<[i32; 4]>,
+    max_error: f32,
+    max_edge_len: i32,
+    build_flags: i32,
+) {
+    let pn = points.len();
+
+    // Keep every vertex where the neighbouring region or area changes
+    let has_connections = points.iter().any(|p| p[3] & RC_CONTOUR_REG_MASK != 0);
+    if has_connections {
+        for i in 0..pn {
+            let ii = (i + 1) % pn;
+            let different_regs =
+                points[i][3] & RC_CONTOUR_REG_MASK != points[ii][3] & RC_CONTOUR_REG_MASK;
+            let area_borders = points[i][3] & RC_AREA_BORDER != points[ii][3] & RC_AREA_BORDER;
+            if different_regs || area_borders {
+                simplified.push([points[i][0], points[i][1], points[i][2], i as i32]);
+            }
+        }
+    }
+
+    if simplified.is_empty() {
+        // No portals at all: seed with the lower-left and upper-right
+        // vertices
+        let mut ll = 0;
+        let mut ur = 0;
+        for (i, p) in points.iter().enumerate() {
+            let (x, z) = (p[0], p[2]);
+            if x < points[ll][0] || (x == points[ll][0] && z < points[ll][2]) {
+                ll = i;
+            }
+            if x > points[ur][0] || (x == points[ur][0] && z > points[ur][2]) {
+                ur = i;
+            }
+        }
+        for i in [ll, ur] {
+            simplified.push([points[i][0], points[i][1], points[i][2], i as i32]);
+        }
+    }
+
+    // Add the furthest raw point of each segment until all are within
+    // tolerance
+    let mut i = 0;
+    while i < simplified.len() {
+        let ii = (i + 1) % simplified.len();
+        let (mut ax, mut az, ai) = (
+            simplified[i][0],
+            simplified[i][2],
+            simplified[i][3] as usize,
+        );
+        let (mut bx, mut bz, bi) = (
+            simplified[ii][0],
+            simplified[ii][2],
+            simplified[ii][3] as usize,
+        );
+
+        // Walk the segment in lexicographic order so opposite segments of
+        // neighbouring regions come out the same
+        let (cinc, mut ci, endi) = if bx > ax || (bx == ax && bz > az) {
+            (1, (ai + 1) % pn, bi)
+        } else {
+            std::mem::swap(&mut ax, &mut bx);
+            std::mem::swap(&mut az, &mut bz);
+            (pn - 1, (bi + pn - 1) % pn, ai)
+        };
+
+        let mut maxd = 0.0;
+        let mut maxi = None;
+        // Only outer edges and edges between areas are tessellated
+        if points[ci][3] & RC_CONTOUR_REG_MASK == 0 || points[ci][3] & RC_AREA_BORDER != 0 {
+            while ci != endi {
+                let d = distance_pt_seg(points[ci][0], points[ci][2], ax, az, bx, bz);
+                if d > maxd {
+                    maxd = d;
+                    maxi = Some(ci);
+                }
+                ci = (ci + cinc) % pn;
+            }
+        }
+
+        match maxi {
+            Some(maxi) if maxd > max_error * max_error => {
+                let p = points[maxi];
+                simplified.insert(i + 1, [p[0], p[1], p[2], maxi as i32]);
+            }
+            _ => i += 1,
+        }
+    }
+
+    // Split edges that are too long
+    let tess_walls = build_flags & RC_CONTOUR_TESS_WALL_EDGES != 0;
+    let tess_areas = build_flags & RC_CONTOUR_TESS_AREA_EDGES != 0;
+    if max_edge_len > 0 && (tess_walls || tess_areas) {
+        let mut i = 0;
+        while i < simplified.len() {
+            let ii = (i + 1) % simplified.len();
+            let (ax, az, ai) = (
+                simplified[i][0],
+                simplified[i][2],
+                simplified[i][3] as usize,
+            );
+            let (bx, bz, bi) = (
+                simplified[ii][0],
+                simplified[ii][2],
+                simplified[ii][3] as usize,
+            );
+
+            let ci = (ai + 1) % pn;
+            let tess = (tess_walls && points[ci][3] & RC_CONTOUR_REG_MASK == 0)
+                || (tess_areas && points[ci][3] & RC_AREA_BORDER != 0);
+
+            let mut maxi = None;
+            if tess {
+                let (dx, dz) = (bx - ax, bz - az);
+                if dx * dx + dz * dz > max_edge_len * max_edge_len {
+                    // Round the same way whichever direction the segment
+                    // is walked
+                    let n = if bi < ai { bi + pn - ai } else { bi - ai };
+                    if n > 1 {
+                        maxi = Some(if bx > ax || (bx == ax && bz > az) {
+                            (ai + n / 2) % pn
+                        } else {
+                            (ai + n.div_ceil(2)) % pn
+                        });
+                    }
+                }
+            }
+
+            match maxi {
+                Some(maxi) => {
+                    let p = points[maxi];
+                    simplified.insert(i + 1, [p[0], p[1], p[2], maxi as i32]);
+                }
+                None => i += 1,
+            }
+        }
+    }
+
+    // The neighbour region comes from the raw vertex after each simplified
+    // vertex, the border flag from the vertex itself
+    for v in simplified.iter_mut() {
+        let ai = (v[3] as usize + 1) % pn;
+        let bi = v[3] as usize;
+        v[3] = (points[ai][3] & (RC_CONTOUR_REG_MASK | RC_AREA_BORDER))
+            | (points[bi][3] & RC_BORDER_VERTEX);
+    }
+}
+
+// Drop vertices equal to the next one on the XZ plane; they confuse the
+// triangulator
+fn remove_degenerate_segments(simplified: &mut Vec<[i32; 4]>) {
+    let mut i = 0;
+    while i < simplified.len() {
+        let ni = (i + 1) % simplified.len();
+        if vequal(&simplified[i], &simplified[ni]) {
+            simplified.remove(i);
+        }
+        i += 1;
+    }
+}
+
+// Twice the signed area on the XZ plane; negative for holes
+fn calc_area_of_polygon_2d(verts: &[[i32; 4]]) -> i32 {
+    let mut area = 0;
+    let mut j = verts.len() - 1;
+    for (i, vi) in verts.iter().enumerate() {
+        let vj = &verts[j];
+        area += vi[0] * vj[2] - vj[0] * vi[2];
+        j = i;
+    }
+    (area + 1) / 2
+}
+
+// Connect each hole contour to the outline of its region with a zero-width
+// bridge, so every region ends up as a single polygon
+fn merge_holes(cset: &mut ContourSet, max_regions: u16, ctx: &mut BuildContext) {
+    let is_hole: Vec<bool> = cset
+        .contours
+        .iter()
+        .map(|c| calc_area_of_polygon_2d(&c.verts) < 0)
+        .collect();
+    if !is_hole.contains(&true) {
+        return;
+    }
+
+    let mut outlines = vec![None; max_regions as usize + 1];
+    let mut holes = vec![Vec::new(); max_regions as usize + 1];
+    for (i, contour) in cset.contours.iter().enumerate() {
+        let reg = contour.reg as usize;
+        if is_hole[i] {
+            holes[reg].push(i);
+        } else if outlines[reg].is_some() {
+            ctx.warn(format!(
+                "build_contours: multiple outlines for region {}",
+                reg
+            ));
+        } else {
+            outlines[reg] = Some(i);
+        }
+    }
+
+    for (reg, holes) in holes.into_iter().enumerate() {
+        if holes.is_empty() {
+            continue;
+        }
+        match outlines[reg] {
+            Some(outline) => merge_region_holes(&mut cset.contours, outline, holes, ctx),
+            None => ctx.warn(format!(
+                "build_contours: missing outline for region {}",
+                reg
+            )),
+        }
+    }
+}
+
+fn merge_region_holes(
+    contours: &mut [Contour],
+    outline: usize,
+    holes: Vec<usize>,
+    ctx: &mut BuildContext,
+) {
+    // Merge from left to right, starting each hole at its leftmost vertex
+    let leftmost = |c: &Contour| {
+        (0..c.verts.len())
+            .min_by_key(|&i| (c.verts[i][0], c.verts[i][2]))
+            .unwrap_or(0)
+    };
+    let mut holes: Vec<(usize, usize)> = holes
+        .into_iter()
+        .map(|h| (h, leftmost(&contours[h])))
+        .collect();
+    holes.sort_by_key(|&(h, l)| (contours[h].verts[l][0], contours[h].verts[l][2]));
+
+    for (i, &(hole, leftmost)) in holes.iter().enumerate() {
+        let hole_len = contours[hole].verts.len();
+        let mut best_vertex = leftmost;
+        let mut index = None;
+
+        for _ in 0..hole_len {
+            // Candidate outline vertices must see the hole corner from
+            // inside their cone; try the closest first
+            let corner = contours[hole].verts[best_vertex];
+            let outline_verts = &contours[outline].verts;
+            let mut diags: Vec<(usize, i32)> = (0..outline_verts.len())
+                .filter(|&j| in_cone(j, outline_verts, &corner))
+                .map(|j| {
+                    let dx = outline_verts[j][0] - corner[0];
+                    let dz = outline_verts[j][2] - corner[2];
+                    (j, dx * dx + dz * dz)
+                })
+                .collect();
+            diags.sort_by_key(|&(_, dist)| dist);
+
+            // The bridge must not cross the outline or any remaining hole
+            index = diags.into_iter().map(|(j, _)| j).find(|&j| {
+                let pt = outline_verts[j];
+                !intersect_seg_contour(&pt, &corner, Some(j), outline_verts)
+                    && holes[i..].iter().all(|&(k, _)| {
+                        !intersect_seg_contour(&pt, &corner, None, &contours[k].verts)
+                    })
+            });
+            if index.is_some() {
+                break;
+            }
+            best_vertex = (best_vertex + 1) % hole_len;
+        }
+
+        let Some(index) = index else {
+            ctx.warn(format!(
+                "build_contours: failed to find merge points for a hole in region {}",
+                contours[outline].reg
+            ));
+            continue;
+        };
+
+        // Walk the outline from the bridge vertex, across to the hole, around
+        // it and back
+        let outline_verts = &contours[outline].verts;
+        let hole_verts = &contours[hole].verts;
+        let mut merged = Vec::with_capacity(outline_verts.len() + hole_verts.len() + 2);
+        for k in 0..=outline_verts.len() {
+            merged.push(outline_verts[(index + k) % outline_verts.len()]);
+        }
+        for k in 0..=hole_verts.len() {
+            merged.push(hole_verts[(best_vertex + k) % hole_verts.len()]);
+        }
+        contours[outline].verts = merged;
+        contours[hole].verts.clear();
+    }
+}
+
+// Predicates on the XZ plane of integer vertices, from O'Rourke's
+// "Computational Geometry in C", shared with polygon mesh building
+
+pub(crate) fn area2(a: &[i32], b: &[i32], c: &[i32]) -> i32 {
+    (b[0] - a[0]) * (c[2] - a[2]) - (c[0] - a[0]) * (b[2] - a[2])
+}
+
+// c is strictly left of the directed line a-b
+pub(crate) fn left(a: &[i32], b: &[i32], c: &[i32]) -> bool {
+    area2(a, b, c) < 0
+}
+
+pub(crate) fn left_on(a: &[i32], b: &[i32], c: &[i32]) -> bool {
+    area2(a, b, c) <= 0
+}
+
+fn collinear(a: &[i32], b: &[i32], c: &[i32]) -> bool {
+    area2(a, b, c) == 0
+}
+
+pub(crate) fn vequal(a: &[i32], b: &[i32]) -> bool {
+    a[0] == b[0] && a[2] == b[2]
+}
+
+// Segments a-b and c-d cross at a point interior to both
+pub(crate) fn intersect_prop(a: &[i32], b: &[i32], c: &[i32], d: &[i32]) -> bool {
+    if collinear(a, b, c) || collinear(a, b, d) || collinear(c, d, a) || collinear(c, d, b) {
+        return false;
+    }
+    (left(a, b, c) ^ left(a, b, d)) && (left(c, d, a) ^ left(c, d, b))
+}
+
+// c is on the closed segment a-b
+fn between(a: &[i32], b: &[i32], c: &[i32]) -> bool {
+    if !collinear(a, b, c) {
+        return false;
+    }
+    if a[0] != b[0] {
+        (a[0] <= c[0] && c[0] <= b[0]) || (a[0] >= c[0] && c[0] >= b[0])
+    } else {
+        (a[2] <= c[2] && c[2] <= b[2]) || (a[2] >= c[2] && c[2] >= b[2])
+    }
+}
+
+// Segments a-b and c-d intersect, properly or improperly
+pub(crate) fn intersect(a: &[i32], b: &[i32], c: &[i32], d: &[i32]) -> bool {
+    intersect_prop(a, b, c, d)
+        || between(a, b, c)
+        || between(a, b, d)
+        || between(c, d, a)
+        || between(c, d, b)
+}
+
+// Segment d0-d1 crosses an edge of the contour, ignoring edges at vertex `skip`
+fn intersect_seg_contour(d0: &[i32], d1: &[i32], skip: Option<usize>, verts: &[[i32; 4]]) -> bool {
+    let n = verts.len();
+    for k in 0..n {
+        let k1 = (k + 1) % n;
+        if skip == Some(k) || skip == Some(k1) {
+            continue;
+        }
+        let (p0, p1) = (&verts[k], &verts[k1]);
+        if vequal(d0, p0) || vequal(d1, p0) || vequal(d0, p1) || vequal(d1, p1) {
+            continue;
+        }
+        if intersect(d0, d1, p0, p1) {
+            return true;
+        }
+    }
+    false
+}
+
+// pj lies inside the cone formed by vertex i and its neighbours
+fn in_cone(i: usize, verts: &[[i32; 4]], pj: &[i32]) -> bool {
+    let n = verts.len();
+    let pi = &verts[i];
+    let pi1 = &verts[(i + 1) % n];
+    let pin1 = &verts[(i + n - 1) % n];
+
+    // Convex vertex: pj must be left of both edges
+    if left_on(pin1, pi, pi1) {
+        return left(pi, pj, pin1) && left(pj, pi, pi1);
+    }
+    // Reflex vertex
+    !(left_on(pi, pj, pi1) && left_on(pj, pi, pin1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debug_draw::RC_WALKABLE_AREA;
+    use crate::heightfield::Heightfield;
+    use crate::regions::build_regions_watershed;
+
+    // A walkable floor with the cells marked '#' left empty
+    fn chf_from_map(map: &[&str]) -> CompactHeightfield {
+        let (w, h) = (map[0].len() as f32, map.len() as f32);
+        let mut hf = Heightfield::new(Vec3::ZERO, Vec3::new(w, 10.0, h), 1.0, 0.1);
+        for (z, row) in map.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                if c != '#' {
+                    hf.add_span(x as i32, z as i32, 0, 1, RC_WALKABLE_AREA, 1);
+                }
+            }
+        }
+        let mut chf = CompactHeightfield::build(&hf, 10, 2);
+        build_regions_watershed(&mut chf, 0, 1, 0);
+        chf
+    }
+
+    #[test]
+    fn test_square_contour() {
+        let chf = chf_from_map(&["....", "....", "....", "...."]);
+        let cset = build_contours(&chf, 1.3, 0, 0, &mut BuildContext::new());
+
+        assert_eq!(cset.contours.len(), 1);
+        let contour = &cset.contours[0];
+        // The raw outline has a vertex per boundary edge; simplification
+        // leaves only the four corners
+        assert_eq!(contour.rverts.len(), 16);
+        let mut corners: Vec<(i32, i32)> = contour.verts.iter().map(|v| (v[0], v[2])).collect();
+        corners.sort_unstable();
+        assert_eq!(corners, vec![(0, 0), (0, 4), (4, 0), (4, 4)]);
+        assert!(calc_area_of_polygon_2d(&contour.verts) > 0);
+        assert_eq!(contour.area, RC_WALKABLE_AREA);
+    }
+
+    #[test]
+    fn test_long_edges_are_split() {
+        let chf = chf_from_map(&[".........."; 4]);
+        let cset = build_contours(
+            &chf,
+            1.3,
+            4,
+            RC_CONTOUR_TESS_WALL_EDGES,
+            &mut BuildContext::new(),
+        );
+        let verts = &cset.contours[0].verts;
+        assert!(verts.len() > 4);
+        for (i, a) in verts.iter().enumerate() {
+            let b = verts[(i + 1) % verts.len()];
+            let (dx, dz) = (b[0] - a[0], b[2] - a[2]);
+            assert!(dx * dx + dz * dz <= 16);
+        }
+    }
+
+    #[test]
+    fn test_hole_is_merged_into_outline() {
+        let chf = chf_from_map(&[
+            ".......", ".......", "..###..", "..###..", "..###..", ".......", ".......",
+        ]);
+        let cset = build_contours(&chf, 1.3, 0, 0, &mut BuildContext::new());
+
+        // Whatever the region layout, no contour is left as a separate hole
+        let outlines: Vec<&Contour> = cset
+            .contours
+            .iter()
+            .filter(|c| !c.verts.is_empty())
+            .collect();
+        assert!(!outlines.is_empty());
+        for c in outlines {
+            assert!(calc_area_of_polygon_2d(&c.verts) > 0);
+        }
+    }
+
+    #[test]
+    fn test_intersection_predicates() {
+        let p = |x, z| [x, 0, z];
+        assert!(intersect(&p(0, 0), &p(2, 2), &p(0, 2), &p(2, 0)));
+        assert!(!intersect(&p(0, 0), &p(1, 0), &p(0, 1), &p(1, 1)));
+        // Touching at an endpoint is an improper intersection
+        assert!(intersect(&p(0, 0), &p(2, 0), &p(1, 0), &p(1, 1)));
+        assert!(!intersect_prop(&p(0, 0), &p(2, 0), &p(1, 0), &p(1, 1)));
+    }
+}
@@ -0,0 +1,410 @@
+use glam::Vec3;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::compact_heightfield::CompactHeightfield;
+use crate::debug_draw::{RC_NULL_AREA, RC_WALKABLE_AREA};
+
+// A prism marking the walkable surface inside it with a custom area id,
+// e.g. water or road. The footprint is a convex polygon on the XZ plane.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConvexVolume {
+    pub verts: Vec<Vec3>,
+    pub hmin: f32,
+    pub hmax: f32,
+    pub area: u8,
+}
+
+impl ConvexVolume {
+    // A volume around the convex hull of `points`, reaching `descent` below
+    // the lowest point and `height` above that
+    pub fn from_points(points: &[Vec3], height: f32, descent: f32, area: u8) -> Option<Self> {
+        let hull = convex_hull(points);
+        if hull.len() < 3 {
+            return None;
+        }
+        let hmin = hull.iter().map(|p| p.y).fold(f32::MAX, f32::min) - descent;
+        Some(Self {
+            verts: hull,
+            hmin,
+            hmax: hmin + height,
+            area,
+        })
+    }
+}
+
+// One `v nverts area hmin hmax` line per volume followed by a `x y z` line
+// per footprint vertex, as in the volume section of Recast's .gset files
+pub fn volumes_to_text(volumes: &[ConvexVolume]) -> String {
+    let mut text = String::new();
+    for vol in volumes {
+        let _ = writeln!(
+            text,
+            "v {} {} {} {}",
+            vol.verts.len(),
+            vol.area,
+            vol.hmin,
+            vol.hmax
+        );
+        for v in &vol.verts {
+            let _ = writeln!(text, "{} {} {}", v.x, v.y, v.z);
+        }
+    }
+    text
+}
+
+// Parse `volumes_to_text` output; blank lines and `#` comments are skipped
+pub fn volumes_from_text(text: &str) -> Result<Vec<ConvexVolume>, String> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+    let mut volumes = Vec::new();
+    while let Some((line_no, line)) = lines.next() {
+        let error = |msg: &str| format!("line {}: {}", line_no, msg);
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let ["v", nverts, area, hmin, hmax] = fields[..] else {
+            return Err(error("expected `v nverts area hmin hmax`"));
+        };
+        let nverts: usize = nverts
+            .parse()
+            .map_err(|_| error("vertex count must be a number"))?;
+        if nverts < 3 {
+            return Err(error("a volume needs at least 3 vertices"));
+        }
+        let area: u8 = area.parse().map_err(|_| error("area must be a number"))?;
+        if area > RC_WALKABLE_AREA {
+            return Err(error("area must be at most 63"));
+        }
+        let (Ok(hmin), Ok(hmax)) = (hmin.parse::<f32>(), hmax.parse::<f32>()) else {
+            return Err(error("heights must be numbers"));
+        };
+
+        let mut verts = Vec::with_capacity(nverts);
+        for _ in 0..nverts {
+            let (line_no, line) = lines
+                .next()
+                .ok_or_else(|| error("volume ends before its vertices"))?;
+            let coords: Vec<f32> = line
+                .split_whitespace()
+                .map(str::parse)
+                .collect::<Result<_, _>>()
+                .map_err(|_| format!("line {}: expected `x y z`", line_no))?;
+            let [x, y, z] = coords[..] else {
+                return Err(format!("line {}: expected `x y z`", line_no));
+            };
+            verts.push(Vec3::new(x, y, z));
+        }
+        volumes.push(ConvexVolume {
+            verts,
+            hmin,
+            hmax,
+            area,
+        });
+    }
+    Ok(volumes)
+}
+
+pub fn save_volumes<P: AsRef<Path>>(path: P, volumes: &[ConvexVolume]) -> io::Result<()> {
+    fs::write(path, volumes_to_text(volumes))
+}
+
+pub fn load_volumes<P: AsRef<Path>>(path: P) -> io::Result<Vec<ConvexVolume>> {
+    let text = fs::read_to_string(path)?;
+    volumes_from_text(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+// Where the volumes of a mesh file are kept: next to it, as `<name>.volumes`
+pub fn volumes_path(mesh_path: &Path) -> PathBuf {
+    mesh_path.with_extension("volumes")
+}
+
+// Set the area of every walkable span whose floor lies within the box
+pub fn mark_box_area(chf: &mut CompactHeightfield, bmin: Vec3, bmax: Vec3, area: u8) {
+    mark_area(chf, bmin, bmax, area, |_| true);
+}
+
+// Set the area of every walkable span whose floor lies inside the convex
+// polygon `verts` on the XZ plane and between `hmin` and `hmax`
+pub fn mark_convex_poly_area(
+    chf: &mut CompactHeightfield,
+    verts: &[Vec3],
+    hmin: f32,
+    hmax: f32,
+    area: u8,
+) {
+    let (mut bmin, mut bmax) = verts.iter().fold(
+        (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+        |(min, max), &v| (min.min(v), max.max(v)),
+    );
+    bmin.y = hmin;
+    bmax.y = hmax;
+    mark_area(chf, bmin, bmax, area, |p| point_in_poly(verts, p));
+}
+
+// Set the area of every walkable span whose floor lies inside the upright
+// cylinder standing on `pos`
+pub fn mark_cylinder_area(
+    chf: &mut CompactHeightfield,
+    pos: Vec3,
+    radius: f32,
+    height: f32,
+    area: u8,
+) {
+    let bmin = pos - Vec3::new(radius, 0.0, radius);
+    let bmax = pos + Vec3::new(radius, height, radius);
+    mark_area(chf, bmin, bmax, area, |p| {
+        let (dx, dz) = (p.x - pos.x, p.z - pos.z);
+        dx * dx + dz * dz <= radius * radius
+    });
+}
+
+// Set the area of every walkable span whose floor lies inside the box
+// centred on `center`, turned by `y_rotation` radians around the Y axis
+pub fn mark_rotated_box_area(
+    chf: &mut CompactHeightfield,
+    center: Vec3,
+    half_extents: Vec3,
+    y_rotation: f32,
+    area: u8,
+) {
+    // Bounds of the turned footprint
+    let (sin, cos) = y_rotation.sin_cos();
+    let reach = Vec3::new(
+        half_extents.x * cos.abs() + half_extents.z * sin.abs(),
+        half_extents.y,
+        half_extents.x * sin.abs() + half_extents.z * cos.abs(),
+    );
+    mark_area(chf, center - reach, center + reach, area, |p| {
+        // Turn the cell centre back into the box frame
+        let (dx, dz) = (p.x - center.x, p.z - center.z);
+        let lx = dx * cos - dz * sin;
+        let lz = dx * sin + dz * cos;
+        lx.abs() <= half_extents.x && lz.abs() <= half_extents.z
+    });
+}
+
+// Stamp `area` on spans within the box whose cell centre passes `inside`
+fn mark_area(
+    chf: &mut CompactHeightfield,
+    bmin: Vec3,
+    bmax: Vec3,
+    area: u8,
+    inside: impl Fn(Vec3) -> bool,
+) {
+    let min = ((bmin - chf.bmin) / Vec3::new(chf.cs, chf.ch, chf.cs)).as_ivec3();
+    let max = ((bmax - chf.bmin) / Vec3::new(chf.cs, chf.ch, chf.cs)).as_ivec3();
+    if max.x < 0 || min.x >= chf.width || max.z < 0 || min.z >= chf.height {
+        return;
+    }
+
+    for z in min.z.max(0)..=max.z.min(chf.height - 1) {
+        for x in min.x.max(0)..=max.x.min(chf.width - 1) {
+            let centre = Vec3::new(
+                chf.bmin.x + (x as f32 + 0.5) * chf.cs,
+                0.0,
+                chf.bmin.z + (z as f32 + 0.5) * chf.cs,
+            );
+            for i in chf.span_range(x, z) {
+                if chf.areas[i] == RC_NULL_AREA {
+                    continue;
+                }
+                let y = chf.spans[i].y as i32;
+                if y >= min.y && y <= max.y && inside(centre) {
+                    chf.areas[i] = area;
+                }
+            }
+        }
+    }
+}
+
+// Crossing test on the XZ plane
+fn point_in_poly(verts: &[Vec3], p: Vec3) -> bool {
+    let n = verts.len();
+    let mut inside = false;
+    for i in 0..n {
+        let (vi, vj) = (verts[i], verts[(i + n - 1) % n]);
+        if (vi.z > p.z) != (vj.z > p.z) && p.x < (vj.x - vi.x) * (p.z - vi.z) / (vj.z - vi.z) + vi.x
+        {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+// Gift-wrapped convex hull of the points on the XZ plane, keeping the
+// original heights
+pub fn convex_hull(points: &[Vec3]) -> Vec<Vec3> {
+    // Leftmost point, lowest z breaking ties, is always on the hull
+    let Some(start) = (0..points.len()).min_by(|&a, &b| {
+        let (pa, pb) = (points[a], points[b]);
+        pa.x.total_cmp(&pb.x).then(pa.z.total_cmp(&pb.z))
+    }) else {
+        return Vec::new();
+    };
+
+    // Whether c lies to the right of a-b, or further along it when collinear
+    let cmp = |a: Vec3, b: Vec3, c: Vec3| {
+        let cross = (b.x - a.x) * (c.z - a.z) - (c.x - a.x) * (b.z - a.z);
+        if cross.abs() < 1e-6 {
+            (c - a).length_squared() > (b - a).length_squared()
+        } else {
+            cross < 0.0
+        }
+    };
+
+    let mut hull = Vec::new();
+    let mut current = start;
+    loop {
+        hull.push(points[current]);
+        let mut next = (current + 1) % points.len();
+        for i in 0..points.len() {
+            if cmp(points[current], points[next], points[i]) {
+                next = i;
+            }
+        }
+        current = next;
+        if current == start || hull.len() > points.len() {
+            break;
+        }
+    }
+    hull
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heightfield::Heightfield;
+
+    const WATER: u8 = 5;
+
+    // Two floors over a 10x10 grid of unit cells: one at height 1, one at 6
+    fn two_floors() -> CompactHeightfield {
+        let mut hf = Heightfield::new(Vec3::ZERO, Vec3::new(10.0, 10.0, 10.0), 1.0, 0.5);
+        for z in 0..10 {
+            for x in 0..10 {
+                hf.add_span(x, z, 0, 2, RC_WALKABLE_AREA, 1);
+                hf.add_span(x, z, 10, 12, RC_WALKABLE_AREA, 1);
+            }
+        }
+        CompactHeightfield::build(&hf, 4, 1)
+    }
+
+    fn marked(chf: &CompactHeightfield) -> Vec<(i32, i32, usize)> {
+        let mut cells = Vec::new();
+        for z in 0..chf.height {
+            for x in 0..chf.width {
+                for (layer, i) in chf.span_range(x, z).enumerate() {
+                    if chf.areas[i] == WATER {
+                        cells.push((x, z, layer));
+                    }
+                }
+            }
+        }
+        cells
+    }
+
+    #[test]
+    fn test_mark_box_area() {
+        let mut chf = two_floors();
+        mark_box_area(
+            &mut chf,
+            Vec3::new(2.0, 0.0, 3.0),
+            Vec3::new(3.5, 2.0, 3.5),
+            WATER,
+        );
+        // Only the lower floor, in columns 2..=3 of row 3
+        assert_eq!(marked(&chf), vec![(2, 3, 0), (3, 3, 0)]);
+    }
+
+    #[test]
+    fn test_mark_convex_poly_area() {
+        let mut chf = two_floors();
+        // A triangle over the upper floor covering the cells whose centres
+        // satisfy x + z < 4
+        let tri = [
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 5.0),
+            Vec3::new(5.0, 0.0, 0.0),
+        ];
+        mark_convex_poly_area(&mut chf, &tri, 4.0, 7.0, WATER);
+
+        let cells = marked(&chf);
+        assert!(cells.iter().all(|&(x, z, layer)| x + z < 4 && layer == 1));
+        assert_eq!(cells.len(), 10);
+    }
+
+    #[test]
+    fn test_convex_hull_drops_inner_points() {
+        let points = [
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(1.0, 0.0, 1.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(2.0, 0.0, 2.0),
+            Vec3::new(0.0, 0.5, 2.0),
+        ];
+        let hull = convex_hull(&points);
+        assert_eq!(hull.len(), 4);
+        assert!(!hull.contains(&points[1]));
+
+        let volume = ConvexVolume::from_points(&points, 3.0, 1.0, WATER).unwrap();
+        assert_eq!((volume.hmin, volume.hmax), (-1.0, 2.0));
+        assert!(ConvexVolume::from_points(&points[..2], 3.0, 1.0, WATER).is_none());
+    }
+
+    #[test]
+    fn test_volumes_text_round_trip() {
+        let points = [
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(2.5, 0.0, 0.0),
+            Vec3::new(2.5, 0.25, 2.0),
+        ];
+        let volumes = vec![
+            ConvexVolume::from_points(&points, 3.0, 0.1, WATER).unwrap(),
+            ConvexVolume::from_points(&points[..3], 1.5, 0.0, 1).unwrap(),
+        ];
+        let text = volumes_to_text(&volumes);
+        assert_eq!(volumes_from_text(&text).unwrap(), volumes);
+        assert_eq!(
+            volumes_from_text(&format!("# saved\n\n{}", text)).unwrap(),
+            volumes
+        );
+
+        assert!(volumes_from_text("v 3 5 0 1\n0 0 0\n1 0 0\n").is_err());
+        assert!(volumes_from_text("v 2 5 0 1\n0 0 0\n1 0 0\n").is_err());
+        assert!(volumes_from_text("v 3 64 0 1\n0 0 0\n1 0 0\n0 0 1\n").is_err());
+        assert!(volumes_from_text("v 3 5 0 1\n0 0 0\n1 0\n0 0 1\n").is_err());
+        assert_eq!(
+            volumes_path(Path::new("levels/dungeon.obj")),
+            PathBuf::from("levels/dungeon.volumes")
+        );
+    }
+
+    #[test]
+    fn test_mark_cylinder_and_rotated_box() {
+        let mut chf = two_floors();
+        // Radius 1.2 around the corner shared by cells (4..=5, 4..=5)
+        mark_cylinder_area(&mut chf, Vec3::new(5.0, 0.0, 5.0), 1.2, 2.0, WATER);
+        let mut cells = marked(&chf);
+        cells.sort();
+        assert_eq!(cells, vec![(4, 4, 0), (4, 5, 0), (5, 4, 0), (5, 5, 0)]);
+
+        // A 4x0.5 bar turned a quarter turn covers one column, not one row
+        let mut chf = two_floors();
+        mark_rotated_box_area(
+            &mut chf,
+            Vec3::new(2.5, 6.0, 5.0),
+            Vec3::new(2.0, 1.0, 0.25),
+            std::f32::consts::FRAC_PI_2,
+            WATER,
+        );
+        let cells = marked(&chf);
+        assert_eq!(cells.len(), 4);
+        assert!(cells.iter().all(|&(x, _, layer)| x == 2 && layer == 1));
+    }
+}
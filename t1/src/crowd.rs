@@ -0,0 +1,542 @@
+use glam::Vec3;
+
+use crate::local_boundary::LocalBoundary;
+use crate::nav_mesh::{NavMesh, NavMeshError};
+use crate::nav_mesh_query::{NavMeshQuery, StraightPathPoint, DT_STRAIGHTPATH_OFFMESH_CONNECTION};
+use crate::obstacle_avoidance::{AvoidanceDebugData, AvoidanceQuality, ObstacleAvoidanceQuery};
+use crate::path_corridor::PathCorridor;
+use crate::proximity_grid::ProximityGrid;
+use crate::query_filter::QueryFilter;
+
+// Per-agent limits of the steering, as in Detour
+const MAX_NEIGHBOURS: usize = 6;
+const MAX_CORNERS: usize = 4;
+const MAX_PATH: usize = 256;
+const MAX_QUERY_NODES: usize = 512;
+// Passes of the agent-agent overlap resolution, and how much of the
+// overlap each one removes
+const COLLISION_ITERATIONS: usize = 4;
+const COLLISION_RESOLVE_FACTOR: f32 = 0.7;
+
+// Size and steering settings of one agent. The defaults are those of the
+// Recast demo.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CrowdAgentParams {
+    pub radius: f32,
+    pub height: f32,
+    pub max_acceleration: f32,
+    pub max_speed: f32,
+    // How far neighbours and walls are looked for
+    pub collision_query_range: f32,
+    // How far ahead the corridor is shortcut when a corner is in sight
+    pub path_optimization_range: f32,
+    // How hard agents push apart when closer than the query range, 0 for
+    // not at all
+    pub separation_weight: f32,
+    pub avoidance: AvoidanceQuality,
+}
+
+impl CrowdAgentParams {
+    // Defaults scaled to an agent of `radius` and `height`
+    pub fn new(radius: f32, height: f32) -> Self {
+        Self {
+            radius,
+            height,
+            max_acceleration: 8.0,
+            max_speed: 3.5,
+            collision_query_range: radius * 12.0,
+            path_optimization_range: radius * 30.0,
+            separation_weight: 2.0,
+            avoidance: AvoidanceQuality::default(),
+        }
+    }
+}
+
+impl Default for CrowdAgentParams {
+    fn default() -> Self {
+        Self::new(0.6, 2.0)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CrowdAgent {
+    pub params: CrowdAgentParams,
+    corridor: PathCorridor,
+    boundary: LocalBoundary,
+    neighbours: Vec<usize>,
+    corners: Vec<StraightPathPoint>,
+    // Position, actual, desired and avoidance-corrected velocity
+    npos: Vec3,
+    vel: Vec3,
+    dvel: Vec3,
+    nvel: Vec3,
+    // A target asked for but not yet searched for
+    target_request: Option<Vec3>,
+    has_target: bool,
+}
+
+impl CrowdAgent {
+    pub fn pos(&self) -> Vec3 {
+        self.npos
+    }
+
+    pub fn vel(&self) -> Vec3 {
+        self.vel
+    }
+
+    pub fn desired_vel(&self) -> Vec3 {
+        self.dvel
+    }
+
+    pub fn corridor(&self) -> &PathCorridor {
+        &self.corridor
+    }
+
+    pub fn boundary(&self) -> &LocalBoundary {
+        &self.boundary
+    }
+
+    // Ids of the agents steered around last update, nearest first
+    pub fn neighbours(&self) -> &[usize] {
+        &self.neighbours
+    }
+
+    // The next few corners of the path, from the last update
+    pub fn corners(&self) -> &[StraightPathPoint] {
+        &self.corners
+    }
+
+    pub fn has_target(&self) -> bool {
+        self.has_target || self.target_request.is_some()
+    }
+}
+
+// Agents moving over a navmesh together: each follows its own corridor,
+// steers around its neighbours and the walls near it, and is pushed out of
+// the others. A pared-down Detour dtCrowd, without off-mesh connection
+// traversal or sliced path requests.
+#[derive(Debug, Clone)]
+pub struct Crowd {
+    // Removed agents leave a hole, so ids stay stable
+    agents: Vec<Option<CrowdAgent>>,
+    max_agents: usize,
+    grid: ProximityGrid,
+    avoidance: ObstacleAvoidanceQuery,
+    avoidance_debug: AvoidanceDebugData,
+    filter: QueryFilter,
+    // Box searched around a point for the polygon under it
+    query_extents: Vec3,
+}
+
+impl Crowd {
+    // `max_agent_radius` sizes the neighbour grid and the search box used
+    // to put agents and targets on the navmesh
+    pub fn new(max_agents: usize, max_agent_radius: f32) -> Self {
+        Self {
+            agents: Vec::new(),
+            max_agents,
+            grid: ProximityGrid::new(max_agent_radius * 3.0),
+            avoidance: ObstacleAvoidanceQuery::new(),
+            avoidance_debug: AvoidanceDebugData::default(),
+            filter: QueryFilter::default(),
+            query_extents: Vec3::new(
+                max_agent_radius * 2.0,
+                max_agent_radius * 1.5,
+                max_agent_radius * 2.0,
+            ),
+        }
+    }
+
+    pub fn filter_mut(&mut self) -> &mut QueryFilter {
+        &mut self.filter
+    }
+
+    pub fn agent(&self, id: usize) -> Option<&CrowdAgent> {
+        self.agents.get(id).and_then(Option::as_ref)
+    }
+
+    // Active agents with their ids
+    pub fn agents(&self) -> impl Iterator<Item = (usize, &CrowdAgent)> {
+        self.agents
+            .iter()
+            .enumerate()
+            .filter_map(|(id, agent)| agent.as_ref().map(|agent| (id, agent)))
+    }
+
+    pub fn agent_count(&self) -> usize {
+        self.agents().count()
+    }
+
+    // Samples scored for the agent passed as `debug_agent` to the last
+    // update
+    pub fn avoidance_debug(&self) -> &AvoidanceDebugData {
+        &self.avoidance_debug
+    }
+
+    // Put an agent on the navmesh polygon nearest `pos`. None if the crowd
+    // is full or there is no polygon near enough.
+    pub fn add_agent(
+        &mut self,
+        nav_mesh: &NavMesh,
+        pos: Vec3,
+        params: CrowdAgentParams,
+    ) -> Result<Option<usize>, NavMeshError> {
+        if self.agent_count() >= self.max_agents {
+            return Ok(None);
+        }
+        let query = NavMeshQuery::new(nav_mesh, MAX_QUERY_NODES);
+        let Some((r, npos)) = query.find_nearest_poly(pos, self.query_extents, &self.filter)?
+        else {
+            return Ok(None);
+        };
+
+        let mut corridor = PathCorridor::new(MAX_PATH);
+        corridor.reset(r, npos);
+        let agent = CrowdAgent {
+            params,
+            corridor,
+            boundary: LocalBoundary::new(),
+            neighbours: Vec::new(),
+            corners: Vec::new(),
+            npos,
+            vel: Vec3::ZERO,
+            dvel: Vec3::ZERO,
+            nvel: Vec3::ZERO,
+            target_request: None,
+            has_target: false,
+        };
+        let id = match self.agents.iter().position(Option::is_none) {
+            Some(id) => {
+                self.agents[id] = Some(agent);
+                id
+            }
+            None => {
+                self.agents.push(Some(agent));
+                self.agents.len() - 1
+            }
+        };
+        Ok(Some(id))
+    }
+
+    pub fn remove_agent(&mut self, id: usize) {
+        if let Some(slot) = self.agents.get_mut(id) {
+            *slot = None;
+        }
+    }
+
+    // Send agent `id` towards `pos`; the path is searched on the next
+    // update. Returns false for an unknown id.
+    pub fn request_move_target(&mut self, id: usize, pos: Vec3) -> bool {
+        match self.agents.get_mut(id).and_then(Option::as_mut) {
+            Some(agent) => {
+                agent.target_request = Some(pos);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Stop agent `id` where it stands
+    pub fn reset_move_target(&mut self, id: usize) {
+        if let Some(agent) = self.agents.get_mut(id).and_then(Option::as_mut) {
+            agent.target_request = None;
+            agent.has_target = false;
+            if let Some(first) = agent.corridor.first_poly() {
+                agent.corridor.reset(first, agent.npos);
+            }
+        }
+    }
+
+    // Advance every agent by `dt` seconds. The avoidance samples of
+    // `debug_agent` are kept for `avoidance_debug`.
+    pub fn update(
+        &mut self,
+        nav_mesh: &NavMesh,
+        dt: f32,
+        debug_agent: Option<usize>,
+    ) -> Result<(), NavMeshError> {
+        let mut query = NavMeshQuery::new(nav_mesh, MAX_QUERY_NODES);
+        let filter = &self.filter;
+        let ids: Vec<usize> = self.agents().map(|(id, _)| id).collect();
+        self.avoidance_debug.samples.clear();
+
+        // Paths for new targets
+        for &id in &ids {
+            let agent = self.agents[id].as_mut().unwrap();
+            let Some(target) = agent.target_request.take() else {
+                continue;
+            };
+            let Some(start) = agent.corridor.first_poly() else {
+                continue;
+            };
+            let Some((end_ref, end_pos)) =
+                query.find_nearest_poly(target, self.query_extents, filter)?
+            else {
+                continue;
+            };
+            let path = query.find_path(start, end_ref, agent.npos, end_pos, filter)?;
+            let Some(&last) = path.polys.last() else {
+                continue;
+            };
+            // A partial path stops at the polygon nearest the target
+            let end_pos = if path.partial {
+                query.closest_point_on_poly_boundary(last, end_pos)?
+            } else {
+                end_pos
+            };
+            agent.corridor.set_corridor(end_pos, &path.polys);
+            agent.has_target = true;
+        }
+
+        self.grid.clear();
+        for &id in &ids {
+            let agent = self.agents[id].as_ref().unwrap();
+            self.grid.add_item(id, agent.npos, agent.params.radius);
+        }
+
+        // Neighbours, walls and the next corners
+        for &id in &ids {
+            let (pos, params) = {
+                let agent = self.agents[id].as_ref().unwrap();
+                (agent.npos, agent.params)
+            };
+            let neighbours: Vec<usize> = self
+                .grid
+                .query_radius(pos, params.collision_query_range)
+                .into_iter()
+                .filter(|&other| other != id)
+                .filter(|&other| {
+                    let other = self.agents[other].as_ref().unwrap();
+                    (other.npos.y - pos.y).abs() < (params.height + other.params.height) * 0.5
+                })
+                .take(MAX_NEIGHBOURS)
+                .collect();
+
+            let agent = self.agents[id].as_mut().unwrap();
+            agent.neighbours = neighbours;
+            let range = params.collision_query_range;
+            if agent.boundary.needs_update(pos, range, &query, filter) {
+                if let Some(first) = agent.corridor.first_poly() {
+                    agent
+                        .boundary
+                        .update(first, pos, range, &mut query, filter)?;
+                }
+            }
+
+            agent.corners.clear();
+            if agent.has_target {
+                agent.corners = agent.corridor.find_corners(&query, MAX_CORNERS)?;
+                if let Some(next) = agent.corners.get(1).or(agent.corners.first()) {
+                    let next = next.pos;
+                    agent.corridor.optimize_path_visibility(
+                        next,
+                        params.path_optimization_range,
+                        &query,
+                        filter,
+                    )?;
+                }
+            }
+        }
+
+        // Desired velocities: towards the next corner, slowing down on
+        // arrival, and away from crowding neighbours
+        for &id in &ids {
+            let agent = self.agents[id].as_ref().unwrap();
+            let params = agent.params;
+            let mut dvel = Vec3::ZERO;
+            if let Some(corner) = agent.corners.first() {
+                let mut dir = corner.pos - agent.npos;
+                dir.y = 0.0;
+                let dist = dir.length();
+                let is_end = agent.corners.len() == 1
+                    && corner.flags & DT_STRAIGHTPATH_OFFMESH_CONNECTION == 0;
+                let slow_down = params.radius * 2.0;
+                let speed_scale = if is_end && dist < slow_down {
+                    dist / slow_down
+                } else {
+                    1.0
+                };
+                dvel = dir.normalize_or_zero() * params.max_speed * speed_scale;
+            }
+
+            if params.separation_weight > 0.0 && dvel != Vec3::ZERO {
+                let range = params.collision_query_range;
+                let mut disp = Vec3::ZERO;
+                let mut weight = 0.0;
+                for &other in &agent.neighbours {
+                    let other = self.agents[other].as_ref().unwrap();
+                    let mut diff = agent.npos - other.npos;
+                    diff.y = 0.0;
+                    let dist_sqr = diff.length_squared();
+                    if dist_sqr < 0.00001 || dist_sqr > range * range {
+                        continue;
+                    }
+                    let w = params.separation_weight * (1.0 - dist_sqr / (range * range));
+                    disp += diff * (w / dist_sqr.sqrt());
+                    weight += 1.0;
+                }
+                if weight > 0.0001 {
+                    let desired_speed = dvel.length();
+                    dvel += disp / weight;
+                    if dvel.length_squared() > desired_speed * desired_speed {
+                        dvel = dvel.normalize_or_zero() * desired_speed;
+                    }
+                }
+            }
+            self.agents[id].as_mut().unwrap().dvel = dvel;
+        }
+
+        // Avoidance
+        for &id in &ids {
+            let agent = self.agents[id].as_ref().unwrap();
+            self.avoidance.reset();
+            for &other in &agent.neighbours {
+                let other = self.agents[other].as_ref().unwrap();
+                self.avoidance
+                    .add_circle(other.npos, other.params.radius, other.vel, other.dvel);
+            }
+            agent
+                .boundary
+                .add_to_avoidance(agent.npos, &mut self.avoidance);
+            let debug = (debug_agent == Some(id)).then_some(&mut self.avoidance_debug);
+            let (nvel, _) = self.avoidance.sample_velocity_adaptive(
+                agent.npos,
+                agent.params.radius,
+                agent.params.max_speed,
+                agent.vel,
+                agent.dvel,
+                &agent.params.avoidance.params(),
+                debug,
+            );
+            self.agents[id].as_mut().unwrap().nvel = nvel;
+        }
+
+        // Integrate, with the change of velocity capped by the acceleration
+        for &id in &ids {
+            let agent = self.agents[id].as_mut().unwrap();
+            let max_delta = agent.params.max_acceleration * dt;
+            let mut dv = agent.nvel - agent.vel;
+            if dv.length() > max_delta {
+                dv = dv.normalize() * max_delta;
+            }
+            agent.vel += dv;
+            if agent.vel.length() < 0.0001 {
+                agent.vel = Vec3::ZERO;
+            }
+            agent.npos += agent.vel * dt;
+        }
+
+        // Push overlapping agents apart
+        for _ in 0..COLLISION_ITERATIONS {
+            let disps: Vec<Vec3> = ids
+                .iter()
+                .map(|&id| {
+                    let agent = self.agents[id].as_ref().unwrap();
+                    let mut disp = Vec3::ZERO;
+                    let mut weight = 0.0;
+                    for &other in &agent.neighbours {
+                        let other = self.agents[other].as_ref().unwrap();
+                        let mut diff = agent.npos - other.npos;
+                        diff.y = 0.0;
+                        let min_dist = agent.params.radius + other.params.radius;
+                        let dist = diff.length();
+                        if dist > min_dist {
+                            continue;
+                        }
+                        let pen = (min_dist - dist) * 0.5 * COLLISION_RESOLVE_FACTOR;
+                        // Agents on top of each other part sideways to
+                        // where they were heading
+                        let dir = if dist < 0.0001 {
+                            Vec3::new(-agent.dvel.z, 0.0, agent.dvel.x).normalize_or(Vec3::X)
+                        } else {
+                            diff / dist
+                        };
+                        disp += dir * pen;
+                        weight += 1.0;
+                    }
+                    if weight > 0.0 {
+                        disp / weight
+                    } else {
+                        disp
+                    }
+                })
+                .collect();
+            for (&id, disp) in ids.iter().zip(disps) {
+                self.agents[id].as_mut().unwrap().npos += disp;
+            }
+        }
+
+        // Keep everyone on the navmesh
+        for &id in &ids {
+            let agent = self.agents[id].as_mut().unwrap();
+            agent.corridor.move_position(agent.npos, &query, filter)?;
+            agent.npos = agent.corridor.pos();
+            if agent.has_target && agent.corners.is_empty() && agent.vel == Vec3::ZERO {
+                agent.has_target = false;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debug_draw::{PolyMesh, RC_MESH_NULL_IDX, RC_WALKABLE_AREA};
+    use crate::nav_mesh::{NavMeshCreateParams, NavMeshData, POLY_FLAG_WALK};
+
+    // A 20x6 floor
+    fn floor() -> NavMesh {
+        let n = RC_MESH_NULL_IDX;
+        let mesh = PolyMesh {
+            nvp: 6,
+            cs: 1.0,
+            ch: 1.0,
+            bmax: Vec3::new(20.0, 1.0, 6.0),
+            verts: vec![
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, 6.0),
+                Vec3::new(20.0, 0.0, 6.0),
+                Vec3::new(20.0, 0.0, 0.0),
+            ],
+            polys: vec![vec![0, 1, 2, 3, n, n]],
+            neighbours: vec![vec![n; 6]],
+            regs: vec![1],
+            flags: vec![POLY_FLAG_WALK],
+            areas: vec![RC_WALKABLE_AREA],
+            ..Default::default()
+        };
+        let params = NavMeshCreateParams::new(&mesh, None, 2.0, 0.6, 0.5);
+        NavMesh::single_tile(NavMeshData::build(&params).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_agents_pass_each_other() {
+        let nav_mesh = floor();
+        let mut crowd = Crowd::new(8, 0.6);
+        let params = CrowdAgentParams::default();
+        let a = crowd
+            .add_agent(&nav_mesh, Vec3::new(2.0, 0.0, 3.0), params)
+            .unwrap()
+            .unwrap();
+        let b = crowd
+            .add_agent(&nav_mesh, Vec3::new(18.0, 0.0, 3.0), params)
+            .unwrap()
+            .unwrap();
+        assert!(crowd.request_move_target(a, Vec3::new(18.0, 0.0, 3.0)));
+        assert!(crowd.request_move_target(b, Vec3::new(2.0, 0.0, 3.0)));
+
+        let mut closest = f32::MAX;
+        for _ in 0..400 {
+            crowd.update(&nav_mesh, 0.05, Some(a)).unwrap();
+            let (pa, pb) = (crowd.agent(a).unwrap().pos(), crowd.agent(b).unwrap().pos());
+            closest = closest.min(pa.distance(pb));
+        }
+        let (pa, pb) = (crowd.agent(a).unwrap().pos(), crowd.agent(b).unwrap().pos());
+        assert!(pa.distance(Vec3::new(18.0, 0.0, 3.0)) < 0.5, "{:?}", pa);
+        assert!(pb.distance(Vec3::new(2.0, 0.0, 3.0)) < 0.5, "{:?}", pb);
+        // Never more than slightly overlapping on the way
+        assert!(closest > 1.0, "{}", closest);
+        assert!(!crowd.avoidance_debug().samples.is_empty());
+    }
+}
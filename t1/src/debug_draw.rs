@@ -1,46 +1,625 @@
+use egui::Vec2;
 use glam::{Vec3, Vec4};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::compact_heightfield::CompactHeightfield;
+use crate::contours::{Contour, ContourSet, RC_AREA_BORDER, RC_BORDER_VERTEX};
+use crate::debug_draw_b::ray_triangle;
+use crate::heightfield::Heightfield;
+
+// Primitive types passed to DebugDraw::begin, numbered like Recast's
+// duDebugDrawPrimitives
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DebugDrawPrimitive {
+    Points = 0,
+    Lines = 1,
+    Tris = 2,
+    Quads = 3,
+}
+
+// Recast-style names for the primitives, kept for existing callers
+pub const DU_DRAW_POINTS: DebugDrawPrimitive = DebugDrawPrimitive::Points;
+pub const DU_DRAW_LINES: DebugDrawPrimitive = DebugDrawPrimitive::Lines;
+pub const DU_DRAW_TRIS: DebugDrawPrimitive = DebugDrawPrimitive::Tris;
+pub const DU_DRAW_QUADS: DebugDrawPrimitive = DebugDrawPrimitive::Quads;
 
-pub const DU_DRAW_QUADS: i32 = 1;
-pub const DU_DRAW_LINES: i32 = 2;
-pub const DU_DRAW_POINTS: i32 = 3;
 pub const RC_NULL_AREA: u8 = 0;
 pub const RC_WALKABLE_AREA: u8 = 63;
 pub const RC_MESH_NULL_IDX: u16 = 0xffff;
 
-#[derive(Debug)]
+// Number of distinct area ids (0..=RC_WALKABLE_AREA)
+pub const AREA_COUNT: usize = 64;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PolyMesh {
-    pub verts: Vec<Vec3>,     // Vertex positions
-    pub polys: Vec<Vec<u16>>, // Polygons as vertex indices
-    pub areas: Vec<u8>,       // Area ID for each polygon
-    pub nvp: i32,             // Max vertices per polygon
-    pub cs: f32,              // Cell size
-    pub ch: f32,              // Cell height
-    pub bmin: Vec3,           // Bounding box min
+    pub verts: Vec<Vec3>,          // Vertex positions in voxel units
+    pub polys: Vec<Vec<u16>>,      // Polygons as vertex indices
+    pub neighbours: Vec<Vec<u16>>, // Polygon across each edge, or RC_MESH_NULL_IDX
+    pub regs: Vec<u16>,            // Region ID for each polygon
+    pub flags: Vec<u16>,           // User flags for each polygon
+    pub areas: Vec<u8>,            // Area ID for each polygon
+    pub nvp: i32,                  // Max vertices per polygon
+    pub cs: f32,                   // Cell size
+    pub ch: f32,                   // Cell height
+    pub bmin: Vec3,                // Bounding box min
+    pub bmax: Vec3,                // Bounding box max
+    pub border_size: i32,          // Tile border width in cells
+    pub max_edge_error: f32,       // Contour simplification error, in cells
+}
+
+// Per-polygon sub-triangles carrying the real surface height under each
+// PolyMesh polygon, matching Recast's rcPolyMeshDetail layout
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PolyMeshDetail {
+    pub meshes: Vec<[u32; 4]>, // Per polygon: vert base, vert count, tri base, tri count
+    pub verts: Vec<Vec3>,      // World-space vertex positions
+    pub tris: Vec<[u8; 4]>,    // Submesh-local vertex indices plus edge flags
+}
+
+impl PolyMeshDetail {
+    // Height of the detail surface of polygon `poly` at `pos` (x/z), or None
+    // if `pos` lies outside all of the polygon's detail triangles
+    pub fn height(&self, poly: usize, pos: Vec3) -> Option<f32> {
+        let [vert_base, _, tri_base, tri_count] = *self.meshes.get(poly)?;
+        let tris = &self.tris[tri_base as usize..(tri_base + tri_count) as usize];
+
+        for t in tris {
+            let v = |k: usize| self.verts[vert_base as usize + t[k] as usize];
+            let (a, b, c) = (v(0), v(1), v(2));
+
+            // Barycentric coordinates in the XZ plane
+            let v0 = c - a;
+            let v1 = b - a;
+            let v2 = pos - a;
+            let denom = v0.x * v1.z - v0.z * v1.x;
+            if denom.abs() < 1e-6 {
+                continue;
+            }
+            let u = (v1.z * v2.x - v1.x * v2.z) / denom;
+            let w = (v0.x * v2.z - v0.z * v2.x) / denom;
+            const EPS: f32 = 1e-4;
+            if u >= -EPS && w >= -EPS && u + w <= 1.0 + EPS {
+                return Some(a.y + v0.y * u + v1.y * w);
+            }
+        }
+        None
+    }
+
+    // Polygon whose detail surface the ray hits first, with the distance in
+    // multiples of `dir`
+    pub fn raycast(&self, origin: Vec3, dir: Vec3) -> Option<(usize, f32)> {
+        let mut best: Option<(usize, f32)> = None;
+        for (poly, &[vert_base, _, tri_base, tri_count]) in self.meshes.iter().enumerate() {
+            for t in &self.tris[tri_base as usize..(tri_base + tri_count) as usize] {
+                let v = |k: usize| self.verts[vert_base as usize + t[k] as usize];
+                if let Some(hit) = ray_triangle(origin, dir, v(0), v(1), v(2)) {
+                    if best.is_none_or(|(_, best_t)| hit < best_t) {
+                        best = Some((poly, hit));
+                    }
+                }
+            }
+        }
+        best
+    }
 }
 
+// Immediate-mode drawing interface shared by all debug draw functions
 pub trait DebugDraw {
-    fn begin(&mut self, prim: i32, size: f32);
-    fn vertex(&mut self, pos: Vec3, color: Vec4);
+    fn begin(&mut self, prim: DebugDrawPrimitive, size: f32);
     fn end(&mut self);
-    fn area_to_col(&self, area: u8) -> Vec4;
+    fn vertex(&mut self, pos: Vec3, color: Vec4);
+    fn vertex_uv(&mut self, pos: Vec3, color: Vec4, uv: Vec2);
+    fn texture(&mut self, state: bool);
+
+    // Hashed hues unless the backend's palette overrides the area
+    fn area_to_col(&self, area: u8) -> Vec4 {
+        self.area_palette().color(area)
+    }
+    fn area_palette(&self) -> &AreaPalette {
+        &AreaPalette::DEFAULT
+    }
+
+    // Colors for everything that isn't an area id
+    fn palette(&self) -> &DebugPalette {
+        &DebugPalette::RECAST
+    }
+}
+
+// Texels per side of the checker laid over textured triangles, and its two
+// gray levels, as in the texture of Recast's demo
+pub const CHECKER_TEXTURE_SIZE: usize = 64;
+pub const CHECKER_LIGHT: u8 = 255;
+pub const CHECKER_DARK: u8 = 215;
+
+// Gray level of the checker at `uv`. It repeats every unit and is light
+// where u and v are in the same half.
+pub fn checker_shade(uv: Vec2) -> u8 {
+    let (u, v) = (uv.x - uv.x.floor(), uv.y - uv.y.floor());
+    if (u < 0.5) == (v < 0.5) {
+        CHECKER_LIGHT
+    } else {
+        CHECKER_DARK
+    }
+}
+
+// Gray levels of the checker texture, row by row, sampled at texel centers
+pub fn checker_texture_pixels() -> Vec<u8> {
+    let texel = |i: usize| (i as f32 + 0.5) / CHECKER_TEXTURE_SIZE as f32;
+    (0..CHECKER_TEXTURE_SIZE * CHECKER_TEXTURE_SIZE)
+        .map(|i| {
+            let (x, y) = (i % CHECKER_TEXTURE_SIZE, i / CHECKER_TEXTURE_SIZE);
+            checker_shade(Vec2::new(texel(x), texel(y)))
+        })
+        .collect()
+}
+
+// One vertex as passed to DebugDraw::vertex_uv
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BatchVertex {
+    pub pos: Vec3,
+    pub color: Vec4,
+    pub uv: Vec2,
+}
+
+// The vertices recorded between a begin and its end, whole primitives
+// only: one per point, two per line and three per triangle. Quads arrive
+// split into two triangles, so `prim` is never Quads.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrimitiveBatch {
+    pub prim: DebugDrawPrimitive,
+    // Line width or point size passed to begin
+    pub size: f32,
+    pub textured: bool,
+    pub vertices: Vec<BatchVertex>,
+}
+
+// Mode and vertex-count bookkeeping for DebugDraw backends. Vertices of an
+// unfinished primitive are held back, and dropped if the batch ends first.
+#[derive(Debug, Clone, Default)]
+pub struct PrimitiveBatcher {
+    batch: Option<PrimitiveBatch>,
+    // Set while the open batch is of quads, whose corners collect in
+    // `quad` until all four are in
+    quads: bool,
+    quad: Vec<BatchVertex>,
+    textured: bool,
+}
+
+impl PrimitiveBatcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Start a batch. A batch left open by a missing `end` is returned so it
+    // can still be drawn.
+    pub fn begin(&mut self, prim: DebugDrawPrimitive, size: f32) -> Option<PrimitiveBatch> {
+        let open = self.end();
+        self.batch = Some(PrimitiveBatch {
+            prim: if prim == DU_DRAW_QUADS {
+                DU_DRAW_TRIS
+            } else {
+                prim
+            },
+            size,
+            textured: self.textured,
+            vertices: Vec::new(),
+        });
+        self.quads = prim == DU_DRAW_QUADS;
+        open
+    }
+
+    pub fn texture(&mut self, state: bool) {
+        self.textured = state;
+    }
+
+    pub fn vertex(&mut self, vertex: BatchVertex) {
+        let Some(batch) = &mut self.batch else {
+            return;
+        };
+        if !self.quads {
+            batch.vertices.push(vertex);
+            return;
+        }
+        self.quad.push(vertex);
+        if let [a, b, c, d] = self.quad[..] {
+            // Both halves share the a-c diagonal
+            batch.vertices.extend([a, b, c, a, c, d]);
+            self.quad.clear();
+        }
+    }
+
+    // The finished batch, without any trailing partial primitive. None when
+    // no batch was open or it has nothing to draw.
+    pub fn end(&mut self) -> Option<PrimitiveBatch> {
+        self.quads = false;
+        self.quad.clear();
+        let mut batch = self.batch.take()?;
+        let per_prim = match batch.prim {
+            DebugDrawPrimitive::Points => 1,
+            DebugDrawPrimitive::Lines => 2,
+            DebugDrawPrimitive::Tris | DebugDrawPrimitive::Quads => 3,
+        };
+        let whole = batch.vertices.len() / per_prim * per_prim;
+        batch.vertices.truncate(whole);
+        (!batch.vertices.is_empty()).then_some(batch)
+    }
+}
+
+// A DebugDraw backend working on whole batches. Anything implementing this
+// is a DebugDraw through its PrimitiveBatcher and only has to draw or store
+// what `flush` is given.
+pub trait BatchedDebugDraw {
+    fn batcher(&mut self) -> &mut PrimitiveBatcher;
+    fn flush(&mut self, batch: PrimitiveBatch);
+
+    fn area_colors(&self) -> &AreaPalette {
+        &AreaPalette::DEFAULT
+    }
+
+    fn debug_palette(&self) -> &DebugPalette {
+        &DebugPalette::RECAST
+    }
+}
+
+impl<T: BatchedDebugDraw> DebugDraw for T {
+    fn begin(&mut self, prim: DebugDrawPrimitive, size: f32) {
+        if let Some(open) = self.batcher().begin(prim, size) {
+            self.flush(open);
+        }
+    }
+
+    fn end(&mut self) {
+        if let Some(batch) = self.batcher().end() {
+            self.flush(batch);
+        }
+    }
+
+    fn vertex(&mut self, pos: Vec3, color: Vec4) {
+        self.vertex_uv(pos, color, Vec2::ZERO);
+    }
+
+    fn vertex_uv(&mut self, pos: Vec3, color: Vec4, uv: Vec2) {
+        self.batcher().vertex(BatchVertex { pos, color, uv });
+    }
+
+    fn texture(&mut self, state: bool) {
+        self.batcher().texture(state);
+    }
+
+    fn area_palette(&self) -> &AreaPalette {
+        self.area_colors()
+    }
+
+    fn palette(&self) -> &DebugPalette {
+        self.debug_palette()
+    }
+}
+
+// Colors for area ids 0..64, shared so all backends draw areas the same way:
+// a hashed hue per id unless the user picked a color for it
+#[derive(Debug, Clone, PartialEq)]
+pub struct AreaPalette {
+    overrides: [Option<Vec4>; AREA_COUNT],
+}
+
+impl Default for AreaPalette {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl AreaPalette {
+    // No overrides, what a DebugDraw without its own palette uses
+    pub const DEFAULT: Self = Self {
+        overrides: [None; AREA_COUNT],
+    };
+
+    // Distinct hue per id: stepping the hue by the golden ratio keeps
+    // neighbouring ids far apart on the color wheel
+    pub fn default_color(area: u8) -> Vec4 {
+        const GOLDEN_RATIO_CONJUGATE: f32 = 0.618_034;
+        let hue = (area as f32 * GOLDEN_RATIO_CONJUGATE).fract();
+        id_color(area as u32, hue).extend(1.0)
+    }
+
+    pub fn color(&self, area: u8) -> Vec4 {
+        self.overrides[area as usize % AREA_COUNT].unwrap_or_else(|| Self::default_color(area))
+    }
+
+    pub fn set_color(&mut self, area: u8, color: Vec4) {
+        self.overrides[area as usize % AREA_COUNT] = Some(color);
+    }
+
+    pub fn reset_color(&mut self, area: u8) {
+        self.overrides[area as usize % AREA_COUNT] = None;
+    }
+
+    pub fn is_overridden(&self, area: u8) -> bool {
+        self.overrides[area as usize % AREA_COUNT].is_some()
+    }
+
+    // One `area r g b a` line (0-255 components) per overridden area
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for (area, color) in self.overrides.iter().enumerate() {
+            if let Some(color) = color {
+                let c = (*color * 255.0).round();
+                text.push_str(&format!("{} {} {} {} {}\n", area, c.x, c.y, c.z, c.w));
+            }
+        }
+        text
+    }
+
+    // Parse the format written by to_text; '#' starts a comment
+    pub fn from_text(text: &str) -> Result<Self, String> {
+        let mut palette = Self::default();
+        for (line_no, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let values: Vec<u8> = line
+                .split_whitespace()
+                .map(|v| v.parse::<u8>())
+                .collect::<Result<_, _>>()
+                .map_err(|e| format!("line {}: {}", line_no + 1, e))?;
+            let [area, r, g, b, a] = values[..] else {
+                return Err(format!("line {}: expected `area r g b a`", line_no + 1));
+            };
+            if area as usize >= AREA_COUNT {
+                return Err(format!("line {}: area {} out of range", line_no + 1, area));
+            }
+            palette.set_color(
+                area,
+                Vec4::new(r as f32, g as f32, b as f32, a as f32) / 255.0,
+            );
+        }
+        Ok(palette)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_text())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        Self::from_text(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+// Color for any id, e.g. a region, like Recast's duIntToCol. Fibonacci
+// hashing: multiplying by 2^32 over the golden ratio steps the hue the way
+// AreaPalette::default_color does, but stays exact for every u32, so colors
+// are stable across runs and consecutive ids land far apart.
+pub fn du_int_to_col(id: u32, alpha: f32) -> Vec4 {
+    let h = id.wrapping_mul(0x9e37_79b9);
+    let hue = (h >> 8) as f32 / (1 << 24) as f32;
+    id_color(id, hue).extend(alpha)
+}
+
+// The shared look of id colors at a given hue. Brightness alternates as
+// well so ids with close hues still differ.
+fn id_color(id: u32, hue: f32) -> Vec3 {
+    let value = if id & 1 == 0 { 0.95 } else { 0.75 };
+    hsv_to_rgb(hue, 0.7, value)
+}
+
+fn hsv_to_rgb(h: f32, s: f32, v: f32) -> Vec3 {
+    let h6 = h * 6.0;
+    let f = h6.fract();
+    let p = v * (1.0 - s);
+    let q = v * (1.0 - s * f);
+    let t = v * (1.0 - s * (1.0 - f));
+    match h6 as u32 % 6 {
+        0 => Vec3::new(v, t, p),
+        1 => Vec3::new(q, v, p),
+        2 => Vec3::new(p, v, t),
+        3 => Vec3::new(p, q, v),
+        4 => Vec3::new(t, p, v),
+        _ => Vec3::new(v, p, q),
+    }
+}
+
+// Named colors for everything the debug draw functions show besides area
+// ids, so a whole scheme can be swapped at once. Values follow Recast's
+// duRGBA colors, written as 0..1 floats.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DebugPalette {
+    pub name: &'static str,
+    pub background: Vec4,
+    pub grid: Vec4,
+    pub highlight: Vec4,         // Selected meshes, picked triangles
+    pub walkable: Vec4,          // Walkable polygons and compact spans
+    pub null_area: Vec4,         // Polygons and compact spans with no area
+    pub span_walkable: Vec4,     // Walkable heightfield spans
+    pub span_null: Vec4,         // Heightfield spans with no area
+    pub unwalkable: Vec4,        // Input triangles too steep to walk on
+    pub boundary: Vec4,          // Outer edges of the polygon mesh
+    pub vertex: Vec4,            // Polygon mesh vertices
+    pub inner_edge: Vec4,        // Edges inside detail meshes
+    pub border: Vec3,            // Tile border vertices and area edges of contours
+    pub unassigned_region: Vec4, // Compact spans outside any region
+    pub distance_edge: Vec4,
+    pub distance_centre: Vec4,
+    pub unlinked: Vec4, // Off-mesh connection ends that found no ground
+    pub axes: [Vec4; 3],
+    pub agent: Vec4,
+    pub agent_selected: Vec4,
+    pub corridor: Vec4,
+    pub desired_velocity: Vec4,
+    pub velocity: Vec4,
+    pub wall: Vec4,
+    pub sample_best: Vec4,
+    pub sample_worst: Vec4,
+    pub path: Vec4,
+    pub path_start: Vec4,
+    pub path_end: Vec4,
+    pub straight_path: Vec4,
+    pub ray: Vec4,
+    pub ray_hit: Vec4,
+    // Colors regions cycle through; empty hashes ids with du_int_to_col
+    pub region_ramp: &'static [Vec4],
+}
+
+impl DebugPalette {
+    pub const RECAST: Self = Self {
+        name: "Recast",
+        background: Vec4::new(0.3, 0.3, 0.32, 1.0),
+        grid: Vec4::new(0.35, 0.35, 0.35, 1.0),
+        highlight: Vec4::new(1.0, 0.85, 0.0, 1.0),
+        walkable: Vec4::new(0.0, 0.75, 1.0, 0.25), // RGBA(0,192,255,64)
+        null_area: Vec4::new(0.0, 0.0, 0.0, 0.25), // RGBA(0,0,0,64)
+        span_walkable: Vec4::new(0.25, 0.5, 0.63, 1.0), // RGBA(64,128,160,255)
+        span_null: Vec4::new(0.25, 0.25, 0.25, 1.0), // RGBA(64,64,64,255)
+        unwalkable: Vec4::new(0.75, 0.5, 0.0, 1.0), // RGBA(192,128,0,255)
+        boundary: Vec4::new(0.0, 0.25, 0.25, 0.86), // RGBA(0,48,64,220)
+        vertex: Vec4::new(0.0, 0.0, 0.0, 0.86),    // RGBA(0,0,0,220)
+        inner_edge: Vec4::new(0.0, 0.0, 0.0, 0.25), // RGBA(0,0,0,64)
+        border: Vec3::ONE,
+        unassigned_region: Vec4::new(0.0, 0.0, 0.0, 0.25),
+        distance_edge: Vec4::new(0.05, 0.05, 0.35, 1.0),
+        distance_centre: Vec4::new(1.0, 0.9, 0.2, 1.0),
+        unlinked: Vec4::new(0.8, 0.1, 0.1, 1.0),
+        axes: [
+            Vec4::new(0.9, 0.2, 0.2, 1.0),
+            Vec4::new(0.2, 0.8, 0.2, 1.0),
+            Vec4::new(0.2, 0.4, 0.9, 1.0),
+        ],
+        agent: Vec4::new(0.0, 0.75, 1.0, 1.0),
+        agent_selected: Vec4::new(1.0, 0.75, 0.0, 1.0),
+        corridor: Vec4::new(0.0, 0.75, 1.0, 0.5),
+        desired_velocity: Vec4::new(0.0, 0.75, 1.0, 1.0),
+        velocity: Vec4::new(1.0, 1.0, 1.0, 1.0),
+        wall: Vec4::new(1.0, 0.25, 0.0, 1.0),
+        sample_best: Vec4::new(1.0, 1.0, 1.0, 0.86),
+        sample_worst: Vec4::new(0.5, 0.375, 0.0, 0.86),
+        path: Vec4::new(0.0, 0.0, 0.0, 0.25),
+        path_start: Vec4::new(0.5, 0.1, 0.0, 0.75),
+        path_end: Vec4::new(0.2, 0.4, 0.0, 0.5),
+        straight_path: Vec4::new(0.25, 0.06, 0.0, 0.86),
+        ray: Vec4::new(1.0, 1.0, 1.0, 0.86),
+        ray_hit: Vec4::new(1.0, 0.25, 0.0, 1.0),
+        region_ramp: &[],
+    };
+
+    // Okabe-Ito colors, which stay apart with every common kind of color
+    // blindness. Walkable against unwalkable is blue against orange rather
+    // than cyan against brown.
+    pub const COLORBLIND: Self = Self {
+        name: "Colorblind safe",
+        highlight: Vec4::new(0.94, 0.89, 0.26, 1.0),
+        walkable: Vec4::new(0.0, 0.45, 0.7, 0.35),
+        span_walkable: Vec4::new(0.34, 0.71, 0.91, 1.0),
+        unwalkable: Vec4::new(0.9, 0.62, 0.0, 1.0),
+        boundary: Vec4::new(0.0, 0.0, 0.0, 0.86),
+        distance_edge: Vec4::new(0.0, 0.45, 0.7, 1.0),
+        distance_centre: Vec4::new(0.94, 0.89, 0.26, 1.0),
+        unlinked: Vec4::new(0.84, 0.37, 0.0, 1.0),
+        axes: [
+            Vec4::new(0.84, 0.37, 0.0, 1.0),
+            Vec4::new(0.94, 0.89, 0.26, 1.0),
+            Vec4::new(0.34, 0.71, 0.91, 1.0),
+        ],
+        agent: Vec4::new(0.34, 0.71, 0.91, 1.0),
+        agent_selected: Vec4::new(0.9, 0.62, 0.0, 1.0),
+        corridor: Vec4::new(0.34, 0.71, 0.91, 0.5),
+        desired_velocity: Vec4::new(0.34, 0.71, 0.91, 1.0),
+        wall: Vec4::new(0.84, 0.37, 0.0, 1.0),
+        path_start: Vec4::new(0.84, 0.37, 0.0, 0.75),
+        path_end: Vec4::new(0.0, 0.62, 0.45, 0.6),
+        ray_hit: Vec4::new(0.84, 0.37, 0.0, 1.0),
+        region_ramp: &[
+            Vec4::new(0.9, 0.62, 0.0, 1.0),
+            Vec4::new(0.34, 0.71, 0.91, 1.0),
+            Vec4::new(0.0, 0.62, 0.45, 1.0),
+            Vec4::new(0.94, 0.89, 0.26, 1.0),
+            Vec4::new(0.0, 0.45, 0.7, 1.0),
+            Vec4::new(0.84, 0.37, 0.0, 1.0),
+            Vec4::new(0.8, 0.47, 0.65, 1.0),
+            Vec4::new(0.6, 0.6, 0.6, 1.0),
+        ],
+        ..Self::RECAST
+    };
+
+    // A near-black background with the dark outlines turned light
+    pub const DARK: Self = Self {
+        name: "Dark",
+        background: Vec4::new(0.07, 0.07, 0.08, 1.0),
+        grid: Vec4::new(0.2, 0.2, 0.22, 1.0),
+        null_area: Vec4::new(1.0, 1.0, 1.0, 0.12),
+        span_null: Vec4::new(0.45, 0.45, 0.45, 1.0),
+        boundary: Vec4::new(0.5, 0.9, 1.0, 0.86),
+        vertex: Vec4::new(1.0, 1.0, 1.0, 0.86),
+        inner_edge: Vec4::new(1.0, 1.0, 1.0, 0.2),
+        unassigned_region: Vec4::new(1.0, 1.0, 1.0, 0.12),
+        path: Vec4::new(1.0, 1.0, 1.0, 0.2),
+        straight_path: Vec4::new(1.0, 0.6, 0.3, 0.86),
+        ..Self::RECAST
+    };
+
+    // A pale background; white lines turn dark to stay visible on it
+    pub const LIGHT: Self = Self {
+        name: "Light",
+        background: Vec4::new(0.9, 0.9, 0.88, 1.0),
+        grid: Vec4::new(0.72, 0.72, 0.72, 1.0),
+        highlight: Vec4::new(0.9, 0.45, 0.0, 1.0),
+        walkable: Vec4::new(0.0, 0.55, 0.85, 0.35),
+        border: Vec3::new(0.1, 0.1, 0.1),
+        velocity: Vec4::new(0.1, 0.1, 0.1, 1.0),
+        sample_best: Vec4::new(0.1, 0.1, 0.1, 0.86),
+        ray: Vec4::new(0.1, 0.1, 0.1, 0.86),
+        ..Self::RECAST
+    };
+
+    pub const PRESETS: [Self; 4] = [Self::RECAST, Self::COLORBLIND, Self::DARK, Self::LIGHT];
+
+    pub fn by_name(name: &str) -> Option<&'static Self> {
+        Self::PRESETS.iter().find(|palette| palette.name == name)
+    }
+
+    // Color of region or submesh `id` with the given alpha
+    pub fn region_color(&self, id: usize, alpha: f32) -> Vec4 {
+        if self.region_ramp.is_empty() {
+            du_int_to_col(id as u32, alpha)
+        } else {
+            self.region_ramp[id % self.region_ramp.len()]
+                .truncate()
+                .extend(alpha)
+        }
+    }
+}
+
+impl Default for DebugPalette {
+    fn default() -> Self {
+        Self::RECAST
+    }
+}
+
+// Fill color of a PolyMesh polygon with the given area id
+pub fn poly_area_color(dd: &impl DebugDraw, area: u8) -> Vec4 {
+    if area == RC_WALKABLE_AREA {
+        dd.palette().walkable
+    } else if area == RC_NULL_AREA {
+        dd.palette().null_area
+    } else {
+        dd.area_to_col(area) // Custom area colors
+    }
 }
 
 pub fn du_debug_draw_poly_mesh(dd: &mut impl DebugDraw, mesh: &PolyMesh) {
     // Begin drawing triangles for polygon fills
-    dd.begin(DU_DRAW_QUADS, 1.0);
+    dd.begin(DU_DRAW_TRIS, 1.0);
 
     // Process each polygon
     for (i, poly) in mesh.polys.iter().enumerate() {
         let area = mesh.areas[i];
 
         // Determine polygon color based on area type
-        let color = if area == RC_WALKABLE_AREA {
-            Vec4::new(0.0, 0.75, 1.0, 0.25) // RGBA(0,192,255,64) -> walkable
-        } else if area == RC_NULL_AREA {
-            Vec4::new(0.0, 0.0, 0.0, 0.25) // RGBA(0,0,0,64) -> null area
-        } else {
-            dd.area_to_col(area) // Custom area colors
-        };
+        let color = poly_area_color(dd, area);
 
         // Triangulate the polygon and draw
         for j in 2..mesh.nvp {
@@ -53,11 +632,11 @@ pub fn du_debug_draw_poly_mesh(dd: &mut impl DebugDraw, mesh: &PolyMesh) {
             let vi = [poly[0], poly[idx - 1], poly[idx]];
 
             // Draw the triangle vertices
-            for k in 0..3 {
-                if vi[k] >= mesh.verts.len() as u16 {
+            for &v in &vi {
+                if v >= mesh.verts.len() as u16 {
                     continue;
                 }
-                let v = &mesh.verts[vi[k] as usize];
+                let v = &mesh.verts[v as usize];
                 let x = mesh.bmin.x + v.x * mesh.cs;
                 let y = mesh.bmin.y + (v.y + 1.0) * mesh.ch;
                 let z = mesh.bmin.z + v.z * mesh.cs;
@@ -69,9 +648,9 @@ pub fn du_debug_draw_poly_mesh(dd: &mut impl DebugDraw, mesh: &PolyMesh) {
 
     // Draw boundary edges
     dd.begin(DU_DRAW_LINES, 2.5);
-    let col_boundary = Vec4::new(0.0, 0.25, 0.25, 0.86); // RGBA(0,48,64,220)
+    let col_boundary = dd.palette().boundary;
 
-    for (i, poly) in mesh.polys.iter().enumerate() {
+    for poly in &mesh.polys {
         for j in 0..mesh.nvp {
             let idx = j as usize;
             if idx >= poly.len() || poly[idx] == RC_MESH_NULL_IDX {
@@ -79,8 +658,9 @@ pub fn du_debug_draw_poly_mesh(dd: &mut impl DebugDraw, mesh: &PolyMesh) {
             }
 
             // Get indices for the edge vertices
-            let nj = if j + 1 >= mesh.nvp.try_into().unwrap()
-                || poly[(j + 1) as usize] == RC_MESH_NULL_IDX
+            let nj = if j + 1 >= mesh.nvp
+                || idx + 1 >= poly.len()
+                || poly[idx + 1] == RC_MESH_NULL_IDX
             {
                 0
             } else {
@@ -90,11 +670,11 @@ pub fn du_debug_draw_poly_mesh(dd: &mut impl DebugDraw, mesh: &PolyMesh) {
             let vi = [poly[idx], poly[nj as usize]];
 
             // Draw edge line
-            for k in 0..2 {
-                if vi[k] >= mesh.verts.len() as u16 {
+            for &v in &vi {
+                if v >= mesh.verts.len() as u16 {
                     continue;
                 }
-                let v = &mesh.verts[vi[k] as usize];
+                let v = &mesh.verts[v as usize];
                 let x = mesh.bmin.x + v.x * mesh.cs;
                 let y = mesh.bmin.y + (v.y + 1.0) * mesh.ch + 0.1;
                 let z = mesh.bmin.z + v.z * mesh.cs;
@@ -106,7 +686,7 @@ pub fn du_debug_draw_poly_mesh(dd: &mut impl DebugDraw, mesh: &PolyMesh) {
 
     // Draw vertices as points
     dd.begin(DU_DRAW_POINTS, 3.0);
-    let col_vertex = Vec4::new(0.0, 0.0, 0.0, 0.86); // RGBA(0,0,0,220)
+    let col_vertex = dd.palette().vertex;
 
     for vert in mesh.verts.iter() {
         let x = mesh.bmin.x + vert.x * mesh.cs;
@@ -116,3 +696,675 @@ pub fn du_debug_draw_poly_mesh(dd: &mut impl DebugDraw, mesh: &PolyMesh) {
     }
     dd.end();
 }
+
+// Edge flags of detail triangle edge `k`: 0 for edges inside the polygon
+fn detail_edge_flags(tri: &[u8; 4], k: usize) -> u8 {
+    (tri[3] >> (k * 2)) & 0x3
+}
+
+pub fn du_debug_draw_poly_mesh_detail(dd: &mut impl DebugDraw, dmesh: &PolyMeshDetail) {
+    // One color per submesh so polygon boundaries stay visible
+    dd.begin(DU_DRAW_TRIS, 1.0);
+    for (i, m) in dmesh.meshes.iter().enumerate() {
+        let [vert_base, _, tri_base, tri_count] = *m;
+        let color = dd.palette().region_color(i, 0.75); // alpha 192
+        for t in &dmesh.tris[tri_base as usize..(tri_base + tri_count) as usize] {
+            for &k in &t[..3] {
+                dd.vertex(dmesh.verts[vert_base as usize + k as usize], color);
+            }
+        }
+    }
+    dd.end();
+
+    let col_edge = dd.palette().inner_edge;
+
+    // Internal edges, drawn once per shared pair
+    dd.begin(DU_DRAW_LINES, 1.0);
+    for m in &dmesh.meshes {
+        let [vert_base, _, tri_base, tri_count] = *m;
+        for t in &dmesh.tris[tri_base as usize..(tri_base + tri_count) as usize] {
+            for k in 0..3 {
+                let kp = (k + 2) % 3;
+                if detail_edge_flags(t, kp) == 0 && t[kp] < t[k] {
+                    dd.vertex(dmesh.verts[vert_base as usize + t[kp] as usize], col_edge);
+                    dd.vertex(dmesh.verts[vert_base as usize + t[k] as usize], col_edge);
+                }
+            }
+        }
+    }
+    dd.end();
+
+    // Polygon boundary edges
+    dd.begin(DU_DRAW_LINES, 2.0);
+    for m in &dmesh.meshes {
+        let [vert_base, _, tri_base, tri_count] = *m;
+        for t in &dmesh.tris[tri_base as usize..(tri_base + tri_count) as usize] {
+            for k in 0..3 {
+                let kp = (k + 2) % 3;
+                if detail_edge_flags(t, kp) != 0 {
+                    dd.vertex(dmesh.verts[vert_base as usize + t[kp] as usize], col_edge);
+                    dd.vertex(dmesh.verts[vert_base as usize + t[k] as usize], col_edge);
+                }
+            }
+        }
+    }
+    dd.end();
+
+    dd.begin(DU_DRAW_POINTS, 3.0);
+    for &v in &dmesh.verts {
+        dd.vertex(v, col_edge);
+    }
+    dd.end();
+}
+
+// Face colors for a box: the top gets `top`, the bottom and sides are
+// shaded versions of `side`, like Recast's duCalcBoxColors
+fn box_colors(top: Vec4, side: Vec4) -> [Vec4; 6] {
+    let shade = |c: Vec4, f: f32| (c.truncate() * f).extend(c.w);
+    [
+        top,
+        shade(side, 0.35),
+        shade(side, 0.85),
+        shade(side, 0.65),
+        shade(side, 0.85),
+        shade(side, 0.65),
+    ]
+}
+
+// Twelve triangles for the box from `min` to `max`, faces in the order
+// top, bottom, -z, +x, +z, -x. Must be called between begin(TRIS) and end.
+fn du_append_box(dd: &mut impl DebugDraw, min: Vec3, max: Vec3, colors: &[Vec4; 6]) {
+    let corner = |i: usize| {
+        Vec3::new(
+            if i & 1 != 0 { max.x } else { min.x },
+            if i & 4 != 0 { max.y } else { min.y },
+            if i & 2 != 0 { max.z } else { min.z },
+        )
+    };
+    // Corner bits: 1 = +x, 2 = +z, 4 = +y; each face wound counter-clockwise
+    // seen from outside
+    const FACES: [[usize; 4]; 6] = [
+        [4, 6, 7, 5],
+        [0, 1, 3, 2],
+        [0, 4, 5, 1],
+        [1, 5, 7, 3],
+        [3, 7, 6, 2],
+        [2, 6, 4, 0],
+    ];
+    for (face, &color) in FACES.iter().zip(colors) {
+        for k in [0, 1, 2, 0, 2, 3] {
+            dd.vertex(corner(face[k]), color);
+        }
+    }
+}
+
+// Lines of a `w` x `h` cell grid on the XZ plane with its corner at
+// `origin`, like Recast's duDebugDrawGridXZ
+pub fn du_debug_draw_grid_xz(
+    dd: &mut impl DebugDraw,
+    origin: Vec3,
+    w: u32,
+    h: u32,
+    size: f32,
+    color: Vec4,
+    line_width: f32,
+) {
+    dd.begin(DU_DRAW_LINES, line_width);
+    for i in 0..=h {
+        let z = origin.z + i as f32 * size;
+        dd.vertex(Vec3::new(origin.x, origin.y, z), color);
+        dd.vertex(Vec3::new(origin.x + w as f32 * size, origin.y, z), color);
+    }
+    for i in 0..=w {
+        let x = origin.x + i as f32 * size;
+        dd.vertex(Vec3::new(x, origin.y, origin.z), color);
+        dd.vertex(Vec3::new(x, origin.y, origin.z + h as f32 * size), color);
+    }
+    dd.end();
+}
+
+// The twelve edges of the box from `min` to `max`
+pub fn du_debug_draw_box_wire(
+    dd: &mut impl DebugDraw,
+    min: Vec3,
+    max: Vec3,
+    color: Vec4,
+    line_width: f32,
+) {
+    let corner = |i: usize| {
+        Vec3::new(
+            if i & 1 != 0 { max.x } else { min.x },
+            if i & 4 != 0 { max.y } else { min.y },
+            if i & 2 != 0 { max.z } else { min.z },
+        )
+    };
+    dd.begin(DU_DRAW_LINES, line_width);
+    for i in 0..8 {
+        // Each edge once, from the corner with the bit clear
+        for bit in [1, 2, 4] {
+            if i & bit == 0 {
+                dd.vertex(corner(i), color);
+                dd.vertex(corner(i | bit), color);
+            }
+        }
+    }
+    dd.end();
+}
+
+// World X, Y and Z from `origin` as red, green and blue lines
+pub fn du_debug_draw_axes(dd: &mut impl DebugDraw, origin: Vec3, length: f32, line_width: f32) {
+    let axes = [Vec3::X, Vec3::Y, Vec3::Z]
+        .into_iter()
+        .zip(dd.palette().axes);
+    dd.begin(DU_DRAW_LINES, line_width);
+    for (axis, color) in axes {
+        dd.vertex(origin, color);
+        dd.vertex(origin + axis * length, color);
+    }
+    dd.end();
+}
+
+// Every span of `hf` as a gray box, for inspecting the voxelization
+pub fn du_debug_draw_heightfield_solid(dd: &mut impl DebugDraw, hf: &Heightfield) {
+    let white = Vec4::ONE;
+    draw_heightfield_spans(dd, hf, |_| box_colors(white, white));
+}
+
+// Every span of `hf` colored by area: walkable and null area spans in
+// their debug palette colors and other areas in their area color
+pub fn du_debug_draw_heightfield_walkable(dd: &mut impl DebugDraw, hf: &Heightfield) {
+    let walkable = dd.palette().span_walkable;
+    let null = dd.palette().span_null;
+    let colors: Vec<[Vec4; 6]> = (0..AREA_COUNT as u8)
+        .map(|area| {
+            let top = match area {
+                RC_WALKABLE_AREA => walkable,
+                RC_NULL_AREA => null,
+                _ => dd.area_to_col(area),
+            };
+            box_colors(top, top)
+        })
+        .collect();
+    draw_heightfield_spans(dd, hf, |area| colors[area as usize % AREA_COUNT]);
+}
+
+fn draw_heightfield_spans(
+    dd: &mut impl DebugDraw,
+    hf: &Heightfield,
+    colors: impl Fn(u8) -> [Vec4; 6],
+) {
+    dd.begin(DU_DRAW_TRIS, 1.0);
+    for z in 0..hf.height {
+        for x in 0..hf.width {
+            let fx = hf.bmin.x + x as f32 * hf.cs;
+            let fz = hf.bmin.z + z as f32 * hf.cs;
+            for span in hf.column(x, z) {
+                let min = Vec3::new(fx, hf.bmin.y + span.smin as f32 * hf.ch, fz);
+                let max = Vec3::new(fx + hf.cs, hf.bmin.y + span.smax as f32 * hf.ch, fz + hf.cs);
+                du_append_box(dd, min, max, &colors(span.area));
+            }
+        }
+    }
+    dd.end();
+}
+
+// The walkable floor of every compact span, colored by area
+pub fn du_debug_draw_compact_heightfield_solid(dd: &mut impl DebugDraw, chf: &CompactHeightfield) {
+    let colors: Vec<Vec4> = (0..AREA_COUNT as u8)
+        .map(|area| match area {
+            RC_WALKABLE_AREA | RC_NULL_AREA => poly_area_color(dd, area),
+            _ => dd.area_to_col(area),
+        })
+        .collect();
+    draw_compact_spans(dd, chf, |i| colors[chf.areas[i] as usize % AREA_COUNT]);
+}
+
+// Compact spans colored by region, with spans outside any region faint
+pub fn du_debug_draw_compact_heightfield_regions(
+    dd: &mut impl DebugDraw,
+    chf: &CompactHeightfield,
+) {
+    let palette = *dd.palette();
+    draw_compact_spans(dd, chf, |i| match chf.spans[i].reg {
+        0 => palette.unassigned_region,
+        reg => palette.region_color(reg as usize, 0.75),
+    });
+}
+
+// Compact spans shaded from dark blue at region edges to yellow at the
+// span furthest from any edge. Draws nothing before the distance field is
+// built.
+pub fn du_debug_draw_compact_heightfield_distance(
+    dd: &mut impl DebugDraw,
+    chf: &CompactHeightfield,
+) {
+    if chf.dist.len() != chf.spans.len() {
+        return;
+    }
+    let edge = dd.palette().distance_edge;
+    let centre = dd.palette().distance_centre;
+    let max_distance = chf.max_distance.max(1) as f32;
+    draw_compact_spans(dd, chf, |i| {
+        edge.lerp(centre, (chf.dist[i] as f32 / max_distance).min(1.0))
+    });
+}
+
+// One quad per compact span, just above its floor
+fn draw_compact_spans(
+    dd: &mut impl DebugDraw,
+    chf: &CompactHeightfield,
+    color: impl Fn(usize) -> Vec4,
+) {
+    dd.begin(DU_DRAW_TRIS, 1.0);
+    for z in 0..chf.height {
+        for x in 0..chf.width {
+            let fx = chf.bmin.x + x as f32 * chf.cs;
+            let fz = chf.bmin.z + z as f32 * chf.cs;
+            for i in chf.span_range(x, z) {
+                let fy = chf.bmin.y + (chf.spans[i].y + 1) as f32 * chf.ch;
+                let c = color(i);
+                let corners = [
+                    Vec3::new(fx, fy, fz),
+                    Vec3::new(fx, fy, fz + chf.cs),
+                    Vec3::new(fx + chf.cs, fy, fz + chf.cs),
+                    Vec3::new(fx + chf.cs, fy, fz),
+                ];
+                for k in [0, 1, 2, 0, 2, 3] {
+                    dd.vertex(corners[k], c);
+                }
+            }
+        }
+    }
+    dd.end();
+}
+
+// The unsimplified outline of every region, as traced from the voxels
+pub fn du_debug_draw_raw_contours(dd: &mut impl DebugDraw, cset: &ContourSet, alpha: f32) {
+    draw_contours(dd, cset, alpha, |c| &c.rverts, 2.0);
+}
+
+// The simplified region outlines the polygon mesh is built from. Edges
+// between two areas are drawn lighter.
+pub fn du_debug_draw_contours(dd: &mut impl DebugDraw, cset: &ContourSet, alpha: f32) {
+    draw_contours(dd, cset, alpha, |c| &c.verts, 2.5);
+}
+
+fn draw_contours(
+    dd: &mut impl DebugDraw,
+    cset: &ContourSet,
+    alpha: f32,
+    verts: impl Fn(&Contour) -> &Vec<[i32; 4]>,
+    width: f32,
+) {
+    // Every other vertex is lifted a voxel so the outlines of neighbouring
+    // regions, which share edges, don't hide each other
+    let pos = |v: &[i32; 4], i: usize| {
+        Vec3::new(
+            cset.bmin.x + v[0] as f32 * cset.cs,
+            cset.bmin.y + (v[1] + 1 + (i & 1) as i32) as f32 * cset.ch,
+            cset.bmin.z + v[2] as f32 * cset.cs,
+        )
+    };
+    let palette = *dd.palette();
+    let color = |c: &Contour| palette.region_color(c.reg as usize, alpha);
+    let border = palette.border.extend(alpha);
+
+    dd.begin(DU_DRAW_LINES, width);
+    for c in &cset.contours {
+        let vs = verts(c);
+        let col = color(c);
+        for (i, v) in vs.iter().enumerate() {
+            let j = if i == 0 { vs.len() - 1 } else { i - 1 };
+            let edge = if v[3] & RC_AREA_BORDER != 0 {
+                col.lerp(border, 0.5)
+            } else {
+                col
+            };
+            dd.vertex(pos(&vs[j], j), edge);
+            dd.vertex(pos(v, i), edge);
+        }
+    }
+    dd.end();
+
+    // Vertices on the tile border are white, or the palette's border color
+    dd.begin(DU_DRAW_POINTS, width + 1.0);
+    for c in &cset.contours {
+        let shade = (color(c).truncate() * 0.5).extend(alpha);
+        for (i, v) in verts(c).iter().enumerate() {
+            let col = if v[3] & RC_BORDER_VERTEX != 0 {
+                border
+            } else {
+                shade
+            };
+            dd.vertex(pos(v, i), col);
+        }
+    }
+    dd.end();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debug_draw_b::{du_debug_draw_tri_mesh_slope, InputMesh};
+
+    // Records vertex counts per primitive type
+    #[derive(Default)]
+    struct MockDebugDraw {
+        prim: Option<DebugDrawPrimitive>,
+        counts: [usize; 4],
+    }
+
+    impl DebugDraw for MockDebugDraw {
+        fn begin(&mut self, prim: DebugDrawPrimitive, _size: f32) {
+            assert!(self.prim.is_none(), "begin without end");
+            self.prim = Some(prim);
+        }
+
+        fn end(&mut self) {
+            assert!(self.prim.take().is_some(), "end without begin");
+        }
+
+        fn vertex(&mut self, pos: Vec3, color: Vec4) {
+            self.vertex_uv(pos, color, Vec2::ZERO);
+        }
+
+        fn vertex_uv(&mut self, _pos: Vec3, _color: Vec4, _uv: Vec2) {
+            self.counts[self.prim.expect("vertex outside begin/end") as usize] += 1;
+        }
+
+        fn texture(&mut self, _state: bool) {}
+
+        fn area_to_col(&self, _area: u8) -> Vec4 {
+            Vec4::ONE
+        }
+    }
+
+    #[test]
+    fn test_default_area_colors_are_distinct() {
+        // The low ids a level typically uses, and every pair of neighbouring
+        // ids, differ by a visible amount
+        let pairs = (1..8u8)
+            .flat_map(|a| ((a + 1)..8).map(move |b| (a, b)))
+            .chain((1..RC_WALKABLE_AREA - 1).map(|a| (a, a + 1)));
+        for (a, b) in pairs {
+            let diff = AreaPalette::default_color(a) - AreaPalette::default_color(b);
+            assert!(
+                diff.truncate().abs().max_element() > 0.05,
+                "areas {} and {} look alike",
+                a,
+                b
+            );
+        }
+    }
+
+    #[test]
+    fn test_region_colors_are_stable_and_distinct() {
+        assert_eq!(du_int_to_col(1234, 0.5), du_int_to_col(1234, 0.5));
+        assert_eq!(du_int_to_col(1234, 0.5).w, 0.5);
+        // Consecutive regions, well past the 64 area ids, don't repeat
+        for id in 0..500 {
+            let diff = du_int_to_col(id, 1.0) - du_int_to_col(id + 1, 1.0);
+            assert!(
+                diff.truncate().abs().max_element() > 0.05,
+                "regions {} and {}",
+                id,
+                id + 1
+            );
+        }
+    }
+
+    #[test]
+    fn test_area_palette_text_round_trip() {
+        let mut palette = AreaPalette::default();
+        assert_ne!(palette.color(1), palette.color(2));
+
+        palette.set_color(5, Vec4::new(1.0, 0.0, 0.0, 1.0));
+        let text = palette.to_text();
+        assert_eq!(text, "5 255 0 0 255\n");
+
+        let loaded = AreaPalette::from_text(&text).unwrap();
+        assert_eq!(loaded, palette);
+        assert!(AreaPalette::from_text("70 1 2 3 4").is_err());
+
+        // Resetting drops the override and goes back to the hashed hue
+        palette.reset_color(5);
+        assert!(!palette.is_overridden(5));
+        assert_eq!(palette, AreaPalette::DEFAULT);
+        assert_eq!(palette.color(5), AreaPalette::default_color(5));
+    }
+
+    // A 2x2 ramp rising one unit along +x, split through an interior vertex
+    fn ramp_detail() -> PolyMeshDetail {
+        let y = |x: f32| x * 0.5;
+        let corners = [(0.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0), (1.0, 1.0)];
+        // Edges 0-1, 1-2, 2-3, 3-0 lie on the polygon boundary (flag 1)
+        let boundary = |k: usize| 1u8 << (k * 2);
+        PolyMeshDetail {
+            meshes: vec![[0, 5, 0, 4]],
+            verts: corners
+                .iter()
+                .map(|&(x, z)| Vec3::new(x, y(x), z))
+                .collect(),
+            tris: vec![
+                [0, 1, 4, boundary(0)],
+                [1, 2, 4, boundary(0)],
+                [2, 3, 4, boundary(0)],
+                [3, 0, 4, boundary(0)],
+            ],
+        }
+    }
+
+    // Keeps every batch it is given
+    #[derive(Default)]
+    struct RecordingDebugDraw {
+        batcher: PrimitiveBatcher,
+        batches: Vec<PrimitiveBatch>,
+    }
+
+    impl BatchedDebugDraw for RecordingDebugDraw {
+        fn batcher(&mut self) -> &mut PrimitiveBatcher {
+            &mut self.batcher
+        }
+
+        fn flush(&mut self, batch: PrimitiveBatch) {
+            self.batches.push(batch);
+        }
+    }
+
+    #[test]
+    fn test_batcher_splits_quads_and_drops_partial_primitives() {
+        let corner = |i: usize| Vec3::new((i & 1) as f32, 0.0, (i >> 1) as f32);
+        let mut dd = RecordingDebugDraw::default();
+        dd.texture(true);
+        dd.begin(DU_DRAW_QUADS, 1.0);
+        // One whole quad and three corners of another
+        for i in [0, 1, 3, 2, 0, 1, 3] {
+            dd.vertex(corner(i), Vec4::ONE);
+        }
+        dd.end();
+        dd.texture(false);
+        dd.begin(DU_DRAW_LINES, 2.0);
+        for i in 0..3 {
+            dd.vertex(corner(i), Vec4::ONE);
+        }
+        // A missing end still hands the lines over at the next begin
+        dd.begin(DU_DRAW_POINTS, 4.0);
+        dd.end();
+
+        assert_eq!(dd.batches.len(), 2);
+        let quads = &dd.batches[0];
+        assert_eq!((quads.prim, quads.textured), (DU_DRAW_TRIS, true));
+        let positions: Vec<Vec3> = quads.vertices.iter().map(|v| v.pos).collect();
+        assert_eq!(positions, [0, 1, 3, 0, 3, 2].map(corner));
+        let lines = &dd.batches[1];
+        assert_eq!(
+            (lines.prim, lines.size, lines.textured),
+            (DU_DRAW_LINES, 2.0, false)
+        );
+        assert_eq!(lines.vertices.len(), 2);
+    }
+
+    #[test]
+    fn test_checker_texture() {
+        assert_eq!(checker_shade(Vec2::new(0.25, 0.25)), CHECKER_LIGHT);
+        assert_eq!(checker_shade(Vec2::new(0.75, 0.25)), CHECKER_DARK);
+        // Repeats, including below zero
+        assert_eq!(checker_shade(Vec2::new(-0.25, 0.25)), CHECKER_DARK);
+        assert_eq!(checker_shade(Vec2::new(3.75, -1.25)), CHECKER_LIGHT);
+
+        let pixels = checker_texture_pixels();
+        let half = CHECKER_TEXTURE_SIZE / 2;
+        assert_eq!(pixels.len(), CHECKER_TEXTURE_SIZE * CHECKER_TEXTURE_SIZE);
+        assert_eq!(pixels[0], CHECKER_LIGHT);
+        assert_eq!(pixels[half], CHECKER_DARK);
+        assert_eq!(pixels[half * CHECKER_TEXTURE_SIZE + half], CHECKER_LIGHT);
+    }
+
+    #[test]
+    fn test_debug_palette_presets() {
+        for preset in &DebugPalette::PRESETS {
+            assert_eq!(DebugPalette::by_name(preset.name), Some(preset));
+        }
+        assert_eq!(DebugPalette::by_name("Sepia"), None);
+        assert_eq!(DebugPalette::default(), DebugPalette::RECAST);
+
+        // Regions cycle through a fixed ramp, or fall back to the area hues
+        let colorblind = DebugPalette::COLORBLIND;
+        let ramp = colorblind.region_ramp.len();
+        assert_eq!(
+            colorblind.region_color(1, 0.5),
+            colorblind.region_color(1 + ramp, 0.5)
+        );
+        assert_eq!(colorblind.region_color(1, 0.5).w, 0.5);
+        assert_eq!(
+            DebugPalette::RECAST.region_color(3, 1.0),
+            du_int_to_col(3, 1.0)
+        );
+
+        // Draw functions take their colors from the backend's palette
+        let mut dd = RecordingDebugDraw::default();
+        du_debug_draw_axes(&mut dd, Vec3::ZERO, 1.0, 1.0);
+        let colors: Vec<Vec4> = dd.batches[0].vertices.iter().map(|v| v.color).collect();
+        assert_eq!(colors[..2], [DebugPalette::RECAST.axes[0]; 2]);
+        assert_eq!(
+            poly_area_color(&dd, RC_WALKABLE_AREA),
+            DebugPalette::RECAST.walkable
+        );
+    }
+
+    #[test]
+    fn test_overlay_line_counts() {
+        let mut dd = MockDebugDraw::default();
+        du_debug_draw_grid_xz(&mut dd, Vec3::ZERO, 4, 2, 1.0, Vec4::ONE, 1.0);
+        // 3 lines along x and 5 along z
+        assert_eq!(dd.counts[DU_DRAW_LINES as usize], 2 * 8);
+        du_debug_draw_box_wire(&mut dd, Vec3::ZERO, Vec3::ONE, Vec4::ONE, 1.0);
+        assert_eq!(dd.counts[DU_DRAW_LINES as usize], 2 * (8 + 12));
+        du_debug_draw_axes(&mut dd, Vec3::ZERO, 1.0, 1.0);
+        assert_eq!(dd.counts[DU_DRAW_LINES as usize], 2 * (8 + 12 + 3));
+        assert!(dd.prim.is_none());
+    }
+
+    #[test]
+    fn test_heightfield_spans_draw_as_boxes() {
+        let mut hf = Heightfield::new(Vec3::ZERO, Vec3::new(2.0, 4.0, 1.0), 1.0, 0.5);
+        hf.add_span(0, 0, 0, 2, RC_WALKABLE_AREA, 1);
+        hf.add_span(0, 0, 4, 6, RC_NULL_AREA, 1);
+        hf.add_span(1, 0, 1, 3, 5, 1);
+
+        let mut dd = MockDebugDraw::default();
+        du_debug_draw_heightfield_solid(&mut dd, &hf);
+        assert_eq!(dd.counts[DU_DRAW_TRIS as usize], 3 * 36);
+        du_debug_draw_heightfield_walkable(&mut dd, &hf);
+        assert_eq!(dd.counts[DU_DRAW_TRIS as usize], 6 * 36);
+        assert!(dd.prim.is_none());
+
+        // Two open spans, one above each walkable solid span
+        let mut chf = CompactHeightfield::build(&hf, 2, 1);
+        assert_eq!(chf.span_count(), 2);
+        let mut dd = MockDebugDraw::default();
+        du_debug_draw_compact_heightfield_solid(&mut dd, &chf);
+        du_debug_draw_compact_heightfield_regions(&mut dd, &chf);
+        assert_eq!(dd.counts[DU_DRAW_TRIS as usize], 2 * chf.span_count() * 6);
+        du_debug_draw_compact_heightfield_distance(&mut dd, &chf);
+        assert_eq!(dd.counts[DU_DRAW_TRIS as usize], 2 * chf.span_count() * 6);
+        crate::regions::build_distance_field(&mut chf);
+        du_debug_draw_compact_heightfield_distance(&mut dd, &chf);
+        assert_eq!(dd.counts[DU_DRAW_TRIS as usize], 3 * chf.span_count() * 6);
+    }
+
+    #[test]
+    fn test_contours_draw_closed_loops() {
+        let contour = |n: usize| Contour {
+            verts: (0..n as i32).map(|i| [i, 0, i * i, 0]).collect(),
+            rverts: (0..2 * n as i32).map(|i| [i, 0, i * i, 0]).collect(),
+            reg: 1,
+            area: RC_WALKABLE_AREA,
+        };
+        let mut cset = ContourSet {
+            contours: vec![contour(4), contour(3)],
+            ..ContourSet::default()
+        };
+        cset.contours[0].verts[0][3] = RC_BORDER_VERTEX;
+
+        let mut dd = MockDebugDraw::default();
+        du_debug_draw_contours(&mut dd, &cset, 0.5);
+        // One line per vertex, closing each loop
+        assert_eq!(dd.counts[DU_DRAW_LINES as usize], 2 * 7);
+        assert_eq!(dd.counts[DU_DRAW_POINTS as usize], 7);
+        du_debug_draw_raw_contours(&mut dd, &cset, 0.5);
+        assert_eq!(dd.counts[DU_DRAW_LINES as usize], 2 * 7 + 2 * 14);
+        assert!(dd.prim.is_none());
+    }
+
+    #[test]
+    fn test_detail_height_tracks_slope() {
+        let dmesh = ramp_detail();
+        for x in [0.0, 0.5, 1.3, 2.0] {
+            let h = dmesh.height(0, Vec3::new(x, 100.0, 0.7)).unwrap();
+            assert!((h - x * 0.5).abs() < 1e-5, "height at x={} was {}", x, h);
+        }
+        assert!(dmesh.height(0, Vec3::new(3.0, 0.0, 1.0)).is_none());
+        assert!(dmesh.height(1, Vec3::ZERO).is_none());
+
+        let mut dd = MockDebugDraw::default();
+        du_debug_draw_poly_mesh_detail(&mut dd, &dmesh);
+        assert_eq!(dd.counts[DU_DRAW_TRIS as usize], 12);
+        // Four spokes to the center drawn once plus four boundary edges
+        assert_eq!(dd.counts[DU_DRAW_LINES as usize], 16);
+        assert_eq!(dd.counts[DU_DRAW_POINTS as usize], 5);
+    }
+
+    #[test]
+    fn test_both_draw_functions_share_one_backend() {
+        let mut dd = MockDebugDraw::default();
+
+        let mut input = InputMesh::new();
+        input.verts = vec![Vec3::ZERO, Vec3::X, Vec3::Z];
+        input.tris = vec![0, 2, 1];
+        input.compute_normals();
+        du_debug_draw_tri_mesh_slope(&mut dd, &input, 45.0, 1.0);
+        assert_eq!(dd.counts[DU_DRAW_TRIS as usize], 3);
+
+        // One quad polygon: two fill triangles, four edges, four vertices
+        let poly_mesh = PolyMesh {
+            verts: vec![
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(1.0, 0.0, 1.0),
+                Vec3::new(0.0, 0.0, 1.0),
+            ],
+            polys: vec![vec![0, 1, 2, 3, RC_MESH_NULL_IDX, RC_MESH_NULL_IDX]],
+            areas: vec![RC_WALKABLE_AREA],
+            nvp: 6,
+            cs: 0.3,
+            ch: 0.2,
+            ..Default::default()
+        };
+        du_debug_draw_poly_mesh(&mut dd, &poly_mesh);
+        assert_eq!(dd.counts[DU_DRAW_TRIS as usize], 3 + 6);
+        assert_eq!(dd.counts[DU_DRAW_LINES as usize], 8);
+        assert_eq!(dd.counts[DU_DRAW_POINTS as usize], 4);
+        assert!(dd.prim.is_none());
+    }
+}
@@ -1,12 +1,62 @@
 use egui::Vec2;
 use glam::{Vec3, Vec4};
 use std::f32::consts::PI;
+use std::fmt;
+
+use crate::area_mapping::AreaMapping;
+use crate::convex_volume::ConvexVolume;
+use crate::crowd::Crowd;
+use crate::debug_draw::*;
+use crate::nav_mesh::{NavMesh, PolyRef};
+use crate::nav_mesh_query::PointToPoint;
+use crate::navmesh_build::mark_walkable_triangles;
+use crate::obj_loader::ObjData;
+use crate::offmesh::OffMeshConnection;
 
 // Input mesh data structure
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct InputMesh {
     pub verts: Vec<Vec3>,
+    // Vertex indices, three per triangle
     pub tris: Vec<i32>,
+    // One face normal per triangle: normals.len() == tris.len() / 3
     pub normals: Vec<Vec3>,
+    // Area id per triangle, empty when the source assigned none
+    pub areas: Vec<u8>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum InputMeshError {
+    // tris.len() is not a multiple of 3
+    IncompleteTriangle(usize),
+    IndexOutOfBounds { tri: usize, index: i32 },
+    NormalCountMismatch { expected: usize, found: usize },
+}
+
+impl fmt::Display for InputMeshError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputMeshError::IncompleteTriangle(len) => {
+                write!(f, "{} indices don't make whole triangles", len)
+            }
+            InputMeshError::IndexOutOfBounds { tri, index } => {
+                write!(
+                    f,
+                    "triangle {} uses vertex {}, which doesn't exist",
+                    tri, index
+                )
+            }
+            InputMeshError::NormalCountMismatch { expected, found } => {
+                write!(f, "expected {} normals, found {}", expected, found)
+            }
+        }
+    }
+}
+
+impl Default for InputMesh {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl InputMesh {
@@ -15,10 +65,178 @@ impl InputMesh {
             verts: Vec::new(),
             tris: Vec::new(),
             normals: Vec::new(),
+            areas: Vec::new(),
+        }
+    }
+
+    pub fn tri_count(&self) -> usize {
+        self.tris.len() / 3
+    }
+
+    // Bounds of the vertices, zero-sized at the origin when there are none
+    pub fn bounds(&self) -> (Vec3, Vec3) {
+        bounds_of(self.verts.iter().copied())
+    }
+
+    // Fill `normals` from `verts` and `tris`
+    pub fn compute_normals(&mut self) {
+        self.normals = compute_tri_normals(&self.verts, &self.tris);
+    }
+
+    // Check index bounds and the normals length so mismatches are reported
+    // up front instead of panicking in the middle of a draw
+    pub fn validate(&self) -> Result<(), InputMeshError> {
+        self.validate_indices()?;
+        if self.normals.len() != self.tri_count() {
+            return Err(InputMeshError::NormalCountMismatch {
+                expected: self.tri_count(),
+                found: self.normals.len(),
+            });
+        }
+        Ok(())
+    }
+
+    // The part of `validate` that must pass before `compute_normals` can run
+    pub fn validate_indices(&self) -> Result<(), InputMeshError> {
+        if !self.tris.len().is_multiple_of(3) {
+            return Err(InputMeshError::IncompleteTriangle(self.tris.len()));
+        }
+        for (i, &index) in self.tris.iter().enumerate() {
+            if index < 0 || index as usize >= self.verts.len() {
+                return Err(InputMeshError::IndexOutOfBounds { tri: i / 3, index });
+            }
         }
+        Ok(())
     }
 }
 
+// Smallest box around `points`, zero-sized at the origin when there are
+// none so grids sized from it come out empty instead of overflowing
+pub fn bounds_of(points: impl IntoIterator<Item = Vec3>) -> (Vec3, Vec3) {
+    let mut points = points.into_iter();
+    let Some(first) = points.next() else {
+        return (Vec3::ZERO, Vec3::ZERO);
+    };
+    points.fold((first, first), |(min, max), v| (min.min(v), max.max(v)))
+}
+
+impl InputMesh {
+    // Closest ray/triangle intersection as (distance along dir, triangle index)
+    pub fn raycast(&self, origin: Vec3, dir: Vec3) -> Option<(f32, usize)> {
+        let mut best: Option<(f32, usize)> = None;
+
+        for (i, tri) in self.tris.chunks_exact(3).enumerate() {
+            let a = self.verts[tri[0] as usize];
+            let b = self.verts[tri[1] as usize];
+            let c = self.verts[tri[2] as usize];
+
+            if let Some(t) = ray_triangle(origin, dir, a, b, c) {
+                if best.is_none_or(|(best_t, _)| t < best_t) {
+                    best = Some((t, i));
+                }
+            }
+        }
+
+        best
+    }
+}
+
+// Distance along `dir` to triangle abc, double sided (Moller-Trumbore)
+pub fn ray_triangle(origin: Vec3, dir: Vec3, a: Vec3, b: Vec3, c: Vec3) -> Option<f32> {
+    let e1 = b - a;
+    let e2 = c - a;
+    let p = dir.cross(e2);
+    let det = e1.dot(p);
+    if det.abs() < 1e-8 {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+    let s = origin - a;
+    let u = s.dot(p) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = s.cross(e1);
+    let v = dir.dot(q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let t = e2.dot(q) * inv_det;
+    (t >= 0.0).then_some(t)
+}
+
+// `areas` maps group, object and `usemtl` names to area ids; faces none of
+// whose names are mapped get RC_WALKABLE_AREA. Faces referencing vertices
+// the file doesn't define are an error.
+pub fn obj_to_input_mesh(
+    obj: &ObjData,
+    areas: Option<&AreaMapping>,
+) -> Result<InputMesh, InputMeshError> {
+    let mut mesh = InputMesh::new();
+
+    // Convert vertices
+    mesh.verts = obj.vertices.iter().skip(1).copied().collect();
+
+    // Triangulate faces and add indices
+    let triangles = obj.triangulate_with_faces();
+    mesh.tris = triangles
+        .iter()
+        // Adjust indices to be 0-based
+        .flat_map(|(_, tri)| tri.map(|i| i as i32 - 1))
+        .collect();
+
+    if let Some(areas) = areas {
+        mesh.areas = triangles
+            .iter()
+            .map(|&(face, _)| {
+                areas
+                    .area_for(
+                        obj.face_group(face),
+                        obj.face_object(face),
+                        obj.face_material(face),
+                    )
+                    .unwrap_or(RC_WALKABLE_AREA)
+            })
+            .collect();
+    }
+
+    // Calculate one normal per triangle, once every index is known to be valid
+    mesh.validate_indices()?;
+    mesh.compute_normals();
+
+    Ok(mesh)
+}
+
+fn tri_normal(verts: &[Vec3], tri: &[i32]) -> Vec3 {
+    let v0 = verts[tri[0] as usize];
+    let v1 = verts[tri[1] as usize];
+    let v2 = verts[tri[2] as usize];
+    // Fall back to straight up rather than producing a NaN normal
+    (v1 - v0).cross(v2 - v0).try_normalize().unwrap_or(Vec3::Y)
+}
+
+#[cfg(not(feature = "rayon"))]
+fn compute_tri_normals(verts: &[Vec3], tris: &[i32]) -> Vec<Vec3> {
+    compute_tri_normals_serial(verts, tris)
+}
+
+#[cfg(feature = "rayon")]
+fn compute_tri_normals(verts: &[Vec3], tris: &[i32]) -> Vec<Vec3> {
+    use rayon::prelude::*;
+
+    // Indexed collect keeps the output order identical to the serial path
+    tris.par_chunks_exact(3)
+        .map(|tri| tri_normal(verts, tri))
+        .collect()
+}
+
+#[cfg(any(test, not(feature = "rayon")))]
+fn compute_tri_normals_serial(verts: &[Vec3], tris: &[i32]) -> Vec<Vec3> {
+    tris.chunks_exact(3)
+        .map(|tri| tri_normal(verts, tri))
+        .collect()
+}
+
 // Debug draw for input triangle mesh with slope visualization
 pub fn du_debug_draw_tri_mesh_slope(
     dd: &mut impl DebugDraw,
@@ -26,28 +244,48 @@ pub fn du_debug_draw_tri_mesh_slope(
     walkable_slope_angle: f32,
     tex_scale: f32,
 ) {
-    if mesh.verts.is_empty() || mesh.tris.is_empty() || mesh.normals.is_empty() {
+    du_debug_draw_tri_mesh_slope_tris(
+        dd,
+        mesh,
+        0..mesh.tri_count(),
+        walkable_slope_angle,
+        tex_scale,
+    );
+}
+
+// `du_debug_draw_tri_mesh_slope` for only the triangles in `tris`, e.g. the
+// ones left after culling. `mesh` must have passed `validate`, which runs
+// once on import rather than on every draw.
+pub fn du_debug_draw_tri_mesh_slope_tris(
+    dd: &mut impl DebugDraw,
+    mesh: &InputMesh,
+    tris: impl IntoIterator<Item = usize>,
+    walkable_slope_angle: f32,
+    tex_scale: f32,
+) {
+    if mesh.verts.is_empty() || mesh.tris.is_empty() {
         return;
     }
 
-    // Calculate walkable threshold from slope angle
-    let walkable_thr = (walkable_slope_angle / 180.0 * PI).cos();
+    // Same classification the build rasterizes with
+    let areas = mark_walkable_triangles(walkable_slope_angle, mesh);
 
     dd.texture(true);
 
     dd.begin(DU_DRAW_TRIS, 1.0);
 
-    let unwalkable = Vec4::new(0.75, 0.5, 0.0, 1.0); // Similar to duRGBA(192,128,0,255)
+    let unwalkable = dd.palette().unwalkable;
 
     // Process triangles
-    for i in (0..mesh.tris.len()).step_by(3) {
-        let norm = &mesh.normals[i];
+    for tri in tris {
+        let i = tri * 3;
+        let norm = &mesh.normals[tri];
 
         // Calculate color based on slope
         let a = ((2.0 + norm.x + norm.y) / 4.0 * 220.0) as u8;
         let base_col = Vec4::new(a as f32 / 255.0, a as f32 / 255.0, a as f32 / 255.0, 1.0);
 
-        let color = if norm.y < walkable_thr {
+        let color = if areas[tri] == RC_NULL_AREA {
             lerp_col(base_col, unwalkable, 64.0 / 255.0)
         } else {
             base_col
@@ -59,18 +297,7 @@ pub fn du_debug_draw_tri_mesh_slope(
         let vc = &mesh.verts[mesh.tris[i + 2] as usize];
 
         // Calculate texture coordinates
-        let mut ax = 0;
-        let mut ay = 0;
-
-        if norm.y.abs() > norm[ax].abs() {
-            ax = 1;
-        }
-        if norm.z.abs() > norm[ax].abs() {
-            ax = 2;
-        }
-
-        ax = (1 << ax) & 3; // +1 mod 3
-        ay = (1 << ax) & 3; // +1 mod 3
+        let (ax, ay) = tex_axes(norm);
 
         let uva = tex_coord(va, ax, ay, tex_scale);
         let uvb = tex_coord(vb, ax, ay, tex_scale);
@@ -85,6 +312,27 @@ pub fn du_debug_draw_tri_mesh_slope(
     dd.texture(false);
 }
 
+// Minimum normal.y of a walkable triangle for a slope limit in degrees
+pub fn walkable_threshold(walkable_slope_angle: f32) -> f32 {
+    (walkable_slope_angle / 180.0 * PI).cos()
+}
+
+// Pick the two in-plane axes for planar UV projection: the axes following
+// the dominant normal axis in x -> y -> z -> x order
+fn tex_axes(norm: &Vec3) -> (usize, usize) {
+    let mut ax = 0;
+    if norm.y.abs() > norm[ax].abs() {
+        ax = 1;
+    }
+    if norm.z.abs() > norm[ax].abs() {
+        ax = 2;
+    }
+
+    let ax = (ax + 1) % 3;
+    let ay = (ax + 1) % 3;
+    (ax, ay)
+}
+
 fn tex_coord(v: &Vec3, ax: usize, ay: usize, scale: f32) -> Vec2 {
     Vec2::new(v[ax] * scale, v[ay] * scale)
 }
@@ -93,14 +341,449 @@ fn lerp_col(a: Vec4, b: Vec4, t: f32) -> Vec4 {
     a.lerp(b, t)
 }
 
-// Add to DebugDraw trait
-pub trait DebugDraw {
-    fn begin(&mut self, prim: i32, size: f32);
-    fn end(&mut self);
-    fn vertex(&mut self, pos: Vec3, color: Vec4);
-    fn vertex_uv(&mut self, pos: Vec3, color: Vec4, uv: Vec2);
-    fn texture(&mut self, state: bool);
+// Debug draw for off-mesh connections: an arc between the endpoints and a
+// circle of the connection radius around each end
+pub fn du_debug_draw_off_mesh_connections(dd: &mut impl DebugDraw, cons: &[OffMeshConnection]) {
+    const CIRCLE_SEGMENTS: usize = 40;
+
+    dd.begin(DU_DRAW_LINES, 2.0);
+
+    for con in cons {
+        let color = dd.area_to_col(con.area);
+        append_arc(dd, con.start, con.end, color);
+
+        // One-way connections get a dimmed start circle
+        let start_color = if con.bidirectional {
+            color
+        } else {
+            Vec4::new(color.x, color.y, color.z, color.w * 0.5)
+        };
+        for (center, col) in [(con.start, start_color), (con.end, color)] {
+            for i in 0..CIRCLE_SEGMENTS {
+                let a0 = i as f32 / CIRCLE_SEGMENTS as f32 * PI * 2.0;
+                let a1 = (i + 1) as f32 / CIRCLE_SEGMENTS as f32 * PI * 2.0;
+                dd.vertex(
+                    center + Vec3::new(a0.cos(), 0.0, a0.sin()) * con.radius,
+                    col,
+                );
+                dd.vertex(
+                    center + Vec3::new(a1.cos(), 0.0, a1.sin()) * con.radius,
+                    col,
+                );
+            }
+        }
+    }
+
+    dd.end();
+}
+
+// Off-mesh connections as baked into the navmesh: an arc between where the
+// ends landed and a line back to each authored end point. Ends that found
+// no ground to link to are red.
+pub fn du_debug_draw_nav_mesh_off_mesh_connections(dd: &mut impl DebugDraw, nav_mesh: &NavMesh) {
+    const POST_HEIGHT: f32 = 0.2;
+    let unlinked = dd.palette().unlinked;
+
+    dd.begin(DU_DRAW_LINES, 2.0);
+    for tile in nav_mesh.tiles() {
+        for con in &tile.data.off_mesh_cons {
+            let ip = con.poly as usize;
+            let poly = &tile.data.polys[ip];
+            let landed = [0, 1].map(|k| tile.data.verts[poly.verts[k] as usize]);
+            // Links out of the connection use edge 0 for the start, 1 for the end
+            let linked = [0, 1].map(|edge| tile.links_of(ip).any(|l| l.edge == edge));
+            let color = dd.area_to_col(poly.area);
+
+            let arc_color = if linked[0] && linked[1] {
+                color
+            } else {
+                unlinked
+            };
+            append_arc(dd, landed[0], landed[1], arc_color);
+            for k in 0..2 {
+                let col = if linked[k] { color } else { unlinked };
+                dd.vertex(con.pos[k], col);
+                dd.vertex(landed[k], col);
+                dd.vertex(landed[k], col);
+                dd.vertex(landed[k] + Vec3::Y * POST_HEIGHT, col);
+            }
+        }
+    }
+    dd.end();
 }
 
-// Constants
-pub const DU_DRAW_TRIS: i32 = 2;
+// Line segments of an arc from `start` to `end` rising a quarter of their
+// distance above the chord, inside a begin(DU_DRAW_LINES)
+fn append_arc(dd: &mut impl DebugDraw, start: Vec3, end: Vec3, color: Vec4) {
+    const ARC_SEGMENTS: usize = 16;
+
+    let delta = end - start;
+    let height = delta.length() * 0.25;
+    let eval_arc = |u: f32| {
+        let mut p = start + delta * u;
+        p.y += height * (1.0 - (u * 2.0 - 1.0) * (u * 2.0 - 1.0));
+        p
+    };
+    let mut prev = eval_arc(0.0);
+    for i in 1..=ARC_SEGMENTS {
+        let next = eval_arc(i as f32 / ARC_SEGMENTS as f32);
+        dd.vertex(prev, color);
+        dd.vertex(next, color);
+        prev = next;
+    }
+}
+
+// Translucent prisms with outlined top and bottom faces, like Recast's
+// ConvexVolumeTool
+pub fn du_debug_draw_convex_volumes(dd: &mut impl DebugDraw, volumes: &[ConvexVolume]) {
+    dd.begin(DU_DRAW_TRIS, 1.0);
+    for vol in volumes {
+        let mut color = dd.area_to_col(vol.area);
+        color.w = 0.125; // alpha 32
+        let at = |v: Vec3, y: f32| Vec3::new(v.x, y, v.z);
+        let n = vol.verts.len();
+        for i in 0..n {
+            let (va, vb) = (vol.verts[(i + n - 1) % n], vol.verts[i]);
+            // Top face as a fan, sides as quads
+            if i >= 2 {
+                dd.vertex(at(vol.verts[0], vol.hmax), color);
+                dd.vertex(at(va, vol.hmax), color);
+                dd.vertex(at(vb, vol.hmax), color);
+            }
+            for (p, q, r) in [
+                (at(va, vol.hmin), at(vb, vol.hmin), at(vb, vol.hmax)),
+                (at(va, vol.hmin), at(vb, vol.hmax), at(va, vol.hmax)),
+            ] {
+                dd.vertex(p, color);
+                dd.vertex(q, color);
+                dd.vertex(r, color);
+            }
+        }
+    }
+    dd.end();
+
+    dd.begin(DU_DRAW_LINES, 2.0);
+    for vol in volumes {
+        let color = dd.area_to_col(vol.area);
+        let at = |v: Vec3, y: f32| Vec3::new(v.x, y, v.z);
+        let n = vol.verts.len();
+        for i in 0..n {
+            let (va, vb) = (vol.verts[(i + n - 1) % n], vol.verts[i]);
+            for y in [vol.hmin, vol.hmax] {
+                dd.vertex(at(va, y), color);
+                dd.vertex(at(vb, y), color);
+            }
+            dd.vertex(at(va, vol.hmin), color);
+            dd.vertex(at(va, vol.hmax), color);
+        }
+    }
+    dd.end();
+}
+
+// What `du_debug_draw_crowd` draws besides the agents themselves
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CrowdDrawOptions {
+    pub corridors: bool,
+    pub velocities: bool,
+    // Walls and avoidance samples, for the selected agent only
+    pub boundaries: bool,
+    pub avoidance_samples: bool,
+}
+
+impl Default for CrowdDrawOptions {
+    fn default() -> Self {
+        Self {
+            corridors: true,
+            velocities: true,
+            boundaries: false,
+            avoidance_samples: true,
+        }
+    }
+}
+
+// Crowd agents as wire cylinders, with their corridors and velocities, like
+// the Recast demo's CrowdTool. The selected agent is highlighted and its
+// avoidance samples come from the crowd's last update.
+pub fn du_debug_draw_crowd(
+    dd: &mut impl DebugDraw,
+    nav_mesh: &NavMesh,
+    crowd: &Crowd,
+    selected: Option<usize>,
+    options: CrowdDrawOptions,
+) {
+    const CYLINDER_SEGMENTS: usize = 16;
+    const LIFT: f32 = 0.05;
+    let palette = *dd.palette();
+    let agent_col = palette.agent;
+    let selected_col = palette.agent_selected;
+    let corridor_col = palette.corridor;
+    let dvel_col = palette.desired_velocity;
+    let vel_col = palette.velocity;
+    let wall_col = palette.wall;
+
+    dd.begin(DU_DRAW_LINES, 2.0);
+    for (id, agent) in crowd.agents() {
+        let pos = agent.pos();
+        let (radius, height) = (agent.params.radius, agent.params.height);
+        let col = if selected == Some(id) {
+            selected_col
+        } else {
+            agent_col
+        };
+
+        let ring = |i: usize, y: f32| {
+            let a = i as f32 / CYLINDER_SEGMENTS as f32 * PI * 2.0;
+            pos + Vec3::new(a.cos() * radius, y, a.sin() * radius)
+        };
+        for i in 0..CYLINDER_SEGMENTS {
+            for y in [LIFT, height] {
+                dd.vertex(ring(i, y), col);
+                dd.vertex(ring(i + 1, y), col);
+            }
+            if i % (CYLINDER_SEGMENTS / 4) == 0 {
+                dd.vertex(ring(i, LIFT), col);
+                dd.vertex(ring(i, height), col);
+            }
+        }
+
+        if options.corridors {
+            for &r in agent.corridor().path() {
+                let Ok((tile, poly)) = nav_mesh.tile_and_poly_by_ref(r) else {
+                    continue;
+                };
+                let verts = poly.vert_indices();
+                for (j, &v) in verts.iter().enumerate() {
+                    let next = verts[(j + 1) % verts.len()];
+                    dd.vertex(tile.data.verts[v as usize] + Vec3::Y * LIFT, corridor_col);
+                    dd.vertex(
+                        tile.data.verts[next as usize] + Vec3::Y * LIFT,
+                        corridor_col,
+                    );
+                }
+            }
+            let mut prev = pos;
+            for corner in agent.corners() {
+                dd.vertex(prev + Vec3::Y * LIFT, col);
+                dd.vertex(corner.pos + Vec3::Y * LIFT, col);
+                prev = corner.pos;
+            }
+        }
+
+        if options.velocities {
+            let base = pos + Vec3::Y * height;
+            dd.vertex(base, dvel_col);
+            dd.vertex(base + agent.desired_vel(), dvel_col);
+            dd.vertex(base, vel_col);
+            dd.vertex(base + agent.vel(), vel_col);
+        }
+
+        if options.boundaries && selected == Some(id) {
+            for seg in agent.boundary().segments() {
+                dd.vertex(seg.p + Vec3::Y * LIFT, wall_col);
+                dd.vertex(seg.q + Vec3::Y * LIFT, wall_col);
+            }
+        }
+    }
+    dd.end();
+
+    // Samples as squares around the selected agent, from white for the
+    // best to brown for the worst
+    let Some(agent) = selected.and_then(|id| crowd.agent(id)) else {
+        return;
+    };
+    if !options.avoidance_samples {
+        return;
+    }
+    let mut debug = crowd.avoidance_debug().clone();
+    debug.normalize_samples();
+    let base = agent.pos() + Vec3::Y * agent.params.height;
+    let (best, worst) = (palette.sample_best, palette.sample_worst);
+    dd.begin(DU_DRAW_TRIS, 1.0);
+    for sample in &debug.samples {
+        let c = base + sample.vel;
+        let s = sample.size;
+        let col = lerp_col(best, worst, sample.penalty);
+        let corners = [
+            c + Vec3::new(-s, 0.0, -s),
+            c + Vec3::new(-s, 0.0, s),
+            c + Vec3::new(s, 0.0, s),
+            c + Vec3::new(s, 0.0, -s),
+        ];
+        for i in [0, 1, 2, 0, 2, 3] {
+            dd.vertex(corners[i], col);
+        }
+    }
+    dd.end();
+}
+
+// Results of the navmesh test tool, like the Recast demo's NavMeshTesterTool:
+// the path corridor shaded with the start and end polygons picked out, the
+// straight path, the raycast up to the wall it hit, and a line from each
+// clicked point to the nearest point on the navmesh
+pub fn du_debug_draw_point_to_point(
+    dd: &mut impl DebugDraw,
+    nav_mesh: &NavMesh,
+    start_pos: Option<Vec3>,
+    end_pos: Option<Vec3>,
+    result: &PointToPoint,
+) {
+    const LIFT: f32 = 0.1;
+    let palette = *dd.palette();
+    let start_col = palette.path_start;
+    let end_col = palette.path_end;
+    let path_col = palette.path;
+    let spath_col = palette.straight_path;
+    let ray_col = palette.ray;
+    let hit_col = palette.ray_hit;
+
+    dd.begin(DU_DRAW_TRIS, 1.0);
+    let mut fill = |r: PolyRef, col: Vec4| {
+        let Ok((tile, poly)) = nav_mesh.tile_and_poly_by_ref(r) else {
+            return;
+        };
+        let verts = poly.vert_indices();
+        for j in 2..verts.len() {
+            for v in [verts[0], verts[j - 1], verts[j]] {
+                dd.vertex(tile.data.verts[v as usize] + Vec3::Y * LIFT, col);
+            }
+        }
+    };
+    for &r in &result.path.polys {
+        fill(r, path_col);
+    }
+    if let Some((r, _)) = result.start {
+        fill(r, start_col);
+    }
+    if let Some((r, _)) = result.end {
+        fill(r, end_col);
+    }
+    dd.end();
+
+    dd.begin(DU_DRAW_LINES, 2.0);
+    for (pos, nearest, col) in [
+        (start_pos, result.start, start_col),
+        (end_pos, result.end, end_col),
+    ] {
+        let Some(pos) = pos else {
+            continue;
+        };
+        let col = col.truncate().extend(1.0);
+        dd.vertex(pos, col);
+        dd.vertex(pos + Vec3::Y * 2.0, col);
+        if let Some((_, p)) = nearest {
+            dd.vertex(pos, col);
+            dd.vertex(p + Vec3::Y * LIFT, col);
+        }
+    }
+    for w in result.straight_path.windows(2) {
+        dd.vertex(w[0].pos + Vec3::Y * LIFT, spath_col);
+        dd.vertex(w[1].pos + Vec3::Y * LIFT, spath_col);
+    }
+    if let (Some(hit), Some((_, start)), Some((_, end))) =
+        (&result.raycast, result.start, result.end)
+    {
+        let reached = hit.t >= 1.0;
+        let pos = start.lerp(end, hit.t.min(1.0)) + Vec3::Y * LIFT * 2.0;
+        dd.vertex(start + Vec3::Y * LIFT * 2.0, ray_col);
+        dd.vertex(pos, if reached { ray_col } else { hit_col });
+        if !reached {
+            dd.vertex(pos, hit_col);
+            dd.vertex(pos + hit.normal * 0.5, hit_col);
+        }
+    }
+    dd.end();
+
+    dd.begin(DU_DRAW_POINTS, 6.0);
+    for p in &result.straight_path {
+        dd.vertex(p.pos + Vec3::Y * LIFT, spath_col);
+    }
+    dd.end();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A displaced grid large enough to be split across several rayon jobs
+    fn medium_grid_mesh(n: usize) -> InputMesh {
+        let mut mesh = InputMesh::new();
+        for z in 0..=n {
+            for x in 0..=n {
+                let y = ((x * 7 + z * 13) % 5) as f32 * 0.3;
+                mesh.verts.push(Vec3::new(x as f32, y, z as f32));
+            }
+        }
+        for z in 0..n {
+            for x in 0..n {
+                let i = (z * (n + 1) + x) as i32;
+                let row = (n + 1) as i32;
+                mesh.tris
+                    .extend_from_slice(&[i, i + row, i + 1, i + 1, i + row, i + row + 1]);
+            }
+        }
+        mesh
+    }
+
+    #[test]
+    fn test_parallel_normals_match_serial() {
+        let mut mesh = medium_grid_mesh(128);
+        mesh.compute_normals();
+
+        let serial = compute_tri_normals_serial(&mesh.verts, &mesh.tris);
+
+        assert_eq!(serial.len(), mesh.tri_count());
+        assert_eq!(serial.len(), mesh.normals.len());
+        for (a, b) in serial.iter().zip(mesh.normals.iter()) {
+            assert_eq!(
+                a.to_array().map(f32::to_bits),
+                b.to_array().map(f32::to_bits)
+            );
+        }
+    }
+
+    #[test]
+    fn test_tex_axes() {
+        // X-dominant projects onto YZ, Y-dominant onto ZX, Z-dominant onto XY
+        assert_eq!(tex_axes(&Vec3::new(0.9, 0.3, 0.1)), (1, 2));
+        assert_eq!(tex_axes(&Vec3::new(0.1, -0.9, 0.3)), (2, 0));
+        assert_eq!(tex_axes(&Vec3::new(0.3, 0.1, -0.9)), (0, 1));
+    }
+
+    #[test]
+    fn test_raycast() {
+        let mesh = medium_grid_mesh(4);
+
+        // Straight down onto the first triangle, whose corners sit at
+        // heights 0.0, 0.9 and 0.6
+        let (t, tri) = mesh
+            .raycast(Vec3::new(0.25, 10.0, 0.25), Vec3::NEG_Y)
+            .unwrap();
+        assert!((t - 9.625).abs() < 1e-5);
+        assert_eq!(tri, 0);
+
+        assert!(mesh
+            .raycast(Vec3::new(-1.0, 10.0, -1.0), Vec3::NEG_Y)
+            .is_none());
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut mesh = medium_grid_mesh(2);
+        assert_eq!(
+            mesh.validate(),
+            Err(InputMeshError::NormalCountMismatch {
+                expected: 8,
+                found: 0
+            })
+        );
+
+        mesh.compute_normals();
+        assert_eq!(mesh.validate(), Ok(()));
+
+        mesh.tris[4] = 100;
+        assert_eq!(
+            mesh.validate(),
+            Err(InputMeshError::IndexOutOfBounds { tri: 1, index: 100 })
+        );
+    }
+}
@@ -0,0 +1,543 @@
+// C interface to the baker and the runtime queries, for engines that call
+// Detour today. Built into the shared library with the `cdylib` feature;
+// include/t1_nav.h declares the same functions and structs.
+//
+// Geometry and navmeshes are opaque handles created by the functions here
+// and released with their *_free function. Every other function returns a
+// NavStatus and writes its results through out pointers, which are left
+// untouched on failure. Pointers must be null or valid for the documented
+// number of elements; strings are NUL-terminated UTF-8 paths. Panics stop at
+// the boundary as NAV_PANIC instead of unwinding into C.
+#![allow(clippy::missing_safety_doc)]
+
+use std::ffi::{c_char, CStr};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::slice;
+
+use glam::Vec3;
+
+use crate::debug_draw_b::InputMesh;
+use crate::gltf_loader::GltfLoadError;
+use crate::headless::{load_input_mesh, HeadlessError};
+use crate::mesh_import::MeshImportError;
+use crate::nav_mesh::{NavMesh, NavMeshError};
+use crate::nav_mesh_bin::NavMeshBinError;
+use crate::nav_mesh_query::NavMeshQuery;
+use crate::navmesh_build::{build_navmesh, tile_data, NavMeshBuildConfig};
+use crate::obj_loader::ObjLoadError;
+use crate::ply_loader::PlyLoadError;
+use crate::query_filter::QueryFilter;
+use crate::stl_loader::StlLoadError;
+
+// Search nodes per path query, as in the Recast demo
+const MAX_NODES: usize = 2048;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NavStatus {
+    Ok = 0,
+    // The path leads to the polygon nearest the unreachable end
+    PartialPath = 1,
+    NullArgument = -1,
+    InvalidPath = -2,
+    Io = -3,
+    UnsupportedFormat = -4,
+    InvalidMesh = -5,
+    InvalidConfig = -6,
+    BuildFailed = -7,
+    // The bake produced no polygons
+    EmptyNavMesh = -8,
+    // No polygon within the query box
+    NotFound = -9,
+    // More path points than the buffer holds; it has the first ones
+    BufferTooSmall = -10,
+    Panic = -11,
+    // A value the query can't use, like a negative or non-finite extent
+    InvalidArgument = -12,
+}
+
+// The bake parameters of NavMeshBuildConfig, in world units unless noted
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NavBakeConfig {
+    pub cell_size: f32,
+    pub cell_height: f32,
+    pub agent_height: f32,
+    pub agent_max_climb: f32,
+    pub agent_radius: f32,
+    // Degrees
+    pub agent_max_slope: f32,
+    // Cells
+    pub min_region_area: i32,
+    pub merge_region_area: i32,
+    pub edge_max_len: f32,
+    // Voxels
+    pub edge_max_error: f32,
+    pub verts_per_poly: i32,
+    pub detail_sample_dist: f32,
+    pub detail_sample_max_error: f32,
+}
+
+impl From<&NavMeshBuildConfig> for NavBakeConfig {
+    fn from(cfg: &NavMeshBuildConfig) -> Self {
+        Self {
+            cell_size: cfg.cell_size,
+            cell_height: cfg.cell_height,
+            agent_height: cfg.agent_height,
+            agent_max_climb: cfg.agent_max_climb,
+            agent_radius: cfg.agent_radius,
+            agent_max_slope: cfg.agent_max_slope,
+            min_region_area: cfg.min_region_area,
+            merge_region_area: cfg.merge_region_area,
+            edge_max_len: cfg.edge_max_len,
+            edge_max_error: cfg.edge_max_error,
+            verts_per_poly: cfg.verts_per_poly as i32,
+            detail_sample_dist: cfg.detail_sample_dist,
+            detail_sample_max_error: cfg.detail_sample_max_error,
+        }
+    }
+}
+
+impl NavBakeConfig {
+    fn to_build_config(self) -> Result<NavMeshBuildConfig, NavStatus> {
+        NavMeshBuildConfig::builder()
+            .cell_size(self.cell_size)
+            .cell_height(self.cell_height)
+            .agent_height(self.agent_height)
+            .agent_max_climb(self.agent_max_climb)
+            .agent_radius(self.agent_radius)
+            .agent_max_slope(self.agent_max_slope)
+            .min_region_area(self.min_region_area)
+            .merge_region_area(self.merge_region_area)
+            .edge_max_len(self.edge_max_len)
+            .edge_max_error(self.edge_max_error)
+            .verts_per_poly(self.verts_per_poly.max(0) as usize)
+            .detail_sample(self.detail_sample_dist, self.detail_sample_max_error)
+            .build()
+            .map_err(|_| NavStatus::InvalidConfig)
+    }
+}
+
+// Triangles to bake, opaque to C
+pub struct NavGeometry {
+    mesh: InputMesh,
+}
+
+// Run `body`, turning a panic into NAV_PANIC
+fn guard(body: impl FnOnce() -> Result<NavStatus, NavStatus>) -> NavStatus {
+    match panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(Ok(status)) | Ok(Err(status)) => status,
+        Err(_) => NavStatus::Panic,
+    }
+}
+
+unsafe fn write<T>(out: *mut T, value: T) -> Result<NavStatus, NavStatus> {
+    if out.is_null() {
+        return Err(NavStatus::NullArgument);
+    }
+    out.write(value);
+    Ok(NavStatus::Ok)
+}
+
+unsafe fn read_vec3(p: *const f32) -> Result<Vec3, NavStatus> {
+    if p.is_null() {
+        return Err(NavStatus::NullArgument);
+    }
+    Ok(Vec3::from_slice(slice::from_raw_parts(p, 3)))
+}
+
+unsafe fn read_path<'a>(path: *const c_char) -> Result<&'a Path, NavStatus> {
+    if path.is_null() {
+        return Err(NavStatus::NullArgument);
+    }
+    let path = CStr::from_ptr(path)
+        .to_str()
+        .map_err(|_| NavStatus::InvalidPath)?;
+    Ok(Path::new(path))
+}
+
+// Files that can't be read are Io; files that read but don't hold usable
+// triangles are InvalidMesh
+fn load_status(err: HeadlessError) -> NavStatus {
+    match err {
+        HeadlessError::UnsupportedFormat(_) => NavStatus::UnsupportedFormat,
+        HeadlessError::Load(ObjLoadError::IoError(_))
+        | HeadlessError::Import(MeshImportError::Obj(ObjLoadError::IoError(_)))
+        | HeadlessError::Import(MeshImportError::Stl(StlLoadError::IoError(_)))
+        | HeadlessError::Import(MeshImportError::Ply(PlyLoadError::IoError(_)))
+        | HeadlessError::Import(MeshImportError::Gltf(GltfLoadError::Gltf(gltf::Error::Io(_)))) => {
+            NavStatus::Io
+        }
+        HeadlessError::Import(MeshImportError::UnsupportedFormat(_)) => {
+            NavStatus::UnsupportedFormat
+        }
+        _ => NavStatus::InvalidMesh,
+    }
+}
+
+fn bin_status(err: NavMeshBinError) -> NavStatus {
+    match err {
+        NavMeshBinError::Io(_) => NavStatus::Io,
+        NavMeshBinError::BadMagic(_) | NavMeshBinError::BadVersion(_) => {
+            NavStatus::UnsupportedFormat
+        }
+        NavMeshBinError::Corrupt(_)
+        | NavMeshBinError::RefBitsExceeded { .. }
+        | NavMeshBinError::NavMesh(_) => NavStatus::InvalidMesh,
+    }
+}
+
+fn query_status(err: NavMeshError) -> NavStatus {
+    match err {
+        NavMeshError::InvalidParams(_) => NavStatus::InvalidArgument,
+        // Anything else is a reference the navmesh doesn't hold
+        _ => NavStatus::InvalidMesh,
+    }
+}
+
+unsafe fn nav_mesh_ref<'a>(nav_mesh: *const NavMesh) -> Result<&'a NavMesh, NavStatus> {
+    nav_mesh.as_ref().ok_or(NavStatus::NullArgument)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn nav_bake_config_default(out: *mut NavBakeConfig) -> NavStatus {
+    guard(|| write(out, NavBakeConfig::from(&NavMeshBuildConfig::default())))
+}
+
+// Load an OBJ, glTF, STL or PLY file
+#[no_mangle]
+pub unsafe extern "C" fn nav_geometry_load(
+    path: *const c_char,
+    out: *mut *mut NavGeometry,
+) -> NavStatus {
+    guard(|| {
+        if out.is_null() {
+            return Err(NavStatus::NullArgument);
+        }
+        let mesh = load_input_mesh(read_path(path)?, None).map_err(load_status)?;
+        write(out, Box::into_raw(Box::new(NavGeometry { mesh })))
+    })
+}
+
+// Geometry from `vert_count` xyz positions and `tri_count` triangles of
+// three vertex indices each
+#[no_mangle]
+pub unsafe extern "C" fn nav_geometry_from_triangles(
+    verts: *const f32,
+    vert_count: i32,
+    tris: *const i32,
+    tri_count: i32,
+    out: *mut *mut NavGeometry,
+) -> NavStatus {
+    guard(|| {
+        if verts.is_null() || tris.is_null() || out.is_null() {
+            return Err(NavStatus::NullArgument);
+        }
+        let mut mesh = InputMesh::new();
+        mesh.verts = slice::from_raw_parts(verts, vert_count.max(0) as usize * 3)
+            .chunks_exact(3)
+            .map(Vec3::from_slice)
+            .collect();
+        mesh.tris = slice::from_raw_parts(tris, tri_count.max(0) as usize * 3).to_vec();
+        mesh.validate_indices()
+            .map_err(|_| NavStatus::InvalidMesh)?;
+        mesh.compute_normals();
+        mesh.validate().map_err(|_| NavStatus::InvalidMesh)?;
+        write(out, Box::into_raw(Box::new(NavGeometry { mesh })))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn nav_geometry_free(geometry: *mut NavGeometry) {
+    if !geometry.is_null() {
+        drop(Box::from_raw(geometry));
+    }
+}
+
+// Bake `geometry` into a single-tile navmesh
+#[no_mangle]
+pub unsafe extern "C" fn nav_bake(
+    geometry: *const NavGeometry,
+    config: *const NavBakeConfig,
+    out: *mut *mut NavMesh,
+) -> NavStatus {
+    guard(|| {
+        let (Some(geometry), Some(config)) = (geometry.as_ref(), config.as_ref()) else {
+            return Err(NavStatus::NullArgument);
+        };
+        if out.is_null() {
+            return Err(NavStatus::NullArgument);
+        }
+        let cfg = config.to_build_config()?;
+        let baked = build_navmesh(&geometry.mesh, &cfg).map_err(|_| NavStatus::BuildFailed)?;
+        let data = tile_data(&baked, &cfg, 0, 0, 0, &[])
+            .map_err(|_| NavStatus::BuildFailed)?
+            .ok_or(NavStatus::EmptyNavMesh)?;
+        let nav_mesh = NavMesh::single_tile(data).map_err(|_| NavStatus::BuildFailed)?;
+        write(out, Box::into_raw(Box::new(nav_mesh)))
+    })
+}
+
+// Write the navmesh as a Recast demo navmesh set, with 32-bit references,
+// so a Detour runtime can load it as it loads the demo's
+#[no_mangle]
+pub unsafe extern "C" fn nav_mesh_save(nav_mesh: *const NavMesh, path: *const c_char) -> NavStatus {
+    guard(|| {
+        let nav_mesh = nav_mesh_ref(nav_mesh)?;
+        let file = File::create(read_path(path)?).map_err(|_| NavStatus::Io)?;
+        let mut writer = BufWriter::new(file);
+        nav_mesh.save_bin(&mut writer).map_err(|_| NavStatus::Io)?;
+        writer.flush().map_err(|_| NavStatus::Io)?;
+        Ok(NavStatus::Ok)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn nav_mesh_load(path: *const c_char, out: *mut *mut NavMesh) -> NavStatus {
+    guard(|| {
+        if out.is_null() {
+            return Err(NavStatus::NullArgument);
+        }
+        let file = File::open(read_path(path)?).map_err(|_| NavStatus::Io)?;
+        let nav_mesh = NavMesh::load_bin(&mut BufReader::new(file)).map_err(bin_status)?;
+        write(out, Box::into_raw(Box::new(nav_mesh)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn nav_mesh_free(nav_mesh: *mut NavMesh) {
+    if !nav_mesh.is_null() {
+        drop(Box::from_raw(nav_mesh));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn nav_mesh_poly_count(nav_mesh: *const NavMesh, out: *mut i32) -> NavStatus {
+    guard(|| {
+        let count = nav_mesh_ref(nav_mesh)?
+            .tiles()
+            .map(|tile| tile.data.polys.len())
+            .sum::<usize>();
+        write(out, count as i32)
+    })
+}
+
+// The point on the navmesh nearest `pos` within `half_extents` of it;
+// `pos`, `half_extents` and `out_pos` are xyz triples
+#[no_mangle]
+pub unsafe extern "C" fn nav_find_nearest_point(
+    nav_mesh: *const NavMesh,
+    pos: *const f32,
+    half_extents: *const f32,
+    out_pos: *mut f32,
+) -> NavStatus {
+    guard(|| {
+        let query = NavMeshQuery::new(nav_mesh_ref(nav_mesh)?, MAX_NODES);
+        let (_, point) = query
+            .find_nearest_poly(
+                read_vec3(pos)?,
+                read_vec3(half_extents)?,
+                &QueryFilter::default(),
+            )
+            .map_err(query_status)?
+            .ok_or(NavStatus::NotFound)?;
+        write(out_pos as *mut [f32; 3], point.to_array())
+    })
+}
+
+// Corners of the shortest path from `start` to `end`, each snapped to the
+// navmesh within `half_extents`. Writes up to `max_points` xyz triples to
+// `out_points`, the DT_STRAIGHTPATH_* flags of each to `out_flags` unless
+// it is null, and the number written to `out_count`.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn nav_find_path(
+    nav_mesh: *const NavMesh,
+    start: *const f32,
+    end: *const f32,
+    half_extents: *const f32,
+    out_points: *mut f32,
+    out_flags: *mut u8,
+    max_points: i32,
+    out_count: *mut i32,
+) -> NavStatus {
+    guard(|| {
+        if out_points.is_null() || out_count.is_null() {
+            return Err(NavStatus::NullArgument);
+        }
+        let mut query = NavMeshQuery::new(nav_mesh_ref(nav_mesh)?, MAX_NODES);
+        let result = query
+            .query_point_to_point(
+                read_vec3(start)?,
+                read_vec3(end)?,
+                read_vec3(half_extents)?,
+                &QueryFilter::default(),
+            )
+            .map_err(query_status)?;
+        if result.start.is_none() || result.end.is_none() {
+            return Err(NavStatus::NotFound);
+        }
+
+        let points = &result.straight_path;
+        let count = points.len().min(max_points.max(0) as usize);
+        let positions = slice::from_raw_parts_mut(out_points, count * 3);
+        for (out, point) in positions.chunks_exact_mut(3).zip(points) {
+            out.copy_from_slice(&point.pos.to_array());
+        }
+        if !out_flags.is_null() {
+            let flags = slice::from_raw_parts_mut(out_flags, count);
+            for (out, point) in flags.iter_mut().zip(points) {
+                *out = point.flags;
+            }
+        }
+        write(out_count, count as i32)?;
+        Ok(if count < points.len() {
+            NavStatus::BufferTooSmall
+        } else if result.path.partial {
+            NavStatus::PartialPath
+        } else {
+            NavStatus::Ok
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CString;
+    use std::ptr;
+
+    #[test]
+    fn test_bake_save_load_and_query_through_c_api() {
+        unsafe {
+            let verts = [
+                0.0, 0.0, 0.0, 0.0, 0.0, 10.0, 10.0, 0.0, 10.0, 10.0, 0.0, 0.0f32,
+            ];
+            let tris = [0, 1, 2, 0, 2, 3];
+            let mut geometry = ptr::null_mut();
+            assert_eq!(
+                nav_geometry_from_triangles(verts.as_ptr(), 4, tris.as_ptr(), 2, &mut geometry),
+                NavStatus::Ok
+            );
+            let bad_tris = [0, 1, 7];
+            let mut bad = ptr::null_mut();
+            assert_eq!(
+                nav_geometry_from_triangles(verts.as_ptr(), 4, bad_tris.as_ptr(), 1, &mut bad),
+                NavStatus::InvalidMesh
+            );
+            assert!(bad.is_null());
+
+            // Files fail with what went wrong rather than a panic
+            let obj = tempfile::Builder::new().suffix(".obj").tempfile().unwrap();
+            std::fs::write(obj.path(), "v 0 0 0\nv 1 0 0\nv 0 0 1\nf 1 3 9\n").unwrap();
+            let obj_path = CString::new(obj.path().to_str().unwrap()).unwrap();
+            assert_eq!(
+                nav_geometry_load(obj_path.as_ptr(), &mut bad),
+                NavStatus::InvalidMesh
+            );
+            let missing = CString::new("missing.obj").unwrap();
+            assert_eq!(nav_geometry_load(missing.as_ptr(), &mut bad), NavStatus::Io);
+            assert_eq!(
+                nav_mesh_load(obj_path.as_ptr(), &mut ptr::null_mut()),
+                NavStatus::UnsupportedFormat
+            );
+            assert_eq!(
+                nav_mesh_load(missing.as_ptr(), &mut ptr::null_mut()),
+                NavStatus::Io
+            );
+
+            let mut config = std::mem::zeroed();
+            assert_eq!(nav_bake_config_default(&mut config), NavStatus::Ok);
+            let mut nav_mesh = ptr::null_mut();
+            assert_eq!(nav_bake(geometry, &config, &mut nav_mesh), NavStatus::Ok);
+            nav_geometry_free(geometry);
+            let invalid = NavBakeConfig {
+                cell_size: 0.0,
+                ..config
+            };
+            assert_eq!(
+                nav_bake(ptr::null(), &invalid, &mut ptr::null_mut()),
+                NavStatus::NullArgument
+            );
+
+            // Saved and loaded back, the navmesh answers queries the same
+            let file = tempfile::NamedTempFile::new().unwrap();
+            let path = CString::new(file.path().to_str().unwrap()).unwrap();
+            assert_eq!(nav_mesh_save(nav_mesh, path.as_ptr()), NavStatus::Ok);
+            nav_mesh_free(nav_mesh);
+            let mut loaded = ptr::null_mut();
+            assert_eq!(nav_mesh_load(path.as_ptr(), &mut loaded), NavStatus::Ok);
+            let mut polys = 0;
+            assert_eq!(nav_mesh_poly_count(loaded, &mut polys), NavStatus::Ok);
+            assert!(polys > 0);
+
+            let extents = [2.0, 4.0, 2.0f32];
+            let mut nearest = [0.0f32; 3];
+            let pos = [5.0, 1.0, 5.0f32];
+            assert_eq!(
+                nav_find_nearest_point(
+                    loaded,
+                    pos.as_ptr(),
+                    extents.as_ptr(),
+                    nearest.as_mut_ptr()
+                ),
+                NavStatus::Ok
+            );
+            assert!((nearest[0] - 5.0).abs() < 1e-3 && nearest[1] < 1.0);
+            let far = [50.0, 0.0, 50.0f32];
+            assert_eq!(
+                nav_find_nearest_point(
+                    loaded,
+                    far.as_ptr(),
+                    extents.as_ptr(),
+                    nearest.as_mut_ptr()
+                ),
+                NavStatus::NotFound
+            );
+            let negative = [-1.0, 4.0, 2.0f32];
+            assert_eq!(
+                nav_find_nearest_point(
+                    loaded,
+                    pos.as_ptr(),
+                    negative.as_ptr(),
+                    nearest.as_mut_ptr()
+                ),
+                NavStatus::InvalidArgument
+            );
+
+            let (start, end) = ([2.0, 0.0, 2.0f32], [8.0, 0.0, 8.0f32]);
+            let mut points = [0.0f32; 3 * 8];
+            let mut flags = [0u8; 8];
+            let mut count = 0;
+            let status = nav_find_path(
+                loaded,
+                start.as_ptr(),
+                end.as_ptr(),
+                extents.as_ptr(),
+                points.as_mut_ptr(),
+                flags.as_mut_ptr(),
+                8,
+                &mut count,
+            );
+            assert_eq!(status, NavStatus::Ok);
+            // Open floor, so straight from start to end
+            assert_eq!(count, 2);
+            assert!((points[3] - 8.0).abs() < 1e-3 && (points[5] - 8.0).abs() < 1e-3);
+            let status = nav_find_path(
+                loaded,
+                start.as_ptr(),
+                end.as_ptr(),
+                extents.as_ptr(),
+                points.as_mut_ptr(),
+                ptr::null_mut(),
+                1,
+                &mut count,
+            );
+            assert_eq!((status, count), (NavStatus::BufferTooSmall, 1));
+            nav_mesh_free(loaded);
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use eframe::egui;
+use std::cell::RefCell;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+// File pickers for the viewer. Natively they are rfd's blocking dialogs over
+// the file system. The browser has neither, so there the picker runs
+// asynchronously and hands over the file's name and contents a few frames
+// later, and saved files are downloaded.
+
+// What a file is being opened as, so the viewer knows what to do with it
+// once it arrives
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilePurpose {
+    // A mesh or a project, told apart by extension
+    Scene,
+    Palette,
+    Volumes,
+}
+
+// A file picked in a dialog or dropped on the window
+#[derive(Debug, Clone)]
+pub struct PickedFile {
+    // The whole path natively; only the file name in the browser
+    pub path: PathBuf,
+    // What the browser read, since there's no reading `path` there later
+    pub contents: Option<Vec<u8>>,
+}
+
+impl PickedFile {
+    pub fn on_disk(path: PathBuf) -> Self {
+        Self {
+            path,
+            contents: None,
+        }
+    }
+
+    pub fn in_memory(name: impl Into<PathBuf>, contents: Vec<u8>) -> Self {
+        Self {
+            path: name.into(),
+            contents: Some(contents),
+        }
+    }
+
+    pub fn read(&self) -> io::Result<Vec<u8>> {
+        match &self.contents {
+            Some(contents) => Ok(contents.clone()),
+            None => fs::read(&self.path),
+        }
+    }
+
+    pub fn read_to_string(&self) -> io::Result<String> {
+        String::from_utf8(self.read()?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+// Name and extensions of a file type offered in a picker
+pub type FileFilter<'a> = (&'a str, &'a [&'a str]);
+
+// Files picked in open dialogs, waiting for the viewer to take them
+#[derive(Debug, Clone, Default)]
+pub struct FileDialogs {
+    picked: Rc<RefCell<Vec<(FilePurpose, PickedFile)>>>,
+}
+
+impl FileDialogs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Ask for a file to open. It shows up in `take_picked` once chosen:
+    // natively before this returns, in the browser some frames later.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn open(&self, _ctx: &egui::Context, purpose: FilePurpose, filters: &[FileFilter]) {
+        let mut dialog = rfd::FileDialog::new();
+        for (name, extensions) in filters {
+            dialog = dialog.add_filter(*name, extensions);
+        }
+        if let Some(path) = dialog.pick_file() {
+            self.picked
+                .borrow_mut()
+                .push((purpose, PickedFile::on_disk(path)));
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn open(&self, ctx: &egui::Context, purpose: FilePurpose, filters: &[FileFilter]) {
+        let mut dialog = rfd::AsyncFileDialog::new();
+        for (name, extensions) in filters {
+            dialog = dialog.add_filter(*name, extensions);
+        }
+        let picked = Rc::clone(&self.picked);
+        let ctx = ctx.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            let Some(handle) = dialog.pick_file().await else {
+                return;
+            };
+            let file = PickedFile::in_memory(handle.file_name(), handle.read().await);
+            picked.borrow_mut().push((purpose, file));
+            // Nothing else wakes the UI while the picker is open
+            ctx.request_repaint();
+        });
+    }
+
+    pub fn take_picked(&self) -> Vec<(FilePurpose, PickedFile)> {
+        self.picked.take()
+    }
+}
+
+// Where to save a file, starting from the folder and name of `default`
+// when it has them. The browser saves by downloading, so there `default`
+// is used without asking and only its file name matters.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save_path(filters: &[FileFilter], default: &Path) -> Option<PathBuf> {
+    let mut dialog = rfd::FileDialog::new();
+    for (name, extensions) in filters {
+        dialog = dialog.add_filter(*name, extensions);
+    }
+    if let Some(dir) = default.parent().filter(|dir| dir.is_dir()) {
+        dialog = dialog.set_directory(dir);
+    }
+    if let Some(name) = default.file_name() {
+        dialog = dialog.set_file_name(name.to_string_lossy());
+    }
+    dialog.save_file()
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn save_path(_filters: &[FileFilter], default: &Path) -> Option<PathBuf> {
+    Some(default.to_path_buf())
+}
+
+// A folder for files saved in bulk, like turntable frames. The browser
+// can't save into folders, so there is never one there.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn pick_folder() -> Option<PathBuf> {
+    rfd::FileDialog::new().pick_folder()
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn pick_folder() -> Option<PathBuf> {
+    None
+}
+
+// Write a file chosen with `save_path`
+#[cfg(not(target_arch = "wasm32"))]
+pub fn write_file(path: &Path, contents: &[u8]) -> io::Result<()> {
+    fs::write(path, contents)
+}
+
+// Download `contents` under the file name of `path`, through a link to a
+// blob that is clicked and thrown away
+#[cfg(target_arch = "wasm32")]
+pub fn write_file(path: &Path, contents: &[u8]) -> io::Result<()> {
+    use wasm_bindgen::{JsCast, JsValue};
+
+    let browser_error = |err: JsValue| io::Error::other(format!("{:?}", err));
+    let name = path
+        .file_name()
+        .map_or_else(|| "download".into(), |name| name.to_string_lossy());
+    let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(contents));
+    let blob = web_sys::Blob::new_with_u8_array_sequence(&parts).map_err(browser_error)?;
+    let url = web_sys::Url::create_object_url_with_blob(&blob).map_err(browser_error)?;
+    let document = web_sys::window()
+        .and_then(|window| window.document())
+        .ok_or_else(|| io::Error::other("no document to download from"))?;
+    let link: web_sys::HtmlAnchorElement = document
+        .create_element("a")
+        .map_err(browser_error)?
+        .unchecked_into();
+    link.set_href(&url);
+    link.set_download(&name);
+    link.click();
+    web_sys::Url::revoke_object_url(&url).map_err(browser_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_picked_files_read_from_memory_or_disk() {
+        let file = PickedFile::in_memory("level.obj", b"v 0 0 0\n".to_vec());
+        assert_eq!(file.path, Path::new("level.obj"));
+        assert_eq!(file.read_to_string().unwrap(), "v 0 0 0\n");
+
+        let temp = tempfile::NamedTempFile::new().unwrap();
+        write_file(temp.path(), b"saved").unwrap();
+        assert_eq!(
+            PickedFile::on_disk(temp.path().into()).read().unwrap(),
+            b"saved"
+        );
+        assert!(PickedFile::on_disk("missing.obj".into()).read().is_err());
+
+        let dialogs = FileDialogs::new();
+        dialogs
+            .picked
+            .borrow_mut()
+            .push((FilePurpose::Palette, file.clone()));
+        let picked = dialogs.take_picked();
+        assert_eq!(picked.len(), 1);
+        assert_eq!(picked[0].0, FilePurpose::Palette);
+        assert!(dialogs.take_picked().is_empty());
+    }
+}
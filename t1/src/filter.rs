@@ -0,0 +1,174 @@
+use crate::compact_heightfield::dir_offset;
+use crate::debug_draw::RC_NULL_AREA;
+use crate::heightfield::Heightfield;
+
+// Top of the open space above the highest span of a column
+const MAX_HEIGHT: i32 = 0xffff;
+
+// Mark unwalkable spans as walkable when they sit at most `walkable_climb`
+// above a walkable span right below them, so agents can step over curbs
+// and stair edges that were rasterized as walls
+pub fn filter_low_hanging_walkable_obstacles(hf: &mut Heightfield, walkable_climb: i32) {
+    for column in &mut hf.spans {
+        let mut previous: Option<(bool, i32, u8)> = None;
+        for span in column.iter_mut() {
+            let walkable = span.area != RC_NULL_AREA;
+            if let Some((true, previous_smax, previous_area)) = previous {
+                if !walkable && (span.smax as i32 - previous_smax).abs() <= walkable_climb {
+                    span.area = previous_area;
+                }
+            }
+            previous = Some((walkable, span.smax as i32, span.area));
+        }
+    }
+}
+
+// Unmark walkable spans next to a drop deeper than `walkable_climb`, or on
+// top of a slope steeper than that between the neighbours the agent can
+// reach. Heights are in cell-height units.
+pub fn filter_ledge_spans(hf: &mut Heightfield, walkable_height: i32, walkable_climb: i32) {
+    let (w, h) = (hf.width, hf.height);
+    for z in 0..h {
+        for x in 0..w {
+            let c = (x + z * w) as usize;
+            for i in 0..hf.spans[c].len() {
+                let span = hf.spans[c][i];
+                if span.area == RC_NULL_AREA {
+                    continue;
+                }
+                let bot = span.smax as i32;
+                let top = hf.spans[c]
+                    .get(i + 1)
+                    .map_or(MAX_HEIGHT, |next| next.smin as i32);
+
+                // Lowest neighbour floor the agent fits over, and the range
+                // of neighbour floors within climbing distance
+                let mut min_neighbour_height = MAX_HEIGHT;
+                let mut accessible_min = bot;
+                let mut accessible_max = bot;
+
+                for dir in 0..4 {
+                    let (dx, dz) = dir_offset(dir);
+                    let (nx, nz) = (x + dx, z + dz);
+                    // Outside the heightfield counts as a drop
+                    if nx < 0 || nz < 0 || nx >= w || nz >= h {
+                        min_neighbour_height = min_neighbour_height.min(-walkable_climb - bot);
+                        continue;
+                    }
+
+                    let neighbours = &hf.spans[(nx + nz * w) as usize];
+                    // The space below the first span is open down to
+                    // the bottom of the heightfield
+                    let floors = std::iter::once((
+                        -walkable_climb,
+                        neighbours.first().map_or(MAX_HEIGHT, |s| s.smin as i32),
+                    ))
+                    .chain(neighbours.iter().enumerate().map(|(k, ns)| {
+                        let next = neighbours.get(k + 1);
+                        (ns.smax as i32, next.map_or(MAX_HEIGHT, |s| s.smin as i32))
+                    }));
+
+                    for (neighbour_bot, neighbour_top) in floors {
+                        if top.min(neighbour_top) - bot.max(neighbour_bot) <= walkable_height {
+                            continue;
+                        }
+                        min_neighbour_height = min_neighbour_height.min(neighbour_bot - bot);
+                        if (neighbour_bot - bot).abs() <= walkable_climb {
+                            accessible_min = accessible_min.min(neighbour_bot);
+                            accessible_max = accessible_max.max(neighbour_bot);
+                        }
+                    }
+                }
+
+                if min_neighbour_height < -walkable_climb
+                    || accessible_max - accessible_min > walkable_climb
+                {
+                    hf.spans[c][i].area = RC_NULL_AREA;
+                }
+            }
+        }
+    }
+}
+
+// Unmark walkable spans with less than `walkable_height` of clearance
+pub fn filter_walkable_low_height_spans(hf: &mut Heightfield, walkable_height: i32) {
+    for column in &mut hf.spans {
+        for i in 0..column.len() {
+            let bot = column[i].smax as i32;
+            let top = column
+                .get(i + 1)
+                .map_or(MAX_HEIGHT, |next| next.smin as i32);
+            if top - bot < walkable_height {
+                column[i].area = RC_NULL_AREA;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debug_draw::RC_WALKABLE_AREA;
+    use glam::Vec3;
+
+    fn heightfield(w: i32, h: i32) -> Heightfield {
+        Heightfield::new(Vec3::ZERO, Vec3::new(w as f32, 10.0, h as f32), 1.0, 0.1)
+    }
+
+    fn area(hf: &Heightfield, x: i32, z: i32, i: usize) -> u8 {
+        hf.column(x, z)[i].area
+    }
+
+    #[test]
+    fn test_low_hanging_obstacles_become_walkable() {
+        let mut hf = heightfield(2, 1);
+        // A curb two cells above the floor, and a wall much higher up
+        hf.add_span(0, 0, 0, 10, RC_WALKABLE_AREA, 0);
+        hf.add_span(0, 0, 11, 12, RC_NULL_AREA, 0);
+        hf.add_span(1, 0, 0, 10, RC_WALKABLE_AREA, 0);
+        hf.add_span(1, 0, 11, 30, RC_NULL_AREA, 0);
+
+        filter_low_hanging_walkable_obstacles(&mut hf, 4);
+        assert_eq!(area(&hf, 0, 0, 1), RC_WALKABLE_AREA);
+        assert_eq!(area(&hf, 1, 0, 1), RC_NULL_AREA);
+    }
+
+    #[test]
+    fn test_ledges_are_removed() {
+        // A 4x4 platform 20 cells up in the middle of a 6x6 floor
+        let mut hf = heightfield(6, 6);
+        for z in 0..6 {
+            for x in 0..6 {
+                let top = if (1..5).contains(&x) && (1..5).contains(&z) {
+                    20
+                } else {
+                    2
+                };
+                hf.add_span(x, z, 0, top, RC_WALKABLE_AREA, 0);
+            }
+        }
+
+        filter_ledge_spans(&mut hf, 5, 3);
+        // The rim of the platform and the floor along the edge of the
+        // heightfield are ledges; the platform centre isn't
+        assert_eq!(area(&hf, 1, 1, 0), RC_NULL_AREA);
+        assert_eq!(area(&hf, 0, 3, 0), RC_NULL_AREA);
+        assert_eq!(area(&hf, 2, 2, 0), RC_WALKABLE_AREA);
+        assert_eq!(area(&hf, 3, 3, 0), RC_WALKABLE_AREA);
+    }
+
+    #[test]
+    fn test_low_ceilings_are_removed() {
+        let mut hf = heightfield(2, 1);
+        hf.add_span(0, 0, 0, 2, RC_WALKABLE_AREA, 0);
+        hf.add_span(0, 0, 6, 8, RC_WALKABLE_AREA, 0);
+        hf.add_span(1, 0, 0, 2, RC_WALKABLE_AREA, 0);
+        hf.add_span(1, 0, 20, 22, RC_WALKABLE_AREA, 0);
+
+        filter_walkable_low_height_spans(&mut hf, 10);
+        assert_eq!(area(&hf, 0, 0, 0), RC_NULL_AREA);
+        assert_eq!(area(&hf, 1, 0, 0), RC_WALKABLE_AREA);
+        // Open sky above the top spans
+        assert_eq!(area(&hf, 0, 0, 1), RC_WALKABLE_AREA);
+    }
+}
@@ -0,0 +1,74 @@
+use glam::{Mat4, Vec3, Vec4};
+
+// The six planes bounding what a camera sees, pointing inwards, for culling
+// boxes before their triangles are projected
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frustum {
+    // xyz normal and w offset; a point p is inside when n.p + w >= 0
+    planes: [Vec4; 6],
+}
+
+impl Frustum {
+    // Planes of an OpenGL style view-projection matrix, with clip depth
+    // from -w to w (Gribb and Hartmann)
+    pub fn from_view_proj(view_proj: Mat4) -> Self {
+        let [r0, r1, r2, r3] = [0, 1, 2, 3].map(|i| view_proj.row(i));
+        let planes = [r3 + r0, r3 - r0, r3 + r1, r3 - r1, r3 + r2, r3 - r2].map(|p| {
+            let len = p.truncate().length();
+            if len > 0.0 {
+                p / len
+            } else {
+                p
+            }
+        });
+        Self { planes }
+    }
+
+    // False only when the box is entirely outside one of the planes, so a
+    // few boxes near the corners pass without being visible
+    pub fn intersects_aabb(&self, bmin: Vec3, bmax: Vec3) -> bool {
+        self.planes.iter().all(|plane| {
+            // Corner furthest along the plane normal
+            let n = plane.truncate();
+            let corner = Vec3::select(n.cmpge(Vec3::ZERO), bmax, bmin);
+            n.dot(corner) + plane.w >= 0.0
+        })
+    }
+
+    pub fn contains_point(&self, p: Vec3) -> bool {
+        self.intersects_aabb(p, p)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frustum_culls_boxes_outside() {
+        // Looking down -Z from the origin
+        let proj = Mat4::perspective_rh_gl(90f32.to_radians(), 1.0, 0.1, 100.0);
+        let frustum = Frustum::from_view_proj(proj);
+
+        assert!(frustum.contains_point(Vec3::new(0.0, 0.0, -10.0)));
+        assert!(!frustum.contains_point(Vec3::new(0.0, 0.0, 10.0)));
+        assert!(!frustum.contains_point(Vec3::new(0.0, 0.0, -200.0)));
+        // 90 degrees wide, so x = 9 at z = -10 is just inside and 11 outside
+        assert!(frustum.contains_point(Vec3::new(9.0, 0.0, -10.0)));
+        assert!(!frustum.contains_point(Vec3::new(11.0, 0.0, -10.0)));
+
+        // A box straddling the left plane is kept, one behind the camera not
+        assert!(frustum.intersects_aabb(Vec3::new(-20.0, -1.0, -11.0), Vec3::new(-5.0, 1.0, -9.0)));
+        assert!(!frustum.intersects_aabb(Vec3::new(-1.0, -1.0, 1.0), Vec3::new(1.0, 1.0, 5.0)));
+
+        // Moving the camera moves the frustum with it
+        let view = Mat4::look_at_rh(
+            Vec3::new(100.0, 0.0, 0.0),
+            Vec3::new(100.0, 0.0, -1.0),
+            Vec3::Y,
+        );
+        let moved = Frustum::from_view_proj(proj * view);
+        assert!(!moved.contains_point(Vec3::new(0.0, 0.0, -10.0)));
+        assert!(moved.contains_point(Vec3::new(100.0, 0.0, -10.0)));
+    }
+}
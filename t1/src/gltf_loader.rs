@@ -0,0 +1,229 @@
+use glam::{Mat4, Vec3};
+use std::fmt;
+use std::path::Path;
+
+use crate::debug_draw_b::InputMesh;
+
+#[derive(Debug)]
+pub enum GltfLoadError {
+    Gltf(gltf::Error),
+    // The file parsed but contains no triangle primitives
+    NoTriangles,
+}
+
+impl fmt::Display for GltfLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GltfLoadError::Gltf(e) => write!(f, "glTF error: {}", e),
+            GltfLoadError::NoTriangles => write!(f, "no triangle meshes in glTF file"),
+        }
+    }
+}
+
+impl From<gltf::Error> for GltfLoadError {
+    fn from(error: gltf::Error) -> Self {
+        GltfLoadError::Gltf(error)
+    }
+}
+
+// Load a .gltf or .glb file into one InputMesh. Every triangle primitive
+// reachable from the default scene (or from all scenes when there is none)
+// is merged, with its node's world transform applied.
+pub fn load_gltf<P: AsRef<Path>>(path: P) -> Result<InputMesh, GltfLoadError> {
+    let path = path.as_ref();
+    let gltf = gltf::Gltf::open(path)?;
+    let buffers = gltf::import_buffers(&gltf.document, path.parent(), gltf.blob)?;
+    gltf_to_input_mesh(&gltf.document, &buffers)
+}
+
+// Same as load_gltf for an in-memory file. External buffers can't be
+// resolved without a path, so only GLB blobs and data URIs work here.
+pub fn load_gltf_slice(bytes: &[u8]) -> Result<InputMesh, GltfLoadError> {
+    let gltf = gltf::Gltf::from_slice(bytes)?;
+    let buffers = gltf::import_buffers(&gltf.document, None, gltf.blob)?;
+    gltf_to_input_mesh(&gltf.document, &buffers)
+}
+
+fn gltf_to_input_mesh(
+    document: &gltf::Document,
+    buffers: &[gltf::buffer::Data],
+) -> Result<InputMesh, GltfLoadError> {
+    let mut mesh = InputMesh::new();
+    let roots: Vec<gltf::Node> = match document.default_scene() {
+        Some(scene) => scene.nodes().collect(),
+        None => document.scenes().flat_map(|s| s.nodes()).collect(),
+    };
+    for node in roots {
+        add_node(&mut mesh, &node, Mat4::IDENTITY, buffers);
+    }
+
+    if mesh.tris.is_empty() {
+        return Err(GltfLoadError::NoTriangles);
+    }
+    mesh.compute_normals();
+    Ok(mesh)
+}
+
+fn add_node(mesh: &mut InputMesh, node: &gltf::Node, parent: Mat4, buffers: &[gltf::buffer::Data]) {
+    let world = parent * Mat4::from_cols_array_2d(&node.transform().matrix());
+    if let Some(node_mesh) = node.mesh() {
+        for primitive in node_mesh.primitives() {
+            add_primitive(mesh, &primitive, world, buffers);
+        }
+    }
+    for child in node.children() {
+        add_node(mesh, &child, world, buffers);
+    }
+}
+
+fn add_primitive(
+    mesh: &mut InputMesh,
+    primitive: &gltf::Primitive,
+    world: Mat4,
+    buffers: &[gltf::buffer::Data],
+) {
+    let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|data| &data[..]));
+    let Some(positions) = reader.read_positions() else {
+        return;
+    };
+    let base = mesh.verts.len() as i32;
+    mesh.verts
+        .extend(positions.map(|p| world.transform_point3(Vec3::from(p))));
+    let count = mesh.verts.len() as u32 - base as u32;
+
+    // Unindexed primitives use their vertices in order
+    let indices: Vec<u32> = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect(),
+        None => (0..count).collect(),
+    };
+    let tris = triangulate(primitive.mode(), &indices);
+
+    // A mirroring transform turns the winding around, so flip it back
+    let flip = world.determinant() < 0.0;
+    for [a, b, c] in tris {
+        if a.max(b).max(c) >= count {
+            continue;
+        }
+        let (b, c) = if flip { (c, b) } else { (b, c) };
+        mesh.tris
+            .extend([base + a as i32, base + b as i32, base + c as i32]);
+    }
+}
+
+// Expand strips and fans into a triangle list; points and lines yield nothing
+fn triangulate(mode: gltf::mesh::Mode, indices: &[u32]) -> Vec<[u32; 3]> {
+    use gltf::mesh::Mode;
+    match mode {
+        Mode::Triangles => indices
+            .chunks_exact(3)
+            .map(|t| [t[0], t[1], t[2]])
+            .collect(),
+        // Every other strip triangle is reversed to keep a consistent winding
+        Mode::TriangleStrip => indices
+            .windows(3)
+            .enumerate()
+            .map(|(i, t)| {
+                if i % 2 == 0 {
+                    [t[0], t[1], t[2]]
+                } else {
+                    [t[1], t[0], t[2]]
+                }
+            })
+            .collect(),
+        Mode::TriangleFan => indices
+            .windows(2)
+            .skip(1)
+            .map(|t| [indices[0], t[0], t[1]])
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Minimal GLB: a unit quad as two indexed triangles, instanced by a
+    // translated child of a scaled root node
+    fn quad_glb() -> Vec<u8> {
+        let mut bin = Vec::new();
+        for p in [
+            [0.0f32, 0.0, 0.0],
+            [0.0, 0.0, 1.0],
+            [1.0, 0.0, 1.0],
+            [1.0, 0.0, 0.0],
+        ] {
+            for c in p {
+                bin.extend(c.to_le_bytes());
+            }
+        }
+        for i in [0u16, 1, 2, 0, 2, 3] {
+            bin.extend(i.to_le_bytes());
+        }
+        let json = r#"{
+            "asset": {"version": "2.0"},
+            "scene": 0,
+            "scenes": [{"nodes": [0]}],
+            "nodes": [
+                {"scale": [2, 2, 2], "children": [1]},
+                {"translation": [10, 0, 0], "mesh": 0}
+            ],
+            "meshes": [{"primitives": [{"attributes": {"POSITION": 0}, "indices": 1}]}],
+            "buffers": [{"byteLength": 60}],
+            "bufferViews": [
+                {"buffer": 0, "byteOffset": 0, "byteLength": 48},
+                {"buffer": 0, "byteOffset": 48, "byteLength": 12}
+            ],
+            "accessors": [
+                {"bufferView": 0, "componentType": 5126, "count": 4, "type": "VEC3",
+                 "min": [0, 0, 0], "max": [1, 0, 1]},
+                {"bufferView": 1, "componentType": 5123, "count": 6, "type": "SCALAR"}
+            ]
+        }"#;
+
+        let mut json = json.as_bytes().to_vec();
+        json.resize(json.len().next_multiple_of(4), b' ');
+        let total = 12 + 8 + json.len() + 8 + bin.len();
+        let mut glb = Vec::new();
+        glb.extend(b"glTF");
+        glb.extend(2u32.to_le_bytes());
+        glb.extend((total as u32).to_le_bytes());
+        glb.extend((json.len() as u32).to_le_bytes());
+        glb.extend(b"JSON");
+        glb.extend(json);
+        glb.extend((bin.len() as u32).to_le_bytes());
+        glb.extend(b"BIN\0");
+        glb.extend(bin);
+        glb
+    }
+
+    #[test]
+    fn test_load_glb_applies_node_transforms() {
+        let mesh = load_gltf_slice(&quad_glb()).unwrap();
+        assert!(mesh.validate().is_ok());
+        assert_eq!(mesh.verts.len(), 4);
+        assert_eq!(mesh.tris, vec![0, 1, 2, 0, 2, 3]);
+        // Child translation is scaled by the parent
+        assert_eq!(mesh.verts[0], Vec3::new(20.0, 0.0, 0.0));
+        assert_eq!(mesh.verts[2], Vec3::new(22.0, 0.0, 2.0));
+        assert!(mesh.normals.iter().all(|n| n.y.abs() > 0.99));
+
+        let file = tempfile::Builder::new().suffix(".glb").tempfile().unwrap();
+        std::fs::write(file.path(), quad_glb()).unwrap();
+        assert_eq!(load_gltf(file.path()).unwrap().verts, mesh.verts);
+    }
+
+    #[test]
+    fn test_triangulate_strip_and_fan() {
+        use gltf::mesh::Mode;
+        assert_eq!(
+            triangulate(Mode::TriangleStrip, &[0, 1, 2, 3]),
+            vec![[0, 1, 2], [2, 1, 3]]
+        );
+        assert_eq!(
+            triangulate(Mode::TriangleFan, &[0, 1, 2, 3]),
+            vec![[0, 1, 2], [0, 2, 3]]
+        );
+        assert!(triangulate(Mode::Lines, &[0, 1, 2]).is_empty());
+    }
+}
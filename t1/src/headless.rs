@@ -0,0 +1,415 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::time::Instant;
+
+use crate::area_mapping::AreaMapping;
+use crate::bake_config::BakeConfig;
+use crate::build_context::BuildContext;
+use crate::debug_draw_b::{obj_to_input_mesh, InputMesh, InputMeshError};
+use crate::mesh_cleanup::IMPORT_WELD_TOLERANCE;
+use crate::mesh_import::{self, MeshImportError};
+use crate::mesh_stats::analyze_obj;
+use crate::nav_mesh::NavMesh;
+use crate::nav_mesh_bin::NavMeshBinError;
+use crate::navmesh_build::{
+    build_heightfield, eroded_compact, partition_compact, polygonize, tile_data, NavMeshBuildError,
+};
+use crate::obj_loader::{self, ObjLoadError};
+
+#[derive(Debug)]
+pub enum HeadlessError {
+    Usage(String),
+    Load(ObjLoadError),
+    Import(MeshImportError),
+    UnsupportedFormat(String),
+    InvalidMesh(InputMeshError),
+    Build(NavMeshBuildError),
+    // The bake produced no polygons, so there is nothing to write
+    EmptyNavMesh,
+    Write(NavMeshBinError),
+}
+
+impl fmt::Display for HeadlessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HeadlessError::Usage(usage) => write!(f, "{}", usage),
+            HeadlessError::Load(err) => write!(f, "{}", err),
+            HeadlessError::Import(err) => write!(f, "{}", err),
+            HeadlessError::UnsupportedFormat(path) => {
+                write!(f, "unsupported mesh format: {}", path)
+            }
+            HeadlessError::InvalidMesh(err) => write!(f, "invalid mesh: {}", err),
+            HeadlessError::Build(err) => write!(f, "bake failed: {}", err),
+            HeadlessError::EmptyNavMesh => write!(f, "the bake produced no polygons"),
+            HeadlessError::Write(err) => write!(f, "writing the navmesh failed: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for HeadlessError {}
+
+impl From<ObjLoadError> for HeadlessError {
+    fn from(error: ObjLoadError) -> Self {
+        HeadlessError::Load(error)
+    }
+}
+
+impl From<MeshImportError> for HeadlessError {
+    fn from(error: MeshImportError) -> Self {
+        HeadlessError::Import(error)
+    }
+}
+
+impl From<NavMeshBinError> for HeadlessError {
+    fn from(error: NavMeshBinError) -> Self {
+        HeadlessError::Write(error)
+    }
+}
+
+const USAGE: &str =
+    "usage: t1 bake <input.obj|gltf|glb|stl|ply> <output> [--config bake_config.json] [--areas areas.txt]";
+
+// Entry point for `t1 bake ...`; `args` excludes the subcommand itself
+pub fn run_cli(args: &[String]) -> Result<(), HeadlessError> {
+    let mut positional = Vec::new();
+    let mut config_path: Option<PathBuf> = None;
+    let mut areas_path: Option<PathBuf> = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--config" => {
+                let path = iter
+                    .next()
+                    .ok_or_else(|| HeadlessError::Usage("--config needs a path".to_string()))?;
+                config_path = Some(PathBuf::from(path));
+            }
+            "--areas" => {
+                let path = iter
+                    .next()
+                    .ok_or_else(|| HeadlessError::Usage("--areas needs a path".to_string()))?;
+                areas_path = Some(PathBuf::from(path));
+            }
+            _ if arg.starts_with("--") => {
+                return Err(HeadlessError::Usage(format!("unknown option {}", arg)));
+            }
+            _ => positional.push(PathBuf::from(arg)),
+        }
+    }
+
+    let [input, output] = &positional[..] else {
+        return Err(HeadlessError::Usage(USAGE.to_string()));
+    };
+
+    // An explicit config must load; the one next to the executable is optional
+    let config = match config_path {
+        Some(path) => load_config(&path)?,
+        None => BakeConfig::default_path()
+            .and_then(|path| BakeConfig::load(path).ok())
+            .unwrap_or_default(),
+    };
+    let areas = areas_path.map(|path| load_areas(&path)).transpose()?;
+
+    run_headless(input, output, &config, areas.as_ref())
+}
+
+const NAVBAKE_USAGE: &str =
+    "usage: navbake <input.obj|gltf|glb|stl|ply> <config.toml|json> <output.bin> [--areas areas.txt]";
+
+// Entry point for the navbake binary, for asset pipelines and CI. Unlike
+// `t1 bake` the config is required, so a bake never depends on whatever
+// sits next to the executable.
+pub fn run_navbake_cli(args: &[String]) -> Result<(), HeadlessError> {
+    let mut positional = Vec::new();
+    let mut areas_path: Option<PathBuf> = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--areas" => {
+                let path = iter
+                    .next()
+                    .ok_or_else(|| HeadlessError::Usage("--areas needs a path".to_string()))?;
+                areas_path = Some(PathBuf::from(path));
+            }
+            _ if arg.starts_with("--") => {
+                return Err(HeadlessError::Usage(format!("unknown option {}", arg)));
+            }
+            _ => positional.push(PathBuf::from(arg)),
+        }
+    }
+
+    let [input, config_path, output] = &positional[..] else {
+        return Err(HeadlessError::Usage(NAVBAKE_USAGE.to_string()));
+    };
+    let config = load_config(config_path)?;
+    let areas = areas_path.map(|path| load_areas(&path)).transpose()?;
+
+    run_headless(input, output, &config, areas.as_ref())
+}
+
+fn load_config(path: &Path) -> Result<BakeConfig, HeadlessError> {
+    BakeConfig::load(path).map_err(|e| HeadlessError::Usage(format!("{}: {}", path.display(), e)))
+}
+
+fn load_areas(path: &Path) -> Result<AreaMapping, HeadlessError> {
+    AreaMapping::load(path).map_err(|e| HeadlessError::Usage(format!("{}: {}", path.display(), e)))
+}
+
+const ANALYZE_USAGE: &str = "usage: t1 analyze <input.obj> [--slope degrees] [--format json|text]";
+
+// Entry point for `t1 analyze ...`: print a walkability report for an OBJ
+pub fn run_analyze_cli(args: &[String]) -> Result<(), HeadlessError> {
+    let mut input: Option<PathBuf> = None;
+    let mut slope = 45.0;
+    let mut json = false;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--slope" => {
+                slope = iter
+                    .next()
+                    .and_then(|v| v.parse().ok())
+                    .ok_or_else(|| HeadlessError::Usage("--slope needs a number".to_string()))?;
+            }
+            "--format" => match iter.next().map(String::as_str) {
+                Some("json") => json = true,
+                Some("text") => json = false,
+                _ => {
+                    return Err(HeadlessError::Usage(
+                        "--format must be json or text".to_string(),
+                    ))
+                }
+            },
+            _ if arg.starts_with("--") => {
+                return Err(HeadlessError::Usage(format!("unknown option {}", arg)));
+            }
+            _ if input.is_none() => input = Some(PathBuf::from(arg)),
+            _ => return Err(HeadlessError::Usage(ANALYZE_USAGE.to_string())),
+        }
+    }
+
+    let input = input.ok_or_else(|| HeadlessError::Usage(ANALYZE_USAGE.to_string()))?;
+    let report =
+        analyze_obj(&obj_loader::load_obj(&input)?, slope).map_err(HeadlessError::InvalidMesh)?;
+    if json {
+        print!("{}", report.to_json());
+    } else {
+        print!("{}", report.to_text());
+    }
+    Ok(())
+}
+
+// Load `input`, bake a navmesh with `cfg` and write it to `output` as a
+// single-tile navmesh set (see `NavMesh::save_bin`), without creating a
+// window. `areas` assigns area ids to OBJ faces by name. Counts and timings
+// go to stdout.
+pub fn run_headless(
+    input: &Path,
+    output: &Path,
+    cfg: &BakeConfig,
+    areas: Option<&AreaMapping>,
+) -> Result<(), HeadlessError> {
+    let start = Instant::now();
+    let mesh = load_input_mesh(input, areas)?;
+    println!(
+        "Loaded {}: {} vertices, {} triangles in {:.1} ms",
+        input.display(),
+        mesh.verts.len(),
+        mesh.tri_count(),
+        start.elapsed().as_secs_f64() * 1000.0
+    );
+
+    println!(
+        "Bake config: cs {} ch {} walkable height {} climb {} radius {} slope {} min region area {} verts per poly {}",
+        cfg.cell_size,
+        cfg.cell_height,
+        cfg.walkable_height,
+        cfg.walkable_climb,
+        cfg.agent_radius,
+        cfg.walkable_slope_angle,
+        cfg.min_region_area,
+        cfg.verts_per_poly
+    );
+
+    let build_config = cfg
+        .to_build_config()
+        .map_err(|e| HeadlessError::Build(e.into()))?;
+    let start = Instant::now();
+    let mut ctx = BuildContext::new();
+    // The stages of `build_navmesh_with_context`, run one by one to count
+    // what each produced
+    let hf = build_heightfield(&mesh, &build_config, &mut ctx).map_err(HeadlessError::Build)?;
+    let mut chf = eroded_compact(&hf, &build_config, &mut ctx);
+    partition_compact(&mut chf, &build_config, &[], 0, &mut ctx);
+    let baked = polygonize(&chf, &build_config, &mut ctx);
+    println!(
+        "Baked {} spans ({} walkable), {} regions, {} polygons, {} vertices, {} detail triangles in {:.1} ms",
+        hf.span_count(),
+        chf.span_count(),
+        chf.max_regions,
+        baked.poly_mesh.polys.len(),
+        baked.poly_mesh.verts.len(),
+        baked.detail_mesh.tris.len(),
+        start.elapsed().as_secs_f64() * 1000.0
+    );
+    print!("{}", ctx.to_text());
+
+    let data = tile_data(&baked, &build_config, 0, 0, 0, &[])
+        .map_err(|e| HeadlessError::Build(e.into()))?
+        .ok_or(HeadlessError::EmptyNavMesh)?;
+    let nav_mesh = NavMesh::single_tile(data).map_err(|e| HeadlessError::Build(e.into()))?;
+
+    let file = File::create(output).map_err(NavMeshBinError::from)?;
+    let mut writer = BufWriter::new(file);
+    nav_mesh.save_bin(&mut writer)?;
+    writer.flush().map_err(NavMeshBinError::from)?;
+    let size = std::fs::metadata(output).map_or(0, |m| m.len());
+    println!("Wrote {} ({} bytes)", output.display(), size);
+    Ok(())
+}
+
+// Only OBJ files carry the names `areas` refers to; other formats ignore it
+pub(crate) fn load_input_mesh(
+    path: &Path,
+    areas: Option<&AreaMapping>,
+) -> Result<InputMesh, HeadlessError> {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase);
+
+    match extension.as_deref() {
+        Some("obj") => {
+            let obj = obj_loader::load_obj_mapped(path, |_| {})?;
+            let mut mesh = obj_to_input_mesh(&obj, areas).map_err(HeadlessError::InvalidMesh)?;
+            mesh.weld(IMPORT_WELD_TOLERANCE);
+            mesh.remove_degenerates();
+            mesh.validate().map_err(HeadlessError::InvalidMesh)?;
+            Ok(mesh)
+        }
+        _ if mesh_import::importer_for(path).is_some() => {
+            let cancel = AtomicBool::new(false);
+            let (mesh, _) = mesh_import::import_mesh(path, &|_| {}, &cancel)?;
+            Ok(mesh)
+        }
+        _ => Err(HeadlessError::UnsupportedFormat(path.display().to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_run_headless_errors() {
+        let output = NamedTempFile::new().unwrap();
+        let config = BakeConfig::default();
+
+        let missing = run_headless(Path::new("missing.obj"), output.path(), &config, None);
+        assert!(matches!(
+            missing,
+            Err(HeadlessError::Load(ObjLoadError::IoError(_)))
+        ));
+
+        let gltf = run_headless(Path::new("missing.glb"), output.path(), &config, None);
+        assert!(matches!(gltf, Err(HeadlessError::Import(_))));
+
+        let fbx = run_headless(Path::new("scene.fbx"), output.path(), &config, None);
+        assert!(matches!(fbx, Err(HeadlessError::UnsupportedFormat(_))));
+
+        // Too small to keep a region
+        let input = tempfile::Builder::new().suffix(".obj").tempfile().unwrap();
+        write(input.path(), "v 0 0 0\nv 1 0 0\nv 0 0 1\nf 1 3 2\n").unwrap();
+        let result = run_headless(input.path(), output.path(), &config, None);
+        assert!(matches!(result, Err(HeadlessError::EmptyNavMesh)));
+
+        // A face past the last vertex is reported rather than panicking
+        let bad = tempfile::Builder::new().suffix(".obj").tempfile().unwrap();
+        write(bad.path(), "v 0 0 0\nv 1 0 0\nv 0 0 1\nf 1 3 9\n").unwrap();
+        let result = run_headless(bad.path(), output.path(), &config, None);
+        assert!(matches!(
+            result,
+            Err(HeadlessError::InvalidMesh(
+                InputMeshError::IndexOutOfBounds { index: 8, .. }
+            ))
+        ));
+
+        let invalid = BakeConfig {
+            cell_size: 0.0,
+            ..BakeConfig::default()
+        };
+        let result = run_headless(input.path(), output.path(), &invalid, None);
+        assert!(matches!(result, Err(HeadlessError::Build(_))));
+
+        let args = vec!["only_input.obj".to_string()];
+        assert!(matches!(run_cli(&args), Err(HeadlessError::Usage(_))));
+    }
+
+    #[test]
+    fn test_navbake_writes_loadable_navmesh() {
+        let input = tempfile::Builder::new().suffix(".obj").tempfile().unwrap();
+        write(
+            input.path(),
+            "v 0 0 0\nv 10 0 0\nv 10 0 10\nv 0 0 10\nf 1 4 3 2\n",
+        )
+        .unwrap();
+        let config = tempfile::Builder::new().suffix(".toml").tempfile().unwrap();
+        write(
+            config.path(),
+            "cell_size = 0.25\npartition = \"monotone\"\n",
+        )
+        .unwrap();
+        let output = NamedTempFile::new().unwrap();
+
+        let args = |list: &[&Path]| {
+            list.iter()
+                .map(|p| p.to_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        run_navbake_cli(&args(&[input.path(), config.path(), output.path()])).unwrap();
+
+        let mut file = File::open(output.path()).unwrap();
+        let nav_mesh = NavMesh::load_bin(&mut file).unwrap();
+        assert_eq!(nav_mesh.tiles().count(), 1);
+
+        assert!(matches!(
+            run_navbake_cli(&args(&[input.path(), output.path()])),
+            Err(HeadlessError::Usage(_))
+        ));
+    }
+
+    #[test]
+    fn test_analyze_cli_errors() {
+        let args = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        assert!(matches!(
+            run_analyze_cli(&args(&["level.obj", "--format", "xml"])),
+            Err(HeadlessError::Usage(_))
+        ));
+        assert!(matches!(
+            run_analyze_cli(&args(&["missing.obj", "--slope", "30"])),
+            Err(HeadlessError::Load(_))
+        ));
+
+        let input = NamedTempFile::new().unwrap();
+        write(input.path(), "v 0 0 0\nv 1 0 0\nf 1 2 x\n").unwrap();
+        let path = input.path().to_str().unwrap();
+        assert!(matches!(
+            run_analyze_cli(&args(&[path, "--format", "json"])),
+            Err(HeadlessError::Load(ObjLoadError::ParseError(_)))
+        ));
+
+        write(input.path(), "v 0 0 0\nv 1 0 0\nv 0 0 1\nf 1 3 9\n").unwrap();
+        assert!(matches!(
+            run_analyze_cli(&args(&[path])),
+            Err(HeadlessError::InvalidMesh(_))
+        ));
+    }
+}
@@ -0,0 +1,441 @@
+use glam::{Mat4, Vec3};
+
+use crate::debug_draw_b::InputMesh;
+
+// Span heights are stored in 13 bits, as in Recast
+pub const RC_SPAN_HEIGHT_BITS: u32 = 13;
+pub const RC_SPAN_MAX_HEIGHT: u16 = (1 << RC_SPAN_HEIGHT_BITS) - 1;
+
+// A run of solid voxels in one column, in cell-height units above bmin.y
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    pub smin: u16,
+    pub smax: u16,
+    pub area: u8,
+}
+
+// Voxelized input geometry: a grid of columns, each a list of solid spans
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Heightfield {
+    pub width: i32,
+    pub height: i32,
+    pub bmin: Vec3,
+    pub bmax: Vec3,
+    // Cell size on the XZ plane and along Y
+    pub cs: f32,
+    pub ch: f32,
+    // One entry per column at x + z * width, spans sorted by smin and
+    // never overlapping
+    pub spans: Vec<Vec<Span>>,
+}
+
+// Number of cells needed to cover the bounds on the XZ plane
+pub fn calc_grid_size(bmin: Vec3, bmax: Vec3, cs: f32) -> (i32, i32) {
+    (
+        ((bmax.x - bmin.x) / cs + 0.5) as i32,
+        ((bmax.z - bmin.z) / cs + 0.5) as i32,
+    )
+}
+
+impl Heightfield {
+    pub fn new(bmin: Vec3, bmax: Vec3, cs: f32, ch: f32) -> Self {
+        let (width, height) = calc_grid_size(bmin, bmax, cs);
+        Self {
+            width,
+            height,
+            bmin,
+            bmax,
+            cs,
+            ch,
+            spans: vec![Vec::new(); (width * height) as usize],
+        }
+    }
+
+    // A heightfield covering the mesh bounds
+    pub fn for_mesh(mesh: &InputMesh, cs: f32, ch: f32) -> Self {
+        let (bmin, bmax) = mesh.bounds();
+        Self::new(bmin, bmax, cs, ch)
+    }
+
+    pub fn column(&self, x: i32, z: i32) -> &[Span] {
+        &self.spans[(x + z * self.width) as usize]
+    }
+
+    pub fn span_count(&self) -> usize {
+        self.spans.iter().map(Vec::len).sum()
+    }
+
+    // Insert a span, merging it with any span it touches. When the tops of
+    // two merged spans are within `flag_merge_threshold` the higher area id
+    // wins, so walkable surfaces aren't lost to a wall sharing the column.
+    pub fn add_span(
+        &mut self,
+        x: i32,
+        z: i32,
+        smin: u16,
+        smax: u16,
+        area: u8,
+        flag_merge_threshold: i32,
+    ) {
+        let column = &mut self.spans[(x + z * self.width) as usize];
+        let mut new = Span { smin, smax, area };
+
+        // Skip the spans entirely below the new one
+        let i = column.partition_point(|cur| cur.smax < new.smin);
+        while i < column.len() && column[i].smin <= new.smax {
+            let cur = column.remove(i);
+            new.smin = new.smin.min(cur.smin);
+            new.smax = new.smax.max(cur.smax);
+            if (new.smax as i32 - cur.smax as i32).abs() <= flag_merge_threshold {
+                new.area = new.area.max(cur.area);
+            }
+        }
+        column.insert(i, new);
+    }
+}
+
+// Voxelize every triangle of `mesh` into `hf`. `areas` holds one area id per
+// triangle; triangles marked RC_NULL_AREA still block but aren't walkable.
+pub fn rasterize_triangles(
+    hf: &mut Heightfield,
+    mesh: &InputMesh,
+    areas: &[u8],
+    flag_merge_threshold: i32,
+) {
+    let all: Vec<usize> = (0..mesh.tri_count().min(areas.len())).collect();
+    rasterize_triangle_subset(hf, mesh, areas, &all, flag_merge_threshold);
+}
+
+// Like rasterize_triangles, but only for the triangles listed in `tris`,
+// such as those a ChunkyTriMesh finds under a tile
+pub fn rasterize_triangle_subset(
+    hf: &mut Heightfield,
+    mesh: &InputMesh,
+    areas: &[u8],
+    tris: &[usize],
+    flag_merge_threshold: i32,
+) {
+    for (x, z, span) in rasterize_spans(hf, mesh, areas, tris) {
+        hf.add_span(x, z, span.smin, span.smax, span.area, flag_merge_threshold);
+    }
+}
+
+// Like rasterize_triangles, with every vertex of `mesh` moved by
+// `transform` as its triangles are voxelized
+pub fn rasterize_triangles_transformed(
+    hf: &mut Heightfield,
+    mesh: &InputMesh,
+    transform: Mat4,
+    areas: &[u8],
+    flag_merge_threshold: i32,
+) {
+    let mut spans = Vec::new();
+    for (t, &area) in areas.iter().enumerate().take(mesh.tri_count()) {
+        let verts = tri_verts(mesh, t).map(|v| transform.transform_point3(v));
+        rasterize_tri(hf, verts, area, &mut spans);
+    }
+    for (x, z, span) in spans {
+        hf.add_span(x, z, span.smin, span.smax, span.area, flag_merge_threshold);
+    }
+}
+
+#[cfg(not(feature = "rayon"))]
+fn rasterize_spans(
+    hf: &Heightfield,
+    mesh: &InputMesh,
+    areas: &[u8],
+    tris: &[usize],
+) -> Vec<(i32, i32, Span)> {
+    rasterize_spans_serial(hf, mesh, areas, tris)
+}
+
+#[cfg(feature = "rayon")]
+fn rasterize_spans(
+    hf: &Heightfield,
+    mesh: &InputMesh,
+    areas: &[u8],
+    tris: &[usize],
+) -> Vec<(i32, i32, Span)> {
+    use rayon::prelude::*;
+
+    // Each job fills its own buffer; the indexed collect keeps triangle
+    // order so merging afterwards matches the serial path exactly
+    let buffers: Vec<Vec<(i32, i32, Span)>> = tris
+        .par_iter()
+        .map(|&t| {
+            let mut buffer = Vec::new();
+            rasterize_tri(hf, tri_verts(mesh, t), areas[t], &mut buffer);
+            buffer
+        })
+        .collect();
+    buffers.concat()
+}
+
+#[cfg(any(test, not(feature = "rayon")))]
+fn rasterize_spans_serial(
+    hf: &Heightfield,
+    mesh: &InputMesh,
+    areas: &[u8],
+    tris: &[usize],
+) -> Vec<(i32, i32, Span)> {
+    let mut spans = Vec::new();
+    for &t in tris {
+        rasterize_tri(hf, tri_verts(mesh, t), areas[t], &mut spans);
+    }
+    spans
+}
+
+fn tri_verts(mesh: &InputMesh, t: usize) -> [Vec3; 3] {
+    let tri = &mesh.tris[t * 3..t * 3 + 3];
+    [
+        mesh.verts[tri[0] as usize],
+        mesh.verts[tri[1] as usize],
+        mesh.verts[tri[2] as usize],
+    ]
+}
+
+// Clip the triangle against each row and then each cell of the grid, and
+// emit the vertical extent of every piece that's left
+fn rasterize_tri(hf: &Heightfield, verts: [Vec3; 3], area: u8, out: &mut Vec<(i32, i32, Span)>) {
+    let tmin = verts[0].min(verts[1]).min(verts[2]);
+    let tmax = verts[0].max(verts[1]).max(verts[2]);
+    if tmin.cmpgt(hf.bmax).any() || tmax.cmplt(hf.bmin).any() {
+        return;
+    }
+
+    let ics = 1.0 / hf.cs;
+    let ich = 1.0 / hf.ch;
+    let by = hf.bmax.y - hf.bmin.y;
+
+    // Row -1 takes whatever lies before the grid so it can be clipped away
+    let z0 = (((tmin.z - hf.bmin.z) * ics) as i32).clamp(-1, hf.height - 1);
+    let z1 = (((tmax.z - hf.bmin.z) * ics) as i32).clamp(0, hf.height - 1);
+
+    let mut rest = verts.to_vec();
+    for z in z0..=z1 {
+        let cz = hf.bmin.z + z as f32 * hf.cs;
+        let (row, remainder) = divide_poly(&rest, cz + hf.cs, 2);
+        rest = remainder;
+        if z < 0 || row.len() < 3 {
+            continue;
+        }
+
+        let (min_x, max_x) = row.iter().fold((f32::MAX, f32::MIN), |(lo, hi), v| {
+            (lo.min(v.x), hi.max(v.x))
+        });
+        let x0 = ((min_x - hf.bmin.x) * ics) as i32;
+        let x1 = ((max_x - hf.bmin.x) * ics) as i32;
+        if x1 < 0 || x0 >= hf.width {
+            continue;
+        }
+        let x0 = x0.clamp(-1, hf.width - 1);
+        let x1 = x1.clamp(0, hf.width - 1);
+
+        let mut row_rest = row;
+        for x in x0..=x1 {
+            let cx = hf.bmin.x + x as f32 * hf.cs;
+            let (cell, remainder) = divide_poly(&row_rest, cx + hf.cs, 0);
+            row_rest = remainder;
+            if x < 0 || cell.len() < 3 {
+                continue;
+            }
+
+            let (smin, smax) = cell.iter().fold((f32::MAX, f32::MIN), |(lo, hi), v| {
+                (lo.min(v.y), hi.max(v.y))
+            });
+            let smin = smin - hf.bmin.y;
+            let smax = smax - hf.bmin.y;
+            // Skip pieces entirely above or below the heightfield
+            if smax < 0.0 || smin > by {
+                continue;
+            }
+            let smin = smin.max(0.0);
+            let smax = smax.min(by);
+
+            let max_height = RC_SPAN_MAX_HEIGHT as i32;
+            let ismin = ((smin * ich).floor() as i32).clamp(0, max_height);
+            let ismax = ((smax * ich).ceil() as i32).max(ismin + 1).min(max_height);
+            out.push((
+                x,
+                z,
+                Span {
+                    smin: ismin as u16,
+                    smax: ismax as u16,
+                    area,
+                },
+            ));
+        }
+    }
+}
+
+// Split a convex polygon by the plane `v[axis] == value` into the part
+// below it and the part above it
+fn divide_poly(poly: &[Vec3], value: f32, axis: usize) -> (Vec<Vec3>, Vec<Vec3>) {
+    let mut below = Vec::with_capacity(poly.len() + 1);
+    let mut above = Vec::with_capacity(poly.len() + 1);
+    let dist = |v: Vec3| value - v[axis];
+    if poly.is_empty() {
+        return (below, above);
+    }
+
+    let mut j = poly.len() - 1;
+    for i in 0..poly.len() {
+        let (da, db) = (dist(poly[j]), dist(poly[i]));
+        if (da >= 0.0) != (db >= 0.0) {
+            // The edge crosses the plane: both halves get the intersection
+            let s = da / (da - db);
+            let p = poly[j] + (poly[i] - poly[j]) * s;
+            below.push(p);
+            above.push(p);
+            if db > 0.0 {
+                below.push(poly[i]);
+            } else if db < 0.0 {
+                above.push(poly[i]);
+            }
+        } else if db > 0.0 {
+            below.push(poly[i]);
+        } else if db < 0.0 {
+            above.push(poly[i]);
+        } else {
+            // Exactly on the plane
+            below.push(poly[i]);
+            above.push(poly[i]);
+        }
+        j = i;
+    }
+    (below, above)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debug_draw::RC_WALKABLE_AREA;
+
+    fn quad(y: f32, size: f32) -> InputMesh {
+        let mut mesh = InputMesh::new();
+        mesh.verts = vec![
+            Vec3::new(0.0, y, 0.0),
+            Vec3::new(size, y, 0.0),
+            Vec3::new(size, y, size),
+            Vec3::new(0.0, y, size),
+        ];
+        mesh.tris = vec![0, 2, 1, 0, 3, 2];
+        mesh.compute_normals();
+        mesh
+    }
+
+    #[test]
+    fn test_empty_mesh_gets_an_empty_heightfield() {
+        let hf = Heightfield::for_mesh(&InputMesh::new(), 0.3, 0.2);
+        assert_eq!((hf.width, hf.height), (0, 0));
+        assert!(hf.spans.is_empty());
+
+        let quad = quad(1.0, 2.0);
+        assert_eq!(quad.bounds(), (Vec3::Y, Vec3::new(2.0, 1.0, 2.0)));
+    }
+
+    #[test]
+    fn test_add_span_merges() {
+        let mut hf = Heightfield::new(Vec3::ZERO, Vec3::new(1.0, 10.0, 1.0), 1.0, 0.1);
+        hf.add_span(0, 0, 10, 20, 1, 1);
+        hf.add_span(0, 0, 30, 40, 2, 1);
+        hf.add_span(0, 0, 0, 5, 0, 1);
+        assert_eq!(hf.column(0, 0).len(), 3);
+        assert!(hf.column(0, 0).windows(2).all(|w| w[0].smax < w[1].smin));
+
+        // Bridging the top two keeps the walkable area of the top surface
+        hf.add_span(0, 0, 15, 39, 0, 1);
+        assert_eq!(
+            hf.column(0, 0),
+            &[
+                Span {
+                    smin: 0,
+                    smax: 5,
+                    area: 0
+                },
+                Span {
+                    smin: 10,
+                    smax: 40,
+                    area: 2
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rasterize_flat_quad() {
+        // The quad sits 1 unit above the bottom of a taller heightfield
+        let mesh = quad(1.0, 4.0);
+        let mut hf = Heightfield::new(Vec3::ZERO, Vec3::new(4.0, 2.0, 4.0), 0.5, 0.25);
+        assert_eq!((hf.width, hf.height), (8, 8));
+
+        rasterize_triangles(&mut hf, &mesh, &[RC_WALKABLE_AREA; 2], 1);
+        assert_eq!(hf.span_count(), 64);
+        for column in &hf.spans {
+            assert_eq!(
+                column,
+                &[Span {
+                    smin: 4,
+                    smax: 5,
+                    area: RC_WALKABLE_AREA
+                }]
+            );
+        }
+    }
+
+    #[test]
+    fn test_rasterize_clips_to_bounds() {
+        // Only the part of the quad over the heightfield is voxelized
+        let mesh = quad(0.0, 4.0);
+        let mut hf = Heightfield::new(
+            Vec3::new(1.0, -1.0, 1.0),
+            Vec3::new(3.0, 1.0, 2.0),
+            0.5,
+            0.1,
+        );
+        rasterize_triangles(&mut hf, &mesh, &[RC_WALKABLE_AREA; 2], 1);
+        assert_eq!(hf.span_count(), (hf.width * hf.height) as usize);
+
+        let outside = quad(5.0, 4.0);
+        let mut hf = Heightfield::new(Vec3::ZERO, Vec3::new(4.0, 1.0, 4.0), 0.5, 0.1);
+        rasterize_triangles(&mut hf, &outside, &[RC_WALKABLE_AREA; 2], 1);
+        assert_eq!(hf.span_count(), 0);
+    }
+
+    #[test]
+    fn test_parallel_rasterization_matches_serial() {
+        // A bumpy grid, so columns receive spans from several triangles
+        let n = 64;
+        let mut mesh = InputMesh::new();
+        for z in 0..=n {
+            for x in 0..=n {
+                let y = ((x * 7 + z * 13) % 5) as f32 * 0.3;
+                mesh.verts.push(Vec3::new(x as f32, y, z as f32));
+            }
+        }
+        let row = n + 1;
+        for z in 0..n {
+            for x in 0..n {
+                let i = z * row + x;
+                mesh.tris
+                    .extend_from_slice(&[i, i + row, i + 1, i + 1, i + row, i + row + 1]);
+            }
+        }
+        mesh.compute_normals();
+        let areas: Vec<u8> = (0..mesh.tri_count()).map(|i| (i % 3) as u8).collect();
+
+        let mut hf = Heightfield::for_mesh(&mesh, 0.3, 0.2);
+        rasterize_triangles(&mut hf, &mesh, &areas, 1);
+
+        let mut serial = Heightfield::for_mesh(&mesh, 0.3, 0.2);
+        let all: Vec<usize> = (0..mesh.tri_count()).collect();
+        for (x, z, span) in rasterize_spans_serial(&serial, &mesh, &areas, &all) {
+            serial.add_span(x, z, span.smin, span.smax, span.area, 1);
+        }
+
+        assert!(hf.span_count() > 0);
+        assert_eq!(hf, serial);
+    }
+}
@@ -0,0 +1,484 @@
+use glam::Vec3;
+
+use crate::compact_heightfield::{CompactCell, CompactHeightfield, CompactSpan, RC_NOT_CONNECTED};
+use crate::debug_draw::RC_NULL_AREA;
+
+// Marks an empty cell in `HeightfieldLayer::heights`
+pub const RC_LAYER_EMPTY: u8 = 0xff;
+
+const NO_REGION: u16 = u16::MAX;
+const NO_LAYER: u16 = u16::MAX;
+
+// A 2.5D slice of a compact heightfield: at most one walkable span per
+// column, with heights small enough to fit a byte. Like Recast's
+// rcHeightfieldLayer.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HeightfieldLayer {
+    // Bounds of the layer; `bmin.y` is the height of the lowest span
+    pub bmin: Vec3,
+    pub bmax: Vec3,
+    pub cs: f32,
+    pub ch: f32,
+    pub width: i32,
+    pub height: i32,
+    // Span heights in cell-height units above `bmin.y`, RC_LAYER_EMPTY
+    // where the layer has no span
+    pub heights: Vec<u8>,
+    pub areas: Vec<u8>,
+    // Per cell, connections to neighbours within the layer in the low four
+    // bits and portals to other layers in the high four, one bit per
+    // direction
+    pub cons: Vec<u8>,
+}
+
+impl HeightfieldLayer {
+    pub fn is_connected(&self, x: i32, z: i32, dir: usize) -> bool {
+        self.cons[(x + z * self.width) as usize] & (1 << dir) != 0
+    }
+
+    pub fn is_portal(&self, x: i32, z: i32, dir: usize) -> bool {
+        self.cons[(x + z * self.width) as usize] & (0x10 << dir) != 0
+    }
+
+    // One span per non-empty cell, for the rest of the build pipeline.
+    // The clearance above the spans isn't kept, so they get the most.
+    pub fn to_compact_heightfield(
+        &self,
+        walkable_height: i32,
+        walkable_climb: i32,
+    ) -> CompactHeightfield {
+        let mut cells = Vec::with_capacity(self.heights.len());
+        let mut spans = Vec::new();
+        let mut areas = Vec::new();
+        for (i, &h) in self.heights.iter().enumerate() {
+            let index = spans.len() as u32;
+            if h == RC_LAYER_EMPTY {
+                cells.push(CompactCell { index, count: 0 });
+                continue;
+            }
+            cells.push(CompactCell { index, count: 1 });
+            let mut con = [RC_NOT_CONNECTED; 4];
+            for (dir, c) in con.iter_mut().enumerate() {
+                if self.cons[i] & (1 << dir) != 0 {
+                    *c = 0;
+                }
+            }
+            spans.push(CompactSpan {
+                y: h as u16,
+                h: u8::MAX,
+                reg: 0,
+                con,
+            });
+            areas.push(self.areas[i]);
+        }
+        CompactHeightfield {
+            width: self.width,
+            height: self.height,
+            walkable_height,
+            walkable_climb,
+            border_size: 0,
+            max_distance: 0,
+            max_regions: 0,
+            bmin: self.bmin,
+            bmax: self.bmax + Vec3::new(0.0, walkable_height as f32 * self.ch, 0.0),
+            cs: self.cs,
+            ch: self.ch,
+            cells,
+            spans,
+            dist: Vec::new(),
+            areas,
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+struct LayerRegion {
+    ymin: u16,
+    ymax: u16,
+    // Regions sharing a column with this one, which can't be in its layer
+    overlaps: Vec<u16>,
+    neighbours: Vec<u16>,
+    layer_id: u16,
+    // First region of its layer
+    base: bool,
+}
+
+#[derive(Clone, Copy, Default)]
+struct LayerSweep {
+    id: u16,
+    // Spans of the run connected to `nei` in the previous row
+    ns: u16,
+    nei: u16,
+}
+
+fn add_unique(list: &mut Vec<u16>, v: u16) {
+    if !list.contains(&v) {
+        list.push(v);
+    }
+}
+
+// Split the walkable spans of `chf` into layers that don't overlap
+// themselves, like rcBuildHeightfieldLayers. The heightfield is first cut
+// into monotone regions, which are then grown into layers as long as no
+// column ends up with two spans, and layers within four agent heights of
+// each other are merged. Layers only covering the outer `border_size`
+// cells are dropped; the others keep their border cells as context for
+// the rest of the build.
+pub fn build_heightfield_layers(
+    chf: &CompactHeightfield,
+    border_size: i32,
+    walkable_height: i32,
+) -> Vec<HeightfieldLayer> {
+    let (w, h) = (chf.width, chf.height);
+    let mut src_reg = vec![NO_REGION; chf.span_count()];
+    let mut reg_count: u16 = 0;
+
+    // Monotone partition, as for region building
+    let mut sweeps: Vec<LayerSweep> = Vec::new();
+    let mut prev_count: Vec<u16> = Vec::new();
+    for z in 0..h {
+        prev_count.clear();
+        prev_count.resize(reg_count as usize, 0);
+        sweeps.clear();
+
+        for x in 0..w {
+            for i in chf.span_range(x, z) {
+                if chf.areas[i] == RC_NULL_AREA {
+                    continue;
+                }
+                let sid = match chf.neighbour(x, z, i, 0) {
+                    Some((_, _, ai))
+                        if chf.areas[ai] != RC_NULL_AREA && src_reg[ai] != NO_REGION =>
+                    {
+                        src_reg[ai]
+                    }
+                    _ => {
+                        sweeps.push(LayerSweep {
+                            nei: NO_REGION,
+                            ..Default::default()
+                        });
+                        (sweeps.len() - 1) as u16
+                    }
+                };
+
+                if let Some((_, _, ai)) = chf.neighbour(x, z, i, 3) {
+                    let nr = src_reg[ai];
+                    if nr != NO_REGION {
+                        let sweep = &mut sweeps[sid as usize];
+                        if sweep.ns == 0 {
+                            sweep.nei = nr;
+                        }
+                        if sweep.nei == nr {
+                            sweep.ns += 1;
+                            prev_count[nr as usize] += 1;
+                        } else {
+                            sweep.nei = NO_REGION;
+                        }
+                    }
+                }
+                src_reg[i] = sid;
+            }
+        }
+
+        // A run continues the region above only when all of that region's
+        // spans in the previous row connect to it
+        for sweep in &mut sweeps {
+            if sweep.nei != NO_REGION && prev_count[sweep.nei as usize] == sweep.ns {
+                sweep.id = sweep.nei;
+            } else {
+                sweep.id = reg_count;
+                reg_count += 1;
+            }
+        }
+        for x in 0..w {
+            for i in chf.span_range(x, z) {
+                if src_reg[i] != NO_REGION {
+                    src_reg[i] = sweeps[src_reg[i] as usize].id;
+                }
+            }
+        }
+    }
+
+    let mut regs = vec![
+        LayerRegion {
+            ymin: u16::MAX,
+            layer_id: NO_LAYER,
+            ..Default::default()
+        };
+        reg_count as usize
+    ];
+    let mut column: Vec<u16> = Vec::new();
+    for z in 0..h {
+        for x in 0..w {
+            column.clear();
+            for i in chf.span_range(x, z) {
+                let ri = src_reg[i];
+                if ri == NO_REGION {
+                    continue;
+                }
+                let reg = &mut regs[ri as usize];
+                reg.ymin = reg.ymin.min(chf.spans[i].y);
+                reg.ymax = reg.ymax.max(chf.spans[i].y);
+                column.push(ri);
+                for dir in 0..4 {
+                    if let Some((_, _, ai)) = chf.neighbour(x, z, i, dir) {
+                        let rai = src_reg[ai];
+                        if rai != NO_REGION && rai != ri {
+                            add_unique(&mut regs[ri as usize].neighbours, rai);
+                        }
+                    }
+                }
+            }
+            for (j, &a) in column.iter().enumerate() {
+                for &b in &column[j + 1..] {
+                    if a != b {
+                        add_unique(&mut regs[a as usize].overlaps, b);
+                        add_unique(&mut regs[b as usize].overlaps, a);
+                    }
+                }
+            }
+        }
+    }
+
+    // Flood neighbouring regions into layers while they don't overlap and
+    // the height range still fits a byte
+    let mut layer_count: u16 = 0;
+    let mut stack: Vec<u16> = Vec::new();
+    for root in 0..regs.len() {
+        if regs[root].layer_id != NO_LAYER {
+            continue;
+        }
+        regs[root].layer_id = layer_count;
+        regs[root].base = true;
+        stack.push(root as u16);
+        while let Some(reg) = stack.pop() {
+            for k in 0..regs[reg as usize].neighbours.len() {
+                let nr = regs[reg as usize].neighbours[k];
+                let nreg = &regs[nr as usize];
+                if nreg.layer_id != NO_LAYER || regs[root].overlaps.contains(&nr) {
+                    continue;
+                }
+                let ymin = regs[root].ymin.min(nreg.ymin);
+                let ymax = regs[root].ymax.max(nreg.ymax);
+                if ymax - ymin >= RC_LAYER_EMPTY as u16 {
+                    continue;
+                }
+                let overlaps = nreg.overlaps.clone();
+                regs[nr as usize].layer_id = layer_count;
+                let root = &mut regs[root];
+                for o in overlaps {
+                    add_unique(&mut root.overlaps, o);
+                }
+                root.ymin = ymin;
+                root.ymax = ymax;
+                stack.push(nr);
+            }
+        }
+        layer_count += 1;
+    }
+
+    // Merge layers close enough in height that aren't stacked anywhere
+    let merge_height = (walkable_height * 4) as u16;
+    for i in 0..regs.len() {
+        if !regs[i].base {
+            continue;
+        }
+        let new_id = regs[i].layer_id;
+        loop {
+            let ri = &regs[i];
+            let old_id = (0..regs.len()).find_map(|j| {
+                let rj = &regs[j];
+                if i == j || !rj.base {
+                    return None;
+                }
+                let near = ri.ymin <= rj.ymax.saturating_add(merge_height)
+                    && rj.ymin <= ri.ymax.saturating_add(merge_height);
+                let ymin = ri.ymin.min(rj.ymin);
+                let ymax = ri.ymax.max(rj.ymax);
+                if !near || ymax - ymin >= RC_LAYER_EMPTY as u16 {
+                    return None;
+                }
+                let stacked = regs.iter().filter(|r| r.layer_id == rj.layer_id).any(|r| {
+                    r.overlaps
+                        .iter()
+                        .any(|&o| regs[o as usize].layer_id == new_id)
+                });
+                (!stacked).then_some(rj.layer_id)
+            });
+            let Some(old_id) = old_id else {
+                break;
+            };
+
+            let mut overlaps = Vec::new();
+            let (mut ymin, mut ymax) = (regs[i].ymin, regs[i].ymax);
+            for rj in regs.iter_mut().filter(|r| r.layer_id == old_id) {
+                rj.base = false;
+                rj.layer_id = new_id;
+                overlaps.extend_from_slice(&rj.overlaps);
+                ymin = ymin.min(rj.ymin);
+                ymax = ymax.max(rj.ymax);
+            }
+            let ri = &mut regs[i];
+            for o in overlaps {
+                add_unique(&mut ri.overlaps, o);
+            }
+            ri.ymin = ymin;
+            ri.ymax = ymax;
+        }
+    }
+
+    // Layer id, height range and whether it reaches inside the border, per
+    // surviving layer id
+    let mut remap = vec![NO_LAYER; layer_count as usize];
+    let mut ranges: Vec<(u16, u16)> = Vec::new();
+    let mut inside = vec![false; layer_count as usize];
+    for z in border_size..h - border_size {
+        for x in border_size..w - border_size {
+            for i in chf.span_range(x, z) {
+                if src_reg[i] != NO_REGION {
+                    inside[regs[src_reg[i] as usize].layer_id as usize] = true;
+                }
+            }
+        }
+    }
+    for reg in regs.iter().filter(|r| r.base) {
+        let id = reg.layer_id as usize;
+        if inside[id] && remap[id] == NO_LAYER {
+            remap[id] = ranges.len() as u16;
+            ranges.push((reg.ymin, reg.ymax));
+        }
+    }
+
+    let mut layers: Vec<HeightfieldLayer> = ranges
+        .iter()
+        .map(|&(hmin, hmax)| HeightfieldLayer {
+            bmin: Vec3::new(chf.bmin.x, chf.bmin.y + hmin as f32 * chf.ch, chf.bmin.z),
+            bmax: Vec3::new(chf.bmax.x, chf.bmin.y + hmax as f32 * chf.ch, chf.bmax.z),
+            cs: chf.cs,
+            ch: chf.ch,
+            width: w,
+            height: h,
+            heights: vec![RC_LAYER_EMPTY; (w * h) as usize],
+            areas: vec![RC_NULL_AREA; (w * h) as usize],
+            cons: vec![0; (w * h) as usize],
+        })
+        .collect();
+    let layer_of = |i: usize| -> Option<usize> {
+        let reg = src_reg[i];
+        if reg == NO_REGION {
+            return None;
+        }
+        let id = remap[regs[reg as usize].layer_id as usize];
+        (id != NO_LAYER).then_some(id as usize)
+    };
+    for z in 0..h {
+        for x in 0..w {
+            let idx = (x + z * w) as usize;
+            for i in chf.span_range(x, z) {
+                let Some(lid) = layer_of(i) else {
+                    continue;
+                };
+                let hmin = ranges[lid].0;
+                let layer = &mut layers[lid];
+                let y = chf.spans[i].y;
+                layer.heights[idx] = (y - hmin).min(RC_LAYER_EMPTY as u16 - 1) as u8;
+                layer.areas[idx] = chf.areas[i];
+
+                let mut con = 0u8;
+                let mut portal = 0u8;
+                for dir in 0..4 {
+                    let Some((_, _, ai)) = chf.neighbour(x, z, i, dir) else {
+                        continue;
+                    };
+                    match layer_of(ai) {
+                        Some(alid) if alid == lid => con |= 1 << dir,
+                        Some(_) => {
+                            portal |= 1 << dir;
+                            // Raise the cell to meet the other side of the
+                            // portal when that's higher
+                            let ay = chf.spans[ai].y;
+                            if ay > hmin {
+                                let raised = (ay - hmin).min(RC_LAYER_EMPTY as u16 - 1) as u8;
+                                layer.heights[idx] = layer.heights[idx].max(raised);
+                            }
+                        }
+                        None => {}
+                    }
+                }
+                layer.cons[idx] = (portal << 4) | con;
+            }
+        }
+    }
+    layers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debug_draw::RC_WALKABLE_AREA;
+    use crate::heightfield::Heightfield;
+
+    #[test]
+    fn test_stacked_floors_split_into_layers() {
+        // A 6x6 floor with a 4x4 deck 40 cells above its middle
+        let mut hf = Heightfield::new(Vec3::ZERO, Vec3::new(6.0, 10.0, 6.0), 1.0, 0.1);
+        for z in 0..6 {
+            for x in 0..6 {
+                hf.add_span(x, z, 0, 2, RC_WALKABLE_AREA, 1);
+                if (1..5).contains(&x) && (1..5).contains(&z) {
+                    hf.add_span(x, z, 40, 42, RC_WALKABLE_AREA, 1);
+                }
+            }
+        }
+        let chf = CompactHeightfield::build(&hf, 10, 4);
+        let layers = build_heightfield_layers(&chf, 0, 10);
+        assert_eq!(layers.len(), 2);
+
+        let filled = |layer: &HeightfieldLayer| {
+            layer
+                .heights
+                .iter()
+                .filter(|&&h| h != RC_LAYER_EMPTY)
+                .count()
+        };
+        let (floor, deck) = if layers[0].bmin.y < layers[1].bmin.y {
+            (&layers[0], &layers[1])
+        } else {
+            (&layers[1], &layers[0])
+        };
+        assert_eq!(filled(floor), 36);
+        assert_eq!(filled(deck), 16);
+        assert!((deck.bmin.y - 4.2).abs() < 1e-4);
+        assert!(floor.is_connected(2, 2, 2));
+        assert!(!deck.is_connected(1, 1, 0));
+
+        // Back to a heightfield with one span per covered column
+        let deck_chf = deck.to_compact_heightfield(10, 4);
+        assert_eq!(deck_chf.span_count(), 16);
+        let centre = deck_chf.span_range(2, 2).start;
+        assert!((0..4).all(|dir| deck_chf.spans[centre].con(dir).is_some()));
+    }
+
+    #[test]
+    fn test_border_only_layers_dropped() {
+        // A strip of floor with a ledge above its first column, which only
+        // the border covers
+        let mut hf = Heightfield::new(Vec3::ZERO, Vec3::new(6.0, 10.0, 3.0), 1.0, 0.1);
+        for z in 0..3 {
+            for x in 0..6 {
+                hf.add_span(x, z, 0, 2, RC_WALKABLE_AREA, 1);
+            }
+            hf.add_span(0, z, 40, 42, RC_WALKABLE_AREA, 1);
+        }
+        let chf = CompactHeightfield::build(&hf, 10, 4);
+        assert_eq!(build_heightfield_layers(&chf, 0, 10).len(), 2);
+
+        let layers = build_heightfield_layers(&chf, 1, 10);
+        assert_eq!(layers.len(), 1);
+        assert!((layers[0].bmin.y - 0.2).abs() < 1e-4);
+        // The floor keeps its border cells
+        assert_eq!(layers[0].heights[0], 0);
+        assert!(!layers[0].is_portal(0, 1, 2));
+    }
+}
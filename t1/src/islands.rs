@@ -0,0 +1,403 @@
+use glam::Vec3;
+
+use crate::debug_draw::{PolyMesh, PolyMeshDetail, RC_MESH_NULL_IDX};
+use crate::nav_mesh::point_in_polygon;
+use crate::navmesh_build::BakedNavMesh;
+use crate::offmesh::OffMeshConnection;
+use crate::poly_mesh::RC_MESH_PORTAL_FLAG;
+
+// Polygons connected through shared edges. Agents can't walk from one
+// island to another, only jump along off-mesh connections.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Island {
+    pub polys: Vec<usize>,
+    // Walkable surface seen from above, in square world units
+    pub area: f32,
+    pub bmin: Vec3,
+    pub bmax: Vec3,
+    // Has an edge on the tile border, so it may go on in the next tile
+    pub touches_tile_border: bool,
+}
+
+impl PolyMesh {
+    // Corners of polygon `ip` in world units
+    pub fn poly_world_verts(&self, ip: usize) -> Vec<Vec3> {
+        self.polys[ip]
+            .iter()
+            .take_while(|&&v| v != RC_MESH_NULL_IDX)
+            .map(|&v| {
+                let v = self.verts[v as usize];
+                self.bmin + Vec3::new(v.x * self.cs, v.y * self.ch, v.z * self.cs)
+            })
+            .collect()
+    }
+
+    // Area of polygon `ip` projected onto the xz plane
+    pub fn poly_area(&self, ip: usize) -> f32 {
+        let verts = self.poly_world_verts(ip);
+        let twice: f32 = (0..verts.len())
+            .map(|i| {
+                let (a, b) = (verts[i], verts[(i + 1) % verts.len()]);
+                a.x * b.z - b.x * a.z
+            })
+            .sum();
+        twice.abs() * 0.5
+    }
+
+    // Flood fill over polygon neighbours, largest island first
+    pub fn islands(&self) -> Vec<Island> {
+        let mut visited = vec![false; self.polys.len()];
+        let mut islands = Vec::new();
+        for seed in 0..self.polys.len() {
+            if visited[seed] {
+                continue;
+            }
+            visited[seed] = true;
+            let mut island = Island {
+                polys: Vec::new(),
+                area: 0.0,
+                bmin: Vec3::splat(f32::MAX),
+                bmax: Vec3::splat(f32::MIN),
+                touches_tile_border: false,
+            };
+            let mut stack = vec![seed];
+            while let Some(ip) = stack.pop() {
+                island.polys.push(ip);
+                island.area += self.poly_area(ip);
+                for v in self.poly_world_verts(ip) {
+                    island.bmin = island.bmin.min(v);
+                    island.bmax = island.bmax.max(v);
+                }
+                for &nei in &self.neighbours[ip] {
+                    if nei == RC_MESH_NULL_IDX {
+                        continue;
+                    }
+                    if nei & RC_MESH_PORTAL_FLAG != 0 {
+                        island.touches_tile_border = true;
+                    } else if !visited[nei as usize] {
+                        visited[nei as usize] = true;
+                        stack.push(nei as usize);
+                    }
+                }
+            }
+            island.polys.sort_unstable();
+            islands.push(island);
+        }
+        islands.sort_by(|a, b| b.area.total_cmp(&a.area));
+        islands
+    }
+
+    // Polygon under `point`, the one closest in height where several
+    // overlap, e.g. on a bridge over a floor
+    pub fn poly_at(&self, detail: Option<&PolyMeshDetail>, point: Vec3) -> Option<usize> {
+        let height = |ip: usize, verts: &[Vec3]| {
+            detail
+                .and_then(|d| d.height(ip, point))
+                .unwrap_or_else(|| verts.iter().map(|v| v.y).sum::<f32>() / verts.len() as f32)
+        };
+        (0..self.polys.len())
+            .filter_map(|ip| {
+                let verts = self.poly_world_verts(ip);
+                point_in_polygon(point, &verts).then(|| (ip, (height(ip, &verts) - point.y).abs()))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(ip, _)| ip)
+    }
+}
+
+impl BakedNavMesh {
+    // Remove the islands smaller than `min_area`, except those reaching the
+    // tile border. Islands joined by `connections` count as one, so a
+    // ledge reached by a jump is weighed with the floor it's reached from.
+    // Returns how many polygons were removed.
+    pub fn prune_islands_smaller_than(
+        &mut self,
+        min_area: f32,
+        connections: &[OffMeshConnection],
+    ) -> usize {
+        let (islands, group) = self.linked_islands(connections);
+        let mut area = vec![0.0; islands.len()];
+        let mut touches_tile_border = vec![false; islands.len()];
+        for (i, island) in islands.iter().enumerate() {
+            area[group[i]] += island.area;
+            touches_tile_border[group[i]] |= island.touches_tile_border;
+        }
+        let mut keep = vec![true; self.poly_mesh.polys.len()];
+        for (i, island) in islands.iter().enumerate() {
+            if area[group[i]] < min_area && !touches_tile_border[group[i]] {
+                for &ip in &island.polys {
+                    keep[ip] = false;
+                }
+            }
+        }
+        self.retain_polys(&keep)
+    }
+
+    // Remove every island but the one under `point` and those joined to it
+    // by `connections`. Returns how many polygons were removed, or None
+    // without touching the mesh when no polygon is under `point`.
+    pub fn keep_island_containing(
+        &mut self,
+        point: Vec3,
+        connections: &[OffMeshConnection],
+    ) -> Option<usize> {
+        let ip = self.poly_mesh.poly_at(Some(&self.detail_mesh), point)?;
+        let (islands, group) = self.linked_islands(connections);
+        let kept = (0..islands.len())
+            .find(|&i| islands[i].polys.binary_search(&ip).is_ok())
+            .map(|i| group[i]);
+        let mut keep = vec![false; self.poly_mesh.polys.len()];
+        for (i, island) in islands.iter().enumerate() {
+            if Some(group[i]) == kept {
+                for &p in &island.polys {
+                    keep[p] = true;
+                }
+            }
+        }
+        Some(self.retain_polys(&keep))
+    }
+
+    // The islands, and for each the first of the islands it's joined to by
+    // off-mesh connections with both ends on the mesh
+    fn linked_islands(&self, connections: &[OffMeshConnection]) -> (Vec<Island>, Vec<usize>) {
+        let islands = self.poly_mesh.islands();
+        let mut island_of = vec![0; self.poly_mesh.polys.len()];
+        for (i, island) in islands.iter().enumerate() {
+            for &ip in &island.polys {
+                island_of[ip] = i;
+            }
+        }
+
+        let mut group: Vec<usize> = (0..islands.len()).collect();
+        fn root(group: &mut [usize], mut i: usize) -> usize {
+            while group[i] != i {
+                group[i] = group[group[i]];
+                i = group[i];
+            }
+            i
+        }
+        let detail = Some(&self.detail_mesh);
+        for con in connections {
+            let start = self.poly_mesh.poly_at(detail, con.start);
+            let end = self.poly_mesh.poly_at(detail, con.end);
+            if let (Some(start), Some(end)) = (start, end) {
+                let a = root(&mut group, island_of[start]);
+                let b = root(&mut group, island_of[end]);
+                group[a.max(b)] = a.min(b);
+            }
+        }
+        for i in 0..group.len() {
+            group[i] = root(&mut group, i);
+        }
+        (islands, group)
+    }
+
+    // Drop the polygons whose `keep` entry is false, with their detail
+    // meshes and any vertices left unused. Returns how many were dropped.
+    fn retain_polys(&mut self, keep: &[bool]) -> usize {
+        let mesh = &mut self.poly_mesh;
+        let mut new_index = vec![RC_MESH_NULL_IDX; mesh.polys.len()];
+        let mut kept = 0;
+        for (ip, _) in keep.iter().enumerate().filter(|(_, &k)| k) {
+            new_index[ip] = kept;
+            kept += 1;
+        }
+        let removed = mesh.polys.len() - kept as usize;
+        if removed == 0 {
+            return 0;
+        }
+
+        // Vertices still used by a kept polygon, renumbered in order
+        let mut used = vec![false; mesh.verts.len()];
+        for (poly, _) in mesh.polys.iter().zip(keep).filter(|(_, &k)| k) {
+            for &v in poly.iter().take_while(|&&v| v != RC_MESH_NULL_IDX) {
+                used[v as usize] = true;
+            }
+        }
+        let mut new_vert = vec![RC_MESH_NULL_IDX; mesh.verts.len()];
+        let mut verts = Vec::new();
+        for (v, _) in used.iter().enumerate().filter(|(_, &u)| u) {
+            new_vert[v] = verts.len() as u16;
+            verts.push(mesh.verts[v]);
+        }
+        mesh.verts = verts;
+
+        retain_kept(&mut mesh.polys, keep);
+        retain_kept(&mut mesh.neighbours, keep);
+        retain_kept(&mut mesh.regs, keep);
+        retain_kept(&mut mesh.flags, keep);
+        retain_kept(&mut mesh.areas, keep);
+        for poly in &mut mesh.polys {
+            for v in poly.iter_mut().filter(|v| **v != RC_MESH_NULL_IDX) {
+                *v = new_vert[*v as usize];
+            }
+        }
+        // Edges to removed polygons become walls; portals stay portals
+        for neis in &mut mesh.neighbours {
+            for nei in neis.iter_mut() {
+                if *nei != RC_MESH_NULL_IDX && *nei & RC_MESH_PORTAL_FLAG == 0 {
+                    *nei = new_index[*nei as usize];
+                }
+            }
+        }
+
+        let detail = &mut self.detail_mesh;
+        if detail.meshes.len() == keep.len() {
+            let mut pruned = PolyMeshDetail::default();
+            for (m, _) in detail.meshes.iter().zip(keep).filter(|(_, &k)| k) {
+                let [vert_base, vert_count, tri_base, tri_count] = *m;
+                let verts = &detail.verts[vert_base as usize..(vert_base + vert_count) as usize];
+                let tris = &detail.tris[tri_base as usize..(tri_base + tri_count) as usize];
+                pruned.meshes.push([
+                    pruned.verts.len() as u32,
+                    vert_count,
+                    pruned.tris.len() as u32,
+                    tri_count,
+                ]);
+                pruned.verts.extend_from_slice(verts);
+                pruned.tris.extend_from_slice(tris);
+            }
+            *detail = pruned;
+        }
+        removed
+    }
+}
+
+fn retain_kept<T>(values: &mut Vec<T>, keep: &[bool]) {
+    let mut i = 0;
+    values.retain(|_| {
+        i += 1;
+        keep[i - 1]
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Unit squares at x = 0, 1 and 3, the first two sharing an edge
+    fn three_squares() -> BakedNavMesh {
+        let mut verts = Vec::new();
+        let mut polys = Vec::new();
+        let mut detail = PolyMeshDetail::default();
+        for (i, x) in [0.0, 1.0, 3.0].into_iter().enumerate() {
+            let corners = [(x, 0.0), (x, 1.0), (x + 1.0, 1.0), (x + 1.0, 0.0)]
+                .map(|(x, z)| Vec3::new(x, 0.0, z));
+            polys.push((0..4).map(|k| (i * 4 + k) as u16).collect());
+            verts.extend(corners);
+            detail.meshes.push([i as u32 * 4, 4, i as u32 * 2, 2]);
+            detail.verts.extend(corners);
+            detail.tris.extend([[0, 1, 2, 0], [0, 2, 3, 0]]);
+        }
+        let null = RC_MESH_NULL_IDX;
+        BakedNavMesh {
+            poly_mesh: PolyMesh {
+                verts,
+                polys,
+                neighbours: vec![
+                    vec![null, 1, null, null],
+                    vec![0, null, null, null],
+                    vec![null; 4],
+                ],
+                regs: vec![1, 1, 2],
+                flags: vec![1; 3],
+                areas: vec![63, 63, 5],
+                nvp: 4,
+                cs: 1.0,
+                ch: 1.0,
+                ..PolyMesh::default()
+            },
+            detail_mesh: detail,
+        }
+    }
+
+    #[test]
+    fn test_islands_are_found_and_pruned() {
+        let mut baked = three_squares();
+        let islands = baked.poly_mesh.islands();
+        assert_eq!(islands.len(), 2);
+        assert_eq!(
+            (islands[0].polys.clone(), islands[0].area),
+            (vec![0, 1], 2.0)
+        );
+        assert_eq!((islands[1].polys.clone(), islands[1].area), (vec![2], 1.0));
+        assert_eq!(islands[1].bmin, Vec3::new(3.0, 0.0, 0.0));
+
+        assert_eq!(baked.prune_islands_smaller_than(1.0, &[]), 0);
+        assert_eq!(baked.prune_islands_smaller_than(1.5, &[]), 1);
+        assert_eq!(baked.poly_mesh.polys.len(), 2);
+        assert_eq!(baked.poly_mesh.verts.len(), 8);
+        assert_eq!(baked.poly_mesh.areas, [63, 63]);
+        assert_eq!(baked.detail_mesh.meshes.len(), 2);
+        assert_eq!(baked.detail_mesh.tris.len(), 4);
+
+        // Keeping the lone square renumbers it to 0 and leaves no neighbours
+        let mut baked = three_squares();
+        assert_eq!(
+            baked.keep_island_containing(Vec3::new(5.0, 0.0, 0.5), &[]),
+            None
+        );
+        assert_eq!(
+            baked.keep_island_containing(Vec3::new(3.5, 0.0, 0.5), &[]),
+            Some(2)
+        );
+        assert_eq!(baked.poly_mesh.polys, [vec![0, 1, 2, 3]]);
+        assert_eq!(baked.poly_mesh.verts[0], Vec3::new(3.0, 0.0, 0.0));
+        assert_eq!(baked.poly_mesh.areas, [5]);
+        assert_eq!(baked.detail_mesh.meshes, [[0, 4, 0, 2]]);
+    }
+
+    #[test]
+    fn test_islands_joined_by_off_mesh_connections_are_kept() {
+        // A jump from the pair of squares to the lone one
+        let jumps = [OffMeshConnection {
+            start: Vec3::new(1.5, 0.0, 0.5),
+            end: Vec3::new(3.5, 0.0, 0.5),
+            radius: 0.1,
+            bidirectional: false,
+            area: 63,
+        }];
+
+        let mut baked = three_squares();
+        assert_eq!(baked.prune_islands_smaller_than(2.5, &jumps), 0);
+        assert_eq!(baked.poly_mesh.polys.len(), 3);
+        assert_eq!(baked.prune_islands_smaller_than(3.5, &jumps), 3);
+
+        // An end off the mesh links nothing
+        let mut baked = three_squares();
+        let off_mesh = OffMeshConnection {
+            end: Vec3::new(9.0, 0.0, 0.5),
+            ..jumps[0].clone()
+        };
+        assert_eq!(baked.prune_islands_smaller_than(1.5, &[off_mesh]), 1);
+
+        let mut baked = three_squares();
+        assert_eq!(
+            baked.keep_island_containing(Vec3::new(3.5, 0.0, 0.5), &jumps),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn test_islands_on_the_tile_border_are_kept() {
+        let mut baked = three_squares();
+        // The lone square's far edge is a portal to the next tile
+        baked.poly_mesh.neighbours[2][2] = RC_MESH_PORTAL_FLAG | 1;
+        let islands = baked.poly_mesh.islands();
+        assert!(!islands[0].touches_tile_border);
+        assert!(islands[1].touches_tile_border);
+
+        assert_eq!(baked.prune_islands_smaller_than(5.0, &[]), 2);
+        assert_eq!(baked.poly_mesh.areas, [5]);
+        // Pruning the other island leaves the portal alone
+        assert_eq!(
+            baked.poly_mesh.neighbours,
+            [vec![
+                RC_MESH_NULL_IDX,
+                RC_MESH_NULL_IDX,
+                RC_MESH_PORTAL_FLAG | 1,
+                RC_MESH_NULL_IDX
+            ]]
+        );
+    }
+}
@@ -0,0 +1,331 @@
+use glam::Vec3;
+use std::fmt::Write;
+
+// Just enough JSON for project and bake config files
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    // Keys in file order
+    Object(Vec<(String, Json)>),
+}
+
+// Through the f32's shortest decimal form, so 0.3 is written as 0.3.
+// JSON has no NaN or infinity, so those become null.
+pub(crate) fn number(x: f32) -> Json {
+    if !x.is_finite() {
+        return Json::Null;
+    }
+    Json::Number(x.to_string().parse().unwrap_or(0.0))
+}
+
+pub(crate) fn vec3(v: Vec3) -> Json {
+    Json::Array(v.to_array().map(number).to_vec())
+}
+
+impl Json {
+    pub(crate) fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn field(&self, key: &str) -> Result<&Json, String> {
+        self.get(key).ok_or_else(|| format!("missing {}", key))
+    }
+
+    // Elements of the array under `key`, none if it's missing
+    pub(crate) fn array(&self, key: &str) -> Result<&[Json], String> {
+        match self.get(key) {
+            None => Ok(&[]),
+            Some(Json::Array(items)) => Ok(items),
+            Some(_) => Err(format!("{} must be an array", key)),
+        }
+    }
+
+    pub(crate) fn as_f32(&self, what: &str) -> Result<f32, String> {
+        match self {
+            Json::Number(n) => Ok(*n as f32),
+            _ => Err(format!("{} must be a number", what)),
+        }
+    }
+
+    pub(crate) fn as_bool(&self, what: &str) -> Result<bool, String> {
+        match self {
+            Json::Bool(b) => Ok(*b),
+            _ => Err(format!("{} must be true or false", what)),
+        }
+    }
+
+    pub(crate) fn as_str(&self, what: &str) -> Result<&str, String> {
+        match self {
+            Json::String(s) => Ok(s),
+            _ => Err(format!("{} must be a string", what)),
+        }
+    }
+
+    pub(crate) fn as_floats(&self, what: &str) -> Result<Vec<f32>, String> {
+        match self {
+            Json::Array(items) => items.iter().map(|item| item.as_f32(what)).collect(),
+            _ => Err(format!("{} must be an array of numbers", what)),
+        }
+    }
+
+    pub(crate) fn as_vec3(&self, what: &str) -> Result<Vec3, String> {
+        match self.as_floats(what)?[..] {
+            [x, y, z] => Ok(Vec3::new(x, y, z)),
+            _ => Err(format!("{} must have 3 components", what)),
+        }
+    }
+
+    pub(crate) fn as_area(&self) -> Result<u8, String> {
+        match self {
+            Json::Number(n) if n.fract() == 0.0 && (0.0..=63.0).contains(n) => Ok(*n as u8),
+            _ => Err("area must be a whole number from 0 to 63".into()),
+        }
+    }
+
+    // Objects and arrays of containers go one entry per line; arrays of
+    // plain values stay on one line
+    pub(crate) fn write(&self, out: &mut String, indent: usize) {
+        match self {
+            Json::Null => out.push_str("null"),
+            Json::Bool(b) => {
+                let _ = write!(out, "{}", b);
+            }
+            Json::Number(n) if !n.is_finite() => out.push_str("null"),
+            Json::Number(n) => {
+                let _ = write!(out, "{}", n);
+            }
+            Json::String(s) => write_json_string(out, s),
+            Json::Array(items) => {
+                let nested = items
+                    .iter()
+                    .any(|item| matches!(item, Json::Array(_) | Json::Object(_)));
+                if !nested {
+                    out.push('[');
+                    for (i, item) in items.iter().enumerate() {
+                        if i > 0 {
+                            out.push_str(", ");
+                        }
+                        item.write(out, indent);
+                    }
+                    out.push(']');
+                    return;
+                }
+                out.push_str("[\n");
+                for (i, item) in items.iter().enumerate() {
+                    out.push_str(&"  ".repeat(indent + 1));
+                    item.write(out, indent + 1);
+                    out.push_str(if i + 1 < items.len() { ",\n" } else { "\n" });
+                }
+                out.push_str(&"  ".repeat(indent));
+                out.push(']');
+            }
+            Json::Object(fields) => {
+                if fields.is_empty() {
+                    out.push_str("{}");
+                    return;
+                }
+                out.push_str("{\n");
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    out.push_str(&"  ".repeat(indent + 1));
+                    write_json_string(out, key);
+                    out.push_str(": ");
+                    value.write(out, indent + 1);
+                    out.push_str(if i + 1 < fields.len() { ",\n" } else { "\n" });
+                }
+                out.push_str(&"  ".repeat(indent));
+                out.push('}');
+            }
+        }
+    }
+}
+
+fn write_json_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+pub(crate) struct JsonParser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> JsonParser<'a> {
+    pub(crate) fn parse(text: &'a str) -> Result<Json, String> {
+        let mut parser = Self { text, pos: 0 };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.pos < text.len() {
+            return Err(parser.error("trailing characters"));
+        }
+        Ok(value)
+    }
+
+    fn error(&self, msg: &str) -> String {
+        format!("offset {}: {}", self.pos, msg)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), String> {
+        self.skip_whitespace();
+        if self.peek() != Some(c) {
+            return Err(self.error(&format!("expected `{}`", c as char)));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    // After an element: true at the closing bracket, false after a comma
+    fn list_end(&mut self, close: u8) -> Result<bool, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b',') => {
+                self.pos += 1;
+                Ok(false)
+            }
+            Some(c) if c == close => {
+                self.pos += 1;
+                Ok(true)
+            }
+            _ => Err(self.error(&format!("expected `,` or `{}`", close as char))),
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'{') => {
+                self.pos += 1;
+                let mut fields = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some(b'}') {
+                    self.pos += 1;
+                    return Ok(Json::Object(fields));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let key = self.string()?;
+                    self.expect(b':')?;
+                    fields.push((key, self.value()?));
+                    if self.list_end(b'}')? {
+                        return Ok(Json::Object(fields));
+                    }
+                }
+            }
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some(b']') {
+                    self.pos += 1;
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    if self.list_end(b']')? {
+                        return Ok(Json::Array(items));
+                    }
+                }
+            }
+            Some(b'"') => self.string().map(Json::String),
+            Some(b't') => self.literal("true", Json::Bool(true)),
+            Some(b'f') => self.literal("false", Json::Bool(false)),
+            Some(b'n') => self.literal("null", Json::Null),
+            Some(_) => {
+                let start = self.pos;
+                while matches!(
+                    self.peek(),
+                    Some(b'0'..=b'9' | b'-' | b'+' | b'.' | b'e' | b'E')
+                ) {
+                    self.pos += 1;
+                }
+                self.text[start..self.pos]
+                    .parse()
+                    .map(Json::Number)
+                    .map_err(|_| self.error("expected a value"))
+            }
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn literal(&mut self, word: &str, value: Json) -> Result<Json, String> {
+        if !self.text[self.pos..].starts_with(word) {
+            return Err(self.error("expected a value"));
+        }
+        self.pos += word.len();
+        Ok(value)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect(b'"')?;
+        let mut out = String::new();
+        loop {
+            let c = self.text[self.pos..]
+                .chars()
+                .next()
+                .ok_or_else(|| self.error("unterminated string"))?;
+            self.pos += c.len_utf8();
+            match c {
+                '"' => return Ok(out),
+                '\\' => {
+                    let escape = self
+                        .peek()
+                        .ok_or_else(|| self.error("unterminated string"))?;
+                    self.pos += 1;
+                    out.push(match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'u' => {
+                            let hex = self
+                                .text
+                                .get(self.pos..self.pos + 4)
+                                .ok_or_else(|| self.error("short \\u escape"))?;
+                            self.pos += 4;
+                            // Surrogate pairs aren't needed for paths and names
+                            u32::from_str_radix(hex, 16)
+                                .ok()
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| self.error("invalid \\u escape"))?
+                        }
+                        _ => return Err(self.error("invalid escape")),
+                    });
+                }
+                c => out.push(c),
+            }
+        }
+    }
+}
@@ -0,0 +1,297 @@
+use glam::Vec3;
+
+use crate::nav_mesh::{NavMesh, PolyType};
+use crate::offmesh::OffMeshConnection;
+
+// Limits of the jumps the annotation pass looks for, in world units
+#[derive(Debug, Clone, PartialEq)]
+pub struct JumpLinkConfig {
+    pub agent_radius: f32,
+    pub agent_height: f32,
+    // Height differences up to this are steps, not jumps
+    pub agent_climb: f32,
+    // Deepest drop an agent may jump down
+    pub max_drop: f32,
+    // Widest horizontal gap an agent may jump across
+    pub max_gap: f32,
+    // Distance between probes along each boundary edge
+    pub sample_spacing: f32,
+    // Area id given to the proposed connections
+    pub area: u8,
+}
+
+impl Default for JumpLinkConfig {
+    fn default() -> Self {
+        Self {
+            agent_radius: 0.6,
+            agent_height: 2.0,
+            agent_climb: 0.9,
+            max_drop: 4.0,
+            max_gap: 3.0,
+            sample_spacing: 0.5,
+            area: 63,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JumpKind {
+    // Off a ledge onto lower ground right below it
+    Down,
+    // Over a gap onto ground at a similar or lower height
+    Across,
+}
+
+// A proposed off-mesh connection, for the user to accept or discard
+#[derive(Debug, Clone, PartialEq)]
+pub struct JumpLinkCandidate {
+    pub kind: JumpKind,
+    pub connection: OffMeshConnection,
+}
+
+// One boundary probe that found somewhere to land
+struct Landing {
+    kind: JumpKind,
+    start: Vec3,
+    end: Vec3,
+}
+
+// Scan the boundary edges of every tile and propose jump links off them,
+// like the annotation builder of the Recast demo. Runs of neighbouring
+// probes along an edge landing the same way become a single connection
+// at their middle. `is_clear` tells whether a segment is free of
+// geometry; the jump arc is approximated by a horizontal then a vertical
+// segment raised to half the agent height.
+pub fn build_jump_links(
+    nav_mesh: &NavMesh,
+    config: &JumpLinkConfig,
+    is_clear: impl Fn(Vec3, Vec3) -> bool,
+) -> Vec<JumpLinkCandidate> {
+    let spacing = config.sample_spacing.max(0.01);
+    let mut candidates: Vec<JumpLinkCandidate> = Vec::new();
+
+    for tile in nav_mesh.tiles() {
+        let data = &tile.data;
+        for poly in &data.polys {
+            if poly.poly_type != PolyType::Ground {
+                continue;
+            }
+            let verts = poly.vert_indices();
+            let n = verts.len();
+            for j in 0..n {
+                // Edges with a neighbour, in this tile or another, aren't
+                // boundaries
+                if poly.neis[j] != 0 {
+                    continue;
+                }
+                let va = data.verts[verts[j] as usize];
+                let vb = data.verts[verts[(j + 1) % n] as usize];
+                let edge = Vec3::new(vb.x - va.x, 0.0, vb.z - va.z);
+                let len = edge.length();
+                if len < spacing * 0.5 {
+                    continue;
+                }
+                // Polygons wind clockwise seen from above
+                let outward = Vec3::new(-edge.z, 0.0, edge.x) / len;
+
+                let samples = (len / spacing).ceil() as usize;
+                let mut run: Vec<Landing> = Vec::new();
+                for k in 0..samples {
+                    let start = va.lerp(vb, (k as f32 + 0.5) / samples as f32);
+                    let landing = probe(nav_mesh, config, start, outward, spacing, &is_clear);
+                    // A run ends at a failed probe or a change of kind or
+                    // landing height
+                    let continues = match (&landing, run.last()) {
+                        (Some(next), Some(last)) => {
+                            next.kind == last.kind
+                                && (next.end.y - last.end.y).abs() <= config.agent_climb
+                        }
+                        _ => true,
+                    };
+                    if !continues || landing.is_none() {
+                        push_run(&mut candidates, &mut run, config);
+                    }
+                    run.extend(landing);
+                }
+                push_run(&mut candidates, &mut run, config);
+            }
+        }
+    }
+    candidates
+}
+
+// Look for ground beyond the edge at `start`: first right below the ledge,
+// then further out across a gap
+fn probe(
+    nav_mesh: &NavMesh,
+    config: &JumpLinkConfig,
+    start: Vec3,
+    outward: Vec3,
+    spacing: f32,
+    is_clear: &impl Fn(Vec3, Vec3) -> bool,
+) -> Option<Landing> {
+    let lowest = start.y - config.max_drop;
+    let near = start + outward * config.agent_radius * 2.0;
+    if let Some(h) = ground_height(nav_mesh, near, lowest, start.y + config.agent_climb) {
+        // Ground within stepping height means the edge isn't a ledge
+        if start.y - h <= config.agent_climb {
+            return None;
+        }
+        let end = Vec3::new(near.x, h, near.z);
+        return trajectory_clear(config, start, end, is_clear).then_some(Landing {
+            kind: JumpKind::Down,
+            start,
+            end,
+        });
+    }
+
+    let mut d = config.agent_radius * 2.0 + spacing;
+    while d <= config.max_gap {
+        let far = start + outward * d;
+        if let Some(h) = ground_height(nav_mesh, far, lowest, start.y + config.agent_climb) {
+            let end = Vec3::new(far.x, h, far.z);
+            return trajectory_clear(config, start, end, is_clear).then_some(Landing {
+                kind: JumpKind::Across,
+                start,
+                end,
+            });
+        }
+        d += spacing;
+    }
+    None
+}
+
+// Highest navmesh surface at the XZ position of `pos` between `lo` and `hi`
+fn ground_height(nav_mesh: &NavMesh, pos: Vec3, lo: f32, hi: f32) -> Option<f32> {
+    let qmin = Vec3::new(pos.x, lo, pos.z);
+    let qmax = Vec3::new(pos.x, hi, pos.z);
+    nav_mesh
+        .tiles()
+        .flat_map(|tile| {
+            tile.query_polygons(qmin, qmax)
+                .into_iter()
+                .filter_map(move |ip| tile.poly_height(ip, pos))
+        })
+        .filter(|&h| h >= lo && h <= hi)
+        .max_by(f32::total_cmp)
+}
+
+fn trajectory_clear(
+    config: &JumpLinkConfig,
+    start: Vec3,
+    end: Vec3,
+    is_clear: &impl Fn(Vec3, Vec3) -> bool,
+) -> bool {
+    let up = Vec3::Y * config.agent_height * 0.5;
+    let apex = Vec3::new(end.x, start.y.max(end.y), end.z);
+    is_clear(start + up, apex + up) && is_clear(apex + up, end + up)
+}
+
+// Turn the middle of `run` into a candidate and clear it. Gaps are found
+// from both sides, so a jump across that mirrors an existing one is
+// dropped.
+fn push_run(
+    candidates: &mut Vec<JumpLinkCandidate>,
+    run: &mut Vec<Landing>,
+    config: &JumpLinkConfig,
+) {
+    let Some(middle) = run.get(run.len() / 2) else {
+        return;
+    };
+    let bidirectional = middle.kind == JumpKind::Across
+        && (middle.end.y - middle.start.y).abs() <= config.agent_climb;
+    let mirrored = bidirectional
+        && candidates.iter().any(|c| {
+            c.kind == JumpKind::Across
+                && c.connection.start.distance(middle.end) <= config.max_gap * 0.5
+                && c.connection.end.distance(middle.start) <= config.max_gap * 0.5
+        });
+    if !mirrored {
+        candidates.push(JumpLinkCandidate {
+            kind: middle.kind,
+            connection: OffMeshConnection {
+                start: middle.start,
+                end: middle.end,
+                radius: config.agent_radius,
+                bidirectional,
+                area: config.area,
+            },
+        });
+    }
+    run.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debug_draw::{PolyMesh, RC_MESH_NULL_IDX, RC_WALKABLE_AREA};
+    use crate::nav_mesh::{NavMeshCreateParams, NavMeshData, POLY_FLAG_WALK};
+
+    // Unlinked 4 units deep quads, each spanning x0..x1 at height y
+    fn quads(quads: &[(f32, f32, f32)]) -> NavMesh {
+        let n = RC_MESH_NULL_IDX;
+        let mut mesh = PolyMesh {
+            nvp: 6,
+            cs: 1.0,
+            ch: 1.0,
+            bmax: Vec3::new(20.0, 5.0, 4.0),
+            ..Default::default()
+        };
+        for (i, &(x0, x1, y)) in quads.iter().enumerate() {
+            mesh.verts.extend([
+                Vec3::new(x0, y, 0.0),
+                Vec3::new(x0, y, 4.0),
+                Vec3::new(x1, y, 4.0),
+                Vec3::new(x1, y, 0.0),
+            ]);
+            let v = (i * 4) as u16;
+            mesh.polys.push(vec![v, v + 1, v + 2, v + 3, n, n]);
+            mesh.neighbours.push(vec![n; 6]);
+        }
+        mesh.regs = vec![1; quads.len()];
+        mesh.flags = vec![POLY_FLAG_WALK; quads.len()];
+        mesh.areas = vec![RC_WALKABLE_AREA; quads.len()];
+        let params = NavMeshCreateParams::new(&mesh, None, 2.0, 0.5, 0.5);
+        NavMesh::single_tile(NavMeshData::build(&params).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_jump_down_off_ledge() {
+        // A platform 2 units above the floor next to it
+        let nav_mesh = quads(&[(0.0, 4.0, 2.0), (4.0, 10.0, 0.0)]);
+        let config = JumpLinkConfig::default();
+        let candidates = build_jump_links(&nav_mesh, &config, |_, _| true);
+
+        // One link down the shared side; the floor side has nothing below
+        assert_eq!(candidates.len(), 1);
+        let link = &candidates[0];
+        assert_eq!(link.kind, JumpKind::Down);
+        assert!(!link.connection.bidirectional);
+        assert_eq!(link.connection.start.x, 4.0);
+        assert!((link.connection.end.x - 5.2).abs() < 1e-4);
+        assert_eq!(link.connection.end.y, 0.0);
+
+        // Geometry in the way blocks it
+        assert!(build_jump_links(&nav_mesh, &config, |_, _| false).is_empty());
+    }
+
+    #[test]
+    fn test_jump_across_gap() {
+        let nav_mesh = quads(&[(0.0, 4.0, 0.0), (6.5, 10.0, 0.0)]);
+        let config = JumpLinkConfig::default();
+        let candidates = build_jump_links(&nav_mesh, &config, |_, _| true);
+
+        // Found from both sides, kept once as a two-way link
+        assert_eq!(candidates.len(), 1);
+        let link = &candidates[0];
+        assert_eq!(link.kind, JumpKind::Across);
+        assert!(link.connection.bidirectional);
+        assert!(link.connection.end.x >= 6.5);
+
+        let narrow = JumpLinkConfig {
+            max_gap: 2.0,
+            ..config
+        };
+        assert!(build_jump_links(&nav_mesh, &narrow, |_, _| true).is_empty());
+    }
+}
@@ -0,0 +1,56 @@
+// Navmesh building, runtime queries and the viewer, shared by the t1 and
+// navbake binaries
+pub mod area_mapping;
+pub mod bake_config;
+pub mod build_context;
+pub mod build_handle;
+pub mod bvh;
+pub mod chunky_tri_mesh;
+pub mod compact_heightfield;
+pub mod contours;
+pub mod convex_volume;
+pub mod crowd;
+pub mod debug_draw;
+pub mod debug_draw_b;
+#[cfg(feature = "cdylib")]
+pub mod ffi;
+pub mod file_dialog;
+pub mod filter;
+pub mod frustum;
+pub mod gltf_loader;
+pub mod headless;
+pub mod heightfield;
+pub mod heightfield_layers;
+pub mod islands;
+mod json;
+pub mod jump_links;
+pub mod local_boundary;
+pub mod mesh_cleanup;
+pub mod mesh_import;
+pub mod mesh_stats;
+pub mod nav_mesh;
+pub mod nav_mesh_bin;
+pub mod nav_mesh_builder;
+pub mod nav_mesh_query;
+pub mod navmesh_build;
+pub mod obj_loader;
+pub mod obstacle_avoidance;
+pub mod offmesh;
+pub mod path_corridor;
+pub mod ply_loader;
+pub mod png;
+pub mod poly_mesh;
+pub mod poly_mesh_detail;
+pub mod project;
+pub mod proximity_grid;
+pub mod query_filter;
+pub mod recent_files;
+pub mod regions;
+pub mod scene;
+pub mod stl_loader;
+pub mod testgen;
+pub mod tile_cache;
+pub mod tile_compressor;
+pub mod viewer;
+#[cfg(feature = "wgpu")]
+pub mod wgpu_draw;
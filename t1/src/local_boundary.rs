@@ -0,0 +1,183 @@
+use glam::Vec3;
+
+use crate::nav_mesh::{distance_pt_seg_sqr_2d, NavMeshError, PolyRef};
+use crate::nav_mesh_query::NavMeshQuery;
+use crate::obstacle_avoidance::ObstacleAvoidanceQuery;
+use crate::query_filter::PolyFilter;
+
+// Nearest wall segments kept, as in Detour
+const MAX_LOCAL_SEGS: usize = 8;
+// Share of the query range an agent may move before the cache is refreshed
+const UPDATE_THRESHOLD: f32 = 0.25;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundarySegment {
+    pub p: Vec3,
+    pub q: Vec3,
+    // Squared distance from the centre on the XZ plane
+    pub dist_sqr: f32,
+}
+
+// The navmesh walls around an agent, cached so the avoidance step doesn't
+// query the navmesh every frame. Like Detour's dtLocalBoundary.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalBoundary {
+    center: Vec3,
+    segments: Vec<BoundarySegment>,
+    polys: Vec<PolyRef>,
+}
+
+impl Default for LocalBoundary {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LocalBoundary {
+    pub fn new() -> Self {
+        Self {
+            center: Vec3::splat(f32::MAX),
+            segments: Vec::new(),
+            polys: Vec::new(),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    pub fn center(&self) -> Vec3 {
+        self.center
+    }
+
+    // Nearest first
+    pub fn segments(&self) -> &[BoundarySegment] {
+        &self.segments
+    }
+
+    // Collect the walls within `collision_query_range` of `pos`, flooding
+    // out from `r` through the flat neighbourhood around it
+    pub fn update(
+        &mut self,
+        r: PolyRef,
+        pos: Vec3,
+        collision_query_range: f32,
+        query: &mut NavMeshQuery,
+        filter: &impl PolyFilter,
+    ) -> Result<(), NavMeshError> {
+        self.reset();
+        self.center = pos;
+        self.polys = query.find_local_neighbourhood(r, pos, collision_query_range, filter)?;
+
+        let range_sqr = collision_query_range * collision_query_range;
+        for &poly in &self.polys {
+            for (p, q) in query.poly_wall_segments(poly, filter)? {
+                let (dist_sqr, _) = distance_pt_seg_sqr_2d(pos, p, q);
+                if dist_sqr <= range_sqr {
+                    self.segments.push(BoundarySegment { p, q, dist_sqr });
+                }
+            }
+        }
+        self.segments
+            .sort_by(|a, b| a.dist_sqr.total_cmp(&b.dist_sqr));
+        self.segments.truncate(MAX_LOCAL_SEGS);
+        Ok(())
+    }
+
+    // Whether every polygon the walls were taken from still passes `filter`
+    pub fn is_valid(&self, query: &NavMeshQuery, filter: &impl PolyFilter) -> bool {
+        !self.polys.is_empty()
+            && self
+                .polys
+                .iter()
+                .all(|&r| query.is_valid_poly_ref(r, filter))
+    }
+
+    // Whether an agent at `pos` has moved far enough from the centre, or
+    // the navmesh changed under it, to call `update` again
+    pub fn needs_update(
+        &self,
+        pos: Vec3,
+        collision_query_range: f32,
+        query: &NavMeshQuery,
+        filter: &impl PolyFilter,
+    ) -> bool {
+        let (dx, dz) = (pos.x - self.center.x, pos.z - self.center.z);
+        let threshold = collision_query_range * UPDATE_THRESHOLD;
+        dx * dx + dz * dz > threshold * threshold || !self.is_valid(query, filter)
+    }
+
+    // Add the walls facing an agent at `pos` to `avoidance`. Walls it has
+    // already crossed, such as those of a polygon behind it, are skipped.
+    pub fn add_to_avoidance(&self, pos: Vec3, avoidance: &mut ObstacleAvoidanceQuery) {
+        for seg in &self.segments {
+            let d = seg.q - seg.p;
+            let outward = Vec3::new(-d.z, 0.0, d.x);
+            if (pos - seg.p).dot(outward) <= 0.0 {
+                avoidance.add_segment(seg.p, seg.q);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debug_draw::{PolyMesh, RC_MESH_NULL_IDX, RC_WALKABLE_AREA};
+    use crate::nav_mesh::{NavMesh, NavMeshCreateParams, NavMeshData, POLY_FLAG_WALK};
+    use crate::query_filter::DefaultFilter;
+
+    // Two 4x4 quads side by side along +X
+    fn strip() -> NavMesh {
+        let n = RC_MESH_NULL_IDX;
+        let mut mesh = PolyMesh {
+            nvp: 6,
+            cs: 1.0,
+            ch: 1.0,
+            bmax: Vec3::new(8.0, 1.0, 4.0),
+            ..Default::default()
+        };
+        for x in [0.0, 4.0] {
+            let v = mesh.verts.len() as u16;
+            mesh.verts.extend([
+                Vec3::new(x, 0.0, 0.0),
+                Vec3::new(x, 0.0, 4.0),
+                Vec3::new(x + 4.0, 0.0, 4.0),
+                Vec3::new(x + 4.0, 0.0, 0.0),
+            ]);
+            mesh.polys.push(vec![v, v + 1, v + 2, v + 3, n, n]);
+        }
+        mesh.neighbours = vec![vec![n, n, 1, n, n, n], vec![0, n, n, n, n, n]];
+        mesh.regs = vec![1; 2];
+        mesh.flags = vec![POLY_FLAG_WALK; 2];
+        mesh.areas = vec![RC_WALKABLE_AREA; 2];
+        let params = NavMeshCreateParams::new(&mesh, None, 2.0, 0.5, 0.5);
+        NavMesh::single_tile(NavMeshData::build(&params).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_update_keeps_nearby_walls() {
+        let nav_mesh = strip();
+        let base = nav_mesh.poly_ref_base(nav_mesh.tiles().next().unwrap());
+        let mut query = NavMeshQuery::new(&nav_mesh, 64);
+        let filter = DefaultFilter;
+
+        // Near the shared edge: the walls at z=0 of both quads are in
+        // range, the far ends and the z=4 side aren't
+        let pos = Vec3::new(4.5, 0.0, 1.0);
+        let mut boundary = LocalBoundary::new();
+        boundary
+            .update(base, pos, 2.0, &mut query, &filter)
+            .unwrap();
+        let segs = boundary.segments();
+        assert_eq!(segs.len(), 2);
+        assert!(segs.iter().all(|s| s.p.z == 0.0 && s.q.z == 0.0));
+        assert!((segs[0].dist_sqr - 1.0).abs() < 1e-4);
+        assert!(!boundary.needs_update(pos, 2.0, &query, &filter));
+        assert!(boundary.needs_update(pos + Vec3::X, 2.0, &query, &filter));
+
+        let mut avoidance = ObstacleAvoidanceQuery::new();
+        boundary.add_to_avoidance(pos, &mut avoidance);
+        assert_eq!(avoidance.segments().len(), 2);
+    }
+}
@@ -1,7 +1,26 @@
-mod debug_draw_b;
-mod obj_loader;
-mod viewer;
+#[cfg(not(target_arch = "wasm32"))]
+use t1::headless;
+use t1::viewer;
 
+#[cfg(not(target_arch = "wasm32"))]
 fn main() -> Result<(), eframe::Error> {
-    viewer::run()
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    // Subcommands run without opening a window; the viewer is the default
+    let result = match args.first().map(String::as_str) {
+        Some("bake") => headless::run_cli(&args[1..]),
+        Some("analyze") => headless::run_analyze_cli(&args[1..]),
+        _ => return viewer::run(),
+    };
+    if let Err(err) = result {
+        eprintln!("{} failed: {}", args[0], err);
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+// The browser build only has the viewer, started in index.html's canvas
+#[cfg(target_arch = "wasm32")]
+fn main() {
+    viewer::run_web("viewer_canvas");
 }
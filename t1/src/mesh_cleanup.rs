@@ -0,0 +1,140 @@
+use glam::Vec3;
+use std::collections::HashMap;
+
+use crate::debug_draw_b::InputMesh;
+use crate::obj_loader::is_degenerate_triangle;
+
+// Weld distance used on import: far below any sensible cell size, but
+// enough to close the cracks left by exporters that split vertices per face
+pub const IMPORT_WELD_TOLERANCE: f32 = 1e-4;
+
+impl InputMesh {
+    // Merge vertices closer than `tolerance` into the first of them and
+    // drop the rest, remapping the triangles. A tolerance of zero only
+    // merges exact duplicates. Returns the number of vertices removed.
+    pub fn weld(&mut self, tolerance: f32) -> usize {
+        let tolerance = tolerance.max(0.0);
+        // Exact duplicates always land in the same cell, so one cell is
+        // enough to search then
+        let (cell_size, reach) = if tolerance > 0.0 {
+            (tolerance, 1)
+        } else {
+            (1.0, 0)
+        };
+        let cell_of = |v: Vec3| (v / cell_size).floor().as_i64vec3().to_array();
+
+        let mut cells: HashMap<[i64; 3], Vec<i32>> = HashMap::new();
+        let mut welded: Vec<Vec3> = Vec::with_capacity(self.verts.len());
+        let mut remap = Vec::with_capacity(self.verts.len());
+        for &v in &self.verts {
+            let [x, y, z] = cell_of(v);
+            let mut found = None;
+            'search: for dz in -reach..=reach {
+                for dy in -reach..=reach {
+                    for dx in -reach..=reach {
+                        let Some(cell) = cells.get(&[x + dx, y + dy, z + dz]) else {
+                            continue;
+                        };
+                        if let Some(&i) = cell
+                            .iter()
+                            .find(|&&i| welded[i as usize].distance(v) <= tolerance)
+                        {
+                            found = Some(i);
+                            break 'search;
+                        }
+                    }
+                }
+            }
+
+            let index = found.unwrap_or_else(|| {
+                welded.push(v);
+                let index = welded.len() as i32 - 1;
+                cells.entry([x, y, z]).or_default().push(index);
+                index
+            });
+            remap.push(index);
+        }
+
+        let removed = self.verts.len() - welded.len();
+        self.verts = welded;
+        for index in &mut self.tris {
+            *index = remap[*index as usize];
+        }
+        removed
+    }
+
+    // Drop triangles that repeat a vertex or have (nearly) no area, keeping
+    // normals and areas parallel. Returns the number of triangles removed.
+    pub fn remove_degenerates(&mut self) -> usize {
+        let before = self.tri_count();
+        let has_normals = self.normals.len() == before;
+        let has_areas = self.areas.len() == before;
+
+        let mut kept = 0;
+        for t in 0..before {
+            let [a, b, c] = [0, 1, 2].map(|k| self.tris[t * 3 + k]);
+            let degenerate = a == b
+                || b == c
+                || a == c
+                || is_degenerate_triangle(
+                    &self.verts[a as usize],
+                    &self.verts[b as usize],
+                    &self.verts[c as usize],
+                );
+            if degenerate {
+                continue;
+            }
+            self.tris.copy_within(t * 3..t * 3 + 3, kept * 3);
+            if has_normals {
+                self.normals[kept] = self.normals[t];
+            }
+            if has_areas {
+                self.areas[kept] = self.areas[t];
+            }
+            kept += 1;
+        }
+
+        self.tris.truncate(kept * 3);
+        if has_normals {
+            self.normals.truncate(kept);
+        }
+        if has_areas {
+            self.areas.truncate(kept);
+        }
+        before - kept
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_weld_then_remove_degenerates() {
+        // A quad exported as two separate triangles with a little noise on
+        // the shared corners, plus a sliver that collapses once welded
+        let mut mesh = InputMesh::new();
+        mesh.verts = vec![
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(1.0, 0.0, 1.0),
+            Vec3::new(0.00001, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 1.00002),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.00001),
+        ];
+        mesh.tris = vec![0, 1, 2, 3, 4, 5, 0, 6, 1];
+        mesh.areas = vec![1, 2, 3];
+        mesh.compute_normals();
+
+        assert_eq!(mesh.weld(0.0), 0);
+        assert_eq!(mesh.weld(IMPORT_WELD_TOLERANCE), 3);
+        assert_eq!(mesh.verts.len(), 4);
+        assert_eq!(mesh.tris, vec![0, 1, 2, 0, 2, 3, 0, 1, 1]);
+
+        assert_eq!(mesh.remove_degenerates(), 1);
+        assert_eq!(mesh.tris, vec![0, 1, 2, 0, 2, 3]);
+        assert_eq!(mesh.areas, vec![1, 2]);
+        assert!(mesh.validate().is_ok());
+    }
+}
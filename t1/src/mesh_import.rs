@@ -0,0 +1,390 @@
+use glam::Vec3;
+use std::fmt;
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+
+use crate::debug_draw_b::{InputMesh, InputMeshError};
+use crate::gltf_loader::{self, GltfLoadError};
+use crate::mesh_cleanup::IMPORT_WELD_TOLERANCE;
+use crate::obj_loader::{self, ObjEvent, ObjLoadError};
+use crate::ply_loader::{self, PlyLoadError};
+use crate::stl_loader::{self, StlLoadError};
+
+#[derive(Debug)]
+pub enum MeshImportError {
+    Obj(ObjLoadError),
+    Gltf(GltfLoadError),
+    Stl(StlLoadError),
+    Ply(PlyLoadError),
+    // No importer handles the file's extension
+    UnsupportedFormat(String),
+    // The file loaded but its triangles don't hold together
+    InvalidMesh(InputMeshError),
+    Cancelled,
+}
+
+impl fmt::Display for MeshImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MeshImportError::Obj(err) => write!(f, "{}", err),
+            MeshImportError::Gltf(err) => write!(f, "{}", err),
+            MeshImportError::Stl(err) => write!(f, "{}", err),
+            MeshImportError::Ply(err) => write!(f, "{}", err),
+            MeshImportError::UnsupportedFormat(path) => {
+                write!(f, "unsupported mesh format: {}", path)
+            }
+            MeshImportError::InvalidMesh(err) => write!(f, "invalid mesh: {}", err),
+            MeshImportError::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
+
+impl From<ObjLoadError> for MeshImportError {
+    fn from(error: ObjLoadError) -> Self {
+        match error {
+            ObjLoadError::Cancelled => MeshImportError::Cancelled,
+            error => MeshImportError::Obj(error),
+        }
+    }
+}
+
+impl From<GltfLoadError> for MeshImportError {
+    fn from(error: GltfLoadError) -> Self {
+        MeshImportError::Gltf(error)
+    }
+}
+
+impl From<StlLoadError> for MeshImportError {
+    fn from(error: StlLoadError) -> Self {
+        MeshImportError::Stl(error)
+    }
+}
+
+impl From<PlyLoadError> for MeshImportError {
+    fn from(error: PlyLoadError) -> Self {
+        MeshImportError::Ply(error)
+    }
+}
+
+// A mesh file format that can be loaded into an InputMesh
+pub trait MeshImporter: Sync {
+    // Shown in file pickers, e.g. "OBJ files"
+    fn name(&self) -> &'static str;
+
+    // Lowercase extensions without the dot
+    fn extensions(&self) -> &'static [&'static str];
+
+    // Load `path`, reporting the fraction done (0.0..=1.0) and giving up
+    // with Cancelled once `cancel` is set. Formats that can't report
+    // progress only do so when finished. Problems that didn't stop the
+    // load are returned as warnings.
+    fn load(
+        &self,
+        path: &Path,
+        progress: &dyn Fn(f32),
+        cancel: &AtomicBool,
+    ) -> Result<(InputMesh, Vec<String>), MeshImportError>;
+
+    // Same as `load` for a file already in memory, e.g. one read by the
+    // browser, which has no paths to open
+    fn load_bytes(
+        &self,
+        bytes: &[u8],
+        progress: &dyn Fn(f32),
+        cancel: &AtomicBool,
+    ) -> Result<(InputMesh, Vec<String>), MeshImportError>;
+}
+
+pub struct ObjImporter;
+pub struct GltfImporter;
+pub struct StlImporter;
+pub struct PlyImporter;
+
+pub const IMPORTERS: [&dyn MeshImporter; 4] =
+    [&ObjImporter, &GltfImporter, &StlImporter, &PlyImporter];
+
+// The importer registered for `path`'s extension
+pub fn importer_for(path: &Path) -> Option<&'static dyn MeshImporter> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    IMPORTERS
+        .into_iter()
+        .find(|importer| importer.extensions().contains(&extension.as_str()))
+}
+
+// Every extension some importer handles
+pub fn supported_extensions() -> Vec<&'static str> {
+    IMPORTERS
+        .iter()
+        .flat_map(|importer| importer.extensions())
+        .copied()
+        .collect()
+}
+
+// Load `path` with the importer for its extension, then weld split
+// vertices and drop degenerate triangles
+pub fn import_mesh(
+    path: &Path,
+    progress: &dyn Fn(f32),
+    cancel: &AtomicBool,
+) -> Result<(InputMesh, Vec<String>), MeshImportError> {
+    let loaded = supported_importer(path)?.load(path, progress, cancel)?;
+    clean_up(loaded)
+}
+
+// Same as import_mesh for `bytes` read from `path`, which only picks the
+// importer
+pub fn import_mesh_bytes(
+    path: &Path,
+    bytes: &[u8],
+    progress: &dyn Fn(f32),
+    cancel: &AtomicBool,
+) -> Result<(InputMesh, Vec<String>), MeshImportError> {
+    let loaded = supported_importer(path)?.load_bytes(bytes, progress, cancel)?;
+    clean_up(loaded)
+}
+
+fn supported_importer(path: &Path) -> Result<&'static dyn MeshImporter, MeshImportError> {
+    importer_for(path).ok_or_else(|| MeshImportError::UnsupportedFormat(path.display().to_string()))
+}
+
+// Weld and drop degenerates, then validate once so the viewer can draw the
+// mesh every frame without checking it again
+fn clean_up(
+    (mut mesh, warnings): (InputMesh, Vec<String>),
+) -> Result<(InputMesh, Vec<String>), MeshImportError> {
+    mesh.weld(IMPORT_WELD_TOLERANCE);
+    mesh.remove_degenerates();
+    mesh.validate().map_err(MeshImportError::InvalidMesh)?;
+    Ok((mesh, warnings))
+}
+
+impl MeshImporter for ObjImporter {
+    fn name(&self) -> &'static str {
+        "OBJ files"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["obj"]
+    }
+
+    fn load(
+        &self,
+        path: &Path,
+        progress: &dyn Fn(f32),
+        cancel: &AtomicBool,
+    ) -> Result<(InputMesh, Vec<String>), MeshImportError> {
+        Ok(stream_obj_to_input_mesh(path, progress, cancel)?)
+    }
+
+    fn load_bytes(
+        &self,
+        bytes: &[u8],
+        progress: &dyn Fn(f32),
+        cancel: &AtomicBool,
+    ) -> Result<(InputMesh, Vec<String>), MeshImportError> {
+        Ok(obj_bytes_to_input_mesh(bytes, progress, cancel)?)
+    }
+}
+
+impl MeshImporter for GltfImporter {
+    fn name(&self) -> &'static str {
+        "glTF files"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["gltf", "glb"]
+    }
+
+    fn load(
+        &self,
+        path: &Path,
+        progress: &dyn Fn(f32),
+        _cancel: &AtomicBool,
+    ) -> Result<(InputMesh, Vec<String>), MeshImportError> {
+        let mesh = gltf_loader::load_gltf(path)?;
+        progress(1.0);
+        Ok((mesh, Vec::new()))
+    }
+
+    fn load_bytes(
+        &self,
+        bytes: &[u8],
+        progress: &dyn Fn(f32),
+        _cancel: &AtomicBool,
+    ) -> Result<(InputMesh, Vec<String>), MeshImportError> {
+        let mesh = gltf_loader::load_gltf_slice(bytes)?;
+        progress(1.0);
+        Ok((mesh, Vec::new()))
+    }
+}
+
+impl MeshImporter for StlImporter {
+    fn name(&self) -> &'static str {
+        "STL files"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["stl"]
+    }
+
+    fn load(
+        &self,
+        path: &Path,
+        progress: &dyn Fn(f32),
+        _cancel: &AtomicBool,
+    ) -> Result<(InputMesh, Vec<String>), MeshImportError> {
+        let mesh = stl_loader::load_stl(path)?;
+        progress(1.0);
+        Ok((mesh, Vec::new()))
+    }
+
+    fn load_bytes(
+        &self,
+        bytes: &[u8],
+        progress: &dyn Fn(f32),
+        _cancel: &AtomicBool,
+    ) -> Result<(InputMesh, Vec<String>), MeshImportError> {
+        let mesh = stl_loader::parse_stl(bytes)?;
+        progress(1.0);
+        Ok((mesh, Vec::new()))
+    }
+}
+
+impl MeshImporter for PlyImporter {
+    fn name(&self) -> &'static str {
+        "PLY files"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["ply"]
+    }
+
+    fn load(
+        &self,
+        path: &Path,
+        progress: &dyn Fn(f32),
+        _cancel: &AtomicBool,
+    ) -> Result<(InputMesh, Vec<String>), MeshImportError> {
+        let mesh = ply_loader::load_ply(path)?;
+        progress(1.0);
+        Ok((mesh, Vec::new()))
+    }
+
+    fn load_bytes(
+        &self,
+        bytes: &[u8],
+        progress: &dyn Fn(f32),
+        _cancel: &AtomicBool,
+    ) -> Result<(InputMesh, Vec<String>), MeshImportError> {
+        let mesh = ply_loader::parse_ply(bytes)?;
+        progress(1.0);
+        Ok((mesh, Vec::new()))
+    }
+}
+
+// Build the InputMesh directly from parser events so the intermediate
+// ObjData never has to exist alongside it. The file is memory-mapped and
+// pre-scanned so the mesh is allocated once. Lines that fail to parse are
+// skipped and reported as warnings.
+fn stream_obj_to_input_mesh(
+    path: &Path,
+    progress: impl Fn(f32),
+    cancel: &AtomicBool,
+) -> Result<(InputMesh, Vec<String>), ObjLoadError> {
+    let map = obj_loader::map_obj_file(path)?;
+    obj_bytes_to_input_mesh(&map, progress, cancel)
+}
+
+fn obj_bytes_to_input_mesh(
+    bytes: &[u8],
+    progress: impl Fn(f32),
+    cancel: &AtomicBool,
+) -> Result<(InputMesh, Vec<String>), ObjLoadError> {
+    let capacity = obj_loader::prescan_obj(bytes);
+    let mut mesh = InputMesh::new();
+    mesh.verts.reserve(capacity.vertices);
+    mesh.tris.reserve(capacity.triangles * 3);
+    let mut dangling = 0;
+
+    let warnings =
+        obj_loader::stream_obj_bytes_lenient(bytes, progress, cancel, |event| match event {
+            ObjEvent::Vertex(v) => mesh.verts.push(v),
+            ObjEvent::Face(face) => {
+                // Triangulate as a fan from the first vertex, with 0-based indices
+                for i in 1..face.len().saturating_sub(1) {
+                    let tri = [
+                        face[0].vertex as i32 - 1,
+                        face[i].vertex as i32 - 1,
+                        face[i + 1].vertex as i32 - 1,
+                    ];
+                    // Faces may only reference vertices that were already read
+                    if tri.iter().any(|&v| v < 0 || v as usize >= mesh.verts.len()) {
+                        dangling += 1;
+                    } else if !is_degenerate_tri(&mesh.verts, &tri) {
+                        mesh.tris.extend_from_slice(&tri);
+                    }
+                }
+            }
+            _ => {}
+        })?;
+
+    let mut warnings: Vec<String> = warnings.iter().map(|w| w.to_string()).collect();
+    if dangling > 0 {
+        warnings.push(format!(
+            "{} triangle(s) referenced undefined vertices and were skipped",
+            dangling
+        ));
+    }
+
+    mesh.compute_normals();
+
+    Ok((mesh, warnings))
+}
+
+// Same rule as ObjData::triangulate, for indices already known to be in range
+fn is_degenerate_tri(verts: &[Vec3], tri: &[i32; 3]) -> bool {
+    let [a, b, c] = *tri;
+    if a == b || b == c || a == c {
+        return true;
+    }
+    obj_loader::is_degenerate_triangle(&verts[a as usize], &verts[b as usize], &verts[c as usize])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::write;
+
+    #[test]
+    fn test_import_dispatches_on_extension() {
+        assert!(importer_for(Path::new("level.OBJ")).is_some());
+        assert_eq!(
+            importer_for(Path::new("scene.glb")).map(|i| i.name()),
+            Some("glTF files")
+        );
+        assert!(importer_for(Path::new("scene.fbx")).is_none());
+        assert!(supported_extensions().contains(&"ply"));
+
+        let cancel = AtomicBool::new(false);
+        let file = tempfile::Builder::new().suffix(".stl").tempfile().unwrap();
+        write(
+            file.path(),
+            "solid t\nfacet normal 0 1 0\nouter loop\n\
+             vertex 0 0 0\nvertex 0 0 1\nvertex 1 0 0\nendloop\nendfacet\nendsolid t\n",
+        )
+        .unwrap();
+        let (mesh, warnings) = import_mesh(file.path(), &|_| {}, &cancel).unwrap();
+        assert_eq!(mesh.tri_count(), 1);
+        assert!(warnings.is_empty());
+
+        assert!(matches!(
+            import_mesh(Path::new("scene.fbx"), &|_| {}, &cancel),
+            Err(MeshImportError::UnsupportedFormat(_))
+        ));
+
+        // Files read into memory go through the same importers and clean-up
+        let obj = b"v 0 0 0\nv 0 0 1\nv 1 0 0\nv 1 0 0\nf 1 2 3\nf 1 3 4\n";
+        let (mesh, _) = import_mesh_bytes(Path::new("picked.obj"), obj, &|_| {}, &cancel).unwrap();
+        assert_eq!((mesh.verts.len(), mesh.tri_count()), (3, 1));
+    }
+}
@@ -0,0 +1,318 @@
+use glam::Vec3;
+
+use crate::debug_draw::RC_NULL_AREA;
+use crate::debug_draw_b::{obj_to_input_mesh, InputMesh, InputMeshError};
+use crate::json::{number, vec3, Json};
+use crate::navmesh_build::mark_walkable_triangles;
+use crate::obj_loader::ObjData;
+
+// Triangles with less area than this count as degenerate
+const DEGENERATE_AREA: f32 = 1e-6;
+
+// Geometry quality summary used to judge a mesh before navmesh baking
+#[derive(Debug, Clone, Default)]
+pub struct MeshStats {
+    pub vertex_count: usize,
+    pub triangle_count: usize,
+    pub bounds_min: Vec3,
+    pub bounds_max: Vec3,
+    pub total_area: f32,
+    pub min_area: f32,
+    pub max_area: f32,
+    // Smallest interior angle over all non-degenerate triangles
+    pub min_angle_deg: f32,
+    // Longest edge divided by shortest altitude, averaged over non-degenerate triangles
+    pub mean_aspect_ratio: f32,
+    pub degenerate_count: usize,
+    // Area of triangles passing the slope test; only filled by InputMesh::compute_stats
+    pub walkable_area: f32,
+}
+
+impl MeshStats {
+    pub fn walkable_percentage(&self) -> f32 {
+        if self.total_area > 0.0 {
+            self.walkable_area / self.total_area * 100.0
+        } else {
+            0.0
+        }
+    }
+}
+
+impl InputMesh {
+    // Quality statistics plus the surface area walkable at the given slope
+    // limit, classified by mark_walkable_triangles like the build
+    pub fn compute_stats(&self, walkable_slope_angle: f32) -> MeshStats {
+        let mut stats = compute_mesh_stats(self);
+        let areas = mark_walkable_triangles(walkable_slope_angle, self);
+
+        for (tri, &area) in self.tris.chunks_exact(3).zip(&areas) {
+            let a = self.verts[tri[0] as usize];
+            let b = self.verts[tri[1] as usize];
+            let c = self.verts[tri[2] as usize];
+
+            let cross = (b - a).cross(c - a);
+            let double_area = cross.length();
+            if double_area * 0.5 > DEGENERATE_AREA && area != RC_NULL_AREA {
+                stats.walkable_area += double_area * 0.5;
+            }
+        }
+
+        stats
+    }
+}
+
+// Walkability report for an OBJ model, shared by the `analyze` subcommand
+#[derive(Debug, Clone)]
+pub struct MeshReport {
+    pub walkable_slope_angle: f32,
+    pub stats: MeshStats,
+    // Dropped triangles as (face index, 1-based OBJ vertex indices)
+    pub degenerate_triangles: Vec<(usize, [usize; 3])>,
+}
+
+// Fails on faces referencing vertices the file doesn't define
+pub fn analyze_obj(obj: &ObjData, walkable_slope_angle: f32) -> Result<MeshReport, InputMeshError> {
+    // Degenerate triangles never reach the InputMesh, so count them on the OBJ
+    let degenerate_triangles = obj.degenerate_triangles();
+    let mut stats = obj_to_input_mesh(obj, None)?.compute_stats(walkable_slope_angle);
+    stats.degenerate_count += degenerate_triangles.len();
+    stats.triangle_count += degenerate_triangles.len();
+
+    Ok(MeshReport {
+        walkable_slope_angle,
+        stats,
+        degenerate_triangles,
+    })
+}
+
+impl MeshReport {
+    // Values that aren't finite, e.g. from degenerate input, are null
+    pub fn to_json(&self) -> String {
+        let stats = &self.stats;
+        let count = |n: usize| Json::Number(n as f64);
+        let degenerate = self
+            .degenerate_triangles
+            .iter()
+            .map(|&(face, tri)| {
+                Json::Object(vec![
+                    ("face".into(), count(face)),
+                    ("vertices".into(), Json::Array(tri.map(count).to_vec())),
+                ])
+            })
+            .collect();
+        let report = Json::Object(vec![
+            (
+                "walkable_slope_angle".into(),
+                number(self.walkable_slope_angle),
+            ),
+            ("vertex_count".into(), count(stats.vertex_count)),
+            ("triangle_count".into(), count(stats.triangle_count)),
+            ("bounds_min".into(), vec3(stats.bounds_min)),
+            ("bounds_max".into(), vec3(stats.bounds_max)),
+            ("total_area".into(), number(stats.total_area)),
+            ("walkable_area".into(), number(stats.walkable_area)),
+            (
+                "walkable_percentage".into(),
+                number(stats.walkable_percentage()),
+            ),
+            ("degenerate_triangles".into(), Json::Array(degenerate)),
+        ]);
+        let mut text = String::new();
+        report.write(&mut text, 0);
+        text.push('\n');
+        text
+    }
+
+    pub fn to_text(&self) -> String {
+        let stats = &self.stats;
+        let mut text = format!(
+            "Vertices: {}\nTriangles: {}\nBounds: {:?} .. {:?}\nWalkable: {:.2} of {:.2} ({:.1}%) at {} degrees\nDegenerate: {}\n",
+            stats.vertex_count,
+            stats.triangle_count,
+            stats.bounds_min.to_array(),
+            stats.bounds_max.to_array(),
+            stats.walkable_area,
+            stats.total_area,
+            stats.walkable_percentage(),
+            self.walkable_slope_angle,
+            self.degenerate_triangles.len()
+        );
+        for (face, tri) in &self.degenerate_triangles {
+            text.push_str(&format!("  face {}: {:?}\n", face, tri));
+        }
+        text
+    }
+}
+
+pub fn compute_mesh_stats(mesh: &InputMesh) -> MeshStats {
+    let mut stats = MeshStats {
+        min_area: f32::INFINITY,
+        min_angle_deg: 180.0,
+        ..Default::default()
+    };
+    let mut aspect_sum = 0.0;
+
+    stats.vertex_count = mesh.verts.len();
+    if !mesh.verts.is_empty() {
+        stats.bounds_min = Vec3::INFINITY;
+        stats.bounds_max = Vec3::NEG_INFINITY;
+        for &v in &mesh.verts {
+            stats.bounds_min = stats.bounds_min.min(v);
+            stats.bounds_max = stats.bounds_max.max(v);
+        }
+    }
+
+    for tri in mesh.tris.chunks_exact(3) {
+        let a = mesh.verts[tri[0] as usize];
+        let b = mesh.verts[tri[1] as usize];
+        let c = mesh.verts[tri[2] as usize];
+
+        let area = (b - a).cross(c - a).length() * 0.5;
+        stats.triangle_count += 1;
+        stats.total_area += area;
+        stats.min_area = stats.min_area.min(area);
+        stats.max_area = stats.max_area.max(area);
+
+        if area <= DEGENERATE_AREA {
+            stats.degenerate_count += 1;
+            continue;
+        }
+
+        let min_angle = corner_angle(a, b, c)
+            .min(corner_angle(b, c, a))
+            .min(corner_angle(c, a, b));
+        stats.min_angle_deg = stats.min_angle_deg.min(min_angle.to_degrees());
+
+        // The shortest altitude is the one dropped onto the longest edge
+        let longest = (b - a).length().max((c - b).length()).max((a - c).length());
+        let shortest_altitude = 2.0 * area / longest;
+        aspect_sum += longest / shortest_altitude;
+    }
+
+    if stats.triangle_count == 0 {
+        return MeshStats {
+            vertex_count: stats.vertex_count,
+            bounds_min: stats.bounds_min,
+            bounds_max: stats.bounds_max,
+            ..Default::default()
+        };
+    }
+
+    let valid = stats.triangle_count - stats.degenerate_count;
+    if valid > 0 {
+        stats.mean_aspect_ratio = aspect_sum / valid as f32;
+    } else {
+        stats.min_angle_deg = 0.0;
+    }
+
+    stats
+}
+
+// Interior angle at `p` in radians
+fn corner_angle(p: Vec3, q: Vec3, r: Vec3) -> f32 {
+    let u = (q - p).normalize();
+    let v = (r - p).normalize();
+    u.dot(v).clamp(-1.0, 1.0).acos()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json::JsonParser;
+
+    #[test]
+    fn test_equilateral_triangle() {
+        let mut mesh = InputMesh::new();
+        mesh.verts = vec![
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 3.0_f32.sqrt()),
+        ];
+        mesh.tris = vec![0, 1, 2];
+        mesh.compute_normals();
+
+        let stats = compute_mesh_stats(&mesh);
+
+        // Side 2: area = sqrt(3), altitude = sqrt(3), aspect = 2 / sqrt(3)
+        assert_eq!(stats.triangle_count, 1);
+        assert_eq!(stats.degenerate_count, 0);
+        assert!((stats.total_area - 3.0_f32.sqrt()).abs() < 1e-5);
+        assert_eq!(stats.min_area, stats.max_area);
+        assert!((stats.min_angle_deg - 60.0).abs() < 1e-3);
+        assert!((stats.mean_aspect_ratio - 2.0 / 3.0_f32.sqrt()).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_analyze_obj() {
+        // A flat quad, a wall triangle and a collinear triangle
+        let obj_content = "\
+v 0 0 0
+v 1 0 0
+v 1 0 1
+v 0 0 1
+v 0 1 0
+v 2 0 0
+f 1 4 3 2
+f 1 2 5
+f 1 2 6";
+        let temp_file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(temp_file.path(), obj_content).unwrap();
+        let obj = crate::obj_loader::load_obj(temp_file.path()).unwrap();
+
+        let report = analyze_obj(&obj, 45.0).unwrap();
+        assert_eq!(report.stats.vertex_count, 6);
+        assert_eq!(report.stats.triangle_count, 4);
+        assert_eq!(report.stats.degenerate_count, 1);
+        assert_eq!(report.degenerate_triangles, vec![(2, [1, 2, 6])]);
+        assert!((report.stats.walkable_percentage() - 100.0 / 1.5).abs() < 1e-3);
+
+        let json = JsonParser::parse(&report.to_json()).unwrap();
+        assert_eq!(json.get("triangle_count"), Some(&Json::Number(4.0)));
+        let degenerate = json.array("degenerate_triangles").unwrap();
+        assert_eq!(degenerate[0].get("face"), Some(&Json::Number(2.0)));
+        assert_eq!(
+            degenerate[0]
+                .field("vertices")
+                .unwrap()
+                .as_floats("")
+                .unwrap(),
+            vec![1.0, 2.0, 6.0]
+        );
+
+        // Degenerate input can leave NaN behind, which JSON can't hold
+        let mut broken = report.clone();
+        broken.stats.bounds_min = Vec3::NAN;
+        broken.stats.total_area = f32::INFINITY;
+        let json = JsonParser::parse(&broken.to_json()).unwrap();
+        assert_eq!(json.get("total_area"), Some(&Json::Null));
+        assert_eq!(
+            json.get("bounds_min"),
+            Some(&Json::Array(vec![Json::Null; 3]))
+        );
+    }
+
+    #[test]
+    fn test_walkable_percentage() {
+        // A flat and a vertical right triangle, both with legs of length 1
+        let mut mesh = InputMesh::new();
+        mesh.verts = vec![
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(5.0, 0.0, 0.0),
+            Vec3::new(6.0, 0.0, 0.0),
+            Vec3::new(5.0, 1.0, 0.0),
+        ];
+        mesh.tris = vec![0, 1, 2, 3, 4, 5];
+        mesh.compute_normals();
+
+        let stats = mesh.compute_stats(45.0);
+
+        assert_eq!(stats.vertex_count, 6);
+        assert_eq!(stats.triangle_count, 2);
+        assert_eq!(stats.bounds_max, Vec3::new(6.0, 1.0, 1.0));
+        assert!((stats.total_area - 1.0).abs() < 1e-6);
+        assert!((stats.walkable_area - 0.5).abs() < 1e-6);
+        assert!((stats.walkable_percentage() - 50.0).abs() < 1e-4);
+    }
+}
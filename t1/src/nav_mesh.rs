@@ -0,0 +1,1802 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use glam::Vec3;
+
+use crate::debug_draw::{PolyMesh, PolyMeshDetail, RC_MESH_NULL_IDX};
+use crate::navmesh_build::MAX_VERTS_PER_POLY;
+use crate::offmesh::OffMeshConnection;
+use crate::poly_mesh::RC_MESH_PORTAL_FLAG;
+
+// Polygon references pack a salt, a tile index and a polygon index, like
+// Detour's 64-bit dtPolyRef. Zero is never a valid reference.
+pub type PolyRef = u64;
+pub type TileRef = u64;
+
+const SALT_BITS: u32 = 16;
+const TILE_BITS: u32 = 28;
+const POLY_BITS: u32 = 20;
+
+// Set on `Poly::neis` for edges that continue into a neighbouring tile,
+// with the tile side in the low bits
+pub const DT_EXT_LINK: u16 = 0x8000;
+// End of a polygon's link list
+pub const DT_NULL_LINK: u32 = u32::MAX;
+// Off-mesh connections that can be traversed in both directions
+pub const DT_OFFMESH_CON_BIDIR: u8 = 1;
+// Detail triangle edge flag for edges on the polygon outline
+pub const DT_DETAIL_EDGE_BOUNDARY: u8 = 0x01;
+
+// Polygon flags given by the bake, for query filters to select on
+pub const POLY_FLAG_WALK: u16 = 0x01;
+pub const POLY_FLAG_JUMP: u16 = 0x02;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PolyType {
+    #[default]
+    Ground,
+    // Two-vertex polygon standing in for an off-mesh connection
+    OffMeshConnection,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Poly {
+    // Head of this polygon's list in `MeshTile::links`
+    pub first_link: u32,
+    // Indices into `MeshTile::verts`
+    pub verts: [u16; MAX_VERTS_PER_POLY],
+    // Per edge: 0 for walls, index + 1 of the polygon across it, or
+    // DT_EXT_LINK | side for tile portals
+    pub neis: [u16; MAX_VERTS_PER_POLY],
+    pub flags: u16,
+    pub vert_count: u8,
+    pub area: u8,
+    pub poly_type: PolyType,
+}
+
+impl Poly {
+    pub fn vert_indices(&self) -> &[u16] {
+        &self.verts[..self.vert_count as usize]
+    }
+}
+
+// Connection from one polygon to another. `edge` is the edge of the owning
+// polygon, or 0xff for links back into an off-mesh connection; `side` is
+// the tile side crossed, 0xff within the tile.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Link {
+    pub poly_ref: PolyRef,
+    pub next: u32,
+    pub edge: u8,
+    pub side: u8,
+    // Portion of the edge covered by a cross-tile link, in 0..=255
+    pub bmin: u8,
+    pub bmax: u8,
+}
+
+// Range of the tile's detail vertices and triangles under one polygon.
+// Detail triangles index the polygon's own vertices first, then
+// `vert_count` extra detail vertices from `vert_base`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PolyDetail {
+    pub vert_base: u32,
+    pub tri_base: u32,
+    pub vert_count: u8,
+    pub tri_count: u8,
+}
+
+// Bounding volume tree node, bounds quantized to the tile. Leaves hold a
+// polygon index; internal nodes hold the negated number of nodes in their
+// subtree, so a query can skip over them.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BvNode {
+    pub bmin: [u16; 3],
+    pub bmax: [u16; 3],
+    pub i: i32,
+}
+
+// Off-mesh connection stored in the tile holding its start point
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TileOffMeshConnection {
+    pub pos: [Vec3; 2],
+    pub rad: f32,
+    // Index of the connection's polygon in the tile
+    pub poly: u16,
+    pub flags: u8,
+    // Tile side the end point lies on, 0xff inside the tile
+    pub side: u8,
+    pub user_id: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MeshHeader {
+    // Tile location in the tile grid
+    pub x: i32,
+    pub y: i32,
+    pub layer: i32,
+    pub user_id: u32,
+    pub walkable_height: f32,
+    pub walkable_radius: f32,
+    pub walkable_climb: f32,
+    pub bmin: Vec3,
+    pub bmax: Vec3,
+    // Scale from world units to BV tree units
+    pub bv_quant_factor: f32,
+    // Index of the first off-mesh connection polygon
+    pub off_mesh_base: usize,
+}
+
+// Everything needed to turn a baked poly mesh into a runtime tile
+#[derive(Debug, Clone)]
+pub struct NavMeshCreateParams<'a> {
+    pub poly_mesh: &'a PolyMesh,
+    // Without a detail mesh the polygons are fanned into flat triangles
+    pub detail_mesh: Option<&'a PolyMeshDetail>,
+    pub off_mesh_connections: &'a [OffMeshConnection],
+    // Agent size in world units
+    pub walkable_height: f32,
+    pub walkable_radius: f32,
+    pub walkable_climb: f32,
+    pub tile_x: i32,
+    pub tile_y: i32,
+    pub tile_layer: i32,
+    pub user_id: u32,
+    pub build_bv_tree: bool,
+}
+
+impl<'a> NavMeshCreateParams<'a> {
+    // Single-tile parameters for `mesh`, with the agent size in world units
+    pub fn new(
+        poly_mesh: &'a PolyMesh,
+        detail_mesh: Option<&'a PolyMeshDetail>,
+        walkable_height: f32,
+        walkable_radius: f32,
+        walkable_climb: f32,
+    ) -> Self {
+        Self {
+            poly_mesh,
+            detail_mesh,
+            off_mesh_connections: &[],
+            walkable_height,
+            walkable_radius,
+            walkable_climb,
+            tile_x: 0,
+            tile_y: 0,
+            tile_layer: 0,
+            user_id: 0,
+            build_bv_tree: true,
+        }
+    }
+}
+
+// Tile contents in the runtime layout, ready to be added to a `NavMesh`.
+// Matches what dtCreateNavMeshData writes, without the links, which are
+// only created once the tile is in a mesh.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NavMeshData {
+    pub header: MeshHeader,
+    pub polys: Vec<Poly>,
+    // World-space polygon vertices, followed by two per off-mesh connection
+    pub verts: Vec<Vec3>,
+    pub detail_meshes: Vec<PolyDetail>,
+    pub detail_verts: Vec<Vec3>,
+    pub detail_tris: Vec<[u8; 4]>,
+    pub bv_tree: Vec<BvNode>,
+    pub off_mesh_cons: Vec<TileOffMeshConnection>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NavMeshError {
+    // Poly mesh with more vertices per polygon than a tile can hold
+    VertsPerPoly(usize),
+    // Vertex indices must fit in u16
+    TooManyVerts(usize),
+    EmptyMesh,
+    InvalidParams(&'static str),
+    TileExists { x: i32, y: i32, layer: i32 },
+    OutOfTiles,
+    NoTile { x: i32, y: i32, layer: i32 },
+    TooManyPolys(usize),
+    InvalidRef(u64),
+}
+
+impl fmt::Display for NavMeshError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NavMeshError::VertsPerPoly(nvp) => {
+                write!(
+                    f,
+                    "{} vertices per polygon, at most {} are supported",
+                    nvp, MAX_VERTS_PER_POLY
+                )
+            }
+            NavMeshError::TooManyVerts(count) => {
+                write!(f, "{} vertices do not fit in a tile", count)
+            }
+            NavMeshError::EmptyMesh => write!(f, "no polygons or off-mesh connections"),
+            NavMeshError::InvalidParams(reason) => {
+                write!(f, "invalid navmesh parameters: {}", reason)
+            }
+            NavMeshError::TileExists { x, y, layer } => {
+                write!(f, "a tile already exists at ({}, {}) layer {}", x, y, layer)
+            }
+            NavMeshError::OutOfTiles => write!(f, "no free tile slots"),
+            NavMeshError::NoTile { x, y, layer } => {
+                write!(f, "no tile at ({}, {}) layer {}", x, y, layer)
+            }
+            NavMeshError::TooManyPolys(count) => {
+                write!(f, "{} polygons do not fit in a tile", count)
+            }
+            NavMeshError::InvalidRef(r) => write!(f, "invalid reference {:#x}", r),
+        }
+    }
+}
+
+impl NavMeshData {
+    // Convert a poly mesh, its detail mesh and the off-mesh connections
+    // starting inside it into a runtime tile, like dtCreateNavMeshData
+    pub fn build(params: &NavMeshCreateParams) -> Result<Self, NavMeshError> {
+        let mesh = params.poly_mesh;
+        let nvp = mesh.nvp.max(0) as usize;
+        if nvp > MAX_VERTS_PER_POLY {
+            return Err(NavMeshError::VertsPerPoly(nvp));
+        }
+        if mesh.verts.len() >= 0xffff {
+            return Err(NavMeshError::TooManyVerts(mesh.verts.len()));
+        }
+        if mesh.polys.is_empty() && params.off_mesh_connections.is_empty() {
+            return Err(NavMeshError::EmptyMesh);
+        }
+
+        let to_world = |v: Vec3| mesh.bmin + v * Vec3::new(mesh.cs, mesh.ch, mesh.cs);
+
+        // Height range of the tile, so off-mesh connections can be
+        // classified against it
+        let (mut hmin, mut hmax) = (f32::MAX, f32::MIN);
+        match params.detail_mesh {
+            Some(dmesh) if !dmesh.verts.is_empty() => {
+                for v in &dmesh.verts {
+                    hmin = hmin.min(v.y);
+                    hmax = hmax.max(v.y);
+                }
+            }
+            _ => {
+                for &v in &mesh.verts {
+                    let y = to_world(v).y;
+                    hmin = hmin.min(y);
+                    hmax = hmax.max(y);
+                }
+            }
+        }
+        hmin -= params.walkable_climb;
+        hmax += params.walkable_climb;
+        let mut bmin = mesh.bmin;
+        let mut bmax = mesh.bmax;
+        if hmin <= hmax {
+            bmin.y = hmin;
+            bmax.y = hmax;
+        }
+
+        // Only connections starting in this tile are stored with it
+        let stored: Vec<(&OffMeshConnection, u8)> = params
+            .off_mesh_connections
+            .iter()
+            .filter(|con| {
+                classify_off_mesh_point(con.start, bmin, bmax) == 0xff
+                    && con.start.y >= bmin.y
+                    && con.start.y <= bmax.y
+            })
+            .map(|con| (con, classify_off_mesh_point(con.end, bmin, bmax)))
+            .collect();
+
+        let mut data = NavMeshData {
+            header: MeshHeader {
+                x: params.tile_x,
+                y: params.tile_y,
+                layer: params.tile_layer,
+                user_id: params.user_id,
+                walkable_height: params.walkable_height,
+                walkable_radius: params.walkable_radius,
+                walkable_climb: params.walkable_climb,
+                bmin,
+                bmax,
+                bv_quant_factor: 1.0 / mesh.cs,
+                off_mesh_base: mesh.polys.len(),
+            },
+            ..Default::default()
+        };
+
+        data.verts = mesh.verts.iter().map(|&v| to_world(v)).collect();
+        for (con, _) in &stored {
+            data.verts.push(con.start);
+            data.verts.push(con.end);
+        }
+
+        for (i, p) in mesh.polys.iter().enumerate() {
+            let mut poly = Poly {
+                first_link: DT_NULL_LINK,
+                verts: [0; MAX_VERTS_PER_POLY],
+                neis: [0; MAX_VERTS_PER_POLY],
+                flags: mesh.flags[i],
+                vert_count: 0,
+                area: mesh.areas[i],
+                poly_type: PolyType::Ground,
+            };
+            for (j, &v) in p.iter().take(nvp).enumerate() {
+                if v == RC_MESH_NULL_IDX {
+                    break;
+                }
+                poly.verts[j] = v;
+                let nei = mesh.neighbours[i][j];
+                poly.neis[j] = if nei == RC_MESH_NULL_IDX {
+                    0
+                } else if nei & RC_MESH_PORTAL_FLAG != 0 {
+                    // Recast portal sides map onto Detour's eight tile sides
+                    match nei & 0xf {
+                        0 => DT_EXT_LINK | 4,
+                        1 => DT_EXT_LINK | 2,
+                        2 => DT_EXT_LINK,
+                        3 => DT_EXT_LINK | 6,
+                        _ => 0,
+                    }
+                } else {
+                    nei + 1
+                };
+                poly.vert_count += 1;
+            }
+            data.polys.push(poly);
+        }
+
+        let vert_base = mesh.verts.len();
+        for (n, (con, end_side)) in stored.iter().enumerate() {
+            let mut verts = [0; MAX_VERTS_PER_POLY];
+            verts[0] = (vert_base + n * 2) as u16;
+            verts[1] = (vert_base + n * 2 + 1) as u16;
+            data.polys.push(Poly {
+                first_link: DT_NULL_LINK,
+                verts,
+                neis: [0; MAX_VERTS_PER_POLY],
+                flags: POLY_FLAG_JUMP,
+                vert_count: 2,
+                area: con.area,
+                poly_type: PolyType::OffMeshConnection,
+            });
+            data.off_mesh_cons.push(TileOffMeshConnection {
+                pos: [con.start, con.end],
+                rad: con.radius,
+                poly: (mesh.polys.len() + n) as u16,
+                flags: if con.bidirectional {
+                    DT_OFFMESH_CON_BIDIR
+                } else {
+                    0
+                },
+                side: *end_side,
+                user_id: n as u32,
+            });
+        }
+
+        match params.detail_mesh {
+            Some(dmesh) if !dmesh.meshes.is_empty() => {
+                // The polygon's own vertices come first in each submesh and
+                // are not stored again
+                for (i, m) in dmesh.meshes.iter().enumerate() {
+                    let [vb, ndv, tb, ntris] = *m;
+                    let nv = data.polys[i].vert_count as u32;
+                    data.detail_meshes.push(PolyDetail {
+                        vert_base: data.detail_verts.len() as u32,
+                        tri_base: tb,
+                        vert_count: ndv.saturating_sub(nv) as u8,
+                        tri_count: ntris as u8,
+                    });
+                    let extra = (vb + nv) as usize..(vb + ndv.max(nv)) as usize;
+                    data.detail_verts.extend_from_slice(&dmesh.verts[extra]);
+                }
+                data.detail_tris = dmesh.tris.clone();
+            }
+            _ => {
+                // Fan each polygon into triangles over its own vertices
+                for poly in &data.polys[..mesh.polys.len()] {
+                    let nv = poly.vert_count as usize;
+                    data.detail_meshes.push(PolyDetail {
+                        vert_base: 0,
+                        tri_base: data.detail_tris.len() as u32,
+                        vert_count: 0,
+                        tri_count: nv.saturating_sub(2) as u8,
+                    });
+                    for j in 2..nv {
+                        let mut flags = DT_DETAIL_EDGE_BOUNDARY << 2;
+                        if j == 2 {
+                            flags |= DT_DETAIL_EDGE_BOUNDARY;
+                        }
+                        if j == nv - 1 {
+                            flags |= DT_DETAIL_EDGE_BOUNDARY << 4;
+                        }
+                        data.detail_tris.push([0, (j - 1) as u8, j as u8, flags]);
+                    }
+                }
+            }
+        }
+
+        if params.build_bv_tree {
+            data.bv_tree = build_bv_tree(&data, mesh.polys.len());
+        }
+
+        Ok(data)
+    }
+
+    // Vertex `k` of detail triangle `tri` under polygon `ip`
+    pub fn detail_vert(&self, ip: usize, tri: &[u8; 4], k: usize) -> Vec3 {
+        let poly = &self.polys[ip];
+        let index = tri[k] as usize;
+        if index < poly.vert_count as usize {
+            self.verts[poly.verts[index] as usize]
+        } else {
+            let base = self.detail_meshes[ip].vert_base as usize;
+            self.detail_verts[base + index - poly.vert_count as usize]
+        }
+    }
+
+    // Detail triangles of ground polygon `ip`
+    pub fn detail_tris_of(&self, ip: usize) -> &[[u8; 4]] {
+        let pd = &self.detail_meshes[ip];
+        let base = pd.tri_base as usize;
+        &self.detail_tris[base..base + pd.tri_count as usize]
+    }
+}
+
+// Side of the tile bounds `pt` lies beyond, in Detour's eight-way tile side
+// numbering, or 0xff if it is inside on the XZ plane
+fn classify_off_mesh_point(pt: Vec3, bmin: Vec3, bmax: Vec3) -> u8 {
+    const XP: u8 = 1;
+    const ZP: u8 = 2;
+    const XM: u8 = 4;
+    const ZM: u8 = 8;
+
+    let mut outcode = 0;
+    if pt.x >= bmax.x {
+        outcode |= XP;
+    }
+    if pt.z >= bmax.z {
+        outcode |= ZP;
+    }
+    if pt.x < bmin.x {
+        outcode |= XM;
+    }
+    if pt.z < bmin.z {
+        outcode |= ZM;
+    }
+
+    match outcode {
+        XP => 0,
+        o if o == XP | ZP => 1,
+        ZP => 2,
+        o if o == XM | ZP => 3,
+        XM => 4,
+        o if o == XM | ZM => 5,
+        ZM => 6,
+        o if o == XP | ZM => 7,
+        _ => 0xff,
+    }
+}
+
+// Item of the BV tree build: quantized polygon bounds
+#[derive(Clone, Copy)]
+struct BvItem {
+    bmin: [u16; 3],
+    bmax: [u16; 3],
+    i: i32,
+}
+
+fn build_bv_tree(data: &NavMeshData, poly_count: usize) -> Vec<BvNode> {
+    let quant = data.header.bv_quant_factor;
+    let origin = data.header.bmin;
+    let quantize =
+        |v: f32, o: f32, round: fn(f32) -> f32| round((v - o) * quant).clamp(0.0, 65535.0) as u16;
+
+    let mut items: Vec<BvItem> = (0..poly_count)
+        .map(|ip| {
+            let (mut bmin, mut bmax) = (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN));
+            let poly = &data.polys[ip];
+            let pd = &data.detail_meshes[ip];
+            let base = pd.vert_base as usize;
+            let points = poly
+                .vert_indices()
+                .iter()
+                .map(|&v| data.verts[v as usize])
+                .chain(
+                    data.detail_verts[base..base + pd.vert_count as usize]
+                        .iter()
+                        .copied(),
+                );
+            for p in points {
+                bmin = bmin.min(p);
+                bmax = bmax.max(p);
+            }
+            BvItem {
+                bmin: [
+                    quantize(bmin.x, origin.x, f32::floor),
+                    quantize(bmin.y, origin.y, f32::floor),
+                    quantize(bmin.z, origin.z, f32::floor),
+                ],
+                bmax: [
+                    quantize(bmax.x, origin.x, f32::ceil),
+                    quantize(bmax.y, origin.y, f32::ceil),
+                    quantize(bmax.z, origin.z, f32::ceil),
+                ],
+                i: ip as i32,
+            }
+        })
+        .collect();
+
+    let mut nodes = Vec::with_capacity(poly_count * 2);
+    if !items.is_empty() {
+        subdivide(&mut items, &mut nodes);
+    }
+    nodes
+}
+
+// Split the items at the median of their longest axis, writing the nodes
+// in depth-first order
+fn subdivide(items: &mut [BvItem], nodes: &mut Vec<BvNode>) {
+    let icur = nodes.len();
+    if let [item] = items {
+        nodes.push(BvNode {
+            bmin: item.bmin,
+            bmax: item.bmax,
+            i: item.i,
+        });
+        return;
+    }
+
+    let mut node = BvNode {
+        bmin: [u16::MAX; 3],
+        bmax: [0; 3],
+        i: 0,
+    };
+    for item in items.iter() {
+        for k in 0..3 {
+            node.bmin[k] = node.bmin[k].min(item.bmin[k]);
+            node.bmax[k] = node.bmax[k].max(item.bmax[k]);
+        }
+    }
+    nodes.push(node);
+
+    let extent = |k: usize| node.bmax[k] as i32 - node.bmin[k] as i32;
+    let axis = if extent(0) >= extent(1) && extent(0) >= extent(2) {
+        0
+    } else if extent(1) >= extent(2) {
+        1
+    } else {
+        2
+    };
+    items.sort_by_key(|item| item.bmin[axis]);
+
+    let (left, right) = items.split_at_mut(items.len() / 2);
+    subdivide(left, nodes);
+    subdivide(right, nodes);
+    nodes[icur].i = -((nodes.len() - icur) as i32);
+}
+
+// A tile added to a `NavMesh`, with its links
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MeshTile {
+    pub salt: u32,
+    pub data: NavMeshData,
+    pub links: Vec<Link>,
+    // Head of the list of unused entries in `links`
+    links_free_list: u32,
+}
+
+impl MeshTile {
+    pub fn new(salt: u32, data: NavMeshData) -> Self {
+        Self {
+            salt,
+            data,
+            links: Vec::new(),
+            links_free_list: DT_NULL_LINK,
+        }
+    }
+
+    // Links of polygon `ip`, most recently added first
+    pub fn links_of(&self, ip: usize) -> impl Iterator<Item = &Link> {
+        let mut next = self.data.polys[ip].first_link;
+        std::iter::from_fn(move || {
+            let link = self.links.get(next as usize)?;
+            next = link.next;
+            Some(link)
+        })
+    }
+
+    // Prepend `link` to the link list of polygon `ip`, reusing a freed
+    // slot if there is one
+    fn push_link(&mut self, ip: usize, mut link: Link) {
+        link.next = self.data.polys[ip].first_link;
+        let index = if self.links_free_list != DT_NULL_LINK {
+            let index = self.links_free_list;
+            self.links_free_list = self.links[index as usize].next;
+            self.links[index as usize] = link;
+            index
+        } else {
+            self.links.push(link);
+            (self.links.len() - 1) as u32
+        };
+        self.data.polys[ip].first_link = index;
+    }
+
+    // Unlink every link `remove` selects, returning the slots to the free
+    // list
+    fn remove_links(&mut self, remove: impl Fn(&Link) -> bool) {
+        for ip in 0..self.data.polys.len() {
+            let mut prev = DT_NULL_LINK;
+            let mut index = self.data.polys[ip].first_link;
+            while index != DT_NULL_LINK {
+                let link = self.links[index as usize];
+                if remove(&link) {
+                    if prev == DT_NULL_LINK {
+                        self.data.polys[ip].first_link = link.next;
+                    } else {
+                        self.links[prev as usize].next = link.next;
+                    }
+                    self.links[index as usize].next = self.links_free_list;
+                    self.links_free_list = index;
+                } else {
+                    prev = index;
+                }
+                index = link.next;
+            }
+        }
+    }
+
+    // Height of the detail surface of ground polygon `ip` at `pos`, or
+    // None if `pos` is outside the polygon on the XZ plane
+    pub fn poly_height(&self, ip: usize, pos: Vec3) -> Option<f32> {
+        let poly = &self.data.polys[ip];
+        if poly.poly_type == PolyType::OffMeshConnection {
+            return None;
+        }
+        let verts: Vec<Vec3> = poly
+            .vert_indices()
+            .iter()
+            .map(|&v| self.data.verts[v as usize])
+            .collect();
+        if !point_in_polygon(pos, &verts) {
+            return None;
+        }
+
+        for tri in self.data.detail_tris_of(ip) {
+            let v = |k| self.data.detail_vert(ip, tri, k);
+            if let Some(h) = closest_height_point_triangle(pos, v(0), v(1), v(2)) {
+                return Some(h);
+            }
+        }
+        // On the outline, between triangles within float precision
+        Some(self.closest_point_on_detail_edges(ip, pos).y)
+    }
+
+    // Point of polygon `ip` closest to `pos`, and whether `pos` lies over
+    // the polygon on the XZ plane
+    pub fn closest_point_on_poly(&self, ip: usize, pos: Vec3) -> (Vec3, bool) {
+        if let Some(h) = self.poly_height(ip, pos) {
+            return (Vec3::new(pos.x, h, pos.z), true);
+        }
+
+        let poly = &self.data.polys[ip];
+        if poly.poly_type == PolyType::OffMeshConnection {
+            let a = self.data.verts[poly.verts[0] as usize];
+            let b = self.data.verts[poly.verts[1] as usize];
+            let (_, t) = distance_pt_seg_sqr_2d(pos, a, b);
+            return (a.lerp(b, t), false);
+        }
+        (self.closest_point_on_detail_edges(ip, pos), false)
+    }
+
+    // Closest point to `pos` on the outline edges of the detail mesh
+    fn closest_point_on_detail_edges(&self, ip: usize, pos: Vec3) -> Vec3 {
+        let mut best = (f32::MAX, Vec3::ZERO);
+        for tri in self.data.detail_tris_of(ip) {
+            for k in 0..3 {
+                if (tri[3] >> (k * 2)) & DT_DETAIL_EDGE_BOUNDARY == 0 {
+                    continue;
+                }
+                let a = self.data.detail_vert(ip, tri, k);
+                let b = self.data.detail_vert(ip, tri, (k + 1) % 3);
+                let (d, t) = distance_pt_seg_sqr_2d(pos, a, b);
+                if d < best.0 {
+                    best = (d, a.lerp(b, t));
+                }
+            }
+        }
+        best.1
+    }
+
+    // Ground polygons whose bounds overlap the box, found through the BV
+    // tree when the tile has one
+    pub fn query_polygons(&self, qmin: Vec3, qmax: Vec3) -> Vec<usize> {
+        let header = &self.data.header;
+        if self.data.bv_tree.is_empty() {
+            return (0..header.off_mesh_base)
+                .filter(|&ip| {
+                    let (bmin, bmax) = self.poly_bounds(ip);
+                    overlap_bounds(qmin, qmax, bmin, bmax)
+                })
+                .collect();
+        }
+
+        // Quantize the box, rounding outwards
+        let lo = (qmin.clamp(header.bmin, header.bmax) - header.bmin) * header.bv_quant_factor;
+        let hi = (qmax.clamp(header.bmin, header.bmax) - header.bmin) * header.bv_quant_factor;
+        let bmin = [
+            lo.x as u16 & 0xfffe,
+            lo.y as u16 & 0xfffe,
+            lo.z as u16 & 0xfffe,
+        ];
+        let bmax = [
+            (hi.x + 1.0) as u16 | 1,
+            (hi.y + 1.0) as u16 | 1,
+            (hi.z + 1.0) as u16 | 1,
+        ];
+
+        let mut polys = Vec::new();
+        let nodes = &self.data.bv_tree;
+        let mut i = 0;
+        while i < nodes.len() {
+            let node = &nodes[i];
+            let overlap = (0..3).all(|k| bmin[k] <= node.bmax[k] && bmax[k] >= node.bmin[k]);
+            let leaf = node.i >= 0;
+            if leaf && overlap {
+                polys.push(node.i as usize);
+            }
+            // Skip the whole subtree of internal nodes the box misses
+            i += if overlap || leaf { 1 } else { -node.i as usize };
+        }
+        polys
+    }
+
+    // Nearest ground polygon to `center` within the box among those
+    // `accept` lets through, with the point on it and its squared distance.
+    // Polygons `center` lies over are preferred, measuring only the height
+    // difference beyond the walkable climb.
+    pub fn find_nearest_poly(
+        &self,
+        center: Vec3,
+        half_extents: Vec3,
+        accept: impl Fn(usize) -> bool,
+    ) -> Option<(usize, Vec3, f32)> {
+        let mut nearest = None;
+        let mut nearest_dist = f32::MAX;
+        for ip in self.query_polygons(center - half_extents, center + half_extents) {
+            if !accept(ip) {
+                continue;
+            }
+            let (closest, over_poly) = self.closest_point_on_poly(ip, center);
+            let diff = center - closest;
+            let d = if over_poly {
+                let d = diff.y.abs() - self.data.header.walkable_climb;
+                if d > 0.0 {
+                    d * d
+                } else {
+                    0.0
+                }
+            } else {
+                diff.length_squared()
+            };
+            if d < nearest_dist {
+                nearest_dist = d;
+                nearest = Some((ip, closest, d));
+            }
+        }
+        nearest
+    }
+
+    // World bounds of ground polygon `ip`, including its detail vertices
+    pub fn poly_bounds(&self, ip: usize) -> (Vec3, Vec3) {
+        let poly = &self.data.polys[ip];
+        let pd = &self.data.detail_meshes[ip];
+        let base = pd.vert_base as usize;
+        poly.vert_indices()
+            .iter()
+            .map(|&v| self.data.verts[v as usize])
+            .chain(
+                self.data.detail_verts[base..base + pd.vert_count as usize]
+                    .iter()
+                    .copied(),
+            )
+            .fold(
+                (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+                |(min, max), p| (min.min(p), max.max(p)),
+            )
+    }
+}
+
+// Origin and size of the tile grid
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NavMeshParams {
+    pub orig: Vec3,
+    pub tile_width: f32,
+    pub tile_height: f32,
+    pub max_tiles: usize,
+    pub max_polys: usize,
+}
+
+// Runtime navigation mesh: tiles of polygons linked to each other, which
+// path queries run on
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "NavMeshSnapshot", into = "NavMeshSnapshot")
+)]
+pub struct NavMesh {
+    params: NavMeshParams,
+    tiles: Vec<Option<MeshTile>>,
+    // Salt of each tile slot, bumped when a slot is reused so stale
+    // references stop resolving
+    salts: Vec<u32>,
+    free_tiles: Vec<usize>,
+    tile_lookup: HashMap<(i32, i32, i32), usize>,
+}
+
+// Serialized form of a NavMesh. The tile lookup is left out, since
+// formats like JSON can't have tuple keys, and rebuilt on load.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct NavMeshSnapshot {
+    params: NavMeshParams,
+    tiles: Vec<Option<MeshTile>>,
+    salts: Vec<u32>,
+    free_tiles: Vec<usize>,
+}
+
+#[cfg(feature = "serde")]
+impl From<NavMesh> for NavMeshSnapshot {
+    fn from(nav_mesh: NavMesh) -> Self {
+        Self {
+            params: nav_mesh.params,
+            tiles: nav_mesh.tiles,
+            salts: nav_mesh.salts,
+            free_tiles: nav_mesh.free_tiles,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<NavMeshSnapshot> for NavMesh {
+    type Error = String;
+
+    fn try_from(snapshot: NavMeshSnapshot) -> Result<Self, String> {
+        let max_tiles = snapshot.params.max_tiles;
+        if snapshot.tiles.len() != max_tiles || snapshot.salts.len() != max_tiles {
+            return Err(format!("expected {} tile slots", max_tiles));
+        }
+        if snapshot.free_tiles.iter().any(|&it| it >= max_tiles) {
+            return Err("free tile slot out of range".to_string());
+        }
+        let tile_lookup = snapshot
+            .tiles
+            .iter()
+            .enumerate()
+            .filter_map(|(it, tile)| {
+                let header = &tile.as_ref()?.data.header;
+                Some(((header.x, header.y, header.layer), it))
+            })
+            .collect();
+        Ok(Self {
+            params: snapshot.params,
+            tiles: snapshot.tiles,
+            salts: snapshot.salts,
+            free_tiles: snapshot.free_tiles,
+            tile_lookup,
+        })
+    }
+}
+
+impl NavMesh {
+    pub fn new(params: NavMeshParams) -> Result<Self, NavMeshError> {
+        if params.max_tiles == 0 || params.max_tiles > 1 << TILE_BITS {
+            return Err(NavMeshError::InvalidParams("max_tiles out of range"));
+        }
+        if params.max_polys == 0 || params.max_polys > 1 << POLY_BITS {
+            return Err(NavMeshError::InvalidParams("max_polys out of range"));
+        }
+        if !(params.tile_width > 0.0 && params.tile_height > 0.0) {
+            return Err(NavMeshError::InvalidParams("tile size must be positive"));
+        }
+        let max_tiles = params.max_tiles;
+        Ok(Self {
+            params,
+            tiles: vec![None; max_tiles],
+            salts: vec![1; max_tiles],
+            free_tiles: (0..max_tiles).rev().collect(),
+            tile_lookup: HashMap::new(),
+        })
+    }
+
+    // A navmesh holding just `data`, covering its bounds
+    pub fn single_tile(data: NavMeshData) -> Result<Self, NavMeshError> {
+        let header = &data.header;
+        let mut nav_mesh = NavMesh::new(NavMeshParams {
+            orig: header.bmin,
+            tile_width: header.bmax.x - header.bmin.x,
+            tile_height: header.bmax.z - header.bmin.z,
+            max_tiles: 1,
+            max_polys: data.polys.len().max(1),
+        })?;
+        nav_mesh.add_tile(data)?;
+        Ok(nav_mesh)
+    }
+
+    pub fn params(&self) -> &NavMeshParams {
+        &self.params
+    }
+
+    // Add a tile and link its polygons and off-mesh connections
+    pub fn add_tile(&mut self, data: NavMeshData) -> Result<TileRef, NavMeshError> {
+        let key = (data.header.x, data.header.y, data.header.layer);
+        if self.tile_lookup.contains_key(&key) {
+            return Err(NavMeshError::TileExists {
+                x: key.0,
+                y: key.1,
+                layer: key.2,
+            });
+        }
+        if data.polys.len() > self.params.max_polys {
+            return Err(NavMeshError::TooManyPolys(data.polys.len()));
+        }
+        let it = self.free_tiles.pop().ok_or(NavMeshError::OutOfTiles)?;
+
+        let mut tile = MeshTile::new(self.salts[it], data);
+        let base = self.encode_poly_id(tile.salt, it, 0);
+        connect_int_links(&mut tile, base);
+        base_off_mesh_links(&mut tile, base);
+        connect_int_off_mesh_links(&mut tile, base);
+
+        self.tiles[it] = Some(tile);
+        self.tile_lookup.insert(key, it);
+
+        // Other layers of the same column, wherever their portals line up
+        let (x, y, _) = key;
+        for other in self.tile_slots_at(x, y) {
+            if other == it {
+                continue;
+            }
+            self.connect_ext_links(it, other, None);
+            self.connect_ext_links(other, it, None);
+            self.connect_ext_off_mesh_links(it, other, None);
+            self.connect_ext_off_mesh_links(other, it, None);
+        }
+        // The eight surrounding columns
+        for side in 0..8 {
+            let (dx, dy) = tile_side_offset(side);
+            for other in self.tile_slots_at(x + dx, y + dy) {
+                self.connect_ext_links(it, other, Some(side));
+                self.connect_ext_links(other, it, Some(opposite_tile(side)));
+                self.connect_ext_off_mesh_links(it, other, Some(side));
+                self.connect_ext_off_mesh_links(other, it, Some(opposite_tile(side)));
+            }
+        }
+        Ok(base)
+    }
+
+    // Take out the tile at (x, y, layer), unlinking its neighbours from it.
+    // References into it stop resolving, even once the slot is reused.
+    pub fn remove_tile(&mut self, x: i32, y: i32, layer: i32) -> Result<NavMeshData, NavMeshError> {
+        let it = self
+            .tile_lookup
+            .remove(&(x, y, layer))
+            .ok_or(NavMeshError::NoTile { x, y, layer })?;
+
+        let mut neighbours = self.tile_slots_at(x, y);
+        for side in 0..8 {
+            let (dx, dy) = tile_side_offset(side);
+            neighbours.extend(self.tile_slots_at(x + dx, y + dy));
+        }
+        let removed_tile = it as u64;
+        for other in neighbours {
+            if let Some(tile) = self.tiles[other].as_mut() {
+                tile.remove_links(|link| {
+                    (link.poly_ref >> POLY_BITS) & ((1 << TILE_BITS) - 1) == removed_tile
+                });
+            }
+        }
+
+        let tile = self.tiles[it].take().expect("looked up tiles are in use");
+        // Zero is reserved so that no reference is ever 0
+        self.salts[it] = (self.salts[it] + 1) & ((1 << SALT_BITS) - 1);
+        if self.salts[it] == 0 {
+            self.salts[it] = 1;
+        }
+        self.free_tiles.push(it);
+        Ok(tile.data)
+    }
+
+    // Slots of every layer of the tile column at (x, y)
+    fn tile_slots_at(&self, x: i32, y: i32) -> Vec<usize> {
+        self.tile_lookup
+            .iter()
+            .filter(|(&(tx, ty, _), _)| tx == x && ty == y)
+            .map(|(_, &it)| it)
+            .collect()
+    }
+
+    // Mutable access to two different tile slots at once
+    fn tile_pair_mut(&mut self, a: usize, b: usize) -> (&mut MeshTile, &mut MeshTile) {
+        debug_assert_ne!(a, b);
+        let (first, second) = self.tiles.split_at_mut(a.max(b));
+        let (low, high) = (first[a.min(b)].as_mut(), second[0].as_mut());
+        let (low, high) = (
+            low.expect("tile slot in use"),
+            high.expect("tile slot in use"),
+        );
+        if a < b {
+            (low, high)
+        } else {
+            (high, low)
+        }
+    }
+
+    // Link the portal edges of tile `it` facing `side` (every side when
+    // None) to the polygons of tile `target` whose edges line up with them
+    fn connect_ext_links(&mut self, it: usize, target: usize, side: Option<u8>) {
+        let target_base = self.encode_poly_id(self.salts[target], target, 0);
+        let (tile, target) = self.tile_pair_mut(it, target);
+
+        for ip in 0..tile.data.header.off_mesh_base {
+            let nv = tile.data.polys[ip].vert_count as usize;
+            for j in 0..nv {
+                let nei = tile.data.polys[ip].neis[j];
+                if nei & DT_EXT_LINK == 0 {
+                    continue;
+                }
+                let dir = (nei & 0xff) as u8;
+                if side.is_some_and(|side| side != dir) {
+                    continue;
+                }
+
+                let poly = &tile.data.polys[ip];
+                let va = tile.data.verts[poly.verts[j] as usize];
+                let vb = tile.data.verts[poly.verts[(j + 1) % nv] as usize];
+                for (other, cmin, cmax) in find_connecting_polys(va, vb, target, opposite_tile(dir))
+                {
+                    // Part of the edge the neighbour covers, along the
+                    // axis the portal runs on
+                    let (a, b) = if dir == 0 || dir == 4 {
+                        (va.z, vb.z)
+                    } else {
+                        (va.x, vb.x)
+                    };
+                    let t0 = ((cmin - a) / (b - a)).clamp(0.0, 1.0);
+                    let t1 = ((cmax - a) / (b - a)).clamp(0.0, 1.0);
+                    tile.push_link(
+                        ip,
+                        Link {
+                            poly_ref: target_base | other as PolyRef,
+                            edge: j as u8,
+                            side: dir,
+                            bmin: (t0.min(t1) * 255.0).round() as u8,
+                            bmax: (t0.max(t1) * 255.0).round() as u8,
+                            ..Default::default()
+                        },
+                    );
+                }
+            }
+        }
+    }
+
+    // Land the off-mesh connections of tile `target` that end beyond its
+    // `opposite_tile(side)` side (inside it when None) on tile `it`
+    fn connect_ext_off_mesh_links(&mut self, it: usize, target: usize, side: Option<u8>) {
+        let base = self.encode_poly_id(self.salts[it], it, 0);
+        let target_base = self.encode_poly_id(self.salts[target], target, 0);
+        let opposite_side = side.map_or(0xff, opposite_tile);
+        let (tile, target) = self.tile_pair_mut(it, target);
+
+        for n in 0..target.data.off_mesh_cons.len() {
+            let con = target.data.off_mesh_cons[n];
+            if con.side != opposite_side {
+                continue;
+            }
+            // Connections whose start could not be anchored stay unlinked
+            if target.data.polys[con.poly as usize].first_link == DT_NULL_LINK {
+                continue;
+            }
+            let half_extents = Vec3::new(con.rad, target.data.header.walkable_climb, con.rad);
+            let Some((land, nearest, _)) =
+                tile.find_nearest_poly(con.pos[1], half_extents, |_| true)
+            else {
+                continue;
+            };
+            if dist_sqr_2d(nearest, con.pos[1]) > con.rad * con.rad {
+                continue;
+            }
+
+            let end_vert = target.data.polys[con.poly as usize].verts[1];
+            target.data.verts[end_vert as usize] = nearest;
+
+            target.push_link(
+                con.poly as usize,
+                Link {
+                    poly_ref: base | land as PolyRef,
+                    edge: 1,
+                    side: opposite_side,
+                    ..Default::default()
+                },
+            );
+            if con.flags & DT_OFFMESH_CON_BIDIR != 0 {
+                tile.push_link(
+                    land,
+                    Link {
+                        poly_ref: target_base | con.poly as PolyRef,
+                        edge: 0xff,
+                        side: side.unwrap_or(0xff),
+                        ..Default::default()
+                    },
+                );
+            }
+        }
+    }
+
+    pub fn encode_poly_id(&self, salt: u32, it: usize, ip: usize) -> PolyRef {
+        ((salt as u64) << (POLY_BITS + TILE_BITS)) | ((it as u64) << POLY_BITS) | ip as u64
+    }
+
+    // Salt, tile index and polygon index of `r`
+    pub fn decode_poly_id(&self, r: PolyRef) -> (u32, usize, usize) {
+        let salt = (r >> (POLY_BITS + TILE_BITS)) & ((1 << SALT_BITS) - 1);
+        let it = (r >> POLY_BITS) & ((1 << TILE_BITS) - 1);
+        let ip = r & ((1 << POLY_BITS) - 1);
+        (salt as u32, it as usize, ip as usize)
+    }
+
+    pub fn is_valid_poly_ref(&self, r: PolyRef) -> bool {
+        self.tile_and_poly_by_ref(r).is_ok()
+    }
+
+    pub fn tile_and_poly_by_ref(&self, r: PolyRef) -> Result<(&MeshTile, &Poly), NavMeshError> {
+        if r == 0 {
+            return Err(NavMeshError::InvalidRef(r));
+        }
+        let (salt, it, ip) = self.decode_poly_id(r);
+        match self.tiles.get(it) {
+            Some(Some(tile)) if tile.salt == salt && ip < tile.data.polys.len() => {
+                Ok((tile, &tile.data.polys[ip]))
+            }
+            _ => Err(NavMeshError::InvalidRef(r)),
+        }
+    }
+
+    pub fn tile_at(&self, x: i32, y: i32, layer: i32) -> Option<&MeshTile> {
+        let it = *self.tile_lookup.get(&(x, y, layer))?;
+        self.tiles[it].as_ref()
+    }
+
+    pub fn tiles(&self) -> impl Iterator<Item = &MeshTile> {
+        self.tiles.iter().flatten()
+    }
+
+    // Tiles with the reference of their first polygon
+    pub fn tiles_with_refs(&self) -> impl Iterator<Item = (PolyRef, &MeshTile)> {
+        self.tiles.iter().enumerate().filter_map(|(it, tile)| {
+            let tile = tile.as_ref()?;
+            Some((self.encode_poly_id(tile.salt, it, 0), tile))
+        })
+    }
+
+    // Reference of the first polygon of `tile`; the rest follow in order
+    pub fn poly_ref_base(&self, tile: &MeshTile) -> PolyRef {
+        let it = self
+            .tiles
+            .iter()
+            .position(|t| t.as_ref().is_some_and(|t| std::ptr::eq(t, tile)))
+            .expect("tile belongs to this navmesh");
+        self.encode_poly_id(tile.salt, it, 0)
+    }
+
+    pub fn off_mesh_connection_by_ref(&self, r: PolyRef) -> Option<&TileOffMeshConnection> {
+        let (tile, poly) = self.tile_and_poly_by_ref(r).ok()?;
+        if poly.poly_type != PolyType::OffMeshConnection {
+            return None;
+        }
+        let (_, _, ip) = self.decode_poly_id(r);
+        tile.data
+            .off_mesh_cons
+            .get(ip - tile.data.header.off_mesh_base)
+    }
+}
+
+// Link polygons to their neighbours within the tile
+fn connect_int_links(tile: &mut MeshTile, base: PolyRef) {
+    for ip in 0..tile.data.polys.len() {
+        tile.data.polys[ip].first_link = DT_NULL_LINK;
+        if tile.data.polys[ip].poly_type != PolyType::Ground {
+            continue;
+        }
+        // Walk the edges backwards so the list ends up in edge order
+        for j in (0..tile.data.polys[ip].vert_count as usize).rev() {
+            let nei = tile.data.polys[ip].neis[j];
+            if nei == 0 || nei & DT_EXT_LINK != 0 {
+                continue;
+            }
+            tile.push_link(
+                ip,
+                Link {
+                    poly_ref: base | (nei - 1) as PolyRef,
+                    edge: j as u8,
+                    side: 0xff,
+                    ..Default::default()
+                },
+            );
+        }
+    }
+}
+
+// Anchor the start of each off-mesh connection to the ground polygon below
+// it, linking both ways
+fn base_off_mesh_links(tile: &mut MeshTile, base: PolyRef) {
+    for n in 0..tile.data.off_mesh_cons.len() {
+        let con = tile.data.off_mesh_cons[n];
+        let half_extents = Vec3::new(con.rad, tile.data.header.walkable_climb, con.rad);
+        let Some((land, nearest, _)) = tile.find_nearest_poly(con.pos[0], half_extents, |_| true)
+        else {
+            continue;
+        };
+        if dist_sqr_2d(nearest, con.pos[0]) > con.rad * con.rad {
+            continue;
+        }
+
+        // Snap the connection onto the surface
+        let start_vert = tile.data.polys[con.poly as usize].verts[0];
+        tile.data.verts[start_vert as usize] = nearest;
+
+        tile.push_link(
+            con.poly as usize,
+            Link {
+                poly_ref: base | land as PolyRef,
+                edge: 0,
+                side: 0xff,
+                ..Default::default()
+            },
+        );
+        // The start is always reachable from the ground
+        tile.push_link(
+            land,
+            Link {
+                poly_ref: base | con.poly as PolyRef,
+                edge: 0xff,
+                side: 0xff,
+                ..Default::default()
+            },
+        );
+    }
+}
+
+// Land the end of each off-mesh connection that ends inside its own tile,
+// linking back from the ground for bidirectional ones
+fn connect_int_off_mesh_links(tile: &mut MeshTile, base: PolyRef) {
+    for n in 0..tile.data.off_mesh_cons.len() {
+        let con = tile.data.off_mesh_cons[n];
+        if con.side != 0xff {
+            continue;
+        }
+        // Connections whose start could not be anchored stay unlinked
+        if tile.data.polys[con.poly as usize].first_link == DT_NULL_LINK {
+            continue;
+        }
+        let half_extents = Vec3::new(con.rad, tile.data.header.walkable_climb, con.rad);
+        let Some((land, nearest, _)) = tile.find_nearest_poly(con.pos[1], half_extents, |_| true)
+        else {
+            continue;
+        };
+        if dist_sqr_2d(nearest, con.pos[1]) > con.rad * con.rad {
+            continue;
+        }
+
+        let end_vert = tile.data.polys[con.poly as usize].verts[1];
+        tile.data.verts[end_vert as usize] = nearest;
+
+        tile.push_link(
+            con.poly as usize,
+            Link {
+                poly_ref: base | land as PolyRef,
+                edge: 1,
+                side: 0xff,
+                ..Default::default()
+            },
+        );
+        if con.flags & DT_OFFMESH_CON_BIDIR != 0 {
+            tile.push_link(
+                land,
+                Link {
+                    poly_ref: base | con.poly as PolyRef,
+                    edge: 0xff,
+                    side: 0xff,
+                    ..Default::default()
+                },
+            );
+        }
+    }
+}
+
+// Detour numbers the eight tile sides counter-clockwise from +x, with
+// +z at 2 and tile y running along z
+fn tile_side_offset(side: u8) -> (i32, i32) {
+    match side & 7 {
+        0 => (1, 0),
+        1 => (1, 1),
+        2 => (0, 1),
+        3 => (-1, 1),
+        4 => (-1, 0),
+        5 => (-1, -1),
+        6 => (0, -1),
+        _ => (1, -1),
+    }
+}
+
+fn opposite_tile(side: u8) -> u8 {
+    (side + 4) & 7
+}
+
+// Ground polygons of `tile` with a portal on `side` running along the
+// segment va-vb, within climbing height of it, with the overlapping range
+// along the portal axis
+fn find_connecting_polys(va: Vec3, vb: Vec3, tile: &MeshTile, side: u8) -> Vec<(usize, f32, f32)> {
+    let (amin, amax) = slab_end_points(va, vb, side);
+    let apos = slab_coord(va, side);
+    let portal = DT_EXT_LINK | side as u16;
+
+    let mut found = Vec::new();
+    for ip in 0..tile.data.header.off_mesh_base {
+        let poly = &tile.data.polys[ip];
+        let nv = poly.vert_count as usize;
+        for j in 0..nv {
+            if poly.neis[j] != portal {
+                continue;
+            }
+            let vc = tile.data.verts[poly.verts[j] as usize];
+            let vd = tile.data.verts[poly.verts[(j + 1) % nv] as usize];
+            // Portals must lie on the same tile boundary
+            if (apos - slab_coord(vc, side)).abs() > 0.01 {
+                continue;
+            }
+            let (bmin, bmax) = slab_end_points(vc, vd, side);
+            if !overlap_slabs(
+                amin,
+                amax,
+                bmin,
+                bmax,
+                0.01,
+                tile.data.header.walkable_climb,
+            ) {
+                continue;
+            }
+            found.push((ip, amin.0.max(bmin.0), amax.0.min(bmax.0)));
+            break;
+        }
+    }
+    found
+}
+
+// Position of a portal across the axis it runs along
+fn slab_coord(v: Vec3, side: u8) -> f32 {
+    if side == 0 || side == 4 {
+        v.x
+    } else {
+        v.z
+    }
+}
+
+// Ends of a portal as (position along it, height), lowest position first
+fn slab_end_points(va: Vec3, vb: Vec3, side: u8) -> ((f32, f32), (f32, f32)) {
+    let (a, b) = if side == 0 || side == 4 {
+        ((va.z, va.y), (vb.z, vb.y))
+    } else {
+        ((va.x, va.y), (vb.x, vb.y))
+    };
+    if a.0 < b.0 {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+// Whether two portal slabs overlap by more than `px` along the portal and
+// come within `py` of each other in height, or cross
+fn overlap_slabs(
+    amin: (f32, f32),
+    amax: (f32, f32),
+    bmin: (f32, f32),
+    bmax: (f32, f32),
+    px: f32,
+    py: f32,
+) -> bool {
+    let minx = (amin.0 + px).max(bmin.0 + px);
+    let maxx = (amax.0 - px).min(bmax.0 - px);
+    if minx > maxx {
+        return false;
+    }
+
+    // Heights of both portals at the ends of the overlap
+    let height_at = |lo: (f32, f32), hi: (f32, f32), x: f32| {
+        let slope = (hi.1 - lo.1) / (hi.0 - lo.0);
+        lo.1 + slope * (x - lo.0)
+    };
+    let dmin = height_at(bmin, bmax, minx) - height_at(amin, amax, minx);
+    let dmax = height_at(bmin, bmax, maxx) - height_at(amin, amax, maxx);
+    if dmin * dmax < 0.0 {
+        return true;
+    }
+    let thr = (py * 2.0) * (py * 2.0);
+    dmin * dmin <= thr || dmax * dmax <= thr
+}
+
+pub fn overlap_bounds(amin: Vec3, amax: Vec3, bmin: Vec3, bmax: Vec3) -> bool {
+    amin.cmple(bmax).all() && amax.cmpge(bmin).all()
+}
+
+fn dist_sqr_2d(a: Vec3, b: Vec3) -> f32 {
+    let (dx, dz) = (b.x - a.x, b.z - a.z);
+    dx * dx + dz * dz
+}
+
+// Squared XZ distance from `pt` to segment p-q, and the parameter of the
+// closest point along it
+pub fn distance_pt_seg_sqr_2d(pt: Vec3, p: Vec3, q: Vec3) -> (f32, f32) {
+    let (pqx, pqz) = (q.x - p.x, q.z - p.z);
+    let (dx, dz) = (pt.x - p.x, pt.z - p.z);
+    let d = pqx * pqx + pqz * pqz;
+    let mut t = pqx * dx + pqz * dz;
+    if d > 0.0 {
+        t /= d;
+    }
+    let t = t.clamp(0.0, 1.0);
+    let (dx, dz) = (p.x + t * pqx - pt.x, p.z + t * pqz - pt.z);
+    (dx * dx + dz * dz, t)
+}
+
+// Crossing test on the XZ plane
+pub fn point_in_polygon(pt: Vec3, verts: &[Vec3]) -> bool {
+    let n = verts.len();
+    let mut inside = false;
+    for i in 0..n {
+        let (vi, vj) = (verts[i], verts[(i + n - 1) % n]);
+        if (vi.z > pt.z) != (vj.z > pt.z)
+            && pt.x < (vj.x - vi.x) * (pt.z - vi.z) / (vj.z - vi.z) + vi.x
+        {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+// Height of triangle a-b-c at `p` on the XZ plane, if `p` lies within it
+fn closest_height_point_triangle(p: Vec3, a: Vec3, b: Vec3, c: Vec3) -> Option<f32> {
+    const EPS: f32 = 1e-6;
+    let v0 = c - a;
+    let v1 = b - a;
+    let v2 = p - a;
+
+    let denom = v0.x * v1.z - v0.z * v1.x;
+    if denom.abs() < EPS {
+        return None;
+    }
+    let mut u = v1.z * v2.x - v1.x * v2.z;
+    let mut v = v0.x * v2.z - v0.z * v2.x;
+    if denom < 0.0 {
+        u = -u;
+        v = -v;
+    }
+    let denom = denom.abs();
+
+    if u >= 0.0 && v >= 0.0 && u + v <= denom {
+        Some(a.y + (v0.y * u + v1.y * v) / denom)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debug_draw::RC_WALKABLE_AREA;
+
+    // Two unit-cell quads side by side along X, sharing the edge at x = 2
+    fn two_quads() -> PolyMesh {
+        let n = RC_MESH_NULL_IDX;
+        PolyMesh {
+            verts: vec![
+                Vec3::new(0.0, 1.0, 0.0),
+                Vec3::new(0.0, 1.0, 2.0),
+                Vec3::new(2.0, 1.0, 2.0),
+                Vec3::new(2.0, 1.0, 0.0),
+                Vec3::new(4.0, 1.0, 2.0),
+                Vec3::new(4.0, 1.0, 0.0),
+            ],
+            polys: vec![vec![0, 1, 2, 3, n, n], vec![3, 2, 4, 5, n, n]],
+            neighbours: vec![vec![n, n, 1, n, n, n], vec![0, n, n, n, n, n]],
+            regs: vec![1, 1],
+            flags: vec![1, 1],
+            areas: vec![RC_WALKABLE_AREA; 2],
+            nvp: 6,
+            cs: 1.0,
+            ch: 0.5,
+            bmin: Vec3::ZERO,
+            bmax: Vec3::new(4.0, 2.0, 2.0),
+            border_size: 0,
+            max_edge_error: 1.3,
+        }
+    }
+
+    #[test]
+    fn test_single_tile_links_neighbours() {
+        let mesh = two_quads();
+        let params = NavMeshCreateParams::new(&mesh, None, 2.0, 0.5, 0.5);
+        let data = NavMeshData::build(&params).unwrap();
+        assert_eq!(data.verts[2], Vec3::new(2.0, 0.5, 2.0));
+        // Two leaves under one root
+        assert_eq!(data.bv_tree.len(), 3);
+        assert_eq!(data.bv_tree[0].i, -3);
+        assert_eq!(data.detail_tris.len(), 4);
+
+        let nav_mesh = NavMesh::single_tile(data).unwrap();
+        let tile = nav_mesh.tiles().next().unwrap();
+        let base = nav_mesh.poly_ref_base(tile);
+        for (ip, edge, other) in [(0, 2, 1), (1, 0, 0)] {
+            let links: Vec<&Link> = tile.links_of(ip).collect();
+            assert_eq!(links.len(), 1);
+            assert_eq!(links[0].edge, edge);
+            assert_eq!(links[0].poly_ref, base | other);
+        }
+
+        // Height comes from the (flat) detail triangles
+        let (point, over) = tile.closest_point_on_poly(1, Vec3::new(3.0, 4.0, 1.0));
+        assert!(over);
+        assert_eq!(point, Vec3::new(3.0, 0.5, 1.0));
+        let (point, over) = tile.closest_point_on_poly(0, Vec3::new(-1.0, 0.5, 1.0));
+        assert!(!over);
+        assert_eq!(point, Vec3::new(0.0, 0.5, 1.0));
+    }
+
+    #[test]
+    fn test_off_mesh_connections_are_linked() {
+        let mesh = two_quads();
+        let cons = [
+            // From the first quad to the second, both ways
+            OffMeshConnection {
+                start: Vec3::new(1.0, 0.6, 1.0),
+                end: Vec3::new(3.0, 0.6, 1.0),
+                radius: 0.5,
+                bidirectional: true,
+                area: 5,
+            },
+            // Starts outside the tile, so another tile would own it
+            OffMeshConnection {
+                start: Vec3::new(-3.0, 0.5, 1.0),
+                end: Vec3::new(1.0, 0.5, 1.0),
+                radius: 0.5,
+                bidirectional: false,
+                area: 5,
+            },
+        ];
+        let params = NavMeshCreateParams {
+            off_mesh_connections: &cons,
+            ..NavMeshCreateParams::new(&mesh, None, 2.0, 0.5, 0.5)
+        };
+        let nav_mesh = NavMesh::single_tile(NavMeshData::build(&params).unwrap()).unwrap();
+        let tile = nav_mesh.tiles().next().unwrap();
+        assert_eq!(tile.data.off_mesh_cons.len(), 1);
+        assert_eq!(tile.data.polys.len(), 3);
+
+        let base = nav_mesh.poly_ref_base(tile);
+        let con = nav_mesh.off_mesh_connection_by_ref(base | 2).unwrap();
+        assert_eq!(con.side, 0xff);
+        assert_eq!(con.flags, DT_OFFMESH_CON_BIDIR);
+
+        let targets: Vec<(PolyRef, u8)> = tile.links_of(2).map(|l| (l.poly_ref, l.edge)).collect();
+        assert_eq!(targets, vec![(base | 1, 1), (base, 0)]);
+        // Both ground polygons link back into the connection
+        for ip in 0..2 {
+            assert!(tile
+                .links_of(ip)
+                .any(|l| l.poly_ref == base | 2 && l.edge == 0xff));
+        }
+        // The end points were snapped onto the surface
+        assert_eq!(tile.data.verts[6].y, 0.5);
+    }
+
+    #[test]
+    fn test_refs_and_tile_slots() {
+        let mesh = two_quads();
+        let params = NavMeshCreateParams::new(&mesh, None, 2.0, 0.5, 0.5);
+        let data = NavMeshData::build(&params).unwrap();
+
+        let mut nav_mesh = NavMesh::new(NavMeshParams {
+            orig: Vec3::ZERO,
+            tile_width: 4.0,
+            tile_height: 4.0,
+            max_tiles: 2,
+            max_polys: 16,
+        })
+        .unwrap();
+        let tile_ref = nav_mesh.add_tile(data.clone()).unwrap();
+        assert_eq!(nav_mesh.decode_poly_id(tile_ref | 1), (1, 0, 1));
+        assert!(nav_mesh.is_valid_poly_ref(tile_ref | 1));
+        assert!(!nav_mesh.is_valid_poly_ref(tile_ref | 2));
+        assert!(!nav_mesh.is_valid_poly_ref(0));
+        assert!(nav_mesh.tile_at(0, 0, 0).is_some());
+
+        assert_eq!(
+            nav_mesh.add_tile(data).unwrap_err(),
+            NavMeshError::TileExists {
+                x: 0,
+                y: 0,
+                layer: 0
+            }
+        );
+
+        let empty = PolyMesh::default();
+        let params = NavMeshCreateParams::new(&empty, None, 2.0, 0.5, 0.5);
+        assert_eq!(
+            NavMeshData::build(&params).unwrap_err(),
+            NavMeshError::EmptyMesh
+        );
+    }
+
+    // A quad over x0..x0+2 and z0..z1 in its own tile, with portals on the
+    // listed Recast sides (0: -x, 2: +x)
+    fn tile_quad(tile_x: i32, z1: f32, portals: &[u16]) -> NavMeshData {
+        let n = RC_MESH_NULL_IDX;
+        let portal = |side| {
+            if portals.contains(&side) {
+                RC_MESH_PORTAL_FLAG | side
+            } else {
+                n
+            }
+        };
+        let mesh = PolyMesh {
+            verts: vec![
+                Vec3::new(0.0, 1.0, 0.0),
+                Vec3::new(0.0, 1.0, z1),
+                Vec3::new(2.0, 1.0, z1),
+                Vec3::new(2.0, 1.0, 0.0),
+            ],
+            polys: vec![vec![0, 1, 2, 3, n, n]],
+            neighbours: vec![vec![portal(0), n, portal(2), n, n, n]],
+            regs: vec![1],
+            flags: vec![1],
+            areas: vec![RC_WALKABLE_AREA],
+            nvp: 6,
+            cs: 1.0,
+            ch: 0.5,
+            bmin: Vec3::new(2.0 * tile_x as f32, 0.0, 0.0),
+            bmax: Vec3::new(2.0 * tile_x as f32 + 2.0, 2.0, 2.0),
+            border_size: 0,
+            max_edge_error: 1.3,
+        };
+        let params = NavMeshCreateParams {
+            tile_x,
+            ..NavMeshCreateParams::new(&mesh, None, 2.0, 0.5, 0.5)
+        };
+        NavMeshData::build(&params).unwrap()
+    }
+
+    #[test]
+    fn test_neighbour_tiles_are_linked() {
+        let mut nav_mesh = NavMesh::new(NavMeshParams {
+            orig: Vec3::ZERO,
+            tile_width: 2.0,
+            tile_height: 2.0,
+            max_tiles: 4,
+            max_polys: 4,
+        })
+        .unwrap();
+        let left = nav_mesh.add_tile(tile_quad(0, 2.0, &[2])).unwrap();
+        // Only covers the lower half of the shared edge
+        let right = nav_mesh.add_tile(tile_quad(1, 1.0, &[0])).unwrap();
+
+        let links = |tile_x| -> Vec<Link> {
+            let tile = nav_mesh.tile_at(tile_x, 0, 0).unwrap();
+            tile.links_of(0).copied().collect()
+        };
+        let from_left = links(0);
+        assert_eq!(from_left.len(), 1);
+        assert_eq!((from_left[0].poly_ref, from_left[0].side), (right, 0));
+        // The left edge runs from z = 2 down to z = 0
+        assert!((127..=128).contains(&from_left[0].bmin));
+        assert_eq!(from_left[0].bmax, 255);
+
+        let from_right = links(1);
+        assert_eq!(from_right.len(), 1);
+        assert_eq!((from_right[0].poly_ref, from_right[0].side), (left, 4));
+        assert_eq!((from_right[0].bmin, from_right[0].bmax), (0, 255));
+
+        // A tile further away has nothing to link to
+        let far = nav_mesh.add_tile(tile_quad(3, 2.0, &[0])).unwrap();
+        let (tile, _) = nav_mesh.tile_and_poly_by_ref(far).unwrap();
+        assert_eq!(tile.links_of(0).count(), 0);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let mut nav_mesh = NavMesh::new(NavMeshParams {
+            orig: Vec3::ZERO,
+            tile_width: 2.0,
+            tile_height: 2.0,
+            max_tiles: 4,
+            max_polys: 4,
+        })
+        .unwrap();
+        nav_mesh.add_tile(tile_quad(0, 2.0, &[2])).unwrap();
+        nav_mesh.add_tile(tile_quad(1, 2.0, &[0])).unwrap();
+
+        let json = serde_json::to_string(&nav_mesh).unwrap();
+        let loaded: NavMesh = serde_json::from_str(&json).unwrap();
+        for x in 0..2 {
+            let (a, b) = (
+                nav_mesh.tile_at(x, 0, 0).unwrap(),
+                loaded.tile_at(x, 0, 0).unwrap(),
+            );
+            assert_eq!(a.data, b.data);
+            assert_eq!(a.links, b.links);
+        }
+        // Slots and salts carry over, so references stay valid
+        let r = nav_mesh.poly_ref_base(nav_mesh.tile_at(1, 0, 0).unwrap());
+        assert!(loaded.is_valid_poly_ref(r));
+
+        let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        value["salts"] = serde_json::json!([1]);
+        assert!(serde_json::from_value::<NavMesh>(value).is_err());
+    }
+
+    #[test]
+    fn test_remove_tile_unlinks_neighbours() {
+        let mut nav_mesh = NavMesh::new(NavMeshParams {
+            orig: Vec3::ZERO,
+            tile_width: 2.0,
+            tile_height: 2.0,
+            max_tiles: 2,
+            max_polys: 4,
+        })
+        .unwrap();
+        let left = nav_mesh.add_tile(tile_quad(0, 2.0, &[2])).unwrap();
+        let right = nav_mesh.add_tile(tile_quad(1, 2.0, &[0])).unwrap();
+        let left_links =
+            |nav_mesh: &NavMesh| nav_mesh.tile_at(0, 0, 0).unwrap().links_of(0).count();
+        assert_eq!(left_links(&nav_mesh), 1);
+
+        let data = nav_mesh.remove_tile(1, 0, 0).unwrap();
+        assert_eq!(left_links(&nav_mesh), 0);
+        assert!(!nav_mesh.is_valid_poly_ref(right));
+        assert!(nav_mesh.is_valid_poly_ref(left));
+        assert_eq!(
+            nav_mesh.remove_tile(1, 0, 0).unwrap_err(),
+            NavMeshError::NoTile {
+                x: 1,
+                y: 0,
+                layer: 0
+            }
+        );
+
+        // Paging it back in reuses the slot under a new salt and relinks
+        let again = nav_mesh.add_tile(data).unwrap();
+        assert_ne!(again, right);
+        assert!(!nav_mesh.is_valid_poly_ref(right));
+        assert_eq!(left_links(&nav_mesh), 1);
+        let link = *nav_mesh
+            .tile_at(0, 0, 0)
+            .unwrap()
+            .links_of(0)
+            .next()
+            .unwrap();
+        assert_eq!(link.poly_ref, again);
+    }
+}
@@ -0,0 +1,693 @@
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use glam::Vec3;
+
+use crate::nav_mesh::{
+    BvNode, MeshHeader, NavMesh, NavMeshData, NavMeshError, NavMeshParams, Poly, PolyDetail,
+    PolyType, TileOffMeshConnection, DT_EXT_LINK, DT_NULL_LINK,
+};
+use crate::navmesh_build::MAX_VERTS_PER_POLY;
+
+// Tile data as written by dtCreateNavMeshData
+pub const DT_NAVMESH_MAGIC: i32 = i32::from_be_bytes(*b"DNAV");
+pub const DT_NAVMESH_VERSION: i32 = 7;
+// Whole navmesh as written by the Recast demo's saveAll
+pub const NAVMESHSET_MAGIC: i32 = i32::from_be_bytes(*b"MSET");
+pub const NAVMESHSET_VERSION: i32 = 1;
+
+// Sizes of the C structs, which are written as they are in memory
+const HEADER_SIZE: usize = 100;
+const POLY_SIZE: usize = 32;
+const POLY_DETAIL_SIZE: usize = 12;
+const BV_NODE_SIZE: usize = 16;
+const OFF_MESH_CON_SIZE: usize = 36;
+const SET_HEADER_SIZE: usize = 40;
+
+// Width of dtPolyRef in the Detour build reading or writing the data.
+// RecastNavigation uses 32-bit refs unless built with DT_POLYREF64, whose
+// layout this crate's references follow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PolyRefWidth {
+    #[default]
+    Bits32,
+    Bits64,
+}
+
+impl PolyRefWidth {
+    fn link_size(self) -> usize {
+        match self {
+            PolyRefWidth::Bits32 => 12,
+            PolyRefWidth::Bits64 => 16,
+        }
+    }
+
+    fn tile_header_size(self) -> usize {
+        match self {
+            PolyRefWidth::Bits32 => 8,
+            PolyRefWidth::Bits64 => 16,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum NavMeshBinError {
+    Io(io::Error),
+    BadMagic(i32),
+    BadVersion(i32),
+    // The data ends early or its counts and indices don't add up
+    Corrupt(&'static str),
+    // The navmesh can't be expressed with 32-bit references
+    RefBitsExceeded { tile_bits: u32, poly_bits: u32 },
+    NavMesh(NavMeshError),
+}
+
+impl fmt::Display for NavMeshBinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NavMeshBinError::Io(e) => write!(f, "I/O error: {}", e),
+            NavMeshBinError::BadMagic(magic) => {
+                write!(f, "not a navmesh file (magic {:#x})", magic)
+            }
+            NavMeshBinError::BadVersion(version) => {
+                write!(f, "unsupported navmesh data version {}", version)
+            }
+            NavMeshBinError::Corrupt(reason) => write!(f, "corrupt navmesh data: {}", reason),
+            NavMeshBinError::RefBitsExceeded {
+                tile_bits,
+                poly_bits,
+            } => write!(
+                f,
+                "{} tile bits and {} polygon bits leave too few salt bits for 32-bit references",
+                tile_bits, poly_bits
+            ),
+            NavMeshBinError::NavMesh(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<io::Error> for NavMeshBinError {
+    fn from(error: io::Error) -> Self {
+        NavMeshBinError::Io(error)
+    }
+}
+
+impl From<NavMeshError> for NavMeshBinError {
+    fn from(error: NavMeshError) -> Self {
+        NavMeshBinError::NavMesh(error)
+    }
+}
+
+impl NavMeshData {
+    // The tile in Detour's binary layout, little-endian, with room for the
+    // links Detour creates when the tile is added
+    pub fn to_bin(&self, width: PolyRefWidth) -> Vec<u8> {
+        let mut out = Vec::new();
+        let header = &self.header;
+        for v in [
+            DT_NAVMESH_MAGIC,
+            DT_NAVMESH_VERSION,
+            header.x,
+            header.y,
+            header.layer,
+            header.user_id as i32,
+            self.polys.len() as i32,
+            self.verts.len() as i32,
+            self.max_link_count() as i32,
+            self.detail_meshes.len() as i32,
+            self.detail_verts.len() as i32,
+            self.detail_tris.len() as i32,
+            self.bv_tree.len() as i32,
+            self.off_mesh_cons.len() as i32,
+            header.off_mesh_base as i32,
+        ] {
+            out.extend_from_slice(&v.to_le_bytes());
+        }
+        for v in [
+            header.walkable_height,
+            header.walkable_radius,
+            header.walkable_climb,
+        ] {
+            out.extend_from_slice(&v.to_le_bytes());
+        }
+        put_vec3(&mut out, header.bmin);
+        put_vec3(&mut out, header.bmax);
+        out.extend_from_slice(&header.bv_quant_factor.to_le_bytes());
+
+        for &v in &self.verts {
+            put_vec3(&mut out, v);
+        }
+        for poly in &self.polys {
+            // Links are rebuilt when the tile is added
+            out.extend_from_slice(&DT_NULL_LINK.to_le_bytes());
+            for v in poly.verts.iter().chain(&poly.neis) {
+                out.extend_from_slice(&v.to_le_bytes());
+            }
+            out.extend_from_slice(&poly.flags.to_le_bytes());
+            out.push(poly.vert_count);
+            let poly_type = match poly.poly_type {
+                PolyType::Ground => 0,
+                PolyType::OffMeshConnection => 1,
+            };
+            out.push((poly.area & 0x3f) | poly_type << 6);
+        }
+        out.resize(out.len() + self.max_link_count() * width.link_size(), 0);
+        for pd in &self.detail_meshes {
+            out.extend_from_slice(&pd.vert_base.to_le_bytes());
+            out.extend_from_slice(&pd.tri_base.to_le_bytes());
+            out.extend_from_slice(&[pd.vert_count, pd.tri_count, 0, 0]);
+        }
+        for &v in &self.detail_verts {
+            put_vec3(&mut out, v);
+        }
+        for tri in &self.detail_tris {
+            out.extend_from_slice(tri);
+        }
+        for node in &self.bv_tree {
+            for v in node.bmin.iter().chain(&node.bmax) {
+                out.extend_from_slice(&v.to_le_bytes());
+            }
+            out.extend_from_slice(&node.i.to_le_bytes());
+        }
+        for con in &self.off_mesh_cons {
+            put_vec3(&mut out, con.pos[0]);
+            put_vec3(&mut out, con.pos[1]);
+            out.extend_from_slice(&con.rad.to_le_bytes());
+            out.extend_from_slice(&con.poly.to_le_bytes());
+            out.extend_from_slice(&[con.flags, con.side]);
+            out.extend_from_slice(&con.user_id.to_le_bytes());
+        }
+        out
+    }
+
+    // Read a tile written by `to_bin` or by dtCreateNavMeshData on a
+    // little-endian machine, with either reference width
+    pub fn from_bin(bytes: &[u8]) -> Result<Self, NavMeshBinError> {
+        let mut r = BinReader { bytes, pos: 0 };
+        let magic = r.i32()?;
+        if magic != DT_NAVMESH_MAGIC {
+            return Err(NavMeshBinError::BadMagic(magic));
+        }
+        let version = r.i32()?;
+        if version != DT_NAVMESH_VERSION {
+            return Err(NavMeshBinError::BadVersion(version));
+        }
+        let (x, y, layer) = (r.i32()?, r.i32()?, r.i32()?);
+        let user_id = r.u32()?;
+        let poly_count = r.count()?;
+        let vert_count = r.count()?;
+        let max_link_count = r.count()?;
+        let detail_mesh_count = r.count()?;
+        let detail_vert_count = r.count()?;
+        let detail_tri_count = r.count()?;
+        let bv_node_count = r.count()?;
+        let off_mesh_con_count = r.count()?;
+        let off_mesh_base = r.count()?;
+        let header = MeshHeader {
+            x,
+            y,
+            layer,
+            user_id,
+            walkable_height: r.f32()?,
+            walkable_radius: r.f32()?,
+            walkable_climb: r.f32()?,
+            bmin: r.vec3()?,
+            bmax: r.vec3()?,
+            bv_quant_factor: r.f32()?,
+            off_mesh_base,
+        };
+
+        // The link size, and so the reference width, follows from whatever
+        // is left over after the fixed-size arrays
+        let fixed = HEADER_SIZE
+            + vert_count * 12
+            + poly_count * POLY_SIZE
+            + detail_mesh_count * POLY_DETAIL_SIZE
+            + detail_vert_count * 12
+            + detail_tri_count * 4
+            + bv_node_count * BV_NODE_SIZE
+            + off_mesh_con_count * OFF_MESH_CON_SIZE;
+        let links_size = bytes
+            .len()
+            .checked_sub(fixed)
+            .ok_or(NavMeshBinError::Corrupt("data ends early"))?;
+        if ![PolyRefWidth::Bits32, PolyRefWidth::Bits64]
+            .iter()
+            .any(|w| w.link_size() * max_link_count == links_size)
+        {
+            return Err(NavMeshBinError::Corrupt(
+                "data size doesn't match its header",
+            ));
+        }
+
+        let mut data = NavMeshData {
+            header,
+            ..Default::default()
+        };
+        for _ in 0..vert_count {
+            data.verts.push(r.vec3()?);
+        }
+        for _ in 0..poly_count {
+            r.u32()?;
+            let mut poly = Poly {
+                first_link: DT_NULL_LINK,
+                verts: [0; MAX_VERTS_PER_POLY],
+                neis: [0; MAX_VERTS_PER_POLY],
+                flags: 0,
+                vert_count: 0,
+                area: 0,
+                poly_type: PolyType::Ground,
+            };
+            for v in poly.verts.iter_mut().chain(poly.neis.iter_mut()) {
+                *v = r.u16()?;
+            }
+            poly.flags = r.u16()?;
+            poly.vert_count = r.u8()?;
+            let area_and_type = r.u8()?;
+            poly.area = area_and_type & 0x3f;
+            poly.poly_type = match area_and_type >> 6 {
+                0 => PolyType::Ground,
+                1 => PolyType::OffMeshConnection,
+                _ => return Err(NavMeshBinError::Corrupt("unknown polygon type")),
+            };
+            data.polys.push(poly);
+        }
+        r.skip(links_size)?;
+        for _ in 0..detail_mesh_count {
+            let vert_base = r.u32()?;
+            let tri_base = r.u32()?;
+            let (vert_count, tri_count) = (r.u8()?, r.u8()?);
+            r.skip(2)?;
+            data.detail_meshes.push(PolyDetail {
+                vert_base,
+                tri_base,
+                vert_count,
+                tri_count,
+            });
+        }
+        for _ in 0..detail_vert_count {
+            data.detail_verts.push(r.vec3()?);
+        }
+        for _ in 0..detail_tri_count {
+            data.detail_tris.push([r.u8()?, r.u8()?, r.u8()?, r.u8()?]);
+        }
+        for _ in 0..bv_node_count {
+            let mut node = BvNode::default();
+            for v in node.bmin.iter_mut().chain(node.bmax.iter_mut()) {
+                *v = r.u16()?;
+            }
+            node.i = r.i32()?;
+            data.bv_tree.push(node);
+        }
+        for _ in 0..off_mesh_con_count {
+            data.off_mesh_cons.push(TileOffMeshConnection {
+                pos: [r.vec3()?, r.vec3()?],
+                rad: r.f32()?,
+                poly: r.u16()?,
+                flags: r.u8()?,
+                side: r.u8()?,
+                user_id: r.u32()?,
+            });
+        }
+        data.validate()?;
+        Ok(data)
+    }
+
+    // Links Detour allocates for the tile: one per polygon edge, two more
+    // per tile portal and per off-mesh connection end in the tile
+    fn max_link_count(&self) -> usize {
+        let edges: usize = self.polys[..self.header.off_mesh_base.min(self.polys.len())]
+            .iter()
+            .map(|p| {
+                let portals = p
+                    .neis
+                    .iter()
+                    .take(p.vert_count as usize)
+                    .filter(|&&n| n & DT_EXT_LINK != 0 && n & 0xf != 0xf)
+                    .count();
+                p.vert_count as usize + portals * 2
+            })
+            .sum();
+        let off_mesh_ends: usize = self
+            .off_mesh_cons
+            .iter()
+            .map(|con| if con.side == 0xff { 2 } else { 1 })
+            .sum();
+        edges + off_mesh_ends * 2
+    }
+
+    // Check every index stays inside its array, so a tile read from disk
+    // can't make queries panic
+    fn validate(&self) -> Result<(), NavMeshBinError> {
+        let corrupt = NavMeshBinError::Corrupt;
+        if self.header.off_mesh_base > self.polys.len() {
+            return Err(corrupt("off-mesh base beyond the polygons"));
+        }
+        if self.detail_meshes.len() < self.header.off_mesh_base {
+            return Err(corrupt("ground polygons without a detail mesh"));
+        }
+        for poly in &self.polys {
+            let nv = poly.vert_count as usize;
+            if nv > MAX_VERTS_PER_POLY
+                || poly
+                    .vert_indices()
+                    .iter()
+                    .any(|&v| v as usize >= self.verts.len())
+            {
+                return Err(corrupt("polygon vertex out of range"));
+            }
+            if poly.neis[..nv]
+                .iter()
+                .any(|&n| n & DT_EXT_LINK == 0 && n as usize > self.polys.len())
+            {
+                return Err(corrupt("polygon neighbour out of range"));
+            }
+        }
+        for (ip, pd) in self.detail_meshes.iter().enumerate() {
+            let verts = pd.vert_base as usize + pd.vert_count as usize;
+            let tris = pd.tri_base as usize + pd.tri_count as usize;
+            if verts > self.detail_verts.len() || tris > self.detail_tris.len() {
+                return Err(corrupt("detail mesh out of range"));
+            }
+            let nv = self.polys.get(ip).map_or(0, |p| p.vert_count as usize);
+            if self.detail_tris[pd.tri_base as usize..tris]
+                .iter()
+                .any(|t| {
+                    t[..3]
+                        .iter()
+                        .any(|&k| k as usize >= nv + pd.vert_count as usize)
+                })
+            {
+                return Err(corrupt("detail triangle vertex out of range"));
+            }
+        }
+        if self
+            .bv_tree
+            .iter()
+            .any(|node| node.i >= 0 && node.i as usize >= self.polys.len())
+        {
+            return Err(corrupt("BV tree leaf out of range"));
+        }
+        if self
+            .off_mesh_cons
+            .iter()
+            .any(|con| con.poly as usize >= self.polys.len())
+        {
+            return Err(corrupt("off-mesh connection polygon out of range"));
+        }
+        Ok(())
+    }
+}
+
+impl NavMesh {
+    // Write every tile in the Recast demo's navmesh set layout, which
+    // Detour-based engines commonly load with the same few lines of code.
+    // 32-bit references, as in a default RecastNavigation build.
+    pub fn save_bin(&self, writer: &mut impl Write) -> Result<(), NavMeshBinError> {
+        self.save_bin_with(writer, PolyRefWidth::Bits32)
+    }
+
+    pub fn save_bin_with(
+        &self,
+        writer: &mut impl Write,
+        width: PolyRefWidth,
+    ) -> Result<(), NavMeshBinError> {
+        let params = self.params();
+        // Detour's 32-bit references fit as many tile and polygon bits as
+        // the parameters need, and the salt in what's left
+        let tile_bits = params.max_tiles.next_power_of_two().trailing_zeros();
+        let poly_bits = params.max_polys.next_power_of_two().trailing_zeros();
+        let salt_bits = 32u32.saturating_sub(tile_bits + poly_bits).min(31);
+        if width == PolyRefWidth::Bits32 && salt_bits < 10 {
+            return Err(NavMeshBinError::RefBitsExceeded {
+                tile_bits,
+                poly_bits,
+            });
+        }
+
+        let tiles: Vec<_> = self.tiles_with_refs().collect();
+        let mut out = Vec::with_capacity(SET_HEADER_SIZE);
+        for v in [NAVMESHSET_MAGIC, NAVMESHSET_VERSION, tiles.len() as i32] {
+            out.extend_from_slice(&v.to_le_bytes());
+        }
+        put_vec3(&mut out, params.orig);
+        out.extend_from_slice(&params.tile_width.to_le_bytes());
+        out.extend_from_slice(&params.tile_height.to_le_bytes());
+        out.extend_from_slice(&(params.max_tiles as i32).to_le_bytes());
+        out.extend_from_slice(&(params.max_polys as i32).to_le_bytes());
+        writer.write_all(&out)?;
+
+        for (base, tile) in tiles {
+            let data = tile.data.to_bin(width);
+            // The demo loader stops at a zero tile reference, and Detour
+            // puts the tile back in the same slot with the same salt
+            let (salt, it, _) = self.decode_poly_id(base);
+            let mut header = Vec::with_capacity(width.tile_header_size());
+            match width {
+                PolyRefWidth::Bits32 => {
+                    let salt = salt & ((1 << salt_bits) - 1);
+                    let tile_ref = (salt << (tile_bits + poly_bits)) | ((it as u32) << poly_bits);
+                    header.extend_from_slice(&tile_ref.to_le_bytes());
+                    header.extend_from_slice(&(data.len() as i32).to_le_bytes());
+                }
+                PolyRefWidth::Bits64 => {
+                    header.extend_from_slice(&base.to_le_bytes());
+                    header.extend_from_slice(&(data.len() as i32).to_le_bytes());
+                    header.extend_from_slice(&[0; 4]);
+                }
+            }
+            writer.write_all(&header)?;
+            writer.write_all(&data)?;
+        }
+        Ok(())
+    }
+
+    // Read a navmesh set written by `save_bin` or by the Recast demo, with
+    // either reference width. Tiles take the first free slots, so polygon
+    // references may differ from those of the mesh that was saved.
+    pub fn load_bin(reader: &mut impl Read) -> Result<NavMesh, NavMeshBinError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let mut r = BinReader {
+            bytes: &bytes,
+            pos: 0,
+        };
+
+        let magic = r.i32()?;
+        if magic != NAVMESHSET_MAGIC {
+            return Err(NavMeshBinError::BadMagic(magic));
+        }
+        let version = r.i32()?;
+        if version != NAVMESHSET_VERSION {
+            return Err(NavMeshBinError::BadVersion(version));
+        }
+        let tile_count = r.i32()?;
+        let orig = r.vec3()?;
+        let (tile_width, tile_height) = (r.f32()?, r.f32()?);
+        let (max_tiles, max_polys) = (r.i32()?, r.i32()?);
+        let mut nav_mesh = NavMesh::new(NavMeshParams {
+            orig,
+            tile_width,
+            tile_height,
+            max_tiles: max_tiles.max(0) as usize,
+            max_polys: max_polys.max(0) as usize,
+        })?;
+
+        for _ in 0..tile_count {
+            let width = tile_header_width(&r)?;
+            let tile_ref = match width {
+                PolyRefWidth::Bits32 => r.u32()? as u64,
+                PolyRefWidth::Bits64 => r.u64()?,
+            };
+            let size = r.count()?;
+            if width == PolyRefWidth::Bits64 {
+                r.skip(4)?;
+            }
+            if tile_ref == 0 || size == 0 {
+                break;
+            }
+            let data = NavMeshData::from_bin(r.take(size)?)?;
+            nav_mesh.add_tile(data)?;
+        }
+        Ok(nav_mesh)
+    }
+}
+
+// Which tile header layout follows, told apart by where the tile magic is
+fn tile_header_width(r: &BinReader) -> Result<PolyRefWidth, NavMeshBinError> {
+    let magic_at = |offset: usize| {
+        r.bytes
+            .get(r.pos + offset..r.pos + offset + 4)
+            .is_some_and(|b| i32::from_le_bytes(b.try_into().unwrap()) == DT_NAVMESH_MAGIC)
+    };
+    [PolyRefWidth::Bits32, PolyRefWidth::Bits64]
+        .into_iter()
+        .find(|w| magic_at(w.tile_header_size()))
+        .ok_or(NavMeshBinError::Corrupt(
+            "tile header not followed by tile data",
+        ))
+}
+
+fn put_vec3(out: &mut Vec<u8>, v: Vec3) {
+    for c in v.to_array() {
+        out.extend_from_slice(&c.to_le_bytes());
+    }
+}
+
+// Little-endian cursor over a byte slice
+struct BinReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BinReader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], NavMeshBinError> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .filter(|&end| end <= self.bytes.len());
+        let end = end.ok_or(NavMeshBinError::Corrupt("data ends early"))?;
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn skip(&mut self, n: usize) -> Result<(), NavMeshBinError> {
+        self.take(n).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Result<u8, NavMeshBinError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, NavMeshBinError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, NavMeshBinError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, NavMeshBinError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> Result<i32, NavMeshBinError> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    // A non-negative int
+    fn count(&mut self) -> Result<usize, NavMeshBinError> {
+        usize::try_from(self.i32()?).map_err(|_| NavMeshBinError::Corrupt("negative count"))
+    }
+
+    fn f32(&mut self) -> Result<f32, NavMeshBinError> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn vec3(&mut self) -> Result<Vec3, NavMeshBinError> {
+        Ok(Vec3::new(self.f32()?, self.f32()?, self.f32()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debug_draw::{PolyMesh, RC_MESH_NULL_IDX, RC_WALKABLE_AREA};
+    use crate::nav_mesh::NavMeshCreateParams;
+    use crate::offmesh::OffMeshConnection;
+
+    // Two quads and an off-mesh connection from one to the other
+    fn tile() -> NavMeshData {
+        let n = RC_MESH_NULL_IDX;
+        let mesh = PolyMesh {
+            verts: vec![
+                Vec3::new(0.0, 1.0, 0.0),
+                Vec3::new(0.0, 1.0, 2.0),
+                Vec3::new(2.0, 1.0, 2.0),
+                Vec3::new(2.0, 1.0, 0.0),
+                Vec3::new(4.0, 1.0, 2.0),
+                Vec3::new(4.0, 1.0, 0.0),
+            ],
+            polys: vec![vec![0, 1, 2, 3, n, n], vec![3, 2, 4, 5, n, n]],
+            neighbours: vec![vec![n, n, 1, n, n, n], vec![0, n, n, n, n, n]],
+            regs: vec![1, 1],
+            flags: vec![1, 1],
+            areas: vec![RC_WALKABLE_AREA; 2],
+            nvp: 6,
+            cs: 1.0,
+            ch: 0.5,
+            bmax: Vec3::new(4.0, 2.0, 2.0),
+            ..Default::default()
+        };
+        let cons = [OffMeshConnection {
+            start: Vec3::new(0.5, 0.5, 1.0),
+            end: Vec3::new(3.5, 0.5, 1.0),
+            radius: 0.5,
+            bidirectional: true,
+            area: 5,
+        }];
+        let params = NavMeshCreateParams {
+            off_mesh_connections: &cons,
+            user_id: 42,
+            ..NavMeshCreateParams::new(&mesh, None, 2.0, 0.5, 0.5)
+        };
+        NavMeshData::build(&params).unwrap()
+    }
+
+    #[test]
+    fn test_tile_round_trip_matches_detour_layout() {
+        let data = tile();
+        for width in [PolyRefWidth::Bits32, PolyRefWidth::Bits64] {
+            let bytes = data.to_bin(width);
+            assert_eq!(&bytes[..4], b"VAND");
+            // 4 + 2 in-tile links per ground quad edge, 2 per connection end
+            let links = 8 + 4;
+            let expected = HEADER_SIZE
+                + data.verts.len() * 12
+                + data.polys.len() * POLY_SIZE
+                + links * width.link_size()
+                + data.detail_meshes.len() * POLY_DETAIL_SIZE
+                + data.detail_verts.len() * 12
+                + data.detail_tris.len() * 4
+                + data.bv_tree.len() * BV_NODE_SIZE
+                + data.off_mesh_cons.len() * OFF_MESH_CON_SIZE;
+            assert_eq!(bytes.len(), expected);
+            assert_eq!(NavMeshData::from_bin(&bytes).unwrap(), data);
+        }
+
+        let mut bytes = data.to_bin(PolyRefWidth::Bits32);
+        bytes.pop();
+        assert!(matches!(
+            NavMeshData::from_bin(&bytes),
+            Err(NavMeshBinError::Corrupt(_))
+        ));
+    }
+
+    #[test]
+    fn test_nav_mesh_set_round_trip() {
+        let nav_mesh = NavMesh::single_tile(tile()).unwrap();
+        for width in [PolyRefWidth::Bits32, PolyRefWidth::Bits64] {
+            let mut bytes = Vec::new();
+            nav_mesh.save_bin_with(&mut bytes, width).unwrap();
+            assert_eq!(&bytes[..4], b"TESM");
+
+            let loaded = NavMesh::load_bin(&mut bytes.as_slice()).unwrap();
+            assert_eq!(loaded.params(), nav_mesh.params());
+            let (a, b) = (
+                nav_mesh.tiles().next().unwrap(),
+                loaded.tiles().next().unwrap(),
+            );
+            assert_eq!(a.data, b.data);
+            assert_eq!(a.links, b.links);
+        }
+
+        let mut bytes = Vec::new();
+        nav_mesh.save_bin(&mut bytes).unwrap();
+        bytes[0] = b'X';
+        assert!(matches!(
+            NavMesh::load_bin(&mut bytes.as_slice()),
+            Err(NavMeshBinError::BadMagic(_))
+        ));
+    }
+}
@@ -0,0 +1,184 @@
+use std::collections::BTreeSet;
+
+use glam::Vec3;
+
+use crate::build_context::BuildContext;
+use crate::chunky_tri_mesh::{ChunkyTriMesh, DEFAULT_TRIS_PER_CHUNK};
+use crate::convex_volume::ConvexVolume;
+use crate::debug_draw_b::InputMesh;
+use crate::nav_mesh::{NavMesh, NavMeshParams};
+use crate::navmesh_build::{
+    bake_filtered, mark_walkable_triangles, rasterize_filtered, tile_data, NavMeshBuildConfig,
+    NavMeshBuildError, TileGrid, MAX_TILE_POLYS,
+};
+use crate::offmesh::OffMeshConnection;
+
+// Keeps the input of a tiled build next to the navmesh built from it, so
+// that when part of the geometry moves or goes away only the tiles under
+// it are baked again. The tile grid is fixed by the mesh the builder was
+// created with; later geometry outside its bounds is ignored.
+//
+// To move a piece of geometry, mark its old bounds dirty, swap in the new
+// mesh, mark the new bounds dirty and call `rebuild_dirty`.
+pub struct NavMeshBuilder {
+    mesh: InputMesh,
+    cfg: NavMeshBuildConfig,
+    volumes: Vec<ConvexVolume>,
+    off_mesh_connections: Vec<OffMeshConnection>,
+    grid: TileGrid,
+    // Rasterization inputs derived from `mesh`
+    areas: Vec<u8>,
+    chunky: ChunkyTriMesh,
+    nav_mesh: NavMesh,
+    // Tiles to re-bake on the next `rebuild_dirty`
+    dirty: BTreeSet<(i32, i32)>,
+    build_stats: BuildContext,
+}
+
+impl NavMeshBuilder {
+    // Bake every tile of `mesh`
+    pub fn build(
+        mesh: InputMesh,
+        cfg: &NavMeshBuildConfig,
+        volumes: &[ConvexVolume],
+        off_mesh_connections: &[OffMeshConnection],
+    ) -> Result<Self, NavMeshBuildError> {
+        cfg.validate()?;
+        if mesh.tri_count() == 0 {
+            return Err(NavMeshBuildError::EmptyMesh);
+        }
+
+        let grid = TileGrid::new(&mesh, cfg);
+        let nav_mesh = NavMesh::new(NavMeshParams {
+            max_tiles: (grid.width * grid.height).max(1) as usize,
+            ..grid.nav_mesh_params(MAX_TILE_POLYS)
+        })?;
+        let mut builder = Self {
+            areas: mark_walkable_triangles(cfg.agent_max_slope, &mesh),
+            chunky: ChunkyTriMesh::build(&mesh, DEFAULT_TRIS_PER_CHUNK),
+            mesh,
+            cfg: cfg.clone(),
+            volumes: volumes.to_vec(),
+            off_mesh_connections: off_mesh_connections.to_vec(),
+            dirty: grid.coords().into_iter().collect(),
+            grid,
+            nav_mesh,
+            build_stats: BuildContext::new(),
+        };
+        builder.rebuild_dirty()?;
+        Ok(builder)
+    }
+
+    pub fn nav_mesh(&self) -> &NavMesh {
+        &self.nav_mesh
+    }
+
+    pub fn mesh(&self) -> &InputMesh {
+        &self.mesh
+    }
+
+    pub fn grid(&self) -> &TileGrid {
+        &self.grid
+    }
+
+    // Stage timings of every tile baked so far
+    pub fn build_stats(&self) -> &BuildContext {
+        &self.build_stats
+    }
+
+    // Replace the source geometry. Nothing is rebuilt until the changed
+    // regions are marked dirty.
+    pub fn set_mesh(&mut self, mesh: InputMesh) {
+        self.areas = mark_walkable_triangles(self.cfg.agent_max_slope, &mesh);
+        self.chunky = ChunkyTriMesh::build(&mesh, DEFAULT_TRIS_PER_CHUNK);
+        self.mesh = mesh;
+    }
+
+    // Queue every tile whose voxelized area, border included, overlaps
+    // the box from `bmin` to `bmax`
+    pub fn mark_dirty(&mut self, bmin: Vec3, bmax: Vec3) {
+        let pad = self.grid.border_size as f32 * self.cfg.cell_size;
+        self.dirty
+            .extend(self.grid.tiles_overlapping(bmin, bmax, pad));
+    }
+
+    // Tiles waiting for `rebuild_dirty`
+    pub fn dirty_tiles(&self) -> impl Iterator<Item = &(i32, i32)> {
+        self.dirty.iter()
+    }
+
+    // Re-bake the dirty tiles from the current mesh and swap them into the
+    // navmesh. Returns the number of tiles rebuilt.
+    pub fn rebuild_dirty(&mut self) -> Result<usize, NavMeshBuildError> {
+        let dirty = std::mem::take(&mut self.dirty);
+        for &(tx, ty) in &dirty {
+            let cfg = &self.cfg;
+            let mut hf = self.grid.heightfield(tx, ty, cfg);
+            rasterize_filtered(
+                &mut hf,
+                &self.mesh,
+                Some(&self.chunky),
+                &self.areas,
+                cfg,
+                &mut self.build_stats,
+            );
+            let baked = bake_filtered(
+                &hf,
+                cfg,
+                &self.volumes,
+                self.grid.border_size,
+                &mut self.build_stats,
+            );
+            let data = tile_data(&baked, cfg, tx, ty, 0, &self.off_mesh_connections)?;
+
+            // The tile may have had no polygons before, or have none now
+            let _ = self.nav_mesh.remove_tile(tx, ty, 0);
+            if let Some(data) = data {
+                self.nav_mesh.add_tile(data)?;
+            }
+        }
+        Ok(dirty.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::navmesh_build::build_tiled_navmesh;
+    use crate::testgen::holed_floor;
+
+    #[test]
+    fn test_rebuild_only_dirty_tiles() {
+        let cfg = NavMeshBuildConfig::builder()
+            .min_region_area(8)
+            .merge_region_area(20)
+            .tile_size(16)
+            .build()
+            .unwrap();
+        let mut builder =
+            NavMeshBuilder::build(holed_floor(12, |_, _| false), &cfg, &[], &[]).unwrap();
+        assert_eq!(builder.nav_mesh().tiles().count(), 9);
+        assert_eq!(builder.dirty_tiles().count(), 0);
+        let far_tile = builder.nav_mesh().tile_at(2, 2, 0).unwrap().salt;
+
+        // Cut a hole in the corner of the first tile
+        let hole = |x: usize, z: usize| x < 2 && z < 2;
+        builder.set_mesh(holed_floor(12, hole));
+        builder.mark_dirty(Vec3::ZERO, Vec3::new(2.0, 0.0, 2.0));
+        assert_eq!(
+            builder.dirty_tiles().copied().collect::<Vec<_>>(),
+            vec![(0, 0)]
+        );
+        assert_eq!(builder.rebuild_dirty().unwrap(), 1);
+
+        // Same result as baking everything again, without touching the
+        // other tiles
+        let full = build_tiled_navmesh(&holed_floor(12, hole), &cfg, &[], &[]).unwrap();
+        let tile = |nav_mesh: &NavMesh| nav_mesh.tile_at(0, 0, 0).unwrap().data.clone();
+        assert_eq!(tile(builder.nav_mesh()), tile(&full));
+        assert_eq!(builder.nav_mesh().tile_at(2, 2, 0).unwrap().salt, far_tile);
+
+        builder.mark_dirty(Vec3::splat(-20.0), Vec3::splat(-10.0));
+        assert_eq!(builder.dirty_tiles().count(), 0);
+    }
+}
@@ -0,0 +1,2115 @@
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+use glam::Vec3;
+
+use crate::nav_mesh::{
+    distance_pt_seg_sqr_2d, overlap_bounds, point_in_polygon, MeshTile, NavMesh, NavMeshError,
+    Poly, PolyRef, PolyType,
+};
+use crate::query_filter::PolyFilter;
+
+// Scale on the straight-line heuristic, just under 1 so it never
+// overestimates the remaining cost
+const H_SCALE: f32 = 0.999;
+
+// Flags of the points returned by `find_straight_path`
+pub const DT_STRAIGHTPATH_START: u8 = 0x01;
+pub const DT_STRAIGHTPATH_END: u8 = 0x02;
+// The point starts an off-mesh connection
+pub const DT_STRAIGHTPATH_OFFMESH_CONNECTION: u8 = 0x04;
+
+// Corner of a straight path, with the polygon entered there
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StraightPathPoint {
+    pub pos: Vec3,
+    pub flags: u8,
+    // 0 for the end point
+    pub poly_ref: PolyRef,
+}
+
+// Polygon settled by a neighbourhood query
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReachedPoly {
+    pub poly_ref: PolyRef,
+    // Polygon it was reached from, 0 for the start polygon
+    pub parent_ref: PolyRef,
+    // Cost of the cheapest route to it from the query centre
+    pub cost: f32,
+}
+
+// Closest boundary point found by `find_distance_to_wall`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WallHit {
+    pub distance: f32,
+    pub pos: Vec3,
+    // Horizontal direction from the wall towards the query centre, zero if
+    // the centre lies on the wall
+    pub normal: Vec3,
+}
+
+// Result of `raycast`
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RaycastHit {
+    // Fraction of the segment travelled before hitting a wall, f32::MAX if
+    // the end was reached
+    pub t: f32,
+    // Horizontal normal of the wall hit, zero if none was
+    pub normal: Vec3,
+    // Polygons crossed, starting with the start polygon
+    pub path: Vec<PolyRef>,
+}
+
+// Polygon corridor from the start polygon towards the end polygon
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PolyPath {
+    pub polys: Vec<PolyRef>,
+    // The end was unreachable; the path leads to the polygon closest to it
+    pub partial: bool,
+    // The node pool ran out before the search finished
+    pub out_of_nodes: bool,
+}
+
+// Result of `query_point_to_point`
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PointToPoint {
+    // Nearest polygon to each end and the point on it, None if no polygon
+    // was in range
+    pub start: Option<(PolyRef, Vec3)>,
+    pub end: Option<(PolyRef, Vec3)>,
+    // The rest is only filled in when both ends were found
+    pub path: PolyPath,
+    pub straight_path: Vec<StraightPathPoint>,
+    pub raycast: Option<RaycastHit>,
+}
+
+const NODE_OPEN: u8 = 0x01;
+const NODE_CLOSED: u8 = 0x02;
+
+#[derive(Debug, Clone)]
+struct Node {
+    pos: Vec3,
+    cost: f32,
+    total: f32,
+    // Index of the parent node in the pool
+    parent: Option<usize>,
+    flags: u8,
+    poly_ref: PolyRef,
+}
+
+// Search nodes, one per visited polygon, up to a fixed budget
+struct NodePool {
+    nodes: Vec<Node>,
+    lookup: HashMap<PolyRef, usize>,
+    max_nodes: usize,
+}
+
+impl NodePool {
+    fn new(max_nodes: usize) -> Self {
+        Self {
+            nodes: Vec::new(),
+            lookup: HashMap::new(),
+            max_nodes,
+        }
+    }
+
+    // Node of `poly_ref`, allocating a fresh one on the first visit
+    fn get_node(&mut self, poly_ref: PolyRef) -> Option<usize> {
+        if let Some(&index) = self.lookup.get(&poly_ref) {
+            return Some(index);
+        }
+        if self.nodes.len() >= self.max_nodes {
+            return None;
+        }
+        self.nodes.push(Node {
+            pos: Vec3::ZERO,
+            cost: 0.0,
+            total: 0.0,
+            parent: None,
+            flags: 0,
+            poly_ref,
+        });
+        self.lookup.insert(poly_ref, self.nodes.len() - 1);
+        Some(self.nodes.len() - 1)
+    }
+
+    fn clear(&mut self) {
+        self.nodes.clear();
+        self.lookup.clear();
+    }
+
+    // Polygons from the search start to `index`
+    fn path_to(&self, index: usize) -> Vec<PolyRef> {
+        let mut path = Vec::new();
+        let mut current = Some(index);
+        while let Some(i) = current {
+            path.push(self.nodes[i].poly_ref);
+            current = self.nodes[i].parent;
+        }
+        path.reverse();
+        path
+    }
+}
+
+// Open list entry. Improving a node pushes it again, so entries whose total
+// no longer matches the node are stale and skipped.
+#[derive(Debug, Clone, Copy)]
+struct OpenEntry {
+    total: f32,
+    node: usize,
+}
+
+impl PartialEq for OpenEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for OpenEntry {}
+
+impl PartialOrd for OpenEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OpenEntry {
+    // Reversed so the max-heap yields the lowest total first
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .total
+            .total_cmp(&self.total)
+            .then(other.node.cmp(&self.node))
+    }
+}
+
+// Progress of a path search, kept between calls by the sliced API
+#[derive(Debug, Clone)]
+struct SearchState {
+    end_ref: PolyRef,
+    end_pos: Vec3,
+    // Node closest to the end so far, for partial results
+    last_best: usize,
+    last_best_cost: f32,
+    out_of_nodes: bool,
+    done: bool,
+}
+
+// Search started by `init_sliced_find_path`, with the filter it runs with
+struct SlicedQuery<'a> {
+    state: SearchState,
+    filter: Box<dyn PolyFilter + 'a>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlicedStatus {
+    InProgress,
+    // Reached the end, or exhausted everything reachable
+    Done,
+}
+
+// Queries over a `NavMesh`, like Detour's dtNavMeshQuery. The node pool
+// and open list are reused by every search.
+pub struct NavMeshQuery<'a> {
+    nav_mesh: &'a NavMesh,
+    node_pool: NodePool,
+    open_list: BinaryHeap<OpenEntry>,
+    sliced: Option<SlicedQuery<'a>>,
+}
+
+impl<'a> NavMeshQuery<'a> {
+    // `max_nodes` bounds the number of polygons a search may visit
+    pub fn new(nav_mesh: &'a NavMesh, max_nodes: usize) -> Self {
+        Self {
+            nav_mesh,
+            node_pool: NodePool::new(max_nodes),
+            open_list: BinaryHeap::new(),
+            sliced: None,
+        }
+    }
+
+    // A* search for the polygons leading from `start_ref` to `end_ref`.
+    // Nodes sit at the midpoints of the portal edges crossed, with the
+    // straight-line distance to `end_pos` as the heuristic.
+    pub fn find_path(
+        &mut self,
+        start_ref: PolyRef,
+        end_ref: PolyRef,
+        start_pos: Vec3,
+        end_pos: Vec3,
+        filter: &impl PolyFilter,
+    ) -> Result<PolyPath, NavMeshError> {
+        // A plain search reuses the pool, so any sliced one is abandoned
+        self.sliced = None;
+        let mut state = self.init_search(start_ref, end_ref, start_pos, end_pos)?;
+        self.step_search(&mut state, filter, usize::MAX)?;
+        Ok(self.search_result(&state))
+    }
+
+    // Start a path search to be advanced with `update_sliced_find_path`,
+    // so a long search can be spread over several frames. Replaces any
+    // sliced search in progress.
+    pub fn init_sliced_find_path(
+        &mut self,
+        start_ref: PolyRef,
+        end_ref: PolyRef,
+        start_pos: Vec3,
+        end_pos: Vec3,
+        filter: impl PolyFilter + 'a,
+    ) -> Result<SlicedStatus, NavMeshError> {
+        self.sliced = None;
+        let state = self.init_search(start_ref, end_ref, start_pos, end_pos)?;
+        let status = if state.done {
+            SlicedStatus::Done
+        } else {
+            SlicedStatus::InProgress
+        };
+        self.sliced = Some(SlicedQuery {
+            state,
+            filter: Box::new(filter),
+        });
+        Ok(status)
+    }
+
+    // Expand at most `max_iters` more nodes of the sliced search. Fails,
+    // dropping the search, if a polygon it visited has been removed.
+    pub fn update_sliced_find_path(
+        &mut self,
+        max_iters: usize,
+    ) -> Result<SlicedStatus, NavMeshError> {
+        let mut sliced = self
+            .sliced
+            .take()
+            .ok_or(NavMeshError::InvalidParams("no sliced search in progress"))?;
+        self.step_search(&mut sliced.state, &*sliced.filter, max_iters)?;
+        let status = if sliced.state.done {
+            SlicedStatus::Done
+        } else {
+            SlicedStatus::InProgress
+        };
+        self.sliced = Some(sliced);
+        Ok(status)
+    }
+
+    // Corridor found by the sliced search, ending the search. If it has not
+    // finished, this is the partial path to the closest polygon so far.
+    pub fn finalize_sliced_find_path(&mut self) -> Result<PolyPath, NavMeshError> {
+        let sliced = self
+            .sliced
+            .take()
+            .ok_or(NavMeshError::InvalidParams("no sliced search in progress"))?;
+        Ok(self.search_result(&sliced.state))
+    }
+
+    // `finalize_sliced_find_path` for an unfinished search meant to
+    // shortcut `existing`: the path leads to the furthest polygon of
+    // `existing` the search visited, when there is one
+    pub fn finalize_sliced_find_path_partial(
+        &mut self,
+        existing: &[PolyRef],
+    ) -> Result<PolyPath, NavMeshError> {
+        let sliced = self
+            .sliced
+            .take()
+            .ok_or(NavMeshError::InvalidParams("no sliced search in progress"))?;
+        if existing.is_empty() {
+            return Err(NavMeshError::InvalidParams("empty path"));
+        }
+        let mut result = self.search_result(&sliced.state);
+        if result.partial {
+            let visited = existing
+                .iter()
+                .rev()
+                .find_map(|r| self.node_pool.lookup.get(r).copied());
+            if let Some(node) = visited {
+                result.polys = self.node_pool.path_to(node);
+            }
+        }
+        Ok(result)
+    }
+
+    // Reset the node pool and open list and seed them with the start
+    fn init_search(
+        &mut self,
+        start_ref: PolyRef,
+        end_ref: PolyRef,
+        start_pos: Vec3,
+        end_pos: Vec3,
+    ) -> Result<SearchState, NavMeshError> {
+        self.nav_mesh.tile_and_poly_by_ref(start_ref)?;
+        self.nav_mesh.tile_and_poly_by_ref(end_ref)?;
+        if !start_pos.is_finite() || !end_pos.is_finite() {
+            return Err(NavMeshError::InvalidParams("non-finite position"));
+        }
+
+        self.node_pool.clear();
+        self.open_list.clear();
+
+        let start = self
+            .node_pool
+            .get_node(start_ref)
+            .ok_or(NavMeshError::InvalidParams("max_nodes is zero"))?;
+        let start_total = start_pos.distance(end_pos) * H_SCALE;
+        self.node_pool.nodes[start] = Node {
+            pos: start_pos,
+            cost: 0.0,
+            total: start_total,
+            parent: None,
+            flags: NODE_OPEN,
+            poly_ref: start_ref,
+        };
+        self.open_list.push(OpenEntry {
+            total: start_total,
+            node: start,
+        });
+
+        Ok(SearchState {
+            end_ref,
+            end_pos,
+            last_best: start,
+            last_best_cost: start_total,
+            out_of_nodes: false,
+            done: start_ref == end_ref,
+        })
+    }
+
+    // Expand up to `max_iters` nodes from the open list
+    fn step_search<F: PolyFilter + ?Sized>(
+        &mut self,
+        state: &mut SearchState,
+        filter: &F,
+        max_iters: usize,
+    ) -> Result<(), NavMeshError> {
+        let nav_mesh = self.nav_mesh;
+        let pool = &mut self.node_pool;
+        let (end_ref, end_pos) = (state.end_ref, state.end_pos);
+
+        let mut iters = 0;
+        while !state.done && iters < max_iters {
+            let Some(entry) = self.open_list.pop() else {
+                state.done = true;
+                break;
+            };
+            let best = entry.node;
+            if pool.nodes[best].flags & NODE_OPEN == 0 || pool.nodes[best].total != entry.total {
+                continue;
+            }
+            iters += 1;
+            pool.nodes[best].flags = NODE_CLOSED;
+
+            let best_ref = pool.nodes[best].poly_ref;
+            if best_ref == end_ref {
+                state.last_best = best;
+                state.done = true;
+                break;
+            }
+
+            let (best_tile, best_poly) = nav_mesh.tile_and_poly_by_ref(best_ref)?;
+            let (_, _, best_ip) = nav_mesh.decode_poly_id(best_ref);
+            let parent_ref = pool.nodes[best].parent.map(|p| pool.nodes[p].poly_ref);
+
+            for link in best_tile.links_of(best_ip) {
+                let neighbour_ref = link.poly_ref;
+                if neighbour_ref == 0 || Some(neighbour_ref) == parent_ref {
+                    continue;
+                }
+                let (neighbour_tile, neighbour_poly) =
+                    nav_mesh.tile_and_poly_by_ref(neighbour_ref)?;
+                if !filter.pass_filter(neighbour_ref, neighbour_tile, neighbour_poly) {
+                    continue;
+                }
+
+                let Some(neighbour) = pool.get_node(neighbour_ref) else {
+                    state.out_of_nodes = true;
+                    continue;
+                };
+
+                // Position the node on the first visit
+                if pool.nodes[neighbour].flags == 0 {
+                    let (left, right) = portal_points(nav_mesh, best_ref, neighbour_ref)?;
+                    pool.nodes[neighbour].pos = (left + right) * 0.5;
+                }
+
+                let best_pos = pool.nodes[best].pos;
+                let neighbour_pos = pool.nodes[neighbour].pos;
+                let cur_cost = filter.cost(best_pos, neighbour_pos, best_poly);
+                let (cost, heuristic) = if neighbour_ref == end_ref {
+                    let end_cost = filter.cost(neighbour_pos, end_pos, neighbour_poly);
+                    (pool.nodes[best].cost + cur_cost + end_cost, 0.0)
+                } else {
+                    (
+                        pool.nodes[best].cost + cur_cost,
+                        neighbour_pos.distance(end_pos) * H_SCALE,
+                    )
+                };
+                let total = cost + heuristic;
+
+                // Keep the existing route if it is at least as good
+                let node = &mut pool.nodes[neighbour];
+                if node.flags != 0 && total >= node.total {
+                    continue;
+                }
+                node.parent = Some(best);
+                node.flags = NODE_OPEN;
+                node.cost = cost;
+                node.total = total;
+                self.open_list.push(OpenEntry {
+                    total,
+                    node: neighbour,
+                });
+
+                if heuristic < state.last_best_cost {
+                    state.last_best_cost = heuristic;
+                    state.last_best = neighbour;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn search_result(&self, state: &SearchState) -> PolyPath {
+        PolyPath {
+            polys: self.node_pool.path_to(state.last_best),
+            partial: self.node_pool.nodes[state.last_best].poly_ref != state.end_ref,
+            out_of_nodes: state.out_of_nodes,
+        }
+    }
+
+    // Random point on the navmesh, picking polygons passing `filter` with
+    // probability proportional to their area. `frand` returns values in
+    // [0, 1). None if no polygon passes.
+    pub fn find_random_point(
+        &self,
+        filter: &impl PolyFilter,
+        frand: &mut impl FnMut() -> f32,
+    ) -> Result<Option<(PolyRef, Vec3)>, NavMeshError> {
+        // Reservoir sampling over every polygon of every tile
+        let mut chosen = None;
+        let mut area_sum = 0.0;
+        for (base, tile) in self.nav_mesh.tiles_with_refs() {
+            for ip in 0..tile.data.header.off_mesh_base {
+                let r = base | ip as PolyRef;
+                let poly = &tile.data.polys[ip];
+                if !filter.pass_filter(r, tile, poly) {
+                    continue;
+                }
+                let area = poly_area_2d(&poly_verts(tile, poly));
+                area_sum += area;
+                if frand() * area_sum <= area {
+                    chosen = Some(r);
+                }
+            }
+        }
+        match chosen {
+            Some(r) => Ok(Some((r, self.random_point_in_poly(r, frand)?))),
+            None => Ok(None),
+        }
+    }
+
+    // Random point on the polygons reachable from `start_ref` without
+    // crossing an edge further than `max_radius` from `center_pos`, with
+    // polygons picked by area. The point itself may lie outside the circle.
+    pub fn find_random_point_around_circle(
+        &mut self,
+        start_ref: PolyRef,
+        center_pos: Vec3,
+        max_radius: f32,
+        filter: &impl PolyFilter,
+        frand: &mut impl FnMut() -> f32,
+    ) -> Result<Option<(PolyRef, Vec3)>, NavMeshError> {
+        let (start_tile, start_poly) = self.nav_mesh.tile_and_poly_by_ref(start_ref)?;
+        if !center_pos.is_finite() || max_radius.is_nan() || max_radius < 0.0 {
+            return Err(NavMeshError::InvalidParams("invalid search circle"));
+        }
+        if !filter.pass_filter(start_ref, start_tile, start_poly) {
+            return Ok(None);
+        }
+
+        let radius_sqr = max_radius * max_radius;
+        let mut chosen = None;
+        let mut area_sum = 0.0;
+        self.flood_polys(
+            start_ref,
+            center_pos,
+            filter,
+            |va, vb| distance_pt_seg_sqr_2d(center_pos, va, vb).0 <= radius_sqr,
+            |reached, tile, poly| {
+                if poly.poly_type == PolyType::Ground {
+                    let area = poly_area_2d(&poly_verts(tile, poly));
+                    area_sum += area;
+                    if frand() * area_sum <= area {
+                        chosen = Some(reached.poly_ref);
+                    }
+                }
+            },
+        )?;
+
+        match chosen {
+            Some(r) => Ok(Some((r, self.random_point_in_poly(r, frand)?))),
+            None => Ok(None),
+        }
+    }
+
+    // Every polygon reachable from `start_ref` without crossing an edge
+    // further than `radius` from `center_pos`, in order of increasing cost
+    // from the centre, with the polygon each was reached from
+    pub fn find_polys_around_circle(
+        &mut self,
+        start_ref: PolyRef,
+        center_pos: Vec3,
+        radius: f32,
+        filter: &impl PolyFilter,
+    ) -> Result<Vec<ReachedPoly>, NavMeshError> {
+        self.nav_mesh.tile_and_poly_by_ref(start_ref)?;
+        if !center_pos.is_finite() || radius.is_nan() || radius < 0.0 {
+            return Err(NavMeshError::InvalidParams("invalid search circle"));
+        }
+
+        let radius_sqr = radius * radius;
+        let mut reached = Vec::new();
+        self.flood_polys(
+            start_ref,
+            center_pos,
+            filter,
+            |va, vb| distance_pt_seg_sqr_2d(center_pos, va, vb).0 <= radius_sqr,
+            |poly, _, _| reached.push(poly),
+        )?;
+        Ok(reached)
+    }
+
+    // `find_polys_around_circle` for a convex shape on the XZ plane: every
+    // polygon reachable from `start_ref` through edges overlapping `shape`.
+    // Costs are measured from the centroid of the shape.
+    pub fn find_polys_around_shape(
+        &mut self,
+        start_ref: PolyRef,
+        shape: &[Vec3],
+        filter: &impl PolyFilter,
+    ) -> Result<Vec<ReachedPoly>, NavMeshError> {
+        self.nav_mesh.tile_and_poly_by_ref(start_ref)?;
+        if shape.len() < 3 || shape.iter().any(|v| !v.is_finite()) {
+            return Err(NavMeshError::InvalidParams("invalid search shape"));
+        }
+
+        // The clipping below expects the winding of navmesh polygons
+        let mut shape = shape.to_vec();
+        if poly_area_2d(&shape) < 0.0 {
+            shape.reverse();
+        }
+        let center = shape.iter().sum::<Vec3>() / shape.len() as f32;
+
+        let mut reached = Vec::new();
+        self.flood_polys(
+            start_ref,
+            center,
+            filter,
+            |va, vb| {
+                intersect_segment_poly_2d(va, vb, &shape)
+                    .is_some_and(|(tmin, tmax)| tmin <= 1.0 && tmax >= 0.0)
+            },
+            |poly, _, _| reached.push(poly),
+        )?;
+        Ok(reached)
+    }
+
+    // Polygons within `radius` of `center_pos` reachable from `start_ref`,
+    // leaving out any that overlap one reached earlier on the XZ plane,
+    // such as a floor above or below. The result is a flat neighbourhood,
+    // for local steering.
+    pub fn find_local_neighbourhood(
+        &mut self,
+        start_ref: PolyRef,
+        center_pos: Vec3,
+        radius: f32,
+        filter: &impl PolyFilter,
+    ) -> Result<Vec<PolyRef>, NavMeshError> {
+        let nav_mesh = self.nav_mesh;
+        let reached = self.find_polys_around_circle(start_ref, center_pos, radius, filter)?;
+        let mut polys: Vec<(PolyRef, Vec<Vec3>)> = Vec::new();
+        for poly in reached {
+            let (tile, p) = nav_mesh.tile_and_poly_by_ref(poly.poly_ref)?;
+            if p.poly_type != PolyType::Ground {
+                continue;
+            }
+            let verts = poly_verts(tile, p);
+            if polys
+                .iter()
+                .all(|(_, past)| !overlap_poly_poly_2d(&verts, past))
+            {
+                polys.push((poly.poly_ref, verts));
+            }
+        }
+        Ok(polys.into_iter().map(|(r, _)| r).collect())
+    }
+
+    // Edges of polygon `r` that can't be crossed into a polygon passing
+    // `filter`, split where only part of an edge is
+    pub fn poly_wall_segments(
+        &self,
+        r: PolyRef,
+        filter: &impl PolyFilter,
+    ) -> Result<Vec<(Vec3, Vec3)>, NavMeshError> {
+        let (tile, poly) = self.nav_mesh.tile_and_poly_by_ref(r)?;
+        if poly.poly_type != PolyType::Ground {
+            return Ok(Vec::new());
+        }
+        let (_, _, ip) = self.nav_mesh.decode_poly_id(r);
+        let verts = poly_verts(tile, poly);
+        let n = verts.len();
+        let mut segments = Vec::new();
+        for j in 0..n {
+            let (vj, vk) = (verts[j], verts[(j + 1) % n]);
+            for (tmin, tmax) in wall_intervals(self.nav_mesh, tile, ip, j, filter) {
+                segments.push((vj.lerp(vk, tmin), vj.lerp(vk, tmax)));
+            }
+        }
+        Ok(segments)
+    }
+
+    // Closest navmesh boundary within `max_radius` of `center_pos`, found
+    // by flooding outwards from `center_ref`. Edges leading only to
+    // polygons the filter rejects count as walls.
+    pub fn find_distance_to_wall(
+        &mut self,
+        center_ref: PolyRef,
+        center_pos: Vec3,
+        max_radius: f32,
+        filter: &impl PolyFilter,
+    ) -> Result<Option<WallHit>, NavMeshError> {
+        self.nav_mesh.tile_and_poly_by_ref(center_ref)?;
+        if !center_pos.is_finite() || max_radius.is_nan() || max_radius < 0.0 {
+            return Err(NavMeshError::InvalidParams("invalid search circle"));
+        }
+
+        // Shrinks as walls are found, pruning the rest of the flood
+        let radius_sqr = Cell::new(max_radius * max_radius);
+        let mut hit_pos = None;
+        let nav_mesh = self.nav_mesh;
+        self.flood_polys(
+            center_ref,
+            center_pos,
+            filter,
+            |va, vb| distance_pt_seg_sqr_2d(center_pos, va, vb).0 <= radius_sqr.get(),
+            |reached, tile, poly| {
+                if poly.poly_type != PolyType::Ground {
+                    return;
+                }
+                let (_, _, ip) = nav_mesh.decode_poly_id(reached.poly_ref);
+                let verts = poly_verts(tile, poly);
+                for j in 0..verts.len() {
+                    let (vj, vk) = (verts[j], verts[(j + 1) % verts.len()]);
+                    for (tmin, tmax) in wall_intervals(nav_mesh, tile, ip, j, filter) {
+                        let (a, b) = (vj.lerp(vk, tmin), vj.lerp(vk, tmax));
+                        let (d, t) = distance_pt_seg_sqr_2d(center_pos, a, b);
+                        if d <= radius_sqr.get() {
+                            radius_sqr.set(d);
+                            hit_pos = Some(a.lerp(b, t));
+                        }
+                    }
+                }
+            },
+        )?;
+
+        Ok(hit_pos.map(|pos| {
+            let mut normal = center_pos - pos;
+            normal.y = 0.0;
+            WallHit {
+                distance: radius_sqr.get().sqrt(),
+                pos,
+                normal: normal.normalize_or_zero(),
+            }
+        }))
+    }
+
+    // Dijkstra flood from `start_ref` through the edges `crosses` accepts,
+    // calling `visit` on each polygon as it is settled. Reuses the node
+    // pool, abandoning any sliced search.
+    fn flood_polys<F: PolyFilter + ?Sized>(
+        &mut self,
+        start_ref: PolyRef,
+        start_pos: Vec3,
+        filter: &F,
+        crosses: impl Fn(Vec3, Vec3) -> bool,
+        mut visit: impl FnMut(ReachedPoly, &MeshTile, &Poly),
+    ) -> Result<(), NavMeshError> {
+        let nav_mesh = self.nav_mesh;
+        self.sliced = None;
+        self.node_pool.clear();
+        self.open_list.clear();
+        let pool = &mut self.node_pool;
+
+        let start = pool
+            .get_node(start_ref)
+            .ok_or(NavMeshError::InvalidParams("max_nodes is zero"))?;
+        pool.nodes[start].pos = start_pos;
+        pool.nodes[start].flags = NODE_OPEN;
+        self.open_list.push(OpenEntry {
+            total: 0.0,
+            node: start,
+        });
+
+        while let Some(entry) = self.open_list.pop() {
+            let best = entry.node;
+            if pool.nodes[best].flags & NODE_OPEN == 0 || pool.nodes[best].total != entry.total {
+                continue;
+            }
+            pool.nodes[best].flags = NODE_CLOSED;
+
+            let best_ref = pool.nodes[best].poly_ref;
+            let parent_ref = pool.nodes[best].parent.map(|p| pool.nodes[p].poly_ref);
+            let (best_tile, best_poly) = nav_mesh.tile_and_poly_by_ref(best_ref)?;
+            visit(
+                ReachedPoly {
+                    poly_ref: best_ref,
+                    parent_ref: parent_ref.unwrap_or(0),
+                    cost: pool.nodes[best].total,
+                },
+                best_tile,
+                best_poly,
+            );
+
+            let (_, _, best_ip) = nav_mesh.decode_poly_id(best_ref);
+            for link in best_tile.links_of(best_ip) {
+                let neighbour_ref = link.poly_ref;
+                if neighbour_ref == 0 || Some(neighbour_ref) == parent_ref {
+                    continue;
+                }
+                let (neighbour_tile, neighbour_poly) =
+                    nav_mesh.tile_and_poly_by_ref(neighbour_ref)?;
+                if !filter.pass_filter(neighbour_ref, neighbour_tile, neighbour_poly) {
+                    continue;
+                }
+                let Ok((va, vb)) = portal_points(nav_mesh, best_ref, neighbour_ref) else {
+                    continue;
+                };
+                if !crosses(va, vb) {
+                    continue;
+                }
+
+                let Some(neighbour) = pool.get_node(neighbour_ref) else {
+                    continue;
+                };
+                if pool.nodes[neighbour].flags & NODE_CLOSED != 0 {
+                    continue;
+                }
+                if pool.nodes[neighbour].flags == 0 {
+                    pool.nodes[neighbour].pos = va.lerp(vb, 0.5);
+                }
+
+                let cost = filter.cost(pool.nodes[best].pos, pool.nodes[neighbour].pos, best_poly);
+                let total = pool.nodes[best].total + cost;
+                let node = &mut pool.nodes[neighbour];
+                if node.flags & NODE_OPEN != 0 && total >= node.total {
+                    continue;
+                }
+                node.parent = Some(best);
+                node.flags = NODE_OPEN;
+                node.total = total;
+                self.open_list.push(OpenEntry {
+                    total,
+                    node: neighbour,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    // Uniformly distributed point on ground polygon `r`, on its detail
+    // surface
+    fn random_point_in_poly(
+        &self,
+        r: PolyRef,
+        frand: &mut impl FnMut() -> f32,
+    ) -> Result<Vec3, NavMeshError> {
+        let (tile, poly) = self.nav_mesh.tile_and_poly_by_ref(r)?;
+        let (_, _, ip) = self.nav_mesh.decode_poly_id(r);
+        let (s, t) = (frand(), frand());
+        let pt = random_point_in_convex_poly(&poly_verts(tile, poly), s, t);
+        let y = tile.poly_height(ip, pt).unwrap_or(pt.y);
+        Ok(Vec3::new(pt.x, y, pt.z))
+    }
+
+    // Ground polygons passing `filter` whose bounds overlap the box around
+    // `center`
+    pub fn query_polygons(
+        &self,
+        center: Vec3,
+        half_extents: Vec3,
+        filter: &impl PolyFilter,
+    ) -> Result<Vec<PolyRef>, NavMeshError> {
+        if !center.is_finite() || !half_extents.is_finite() || half_extents.min_element() < 0.0 {
+            return Err(NavMeshError::InvalidParams("invalid query box"));
+        }
+        let (qmin, qmax) = (center - half_extents, center + half_extents);
+        let mut polys = Vec::new();
+        for (base, tile) in self.nav_mesh.tiles_with_refs() {
+            let header = &tile.data.header;
+            if !overlap_bounds(qmin, qmax, header.bmin, header.bmax) {
+                continue;
+            }
+            for ip in tile.query_polygons(qmin, qmax) {
+                let r = base | ip as PolyRef;
+                if filter.pass_filter(r, tile, &tile.data.polys[ip]) {
+                    polys.push(r);
+                }
+            }
+        }
+        Ok(polys)
+    }
+
+    // Polygon passing `filter` nearest to `center` within the box, and the
+    // point on it closest to `center`. None if no polygon overlaps the box.
+    pub fn find_nearest_poly(
+        &self,
+        center: Vec3,
+        half_extents: Vec3,
+        filter: &impl PolyFilter,
+    ) -> Result<Option<(PolyRef, Vec3)>, NavMeshError> {
+        if !center.is_finite() || !half_extents.is_finite() || half_extents.min_element() < 0.0 {
+            return Err(NavMeshError::InvalidParams("invalid query box"));
+        }
+        let (qmin, qmax) = (center - half_extents, center + half_extents);
+        let mut nearest = None;
+        let mut nearest_dist = f32::MAX;
+        for (base, tile) in self.nav_mesh.tiles_with_refs() {
+            let header = &tile.data.header;
+            if !overlap_bounds(qmin, qmax, header.bmin, header.bmax) {
+                continue;
+            }
+            let accept =
+                |ip: usize| filter.pass_filter(base | ip as PolyRef, tile, &tile.data.polys[ip]);
+            if let Some((ip, point, d)) = tile.find_nearest_poly(center, half_extents, accept) {
+                if d < nearest_dist {
+                    nearest_dist = d;
+                    nearest = Some((base | ip as PolyRef, point));
+                }
+            }
+        }
+        Ok(nearest)
+    }
+
+    // Left and right end of the edge crossed going from `from` to `to`.
+    // Off-mesh connections are crossed at their end points.
+    pub fn portal_points(&self, from: PolyRef, to: PolyRef) -> Result<(Vec3, Vec3), NavMeshError> {
+        portal_points(self.nav_mesh, from, to)
+    }
+
+    // `pos` if it lies over polygon `r` on the XZ plane, otherwise the
+    // closest point on the polygon's outline
+    pub fn closest_point_on_poly_boundary(
+        &self,
+        r: PolyRef,
+        pos: Vec3,
+    ) -> Result<Vec3, NavMeshError> {
+        let (tile, poly) = self.nav_mesh.tile_and_poly_by_ref(r)?;
+        let verts: Vec<Vec3> = poly
+            .vert_indices()
+            .iter()
+            .map(|&v| tile.data.verts[v as usize])
+            .collect();
+        if point_in_polygon(pos, &verts) {
+            return Ok(pos);
+        }
+
+        let n = verts.len();
+        let mut best = (f32::MAX, pos);
+        for j in 0..n {
+            let (a, b) = (verts[j], verts[(j + 1) % n]);
+            let (d, t) = distance_pt_seg_sqr_2d(pos, a, b);
+            if d < best.0 {
+                best = (d, a.lerp(b, t));
+            }
+        }
+        Ok(best.1)
+    }
+
+    // Whether `r` is a polygon of the navmesh that passes `filter`
+    pub fn is_valid_poly_ref(&self, r: PolyRef, filter: &impl PolyFilter) -> bool {
+        self.nav_mesh
+            .tile_and_poly_by_ref(r)
+            .is_ok_and(|(tile, poly)| filter.pass_filter(r, tile, poly))
+    }
+
+    // Height of the detail surface of polygon `r` at `pos`, or None if
+    // `pos` isn't over it
+    pub fn poly_height(&self, r: PolyRef, pos: Vec3) -> Result<Option<f32>, NavMeshError> {
+        let (tile, _) = self.nav_mesh.tile_and_poly_by_ref(r)?;
+        let (_, _, ip) = self.nav_mesh.decode_poly_id(r);
+        Ok(tile.poly_height(ip, pos))
+    }
+
+    // Walk the segment from `start_pos` to `end_pos` over the navmesh on
+    // the XZ plane, stopping at the first wall, like Detour's raycast. Good
+    // for checking whether an agent can head straight for a point.
+    pub fn raycast(
+        &self,
+        start_ref: PolyRef,
+        start_pos: Vec3,
+        end_pos: Vec3,
+        filter: &impl PolyFilter,
+    ) -> Result<RaycastHit, NavMeshError> {
+        self.nav_mesh.tile_and_poly_by_ref(start_ref)?;
+        if !start_pos.is_finite() || !end_pos.is_finite() {
+            return Err(NavMeshError::InvalidParams("non-finite position"));
+        }
+
+        let mut hit = RaycastHit::default();
+        let mut cur_ref = start_ref;
+        loop {
+            let (tile, poly) = self.nav_mesh.tile_and_poly_by_ref(cur_ref)?;
+            let verts = poly_verts(tile, poly);
+            let Some((_, tmax, _, seg_max)) =
+                intersect_segment_poly_edges_2d(start_pos, end_pos, &verts)
+            else {
+                // Doesn't touch the polygon it should start in
+                return Ok(hit);
+            };
+            hit.t = hit.t.max(tmax);
+            hit.path.push(cur_ref);
+            let Some(edge) = seg_max else {
+                // Ends inside this polygon
+                hit.t = f32::MAX;
+                return Ok(hit);
+            };
+
+            let (_, _, ip) = self.nav_mesh.decode_poly_id(cur_ref);
+            let n = verts.len();
+            let (va, vb) = (verts[edge], verts[(edge + 1) % n]);
+            let next = tile.links_of(ip).find(|link| {
+                if link.edge as usize != edge || link.poly_ref == 0 {
+                    return false;
+                }
+                let Ok((next_tile, next_poly)) = self.nav_mesh.tile_and_poly_by_ref(link.poly_ref)
+                else {
+                    return false;
+                };
+                if next_poly.poly_type == PolyType::OffMeshConnection
+                    || !filter.pass_filter(link.poly_ref, next_tile, next_poly)
+                {
+                    return false;
+                }
+                if link.side == 0xff || (link.bmin == 0 && link.bmax == 255) {
+                    return true;
+                }
+                // A tile portal covering part of the edge: the ray must
+                // cross within that part
+                let s = 1.0 / 255.0;
+                let (a, b, v) = if link.side == 0 || link.side == 4 {
+                    (va.z, vb.z, start_pos.z + (end_pos.z - start_pos.z) * tmax)
+                } else {
+                    (va.x, vb.x, start_pos.x + (end_pos.x - start_pos.x) * tmax)
+                };
+                let lo = a + (b - a) * link.bmin as f32 * s;
+                let hi = a + (b - a) * link.bmax as f32 * s;
+                v >= lo.min(hi) && v <= lo.max(hi)
+            });
+
+            match next {
+                Some(link) => cur_ref = link.poly_ref,
+                None => {
+                    // Facing back into the polygon
+                    let (dx, dz) = (vb.x - va.x, vb.z - va.z);
+                    hit.normal = Vec3::new(dz, 0.0, -dx).normalize_or_zero();
+                    return Ok(hit);
+                }
+            }
+        }
+    }
+
+    // Move from `start_pos` towards `end_pos` over the navmesh, sliding
+    // along walls, like Detour's moveAlongSurface. Meant for short steps:
+    // only polygons within half the move of its midpoint are searched.
+    // Returns the position reached, at the height of `end_pos`, and the
+    // polygons from the start polygon to the one it's in.
+    pub fn move_along_surface(
+        &self,
+        start_ref: PolyRef,
+        start_pos: Vec3,
+        end_pos: Vec3,
+        filter: &impl PolyFilter,
+    ) -> Result<(Vec3, Vec<PolyRef>), NavMeshError> {
+        const MAX_VISITED: usize = 64;
+
+        self.nav_mesh.tile_and_poly_by_ref(start_ref)?;
+        if !start_pos.is_finite() || !end_pos.is_finite() {
+            return Err(NavMeshError::InvalidParams("non-finite position"));
+        }
+
+        let search_pos = start_pos.lerp(end_pos, 0.5);
+        let search_rad_sqr = (start_pos.distance(end_pos) / 2.0 + 0.001).powi(2);
+
+        // Visited polygons with the index of the one they were reached from
+        let mut visited: Vec<(PolyRef, Option<usize>)> = vec![(start_ref, None)];
+        let mut best = (0, start_pos, f32::MAX);
+        let mut next = 0;
+        while next < visited.len() {
+            let cur = next;
+            next += 1;
+            let cur_ref = visited[cur].0;
+            let (tile, poly) = self.nav_mesh.tile_and_poly_by_ref(cur_ref)?;
+            let verts = poly_verts(tile, poly);
+            if point_in_polygon(end_pos, &verts) {
+                best = (cur, end_pos, 0.0);
+                break;
+            }
+
+            let (_, _, ip) = self.nav_mesh.decode_poly_id(cur_ref);
+            let n = verts.len();
+            for j in 0..n {
+                let (va, vb) = (verts[j], verts[(j + 1) % n]);
+                let neighbours: Vec<PolyRef> = tile
+                    .links_of(ip)
+                    .filter(|link| link.edge as usize == j && link.poly_ref != 0)
+                    .map(|link| link.poly_ref)
+                    .filter(|&r| self.is_valid_poly_ref(r, filter))
+                    .collect();
+
+                if neighbours.is_empty() {
+                    // A wall: slide along it
+                    let (d, t) = distance_pt_seg_sqr_2d(end_pos, va, vb);
+                    if d < best.2 {
+                        best = (cur, va.lerp(vb, t), d);
+                    }
+                    continue;
+                }
+                // Only edges near the move lead anywhere useful
+                if distance_pt_seg_sqr_2d(search_pos, va, vb).0 > search_rad_sqr {
+                    continue;
+                }
+                for r in neighbours {
+                    if visited.len() < MAX_VISITED && visited.iter().all(|&(v, _)| v != r) {
+                        visited.push((r, Some(cur)));
+                    }
+                }
+            }
+        }
+
+        let mut path = Vec::new();
+        let mut node = Some(best.0);
+        while let Some(i) = node {
+            path.push(visited[i].0);
+            node = visited[i].1;
+        }
+        path.reverse();
+        let pos = best.1;
+        Ok((Vec3::new(pos.x, end_pos.y, pos.z), path))
+    }
+
+    // Snap both points onto the navmesh, then find the path and the
+    // straight path between them and raycast from one to the other. What
+    // the viewer's navmesh test tool shows.
+    pub fn query_point_to_point(
+        &mut self,
+        start_pos: Vec3,
+        end_pos: Vec3,
+        half_extents: Vec3,
+        filter: &impl PolyFilter,
+    ) -> Result<PointToPoint, NavMeshError> {
+        let mut result = PointToPoint {
+            start: self.find_nearest_poly(start_pos, half_extents, filter)?,
+            end: self.find_nearest_poly(end_pos, half_extents, filter)?,
+            ..Default::default()
+        };
+        let (Some((start_ref, start)), Some((end_ref, end))) = (result.start, result.end) else {
+            return Ok(result);
+        };
+        result.path = self.find_path(start_ref, end_ref, start, end, filter)?;
+        if !result.path.polys.is_empty() {
+            result.straight_path = self.find_straight_path(start, end, &result.path.polys)?;
+        }
+        result.raycast = Some(self.raycast(start_ref, start, end, filter)?);
+        Ok(result)
+    }
+
+    // Pull the corridor `path` taut between `start_pos` and `end_pos` with
+    // the funnel algorithm, returning the corners to walk through. Points
+    // where an off-mesh connection starts are flagged so the agent can
+    // switch to traversing it.
+    pub fn find_straight_path(
+        &self,
+        start_pos: Vec3,
+        end_pos: Vec3,
+        path: &[PolyRef],
+    ) -> Result<Vec<StraightPathPoint>, NavMeshError> {
+        let (&first, &last) = match (path.first(), path.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return Err(NavMeshError::InvalidParams("empty path")),
+        };
+        if !start_pos.is_finite() || !end_pos.is_finite() {
+            return Err(NavMeshError::InvalidParams("non-finite position"));
+        }
+
+        let start = self.closest_point_on_poly_boundary(first, start_pos)?;
+        let end = self.closest_point_on_poly_boundary(last, end_pos)?;
+
+        let mut points = Vec::new();
+        append_vertex(&mut points, start, DT_STRAIGHTPATH_START, first);
+
+        if path.len() > 1 {
+            let mut apex = start;
+            let (mut portal_left, mut portal_right) = (start, start);
+            let (mut left_index, mut right_index) = (0, 0);
+            let (mut left_type, mut right_type) = (PolyType::Ground, PolyType::Ground);
+            let (mut left_ref, mut right_ref) = (first, first);
+
+            let mut i = 0;
+            while i < path.len() {
+                let (left, right, to_type) = if i + 1 < path.len() {
+                    let Ok((left, right)) = self.portal_points(path[i], path[i + 1]) else {
+                        // The corridor is broken; end at the last good polygon
+                        let end = self.closest_point_on_poly_boundary(path[i], end_pos)?;
+                        append_vertex(&mut points, end, 0, path[i]);
+                        return Ok(points);
+                    };
+                    let (_, to_poly) = self.nav_mesh.tile_and_poly_by_ref(path[i + 1])?;
+
+                    // Starting right on the first portal, skip past it
+                    if i == 0 && distance_pt_seg_sqr_2d(apex, left, right).0 < 0.001 * 0.001 {
+                        i += 1;
+                        continue;
+                    }
+                    (left, right, to_poly.poly_type)
+                } else {
+                    (end, end, PolyType::Ground)
+                };
+                let next_ref = path.get(i + 1).copied().unwrap_or(0);
+
+                // Tighten the right side of the funnel
+                if tri_area_2d(apex, portal_right, right) <= 0.0 {
+                    if vequal(apex, portal_right) || tri_area_2d(apex, portal_left, right) > 0.0 {
+                        portal_right = right;
+                        right_ref = next_ref;
+                        right_type = to_type;
+                        right_index = i;
+                    } else {
+                        // Right over left: the left point becomes a corner
+                        apex = portal_left;
+                        let apex_index = left_index;
+                        let flags = corner_flags(left_ref, left_type);
+                        if append_vertex(&mut points, apex, flags, left_ref) {
+                            return Ok(points);
+                        }
+                        portal_left = apex;
+                        portal_right = apex;
+                        left_index = apex_index;
+                        right_index = apex_index;
+                        i = apex_index + 1;
+                        continue;
+                    }
+                }
+
+                // Tighten the left side of the funnel
+                if tri_area_2d(apex, portal_left, left) >= 0.0 {
+                    if vequal(apex, portal_left) || tri_area_2d(apex, portal_right, left) < 0.0 {
+                        portal_left = left;
+                        left_ref = next_ref;
+                        left_type = to_type;
+                        left_index = i;
+                    } else {
+                        // Left over right: the right point becomes a corner
+                        apex = portal_right;
+                        let apex_index = right_index;
+                        let flags = corner_flags(right_ref, right_type);
+                        if append_vertex(&mut points, apex, flags, right_ref) {
+                            return Ok(points);
+                        }
+                        portal_left = apex;
+                        portal_right = apex;
+                        left_index = apex_index;
+                        right_index = apex_index;
+                        i = apex_index + 1;
+                        continue;
+                    }
+                }
+
+                i += 1;
+            }
+        }
+
+        append_vertex(&mut points, end, DT_STRAIGHTPATH_END, 0);
+        Ok(points)
+    }
+}
+
+fn portal_points(
+    nav_mesh: &NavMesh,
+    from: PolyRef,
+    to: PolyRef,
+) -> Result<(Vec3, Vec3), NavMeshError> {
+    let (from_tile, from_poly) = nav_mesh.tile_and_poly_by_ref(from)?;
+    let (to_tile, to_poly) = nav_mesh.tile_and_poly_by_ref(to)?;
+    let (_, _, from_ip) = nav_mesh.decode_poly_id(from);
+    let (_, _, to_ip) = nav_mesh.decode_poly_id(to);
+
+    let link = from_tile
+        .links_of(from_ip)
+        .find(|l| l.poly_ref == to)
+        .ok_or(NavMeshError::InvalidRef(to))?;
+    let vert = |tile: &MeshTile, poly: &Poly, k: usize| tile.data.verts[poly.verts[k] as usize];
+
+    if from_poly.poly_type == PolyType::OffMeshConnection {
+        let v = vert(from_tile, from_poly, link.edge as usize);
+        return Ok((v, v));
+    }
+    if to_poly.poly_type == PolyType::OffMeshConnection {
+        let back = to_tile
+            .links_of(to_ip)
+            .find(|l| l.poly_ref == from)
+            .ok_or(NavMeshError::InvalidRef(from))?;
+        let v = vert(to_tile, to_poly, back.edge as usize);
+        return Ok((v, v));
+    }
+
+    let nv = from_poly.vert_count as usize;
+    let edge = link.edge as usize;
+    let v0 = vert(from_tile, from_poly, edge);
+    let v1 = vert(from_tile, from_poly, (edge + 1) % nv);
+
+    // Links across tile borders may cover only part of the edge
+    if link.side != 0xff && (link.bmin != 0 || link.bmax != 255) {
+        let (tmin, tmax) = (link.bmin as f32 / 255.0, link.bmax as f32 / 255.0);
+        return Ok((v0.lerp(v1, tmin), v0.lerp(v1, tmax)));
+    }
+    Ok((v0, v1))
+}
+
+fn poly_verts(tile: &MeshTile, poly: &Poly) -> Vec<Vec3> {
+    poly.vert_indices()
+        .iter()
+        .map(|&v| tile.data.verts[v as usize])
+        .collect()
+}
+
+// Area of a convex polygon on the XZ plane
+fn poly_area_2d(verts: &[Vec3]) -> f32 {
+    (2..verts.len())
+        .map(|i| tri_area_2d(verts[0], verts[i - 1], verts[i]))
+        .sum::<f32>()
+        * 0.5
+}
+
+// Point of a convex polygon for the random numbers `s` and `t` in [0, 1):
+// `s` picks a fan triangle by area, and both place the point in it
+fn random_point_in_convex_poly(pts: &[Vec3], s: f32, t: f32) -> Vec3 {
+    let n = pts.len();
+    let areas: Vec<f32> = (2..n)
+        .map(|i| tri_area_2d(pts[0], pts[i - 1], pts[i]).max(0.001))
+        .collect();
+    let thr = s * areas.iter().sum::<f32>();
+
+    let (mut tri, mut u) = (n - 1, 1.0);
+    let mut acc = 0.0;
+    for (k, &area) in areas.iter().enumerate() {
+        if thr >= acc && thr < acc + area {
+            u = (thr - acc) / area;
+            tri = k + 2;
+            break;
+        }
+        acc += area;
+    }
+
+    let v = t.sqrt();
+    let (a, b, c) = (1.0 - v, (1.0 - u) * v, u * v);
+    pts[0] * a + pts[tri - 1] * b + pts[tri] * c
+}
+
+// Parts of edge `edge` of polygon `ip`, as parameter ranges along it, not
+// covered by a link to a polygon the filter accepts
+fn wall_intervals(
+    nav_mesh: &NavMesh,
+    tile: &MeshTile,
+    ip: usize,
+    edge: usize,
+    filter: &(impl PolyFilter + ?Sized),
+) -> Vec<(f32, f32)> {
+    let mut open: Vec<(u8, u8)> = tile
+        .links_of(ip)
+        .filter(|link| link.edge as usize == edge)
+        .filter(|link| {
+            nav_mesh
+                .tile_and_poly_by_ref(link.poly_ref)
+                .is_ok_and(|(t, p)| filter.pass_filter(link.poly_ref, t, p))
+        })
+        // Links inside the tile span the whole edge
+        .map(|link| match link.side {
+            0xff => (0, 255),
+            _ => (link.bmin, link.bmax),
+        })
+        .collect();
+    open.sort();
+
+    let mut walls = Vec::new();
+    let mut covered = 0u8;
+    for (bmin, bmax) in open {
+        if bmin > covered {
+            walls.push((covered as f32 / 255.0, bmin as f32 / 255.0));
+        }
+        covered = covered.max(bmax);
+    }
+    if covered < 255 {
+        walls.push((covered as f32 / 255.0, 1.0));
+    }
+    walls
+}
+
+// Range of parameters along p0-p1 inside the convex polygon `verts` on the
+// XZ plane, or None if the segment misses it
+fn intersect_segment_poly_2d(p0: Vec3, p1: Vec3, verts: &[Vec3]) -> Option<(f32, f32)> {
+    intersect_segment_poly_edges_2d(p0, p1, verts).map(|(tmin, tmax, _, _)| (tmin, tmax))
+}
+
+// `intersect_segment_poly_2d`, also giving the edges the segment enters
+// and leaves the polygon through, None where it starts or ends inside
+fn intersect_segment_poly_edges_2d(
+    p0: Vec3,
+    p1: Vec3,
+    verts: &[Vec3],
+) -> Option<(f32, f32, Option<usize>, Option<usize>)> {
+    const EPS: f32 = 1e-8;
+    let perp = |u: Vec3, v: Vec3| u.z * v.x - u.x * v.z;
+
+    let (mut tmin, mut tmax) = (0.0f32, 1.0f32);
+    let (mut seg_min, mut seg_max) = (None, None);
+    let dir = p1 - p0;
+    let n = verts.len();
+    for i in 0..n {
+        let j = (i + n - 1) % n;
+        let edge = verts[i] - verts[j];
+        let diff = p0 - verts[j];
+        let num = perp(edge, diff);
+        let den = perp(dir, edge);
+        if den.abs() < EPS {
+            // Parallel to the edge: either fully outside or irrelevant
+            if num < 0.0 {
+                return None;
+            }
+            continue;
+        }
+        let t = num / den;
+        if den < 0.0 {
+            if t > tmin {
+                tmin = t;
+                seg_min = Some(j);
+            }
+        } else if t < tmax {
+            tmax = t;
+            seg_max = Some(j);
+        }
+        if tmin > tmax {
+            return None;
+        }
+    }
+    Some((tmin, tmax, seg_min, seg_max))
+}
+
+// Whether convex polygons `a` and `b` overlap on the XZ plane, by looking
+// for a separating edge. Polygons that only touch don't overlap.
+fn overlap_poly_poly_2d(a: &[Vec3], b: &[Vec3]) -> bool {
+    const EPS: f32 = 1e-4;
+    let separated = |poly: &[Vec3]| {
+        let n = poly.len();
+        (0..n).any(|j| {
+            let (va, vb) = (poly[j], poly[(j + 1) % n]);
+            let axis = Vec3::new(vb.z - va.z, 0.0, -(vb.x - va.x));
+            let project = |verts: &[Vec3]| {
+                verts.iter().fold((f32::MAX, f32::MIN), |(lo, hi), v| {
+                    let d = axis.x * v.x + axis.z * v.z;
+                    (lo.min(d), hi.max(d))
+                })
+            };
+            let ((amin, amax), (bmin, bmax)) = (project(a), project(b));
+            amin + EPS > bmax || amax - EPS < bmin
+        })
+    };
+    !separated(a) && !separated(b)
+}
+
+// Flags of a funnel corner reached through polygon `r`
+fn corner_flags(r: PolyRef, poly_type: PolyType) -> u8 {
+    if r == 0 {
+        DT_STRAIGHTPATH_END
+    } else if poly_type == PolyType::OffMeshConnection {
+        DT_STRAIGHTPATH_OFFMESH_CONNECTION
+    } else {
+        0
+    }
+}
+
+// Add a corner, merging it into the previous one if they coincide.
+// Returns true once the end of the path was added.
+fn append_vertex(points: &mut Vec<StraightPathPoint>, pos: Vec3, flags: u8, r: PolyRef) -> bool {
+    match points.last_mut() {
+        Some(last) if vequal(last.pos, pos) => {
+            last.flags = flags;
+            last.poly_ref = r;
+        }
+        _ => points.push(StraightPathPoint {
+            pos,
+            flags,
+            poly_ref: r,
+        }),
+    }
+    flags == DT_STRAIGHTPATH_END
+}
+
+// Twice the signed XZ area of triangle a-b-c, positive when c lies to the
+// left of a-b in Detour's polygon winding
+fn tri_area_2d(a: Vec3, b: Vec3, c: Vec3) -> f32 {
+    let (abx, abz) = (b.x - a.x, b.z - a.z);
+    let (acx, acz) = (c.x - a.x, c.z - a.z);
+    acx * abz - abx * acz
+}
+
+fn vequal(a: Vec3, b: Vec3) -> bool {
+    const THR: f32 = 1.0 / 16384.0;
+    a.distance_squared(b) < THR * THR
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debug_draw::{PolyMesh, RC_MESH_NULL_IDX, RC_WALKABLE_AREA};
+    use crate::nav_mesh::{NavMeshCreateParams, NavMeshData, POLY_FLAG_WALK};
+    use crate::offmesh::OffMeshConnection;
+    use crate::query_filter::{DefaultFilter, QueryFilter};
+
+    // A row of 2x2 quads along X, with the listed quads not linked to the
+    // next one
+    fn strip(count: usize, gaps: &[usize]) -> PolyMesh {
+        let n = RC_MESH_NULL_IDX;
+        let mut mesh = PolyMesh {
+            nvp: 6,
+            cs: 1.0,
+            ch: 1.0,
+            bmax: Vec3::new(2.0 * count as f32, 1.0, 2.0),
+            ..Default::default()
+        };
+        for i in 0..=count {
+            let x = 2.0 * i as f32;
+            mesh.verts.push(Vec3::new(x, 0.0, 0.0));
+            mesh.verts.push(Vec3::new(x, 0.0, 2.0));
+        }
+        for i in 0..count {
+            let v = (i * 2) as u16;
+            mesh.polys.push(vec![v, v + 1, v + 3, v + 2, n, n]);
+            let prev = if i > 0 && !gaps.contains(&(i - 1)) {
+                i as u16 - 1
+            } else {
+                n
+            };
+            let next = if i + 1 < count && !gaps.contains(&i) {
+                i as u16 + 1
+            } else {
+                n
+            };
+            mesh.neighbours.push(vec![prev, n, next, n, n, n]);
+        }
+        mesh.regs = vec![1; count];
+        mesh.flags = vec![1; count];
+        mesh.areas = vec![RC_WALKABLE_AREA; count];
+        mesh
+    }
+
+    // A w x h grid of 2x2 quads, all linked to their neighbours
+    fn grid(w: usize, h: usize) -> PolyMesh {
+        let n = RC_MESH_NULL_IDX;
+        let vi = |x: usize, z: usize| (z * (w + 1) + x) as u16;
+        let cell = |x: usize, z: usize| (z * w + x) as u16;
+        let mut mesh = PolyMesh {
+            nvp: 6,
+            cs: 1.0,
+            ch: 1.0,
+            bmax: Vec3::new(2.0 * w as f32, 1.0, 2.0 * h as f32),
+            ..Default::default()
+        };
+        for z in 0..=h {
+            for x in 0..=w {
+                mesh.verts
+                    .push(Vec3::new(2.0 * x as f32, 0.0, 2.0 * z as f32));
+            }
+        }
+        for z in 0..h {
+            for x in 0..w {
+                mesh.polys.push(vec![
+                    vi(x, z),
+                    vi(x, z + 1),
+                    vi(x + 1, z + 1),
+                    vi(x + 1, z),
+                    n,
+                    n,
+                ]);
+                mesh.neighbours.push(vec![
+                    if x > 0 { cell(x - 1, z) } else { n },
+                    if z + 1 < h { cell(x, z + 1) } else { n },
+                    if x + 1 < w { cell(x + 1, z) } else { n },
+                    if z > 0 { cell(x, z - 1) } else { n },
+                    n,
+                    n,
+                ]);
+            }
+        }
+        mesh.regs = vec![1; w * h];
+        mesh.flags = vec![POLY_FLAG_WALK; w * h];
+        mesh.areas = vec![RC_WALKABLE_AREA; w * h];
+        mesh
+    }
+
+    fn nav_mesh(mesh: &PolyMesh, cons: &[OffMeshConnection]) -> NavMesh {
+        let params = NavMeshCreateParams {
+            off_mesh_connections: cons,
+            ..NavMeshCreateParams::new(mesh, None, 2.0, 0.5, 0.5)
+        };
+        NavMesh::single_tile(NavMeshData::build(&params).unwrap()).unwrap()
+    }
+
+    // Skips the polygon with this index
+    struct Avoid(u64);
+
+    impl PolyFilter for Avoid {
+        fn pass_filter(&self, poly_ref: PolyRef, _tile: &MeshTile, _poly: &Poly) -> bool {
+            poly_ref & 0xfffff != self.0
+        }
+    }
+
+    #[test]
+    fn test_find_path_along_strip() {
+        let nav_mesh = nav_mesh(&strip(5, &[]), &[]);
+        let base = nav_mesh.poly_ref_base(nav_mesh.tiles().next().unwrap());
+        let mut query = NavMeshQuery::new(&nav_mesh, 64);
+
+        let start = Vec3::new(1.0, 0.0, 1.0);
+        let end = Vec3::new(9.0, 0.0, 1.0);
+        let path = query
+            .find_path(base, base | 4, start, end, &DefaultFilter)
+            .unwrap();
+        assert_eq!(path.polys, (0..5).map(|i| base | i).collect::<Vec<_>>());
+        assert!(!path.partial && !path.out_of_nodes);
+
+        let same = query.find_path(base | 2, base | 2, end, end, &DefaultFilter);
+        assert_eq!(same.unwrap().polys, vec![base | 2]);
+
+        // Blocked in the middle: get as close as possible instead
+        let blocked = query
+            .find_path(base, base | 4, start, end, &Avoid(2))
+            .unwrap();
+        assert!(blocked.partial);
+        assert_eq!(blocked.polys, vec![base, base | 1]);
+
+        // Too few nodes to reach the end
+        let mut small = NavMeshQuery::new(&nav_mesh, 3);
+        let path = small
+            .find_path(base, base | 4, start, end, &DefaultFilter)
+            .unwrap();
+        assert!(path.partial && path.out_of_nodes);
+    }
+
+    #[test]
+    fn test_find_path_takes_off_mesh_connection() {
+        // Quads 0-1 and 2-3 are not connected, except by a jump
+        let cons = [OffMeshConnection {
+            start: Vec3::new(3.5, 0.0, 1.0),
+            end: Vec3::new(4.5, 0.0, 1.0),
+            radius: 0.5,
+            bidirectional: false,
+            area: RC_WALKABLE_AREA,
+        }];
+        let nav_mesh = nav_mesh(&strip(4, &[1]), &cons);
+        let base = nav_mesh.poly_ref_base(nav_mesh.tiles().next().unwrap());
+        let mut query = NavMeshQuery::new(&nav_mesh, 64);
+
+        let (a, b) = (Vec3::new(1.0, 0.0, 1.0), Vec3::new(7.0, 0.0, 1.0));
+        let path = query
+            .find_path(base, base | 3, a, b, &DefaultFilter)
+            .unwrap();
+        assert_eq!(
+            path.polys,
+            vec![base, base | 1, base | 4, base | 2, base | 3]
+        );
+        assert!(!path.partial);
+        let (left, right) = query.portal_points(base | 1, base | 4).unwrap();
+        assert_eq!((left, right), (cons[0].start, cons[0].start));
+
+        // The jump only goes one way
+        let back = query
+            .find_path(base | 3, base, b, a, &DefaultFilter)
+            .unwrap();
+        assert!(back.partial);
+        assert_eq!(back.polys, vec![base | 3, base | 2]);
+
+        assert!(query
+            .find_path(base | 9, base, a, b, &DefaultFilter)
+            .is_err());
+    }
+
+    #[test]
+    fn test_straight_path_turns_corner() {
+        // An L of three quads: A, B to its right, and C above B
+        let n = RC_MESH_NULL_IDX;
+        let mesh = PolyMesh {
+            verts: [
+                (0, 0),
+                (0, 2),
+                (2, 2),
+                (2, 0),
+                (4, 2),
+                (4, 0),
+                (2, 4),
+                (4, 4),
+            ]
+            .iter()
+            .map(|&(x, z)| Vec3::new(x as f32, 0.0, z as f32))
+            .collect(),
+            polys: vec![
+                vec![0, 1, 2, 3, n, n],
+                vec![3, 2, 4, 5, n, n],
+                vec![2, 6, 7, 4, n, n],
+            ],
+            neighbours: vec![
+                vec![n, n, 1, n, n, n],
+                vec![0, 2, n, n, n, n],
+                vec![n, n, n, 1, n, n],
+            ],
+            regs: vec![1; 3],
+            flags: vec![1; 3],
+            areas: vec![RC_WALKABLE_AREA; 3],
+            nvp: 6,
+            cs: 1.0,
+            ch: 1.0,
+            bmax: Vec3::new(4.0, 1.0, 4.0),
+            ..Default::default()
+        };
+        let nav_mesh = nav_mesh(&mesh, &[]);
+        let base = nav_mesh.poly_ref_base(nav_mesh.tiles().next().unwrap());
+        let mut query = NavMeshQuery::new(&nav_mesh, 64);
+
+        let (start, end) = (Vec3::new(1.0, 0.0, 1.0), Vec3::new(2.5, 0.0, 3.5));
+        let path = query
+            .find_path(base, base | 2, start, end, &DefaultFilter)
+            .unwrap();
+        assert_eq!(path.polys, vec![base, base | 1, base | 2]);
+
+        let points = query.find_straight_path(start, end, &path.polys).unwrap();
+        let positions: Vec<Vec3> = points.iter().map(|p| p.pos).collect();
+        assert_eq!(positions, vec![start, Vec3::new(2.0, 0.0, 2.0), end]);
+        let flags: Vec<u8> = points.iter().map(|p| p.flags).collect();
+        assert_eq!(flags, vec![DT_STRAIGHTPATH_START, 0, DT_STRAIGHTPATH_END]);
+        assert_eq!(points[1].poly_ref, base | 2);
+
+        // An end outside the last polygon is clamped onto its outline
+        let points = query
+            .find_straight_path(start, Vec3::new(1.0, 0.0, 5.0), &[base])
+            .unwrap();
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[1].pos, Vec3::new(1.0, 0.0, 2.0));
+        assert!(query.find_straight_path(start, end, &[]).is_err());
+    }
+
+    #[test]
+    fn test_straight_path_flags_off_mesh_connections() {
+        let cons = [OffMeshConnection {
+            start: Vec3::new(3.5, 0.0, 1.0),
+            end: Vec3::new(4.5, 0.0, 1.0),
+            radius: 0.5,
+            bidirectional: false,
+            area: RC_WALKABLE_AREA,
+        }];
+        let nav_mesh = nav_mesh(&strip(4, &[1]), &cons);
+        let base = nav_mesh.poly_ref_base(nav_mesh.tiles().next().unwrap());
+        let mut query = NavMeshQuery::new(&nav_mesh, 64);
+
+        let (a, b) = (Vec3::new(1.0, 0.0, 1.0), Vec3::new(7.0, 0.0, 1.0));
+        let path = query
+            .find_path(base, base | 3, a, b, &DefaultFilter)
+            .unwrap();
+        let points = query.find_straight_path(a, b, &path.polys).unwrap();
+        let summary: Vec<(Vec3, u8)> = points.iter().map(|p| (p.pos, p.flags)).collect();
+        assert_eq!(
+            summary,
+            vec![
+                (a, DT_STRAIGHTPATH_START),
+                (cons[0].start, DT_STRAIGHTPATH_OFFMESH_CONNECTION),
+                (cons[0].end, 0),
+                (b, DT_STRAIGHTPATH_END),
+            ]
+        );
+        assert_eq!(points[1].poly_ref, base | 4);
+    }
+
+    #[test]
+    fn test_query_polygons_uses_bv_tree() {
+        let mesh = strip(8, &[]);
+        let nav_mesh = nav_mesh(&mesh, &[]);
+        let tile = nav_mesh.tiles().next().unwrap();
+        assert_eq!(tile.data.bv_tree.len(), 15);
+
+        // Everything a scan of the polygon bounds finds, plus the polygons
+        // within a quantization step of the box
+        let (qmin, qmax) = (Vec3::new(5.0, -1.0, 0.5), Vec3::new(9.5, 1.0, 1.5));
+        let mut found = tile.query_polygons(qmin, qmax);
+        found.sort();
+        let scanned: Vec<usize> = (0..8)
+            .filter(|&ip| {
+                let (bmin, bmax) = tile.poly_bounds(ip);
+                overlap_bounds(qmin, qmax, bmin, bmax)
+            })
+            .collect();
+        assert_eq!(scanned, vec![2, 3, 4]);
+        assert_eq!(found, vec![1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_find_nearest_poly() {
+        let nav_mesh = nav_mesh(&strip(4, &[]), &[]);
+        let base = nav_mesh.poly_ref_base(nav_mesh.tiles().next().unwrap());
+        let query = NavMeshQuery::new(&nav_mesh, 64);
+        let extents = Vec3::new(1.0, 2.0, 1.0);
+
+        // Over a polygon: projected straight down onto it
+        let (r, point) = query
+            .find_nearest_poly(Vec3::new(5.0, 1.0, 1.5), extents, &DefaultFilter)
+            .unwrap()
+            .unwrap();
+        assert_eq!(r, base | 2);
+        assert_eq!(point, Vec3::new(5.0, 0.0, 1.5));
+
+        // Beside the mesh: the closest point on the outline
+        let (r, point) = query
+            .find_nearest_poly(Vec3::new(7.0, 0.0, 2.5), extents, &DefaultFilter)
+            .unwrap()
+            .unwrap();
+        assert_eq!(r, base | 3);
+        assert_eq!(point, Vec3::new(7.0, 0.0, 2.0));
+
+        // Filtered polygons are passed over for the next best
+        let (r, _) = query
+            .find_nearest_poly(Vec3::new(5.0, 1.0, 1.5), extents, &Avoid(2))
+            .unwrap()
+            .unwrap();
+        assert_eq!(r, base | 1);
+
+        let far = query.find_nearest_poly(Vec3::new(20.0, 0.0, 1.0), extents, &DefaultFilter);
+        assert_eq!(far.unwrap(), None);
+        assert!(query
+            .find_nearest_poly(Vec3::ZERO, Vec3::splat(-1.0), &DefaultFilter)
+            .is_err());
+    }
+
+    #[test]
+    fn test_sliced_find_path_matches_find_path() {
+        let nav_mesh = nav_mesh(&strip(12, &[]), &[]);
+        let base = nav_mesh.poly_ref_base(nav_mesh.tiles().next().unwrap());
+        let mut query = NavMeshQuery::new(&nav_mesh, 64);
+        let (start, end) = (Vec3::new(1.0, 0.0, 1.0), Vec3::new(23.0, 0.0, 1.0));
+
+        let status = query
+            .init_sliced_find_path(base, base | 11, start, end, DefaultFilter)
+            .unwrap();
+        assert_eq!(status, SlicedStatus::InProgress);
+        let mut updates = 1;
+        while query.update_sliced_find_path(3).unwrap() == SlicedStatus::InProgress {
+            updates += 1;
+        }
+        // Twelve polygons at three per update
+        assert_eq!(updates, 4);
+        let sliced = query.finalize_sliced_find_path().unwrap();
+        let full = query
+            .find_path(base, base | 11, start, end, &DefaultFilter)
+            .unwrap();
+        assert_eq!(sliced, full);
+        assert!(!sliced.partial);
+
+        // Finalizing early gives the best partial corridor so far
+        query
+            .init_sliced_find_path(base, base | 11, start, end, DefaultFilter)
+            .unwrap();
+        query.update_sliced_find_path(2).unwrap();
+        let early = query.finalize_sliced_find_path().unwrap();
+        assert!(early.partial);
+        assert_eq!(early.polys, vec![base, base | 1, base | 2]);
+
+        // There is nothing left to update or finalize
+        assert!(query.update_sliced_find_path(1).is_err());
+        assert!(query.finalize_sliced_find_path().is_err());
+    }
+
+    #[test]
+    fn test_query_filter_flags_and_costs() {
+        // A 3x3 grid with the centre cell disabled, leaving two routes
+        // around it from one corner to the other
+        const DISABLED: u16 = 0x10;
+        const MUD: u8 = 2;
+        let mut mesh = grid(3, 3);
+        mesh.flags[4] |= DISABLED;
+        mesh.areas[1] = MUD;
+        let nav_mesh = nav_mesh(&mesh, &[]);
+        let base = nav_mesh.poly_ref_base(nav_mesh.tiles().next().unwrap());
+        let mut query = NavMeshQuery::new(&nav_mesh, 64);
+        let (start, end) = (Vec3::new(1.0, 0.0, 1.0), Vec3::new(5.0, 0.0, 5.0));
+
+        let mut filter = QueryFilter::default();
+        filter.set_exclude_flags(DISABLED);
+        let path = query
+            .find_path(base, base | 8, start, end, &filter)
+            .unwrap();
+        assert_eq!(path.polys.len(), 5);
+        assert!(!path.polys.contains(&(base | 4)));
+
+        // Mud along the bottom row sends the path up the left column
+        filter.set_area_cost(MUD, 10.0);
+        let path = query
+            .find_path(base, base | 8, start, end, &filter)
+            .unwrap();
+        assert_eq!(path.polys, [0, 3, 6, 7, 8].map(|ip| base | ip).to_vec());
+
+        // Without the exclusion the straight route through the centre wins
+        let path = query
+            .find_path(base, base | 8, start, end, &QueryFilter::default())
+            .unwrap();
+        assert!(path.polys.contains(&(base | 4)));
+        assert_eq!(
+            query
+                .find_nearest_poly(Vec3::new(3.0, 0.0, 3.0), Vec3::ONE, &filter)
+                .unwrap()
+                .map(|(r, _)| r == base | 4),
+            Some(false)
+        );
+    }
+
+    // Deterministic stand-in for a random number generator
+    fn lcg(seed: u32) -> impl FnMut() -> f32 {
+        let mut state = seed;
+        move || {
+            state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+            (state >> 8) as f32 / (1 << 24) as f32
+        }
+    }
+
+    #[test]
+    fn test_random_points_are_area_weighted() {
+        // A 2x2 quad next to a 6x2 one, three times its area
+        let n = RC_MESH_NULL_IDX;
+        let mesh = PolyMesh {
+            verts: [(0, 0), (0, 2), (2, 2), (2, 0), (8, 2), (8, 0)]
+                .iter()
+                .map(|&(x, z)| Vec3::new(x as f32, 0.0, z as f32))
+                .collect(),
+            polys: vec![vec![0, 1, 2, 3, n, n], vec![3, 2, 4, 5, n, n]],
+            neighbours: vec![vec![n, n, 1, n, n, n], vec![0, n, n, n, n, n]],
+            regs: vec![1; 2],
+            flags: vec![POLY_FLAG_WALK; 2],
+            areas: vec![RC_WALKABLE_AREA; 2],
+            nvp: 6,
+            cs: 1.0,
+            ch: 1.0,
+            bmax: Vec3::new(8.0, 1.0, 2.0),
+            ..Default::default()
+        };
+        let nav_mesh = nav_mesh(&mesh, &[]);
+        let base = nav_mesh.poly_ref_base(nav_mesh.tiles().next().unwrap());
+        let query = NavMeshQuery::new(&nav_mesh, 64);
+
+        let mut frand = lcg(7);
+        let mut small = 0;
+        for _ in 0..4000 {
+            let (r, p) = query
+                .find_random_point(&DefaultFilter, &mut frand)
+                .unwrap()
+                .unwrap();
+            assert!((0.0..=8.0).contains(&p.x) && (0.0..=2.0).contains(&p.z));
+            assert_eq!(r == base, p.x <= 2.0);
+            if r == base {
+                small += 1;
+            }
+        }
+        assert!(
+            (800..1200).contains(&small),
+            "{} of 4000 in the small quad",
+            small
+        );
+
+        let mut none = QueryFilter::default();
+        none.set_include_flags(0);
+        assert_eq!(query.find_random_point(&none, &mut frand).unwrap(), None);
+    }
+
+    #[test]
+    fn test_random_point_around_circle_stays_nearby() {
+        let nav_mesh = nav_mesh(&strip(10, &[]), &[]);
+        let base = nav_mesh.poly_ref_base(nav_mesh.tiles().next().unwrap());
+        let mut query = NavMeshQuery::new(&nav_mesh, 64);
+        let center = Vec3::new(1.0, 0.0, 1.0);
+
+        // Edges at x = 2 and x = 4 are within reach, the one at x = 6 isn't
+        let mut frand = lcg(3);
+        let mut seen = [false; 3];
+        for _ in 0..200 {
+            let (r, p) = query
+                .find_random_point_around_circle(base, center, 3.0, &DefaultFilter, &mut frand)
+                .unwrap()
+                .unwrap();
+            let ip = (r - base) as usize;
+            assert!(ip < 3 && p.x < 6.0);
+            seen[ip] = true;
+        }
+        assert_eq!(seen, [true; 3]);
+        assert!(query
+            .find_random_point_around_circle(base | 40, center, 3.0, &DefaultFilter, &mut frand)
+            .is_err());
+    }
+
+    #[test]
+    fn test_find_polys_around_circle() {
+        let nav_mesh = nav_mesh(&strip(10, &[]), &[]);
+        let base = nav_mesh.poly_ref_base(nav_mesh.tiles().next().unwrap());
+        let mut query = NavMeshQuery::new(&nav_mesh, 64);
+
+        let center = Vec3::new(5.0, 0.0, 1.0);
+        let reached = query
+            .find_polys_around_circle(base | 2, center, 2.0, &DefaultFilter)
+            .unwrap();
+        let summary: Vec<(PolyRef, PolyRef, f32)> = reached
+            .iter()
+            .map(|p| (p.poly_ref, p.parent_ref, p.cost))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (base | 2, 0, 0.0),
+                (base | 1, base | 2, 1.0),
+                (base | 3, base | 2, 1.0),
+            ]
+        );
+
+        // Area costs scale the reported costs
+        let mut filter = QueryFilter::default();
+        filter.set_area_cost(RC_WALKABLE_AREA, 2.0);
+        let reached = query
+            .find_polys_around_circle(base | 2, center, 2.0, &filter)
+            .unwrap();
+        assert_eq!(reached[2].cost, 2.0);
+    }
+
+    #[test]
+    fn test_find_polys_around_shape() {
+        let nav_mesh = nav_mesh(&grid(4, 4), &[]);
+        let base = nav_mesh.poly_ref_base(nav_mesh.tiles().next().unwrap());
+        let mut query = NavMeshQuery::new(&nav_mesh, 64);
+
+        // A thin diagonal box from cell (0, 0) to cell (3, 3), given in
+        // either winding
+        let mut shape = vec![
+            Vec3::new(0.5, 0.0, 1.5),
+            Vec3::new(1.5, 0.0, 0.5),
+            Vec3::new(7.5, 0.0, 6.5),
+            Vec3::new(6.5, 0.0, 7.5),
+        ];
+        let mut cells: Vec<u64> = query
+            .find_polys_around_shape(base, &shape, &DefaultFilter)
+            .unwrap()
+            .iter()
+            .map(|p| p.poly_ref - base)
+            .collect();
+        cells.sort();
+        // The diagonal, and the cells whose shared edges it clips on the
+        // way past the grid corners
+        assert!([0, 5, 10, 15].iter().all(|c| cells.contains(c)));
+        assert!(!cells.contains(&3) && !cells.contains(&12));
+
+        shape.reverse();
+        let reversed = query
+            .find_polys_around_shape(base, &shape, &DefaultFilter)
+            .unwrap();
+        assert_eq!(reversed.len(), cells.len());
+        assert!(query
+            .find_polys_around_shape(base, &shape[..2], &DefaultFilter)
+            .is_err());
+    }
+
+    #[test]
+    fn test_find_distance_to_wall() {
+        let nav_mesh = nav_mesh(&grid(4, 4), &[]);
+        let base = nav_mesh.poly_ref_base(nav_mesh.tiles().next().unwrap());
+        let mut query = NavMeshQuery::new(&nav_mesh, 64);
+
+        // Cell (1, 1) is 2.5 from the wall at z = 0
+        let center = Vec3::new(3.0, 0.0, 2.5);
+        let hit = query
+            .find_distance_to_wall(base | 5, center, 10.0, &DefaultFilter)
+            .unwrap()
+            .unwrap();
+        assert!((hit.distance - 2.5).abs() < 1e-5);
+        assert!((hit.pos - Vec3::new(3.0, 0.0, 0.0)).length() < 1e-5);
+        assert!((hit.normal - Vec3::Z).length() < 1e-5);
+
+        // Nothing within a short radius, until the cell next door is
+        // excluded and its edge becomes a wall
+        assert_eq!(
+            query
+                .find_distance_to_wall(base | 5, center, 1.0, &DefaultFilter)
+                .unwrap(),
+            None
+        );
+        let hit = query
+            .find_distance_to_wall(base | 5, center, 1.0, &Avoid(4))
+            .unwrap()
+            .unwrap();
+        assert!((hit.distance - 1.0).abs() < 1e-5);
+        assert!((hit.normal - Vec3::X).length() < 1e-5);
+    }
+
+    #[test]
+    fn test_raycast_stops_at_wall() {
+        // Quads 0-1-2 linked, 3 cut off from them
+        let nav_mesh = nav_mesh(&strip(4, &[2]), &[]);
+        let base = nav_mesh.poly_ref_base(nav_mesh.tiles().next().unwrap());
+        let query = NavMeshQuery::new(&nav_mesh, 64);
+
+        let start = Vec3::new(1.0, 0.0, 1.0);
+        let hit = query
+            .raycast(base, start, Vec3::new(5.0, 0.0, 1.0), &DefaultFilter)
+            .unwrap();
+        assert_eq!(hit.t, f32::MAX);
+        assert_eq!(hit.path, vec![base, base | 1, base | 2]);
+
+        let hit = query
+            .raycast(base, start, Vec3::new(9.0, 0.0, 1.0), &DefaultFilter)
+            .unwrap();
+        assert!((hit.t - 0.625).abs() < 1e-5);
+        assert!((hit.normal + Vec3::X).length() < 1e-5);
+        assert_eq!(hit.path.len(), 3);
+    }
+
+    #[test]
+    fn test_query_point_to_point() {
+        let nav_mesh = nav_mesh(&strip(4, &[2]), &[]);
+        let base = nav_mesh.poly_ref_base(nav_mesh.tiles().next().unwrap());
+        let mut query = NavMeshQuery::new(&nav_mesh, 64);
+        let extents = Vec3::new(1.0, 2.0, 1.0);
+
+        // Both ends are snapped down onto the strip
+        let (start, end) = (Vec3::new(1.0, 1.0, 1.0), Vec3::new(5.0, 1.0, 1.0));
+        let result = query
+            .query_point_to_point(start, end, extents, &DefaultFilter)
+            .unwrap();
+        assert_eq!(result.start, Some((base, Vec3::new(1.0, 0.0, 1.0))));
+        assert_eq!(result.end, Some((base | 2, Vec3::new(5.0, 0.0, 1.0))));
+        assert_eq!(result.path.polys, vec![base, base | 1, base | 2]);
+        assert_eq!(result.straight_path.len(), 2);
+        assert_eq!(result.raycast.unwrap().t, f32::MAX);
+
+        // Across the gap the path stops short and the raycast hits the wall
+        let result = query
+            .query_point_to_point(start, Vec3::new(7.0, 0.0, 1.0), extents, &DefaultFilter)
+            .unwrap();
+        assert!(result.path.partial);
+        assert!(result.raycast.unwrap().t < 1.0);
+
+        // Nothing else is queried until both ends are on the navmesh
+        let result = query
+            .query_point_to_point(start, Vec3::new(20.0, 0.0, 1.0), extents, &DefaultFilter)
+            .unwrap();
+        assert!(result.start.is_some() && result.end.is_none());
+        assert!(result.path.polys.is_empty() && result.raycast.is_none());
+    }
+}
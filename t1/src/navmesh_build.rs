@@ -0,0 +1,1005 @@
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use glam::{Mat4, Vec3};
+
+use crate::build_context::{BuildContext, BuildStage, MemoryUsage};
+use crate::chunky_tri_mesh::{ChunkyTriMesh, DEFAULT_TRIS_PER_CHUNK};
+use crate::compact_heightfield::CompactHeightfield;
+use crate::contours::{build_contours, ContourSet, RC_CONTOUR_TESS_WALL_EDGES};
+use crate::convex_volume::{mark_convex_poly_area, ConvexVolume};
+use crate::debug_draw::{PolyMesh, PolyMeshDetail, RC_NULL_AREA, RC_WALKABLE_AREA};
+use crate::debug_draw_b::{walkable_threshold, InputMesh};
+use crate::filter::{
+    filter_ledge_spans, filter_low_hanging_walkable_obstacles, filter_walkable_low_height_spans,
+};
+use crate::heightfield::{
+    calc_grid_size, rasterize_triangle_subset, rasterize_triangles, Heightfield,
+};
+use crate::nav_mesh::{
+    NavMesh, NavMeshCreateParams, NavMeshData, NavMeshError, NavMeshParams, POLY_FLAG_WALK,
+};
+use crate::offmesh::OffMeshConnection;
+use crate::regions::{build_regions, PartitionType};
+use crate::scene::Scene;
+use crate::tile_compressor::CompressionError;
+
+// Detour stores polygons with at most this many vertices
+pub const MAX_VERTS_PER_POLY: usize = 6;
+
+// Tiles can't have more polygons than vertices, which are u16 indices
+pub const MAX_TILE_POLYS: usize = 0xffff;
+
+// Everything the bake pipeline needs, in world units unless noted otherwise.
+// Converted to voxel units by `build_navmesh`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NavMeshBuildConfig {
+    // Voxel size on the XZ plane and along Y
+    pub cell_size: f32,
+    pub cell_height: f32,
+    // Agent clearance, step height, radius kept from walls and max slope
+    // in degrees
+    pub agent_height: f32,
+    pub agent_max_climb: f32,
+    pub agent_radius: f32,
+    pub agent_max_slope: f32,
+    // Regions with fewer cells are removed, and ones with fewer are merged
+    // into neighbours when possible
+    pub min_region_area: i32,
+    pub merge_region_area: i32,
+    pub partition: PartitionType,
+    // Longest outline edge, and max distance of the simplified outline from
+    // the voxel boundary in cells
+    pub edge_max_len: f32,
+    pub edge_max_error: f32,
+    pub verts_per_poly: usize,
+    // Detail mesh sample spacing and max height error, in cells; spacings
+    // below 0.9 disable sampling
+    pub detail_sample_dist: f32,
+    pub detail_sample_max_error: f32,
+    // Tile edge length in cells for tiled builds
+    pub tile_size: i32,
+}
+
+impl Default for NavMeshBuildConfig {
+    fn default() -> Self {
+        Self {
+            cell_size: 0.3,
+            cell_height: 0.2,
+            agent_height: 2.0,
+            agent_max_climb: 0.9,
+            agent_radius: 0.6,
+            agent_max_slope: 45.0,
+            min_region_area: 64,
+            merge_region_area: 400,
+            partition: PartitionType::Watershed,
+            edge_max_len: 12.0,
+            edge_max_error: 1.3,
+            verts_per_poly: MAX_VERTS_PER_POLY,
+            detail_sample_dist: 6.0,
+            detail_sample_max_error: 1.0,
+            tile_size: 64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuildConfigError {
+    // The named value must be finite and above zero
+    NotPositive(&'static str),
+    // The named value must be finite and zero or more
+    Negative(&'static str),
+    SlopeOutOfRange(f32),
+    VertsPerPoly(usize),
+}
+
+impl fmt::Display for BuildConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildConfigError::NotPositive(name) => write!(f, "{} must be greater than zero", name),
+            BuildConfigError::Negative(name) => write!(f, "{} must not be negative", name),
+            BuildConfigError::SlopeOutOfRange(slope) => {
+                write!(f, "max slope {} is outside 0..90 degrees", slope)
+            }
+            BuildConfigError::VertsPerPoly(nvp) => write!(
+                f,
+                "{} vertices per polygon is outside 3..={}",
+                nvp, MAX_VERTS_PER_POLY
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NavMeshBuildError {
+    InvalidConfig(BuildConfigError),
+    // No triangles to voxelize
+    EmptyMesh,
+    // Assembling the runtime tiles failed
+    Tile(NavMeshError),
+    // Packing or unpacking cached tile data failed
+    Compression(CompressionError),
+    // Stopped through `BuildTask::cancel`
+    Cancelled,
+}
+
+impl From<BuildConfigError> for NavMeshBuildError {
+    fn from(error: BuildConfigError) -> Self {
+        NavMeshBuildError::InvalidConfig(error)
+    }
+}
+
+impl From<NavMeshError> for NavMeshBuildError {
+    fn from(error: NavMeshError) -> Self {
+        NavMeshBuildError::Tile(error)
+    }
+}
+
+impl From<CompressionError> for NavMeshBuildError {
+    fn from(error: CompressionError) -> Self {
+        NavMeshBuildError::Compression(error)
+    }
+}
+
+impl fmt::Display for NavMeshBuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NavMeshBuildError::InvalidConfig(error) => write!(f, "invalid config: {}", error),
+            NavMeshBuildError::EmptyMesh => write!(f, "the input mesh has no triangles"),
+            NavMeshBuildError::Tile(error) => write!(f, "tile assembly failed: {}", error),
+            NavMeshBuildError::Compression(error) => write!(f, "{}", error),
+            NavMeshBuildError::Cancelled => write!(f, "the build was cancelled"),
+        }
+    }
+}
+
+impl NavMeshBuildConfig {
+    pub fn builder() -> NavMeshBuildConfigBuilder {
+        NavMeshBuildConfigBuilder {
+            config: Self::default(),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), BuildConfigError> {
+        let positive = [
+            ("cell_size", self.cell_size),
+            ("cell_height", self.cell_height),
+            ("agent_height", self.agent_height),
+            ("tile_size", self.tile_size as f32),
+        ];
+        for (name, value) in positive {
+            if !(value.is_finite() && value > 0.0) {
+                return Err(BuildConfigError::NotPositive(name));
+            }
+        }
+
+        let non_negative = [
+            ("agent_max_climb", self.agent_max_climb),
+            ("agent_radius", self.agent_radius),
+            ("min_region_area", self.min_region_area as f32),
+            ("merge_region_area", self.merge_region_area as f32),
+            ("edge_max_len", self.edge_max_len),
+            ("edge_max_error", self.edge_max_error),
+            ("detail_sample_dist", self.detail_sample_dist),
+            ("detail_sample_max_error", self.detail_sample_max_error),
+        ];
+        for (name, value) in non_negative {
+            if !(value.is_finite() && value >= 0.0) {
+                return Err(BuildConfigError::Negative(name));
+            }
+        }
+
+        if !(0.0..=90.0).contains(&self.agent_max_slope) {
+            return Err(BuildConfigError::SlopeOutOfRange(self.agent_max_slope));
+        }
+        if !(3..=MAX_VERTS_PER_POLY).contains(&self.verts_per_poly) {
+            return Err(BuildConfigError::VertsPerPoly(self.verts_per_poly));
+        }
+        Ok(())
+    }
+
+    // Agent dimensions in voxels: clearance and step rounded to stay safe,
+    // radius rounded up
+    pub fn walkable_height(&self) -> i32 {
+        (self.agent_height / self.cell_height).ceil() as i32
+    }
+
+    pub fn walkable_climb(&self) -> i32 {
+        (self.agent_max_climb / self.cell_height).floor() as i32
+    }
+
+    pub fn walkable_radius(&self) -> i32 {
+        (self.agent_radius / self.cell_size).ceil() as i32
+    }
+}
+
+// Fluent construction of a NavMeshBuildConfig, validated by `build`
+#[derive(Debug, Clone)]
+pub struct NavMeshBuildConfigBuilder {
+    config: NavMeshBuildConfig,
+}
+
+impl NavMeshBuildConfigBuilder {
+    pub fn cell_size(mut self, cell_size: f32) -> Self {
+        self.config.cell_size = cell_size;
+        self
+    }
+
+    pub fn cell_height(mut self, cell_height: f32) -> Self {
+        self.config.cell_height = cell_height;
+        self
+    }
+
+    pub fn agent_height(mut self, height: f32) -> Self {
+        self.config.agent_height = height;
+        self
+    }
+
+    pub fn agent_max_climb(mut self, climb: f32) -> Self {
+        self.config.agent_max_climb = climb;
+        self
+    }
+
+    pub fn agent_radius(mut self, radius: f32) -> Self {
+        self.config.agent_radius = radius;
+        self
+    }
+
+    pub fn agent_max_slope(mut self, degrees: f32) -> Self {
+        self.config.agent_max_slope = degrees;
+        self
+    }
+
+    pub fn min_region_area(mut self, cells: i32) -> Self {
+        self.config.min_region_area = cells;
+        self
+    }
+
+    pub fn merge_region_area(mut self, cells: i32) -> Self {
+        self.config.merge_region_area = cells;
+        self
+    }
+
+    pub fn partition(mut self, partition: PartitionType) -> Self {
+        self.config.partition = partition;
+        self
+    }
+
+    pub fn edge_max_len(mut self, len: f32) -> Self {
+        self.config.edge_max_len = len;
+        self
+    }
+
+    pub fn edge_max_error(mut self, error: f32) -> Self {
+        self.config.edge_max_error = error;
+        self
+    }
+
+    pub fn verts_per_poly(mut self, nvp: usize) -> Self {
+        self.config.verts_per_poly = nvp;
+        self
+    }
+
+    pub fn detail_sample(mut self, dist: f32, max_error: f32) -> Self {
+        self.config.detail_sample_dist = dist;
+        self.config.detail_sample_max_error = max_error;
+        self
+    }
+
+    pub fn tile_size(mut self, cells: i32) -> Self {
+        self.config.tile_size = cells;
+        self
+    }
+
+    pub fn build(self) -> Result<NavMeshBuildConfig, BuildConfigError> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
+
+// Output of the bake pipeline
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BakedNavMesh {
+    pub poly_mesh: PolyMesh,
+    pub detail_mesh: PolyMeshDetail,
+}
+
+// Run the whole pipeline on `mesh`: rasterize, filter, partition into
+// regions, trace contours and build the polygon and detail meshes
+pub fn build_navmesh(
+    mesh: &InputMesh,
+    cfg: &NavMeshBuildConfig,
+) -> Result<BakedNavMesh, NavMeshBuildError> {
+    build_navmesh_with_volumes(mesh, cfg, &[])
+}
+
+// `build_navmesh`, stamping the areas of `volumes` before partitioning
+pub fn build_navmesh_with_volumes(
+    mesh: &InputMesh,
+    cfg: &NavMeshBuildConfig,
+    volumes: &[ConvexVolume],
+) -> Result<BakedNavMesh, NavMeshBuildError> {
+    build_navmesh_with_context(mesh, cfg, volumes, &mut BuildContext::new())
+}
+
+// `build_navmesh_with_volumes`, recording stage timings in `ctx`
+pub fn build_navmesh_with_context(
+    mesh: &InputMesh,
+    cfg: &NavMeshBuildConfig,
+    volumes: &[ConvexVolume],
+    ctx: &mut BuildContext,
+) -> Result<BakedNavMesh, NavMeshBuildError> {
+    let hf = build_heightfield(mesh, cfg, ctx)?;
+    Ok(bake_filtered(&hf, cfg, volumes, 0, ctx))
+}
+
+// The first half of `build_navmesh`: `mesh` voxelized over its bounds with
+// the unwalkable spans filtered out. Finish with `bake_filtered`.
+pub fn build_heightfield(
+    mesh: &InputMesh,
+    cfg: &NavMeshBuildConfig,
+    ctx: &mut BuildContext,
+) -> Result<Heightfield, NavMeshBuildError> {
+    cfg.validate()?;
+    if mesh.tri_count() == 0 {
+        return Err(NavMeshBuildError::EmptyMesh);
+    }
+
+    let areas = mark_walkable_triangles(cfg.agent_max_slope, mesh);
+    let mut hf = Heightfield::for_mesh(mesh, cfg.cell_size, cfg.cell_height);
+    rasterize_filtered(&mut hf, mesh, None, &areas, cfg, ctx);
+    Ok(hf)
+}
+
+// `build_navmesh_with_context` over the instances of `scene`, each
+// transformed as it is rasterized
+pub fn build_scene_navmesh(
+    scene: &Scene,
+    cfg: &NavMeshBuildConfig,
+    volumes: &[ConvexVolume],
+    ctx: &mut BuildContext,
+) -> Result<BakedNavMesh, NavMeshBuildError> {
+    cfg.validate()?;
+    if scene.tri_count() == 0 {
+        return Err(NavMeshBuildError::EmptyMesh);
+    }
+
+    let areas = scene.walkable_areas(cfg.agent_max_slope);
+    let (bmin, bmax) = scene.bounds();
+    let mut hf = Heightfield::new(bmin, bmax, cfg.cell_size, cfg.cell_height);
+    ctx.time(BuildStage::Rasterize, |_| {
+        scene.rasterize(&mut hf, &areas, cfg.walkable_climb())
+    });
+    ctx.record_memory(BuildStage::Rasterize, hf.memory_bytes());
+    filter_spans(&mut hf, cfg, ctx);
+    Ok(bake_filtered(&hf, cfg, volumes, 0, ctx))
+}
+
+// Tiles finished so far in a tiled build, out of `tile_count`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BuildProgress {
+    pub tiles_done: usize,
+    pub tile_count: usize,
+}
+
+// Build `mesh` as a grid of `tile_size` cell tiles over its bounds and
+// link them into one navmesh. Each tile is voxelized with a border of
+// extra cells around it, so its edges come out the same as in a single
+// build. Tiles without polygons are left out.
+pub fn build_tiled_navmesh(
+    mesh: &InputMesh,
+    cfg: &NavMeshBuildConfig,
+    volumes: &[ConvexVolume],
+    off_mesh_connections: &[OffMeshConnection],
+) -> Result<NavMesh, NavMeshBuildError> {
+    build_tiled_navmesh_with_progress(
+        mesh,
+        cfg,
+        volumes,
+        off_mesh_connections,
+        &mut BuildContext::new(),
+        |_| {},
+    )
+}
+
+// `build_tiled_navmesh`, adding the stage timings of every tile to `ctx`
+// and calling `progress` as each tile completes. With the rayon feature
+// tiles are baked in parallel and the calls come from worker threads,
+// possibly out of order.
+pub fn build_tiled_navmesh_with_progress(
+    mesh: &InputMesh,
+    cfg: &NavMeshBuildConfig,
+    volumes: &[ConvexVolume],
+    off_mesh_connections: &[OffMeshConnection],
+    ctx: &mut BuildContext,
+    progress: impl Fn(BuildProgress) + Sync,
+) -> Result<NavMesh, NavMeshBuildError> {
+    cfg.validate()?;
+    if mesh.tri_count() == 0 {
+        return Err(NavMeshBuildError::EmptyMesh);
+    }
+
+    let grid = TileGrid::new(mesh, cfg);
+    let areas = mark_walkable_triangles(cfg.agent_max_slope, mesh);
+    let chunky = ChunkyTriMesh::build(mesh, DEFAULT_TRIS_PER_CHUNK);
+    let rasterize = |hf: &mut Heightfield, tile_ctx: &mut BuildContext| {
+        rasterize_filtered(hf, mesh, Some(&chunky), &areas, cfg, tile_ctx)
+    };
+    build_tiles(
+        &grid,
+        cfg,
+        volumes,
+        off_mesh_connections,
+        ctx,
+        progress,
+        rasterize,
+    )
+}
+
+// `build_tiled_navmesh_with_progress` over the instances of `scene`. Each
+// tile only rasterizes the instances whose bounds reach it.
+pub fn build_tiled_scene_navmesh(
+    scene: &Scene,
+    cfg: &NavMeshBuildConfig,
+    volumes: &[ConvexVolume],
+    off_mesh_connections: &[OffMeshConnection],
+    ctx: &mut BuildContext,
+    progress: impl Fn(BuildProgress) + Sync,
+) -> Result<NavMesh, NavMeshBuildError> {
+    cfg.validate()?;
+    if scene.tri_count() == 0 {
+        return Err(NavMeshBuildError::EmptyMesh);
+    }
+
+    let (bmin, bmax) = scene.bounds();
+    let grid = TileGrid::for_bounds(bmin, bmax, cfg);
+    let areas = scene.walkable_areas(cfg.agent_max_slope);
+    let rasterize = |hf: &mut Heightfield, tile_ctx: &mut BuildContext| {
+        tile_ctx.time(BuildStage::Rasterize, |_| {
+            scene.rasterize(hf, &areas, cfg.walkable_climb())
+        });
+        tile_ctx.record_memory(BuildStage::Rasterize, hf.memory_bytes());
+        filter_spans(hf, cfg, tile_ctx);
+    };
+    build_tiles(
+        &grid,
+        cfg,
+        volumes,
+        off_mesh_connections,
+        ctx,
+        progress,
+        rasterize,
+    )
+}
+
+// Bake every tile of `grid`, filling each tile's heightfield with
+// `rasterize`, and link the tiles with polygons into one navmesh
+fn build_tiles(
+    grid: &TileGrid,
+    cfg: &NavMeshBuildConfig,
+    volumes: &[ConvexVolume],
+    off_mesh_connections: &[OffMeshConnection],
+    ctx: &mut BuildContext,
+    progress: impl Fn(BuildProgress) + Sync,
+    rasterize: impl Fn(&mut Heightfield, &mut BuildContext) + Sync,
+) -> Result<NavMesh, NavMeshBuildError> {
+    let coords = grid.coords();
+    let tiles_done = AtomicUsize::new(0);
+    let bake = |(tx, ty): (i32, i32)| {
+        let mut tile_ctx = BuildContext::new();
+        let mut hf = grid.heightfield(tx, ty, cfg);
+        rasterize(&mut hf, &mut tile_ctx);
+        let baked = bake_filtered(&hf, cfg, volumes, grid.border_size, &mut tile_ctx);
+        let data = tile_data(&baked, cfg, tx, ty, 0, off_mesh_connections);
+        progress(BuildProgress {
+            tiles_done: tiles_done.fetch_add(1, Ordering::Relaxed) + 1,
+            tile_count: coords.len(),
+        });
+        (data, tile_ctx)
+    };
+    let tiles = map_tiles(&coords, bake)
+        .into_iter()
+        .filter_map(|(data, tile_ctx)| {
+            ctx.merge(&tile_ctx);
+            data.transpose()
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let max_polys = tiles.iter().map(|t| t.polys.len()).max().unwrap_or(1);
+    let mut nav_mesh = NavMesh::new(grid.nav_mesh_params(max_polys))?;
+    for data in tiles {
+        nav_mesh.add_tile(data)?;
+    }
+    Ok(nav_mesh)
+}
+
+// Grid of square tiles over the bounds of an input mesh
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileGrid {
+    pub bmin: Vec3,
+    pub bmax: Vec3,
+    pub tile_width: f32,
+    // Tile columns along X and rows along Z
+    pub width: i32,
+    pub height: i32,
+    // Cells voxelized around each tile for context. Enough that erosion
+    // and ledge filtering along the tile edge see the same neighbourhood
+    // as they would without tiling.
+    pub border_size: i32,
+}
+
+impl TileGrid {
+    pub fn new(mesh: &InputMesh, cfg: &NavMeshBuildConfig) -> Self {
+        let (bmin, bmax) = mesh.bounds();
+        Self::for_bounds(bmin, bmax, cfg)
+    }
+
+    pub fn for_bounds(bmin: Vec3, bmax: Vec3, cfg: &NavMeshBuildConfig) -> Self {
+        let (gw, gh) = calc_grid_size(bmin, bmax, cfg.cell_size);
+        let ts = cfg.tile_size;
+        Self {
+            bmin,
+            bmax,
+            tile_width: ts as f32 * cfg.cell_size,
+            width: (gw + ts - 1) / ts,
+            height: (gh + ts - 1) / ts,
+            border_size: cfg.walkable_radius() + 3,
+        }
+    }
+
+    // Every tile, row by row
+    pub fn coords(&self) -> Vec<(i32, i32)> {
+        (0..self.height)
+            .flat_map(|ty| (0..self.width).map(move |tx| (tx, ty)))
+            .collect()
+    }
+
+    pub fn tile_bounds(&self, tx: i32, ty: i32) -> (Vec3, Vec3) {
+        let bmin = Vec3::new(
+            self.bmin.x + tx as f32 * self.tile_width,
+            self.bmin.y,
+            self.bmin.z + ty as f32 * self.tile_width,
+        );
+        let bmax = Vec3::new(
+            bmin.x + self.tile_width,
+            self.bmax.y,
+            bmin.z + self.tile_width,
+        );
+        (bmin, bmax)
+    }
+
+    // Tiles whose bounds, grown by `pad` on the XZ plane, overlap the box
+    // from `bmin` to `bmax`, row by row. Parts of the box outside the grid
+    // are ignored.
+    pub fn tiles_overlapping(&self, bmin: Vec3, bmax: Vec3, pad: f32) -> Vec<(i32, i32)> {
+        let tile_of = |v: f32, origin: f32, max: i32| {
+            (((v - origin) / self.tile_width).floor() as i32).clamp(0, max - 1)
+        };
+        if bmax.x + pad < self.bmin.x
+            || bmax.z + pad < self.bmin.z
+            || bmin.x - pad > self.bmin.x + self.width as f32 * self.tile_width
+            || bmin.z - pad > self.bmin.z + self.height as f32 * self.tile_width
+        {
+            return Vec::new();
+        }
+        let (x0, x1) = (
+            tile_of(bmin.x - pad, self.bmin.x, self.width),
+            tile_of(bmax.x + pad, self.bmin.x, self.width),
+        );
+        let (z0, z1) = (
+            tile_of(bmin.z - pad, self.bmin.z, self.height),
+            tile_of(bmax.z + pad, self.bmin.z, self.height),
+        );
+        (z0..=z1)
+            .flat_map(|ty| (x0..=x1).map(move |tx| (tx, ty)))
+            .collect()
+    }
+
+    // Empty heightfield over the tile and its border
+    pub fn heightfield(&self, tx: i32, ty: i32, cfg: &NavMeshBuildConfig) -> Heightfield {
+        let (bmin, bmax) = self.tile_bounds(tx, ty);
+        let pad = self.border_size as f32 * cfg.cell_size;
+        let pad = Vec3::new(pad, 0.0, pad);
+        Heightfield::new(bmin - pad, bmax + pad, cfg.cell_size, cfg.cell_height)
+    }
+
+    pub fn nav_mesh_params(&self, max_polys: usize) -> NavMeshParams {
+        NavMeshParams {
+            orig: self.bmin,
+            tile_width: self.tile_width,
+            tile_height: self.tile_width,
+            max_tiles: (self.width * self.height).max(1) as usize,
+            max_polys,
+        }
+    }
+}
+
+// Runtime data for tile (tx, ty, layer), or None if it has no polygons
+pub fn tile_data(
+    baked: &BakedNavMesh,
+    cfg: &NavMeshBuildConfig,
+    tx: i32,
+    ty: i32,
+    layer: i32,
+    off_mesh_connections: &[OffMeshConnection],
+) -> Result<Option<NavMeshData>, NavMeshError> {
+    if baked.poly_mesh.polys.is_empty() {
+        return Ok(None);
+    }
+    let params = NavMeshCreateParams {
+        off_mesh_connections,
+        tile_x: tx,
+        tile_y: ty,
+        tile_layer: layer,
+        ..NavMeshCreateParams::new(
+            &baked.poly_mesh,
+            Some(&baked.detail_mesh),
+            cfg.agent_height,
+            cfg.agent_radius,
+            cfg.agent_max_climb,
+        )
+    };
+    NavMeshData::build(&params).map(Some)
+}
+
+#[cfg(not(feature = "rayon"))]
+fn map_tiles<T>(coords: &[(i32, i32)], bake: impl Fn((i32, i32)) -> T) -> Vec<T> {
+    coords.iter().map(|&c| bake(c)).collect()
+}
+
+#[cfg(feature = "rayon")]
+fn map_tiles<T: Send>(coords: &[(i32, i32)], bake: impl Fn((i32, i32)) -> T + Sync) -> Vec<T> {
+    use rayon::prelude::*;
+
+    // Indexed collect keeps tiles in grid order, so tile slots and
+    // references match the serial build
+    coords.par_iter().map(|&c| bake(c)).collect()
+}
+
+// Area id of each triangle of `mesh` as fed to rasterization: its own
+// area (RC_WALKABLE_AREA if it has none), or RC_NULL_AREA when steeper
+// than `walkable_slope_angle` degrees. The viewer's slope coloring uses the
+// same result. Like Recast's rcMarkWalkableTriangles.
+pub fn mark_walkable_triangles(walkable_slope_angle: f32, mesh: &InputMesh) -> Vec<u8> {
+    mark_walkable_triangles_transformed(walkable_slope_angle, mesh, Mat4::IDENTITY)
+}
+
+// `mark_walkable_triangles` for `mesh` placed in the world by `transform`.
+// A mirroring transform keeps the triangles facing the same way.
+pub fn mark_walkable_triangles_transformed(
+    walkable_slope_angle: f32,
+    mesh: &InputMesh,
+    transform: Mat4,
+) -> Vec<u8> {
+    let walkable_thr = walkable_threshold(walkable_slope_angle);
+    let facing = transform.determinant().signum();
+    mesh.tris
+        .chunks_exact(3)
+        .enumerate()
+        .map(|(i, tri)| {
+            let [a, b, c] =
+                [0, 1, 2].map(|k| transform.transform_point3(mesh.verts[tri[k] as usize]));
+            // Degenerate triangles have no normal and count as steep
+            if facing * (b - a).cross(c - a).normalize_or_zero().y < walkable_thr {
+                RC_NULL_AREA
+            } else {
+                mesh.areas.get(i).copied().unwrap_or(RC_WALKABLE_AREA)
+            }
+        })
+        .collect()
+}
+
+// Rasterize `mesh` into `hf` with the given triangle areas and filter out
+// the spans an agent can't stand on. With `chunky`, only the triangles
+// under the heightfield's bounds are visited.
+pub fn rasterize_filtered(
+    hf: &mut Heightfield,
+    mesh: &InputMesh,
+    chunky: Option<&ChunkyTriMesh>,
+    areas: &[u8],
+    cfg: &NavMeshBuildConfig,
+    ctx: &mut BuildContext,
+) {
+    let walkable_climb = cfg.walkable_climb();
+    ctx.time(BuildStage::Rasterize, |_| match chunky {
+        Some(chunky) => {
+            let tris = chunky.tris_overlapping(hf.bmin, hf.bmax);
+            rasterize_triangle_subset(hf, mesh, areas, &tris, walkable_climb);
+        }
+        None => rasterize_triangles(hf, mesh, areas, walkable_climb),
+    });
+    ctx.record_memory(BuildStage::Rasterize, hf.memory_bytes());
+    filter_spans(hf, cfg, ctx);
+}
+
+// Remove the spans an agent can't stand on
+fn filter_spans(hf: &mut Heightfield, cfg: &NavMeshBuildConfig, ctx: &mut BuildContext) {
+    let walkable_height = cfg.walkable_height();
+    let walkable_climb = cfg.walkable_climb();
+    ctx.time(BuildStage::Filter, |_| {
+        filter_low_hanging_walkable_obstacles(hf, walkable_climb);
+        filter_ledge_spans(hf, walkable_height, walkable_climb);
+        filter_walkable_low_height_spans(hf, walkable_height);
+    });
+}
+
+// Run the rest of the pipeline on a filtered heightfield. The outer
+// `border_size` cells only give context and produce no polygons.
+pub fn bake_filtered(
+    hf: &Heightfield,
+    cfg: &NavMeshBuildConfig,
+    volumes: &[ConvexVolume],
+    border_size: i32,
+    ctx: &mut BuildContext,
+) -> BakedNavMesh {
+    let chf = eroded_compact(hf, cfg, ctx);
+    bake_compact(chf, cfg, volumes, border_size, ctx)
+}
+
+// Compact heightfield of `hf` with the agent radius eroded off its edges.
+// Counted as part of the filter stage.
+pub fn eroded_compact(
+    hf: &Heightfield,
+    cfg: &NavMeshBuildConfig,
+    ctx: &mut BuildContext,
+) -> CompactHeightfield {
+    let chf = ctx.time(BuildStage::Filter, |_| {
+        let mut chf = CompactHeightfield::build(hf, cfg.walkable_height(), cfg.walkable_climb());
+        chf.erode_walkable_area(cfg.walkable_radius());
+        chf
+    });
+    ctx.record_memory(BuildStage::Filter, chf.memory_bytes());
+    chf
+}
+
+// Partition an eroded compact heightfield and turn it into polygons
+pub fn bake_compact(
+    mut chf: CompactHeightfield,
+    cfg: &NavMeshBuildConfig,
+    volumes: &[ConvexVolume],
+    border_size: i32,
+    ctx: &mut BuildContext,
+) -> BakedNavMesh {
+    partition_compact(&mut chf, cfg, volumes, border_size, ctx);
+    polygonize(&chf, cfg, ctx)
+}
+
+// Stamp the areas of `volumes` into `chf` and split it into regions
+pub fn partition_compact(
+    chf: &mut CompactHeightfield,
+    cfg: &NavMeshBuildConfig,
+    volumes: &[ConvexVolume],
+    border_size: i32,
+    ctx: &mut BuildContext,
+) {
+    ctx.time(BuildStage::Regions, |_| {
+        for vol in volumes {
+            mark_convex_poly_area(chf, &vol.verts, vol.hmin, vol.hmax, vol.area);
+        }
+        build_regions(
+            chf,
+            cfg.partition,
+            border_size,
+            cfg.min_region_area,
+            cfg.merge_region_area,
+        );
+    });
+    ctx.record_memory(BuildStage::Regions, chf.memory_bytes());
+}
+
+// Trace the regions of a partitioned compact heightfield and build the
+// polygon and detail meshes from them
+pub fn polygonize(
+    chf: &CompactHeightfield,
+    cfg: &NavMeshBuildConfig,
+    ctx: &mut BuildContext,
+) -> BakedNavMesh {
+    let cset = trace_contours(chf, cfg, ctx);
+    mesh_contours(&cset, chf, cfg, ctx)
+}
+
+// Simplified outlines of the regions of `chf`
+pub fn trace_contours(
+    chf: &CompactHeightfield,
+    cfg: &NavMeshBuildConfig,
+    ctx: &mut BuildContext,
+) -> ContourSet {
+    let cset = ctx.time(BuildStage::Contours, |ctx| {
+        build_contours(
+            chf,
+            cfg.edge_max_error,
+            (cfg.edge_max_len / cfg.cell_size) as i32,
+            RC_CONTOUR_TESS_WALL_EDGES,
+            ctx,
+        )
+    });
+    ctx.record_memory(BuildStage::Contours, cset.memory_bytes());
+    cset
+}
+
+// Polygon and detail meshes from the contours traced out of `chf`
+pub fn mesh_contours(
+    cset: &ContourSet,
+    chf: &CompactHeightfield,
+    cfg: &NavMeshBuildConfig,
+    ctx: &mut BuildContext,
+) -> BakedNavMesh {
+    let (cs, ch) = (cfg.cell_size, cfg.cell_height);
+    let mut poly_mesh = ctx.time(BuildStage::PolyMesh, |ctx| {
+        PolyMesh::build(cset, cfg.verts_per_poly, ctx)
+    });
+    poly_mesh.flags.fill(POLY_FLAG_WALK);
+    ctx.record_memory(BuildStage::PolyMesh, poly_mesh.memory_bytes());
+
+    let sample_dist = if cfg.detail_sample_dist < 0.9 {
+        0.0
+    } else {
+        cs * cfg.detail_sample_dist
+    };
+    let detail_mesh = ctx.time(BuildStage::Detail, |ctx| {
+        PolyMeshDetail::build(
+            &poly_mesh,
+            chf,
+            sample_dist,
+            ch * cfg.detail_sample_max_error,
+            ctx,
+        )
+    });
+    ctx.record_memory(BuildStage::Detail, detail_mesh.memory_bytes());
+
+    BakedNavMesh {
+        poly_mesh,
+        detail_mesh,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testgen::plane;
+
+    #[test]
+    fn test_builder_validates() {
+        let config = NavMeshBuildConfig::builder()
+            .cell_size(0.25)
+            .agent_radius(0.4)
+            .verts_per_poly(4)
+            .build()
+            .unwrap();
+        assert_eq!(config.cell_size, 0.25);
+        assert_eq!(config.walkable_radius(), 2);
+        assert_eq!(config.verts_per_poly, 4);
+
+        let error = |builder: NavMeshBuildConfigBuilder| builder.build().unwrap_err();
+        assert_eq!(
+            error(NavMeshBuildConfig::builder().cell_size(0.0)),
+            BuildConfigError::NotPositive("cell_size")
+        );
+        assert_eq!(
+            error(NavMeshBuildConfig::builder().agent_max_climb(f32::NAN)),
+            BuildConfigError::Negative("agent_max_climb")
+        );
+        assert_eq!(
+            error(NavMeshBuildConfig::builder().agent_max_slope(95.0)),
+            BuildConfigError::SlopeOutOfRange(95.0)
+        );
+        assert_eq!(
+            error(NavMeshBuildConfig::builder().verts_per_poly(8)),
+            BuildConfigError::VertsPerPoly(8)
+        );
+    }
+
+    #[test]
+    fn test_build_flat_plane() {
+        let config = NavMeshBuildConfig::builder()
+            .min_region_area(8)
+            .merge_region_area(20)
+            .build()
+            .unwrap();
+        let baked = build_navmesh(&plane(10.0, 0.0), &config).unwrap();
+        let mesh = &baked.poly_mesh;
+        assert!(!mesh.polys.is_empty());
+        assert_eq!(baked.detail_mesh.meshes.len(), mesh.polys.len());
+        assert!(mesh.areas.iter().all(|&a| a == RC_WALKABLE_AREA));
+        assert!(mesh.flags.iter().all(|&f| f == POLY_FLAG_WALK));
+
+        // The agent radius keeps the polygons off the edges of the plane
+        let inset = config.walkable_radius() as f32;
+        for v in &mesh.verts {
+            assert!(v.x >= inset && v.z >= inset);
+        }
+    }
+
+    #[test]
+    fn test_mark_walkable_triangles() {
+        let mut mesh = plane(10.0, 30.0);
+        assert_eq!(
+            mark_walkable_triangles(45.0, &mesh),
+            vec![RC_WALKABLE_AREA; 2]
+        );
+        assert_eq!(mark_walkable_triangles(20.0, &mesh), vec![RC_NULL_AREA; 2]);
+
+        // Assigned areas survive on walkable triangles only
+        mesh.areas = vec![5, 7];
+        assert_eq!(mark_walkable_triangles(45.0, &mesh), vec![5, 7]);
+        assert_eq!(mark_walkable_triangles(20.0, &mesh), vec![RC_NULL_AREA; 2]);
+    }
+
+    #[test]
+    fn test_build_rejects_steep_and_empty_input() {
+        let config = NavMeshBuildConfig::default();
+        let steep = build_navmesh(&plane(10.0, 60.0), &config).unwrap();
+        assert!(steep.poly_mesh.polys.is_empty());
+
+        assert_eq!(
+            build_navmesh(&InputMesh::new(), &config).unwrap_err(),
+            NavMeshBuildError::EmptyMesh
+        );
+        let invalid = NavMeshBuildConfig {
+            cell_height: -1.0,
+            ..Default::default()
+        };
+        assert_eq!(
+            build_navmesh(&plane(10.0, 0.0), &invalid).unwrap_err(),
+            NavMeshBuildError::InvalidConfig(BuildConfigError::NotPositive("cell_height"))
+        );
+    }
+
+    #[test]
+    fn test_build_tiled_navmesh() {
+        let config = NavMeshBuildConfig::builder()
+            .min_region_area(8)
+            .merge_region_area(20)
+            .tile_size(16)
+            .build()
+            .unwrap();
+        // 40 cells across, so three tiles each way
+        let reports = std::sync::Mutex::new(Vec::new());
+        let mut ctx = BuildContext::new();
+        let nav_mesh = build_tiled_navmesh_with_progress(
+            &plane(12.0, 0.0),
+            &config,
+            &[],
+            &[],
+            &mut ctx,
+            |p| reports.lock().unwrap().push(p),
+        )
+        .unwrap();
+        assert_eq!(nav_mesh.tiles().count(), 9);
+        assert!(BuildStage::ALL
+            .iter()
+            .all(|&stage| ctx.peak_memory(stage) > 0));
+        let mut reports = reports.into_inner().unwrap();
+        reports.sort_by_key(|p| p.tiles_done);
+        assert_eq!(reports.len(), 9);
+        assert!(reports
+            .iter()
+            .enumerate()
+            .all(|(i, p)| p.tiles_done == i + 1 && p.tile_count == 9));
+        let centre = nav_mesh.tile_at(1, 1, 0).unwrap();
+        assert!((centre.data.header.bmin.x - 4.8).abs() < 1e-4);
+
+        // Every tile is linked to the tiles next to it, and only those
+        for tile in nav_mesh.tiles() {
+            let (x, y) = (tile.data.header.x, tile.data.header.y);
+            let mut linked: Vec<(i32, i32)> = (0..tile.data.polys.len())
+                .flat_map(|ip| tile.links_of(ip))
+                .filter(|link| link.side != 0xff)
+                .map(|link| {
+                    let (other, _) = nav_mesh.tile_and_poly_by_ref(link.poly_ref).unwrap();
+                    (other.data.header.x, other.data.header.y)
+                })
+                .collect();
+            linked.sort();
+            linked.dedup();
+            let mut expected: Vec<(i32, i32)> = [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)]
+                .into_iter()
+                .filter(|&(nx, ny)| (0..3).contains(&nx) && (0..3).contains(&ny))
+                .collect();
+            expected.sort();
+            assert_eq!(linked, expected, "tile ({}, {})", x, y);
+        }
+    }
+}
@@ -1,24 +1,98 @@
+use glam::Vec3;
+use memmap2::Mmap;
+use std::collections::HashMap;
+use std::fmt;
 use std::fs::File;
 use std::io::{self, BufRead, BufReader};
 use std::path::Path;
-
-#[derive(Debug, Clone)]
-pub struct Vec3 {
-    pub x: f32,
-    pub y: f32,
-    pub z: f32,
-}
+use std::sync::atomic::{AtomicBool, Ordering};
 
 #[derive(Debug)]
 pub struct ObjData {
     pub vertices: Vec<Vec3>,
     pub faces: Vec<Vec<usize>>,
+    // Vertex normals in file order (face references are 1-based)
+    pub normals: Vec<Vec3>,
+    // File named by the first `mtllib` statement, relative to the OBJ file
+    pub material_lib: Option<String>,
+    // Material active (`usemtl`) when each face was read, parallel to `faces`
+    pub face_materials: Vec<Option<String>>,
+    // Group (`g`) and object (`o`) active when each face was read, also
+    // parallel to `faces`
+    pub face_groups: Vec<Option<String>>,
+    pub face_objects: Vec<Option<String>>,
+}
+
+// A material from an MTL library; only the diffuse color is kept
+#[derive(Debug, Clone)]
+pub struct MtlMaterial {
+    pub name: String,
+    // Kd
+    pub diffuse: Vec3,
+}
+
+// One corner of a face: 1-based vertex index plus optional texcoord/normal indices
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FaceVertex {
+    pub vertex: usize,
+    pub texcoord: Option<usize>,
+    pub normal: Option<usize>,
+}
+
+// A single element parsed from an OBJ file, in file order
+#[derive(Debug, Clone)]
+pub enum ObjEvent {
+    Vertex(Vec3),
+    Normal(Vec3),
+    TexCoord(f32, f32),
+    Face(Vec<FaceVertex>),
+    // `mtllib` file name
+    MaterialLib(String),
+    // `usemtl` material name, applying to the faces that follow
+    UseMaterial(String),
+    // `g` group and `o` object names, applying to the faces that follow;
+    // empty when the statement has no name
+    Group(String),
+    Object(String),
 }
 
 #[derive(Debug)]
 pub enum ObjLoadError {
     IoError(io::Error),
-    ParseError(String),
+    ParseError(ObjParseError),
+    Cancelled,
+}
+
+// Where and why a line failed to parse
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjParseError {
+    // 1-based line number
+    pub line: usize,
+    // The offending line, without surrounding whitespace
+    pub text: String,
+    // The token that could not be parsed, or None if one was missing
+    pub token: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for ObjParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)?;
+        if let Some(token) = &self.token {
+            write!(f, " ('{}')", token)?;
+        }
+        write!(f, " in `{}`", self.text)
+    }
+}
+
+impl fmt::Display for ObjLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjLoadError::IoError(err) => write!(f, "{}", err),
+            ObjLoadError::ParseError(err) => write!(f, "{}", err),
+            ObjLoadError::Cancelled => write!(f, "cancelled"),
+        }
+    }
 }
 
 impl From<io::Error> for ObjLoadError {
@@ -27,62 +101,667 @@ impl From<io::Error> for ObjLoadError {
     }
 }
 
+// A parse failure within one line, before the line number is known
+struct TokenError {
+    message: String,
+    token: Option<String>,
+}
+
+impl TokenError {
+    fn new(message: impl Into<String>, token: Option<&str>) -> Self {
+        Self {
+            message: message.into(),
+            token: token.map(str::to_string),
+        }
+    }
+
+    fn at(self, line: usize, text: &str) -> ObjParseError {
+        ObjParseError {
+            line,
+            text: text.trim().to_string(),
+            token: self.token,
+            message: self.message,
+        }
+    }
+}
+
+// Accumulates parser events into an ObjData
+struct ObjDataBuilder {
+    data: ObjData,
+    current_material: Option<String>,
+    current_group: Option<String>,
+    current_object: Option<String>,
+}
+
+impl ObjDataBuilder {
+    fn new() -> Self {
+        Self {
+            data: ObjData {
+                // OBJ files are 1-indexed, so we'll push a dummy vertex at index 0
+                vertices: vec![Vec3::ZERO],
+                faces: Vec::new(),
+                normals: Vec::new(),
+                material_lib: None,
+                face_materials: Vec::new(),
+                face_groups: Vec::new(),
+                face_objects: Vec::new(),
+            },
+            current_material: None,
+            current_group: None,
+            current_object: None,
+        }
+    }
+
+    fn with_capacity(capacity: ObjCapacity) -> Self {
+        let mut builder = Self::new();
+        let data = &mut builder.data;
+        data.vertices.reserve(capacity.vertices);
+        data.normals.reserve(capacity.normals);
+        data.faces.reserve(capacity.faces);
+        data.face_materials.reserve(capacity.faces);
+        data.face_groups.reserve(capacity.faces);
+        data.face_objects.reserve(capacity.faces);
+        builder
+    }
+
+    fn push(&mut self, event: ObjEvent) {
+        let data = &mut self.data;
+        match event {
+            ObjEvent::Vertex(v) => data.vertices.push(v),
+            ObjEvent::Normal(n) => data.normals.push(n),
+            ObjEvent::Face(face) => {
+                data.faces.push(face.iter().map(|fv| fv.vertex).collect());
+                data.face_materials.push(self.current_material.clone());
+                data.face_groups.push(self.current_group.clone());
+                data.face_objects.push(self.current_object.clone());
+            }
+            ObjEvent::MaterialLib(lib) => {
+                data.material_lib.get_or_insert(lib);
+            }
+            ObjEvent::UseMaterial(name) => self.current_material = Some(name),
+            ObjEvent::Group(name) => self.current_group = (!name.is_empty()).then_some(name),
+            ObjEvent::Object(name) => self.current_object = (!name.is_empty()).then_some(name),
+            ObjEvent::TexCoord(..) => {}
+        }
+    }
+}
+
 pub fn load_obj<P: AsRef<Path>>(path: P) -> Result<ObjData, ObjLoadError> {
+    load_obj_with_progress(path, |_| {})
+}
+
+// Load an OBJ file, reporting the fraction of bytes consumed (0.0..=1.0)
+pub fn load_obj_with_progress<P, F>(path: P, progress: F) -> Result<ObjData, ObjLoadError>
+where
+    P: AsRef<Path>,
+    F: Fn(f32) + Send,
+{
+    load_obj_cancellable(path, progress, &AtomicBool::new(false))
+}
+
+// Same as load_obj_with_progress, but stops with ObjLoadError::Cancelled
+// as soon as `cancel` is set
+pub fn load_obj_cancellable<P, F>(
+    path: P,
+    progress: F,
+    cancel: &AtomicBool,
+) -> Result<ObjData, ObjLoadError>
+where
+    P: AsRef<Path>,
+    F: Fn(f32) + Send,
+{
+    let mut builder = ObjDataBuilder::new();
+    stream_obj_cancellable(path, progress, cancel, |event| builder.push(event))?;
+    Ok(builder.data)
+}
+
+// Load an OBJ file, skipping lines that fail to parse instead of stopping.
+// The skipped lines are returned as warnings; only I/O errors are fatal.
+pub fn load_obj_lenient<P: AsRef<Path>>(
+    path: P,
+) -> Result<(ObjData, Vec<ObjParseError>), ObjLoadError> {
+    let mut builder = ObjDataBuilder::new();
+    let warnings = stream_obj_lenient(
+        path,
+        |_| {},
+        &AtomicBool::new(false),
+        |event| builder.push(event),
+    )?;
+    Ok((builder.data, warnings))
+}
+
+// Parse an OBJ file line by line, handing each element to `callback` as soon
+// as it is read instead of collecting the whole model in memory
+pub fn stream_obj<P, F>(path: P, callback: F) -> Result<(), ObjLoadError>
+where
+    P: AsRef<Path>,
+    F: FnMut(ObjEvent),
+{
+    stream_obj_cancellable(path, |_| {}, &AtomicBool::new(false), callback)
+}
+
+// Streaming parser with the progress reporting and cancellation of load_obj_cancellable
+pub fn stream_obj_cancellable<P, G, F>(
+    path: P,
+    progress: G,
+    cancel: &AtomicBool,
+    callback: F,
+) -> Result<(), ObjLoadError>
+where
+    P: AsRef<Path>,
+    G: Fn(f32),
+    F: FnMut(ObjEvent),
+{
+    stream_obj_impl(path, progress, cancel, callback, None)
+}
+
+// Like stream_obj_cancellable, but lines that fail to parse are skipped
+// and returned instead of ending the stream
+pub fn stream_obj_lenient<P, G, F>(
+    path: P,
+    progress: G,
+    cancel: &AtomicBool,
+    callback: F,
+) -> Result<Vec<ObjParseError>, ObjLoadError>
+where
+    P: AsRef<Path>,
+    G: Fn(f32),
+    F: FnMut(ObjEvent),
+{
+    let mut warnings = Vec::new();
+    stream_obj_impl(path, progress, cancel, callback, Some(&mut warnings))?;
+    Ok(warnings)
+}
+
+// Shared parser loop; parse errors go to `warnings` when given, otherwise
+// they are returned
+fn stream_obj_impl<P, G, F>(
+    path: P,
+    progress: G,
+    cancel: &AtomicBool,
+    mut callback: F,
+    mut warnings: Option<&mut Vec<ObjParseError>>,
+) -> Result<(), ObjLoadError>
+where
+    P: AsRef<Path>,
+    G: Fn(f32),
+    F: FnMut(ObjEvent),
+{
     let file = File::open(path)?;
-    let reader = BufReader::new(file);
+    let total_bytes = file.metadata()?.len().max(1);
+    let mut reader = BufReader::new(file);
+
+    // Reuse one line buffer instead of allocating a String per line
+    let mut line = String::new();
+    let mut bytes_read: u64 = 0;
+    let mut line_no = 0;
+    let mut last_reported = 0.0;
+    let mut numbering = VertexNumbering::default();
+    progress(0.0);
+
+    loop {
+        line.clear();
+        let n = reader.read_line(&mut line)?;
+        if n == 0 {
+            break;
+        }
+        bytes_read += n as u64;
+        line_no += 1;
+
+        // Report roughly every percent to avoid flooding the callback
+        let fraction = (bytes_read as f64 / total_bytes as f64) as f32;
+        if fraction - last_reported >= 0.01 {
+            if cancel.load(Ordering::Relaxed) {
+                return Err(ObjLoadError::Cancelled);
+            }
+            progress(fraction);
+            last_reported = fraction;
+        }
+
+        handle_line(
+            &line,
+            line_no,
+            &mut numbering,
+            &mut callback,
+            warnings.as_deref_mut(),
+        )?;
+    }
 
-    let mut vertices = Vec::new();
-    let mut faces = Vec::new();
+    progress(1.0);
 
-    // OBJ files are 1-indexed, so we'll push a dummy vertex at index 0
-    vertices.push(Vec3 {
-        x: 0.0,
-        y: 0.0,
-        z: 0.0,
+    Ok(())
+}
+
+// Vertices read so far, and which of them failed to parse, so a skipped
+// vertex line doesn't renumber the vertices after it
+#[derive(Default)]
+struct VertexNumbering {
+    count: usize,
+    // 1-based, in increasing order
+    invalid: Vec<usize>,
+}
+
+// Parse one line and pass its event on; a parse error goes to `warnings`
+// when given, otherwise it is returned. When skipping, a vertex that fails
+// to parse is passed on as a NaN placeholder to keep later vertices at
+// their numbers, and faces using it are skipped too.
+fn handle_line<F: FnMut(ObjEvent)>(
+    line: &str,
+    line_no: usize,
+    numbering: &mut VertexNumbering,
+    callback: &mut F,
+    warnings: Option<&mut Vec<ObjParseError>>,
+) -> Result<(), ObjLoadError> {
+    let result = parse_line(line).and_then(|event| {
+        match &event {
+            Some(ObjEvent::Vertex(_)) => numbering.count += 1,
+            Some(ObjEvent::Face(face)) => {
+                let invalid = face
+                    .iter()
+                    .find(|fv| numbering.invalid.binary_search(&fv.vertex).is_ok());
+                if let Some(fv) = invalid {
+                    let token = fv.vertex.to_string();
+                    return Err(TokenError::new(
+                        "Face uses a vertex that failed to parse",
+                        Some(&token),
+                    ));
+                }
+            }
+            _ => {}
+        }
+        Ok(event)
     });
+    match result {
+        Ok(Some(event)) => callback(event),
+        Ok(None) => {}
+        Err(err) => {
+            let err = err.at(line_no, line);
+            let Some(warnings) = warnings else {
+                return Err(ObjLoadError::ParseError(err));
+            };
+            if line.split_whitespace().next() == Some("v") {
+                numbering.count += 1;
+                numbering.invalid.push(numbering.count);
+                callback(ObjEvent::Vertex(Vec3::NAN));
+            }
+            warnings.push(err);
+        }
+    }
+    Ok(())
+}
+
+// Bytes parsed between progress reports and cancellation checks
+const MAPPED_CHUNK_SIZE: usize = 4 << 20;
+
+// Element counts from a quick pass over the raw bytes, so vectors can be
+// sized before parsing instead of growing as they fill
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ObjCapacity {
+    pub vertices: usize,
+    pub normals: usize,
+    pub faces: usize,
+    // Triangles after fan triangulation of every face
+    pub triangles: usize,
+}
+
+// Count the `v`, `vn` and `f` statements without parsing any numbers
+pub fn prescan_obj(bytes: &[u8]) -> ObjCapacity {
+    let mut capacity = ObjCapacity::default();
+    for line in bytes.split(|&b| b == b'\n') {
+        let line = line.trim_ascii_start();
+        let mut words = line
+            .split(u8::is_ascii_whitespace)
+            .filter(|w| !w.is_empty());
+        match words.next() {
+            Some(b"v") => capacity.vertices += 1,
+            Some(b"vn") => capacity.normals += 1,
+            Some(b"f") => {
+                capacity.faces += 1;
+                capacity.triangles += words.count().saturating_sub(2);
+            }
+            _ => {}
+        }
+    }
+    capacity
+}
+
+// Memory-map an OBJ file for load_obj_mapped and friends
+pub fn map_obj_file<P: AsRef<Path>>(path: P) -> io::Result<Mmap> {
+    let file = File::open(path)?;
+    // The file must not be truncated while mapped; like every other loader
+    // here we assume nothing else writes to it during the load
+    unsafe { Mmap::map(&file) }
+}
+
+// Load an OBJ file through a memory map, in large chunks, with every vector
+// sized by prescan_obj up front. Meant for level geometry of millions of
+// triangles, where load_obj spends much of its time reallocating.
+pub fn load_obj_mapped<P, F>(path: P, progress: F) -> Result<ObjData, ObjLoadError>
+where
+    P: AsRef<Path>,
+    F: Fn(f32),
+{
+    let map = map_obj_file(path)?;
+    let mut builder = ObjDataBuilder::with_capacity(prescan_obj(&map));
+    stream_obj_bytes_impl(
+        &map,
+        MAPPED_CHUNK_SIZE,
+        progress,
+        &AtomicBool::new(false),
+        |event| builder.push(event),
+        None,
+    )?;
+    Ok(builder.data)
+}
+
+// Like stream_obj_lenient, over bytes already in memory, typically from
+// map_obj_file
+pub fn stream_obj_bytes_lenient<G, F>(
+    bytes: &[u8],
+    progress: G,
+    cancel: &AtomicBool,
+    callback: F,
+) -> Result<Vec<ObjParseError>, ObjLoadError>
+where
+    G: Fn(f32),
+    F: FnMut(ObjEvent),
+{
+    let mut warnings = Vec::new();
+    stream_obj_bytes_impl(
+        bytes,
+        MAPPED_CHUNK_SIZE,
+        progress,
+        cancel,
+        callback,
+        Some(&mut warnings),
+    )?;
+    Ok(warnings)
+}
+
+// Parse `bytes` in chunks of about `chunk_size`, each extended to the end
+// of its last line
+fn stream_obj_bytes_impl<G, F>(
+    bytes: &[u8],
+    chunk_size: usize,
+    progress: G,
+    cancel: &AtomicBool,
+    mut callback: F,
+    mut warnings: Option<&mut Vec<ObjParseError>>,
+) -> Result<(), ObjLoadError>
+where
+    G: Fn(f32),
+    F: FnMut(ObjEvent),
+{
+    let total_bytes = bytes.len().max(1);
+    let mut offset = 0;
+    let mut line_no = 0;
+    let mut numbering = VertexNumbering::default();
+    progress(0.0);
+
+    while offset < bytes.len() {
+        if cancel.load(Ordering::Relaxed) {
+            return Err(ObjLoadError::Cancelled);
+        }
+        let mut end = (offset + chunk_size.max(1)).min(bytes.len());
+        end = bytes[end..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(bytes.len(), |i| end + i + 1);
+        let chunk = std::str::from_utf8(&bytes[offset..end])
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        for line in chunk.lines() {
+            line_no += 1;
+            handle_line(
+                line,
+                line_no,
+                &mut numbering,
+                &mut callback,
+                warnings.as_deref_mut(),
+            )?;
+        }
+
+        offset = end;
+        progress((offset as f64 / total_bytes as f64) as f32);
+    }
 
-    for line in reader.lines() {
+    progress(1.0);
+
+    Ok(())
+}
+
+// Parse one line into an event; lines we don't use yield None
+fn parse_line(line: &str) -> Result<Option<ObjEvent>, TokenError> {
+    let mut tokens = line.split_whitespace();
+
+    let event = match tokens.next() {
+        Some("v") => {
+            // Parse vertex
+            ObjEvent::Vertex(parse_vec3(&mut tokens, "vertex")?)
+        }
+        Some("vn") => ObjEvent::Normal(parse_vec3(&mut tokens, "normal")?),
+        Some("vt") => {
+            let token = tokens.next();
+            let u = token
+                .and_then(|s| s.parse().ok())
+                .ok_or_else(|| TokenError::new("Invalid texture u coordinate", token))?;
+            // v is optional in the OBJ spec
+            let v = tokens.next().and_then(|s| s.parse().ok()).unwrap_or(0.0);
+            ObjEvent::TexCoord(u, v)
+        }
+        Some("f") => {
+            // Parse face: collect vertex/texture/normal indices
+            let face: Result<Vec<FaceVertex>, _> = tokens.map(parse_face_vertex).collect();
+            ObjEvent::Face(face?)
+        }
+        Some("mtllib") => ObjEvent::MaterialLib(rest_of_line(tokens, "mtllib")?),
+        Some("usemtl") => ObjEvent::UseMaterial(rest_of_line(tokens, "usemtl")?),
+        // A bare `g` or `o` goes back to no name
+        Some("g") => ObjEvent::Group(tokens.collect::<Vec<_>>().join(" ")),
+        Some("o") => ObjEvent::Object(tokens.collect::<Vec<_>>().join(" ")),
+        // Ignore other lines
+        _ => return Ok(None),
+    };
+
+    Ok(Some(event))
+}
+
+// Parse an MTL library into materials keyed by name. Only `newmtl` and
+// `Kd` are read; other statements are ignored.
+pub fn load_mtl<P: AsRef<Path>>(path: P) -> Result<HashMap<String, MtlMaterial>, ObjLoadError> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut materials = HashMap::new();
+    let mut current: Option<MtlMaterial> = None;
+
+    for (line_no, line) in reader.lines().enumerate() {
         let line = line?;
+        let at_line = |err: TokenError| ObjLoadError::ParseError(err.at(line_no + 1, &line));
         let mut tokens = line.split_whitespace();
-
         match tokens.next() {
-            Some("v") => {
-                // Parse vertex
-                let x = tokens.next().and_then(|s| s.parse().ok()).ok_or_else(|| {
-                    ObjLoadError::ParseError("Invalid vertex x coordinate".to_string())
-                })?;
-                let y = tokens.next().and_then(|s| s.parse().ok()).ok_or_else(|| {
-                    ObjLoadError::ParseError("Invalid vertex y coordinate".to_string())
-                })?;
-                let z = tokens.next().and_then(|s| s.parse().ok()).ok_or_else(|| {
-                    ObjLoadError::ParseError("Invalid vertex z coordinate".to_string())
-                })?;
-
-                vertices.push(Vec3 { x, y, z });
+            Some("newmtl") => {
+                if let Some(material) = current.take() {
+                    materials.insert(material.name.clone(), material);
+                }
+                current = Some(MtlMaterial {
+                    name: rest_of_line(tokens, "newmtl").map_err(at_line)?,
+                    // MTL default diffuse color
+                    diffuse: Vec3::splat(0.8),
+                });
             }
-            Some("f") => {
-                // Parse face: collect vertex indices
-                let indices: Result<Vec<usize>, _> = tokens
-                    .map(|token| {
-                        // Handle vertex/texture/normal format by taking first number
-                        token
-                            .split('/')
-                            .next()
-                            .and_then(|idx| idx.parse().ok())
-                            .ok_or_else(|| {
-                                ObjLoadError::ParseError(format!("Invalid face index: {}", token))
-                            })
-                    })
-                    .collect();
-
-                faces.push(indices?);
+            Some("Kd") => {
+                let material = current
+                    .as_mut()
+                    .ok_or_else(|| at_line(TokenError::new("Kd before newmtl", None)))?;
+                material.diffuse = parse_vec3(&mut tokens, "diffuse").map_err(at_line)?;
             }
-            // Ignore other lines
             _ => continue,
         }
     }
 
-    Ok(ObjData { vertices, faces })
+    if let Some(material) = current {
+        materials.insert(material.name.clone(), material);
+    }
+
+    Ok(materials)
+}
+
+// Triangles whose doubled area is below this fraction of their longest
+// edge squared are treated as collinear. Relative to the triangle's own
+// size, so the same slivers go at any mesh scale.
+pub const DEGENERATE_EPSILON: f32 = 1e-6;
+
+pub fn is_degenerate_triangle(a: &Vec3, b: &Vec3, c: &Vec3) -> bool {
+    let longest = (*b - *a)
+        .length_squared()
+        .max((*c - *b).length_squared())
+        .max((*a - *c).length_squared());
+    (*b - *a).cross(*c - *a).length() <= DEGENERATE_EPSILON * longest
+}
+
+fn parse_vec3<'a>(
+    tokens: &mut impl Iterator<Item = &'a str>,
+    what: &str,
+) -> Result<Vec3, TokenError> {
+    let mut next = |axis: &str| {
+        let token = tokens.next();
+        token
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| TokenError::new(format!("Invalid {} {} coordinate", what, axis), token))
+    };
+    Ok(Vec3::new(next("x")?, next("y")?, next("z")?))
+}
+
+// Names may contain spaces, so keep everything after the keyword
+fn rest_of_line<'a>(
+    tokens: impl Iterator<Item = &'a str>,
+    what: &str,
+) -> Result<String, TokenError> {
+    let rest = tokens.collect::<Vec<_>>().join(" ");
+    if rest.is_empty() {
+        return Err(TokenError::new(format!("Missing {} name", what), None));
+    }
+    Ok(rest)
+}
+
+// Parse a `v`, `v/vt`, `v//vn` or `v/vt/vn` face token
+fn parse_face_vertex(token: &str) -> Result<FaceVertex, TokenError> {
+    let invalid = || TokenError::new("Invalid face index", Some(token));
+    let mut parts = token.split('/');
+
+    let vertex = parts
+        .next()
+        .and_then(|idx| idx.parse().ok())
+        .ok_or_else(invalid)?;
+    let optional = |part: Option<&str>| match part {
+        None | Some("") => Ok(None),
+        Some(idx) => idx.parse().map(Some).map_err(|_| invalid()),
+    };
+    let texcoord = optional(parts.next())?;
+    let normal = optional(parts.next())?;
+
+    Ok(FaceVertex {
+        vertex,
+        texcoord,
+        normal,
+    })
+}
+
+// Utility functions for working with the loaded data
+impl ObjData {
+    // Get total number of vertices (excluding dummy vertex)
+    pub fn vertex_count(&self) -> usize {
+        self.vertices.len() - 1 // Subtract dummy vertex
+    }
+
+    // Get total number of faces
+    pub fn face_count(&self) -> usize {
+        self.faces.len()
+    }
+
+    // Convert all faces to triangles (using simple fan triangulation),
+    // dropping degenerate triangles
+    pub fn triangulate(&self) -> Vec<[usize; 3]> {
+        self.triangulate_with_faces()
+            .into_iter()
+            .map(|(_, tri)| tri)
+            .collect()
+    }
+
+    // Like triangulate, but pairs each triangle with the index of its source face
+    pub fn triangulate_with_faces(&self) -> Vec<(usize, [usize; 3])> {
+        let mut triangles = Vec::new();
+
+        for (face_index, face) in self.faces.iter().enumerate() {
+            if face.len() >= 3 {
+                // Triangulate as a fan from the first vertex
+                for i in 1..(face.len() - 1) {
+                    let tri = [face[0], face[i], face[i + 1]];
+                    if !self.is_degenerate(tri) {
+                        triangles.push((face_index, tri));
+                    }
+                }
+            }
+        }
+
+        triangles
+    }
+
+    // The fan triangles that triangulate drops, paired with their source face
+    pub fn degenerate_triangles(&self) -> Vec<(usize, [usize; 3])> {
+        let mut degenerate = Vec::new();
+        for (face_index, face) in self.faces.iter().enumerate() {
+            for i in 1..face.len().saturating_sub(1) {
+                let tri = [face[0], face[i], face[i + 1]];
+                if self.is_degenerate(tri) {
+                    degenerate.push((face_index, tri));
+                }
+            }
+        }
+        degenerate
+    }
+
+    // Material assigned to a face, if any
+    pub fn face_material(&self, face: usize) -> Option<&str> {
+        self.face_materials.get(face)?.as_deref()
+    }
+
+    pub fn face_group(&self, face: usize) -> Option<&str> {
+        self.face_groups.get(face)?.as_deref()
+    }
+
+    pub fn face_object(&self, face: usize) -> Option<&str> {
+        self.face_objects.get(face)?.as_deref()
+    }
+
+    // A triangle is degenerate if it repeats an index or its corners are
+    // (nearly) collinear. Out-of-range indices are left for the caller to report.
+    fn is_degenerate(&self, [a, b, c]: [usize; 3]) -> bool {
+        if a == b || b == c || a == c {
+            return true;
+        }
+        match (
+            self.vertices.get(a),
+            self.vertices.get(b),
+            self.vertices.get(c),
+        ) {
+            (Some(va), Some(vb), Some(vc)) => is_degenerate_triangle(va, vb, vc),
+            _ => false,
+        }
+    }
+
+    // Get bounds of the model
+    pub fn get_bounds(&self) -> (Vec3, Vec3) {
+        let mut min = Vec3::INFINITY;
+        let mut max = Vec3::NEG_INFINITY;
+
+        // Skip dummy vertex at index 0
+        for vertex in self.vertices.iter().skip(1) {
+            min = min.min(*vertex);
+            max = max.max(*vertex);
+        }
+
+        (min, max)
+    }
 }
 
 // Example usage and testing
@@ -118,66 +797,268 @@ f 1 5 6";
         let first_vertex = &obj_data.vertices[1]; // Index 1 due to dummy vertex
         assert!((first_vertex.x - -21.847065).abs() < 1e-6);
         assert!((first_vertex.y - -2.492895).abs() < 1e-6);
-        assert!((first_vertex.z - 19.569759).abs() < 1e-6);
+        assert!((first_vertex.z - 19.569_76).abs() < 1e-6);
 
         // Check faces
         assert_eq!(obj_data.faces[0], vec![1, 2, 3, 4, 5]);
         assert_eq!(obj_data.faces[1], vec![1, 5, 6]);
+
+        // Bounds come back as glam vectors and exclude the dummy vertex
+        let (min, max): (glam::Vec3, glam::Vec3) = obj_data.get_bounds();
+        assert_eq!(min, Vec3::new(-21.847065, -2.492895, 18.838863));
+        assert_eq!(max, Vec3::new(-15.585992, -0.895197, 21.71773));
     }
-}
 
-// Utility functions for working with the loaded data
-impl ObjData {
-    // Get total number of vertices (excluding dummy vertex)
-    pub fn vertex_count(&self) -> usize {
-        self.vertices.len() - 1 // Subtract dummy vertex
+    #[test]
+    fn test_triangulate_skips_degenerate() {
+        // The quad repeats vertex 2 and the triangle is collinear
+        let obj_content = "\
+v 0.0 0.0 0.0
+v 1.0 0.0 0.0
+v 1.0 0.0 1.0
+v 2.0 0.0 0.0
+f 1 2 2 3
+f 1 2 4";
+
+        let temp_file = NamedTempFile::new().unwrap();
+        write(temp_file.path(), obj_content).unwrap();
+
+        let obj_data = load_obj(temp_file.path()).unwrap();
+        let triangles = obj_data.triangulate();
+
+        assert_eq!(triangles, vec![[1, 2, 3]]);
+        for tri in &triangles {
+            assert!(tri[0] != tri[1] && tri[1] != tri[2] && tri[0] != tri[2]);
+        }
     }
 
-    // Get total number of faces
-    pub fn face_count(&self) -> usize {
-        self.faces.len()
+    #[test]
+    fn test_degenerate_check_is_scale_independent() {
+        // A thin but valid triangle, and a sliver 1000 times thinner
+        let thin = [Vec3::ZERO, Vec3::X, Vec3::new(0.5, 0.0, 1e-3)];
+        let sliver = [Vec3::ZERO, Vec3::X, Vec3::new(0.5, 0.0, 1e-7)];
+        for scale in [1e-3, 1.0, 1e3] {
+            let [a, b, c] = thin.map(|v| v * scale);
+            assert!(!is_degenerate_triangle(&a, &b, &c));
+            let [a, b, c] = sliver.map(|v| v * scale);
+            assert!(is_degenerate_triangle(&a, &b, &c));
+        }
     }
 
-    // Convert all faces to triangles (using simple fan triangulation)
-    pub fn triangulate(&self) -> Vec<[usize; 3]> {
-        let mut triangles = Vec::new();
+    #[test]
+    fn test_stream_obj() {
+        let obj_content = "\
+v 0.0 0.0 0.0
+v 1.0 0.0 0.0
+vn 0.0 1.0 0.0
+v 0.0 0.0 1.0
+f 1//1 2//1 3//1";
 
-        for face in &self.faces {
-            if face.len() >= 3 {
-                // Triangulate as a fan from the first vertex
-                for i in 1..(face.len() - 1) {
-                    triangles.push([face[0], face[i], face[i + 1]]);
-                }
+        let temp_file = NamedTempFile::new().unwrap();
+        write(temp_file.path(), obj_content).unwrap();
+
+        let mut events = Vec::new();
+        stream_obj(temp_file.path(), |event| events.push(event)).unwrap();
+
+        assert_eq!(events.len(), 5);
+        assert!(matches!(events[2], ObjEvent::Normal(Vec3 { y, .. }) if y == 1.0));
+        match &events[4] {
+            ObjEvent::Face(face) => {
+                assert_eq!(face.len(), 3);
+                assert_eq!(
+                    face[1],
+                    FaceVertex {
+                        vertex: 2,
+                        texcoord: None,
+                        normal: Some(1),
+                    }
+                );
             }
+            other => panic!("expected face, got {:?}", other),
         }
+    }
 
-        triangles
+    #[test]
+    fn test_load_obj_with_progress() {
+        let mut obj_content = String::new();
+        for i in 0..500 {
+            obj_content.push_str(&format!("v {} 0.0 {}\n", i, i));
+        }
+        obj_content.push_str("f 1 2 3\n");
+
+        let temp_file = NamedTempFile::new().unwrap();
+        write(temp_file.path(), obj_content).unwrap();
+
+        let reported = std::sync::Mutex::new(Vec::new());
+        let obj_data =
+            load_obj_with_progress(temp_file.path(), |p| reported.lock().unwrap().push(p)).unwrap();
+        assert_eq!(obj_data.vertex_count(), 500);
+
+        // Progress is monotonic and finishes at exactly 1.0
+        let reported = reported.into_inner().unwrap();
+        assert!(reported.len() > 2);
+        assert!(reported.windows(2).all(|w| w[0] <= w[1]));
+        assert_eq!(*reported.last().unwrap(), 1.0);
     }
 
-    // Get bounds of the model
-    pub fn get_bounds(&self) -> (Vec3, Vec3) {
-        let mut min = Vec3 {
-            x: f32::INFINITY,
-            y: f32::INFINITY,
-            z: f32::INFINITY,
-        };
-        let mut max = Vec3 {
-            x: f32::NEG_INFINITY,
-            y: f32::NEG_INFINITY,
-            z: f32::NEG_INFINITY,
+    #[test]
+    fn test_materials() {
+        let obj_content = "\
+mtllib scene materials.mtl
+o level
+v 0.0 0.0 0.0
+v 1.0 0.0 0.0
+v 0.0 0.0 1.0
+f 1 2 3
+g river bank
+usemtl grass
+f 1 3 2
+g
+usemtl water
+f 3 2 1";
+
+        let temp_file = NamedTempFile::new().unwrap();
+        write(temp_file.path(), obj_content).unwrap();
+
+        let obj_data = load_obj(temp_file.path()).unwrap();
+        assert_eq!(
+            obj_data.material_lib.as_deref(),
+            Some("scene materials.mtl")
+        );
+        assert_eq!(obj_data.face_materials.len(), obj_data.faces.len());
+        assert_eq!(obj_data.face_material(0), None);
+        assert_eq!(obj_data.face_material(1), Some("grass"));
+        assert_eq!(obj_data.face_material(2), Some("water"));
+        assert_eq!(obj_data.face_group(0), None);
+        assert_eq!(obj_data.face_group(1), Some("river bank"));
+        assert_eq!(obj_data.face_group(2), None);
+        assert_eq!(obj_data.face_object(2), Some("level"));
+
+        let mtl_content = "\
+# two materials
+newmtl grass
+Ka 0.1 0.1 0.1
+Kd 0.2 0.8 0.1
+newmtl water
+Kd 0.0 0.3 0.9";
+        let mtl_file = NamedTempFile::new().unwrap();
+        write(mtl_file.path(), mtl_content).unwrap();
+
+        let materials = load_mtl(mtl_file.path()).unwrap();
+        assert_eq!(materials.len(), 2);
+        assert_eq!(materials["grass"].diffuse.y, 0.8);
+        assert_eq!(materials["water"].diffuse.z, 0.9);
+    }
+
+    #[test]
+    fn test_parse_error_line_number() {
+        let obj_content = "\
+v 0.0 0.0 0.0
+v 1.0 0.0 0.0
+v 0.0 abc 1.0
+v 0.0 0.0 1.0
+f 1 2 3";
+
+        let temp_file = NamedTempFile::new().unwrap();
+        write(temp_file.path(), obj_content).unwrap();
+
+        let Err(ObjLoadError::ParseError(err)) = load_obj(temp_file.path()) else {
+            panic!("expected a parse error");
         };
+        assert_eq!(err.line, 3);
+        assert_eq!(err.text, "v 0.0 abc 1.0");
+        assert_eq!(err.token.as_deref(), Some("abc"));
+        assert_eq!(
+            err.to_string(),
+            "line 3: Invalid vertex y coordinate ('abc') in `v 0.0 abc 1.0`"
+        );
 
-        // Skip dummy vertex at index 0
-        for vertex in self.vertices.iter().skip(1) {
-            min.x = min.x.min(vertex.x);
-            min.y = min.y.min(vertex.y);
-            min.z = min.z.min(vertex.z);
+        // The lenient loader keeps the bad vertex's place and skips the
+        // face using it, rather than joining the wrong vertices
+        let (obj_data, warnings) = load_obj_lenient(temp_file.path()).unwrap();
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(warnings[0], err);
+        assert_eq!(
+            (warnings[1].line, warnings[1].token.as_deref()),
+            (5, Some("3"))
+        );
+        assert_eq!(obj_data.vertex_count(), 4);
+        assert!(obj_data.vertices[3].is_nan());
+        assert_eq!(obj_data.vertices[4], Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(obj_data.face_count(), 0);
+    }
 
-            max.x = max.x.max(vertex.x);
-            max.y = max.y.max(vertex.y);
-            max.z = max.z.max(vertex.z);
+    #[test]
+    fn test_load_obj_cancelled() {
+        let mut obj_content = String::new();
+        for i in 0..500 {
+            obj_content.push_str(&format!("v {} 0.0 {}\n", i, i));
         }
 
-        (min, max)
+        let temp_file = NamedTempFile::new().unwrap();
+        write(temp_file.path(), obj_content).unwrap();
+
+        let cancel = AtomicBool::new(true);
+        let result = load_obj_cancellable(temp_file.path(), |_| {}, &cancel);
+        assert!(matches!(result, Err(ObjLoadError::Cancelled)));
+    }
+
+    #[test]
+    fn test_mapped_matches_buffered() {
+        let obj_content = "\
+o level
+v 0 0 0
+v 1 0 0
+v 1 0 1
+v 0 0 1
+vn 0 1 0
+usemtl stone
+f 1 2 3 4
+  f 1 3 2
+v 2 0 0
+f 2 5 3
+";
+        let temp_file = NamedTempFile::new().unwrap();
+        write(temp_file.path(), obj_content).unwrap();
+
+        let capacity = prescan_obj(obj_content.as_bytes());
+        assert_eq!(
+            capacity,
+            ObjCapacity {
+                vertices: 5,
+                normals: 1,
+                faces: 3,
+                triangles: 4,
+            }
+        );
+
+        let buffered = load_obj(temp_file.path()).unwrap();
+        let mapped = load_obj_mapped(temp_file.path(), |_| {}).unwrap();
+        assert_eq!(mapped.vertices, buffered.vertices);
+        assert_eq!(mapped.faces, buffered.faces);
+        assert_eq!(mapped.face_materials, buffered.face_materials);
+        assert_eq!(mapped.face_objects, buffered.face_objects);
+
+        // Chunks far smaller than a line still split at line ends, so line
+        // numbers in errors are right
+        let mut events = 0;
+        let bad = format!("{}v 1 x 0\n", obj_content);
+        let warnings = {
+            let mut warnings = Vec::new();
+            stream_obj_bytes_impl(
+                bad.as_bytes(),
+                3,
+                |_| {},
+                &AtomicBool::new(false),
+                |_| events += 1,
+                Some(&mut warnings),
+            )
+            .unwrap();
+            warnings
+        };
+        // The bad vertex still passes on a placeholder
+        assert_eq!(events, 12);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].line, 12);
     }
 }
@@ -0,0 +1,553 @@
+use std::f32::consts::PI;
+
+use glam::Vec3;
+
+use crate::nav_mesh::distance_pt_seg_sqr_2d;
+
+const MAX_PATTERN_DIVS: u8 = 32;
+const MAX_PATTERN_RINGS: u8 = 4;
+
+// Weights and sampling resolution of the velocity selection, like Detour's
+// dtObstacleAvoidanceParams
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ObstacleAvoidanceParams {
+    // How far samples are centred on the desired velocity, 0 to 1
+    pub vel_bias: f32,
+    // Penalties for straying from the desired and current velocity, for
+    // passing an obstacle on the wrong side and for time to impact
+    pub weight_des_vel: f32,
+    pub weight_cur_vel: f32,
+    pub weight_side: f32,
+    pub weight_toi: f32,
+    // Seconds ahead collisions are looked for
+    pub horiz_time: f32,
+    // Samples per side of the grid, for `sample_velocity_grid`
+    pub grid_size: u8,
+    // Samples per ring, rings and refinement passes, for
+    // `sample_velocity_adaptive`
+    pub adaptive_divs: u8,
+    pub adaptive_rings: u8,
+    pub adaptive_depth: u8,
+}
+
+impl Default for ObstacleAvoidanceParams {
+    fn default() -> Self {
+        AvoidanceQuality::default().params()
+    }
+}
+
+// Presets trading sample count for smoothness, those of the Recast demo
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AvoidanceQuality {
+    Low,
+    Medium,
+    #[default]
+    Good,
+    High,
+}
+
+impl AvoidanceQuality {
+    pub const ALL: [AvoidanceQuality; 4] = [
+        AvoidanceQuality::Low,
+        AvoidanceQuality::Medium,
+        AvoidanceQuality::Good,
+        AvoidanceQuality::High,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AvoidanceQuality::Low => "low",
+            AvoidanceQuality::Medium => "medium",
+            AvoidanceQuality::Good => "good",
+            AvoidanceQuality::High => "high",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|q| q.name() == name)
+    }
+
+    pub fn params(self) -> ObstacleAvoidanceParams {
+        let (divs, rings, depth) = match self {
+            AvoidanceQuality::Low => (5, 2, 1),
+            AvoidanceQuality::Medium => (5, 2, 2),
+            AvoidanceQuality::Good => (7, 2, 3),
+            AvoidanceQuality::High => (7, 3, 3),
+        };
+        ObstacleAvoidanceParams {
+            vel_bias: 0.4,
+            weight_des_vel: 2.0,
+            weight_cur_vel: 0.75,
+            weight_side: 0.75,
+            weight_toi: 2.5,
+            horiz_time: 2.5,
+            grid_size: 33,
+            adaptive_divs: divs,
+            adaptive_rings: rings,
+            adaptive_depth: depth,
+        }
+    }
+}
+
+// Another agent, moving with `vel` and wanting to move with `dvel`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ObstacleCircle {
+    pub pos: Vec3,
+    pub radius: f32,
+    pub vel: Vec3,
+    pub dvel: Vec3,
+    // Direction to the obstacle and the side to pass it on, set per query
+    dp: Vec3,
+    np: Vec3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ObstacleSegment {
+    pub p: Vec3,
+    pub q: Vec3,
+    // Whether the agent already touches it
+    touch: bool,
+}
+
+// One evaluated candidate velocity, for debug drawing
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AvoidanceSample {
+    pub vel: Vec3,
+    // Spacing of the samples around it
+    pub size: f32,
+    pub penalty: f32,
+    pub vel_penalty: f32,
+    pub cur_vel_penalty: f32,
+    pub side_penalty: f32,
+    pub toi_penalty: f32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AvoidanceDebugData {
+    pub samples: Vec<AvoidanceSample>,
+}
+
+impl AvoidanceDebugData {
+    // Scale every penalty kind to 0..1 over the samples, for colouring
+    pub fn normalize_samples(&mut self) {
+        fn normalize(samples: &mut [AvoidanceSample], get: fn(&mut AvoidanceSample) -> &mut f32) {
+            let (lo, hi) = samples
+                .iter_mut()
+                .map(|s| *get(s))
+                .fold((f32::MAX, f32::MIN), |(lo, hi), v| (lo.min(v), hi.max(v)));
+            let range = hi - lo;
+            let scale = if range > 0.001 { 1.0 / range } else { 1.0 };
+            for s in samples {
+                let v = get(s);
+                *v = ((*v - lo) * scale).clamp(0.0, 1.0);
+            }
+        }
+        normalize(&mut self.samples, |s| &mut s.penalty);
+        normalize(&mut self.samples, |s| &mut s.vel_penalty);
+        normalize(&mut self.samples, |s| &mut s.cur_vel_penalty);
+        normalize(&mut self.samples, |s| &mut s.side_penalty);
+        normalize(&mut self.samples, |s| &mut s.toi_penalty);
+    }
+}
+
+// Picks a velocity close to the desired one that keeps an agent clear of
+// nearby agents and walls, by scoring candidate velocities against them.
+// Like Detour's dtObstacleAvoidanceQuery.
+#[derive(Debug, Clone, Default)]
+pub struct ObstacleAvoidanceQuery {
+    circles: Vec<ObstacleCircle>,
+    segments: Vec<ObstacleSegment>,
+    params: ObstacleAvoidanceParams,
+    inv_horiz_time: f32,
+    inv_vmax: f32,
+}
+
+impl ObstacleAvoidanceQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn reset(&mut self) {
+        self.circles.clear();
+        self.segments.clear();
+    }
+
+    pub fn add_circle(&mut self, pos: Vec3, radius: f32, vel: Vec3, dvel: Vec3) {
+        self.circles.push(ObstacleCircle {
+            pos,
+            radius,
+            vel,
+            dvel,
+            dp: Vec3::ZERO,
+            np: Vec3::ZERO,
+        });
+    }
+
+    pub fn add_segment(&mut self, p: Vec3, q: Vec3) {
+        self.segments.push(ObstacleSegment { p, q, touch: false });
+    }
+
+    pub fn circles(&self) -> &[ObstacleCircle] {
+        &self.circles
+    }
+
+    pub fn segments(&self) -> &[ObstacleSegment] {
+        &self.segments
+    }
+
+    // Best velocity for an agent of `radius` at `pos` among a grid of
+    // candidates around the biased desired velocity, and the number of
+    // candidates scored
+    #[allow(clippy::too_many_arguments)]
+    pub fn sample_velocity_grid(
+        &mut self,
+        pos: Vec3,
+        radius: f32,
+        vmax: f32,
+        vel: Vec3,
+        dvel: Vec3,
+        params: &ObstacleAvoidanceParams,
+        mut debug: Option<&mut AvoidanceDebugData>,
+    ) -> (Vec3, usize) {
+        self.prepare(pos, dvel, params, vmax);
+        if let Some(debug) = debug.as_deref_mut() {
+            debug.samples.clear();
+        }
+
+        let centre = Vec3::new(dvel.x, 0.0, dvel.z) * params.vel_bias;
+        let grid = params.grid_size.max(2) as f32;
+        let cs = vmax * 2.0 * (1.0 - params.vel_bias) / (grid - 1.0);
+        let half = (grid - 1.0) * cs * 0.5;
+
+        let mut best = (Vec3::ZERO, f32::MAX);
+        let mut count = 0;
+        for z in 0..params.grid_size.max(2) {
+            for x in 0..params.grid_size.max(2) {
+                let vcand = centre + Vec3::new(x as f32 * cs - half, 0.0, z as f32 * cs - half);
+                if vcand.x * vcand.x + vcand.z * vcand.z > (vmax + cs / 2.0).powi(2) {
+                    continue;
+                }
+                let penalty =
+                    self.process_sample(vcand, cs, pos, radius, vel, dvel, best.1, &mut debug);
+                count += 1;
+                if penalty < best.1 {
+                    best = (vcand, penalty);
+                }
+            }
+        }
+        (best.0, count)
+    }
+
+    // Like `sample_velocity_grid`, but with rings of candidates around the
+    // desired direction, refined around the best one on each pass. Far
+    // fewer samples for a similar result.
+    #[allow(clippy::too_many_arguments)]
+    pub fn sample_velocity_adaptive(
+        &mut self,
+        pos: Vec3,
+        radius: f32,
+        vmax: f32,
+        vel: Vec3,
+        dvel: Vec3,
+        params: &ObstacleAvoidanceParams,
+        mut debug: Option<&mut AvoidanceDebugData>,
+    ) -> (Vec3, usize) {
+        self.prepare(pos, dvel, params, vmax);
+        if let Some(debug) = debug.as_deref_mut() {
+            debug.samples.clear();
+        }
+
+        let pattern = sample_pattern(dvel, params);
+        let mut cr = vmax * (1.0 - params.vel_bias);
+        let mut res = Vec3::new(dvel.x, 0.0, dvel.z) * params.vel_bias;
+        let mut count = 0;
+        for _ in 0..params.adaptive_depth {
+            let mut best = (Vec3::ZERO, f32::MAX);
+            for &(px, pz) in &pattern {
+                let vcand = res + Vec3::new(px * cr, 0.0, pz * cr);
+                if vcand.x * vcand.x + vcand.z * vcand.z > (vmax + 0.001).powi(2) {
+                    continue;
+                }
+                let penalty = self.process_sample(
+                    vcand,
+                    cr / 10.0,
+                    pos,
+                    radius,
+                    vel,
+                    dvel,
+                    best.1,
+                    &mut debug,
+                );
+                count += 1;
+                if penalty < best.1 {
+                    best = (vcand, penalty);
+                }
+            }
+            res = best.0;
+            cr *= 0.5;
+        }
+        (res, count)
+    }
+
+    fn prepare(&mut self, pos: Vec3, dvel: Vec3, params: &ObstacleAvoidanceParams, vmax: f32) {
+        self.params = *params;
+        self.inv_horiz_time = 1.0 / params.horiz_time;
+        self.inv_vmax = if vmax > 0.0 { 1.0 / vmax } else { f32::MAX };
+
+        for cir in &mut self.circles {
+            let d = cir.pos - pos;
+            cir.dp = Vec3::new(d.x, 0.0, d.z).normalize_or_zero();
+            // Pass on the side the obstacle is already moving away from
+            let dv = cir.dvel - dvel;
+            let a = tri_area_2d(Vec3::ZERO, cir.dp, dv);
+            cir.np = if a < 0.01 {
+                Vec3::new(-cir.dp.z, 0.0, cir.dp.x)
+            } else {
+                Vec3::new(cir.dp.z, 0.0, -cir.dp.x)
+            };
+        }
+        for seg in &mut self.segments {
+            const TOUCH_DIST: f32 = 0.01;
+            let (d, _) = distance_pt_seg_sqr_2d(pos, seg.p, seg.q);
+            seg.touch = d < TOUCH_DIST * TOUCH_DIST;
+        }
+    }
+
+    // Penalty of moving with `vcand`, giving up with `min_penalty` as soon
+    // as it can't beat it
+    #[allow(clippy::too_many_arguments)]
+    fn process_sample(
+        &self,
+        vcand: Vec3,
+        cs: f32,
+        pos: Vec3,
+        radius: f32,
+        vel: Vec3,
+        dvel: Vec3,
+        min_penalty: f32,
+        debug: &mut Option<&mut AvoidanceDebugData>,
+    ) -> f32 {
+        let p = &self.params;
+        let vel_penalty = p.weight_des_vel * dist_2d(vcand, dvel) * self.inv_vmax;
+        let cur_vel_penalty = p.weight_cur_vel * dist_2d(vcand, vel) * self.inv_vmax;
+
+        // Impact time below which the sample can't beat `min_penalty`
+        let min_pen = min_penalty - vel_penalty - cur_vel_penalty;
+        let t_thres = (p.weight_toi / min_pen - 0.1) * p.horiz_time;
+        if t_thres - p.horiz_time > -f32::EPSILON {
+            return min_penalty;
+        }
+
+        let mut tmin = p.horiz_time;
+        let mut side = 0.0;
+        for cir in &self.circles {
+            // Each agent takes half the responsibility for avoiding
+            let vab = vcand * 2.0 - vel - cir.vel;
+            side += (dot_2d(cir.dp, vab) * 0.5 + 0.5)
+                .min(dot_2d(cir.np, vab) * 2.0)
+                .clamp(0.0, 1.0);
+
+            let Some((mut htmin, htmax)) =
+                sweep_circle_circle(pos, radius, vab, cir.pos, cir.radius)
+            else {
+                continue;
+            };
+            // Already overlapping: avoid less, to let the agents separate
+            if htmin < 0.0 && htmax > 0.0 {
+                htmin = -htmin * 0.5;
+            }
+            if htmin >= 0.0 && htmin < tmin {
+                tmin = htmin;
+                if tmin < t_thres {
+                    return min_penalty;
+                }
+            }
+        }
+
+        for seg in &self.segments {
+            let htmin = if seg.touch {
+                // Moving away from a touching wall is fine
+                let dir = seg.q - seg.p;
+                let normal = Vec3::new(-dir.z, 0.0, dir.x);
+                if dot_2d(normal, vcand) < 0.0 {
+                    continue;
+                }
+                0.0
+            } else {
+                let Some(t) = intersect_ray_segment(pos, vcand, seg.p, seg.q) else {
+                    continue;
+                };
+                t
+            };
+            // Avoid walls less than agents
+            let htmin = htmin * 2.0;
+            if htmin < tmin {
+                tmin = htmin;
+                if tmin < t_thres {
+                    return min_penalty;
+                }
+            }
+        }
+
+        if !self.circles.is_empty() {
+            side /= self.circles.len() as f32;
+        }
+        let side_penalty = p.weight_side * side;
+        let toi_penalty = p.weight_toi * (1.0 / (0.1 + tmin * self.inv_horiz_time));
+        let penalty = vel_penalty + cur_vel_penalty + side_penalty + toi_penalty;
+
+        if let Some(debug) = debug {
+            debug.samples.push(AvoidanceSample {
+                vel: vcand,
+                size: cs,
+                penalty,
+                vel_penalty,
+                cur_vel_penalty,
+                side_penalty,
+                toi_penalty,
+            });
+        }
+        penalty
+    }
+}
+
+// Unit-radius candidate offsets: the centre, then rings shrinking inwards
+// with every other ring turned half a step, starting along `dvel`
+fn sample_pattern(dvel: Vec3, params: &ObstacleAvoidanceParams) -> Vec<(f32, f32)> {
+    let divs = params.adaptive_divs.clamp(1, MAX_PATTERN_DIVS) as usize;
+    let rings = params.adaptive_rings.clamp(1, MAX_PATTERN_RINGS) as usize;
+    let da = PI * 2.0 / divs as f32;
+    let base = if dvel.x == 0.0 && dvel.z == 0.0 {
+        0.0
+    } else {
+        dvel.z.atan2(dvel.x)
+    };
+
+    let mut pattern = vec![(0.0, 0.0)];
+    for j in 0..rings {
+        let r = (rings - j) as f32 / rings as f32;
+        let offset = if j % 2 == 1 { da * 0.5 } else { 0.0 };
+        pattern.extend((0..divs).map(|k| {
+            let (sin, cos) = (base + offset + k as f32 * da).sin_cos();
+            (cos * r, sin * r)
+        }));
+    }
+    pattern
+}
+
+// Times at which circle c0 moving with `v` starts and stops overlapping the
+// still circle c1, on the XZ plane
+fn sweep_circle_circle(c0: Vec3, r0: f32, v: Vec3, c1: Vec3, r1: f32) -> Option<(f32, f32)> {
+    const EPS: f32 = 0.0001;
+    let s = c1 - c0;
+    let r = r0 + r1;
+    let c = dot_2d(s, s) - r * r;
+    let a = dot_2d(v, v);
+    if a < EPS {
+        return None;
+    }
+    let b = dot_2d(v, s);
+    let d = b * b - a * c;
+    if d < 0.0 {
+        return None;
+    }
+    let rd = d.sqrt();
+    Some(((b - rd) / a, (b + rd) / a))
+}
+
+// Parameter along the ray `ap + t * u` where it crosses segment bp-bq
+fn intersect_ray_segment(ap: Vec3, u: Vec3, bp: Vec3, bq: Vec3) -> Option<f32> {
+    let v = bq - bp;
+    let w = ap - bp;
+    let d = perp_2d(u, v);
+    if d.abs() < 1e-6 {
+        return None;
+    }
+    let t = perp_2d(v, w) / d;
+    let s = perp_2d(u, w) / d;
+    ((0.0..=1.0).contains(&t) && (0.0..=1.0).contains(&s)).then_some(t)
+}
+
+fn tri_area_2d(a: Vec3, b: Vec3, c: Vec3) -> f32 {
+    let (abx, abz) = (b.x - a.x, b.z - a.z);
+    let (acx, acz) = (c.x - a.x, c.z - a.z);
+    acx * abz - abx * acz
+}
+
+fn dot_2d(a: Vec3, b: Vec3) -> f32 {
+    a.x * b.x + a.z * b.z
+}
+
+fn perp_2d(a: Vec3, b: Vec3) -> f32 {
+    a.z * b.x - a.x * b.z
+}
+
+fn dist_2d(a: Vec3, b: Vec3) -> f32 {
+    (a.x - b.x).hypot(a.z - b.z)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_steers_around_oncoming_agent() {
+        // Another agent walking straight at us
+        let mut query = ObstacleAvoidanceQuery::new();
+        query.add_circle(
+            Vec3::new(3.0, 0.0, 0.0),
+            0.5,
+            Vec3::new(-1.0, 0.0, 0.0),
+            Vec3::new(-1.0, 0.0, 0.0),
+        );
+        let dvel = Vec3::new(1.0, 0.0, 0.0);
+        for quality in AvoidanceQuality::ALL {
+            let mut debug = AvoidanceDebugData::default();
+            let (vel, count) = query.sample_velocity_adaptive(
+                Vec3::ZERO,
+                0.5,
+                1.5,
+                dvel,
+                dvel,
+                &quality.params(),
+                Some(&mut debug),
+            );
+            assert!(count > 0 && debug.samples.len() <= count);
+            // Still heading on, but sidestepping
+            // Slows down or sidesteps enough to stay clear for a second
+            assert!(vel.distance(dvel) > 0.1, "{}", quality.name());
+            let clear = (0..=10).all(|i| {
+                let t = i as f32 * 0.1;
+                let other = Vec3::new(3.0 - t, 0.0, 0.0);
+                (vel * t).distance(other) > 1.0
+            });
+            assert!(clear, "{}", quality.name());
+        }
+
+        // Nothing in the way: keep the desired velocity
+        query.reset();
+        let params = ObstacleAvoidanceParams::default();
+        let (vel, _) = query.sample_velocity_grid(Vec3::ZERO, 0.5, 1.5, dvel, dvel, &params, None);
+        assert!(vel.distance(dvel) < 0.1);
+    }
+
+    #[test]
+    fn test_wall_ahead_slows_agent() {
+        let mut query = ObstacleAvoidanceQuery::new();
+        query.add_segment(Vec3::new(0.6, 0.0, -5.0), Vec3::new(0.6, 0.0, 5.0));
+        let dvel = Vec3::new(2.0, 0.0, 0.0);
+        let params = ObstacleAvoidanceParams::default();
+        let (vel, _) =
+            query.sample_velocity_adaptive(Vec3::ZERO, 0.5, 2.0, dvel, dvel, &params, None);
+        // Doesn't run into the wall within the next half second
+        assert!(vel.x * 0.5 < 0.6);
+
+        let mut debug = AvoidanceDebugData::default();
+        query.sample_velocity_grid(Vec3::ZERO, 0.5, 2.0, dvel, dvel, &params, Some(&mut debug));
+        debug.normalize_samples();
+        assert!(debug
+            .samples
+            .iter()
+            .all(|s| (0.0..=1.0).contains(&s.penalty)));
+    }
+}
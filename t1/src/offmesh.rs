@@ -0,0 +1,14 @@
+use glam::Vec3;
+
+// A hand-placed link between two points of the walkable surface that the
+// mesh itself does not connect, e.g. a jump-down ledge or a ladder
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OffMeshConnection {
+    pub start: Vec3,
+    pub end: Vec3,
+    pub radius: f32,
+    // Whether the link can also be traversed from end to start
+    pub bidirectional: bool,
+    pub area: u8,
+}
@@ -0,0 +1,411 @@
+use glam::Vec3;
+
+use crate::nav_mesh::{NavMeshError, PolyRef};
+use crate::nav_mesh_query::{NavMeshQuery, StraightPathPoint, DT_STRAIGHTPATH_OFFMESH_CONNECTION};
+use crate::query_filter::PolyFilter;
+
+// Corners closer than this to the agent count as reached
+const MIN_TARGET_DIST: f32 = 0.01;
+// Search budget of `optimize_path_topology`
+const TOPOLOGY_MAX_ITERS: usize = 32;
+
+// The polygons an agent walks through from its position to its target,
+// kept up to date as both move so the path never has to be searched again
+// from scratch. Like Detour's dtPathCorridor.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathCorridor {
+    pos: Vec3,
+    target: Vec3,
+    path: Vec<PolyRef>,
+    max_path: usize,
+}
+
+impl PathCorridor {
+    // `max_path` caps the number of polygons kept
+    pub fn new(max_path: usize) -> Self {
+        Self {
+            pos: Vec3::ZERO,
+            target: Vec3::ZERO,
+            path: Vec::new(),
+            max_path: max_path.max(1),
+        }
+    }
+
+    // Collapse the corridor to polygon `r`, with the agent and target at
+    // `pos`
+    pub fn reset(&mut self, r: PolyRef, pos: Vec3) {
+        self.pos = pos;
+        self.target = pos;
+        self.path.clear();
+        self.path.push(r);
+    }
+
+    // Aim for `target` through `path`, which must start at the agent's
+    // polygon
+    pub fn set_corridor(&mut self, target: Vec3, path: &[PolyRef]) {
+        self.target = target;
+        self.path.clear();
+        self.path
+            .extend_from_slice(&path[..path.len().min(self.max_path)]);
+    }
+
+    pub fn pos(&self) -> Vec3 {
+        self.pos
+    }
+
+    pub fn target(&self) -> Vec3 {
+        self.target
+    }
+
+    pub fn path(&self) -> &[PolyRef] {
+        &self.path
+    }
+
+    pub fn first_poly(&self) -> Option<PolyRef> {
+        self.path.first().copied()
+    }
+
+    pub fn last_poly(&self) -> Option<PolyRef> {
+        self.path.last().copied()
+    }
+
+    // The next `max_corners` corners of the straight path to the target.
+    // Corners the agent already stands on are dropped, and the list stops
+    // at the start of an off-mesh connection.
+    pub fn find_corners(
+        &self,
+        query: &NavMeshQuery,
+        max_corners: usize,
+    ) -> Result<Vec<StraightPathPoint>, NavMeshError> {
+        let mut corners = query.find_straight_path(self.pos, self.target, &self.path)?;
+        let reached = corners.iter().position(|c| {
+            c.flags & DT_STRAIGHTPATH_OFFMESH_CONNECTION != 0
+                || dist_sqr_2d(c.pos, self.pos) > MIN_TARGET_DIST * MIN_TARGET_DIST
+        });
+        corners.drain(..reached.unwrap_or(corners.len()));
+        if let Some(off_mesh) = corners
+            .iter()
+            .position(|c| c.flags & DT_STRAIGHTPATH_OFFMESH_CONNECTION != 0)
+        {
+            corners.truncate(off_mesh + 1);
+        }
+        corners.truncate(max_corners);
+        Ok(corners)
+    }
+
+    // Cut the corridor short if the agent can see `next`, usually the next
+    // corner, straight over the navmesh. Looks at most
+    // `optimization_range` ahead.
+    pub fn optimize_path_visibility(
+        &mut self,
+        next: Vec3,
+        optimization_range: f32,
+        query: &NavMeshQuery,
+        filter: &impl PolyFilter,
+    ) -> Result<(), NavMeshError> {
+        let Some(&first) = self.path.first() else {
+            return Ok(());
+        };
+        let dist = dist_sqr_2d(self.pos, next).sqrt();
+        if dist < 0.01 {
+            return Ok(());
+        }
+        // Overshoot a little so the corner's own polygon is entered
+        let dist = (dist + 0.01).min(optimization_range);
+        let goal = self.pos + (next - self.pos) * (optimization_range / dist);
+
+        let hit = query.raycast(first, self.pos, goal, filter)?;
+        if hit.path.len() > 1 && hit.t > 0.99 {
+            self.path = merge_corridor_start_shortcut(&self.path, &hit.path);
+            self.path.truncate(self.max_path);
+        }
+        Ok(())
+    }
+
+    // Look for a cheaper route to a polygon further down the corridor with
+    // a short search, and splice it in. Returns whether it did. Meant to be
+    // called every now and then rather than every frame.
+    pub fn optimize_path_topology<'a>(
+        &mut self,
+        query: &mut NavMeshQuery<'a>,
+        filter: impl PolyFilter + 'a,
+    ) -> Result<bool, NavMeshError> {
+        let (Some(&first), Some(&last)) = (self.path.first(), self.path.last()) else {
+            return Ok(false);
+        };
+        if self.path.len() < 3 {
+            return Ok(false);
+        }
+        query.init_sliced_find_path(first, last, self.pos, self.target, filter)?;
+        query.update_sliced_find_path(TOPOLOGY_MAX_ITERS)?;
+        let result = query.finalize_sliced_find_path_partial(&self.path)?;
+        if result.polys.is_empty() {
+            return Ok(false);
+        }
+        let merged = merge_corridor_start_shortcut(&self.path, &result.polys);
+        let changed = merged != self.path;
+        self.path = merged;
+        self.path.truncate(self.max_path);
+        Ok(changed)
+    }
+
+    // Move the agent towards `npos` over the navmesh, sliding along walls,
+    // and drop the polygons left behind. Returns whether the agent moved.
+    pub fn move_position(
+        &mut self,
+        npos: Vec3,
+        query: &NavMeshQuery,
+        filter: &impl PolyFilter,
+    ) -> Result<bool, NavMeshError> {
+        let Some(&first) = self.path.first() else {
+            return Ok(false);
+        };
+        let (mut result, visited) = query.move_along_surface(first, self.pos, npos, filter)?;
+        self.path = merge_corridor_start_moved(&self.path, &visited);
+        self.path.truncate(self.max_path);
+
+        // Keep the agent on the surface
+        if let Some(h) = query.poly_height(self.path[0], result)? {
+            result.y = h;
+        }
+        let moved = result != self.pos;
+        self.pos = result;
+        Ok(moved)
+    }
+
+    // Move the target towards `npos` over the navmesh, extending or
+    // trimming the end of the corridor
+    pub fn move_target_position(
+        &mut self,
+        npos: Vec3,
+        query: &NavMeshQuery,
+        filter: &impl PolyFilter,
+    ) -> Result<bool, NavMeshError> {
+        let Some(&last) = self.path.last() else {
+            return Ok(false);
+        };
+        let (result, visited) = query.move_along_surface(last, self.target, npos, filter)?;
+        self.path = merge_corridor_end_moved(&self.path, &visited);
+        self.path.truncate(self.max_path);
+        let moved = result != self.target;
+        self.target = result;
+        Ok(moved)
+    }
+
+    // Whether the first `max_look_ahead` polygons still exist and pass
+    // `filter`
+    pub fn is_valid(
+        &self,
+        max_look_ahead: usize,
+        query: &NavMeshQuery,
+        filter: &impl PolyFilter,
+    ) -> bool {
+        self.path
+            .iter()
+            .take(max_look_ahead)
+            .all(|&r| query.is_valid_poly_ref(r, filter))
+    }
+
+    // Cut the corridor at its first polygon that no longer exists or
+    // passes `filter`, falling back to `safe_ref` and `safe_pos` if that's
+    // the first one. The target is pulled onto the new last polygon.
+    pub fn trim_invalid_path(
+        &mut self,
+        safe_ref: PolyRef,
+        safe_pos: Vec3,
+        query: &NavMeshQuery,
+        filter: &impl PolyFilter,
+    ) -> Result<(), NavMeshError> {
+        let valid = self
+            .path
+            .iter()
+            .take_while(|&&r| query.is_valid_poly_ref(r, filter))
+            .count();
+        if valid == self.path.len() {
+            return Ok(());
+        }
+        if valid == 0 {
+            self.pos = safe_pos;
+            self.path.clear();
+            self.path.push(safe_ref);
+        } else {
+            self.path.truncate(valid);
+        }
+        let last = self.path[self.path.len() - 1];
+        self.target = query.closest_point_on_poly_boundary(last, self.target)?;
+        Ok(())
+    }
+}
+
+// Furthest pair of indices into `path` and `visited` holding the same
+// polygon, scanning `path` from `path_order`
+fn furthest_common(
+    path: &[PolyRef],
+    visited: &[PolyRef],
+    path_order: impl Iterator<Item = usize>,
+) -> Option<(usize, usize)> {
+    for i in path_order {
+        if let Some(j) = visited.iter().rposition(|&v| v == path[i]) {
+            return Some((i, j));
+        }
+    }
+    None
+}
+
+// After moving the start along `visited`, replace the corridor up to the
+// last polygon both share with the way back from where the agent ended up
+fn merge_corridor_start_moved(path: &[PolyRef], visited: &[PolyRef]) -> Vec<PolyRef> {
+    let Some((fp, fv)) = furthest_common(path, visited, (0..path.len()).rev()) else {
+        return path.to_vec();
+    };
+    visited[fv..]
+        .iter()
+        .rev()
+        .chain(&path[(fp + 1).min(path.len())..])
+        .copied()
+        .collect()
+}
+
+// After moving the target along `visited`, continue the corridor from the
+// first polygon both share
+fn merge_corridor_end_moved(path: &[PolyRef], visited: &[PolyRef]) -> Vec<PolyRef> {
+    let Some((fp, fv)) = furthest_common(path, visited, 0..path.len()) else {
+        return path.to_vec();
+    };
+    path[..fp].iter().chain(&visited[fv..]).copied().collect()
+}
+
+// Replace the corridor up to the furthest polygon `shortcut`, which starts
+// at the same polygon, also reaches
+fn merge_corridor_start_shortcut(path: &[PolyRef], shortcut: &[PolyRef]) -> Vec<PolyRef> {
+    match furthest_common(path, shortcut, (0..path.len()).rev()) {
+        Some((fp, fv)) if fv > 0 => shortcut[..fv].iter().chain(&path[fp..]).copied().collect(),
+        _ => path.to_vec(),
+    }
+}
+
+fn dist_sqr_2d(a: Vec3, b: Vec3) -> f32 {
+    let (dx, dz) = (b.x - a.x, b.z - a.z);
+    dx * dx + dz * dz
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debug_draw::{PolyMesh, RC_MESH_NULL_IDX, RC_WALKABLE_AREA};
+    use crate::nav_mesh::{NavMesh, NavMeshCreateParams, NavMeshData, POLY_FLAG_WALK};
+    use crate::query_filter::DefaultFilter;
+
+    // An L of 2x2 quads: `arm` quads along +X, then `arm` along +Z from the
+    // last one
+    fn corner(arm: usize) -> NavMesh {
+        let n = RC_MESH_NULL_IDX;
+        let mut mesh = PolyMesh {
+            nvp: 6,
+            cs: 1.0,
+            ch: 1.0,
+            bmax: Vec3::new(2.0 * arm as f32, 1.0, 2.0 * arm as f32),
+            ..Default::default()
+        };
+        let mut quad = |x: f32, z: f32| {
+            let v = mesh.verts.len() as u16;
+            mesh.verts.extend([
+                Vec3::new(x, 0.0, z),
+                Vec3::new(x, 0.0, z + 2.0),
+                Vec3::new(x + 2.0, 0.0, z + 2.0),
+                Vec3::new(x + 2.0, 0.0, z),
+            ]);
+            mesh.polys.push(vec![v, v + 1, v + 2, v + 3, n, n]);
+        };
+        let last = 2.0 * (arm - 1) as f32;
+        for i in 0..arm {
+            quad(2.0 * i as f32, 0.0);
+        }
+        for i in 1..arm {
+            quad(last, 2.0 * i as f32);
+        }
+        // Edges 0..4 face -x, +z, +x and -z
+        let count = 2 * arm - 1;
+        for i in 0..count {
+            let mut neis = vec![n; 6];
+            if i > 0 {
+                neis[if i < arm { 0 } else { 3 }] = i as u16 - 1;
+            }
+            if i + 1 < count {
+                neis[if i + 1 < arm { 2 } else { 1 }] = i as u16 + 1;
+            }
+            mesh.neighbours.push(neis);
+        }
+        mesh.regs = vec![1; count];
+        mesh.flags = vec![POLY_FLAG_WALK; count];
+        mesh.areas = vec![RC_WALKABLE_AREA; count];
+        let params = NavMeshCreateParams::new(&mesh, None, 2.0, 0.5, 0.5);
+        NavMesh::single_tile(NavMeshData::build(&params).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_move_position_follows_corridor() {
+        let nav_mesh = corner(3);
+        let base = nav_mesh.poly_ref_base(nav_mesh.tiles().next().unwrap());
+        let mut query = NavMeshQuery::new(&nav_mesh, 64);
+
+        let start = Vec3::new(1.0, 0.0, 1.0);
+        let target = Vec3::new(5.0, 0.0, 5.0);
+        let path = query
+            .find_path(base, base | 4, start, target, &DefaultFilter)
+            .unwrap();
+        let mut corridor = PathCorridor::new(16);
+        corridor.reset(base, start);
+        corridor.set_corridor(target, &path.polys);
+        assert_eq!(corridor.path().len(), 5);
+
+        // Round the inside corner of the L
+        let corners = corridor.find_corners(&query, 4).unwrap();
+        assert_eq!(corners.len(), 2);
+        assert_eq!(corners[0].pos, Vec3::new(4.0, 0.0, 2.0));
+
+        // Walking into the first corner drops the polygons passed
+        assert!(corridor
+            .move_position(Vec3::new(3.9, 0.0, 1.9), &query, &DefaultFilter)
+            .unwrap());
+        assert_eq!(corridor.first_poly(), Some(base | 1));
+        assert_eq!(corridor.path().len(), 4);
+
+        // Walls stop the agent on the outside of the L
+        corridor
+            .move_position(Vec3::new(2.5, 0.0, 2.5), &query, &DefaultFilter)
+            .unwrap();
+        assert!((corridor.pos() - Vec3::new(2.5, 0.0, 2.0)).length() < 1e-5);
+        assert_eq!(corridor.first_poly(), Some(base | 1));
+        assert_eq!(corridor.last_poly(), Some(base | 4));
+    }
+
+    #[test]
+    fn test_optimizations_shortcut_the_corridor() {
+        let nav_mesh = corner(3);
+        let base = nav_mesh.poly_ref_base(nav_mesh.tiles().next().unwrap());
+        let mut query = NavMeshQuery::new(&nav_mesh, 64);
+
+        // A detour through the first quad and back
+        let start = Vec3::new(3.0, 0.0, 1.0);
+        let target = Vec3::new(5.0, 0.0, 3.0);
+        let mut corridor = PathCorridor::new(16);
+        corridor.reset(base | 1, start);
+        corridor.set_corridor(target, &[base | 1, base, base | 1, base | 2, base | 3]);
+
+        assert!(corridor
+            .optimize_path_topology(&mut query, DefaultFilter)
+            .unwrap());
+        assert_eq!(corridor.path(), &[base | 1, base | 2, base | 3]);
+
+        // The next corner is in sight, so the corridor goes straight there
+        let mut corridor = PathCorridor::new(16);
+        corridor.reset(base | 1, start);
+        corridor.set_corridor(target, &[base | 1, base, base | 1, base | 2, base | 3]);
+        corridor
+            .optimize_path_visibility(Vec3::new(5.0, 0.0, 1.0), 2.0, &query, &DefaultFilter)
+            .unwrap();
+        assert_eq!(corridor.path(), &[base | 1, base | 2, base | 3]);
+        assert!(corridor.is_valid(3, &query, &DefaultFilter));
+    }
+}
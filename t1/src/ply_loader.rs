@@ -0,0 +1,370 @@
+use glam::Vec3;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use crate::debug_draw_b::InputMesh;
+
+#[derive(Debug)]
+pub enum PlyLoadError {
+    IoError(io::Error),
+    ParseError(String),
+}
+
+impl fmt::Display for PlyLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlyLoadError::IoError(err) => write!(f, "{}", err),
+            PlyLoadError::ParseError(message) => write!(f, "PLY: {}", message),
+        }
+    }
+}
+
+impl From<io::Error> for PlyLoadError {
+    fn from(error: io::Error) -> Self {
+        PlyLoadError::IoError(error)
+    }
+}
+
+fn parse_error(message: impl Into<String>) -> PlyLoadError {
+    PlyLoadError::ParseError(message.into())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PlyFormat {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PlyScalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl PlyScalar {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => PlyScalar::I8,
+            "uchar" | "uint8" => PlyScalar::U8,
+            "short" | "int16" => PlyScalar::I16,
+            "ushort" | "uint16" => PlyScalar::U16,
+            "int" | "int32" => PlyScalar::I32,
+            "uint" | "uint32" => PlyScalar::U32,
+            "float" | "float32" => PlyScalar::F32,
+            "double" | "float64" => PlyScalar::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            PlyScalar::I8 | PlyScalar::U8 => 1,
+            PlyScalar::I16 | PlyScalar::U16 => 2,
+            PlyScalar::I32 | PlyScalar::U32 | PlyScalar::F32 => 4,
+            PlyScalar::F64 => 8,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum PlyProperty {
+    Scalar(String, PlyScalar),
+    // Count type, item type, name
+    List(PlyScalar, PlyScalar, String),
+}
+
+#[derive(Debug, Clone)]
+struct PlyElement {
+    name: String,
+    count: usize,
+    properties: Vec<PlyProperty>,
+}
+
+// Reads scalars from the body in whichever encoding the header declared
+enum BodyReader<'a> {
+    Ascii(std::str::SplitAsciiWhitespace<'a>),
+    Binary { bytes: &'a [u8], big_endian: bool },
+}
+
+impl BodyReader<'_> {
+    fn read(&mut self, ty: PlyScalar) -> Result<f64, PlyLoadError> {
+        match self {
+            BodyReader::Ascii(tokens) => tokens
+                .next()
+                .and_then(|t| t.parse::<f64>().ok())
+                .ok_or_else(|| parse_error("missing or invalid value")),
+            BodyReader::Binary { bytes, big_endian } => {
+                if bytes.len() < ty.size() {
+                    return Err(parse_error("unexpected end of file"));
+                }
+                let (head, rest) = bytes.split_at(ty.size());
+                *bytes = rest;
+                let mut buf = [0u8; 8];
+                buf[..head.len()].copy_from_slice(head);
+                if *big_endian {
+                    buf[..head.len()].reverse();
+                }
+                let [b0, b1, b2, b3, ..] = buf;
+                Ok(match ty {
+                    PlyScalar::I8 => b0 as i8 as f64,
+                    PlyScalar::U8 => b0 as f64,
+                    PlyScalar::I16 => i16::from_le_bytes([b0, b1]) as f64,
+                    PlyScalar::U16 => u16::from_le_bytes([b0, b1]) as f64,
+                    PlyScalar::I32 => i32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    PlyScalar::U32 => u32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    PlyScalar::F32 => f32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    PlyScalar::F64 => f64::from_le_bytes(buf),
+                })
+            }
+        }
+    }
+}
+
+// Load an ASCII or binary PLY file. Only vertex positions and face
+// vertex indices are used; polygons are triangulated as fans.
+pub fn load_ply<P: AsRef<Path>>(path: P) -> Result<InputMesh, PlyLoadError> {
+    parse_ply(&std::fs::read(path)?)
+}
+
+pub fn parse_ply(bytes: &[u8]) -> Result<InputMesh, PlyLoadError> {
+    let (format, elements, body) = parse_header(bytes)?;
+    let mut reader = match format {
+        PlyFormat::Ascii => BodyReader::Ascii(
+            std::str::from_utf8(body)
+                .map_err(|_| parse_error("ASCII body is not valid UTF-8"))?
+                .split_ascii_whitespace(),
+        ),
+        PlyFormat::BinaryLittleEndian | PlyFormat::BinaryBigEndian => BodyReader::Binary {
+            bytes: body,
+            big_endian: format == PlyFormat::BinaryBigEndian,
+        },
+    };
+
+    let mut mesh = InputMesh::new();
+    for element in &elements {
+        match element.name.as_str() {
+            "vertex" => read_vertices(&mut reader, element, &mut mesh)?,
+            "face" => read_faces(&mut reader, element, &mut mesh)?,
+            _ => skip_element(&mut reader, element)?,
+        }
+    }
+
+    if let Some(&bad) = mesh
+        .tris
+        .iter()
+        .find(|&&i| i < 0 || i as usize >= mesh.verts.len())
+    {
+        return Err(parse_error(format!("face index {} out of range", bad)));
+    }
+    mesh.compute_normals();
+    Ok(mesh)
+}
+
+// Split the file into its format, element declarations and body
+fn parse_header(bytes: &[u8]) -> Result<(PlyFormat, Vec<PlyElement>, &[u8]), PlyLoadError> {
+    const END: &[u8] = b"end_header";
+    let end = bytes
+        .windows(END.len())
+        .position(|w| w == END)
+        .ok_or_else(|| parse_error("missing end_header"))?;
+    let header = std::str::from_utf8(&bytes[..end]).map_err(|_| parse_error("bad header"))?;
+    // The body starts after the end_header line break, which may be CRLF
+    let mut body = &bytes[end + END.len()..];
+    if let Some(rest) = body.strip_prefix(b"\r") {
+        body = rest;
+    }
+    let body = body.strip_prefix(b"\n").unwrap_or(body);
+
+    let mut lines = header.lines().map(str::trim);
+    if lines.next() != Some("ply") {
+        return Err(parse_error("missing ply magic"));
+    }
+
+    let mut format = None;
+    let mut elements: Vec<PlyElement> = Vec::new();
+    for line in lines {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens[..] {
+            ["format", name, _] => {
+                format = Some(match name {
+                    "ascii" => PlyFormat::Ascii,
+                    "binary_little_endian" => PlyFormat::BinaryLittleEndian,
+                    "binary_big_endian" => PlyFormat::BinaryBigEndian,
+                    _ => return Err(parse_error(format!("unknown format {}", name))),
+                });
+            }
+            ["element", name, count] => elements.push(PlyElement {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| parse_error(format!("bad element count {}", count)))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count_ty, item_ty, name] => {
+                let property = PlyProperty::List(scalar(count_ty)?, scalar(item_ty)?, name.into());
+                current(&mut elements)?.properties.push(property);
+            }
+            ["property", ty, name] => {
+                let property = PlyProperty::Scalar(name.to_string(), scalar(ty)?);
+                current(&mut elements)?.properties.push(property);
+            }
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            _ => return Err(parse_error(format!("unexpected header line `{}`", line))),
+        }
+    }
+
+    let format = format.ok_or_else(|| parse_error("missing format line"))?;
+    Ok((format, elements, body))
+}
+
+fn scalar(name: &str) -> Result<PlyScalar, PlyLoadError> {
+    PlyScalar::from_name(name).ok_or_else(|| parse_error(format!("unknown type {}", name)))
+}
+
+fn current(elements: &mut [PlyElement]) -> Result<&mut PlyElement, PlyLoadError> {
+    elements
+        .last_mut()
+        .ok_or_else(|| parse_error("property before any element"))
+}
+
+fn read_vertices(
+    reader: &mut BodyReader,
+    element: &PlyElement,
+    mesh: &mut InputMesh,
+) -> Result<(), PlyLoadError> {
+    mesh.verts.reserve(element.count);
+    for _ in 0..element.count {
+        let mut pos = Vec3::ZERO;
+        for property in &element.properties {
+            match property {
+                PlyProperty::Scalar(name, ty) => {
+                    let value = reader.read(*ty)? as f32;
+                    match name.as_str() {
+                        "x" => pos.x = value,
+                        "y" => pos.y = value,
+                        "z" => pos.z = value,
+                        _ => {}
+                    }
+                }
+                PlyProperty::List(count_ty, item_ty, _) => skip_list(reader, *count_ty, *item_ty)?,
+            }
+        }
+        mesh.verts.push(pos);
+    }
+    Ok(())
+}
+
+fn read_faces(
+    reader: &mut BodyReader,
+    element: &PlyElement,
+    mesh: &mut InputMesh,
+) -> Result<(), PlyLoadError> {
+    let mut face = Vec::new();
+    for _ in 0..element.count {
+        for property in &element.properties {
+            match property {
+                PlyProperty::List(count_ty, item_ty, name)
+                    if name == "vertex_indices" || name == "vertex_index" =>
+                {
+                    let count = reader.read(*count_ty)? as usize;
+                    face.clear();
+                    for _ in 0..count {
+                        face.push(reader.read(*item_ty)? as i32);
+                    }
+                    for i in 1..face.len().saturating_sub(1) {
+                        mesh.tris.extend([face[0], face[i], face[i + 1]]);
+                    }
+                }
+                PlyProperty::List(count_ty, item_ty, _) => skip_list(reader, *count_ty, *item_ty)?,
+                PlyProperty::Scalar(_, ty) => {
+                    reader.read(*ty)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn skip_element(reader: &mut BodyReader, element: &PlyElement) -> Result<(), PlyLoadError> {
+    for _ in 0..element.count {
+        for property in &element.properties {
+            match property {
+                PlyProperty::Scalar(_, ty) => {
+                    reader.read(*ty)?;
+                }
+                PlyProperty::List(count_ty, item_ty, _) => skip_list(reader, *count_ty, *item_ty)?,
+            }
+        }
+    }
+    Ok(())
+}
+
+fn skip_list(
+    reader: &mut BodyReader,
+    count_ty: PlyScalar,
+    item_ty: PlyScalar,
+) -> Result<(), PlyLoadError> {
+    let count = reader.read(count_ty)? as usize;
+    for _ in 0..count {
+        reader.read(item_ty)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ascii_and_binary_ply_match() {
+        let ascii = "ply
+format ascii 1.0
+comment a unit quad
+element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+element face 1
+property list uchar int vertex_indices
+end_header
+0 0 0 255
+0 0 1 255
+1 0 1 255
+1 0 0 255
+4 0 1 2 3
+";
+        let mesh = parse_ply(ascii.as_bytes()).unwrap();
+        assert_eq!(mesh.verts[2], Vec3::new(1.0, 0.0, 1.0));
+        assert_eq!(mesh.tris, vec![0, 1, 2, 0, 2, 3]);
+        assert!(mesh.validate().is_ok());
+
+        let mut binary = b"ply\r\nformat binary_big_endian 1.0\r\n\
+element vertex 4\r\nproperty double x\r\nproperty double y\r\nproperty double z\r\n\
+element face 1\r\nproperty list uchar ushort vertex_index\r\nend_header\r\n"
+            .to_vec();
+        for v in &mesh.verts {
+            for c in v.to_array() {
+                binary.extend((c as f64).to_be_bytes());
+            }
+        }
+        binary.push(4);
+        for i in 0u16..4 {
+            binary.extend(i.to_be_bytes());
+        }
+        let from_binary = parse_ply(&binary).unwrap();
+        assert_eq!(from_binary.verts, mesh.verts);
+        assert_eq!(from_binary.tris, mesh.tris);
+
+        // Truncated body
+        assert!(parse_ply(&binary[..binary.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,136 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+// Largest block deflate can store uncompressed
+const MAX_STORED_BLOCK: usize = 0xffff;
+
+// Encode 8-bit RGBA pixels, row by row from the top, as a PNG. The image
+// data is stored without compression, which keeps this small and is fine
+// for screenshots.
+pub fn encode_png(width: u32, height: u32, rgba: &[u8]) -> Vec<u8> {
+    assert_eq!(rgba.len(), width as usize * height as usize * 4);
+
+    let mut png = SIGNATURE.to_vec();
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // 8 bits per channel, RGBA, deflate, adaptive filtering, no interlace
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+    write_chunk(&mut png, b"IHDR", &header);
+
+    // Every scanline starts with its filter type, 0 for none
+    let row_len = width as usize * 4;
+    let mut raw = Vec::with_capacity((row_len + 1) * height as usize);
+    for row in rgba.chunks_exact(row_len.max(1)).take(height as usize) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+pub fn save_png<P: AsRef<Path>>(path: P, width: u32, height: u32, rgba: &[u8]) -> io::Result<()> {
+    fs::write(path, encode_png(width, height, rgba))
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    // The CRC covers the type and the data but not the length
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+// A zlib stream of uncompressed deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_STORED_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        // Even no data needs one final block
+        out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let len = block.len() as u16;
+        out.push(last as u8);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_png() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+
+        // Wide enough that the pixel data spans several deflate blocks
+        let (width, height) = (200u32, 100u32);
+        let rgba: Vec<u8> = (0..width * height * 4).map(|i| i as u8).collect();
+        let png = encode_png(width, height, &rgba);
+        assert_eq!(png[..8], SIGNATURE);
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(png[16..20], width.to_be_bytes());
+        assert_eq!(png[20..24], height.to_be_bytes());
+        // IEND with its fixed CRC closes the file
+        assert_eq!(
+            png[png.len() - 12..],
+            [0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xae, 0x42, 0x60, 0x82]
+        );
+
+        // Unpacking the stored blocks gives back the scanlines
+        let idat_len = u32::from_be_bytes(png[33..37].try_into().unwrap()) as usize;
+        assert_eq!(&png[37..41], b"IDAT");
+        let zlib = &png[41..41 + idat_len];
+        let mut raw = Vec::new();
+        let mut pos = 2;
+        loop {
+            let last = zlib[pos] == 1;
+            let len = u16::from_le_bytes([zlib[pos + 1], zlib[pos + 2]]) as usize;
+            raw.extend_from_slice(&zlib[pos + 5..pos + 5 + len]);
+            pos += 5 + len;
+            if last {
+                break;
+            }
+        }
+        assert_eq!(raw.len(), (width as usize * 4 + 1) * height as usize);
+        assert_eq!(raw[0], 0);
+        assert_eq!(raw[1..801], rgba[..800]);
+        assert_eq!(zlib[pos..], adler32(&raw).to_be_bytes());
+    }
+}
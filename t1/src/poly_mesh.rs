@@ -0,0 +1,692 @@
+use std::collections::{HashMap, VecDeque};
+
+use glam::Vec3;
+
+use crate::build_context::BuildContext;
+use crate::contours::{
+    intersect, intersect_prop, left, left_on, vequal, ContourSet, RC_BORDER_VERTEX,
+};
+use crate::debug_draw::{PolyMesh, RC_MESH_NULL_IDX};
+
+// Region id of polygons merged from triangles of several regions
+pub const RC_MULTIPLE_REGS: u16 = 0;
+
+// Neighbour value marking an edge on the tile border; the low bits hold the
+// side of the tile (0: -x, 1: +z, 2: +x, 3: -z)
+pub const RC_MESH_PORTAL_FLAG: u16 = 0x8000;
+
+// Working copy of the mesh while it is assembled: integer vertices and
+// fixed-size polygons padded with RC_MESH_NULL_IDX
+struct MeshBuilder {
+    nvp: usize,
+    verts: Vec<[i32; 3]>,
+    polys: Vec<Vec<u16>>,
+    regs: Vec<u16>,
+    areas: Vec<u8>,
+}
+
+impl PolyMesh {
+    // Triangulate every contour, merge the triangles into convex polygons
+    // of up to `nvp` vertices and connect neighbouring polygons. Failed
+    // triangulations are logged to `ctx`.
+    pub fn build(cset: &ContourSet, nvp: usize, ctx: &mut BuildContext) -> PolyMesh {
+        let mut builder = MeshBuilder {
+            nvp,
+            verts: Vec::new(),
+            polys: Vec::new(),
+            regs: Vec::new(),
+            areas: Vec::new(),
+        };
+        // Vertices along the tile border, removed once the mesh is built
+        let mut vflags: Vec<bool> = Vec::new();
+        // Vertices by XZ position, to weld the contours together
+        let mut vertex_lookup: HashMap<(i32, i32), Vec<usize>> = HashMap::new();
+
+        for cont in &cset.contours {
+            if cont.verts.len() < 3 {
+                continue;
+            }
+
+            let positions: Vec<[i32; 3]> = cont.verts.iter().map(|v| [v[0], v[1], v[2]]).collect();
+            let (tris, ok) = triangulate(&positions);
+            if !ok {
+                ctx.warn(format!(
+                    "PolyMesh::build: bad triangulation of a contour in region {}",
+                    cont.reg
+                ));
+            }
+
+            let indices: Vec<u16> = cont
+                .verts
+                .iter()
+                .map(|v| {
+                    let index = builder.add_vertex([v[0], v[1], v[2]], &mut vertex_lookup);
+                    if vflags.len() <= index as usize {
+                        vflags.resize(index as usize + 1, false);
+                    }
+                    if v[3] & RC_BORDER_VERTEX != 0 {
+                        vflags[index as usize] = true;
+                    }
+                    index
+                })
+                .collect();
+
+            let mut polys: Vec<Vec<u16>> = tris
+                .iter()
+                .filter(|t| t[0] != t[1] && t[0] != t[2] && t[1] != t[2])
+                .map(|t| {
+                    let mut p = vec![RC_MESH_NULL_IDX; nvp];
+                    for k in 0..3 {
+                        p[k] = indices[t[k]];
+                    }
+                    p
+                })
+                .collect();
+            if polys.is_empty() {
+                continue;
+            }
+
+            if nvp > 3 {
+                merge_polys(&mut polys, &builder.verts, nvp, |_, _| {});
+            }
+
+            for p in polys {
+                builder.polys.push(p);
+                builder.regs.push(cont.reg);
+                builder.areas.push(cont.area);
+            }
+        }
+
+        // Remove the tile border vertices where that leaves valid polygons
+        let mut i = 0;
+        while i < builder.verts.len() {
+            if vflags[i] && builder.can_remove_vertex(i as u16) {
+                builder.remove_vertex(i as u16, ctx);
+                vflags.remove(i);
+            } else {
+                i += 1;
+            }
+        }
+
+        let mut neighbours = build_mesh_adjacency(&builder.polys, builder.verts.len(), nvp);
+
+        // Mark open edges on the tile border as portals
+        if cset.border_size > 0 {
+            let (w, h) = (cset.width, cset.height);
+            for (p, neis) in builder.polys.iter().zip(neighbours.iter_mut()) {
+                let nv = count_poly_verts(p);
+                for j in 0..nv {
+                    if neis[j] != RC_MESH_NULL_IDX {
+                        continue;
+                    }
+                    let va = builder.verts[p[j] as usize];
+                    let vb = builder.verts[p[(j + 1) % nv] as usize];
+                    let side = if va[0] == 0 && vb[0] == 0 {
+                        0
+                    } else if va[2] == h && vb[2] == h {
+                        1
+                    } else if va[0] == w && vb[0] == w {
+                        2
+                    } else if va[2] == 0 && vb[2] == 0 {
+                        3
+                    } else {
+                        continue;
+                    };
+                    neis[j] = RC_MESH_PORTAL_FLAG | side;
+                }
+            }
+        }
+
+        let npolys = builder.polys.len();
+        PolyMesh {
+            verts: builder
+                .verts
+                .iter()
+                .map(|v| Vec3::new(v[0] as f32, v[1] as f32, v[2] as f32))
+                .collect(),
+            polys: builder.polys,
+            neighbours,
+            regs: builder.regs,
+            flags: vec![0; npolys],
+            areas: builder.areas,
+            nvp: nvp as i32,
+            cs: cset.cs,
+            ch: cset.ch,
+            bmin: cset.bmin,
+            bmax: cset.bmax,
+            border_size: cset.border_size,
+            max_edge_error: cset.max_error,
+        }
+    }
+}
+
+impl MeshBuilder {
+    // Index of the vertex at `v`, welding to an existing one at the same XZ
+    // position within two voxels of height
+    fn add_vertex(&mut self, v: [i32; 3], lookup: &mut HashMap<(i32, i32), Vec<usize>>) -> u16 {
+        let bucket = lookup.entry((v[0], v[2])).or_default();
+        if let Some(&i) = bucket
+            .iter()
+            .find(|&&i| (self.verts[i][1] - v[1]).abs() <= 2)
+        {
+            return i as u16;
+        }
+        bucket.push(self.verts.len());
+        self.verts.push(v);
+        (self.verts.len() - 1) as u16
+    }
+
+    // Removing `rem` must leave enough edges for a polygon, and the
+    // polygons around it must form a single fan
+    fn can_remove_vertex(&self, rem: u16) -> bool {
+        let mut remaining_edges = 0;
+        for p in &self.polys {
+            let nv = count_poly_verts(p);
+            let removed = p[..nv].iter().filter(|&&v| v == rem).count();
+            if removed > 0 {
+                remaining_edges += nv - (removed + 1);
+            }
+        }
+        if remaining_edges <= 2 {
+            return false;
+        }
+
+        // Count how often each edge at `rem` is used; edges used once are
+        // open, and a fan has exactly two of them
+        let mut edges: Vec<(u16, u32)> = Vec::new();
+        for p in &self.polys {
+            let nv = count_poly_verts(p);
+            let mut k = nv - 1;
+            for j in 0..nv {
+                if p[j] == rem || p[k] == rem {
+                    let other = if p[j] == rem { p[k] } else { p[j] };
+                    match edges.iter_mut().find(|(b, _)| *b == other) {
+                        Some((_, count)) => *count += 1,
+                        None => edges.push((other, 1)),
+                    }
+                }
+                k = j;
+            }
+        }
+        edges.iter().filter(|&&(_, count)| count < 2).count() <= 2
+    }
+
+    // Remove vertex `rem` and the polygons using it, then fill the hole
+    // with new polygons
+    fn remove_vertex(&mut self, rem: u16, ctx: &mut BuildContext) {
+        let nvp = self.nvp;
+
+        // Keep the edges of the removed polygons that don't touch `rem`
+        let mut edges: Vec<(u16, u16, u16, u8)> = Vec::new();
+        let mut i = 0;
+        while i < self.polys.len() {
+            let p = &self.polys[i];
+            let nv = count_poly_verts(p);
+            if !p[..nv].contains(&rem) {
+                i += 1;
+                continue;
+            }
+            let mut k = nv - 1;
+            for j in 0..nv {
+                if p[j] != rem && p[k] != rem {
+                    edges.push((p[k], p[j], self.regs[i], self.areas[i]));
+                }
+                k = j;
+            }
+            self.polys.swap_remove(i);
+            self.regs.swap_remove(i);
+            self.areas.swap_remove(i);
+        }
+
+        self.verts.remove(rem as usize);
+        let shift = |v: &mut u16| {
+            if *v != RC_MESH_NULL_IDX && *v > rem {
+                *v -= 1;
+            }
+        };
+        for p in &mut self.polys {
+            p.iter_mut().for_each(shift);
+        }
+        for e in &mut edges {
+            shift(&mut e.0);
+            shift(&mut e.1);
+        }
+        if edges.is_empty() {
+            return;
+        }
+
+        // Chain the edges into the outline of the hole, growing it at
+        // either end
+        let (start, _, reg, area) = edges[0];
+        let mut hole = VecDeque::from([start]);
+        let mut hreg = VecDeque::from([reg]);
+        let mut harea = VecDeque::from([area]);
+
+        loop {
+            let mut matched = false;
+            let mut i = 0;
+            while i < edges.len() {
+                let (ea, eb, r, a) = edges[i];
+                if hole[0] == eb {
+                    hole.push_front(ea);
+                    hreg.push_front(r);
+                    harea.push_front(a);
+                } else if hole[hole.len() - 1] == ea {
+                    hole.push_back(eb);
+                    hreg.push_back(r);
+                    harea.push_back(a);
+                } else {
+                    i += 1;
+                    continue;
+                }
+                edges.swap_remove(i);
+                matched = true;
+            }
+            if !matched || edges.is_empty() {
+                break;
+            }
+        }
+
+        let hole: Vec<u16> = hole.into();
+        let hreg: Vec<u16> = hreg.into();
+        let harea: Vec<u8> = harea.into();
+        let positions: Vec<[i32; 3]> = hole.iter().map(|&v| self.verts[v as usize]).collect();
+        let (tris, ok) = triangulate(&positions);
+        if !ok {
+            ctx.warn(
+                "PolyMesh::build: bad triangulation of a hole left by a removed vertex".to_string(),
+            );
+        }
+
+        let mut polys = Vec::new();
+        let mut pregs = Vec::new();
+        let mut pareas = Vec::new();
+        for t in &tris {
+            if t[0] == t[1] || t[0] == t[2] || t[1] == t[2] {
+                continue;
+            }
+            let mut p = vec![RC_MESH_NULL_IDX; nvp];
+            for k in 0..3 {
+                p[k] = hole[t[k]];
+            }
+            polys.push(p);
+            // A polygon spanning several regions belongs to none of them
+            pregs.push(if hreg[t[0]] != hreg[t[1]] || hreg[t[1]] != hreg[t[2]] {
+                RC_MULTIPLE_REGS
+            } else {
+                hreg[t[0]]
+            });
+            pareas.push(harea[t[0]]);
+        }
+
+        if nvp > 3 {
+            merge_polys(&mut polys, &self.verts, nvp, |pa, pb| {
+                if pregs[pa] != pregs[pb] {
+                    pregs[pa] = RC_MULTIPLE_REGS;
+                }
+                pregs.swap_remove(pb);
+                pareas.swap_remove(pb);
+            });
+        }
+
+        self.polys.extend(polys);
+        self.regs.extend(pregs);
+        self.areas.extend(pareas);
+    }
+}
+
+fn count_poly_verts(p: &[u16]) -> usize {
+    p.iter()
+        .position(|&v| v == RC_MESH_NULL_IDX)
+        .unwrap_or(p.len())
+}
+
+// Greedily merge pairs of polygons sharing an edge, longest shared edge
+// first, while the result stays convex and within `nvp` vertices.
+// `on_merge(pa, pb)` runs after `pb` is merged into `pa` and before `pb` is
+// swap-removed, so callers can update parallel arrays.
+fn merge_polys(
+    polys: &mut Vec<Vec<u16>>,
+    verts: &[[i32; 3]],
+    nvp: usize,
+    mut on_merge: impl FnMut(usize, usize),
+) {
+    loop {
+        let mut best_merge_val = 0;
+        let mut best = (0, 0, 0, 0);
+        for j in 0..polys.len() {
+            for k in j + 1..polys.len() {
+                if let Some((val, ea, eb)) = poly_merge_value(&polys[j], &polys[k], verts, nvp) {
+                    if val > best_merge_val {
+                        best_merge_val = val;
+                        best = (j, k, ea, eb);
+                    }
+                }
+            }
+        }
+        if best_merge_val == 0 {
+            break;
+        }
+
+        let (pa, pb, ea, eb) = best;
+        polys[pa] = merge_poly_verts(&polys[pa], &polys[pb], ea, eb, nvp);
+        on_merge(pa, pb);
+        polys.swap_remove(pb);
+    }
+}
+
+// Squared length of the edge `pa` and `pb` share, with the index of that
+// edge in each, or None if they can't be merged into a convex polygon
+fn poly_merge_value(
+    pa: &[u16],
+    pb: &[u16],
+    verts: &[[i32; 3]],
+    nvp: usize,
+) -> Option<(i32, usize, usize)> {
+    let na = count_poly_verts(pa);
+    let nb = count_poly_verts(pb);
+    if na + nb - 2 > nvp {
+        return None;
+    }
+
+    let sorted = |a: u16, b: u16| if a > b { (b, a) } else { (a, b) };
+    let (ea, eb) = (0..na).find_map(|i| {
+        let edge = sorted(pa[i], pa[(i + 1) % na]);
+        (0..nb)
+            .find(|&j| sorted(pb[j], pb[(j + 1) % nb]) == edge)
+            .map(|j| (i, j))
+    })?;
+
+    // Both corners of the shared edge must stay convex
+    let v = |i: u16| &verts[i as usize][..];
+    let convex = |a: u16, b: u16, c: u16| left(v(a), v(b), v(c));
+    if !convex(pa[(ea + na - 1) % na], pa[ea], pb[(eb + 2) % nb]) {
+        return None;
+    }
+    if !convex(pb[(eb + nb - 1) % nb], pb[eb], pa[(ea + 2) % na]) {
+        return None;
+    }
+
+    let (va, vb) = (v(pa[ea]), v(pa[(ea + 1) % na]));
+    let dx = va[0] - vb[0];
+    let dz = va[2] - vb[2];
+    Some((dx * dx + dz * dz, ea, eb))
+}
+
+fn merge_poly_verts(pa: &[u16], pb: &[u16], ea: usize, eb: usize, nvp: usize) -> Vec<u16> {
+    let na = count_poly_verts(pa);
+    let nb = count_poly_verts(pb);
+    let mut merged = Vec::with_capacity(nvp);
+    merged.extend((0..na - 1).map(|i| pa[(ea + 1 + i) % na]));
+    merged.extend((0..nb - 1).map(|i| pb[(eb + 1 + i) % nb]));
+    merged.resize(nvp, RC_MESH_NULL_IDX);
+    merged
+}
+
+// Ear-clipping triangulation of a simple polygon, always cutting the
+// shortest diagonal first. Returns vertex indices, and false if the
+// polygon was too broken to triangulate completely.
+fn triangulate(verts: &[[i32; 3]]) -> (Vec<[usize; 3]>, bool) {
+    let mut indices: Vec<usize> = (0..verts.len()).collect();
+    // Whether the vertex is the tip of an ear that can be clipped
+    let mut ears = vec![false; verts.len()];
+    let mut tris = Vec::new();
+
+    let mut n = indices.len();
+    for i in 0..n {
+        ears[(i + 1) % n] = diagonal(i, (i + 2) % n, verts, &indices, false);
+    }
+
+    while n > 3 {
+        let shortest = |loose: bool, ears: &[bool]| {
+            let mut min_len = -1;
+            let mut mini = None;
+            for i in 0..n {
+                let i1 = (i + 1) % n;
+                let i2 = (i1 + 1) % n;
+                let clippable = if loose {
+                    diagonal(i, i2, verts, &indices[..n], true)
+                } else {
+                    ears[i1]
+                };
+                if clippable {
+                    // Loose diagonals are measured to the vertex after
+                    // the ear, as Recast does
+                    let far = if loose { (i2 + 1) % n } else { i2 };
+                    let (p0, p2) = (&verts[indices[i]], &verts[indices[far]]);
+                    let dx = p2[0] - p0[0];
+                    let dz = p2[2] - p0[2];
+                    let len = dx * dx + dz * dz;
+                    if min_len < 0 || len < min_len {
+                        min_len = len;
+                        mini = Some(i);
+                    }
+                }
+            }
+            mini
+        };
+
+        // Overlapping contour segments can leave no strict ear; retry with
+        // a looser test before giving up
+        let Some(i) = shortest(false, &ears).or_else(|| shortest(true, &ears)) else {
+            return (tris, false);
+        };
+
+        let i1 = (i + 1) % n;
+        let i2 = (i1 + 1) % n;
+        tris.push([indices[i], indices[i1], indices[i2]]);
+
+        // Clip the ear tip and refresh the flags of its neighbours
+        indices.remove(i1);
+        ears.remove(i1);
+        n -= 1;
+        let i1 = if i1 >= n { 0 } else { i1 };
+        let i = (i1 + n - 1) % n;
+        ears[i] = diagonal((i + n - 1) % n, i1, verts, &indices, false);
+        ears[i1] = diagonal(i, (i1 + 1) % n, verts, &indices, false);
+    }
+
+    tris.push([indices[0], indices[1], indices[2]]);
+    (tris, true)
+}
+
+// Whether i-j is a proper internal diagonal of the polygon `indices`.
+// `loose` accepts diagonals touching other edges, to recover from
+// overlapping segments.
+fn diagonal(i: usize, j: usize, verts: &[[i32; 3]], indices: &[usize], loose: bool) -> bool {
+    in_cone(i, j, verts, indices, loose) && diagonalie(i, j, verts, indices, loose)
+}
+
+// i-j doesn't cross any polygon edge not incident to i or j
+fn diagonalie(i: usize, j: usize, verts: &[[i32; 3]], indices: &[usize], loose: bool) -> bool {
+    let n = indices.len();
+    let d0 = &verts[indices[i]][..];
+    let d1 = &verts[indices[j]][..];
+    for k in 0..n {
+        let k1 = (k + 1) % n;
+        if k == i || k1 == i || k == j || k1 == j {
+            continue;
+        }
+        let p0 = &verts[indices[k]][..];
+        let p1 = &verts[indices[k1]][..];
+        if vequal(d0, p0) || vequal(d1, p0) || vequal(d0, p1) || vequal(d1, p1) {
+            continue;
+        }
+        let crosses = if loose {
+            intersect_prop(d0, d1, p0, p1)
+        } else {
+            intersect(d0, d1, p0, p1)
+        };
+        if crosses {
+            return false;
+        }
+    }
+    true
+}
+
+// The diagonal from i to j lies inside the polygon near i
+fn in_cone(i: usize, j: usize, verts: &[[i32; 3]], indices: &[usize], loose: bool) -> bool {
+    let n = indices.len();
+    let pi = &verts[indices[i]][..];
+    let pj = &verts[indices[j]][..];
+    let pi1 = &verts[indices[(i + 1) % n]][..];
+    let pin1 = &verts[indices[(i + n - 1) % n]][..];
+
+    if left_on(pin1, pi, pi1) {
+        // Convex vertex
+        return if loose {
+            left_on(pi, pj, pin1) && left_on(pj, pi, pi1)
+        } else {
+            left(pi, pj, pin1) && left(pj, pi, pi1)
+        };
+    }
+    // Reflex vertex
+    !(left_on(pi, pj, pi1) && left_on(pj, pi, pin1))
+}
+
+// Neighbouring polygon across each edge, RC_MESH_NULL_IDX for open edges
+fn build_mesh_adjacency(polys: &[Vec<u16>], nverts: usize, nvp: usize) -> Vec<Vec<u16>> {
+    let mut neighbours = vec![vec![RC_MESH_NULL_IDX; nvp]; polys.len()];
+
+    // Each interior edge is walked once in each direction; record the
+    // ascending ones and match the descending ones against them
+    let mut edges: HashMap<(u16, u16), (usize, usize)> = HashMap::with_capacity(nverts);
+    for (i, p) in polys.iter().enumerate() {
+        let nv = count_poly_verts(p);
+        for j in 0..nv {
+            let (v0, v1) = (p[j], p[(j + 1) % nv]);
+            if v0 < v1 {
+                edges.insert((v0, v1), (i, j));
+            }
+        }
+    }
+    for (i, p) in polys.iter().enumerate() {
+        let nv = count_poly_verts(p);
+        for j in 0..nv {
+            let (v0, v1) = (p[j], p[(j + 1) % nv]);
+            if v0 > v1 {
+                if let Some(&(pi, pj)) = edges.get(&(v1, v0)) {
+                    if neighbours[pi][pj] == RC_MESH_NULL_IDX {
+                        neighbours[pi][pj] = i as u16;
+                        neighbours[i][j] = pi as u16;
+                    }
+                }
+            }
+        }
+    }
+    neighbours
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compact_heightfield::CompactHeightfield;
+    use crate::contours::build_contours;
+    use crate::debug_draw::RC_WALKABLE_AREA;
+    use crate::heightfield::Heightfield;
+    use crate::regions::build_regions_watershed;
+
+    fn poly_mesh_from_map(map: &[&str], nvp: usize) -> PolyMesh {
+        let (w, h) = (map[0].len() as f32, map.len() as f32);
+        let mut hf = Heightfield::new(Vec3::ZERO, Vec3::new(w, 10.0, h), 1.0, 0.1);
+        for (z, row) in map.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                if c != '#' {
+                    hf.add_span(x as i32, z as i32, 0, 1, RC_WALKABLE_AREA, 1);
+                }
+            }
+        }
+        let mut chf = CompactHeightfield::build(&hf, 10, 2);
+        build_regions_watershed(&mut chf, 0, 1, 0);
+        let mut ctx = BuildContext::new();
+        let cset = build_contours(&chf, 1.3, 0, 0, &mut ctx);
+        PolyMesh::build(&cset, nvp, &mut ctx)
+    }
+
+    #[test]
+    fn test_triangulate_concave() {
+        // An L shape: 6 vertices give 4 triangles covering area 3
+        let verts = [
+            [0, 0, 0],
+            [0, 0, 2],
+            [2, 0, 2],
+            [2, 0, 1],
+            [1, 0, 1],
+            [1, 0, 0],
+        ];
+        let (tris, ok) = triangulate(&verts);
+        assert!(ok);
+        assert_eq!(tris.len(), 4);
+        let area2: i32 = tris
+            .iter()
+            .map(|t| crate::contours::area2(&verts[t[0]], &verts[t[1]], &verts[t[2]]).abs())
+            .sum();
+        assert_eq!(area2, 6);
+    }
+
+    #[test]
+    fn test_square_becomes_one_polygon() {
+        let mesh = poly_mesh_from_map(&["....", "....", "....", "...."], 6);
+        assert_eq!(mesh.polys.len(), 1);
+        assert_eq!(count_poly_verts(&mesh.polys[0]), 4);
+        assert_eq!(mesh.verts.len(), 4);
+        assert_eq!(mesh.areas, vec![RC_WALKABLE_AREA]);
+        assert!(mesh.neighbours[0].iter().all(|&n| n == RC_MESH_NULL_IDX));
+    }
+
+    #[test]
+    fn test_polygons_are_convex_and_connected() {
+        let map = [
+            "..........",
+            "..........",
+            "...####...",
+            "...####...",
+            "..........",
+            "..........",
+        ];
+        for nvp in [3, 6] {
+            let mesh = poly_mesh_from_map(&map, nvp);
+            assert!(mesh.polys.len() > 1);
+            let v = |i: u16| {
+                let p = mesh.verts[i as usize];
+                [p.x as i32, p.y as i32, p.z as i32]
+            };
+
+            for (i, p) in mesh.polys.iter().enumerate() {
+                let nv = count_poly_verts(p);
+                assert!((3..=nvp).contains(&nv));
+                for j in 0..nv {
+                    let (a, b, c) = (v(p[j]), v(p[(j + 1) % nv]), v(p[(j + 2) % nv]));
+                    assert!(left_on(&a, &b, &c), "polygon {} is not convex", i);
+                }
+                // Links are symmetric
+                for (j, &n) in mesh.neighbours[i][..nv].iter().enumerate() {
+                    if n != RC_MESH_NULL_IDX {
+                        assert!(
+                            mesh.neighbours[n as usize].contains(&(i as u16)),
+                            "{} {}",
+                            i,
+                            j
+                        );
+                    }
+                }
+            }
+
+            // Every polygon is reachable from the first through its links
+            let mut seen = vec![false; mesh.polys.len()];
+            let mut stack = vec![0];
+            while let Some(i) = stack.pop() {
+                if std::mem::replace(&mut seen[i], true) {
+                    continue;
+                }
+                stack.extend(
+                    mesh.neighbours[i]
+                        .iter()
+                        .filter(|&&n| n != RC_MESH_NULL_IDX)
+                        .map(|&n| n as usize),
+                );
+            }
+            assert!(seen.iter().all(|&s| s));
+        }
+    }
+}
@@ -0,0 +1,845 @@
+use std::collections::VecDeque;
+
+use glam::Vec3;
+
+use crate::build_context::BuildContext;
+use crate::compact_heightfield::CompactHeightfield;
+use crate::debug_draw::{PolyMesh, PolyMeshDetail, RC_MESH_NULL_IDX};
+use crate::poly_mesh::RC_MULTIPLE_REGS;
+
+// Height patch cell not reached by any span of the polygon
+const RC_UNSET_HEIGHT: u16 = 0xffff;
+
+const MAX_VERTS: usize = 127;
+// Delaunay gives at most 2n - 2 - k triangles for n verts, k on the hull
+const MAX_TRIS: usize = 255;
+const MAX_VERTS_PER_EDGE: usize = 32;
+
+// Edge face markers used while building the Delaunay triangulation
+const EV_UNDEF: i32 = -1;
+const EV_HULL: i32 = -2;
+
+// Floor heights of the compact heightfield under one polygon's bounds
+struct HeightPatch {
+    xmin: i32,
+    zmin: i32,
+    width: i32,
+    height: i32,
+    data: Vec<u16>,
+}
+
+// `PolyMeshDetail::build` for callers without a build context of their
+// own, like Recast's rcBuildPolyMeshDetail. Warnings are dropped.
+pub fn build_poly_mesh_detail(
+    pmesh: &PolyMesh,
+    chf: &CompactHeightfield,
+    sample_dist: f32,
+    sample_max_error: f32,
+) -> PolyMeshDetail {
+    PolyMeshDetail::build(
+        pmesh,
+        chf,
+        sample_dist,
+        sample_max_error,
+        &mut BuildContext::new(),
+    )
+}
+
+impl PolyMeshDetail {
+    // Sample the compact heightfield under every polygon of `mesh` and
+    // triangulate the samples, adding points until the surface is within
+    // `sample_max_error` of the heightfield. Both distances are in world
+    // units; a `sample_dist` of 0 keeps just the polygon corners.
+    // Polygons cut short of triangles are logged to `ctx`.
+    pub fn build(
+        mesh: &PolyMesh,
+        chf: &CompactHeightfield,
+        sample_dist: f32,
+        sample_max_error: f32,
+        ctx: &mut BuildContext,
+    ) -> PolyMeshDetail {
+        let mut dmesh = PolyMeshDetail::default();
+        if mesh.verts.is_empty() || mesh.polys.is_empty() {
+            return dmesh;
+        }
+
+        let (cs, ch) = (mesh.cs, mesh.ch);
+        let orig = mesh.bmin;
+        let border_size = mesh.border_size;
+        let height_search_radius = (mesh.max_edge_error.ceil() as i32).max(1);
+
+        for (i, p) in mesh.polys.iter().enumerate() {
+            let nv = p
+                .iter()
+                .position(|&v| v == RC_MESH_NULL_IDX)
+                .unwrap_or(p.len());
+
+            // Polygon corners relative to the mesh origin
+            let poly: Vec<Vec3> = p[..nv]
+                .iter()
+                .map(|&v| {
+                    let v = mesh.verts[v as usize];
+                    Vec3::new(v.x * cs, v.y * ch, v.z * cs)
+                })
+                .collect();
+
+            let hp = height_patch(chf, mesh, p, nv, mesh.regs[i], border_size);
+            let (verts, tris) = build_poly_detail(
+                &poly,
+                sample_dist,
+                sample_max_error,
+                height_search_radius,
+                chf,
+                &hp,
+                ctx,
+            );
+
+            dmesh.meshes.push([
+                dmesh.verts.len() as u32,
+                verts.len() as u32,
+                dmesh.tris.len() as u32,
+                tris.len() as u32,
+            ]);
+            // Detail vertices sit on top of the spans, like the polygons do
+            let offset = orig + Vec3::new(0.0, ch, 0.0);
+            dmesh.verts.extend(verts.iter().map(|&v| v + offset));
+            dmesh.tris.extend(tris.iter().map(|t| {
+                let flags = tri_edge_flags(&verts, t, &poly);
+                [t[0] as u8, t[1] as u8, t[2] as u8, flags]
+            }));
+        }
+
+        dmesh
+    }
+}
+
+// Copy the heights of the polygon's region under its bounds, then flood
+// outwards so cells covered by neighbouring regions get a height too
+fn height_patch(
+    chf: &CompactHeightfield,
+    mesh: &PolyMesh,
+    p: &[u16],
+    nv: usize,
+    region: u16,
+    border_size: i32,
+) -> HeightPatch {
+    let (mut xmin, mut xmax) = (chf.width, 0);
+    let (mut zmin, mut zmax) = (chf.height, 0);
+    for &v in &p[..nv] {
+        let v = mesh.verts[v as usize];
+        xmin = xmin.min(v.x as i32);
+        xmax = xmax.max(v.x as i32);
+        zmin = zmin.min(v.z as i32);
+        zmax = zmax.max(v.z as i32);
+    }
+    // Pad by a cell so samples on the far edges have data
+    xmin = (xmin - 1).max(0);
+    zmin = (zmin - 1).max(0);
+    xmax = (xmax + 1).min(chf.width - border_size * 2);
+    zmax = (zmax + 1).min(chf.height - border_size * 2);
+
+    let width = (xmax - xmin).max(1);
+    let height = (zmax - zmin).max(1);
+    let mut data = vec![RC_UNSET_HEIGHT; (width * height) as usize];
+
+    let mut queue = VecDeque::new();
+    let patch_index = |x: i32, z: i32| {
+        let (hx, hz) = (x - border_size - xmin, z - border_size - zmin);
+        (hx >= 0 && hz >= 0 && hx < width && hz < height).then_some((hx + hz * width) as usize)
+    };
+
+    if region != RC_MULTIPLE_REGS {
+        for hz in 0..height {
+            for hx in 0..width {
+                let (x, z) = (xmin + hx + border_size, zmin + hz + border_size);
+                if let Some(i) = chf.span_range(x, z).find(|&i| chf.spans[i].reg == region) {
+                    data[(hx + hz * width) as usize] = chf.spans[i].y;
+                    queue.push_back((x, z, i));
+                }
+            }
+        }
+    }
+
+    // No span of the region under the polygon: start from the span closest
+    // in height below its centre
+    if queue.is_empty() {
+        let centre = p[..nv]
+            .iter()
+            .map(|&v| mesh.verts[v as usize])
+            .sum::<Vec3>()
+            / nv as f32;
+        let x = (centre.x as i32).clamp(xmin, xmin + width - 1) + border_size;
+        let z = (centre.z as i32).clamp(zmin, zmin + height - 1) + border_size;
+        let closest = chf
+            .span_range(x, z)
+            .min_by_key(|&i| (chf.spans[i].y as i32 - centre.y as i32).abs());
+        if let Some(i) = closest {
+            data[patch_index(x, z).unwrap()] = chf.spans[i].y;
+            queue.push_back((x, z, i));
+        }
+    }
+
+    while let Some((x, z, i)) = queue.pop_front() {
+        for dir in 0..4 {
+            let Some((ax, az, ai)) = chf.neighbour(x, z, i, dir) else {
+                continue;
+            };
+            let Some(hi) = patch_index(ax, az) else {
+                continue;
+            };
+            if data[hi] != RC_UNSET_HEIGHT {
+                continue;
+            }
+            data[hi] = chf.spans[ai].y;
+            queue.push_back((ax, az, ai));
+        }
+    }
+
+    HeightPatch {
+        xmin,
+        zmin,
+        width,
+        height,
+        data,
+    }
+}
+
+// Floor height in cell units at (fx, fz), searching outwards up to `radius`
+// cells when the patch has no data there
+fn get_height(fx: f32, fy: f32, fz: f32, cs: f32, ch: f32, radius: i32, hp: &HeightPatch) -> u16 {
+    let ix = ((fx / cs + 0.01).floor() as i32 - hp.xmin).clamp(0, hp.width - 1);
+    let iz = ((fz / cs + 0.01).floor() as i32 - hp.zmin).clamp(0, hp.height - 1);
+    let h = hp.data[(ix + iz * hp.width) as usize];
+    if h != RC_UNSET_HEIGHT {
+        return h;
+    }
+
+    // Nearest ring with data, picking the height closest to the query
+    for r in 1..=radius {
+        let mut best: Option<u16> = None;
+        for dz in -r..=r {
+            for dx in -r..=r {
+                if dx.abs() != r && dz.abs() != r {
+                    continue;
+                }
+                let (nx, nz) = (ix + dx, iz + dz);
+                if nx < 0 || nz < 0 || nx >= hp.width || nz >= hp.height {
+                    continue;
+                }
+                let nh = hp.data[(nx + nz * hp.width) as usize];
+                if nh == RC_UNSET_HEIGHT {
+                    continue;
+                }
+                let dy = |h: u16| (h as f32 * ch - fy).abs();
+                if best.is_none_or(|b| dy(nh) < dy(b)) {
+                    best = Some(nh);
+                }
+            }
+        }
+        if let Some(h) = best {
+            return h;
+        }
+    }
+    h
+}
+
+// Detail vertices and triangles for one polygon, relative to the mesh
+// origin. The first vertices are the polygon's own corners.
+fn build_poly_detail(
+    poly: &[Vec3],
+    sample_dist: f32,
+    sample_max_error: f32,
+    height_search_radius: i32,
+    chf: &CompactHeightfield,
+    hp: &HeightPatch,
+    ctx: &mut BuildContext,
+) -> (Vec<Vec3>, Vec<[usize; 3]>) {
+    let (cs, ch) = (chf.cs, chf.ch);
+    let nin = poly.len();
+    let mut verts = poly.to_vec();
+    let mut hull = Vec::with_capacity(MAX_VERTS);
+
+    let min_extent = poly_min_extent(&verts);
+
+    // Tessellate the outline, keeping edge samples that deviate from the
+    // straight edge by more than the allowed error
+    if sample_dist > 0.0 {
+        let mut edge = Vec::with_capacity(MAX_VERTS_PER_EDGE + 1);
+        for i in 0..nin {
+            let j = (i + nin - 1) % nin;
+            // Shared edges must be sampled in the same direction from
+            // both sides or there will be seams
+            let (mut vj, mut vi) = (poly[j], poly[i]);
+            let swapped = if (vj.x - vi.x).abs() < 1e-6 {
+                vj.z > vi.z
+            } else {
+                vj.x > vi.x
+            };
+            if swapped {
+                std::mem::swap(&mut vj, &mut vi);
+            }
+
+            let delta = vi - vj;
+            let d = (delta.x * delta.x + delta.z * delta.z).sqrt();
+            let mut nn = 1 + (d / sample_dist).floor() as usize;
+            nn = nn.min(MAX_VERTS_PER_EDGE - 1);
+            if verts.len() + nn >= MAX_VERTS {
+                nn = (MAX_VERTS - 1).saturating_sub(verts.len()).max(1);
+            }
+
+            edge.clear();
+            for k in 0..=nn {
+                let mut pos = vj + delta * (k as f32 / nn as f32);
+                pos.y =
+                    get_height(pos.x, pos.y, pos.z, cs, ch, height_search_radius, hp) as f32 * ch;
+                edge.push(pos);
+            }
+
+            let mut idx = vec![0, nn];
+            let mut k = 0;
+            while k + 1 < idx.len() {
+                let (a, b) = (idx[k], idx[k + 1]);
+                let mut maxd = 0.0;
+                let mut maxi = None;
+                for m in a + 1..b {
+                    let dev = distance_pt_seg(edge[m], edge[a], edge[b]);
+                    if dev > maxd {
+                        maxd = dev;
+                        maxi = Some(m);
+                    }
+                }
+                match maxi {
+                    Some(m) if maxd > sample_max_error * sample_max_error => idx.insert(k + 1, m),
+                    _ => k += 1,
+                }
+            }
+
+            hull.push(j);
+            let inner = &idx[1..idx.len() - 1];
+            let mut add = |k: &usize| {
+                hull.push(verts.len());
+                verts.push(edge[*k]);
+            };
+            if swapped {
+                inner.iter().rev().for_each(&mut add);
+            } else {
+                inner.iter().for_each(&mut add);
+            }
+        }
+    } else {
+        hull.extend(0..nin);
+    }
+
+    let mut tris = triangulate_hull(&verts, &hull, nin);
+
+    // Slivers and small polygons get no interior points
+    if min_extent < sample_dist * 2.0 || tris.is_empty() {
+        return (verts, tris);
+    }
+
+    if sample_dist > 0.0 {
+        let (bmin, bmax) = poly.iter().fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), &v| (min.min(v), max.max(v)),
+        );
+        let x0 = (bmin.x / sample_dist).floor() as i32;
+        let x1 = (bmax.x / sample_dist).ceil() as i32;
+        let z0 = (bmin.z / sample_dist).floor() as i32;
+        let z1 = (bmax.z / sample_dist).ceil() as i32;
+
+        // Grid points not too close to the polygon edges, with whether
+        // they've been added yet
+        let mut samples: Vec<([i32; 3], bool)> = Vec::new();
+        for z in z0..z1 {
+            for x in x0..x1 {
+                let pt = Vec3::new(
+                    x as f32 * sample_dist,
+                    (bmax.y + bmin.y) * 0.5,
+                    z as f32 * sample_dist,
+                );
+                if dist_to_poly(poly, pt) > -sample_dist / 2.0 {
+                    continue;
+                }
+                let y = get_height(pt.x, pt.y, pt.z, cs, ch, height_search_radius, hp);
+                samples.push(([x, y as i32, z], false));
+            }
+        }
+
+        // Add the sample with the largest error until all are within the
+        // threshold
+        for _ in 0..samples.len() {
+            if verts.len() >= MAX_VERTS {
+                break;
+            }
+
+            let mut bestd = 0.0;
+            let mut best = None;
+            for (i, &(s, added)) in samples.iter().enumerate() {
+                if added {
+                    continue;
+                }
+                // Jitter the grid so symmetric data doesn't produce
+                // degenerate triangulations
+                let pt = Vec3::new(
+                    s[0] as f32 * sample_dist + jitter_x(i) * cs * 0.1,
+                    s[1] as f32 * ch,
+                    s[2] as f32 * sample_dist + jitter_z(i) * cs * 0.1,
+                );
+                let Some(d) = dist_to_tri_mesh(pt, &verts, &tris) else {
+                    continue;
+                };
+                if d > bestd {
+                    bestd = d;
+                    best = Some((i, pt));
+                }
+            }
+            let Some((i, pt)) = best.filter(|_| bestd > sample_max_error) else {
+                break;
+            };
+
+            samples[i].1 = true;
+            verts.push(pt);
+            tris = delaunay_hull(&verts, &hull);
+        }
+    }
+
+    if tris.len() > MAX_TRIS {
+        ctx.warn(format!(
+            "PolyMeshDetail::build: shrinking triangle count from {} to {}",
+            tris.len(),
+            MAX_TRIS
+        ));
+        tris.truncate(MAX_TRIS);
+    }
+    (verts, tris)
+}
+
+fn jitter_x(i: usize) -> f32 {
+    ((i as u32).wrapping_mul(0x8da6b343) & 0xffff) as f32 / 65535.0 * 2.0 - 1.0
+}
+
+fn jitter_z(i: usize) -> f32 {
+    ((i as u32).wrapping_mul(0xd8163841) & 0xffff) as f32 / 65535.0 * 2.0 - 1.0
+}
+
+// Twice the signed XZ area of triangle p1-p2-p3
+fn vcross2(p1: Vec3, p2: Vec3, p3: Vec3) -> f32 {
+    let (u1, v1) = (p2.x - p1.x, p2.z - p1.z);
+    let (u2, v2) = (p3.x - p1.x, p3.z - p1.z);
+    u1 * v2 - v1 * u2
+}
+
+fn vdist2(a: Vec3, b: Vec3) -> f32 {
+    (a.x - b.x).hypot(a.z - b.z)
+}
+
+// Squared 3D distance from `pt` to segment p-q
+fn distance_pt_seg(pt: Vec3, p: Vec3, q: Vec3) -> f32 {
+    let pq = q - p;
+    let d = pq.length_squared();
+    let mut t = pq.dot(pt - p);
+    if d > 0.0 {
+        t /= d;
+    }
+    (p + pq * t.clamp(0.0, 1.0) - pt).length_squared()
+}
+
+// Squared XZ distance from `pt` to segment p-q
+fn distance_pt_seg_2d(pt: Vec3, p: Vec3, q: Vec3) -> f32 {
+    let (pqx, pqz) = (q.x - p.x, q.z - p.z);
+    let (dx, dz) = (pt.x - p.x, pt.z - p.z);
+    let d = pqx * pqx + pqz * pqz;
+    let mut t = pqx * dx + pqz * dz;
+    if d > 0.0 {
+        t /= d;
+    }
+    let t = t.clamp(0.0, 1.0);
+    let (dx, dz) = (p.x + t * pqx - pt.x, p.z + t * pqz - pt.z);
+    dx * dx + dz * dz
+}
+
+// Squared XZ distance to the polygon outline, negative inside
+fn dist_to_poly(poly: &[Vec3], p: Vec3) -> f32 {
+    let mut dmin = f32::MAX;
+    let mut inside = false;
+    let n = poly.len();
+    for i in 0..n {
+        let (vi, vj) = (poly[i], poly[(i + n - 1) % n]);
+        if (vi.z > p.z) != (vj.z > p.z) && p.x < (vj.x - vi.x) * (p.z - vi.z) / (vj.z - vi.z) + vi.x
+        {
+            inside = !inside;
+        }
+        dmin = dmin.min(distance_pt_seg_2d(p, vj, vi));
+    }
+    if inside {
+        -dmin
+    } else {
+        dmin
+    }
+}
+
+// Vertical distance from `p` to the triangle below or above it, if any
+fn dist_pt_tri(p: Vec3, a: Vec3, b: Vec3, c: Vec3) -> Option<f32> {
+    let v0 = c - a;
+    let v1 = b - a;
+    let v2 = p - a;
+    let dot2 = |u: Vec3, v: Vec3| u.x * v.x + u.z * v.z;
+    let (dot00, dot01, dot02) = (dot2(v0, v0), dot2(v0, v1), dot2(v0, v2));
+    let (dot11, dot12) = (dot2(v1, v1), dot2(v1, v2));
+
+    let inv_denom = 1.0 / (dot00 * dot11 - dot01 * dot01);
+    let u = (dot11 * dot02 - dot01 * dot12) * inv_denom;
+    let v = (dot00 * dot12 - dot01 * dot02) * inv_denom;
+
+    const EPS: f32 = 1e-4;
+    (u >= -EPS && v >= -EPS && u + v <= 1.0 + EPS).then(|| {
+        let y = a.y + v0.y * u + v1.y * v;
+        (y - p.y).abs()
+    })
+}
+
+fn dist_to_tri_mesh(p: Vec3, verts: &[Vec3], tris: &[[usize; 3]]) -> Option<f32> {
+    tris.iter()
+        .filter_map(|t| dist_pt_tri(p, verts[t[0]], verts[t[1]], verts[t[2]]))
+        .min_by(f32::total_cmp)
+}
+
+// Smallest width of the polygon across any of its edges
+fn poly_min_extent(verts: &[Vec3]) -> f32 {
+    let n = verts.len();
+    let mut min_dist = f32::MAX;
+    for i in 0..n {
+        let ni = (i + 1) % n;
+        let max_edge_dist = (0..n)
+            .filter(|&j| j != i && j != ni)
+            .map(|j| distance_pt_seg_2d(verts[j], verts[i], verts[ni]))
+            .fold(0.0, f32::max);
+        min_dist = min_dist.min(max_edge_dist);
+    }
+    min_dist.sqrt()
+}
+
+// Triangulate the hull without interior points, starting from the ear with
+// the shortest perimeter and then advancing whichever side makes the
+// shorter triangle. Handles tessellated straight edges well.
+fn triangulate_hull(verts: &[Vec3], hull: &[usize], nin: usize) -> Vec<[usize; 3]> {
+    let nhull = hull.len();
+    let next = |i: usize| (i + 1) % nhull;
+    let prev = |i: usize| (i + nhull - 1) % nhull;
+
+    let (mut start, mut left, mut right) = (0, 1, nhull - 1);
+    let mut dmin = f32::MAX;
+    for i in 0..nhull {
+        // Only original corners can be ear tips; edge samples are flat
+        if hull[i] >= nin {
+            continue;
+        }
+        let (pi, ni) = (prev(i), next(i));
+        let (pv, cv, nv) = (verts[hull[pi]], verts[hull[i]], verts[hull[ni]]);
+        let d = vdist2(pv, cv) + vdist2(cv, nv) + vdist2(nv, pv);
+        if d < dmin {
+            (start, left, right) = (i, ni, pi);
+            dmin = d;
+        }
+    }
+
+    let mut tris = vec![[hull[start], hull[left], hull[right]]];
+    while next(left) != right {
+        let (nleft, nright) = (next(left), prev(right));
+        let (cvleft, nvleft) = (verts[hull[left]], verts[hull[nleft]]);
+        let (cvright, nvright) = (verts[hull[right]], verts[hull[nright]]);
+        let dleft = vdist2(cvleft, nvleft) + vdist2(nvleft, cvright);
+        let dright = vdist2(cvright, nvright) + vdist2(cvleft, nvright);
+
+        if dleft < dright {
+            tris.push([hull[left], hull[nleft], hull[right]]);
+            left = nleft;
+        } else {
+            tris.push([hull[left], hull[nright], hull[right]]);
+            right = nright;
+        }
+    }
+    tris
+}
+
+// Edge of the Delaunay triangulation: endpoints and the faces to its left
+// and right, EV_UNDEF until found or EV_HULL outside the polygon
+#[derive(Clone, Copy)]
+struct Edge {
+    s: usize,
+    t: usize,
+    left: i32,
+    right: i32,
+}
+
+fn find_edge(edges: &[Edge], s: usize, t: usize) -> Option<usize> {
+    edges
+        .iter()
+        .position(|e| (e.s == s && e.t == t) || (e.s == t && e.t == s))
+}
+
+fn update_left_face(e: &mut Edge, s: usize, t: usize, f: i32) {
+    if e.s == s && e.t == t && e.left == EV_UNDEF {
+        e.left = f;
+    } else if e.t == s && e.s == t && e.right == EV_UNDEF {
+        e.right = f;
+    }
+}
+
+fn overlap_seg_seg_2d(a: Vec3, b: Vec3, c: Vec3, d: Vec3) -> bool {
+    let a1 = vcross2(a, b, d);
+    let a2 = vcross2(a, b, c);
+    if a1 * a2 < 0.0 {
+        let a3 = vcross2(c, d, a);
+        let a4 = a3 + a2 - a1;
+        return a3 * a4 < 0.0;
+    }
+    false
+}
+
+fn overlap_edges(pts: &[Vec3], edges: &[Edge], s1: usize, t1: usize) -> bool {
+    edges.iter().any(|e| {
+        // Edges sharing a vertex don't overlap
+        let connected = e.s == s1 || e.s == t1 || e.t == s1 || e.t == t1;
+        !connected && overlap_seg_seg_2d(pts[e.s], pts[e.t], pts[s1], pts[t1])
+    })
+}
+
+// Centre and XZ radius of the circle through three points
+fn circum_circle(p1: Vec3, p2: Vec3, p3: Vec3) -> (Vec3, f32) {
+    // Relative to p1 for precision
+    let v2 = p2 - p1;
+    let v3 = p3 - p1;
+    let cp = vcross2(Vec3::ZERO, v2, v3);
+    if cp.abs() > 1e-6 {
+        let v2_sq = v2.x * v2.x + v2.z * v2.z;
+        let v3_sq = v3.x * v3.x + v3.z * v3.z;
+        let c = Vec3::new(
+            (v2_sq * v3.z - v3_sq * v2.z) / (2.0 * cp),
+            0.0,
+            (v3_sq * v2.x - v2_sq * v3.x) / (2.0 * cp),
+        );
+        let r = vdist2(c, Vec3::ZERO);
+        return (c + p1, r);
+    }
+    (p1, 0.0)
+}
+
+// Find the point left of edge `e` whose circumcircle with it contains no
+// other point, and add the triangle
+fn complete_facet(pts: &[Vec3], edges: &mut Vec<Edge>, nfaces: &mut i32, e: usize) {
+    const EPS: f32 = 1e-5;
+
+    let edge = edges[e];
+    let (s, t) = if edge.left == EV_UNDEF {
+        (edge.s, edge.t)
+    } else if edge.right == EV_UNDEF {
+        (edge.t, edge.s)
+    } else {
+        return;
+    };
+
+    let mut pt = None;
+    let mut c = Vec3::ZERO;
+    let mut r = -1.0;
+    for u in 0..pts.len() {
+        if u == s || u == t || vcross2(pts[s], pts[t], pts[u]) <= EPS {
+            continue;
+        }
+        if r < 0.0 {
+            pt = Some(u);
+            (c, r) = circum_circle(pts[s], pts[t], pts[u]);
+            continue;
+        }
+        let d = vdist2(c, pts[u]);
+        const TOL: f32 = 0.001;
+        if d > r * (1.0 + TOL) {
+            // Outside the current circle
+            continue;
+        } else if d >= r * (1.0 - TOL) {
+            // On the circle: only take it if the new edges don't cross
+            // existing ones
+            if overlap_edges(pts, edges, s, u) || overlap_edges(pts, edges, t, u) {
+                continue;
+            }
+        }
+        pt = Some(u);
+        (c, r) = circum_circle(pts[s], pts[t], pts[u]);
+    }
+
+    let Some(pt) = pt else {
+        update_left_face(&mut edges[e], s, t, EV_HULL);
+        return;
+    };
+
+    update_left_face(&mut edges[e], s, t, *nfaces);
+    for (a, b) in [(pt, s), (t, pt)] {
+        match find_edge(edges, a, b) {
+            Some(e) => update_left_face(&mut edges[e], a, b, *nfaces),
+            None => edges.push(Edge {
+                s: a,
+                t: b,
+                left: *nfaces,
+                right: EV_UNDEF,
+            }),
+        }
+    }
+    *nfaces += 1;
+}
+
+// Delaunay triangulation of `pts` bounded by the polygon `hull`
+fn delaunay_hull(pts: &[Vec3], hull: &[usize]) -> Vec<[usize; 3]> {
+    let max_edges = pts.len() * 10;
+    let mut edges = Vec::with_capacity(max_edges);
+    let nhull = hull.len();
+    for i in 0..nhull {
+        let j = (i + nhull - 1) % nhull;
+        edges.push(Edge {
+            s: hull[j],
+            t: hull[i],
+            left: EV_HULL,
+            right: EV_UNDEF,
+        });
+    }
+
+    let mut nfaces = 0;
+    let mut current = 0;
+    while current < edges.len() && edges.len() < max_edges {
+        if edges[current].left == EV_UNDEF {
+            complete_facet(pts, &mut edges, &mut nfaces, current);
+        }
+        if edges[current].right == EV_UNDEF {
+            complete_facet(pts, &mut edges, &mut nfaces, current);
+        }
+        current += 1;
+    }
+
+    // Collect each face's corners from its edges
+    let mut tris = vec![[usize::MAX; 3]; nfaces as usize];
+    for e in &edges {
+        if e.right >= 0 {
+            let t = &mut tris[e.right as usize];
+            if t[0] == usize::MAX {
+                (t[0], t[1]) = (e.s, e.t);
+            } else if t[0] == e.t {
+                t[2] = e.s;
+            } else if t[1] == e.s {
+                t[2] = e.t;
+            }
+        }
+        if e.left >= 0 {
+            let t = &mut tris[e.left as usize];
+            if t[0] == usize::MAX {
+                (t[0], t[1]) = (e.t, e.s);
+            } else if t[0] == e.s {
+                t[2] = e.t;
+            } else if t[1] == e.t {
+                t[2] = e.s;
+            }
+        }
+    }
+
+    // Faces left incomplete by a failed triangulation are dropped
+    tris.retain(|t| !t.contains(&usize::MAX));
+    tris
+}
+
+// Flags for edges of detail triangle `t` lying on the polygon outline, in
+// the layout read by `detail_edge_flags`
+fn tri_edge_flags(verts: &[Vec3], t: &[usize; 3], poly: &[Vec3]) -> u8 {
+    const THR_SQR: f32 = 0.001 * 0.001;
+    let on_outline = |va: Vec3, vb: Vec3| {
+        let n = poly.len();
+        (0..n).any(|i| {
+            let (p, q) = (poly[(i + n - 1) % n], poly[i]);
+            distance_pt_seg_2d(va, p, q) < THR_SQR && distance_pt_seg_2d(vb, p, q) < THR_SQR
+        })
+    };
+    (0..3)
+        .filter(|&k| on_outline(verts[t[k]], verts[t[(k + 1) % 3]]))
+        .fold(0, |flags, k| flags | 1 << (k * 2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contours::build_contours;
+    use crate::debug_draw::RC_WALKABLE_AREA;
+    use crate::heightfield::Heightfield;
+    use crate::regions::build_regions_watershed;
+
+    // A 12x12 floor whose height rises by `rise` cells per column
+    fn ramp(rise: i32) -> (PolyMesh, CompactHeightfield) {
+        let mut hf = Heightfield::new(Vec3::ZERO, Vec3::new(12.0, 20.0, 12.0), 1.0, 0.1);
+        for z in 0..12 {
+            for x in 0..12 {
+                let top = 1 + x * rise;
+                hf.add_span(x, z, 0, top as u16, RC_WALKABLE_AREA, 1);
+            }
+        }
+        let mut chf = CompactHeightfield::build(&hf, 10, 4);
+        build_regions_watershed(&mut chf, 0, 1, 0);
+        let mut ctx = BuildContext::new();
+        let cset = build_contours(&chf, 1.3, 0, 0, &mut ctx);
+        (PolyMesh::build(&cset, 6, &mut ctx), chf)
+    }
+
+    #[test]
+    fn test_flat_floor_keeps_polygon() {
+        let (mesh, chf) = ramp(0);
+        let dmesh = build_poly_mesh_detail(&mesh, &chf, 3.0, 0.1);
+        assert_eq!(dmesh.meshes.len(), mesh.polys.len());
+
+        // A flat polygon needs no extra points inside
+        for m in &dmesh.meshes {
+            assert_eq!(m[3], m[1] - 2);
+        }
+        // Every triangle has at least one edge on the polygon outline
+        assert!(dmesh.tris.iter().all(|t| t[3] != 0));
+        let y = mesh.bmin.y + 2.0 * mesh.ch;
+        assert!(dmesh.verts.iter().all(|v| (v.y - y).abs() < 1e-5));
+    }
+
+    #[test]
+    fn test_detail_follows_ramp() {
+        let (mesh, chf) = ramp(4);
+        let dmesh = PolyMeshDetail::build(&mesh, &chf, 1.0, 0.05, &mut BuildContext::new());
+
+        // The polygon itself is flat at its corner heights, the detail mesh
+        // matches the floor in the middle
+        for x in [3.5f32, 6.5, 8.5] {
+            let pos = Vec3::new(x, 0.0, 6.5);
+            let floor = (1.0 + x.floor() * 4.0 + 1.0) * mesh.ch;
+            let h = (0..dmesh.meshes.len())
+                .find_map(|i| dmesh.height(i, pos))
+                .unwrap();
+            assert!(
+                (h - floor).abs() <= 4.0 * mesh.ch,
+                "height at x={} was {}",
+                x,
+                h
+            );
+        }
+    }
+
+    #[test]
+    fn test_delaunay_covers_hull() {
+        let hull_pts = [
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 4.0),
+            Vec3::new(4.0, 0.0, 4.0),
+            Vec3::new(4.0, 0.0, 0.0),
+        ];
+        let mut pts = hull_pts.to_vec();
+        pts.push(Vec3::new(1.0, 0.0, 1.3));
+        pts.push(Vec3::new(2.7, 0.0, 2.1));
+        let tris = delaunay_hull(&pts, &[0, 1, 2, 3]);
+        assert_eq!(tris.len(), 2 * pts.len() - 2 - 4);
+
+        let area: f32 = tris
+            .iter()
+            .map(|t| vcross2(pts[t[0]], pts[t[1]], pts[t[2]]).abs() * 0.5)
+            .sum();
+        assert!((area - 16.0).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,247 @@
+use glam::{Quat, Vec3};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::bake_config::BakeConfig;
+use crate::convex_volume::ConvexVolume;
+use crate::json::{number, vec3, Json, JsonParser};
+use crate::offmesh::OffMeshConnection;
+
+pub const PROJECT_EXTENSION: &str = "navproj";
+
+// A saved camera view
+#[derive(Debug, Clone, PartialEq)]
+pub struct CameraBookmark {
+    pub name: String,
+    pub position: Vec3,
+    pub rotation: Quat,
+    // Point orbited around in the orbit camera mode
+    pub focus: Vec3,
+}
+
+// Everything needed to pick a tuning session back up: the meshes, bake
+// settings, authored volumes and connections, and saved camera views.
+// Stored as JSON; mesh paths are relative to the project file when they
+// are below its directory.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Project {
+    pub meshes: Vec<PathBuf>,
+    pub bake_config: BakeConfig,
+    pub convex_volumes: Vec<ConvexVolume>,
+    pub off_mesh_connections: Vec<OffMeshConnection>,
+    pub bookmarks: Vec<CameraBookmark>,
+}
+
+impl Project {
+    // Mesh paths are written relative to `dir` where possible
+    pub fn to_json(&self, dir: &Path) -> String {
+        let meshes = self
+            .meshes
+            .iter()
+            .map(|path| {
+                let path = path.strip_prefix(dir).unwrap_or(path);
+                Json::String(path.to_string_lossy().into_owned())
+            })
+            .collect();
+        let volumes = self
+            .convex_volumes
+            .iter()
+            .map(|vol| {
+                Json::Object(vec![
+                    (
+                        "verts".into(),
+                        Json::Array(vol.verts.iter().map(|&v| vec3(v)).collect()),
+                    ),
+                    ("hmin".into(), number(vol.hmin)),
+                    ("hmax".into(), number(vol.hmax)),
+                    ("area".into(), Json::Number(vol.area as f64)),
+                ])
+            })
+            .collect();
+        let connections = self
+            .off_mesh_connections
+            .iter()
+            .map(|con| {
+                Json::Object(vec![
+                    ("start".into(), vec3(con.start)),
+                    ("end".into(), vec3(con.end)),
+                    ("radius".into(), number(con.radius)),
+                    ("bidirectional".into(), Json::Bool(con.bidirectional)),
+                    ("area".into(), Json::Number(con.area as f64)),
+                ])
+            })
+            .collect();
+        let bookmarks = self
+            .bookmarks
+            .iter()
+            .map(|mark| {
+                Json::Object(vec![
+                    ("name".into(), Json::String(mark.name.clone())),
+                    ("position".into(), vec3(mark.position)),
+                    (
+                        "rotation".into(),
+                        Json::Array(mark.rotation.to_array().map(number).to_vec()),
+                    ),
+                    ("focus".into(), vec3(mark.focus)),
+                ])
+            })
+            .collect();
+
+        let project = Json::Object(vec![
+            ("meshes".into(), Json::Array(meshes)),
+            ("bake_config".into(), self.bake_config.to_json_value()),
+            ("convex_volumes".into(), Json::Array(volumes)),
+            ("off_mesh_connections".into(), Json::Array(connections)),
+            ("bookmarks".into(), Json::Array(bookmarks)),
+        ]);
+        let mut text = String::new();
+        project.write(&mut text, 0);
+        text.push('\n');
+        text
+    }
+
+    // Relative mesh paths are resolved against `dir`. Missing sections are
+    // left empty or at their defaults.
+    pub fn from_json(text: &str, dir: &Path) -> Result<Self, String> {
+        let json = JsonParser::parse(text)?;
+        let mut project = Project::default();
+
+        for mesh in json.array("meshes")? {
+            let path = PathBuf::from(mesh.as_str("mesh path")?);
+            project.meshes.push(if path.is_relative() {
+                dir.join(path)
+            } else {
+                path
+            });
+        }
+        if let Some(config) = json.get("bake_config") {
+            project.bake_config = BakeConfig::from_json_value(config)?;
+        }
+        // Older projects keep the slope beside the bake config
+        let in_config = json
+            .get("bake_config")
+            .and_then(|config| config.get("walkable_slope_angle"));
+        if let (Some(angle), None) = (json.get("walkable_slope_angle"), in_config) {
+            project.bake_config.walkable_slope_angle = angle.as_f32("walkable_slope_angle")?;
+        }
+        for vol in json.array("convex_volumes")? {
+            let verts = vol
+                .array("verts")?
+                .iter()
+                .map(|v| v.as_vec3("volume vertex"))
+                .collect::<Result<Vec<_>, _>>()?;
+            if verts.len() < 3 {
+                return Err("a convex volume needs at least 3 vertices".into());
+            }
+            project.convex_volumes.push(ConvexVolume {
+                verts,
+                hmin: vol.field("hmin")?.as_f32("hmin")?,
+                hmax: vol.field("hmax")?.as_f32("hmax")?,
+                area: vol.field("area")?.as_area()?,
+            });
+        }
+        for con in json.array("off_mesh_connections")? {
+            project.off_mesh_connections.push(OffMeshConnection {
+                start: con.field("start")?.as_vec3("start")?,
+                end: con.field("end")?.as_vec3("end")?,
+                radius: con.field("radius")?.as_f32("radius")?,
+                bidirectional: con.field("bidirectional")?.as_bool("bidirectional")?,
+                area: con.field("area")?.as_area()?,
+            });
+        }
+        for mark in json.array("bookmarks")? {
+            let rotation = mark.field("rotation")?.as_floats("rotation")?;
+            let [x, y, z, w] = rotation[..] else {
+                return Err("rotation must have 4 components".into());
+            };
+            project.bookmarks.push(CameraBookmark {
+                name: mark.field("name")?.as_str("name")?.to_string(),
+                position: mark.field("position")?.as_vec3("position")?,
+                rotation: Quat::from_xyzw(x, y, z, w).normalize(),
+                focus: mark.field("focus")?.as_vec3("focus")?,
+            });
+        }
+        Ok(project)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        fs::write(path, self.to_json(path.parent().unwrap_or(Path::new(""))))
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        Self::from_json(&text, path.parent().unwrap_or(Path::new("")))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::regions::PartitionType;
+
+    #[test]
+    fn test_project_round_trip() {
+        let dir = Path::new("/levels");
+        let project = Project {
+            meshes: vec![
+                PathBuf::from("/levels/dungeon.obj"),
+                PathBuf::from("/props/crate \"big\".obj"),
+            ],
+            bake_config: BakeConfig {
+                cell_size: 0.25,
+                walkable_slope_angle: 40.0,
+                partition: PartitionType::Monotone,
+                ..BakeConfig::default()
+            },
+            convex_volumes: vec![ConvexVolume {
+                verts: vec![Vec3::ZERO, Vec3::X, Vec3::new(0.3, 0.1, 1.0)],
+                hmin: -1.0,
+                hmax: 2.5,
+                area: 5,
+            }],
+            off_mesh_connections: vec![OffMeshConnection {
+                start: Vec3::new(1.0, 0.5, 1.0),
+                end: Vec3::new(3.0, -0.5, 1.0),
+                radius: 0.6,
+                bidirectional: false,
+                area: 63,
+            }],
+            bookmarks: vec![CameraBookmark {
+                name: "Gate\ttop".into(),
+                position: Vec3::new(0.0, 10.0, 5.0),
+                rotation: Quat::from_rotation_x(-0.5),
+                focus: Vec3::new(0.0, 0.0, 2.0),
+            }],
+        };
+
+        let text = project.to_json(dir);
+        // Paths under the project directory are stored relative to it
+        assert!(text.contains("\"dungeon.obj\""));
+        let loaded = Project::from_json(&text, dir).unwrap();
+        assert_eq!(loaded.meshes, project.meshes);
+        assert_eq!(loaded.bake_config, project.bake_config);
+        assert_eq!(loaded.convex_volumes, project.convex_volumes);
+        assert_eq!(loaded.off_mesh_connections, project.off_mesh_connections);
+        assert_eq!(loaded.bookmarks[0].name, "Gate\ttop");
+        assert!(loaded.bookmarks[0]
+            .rotation
+            .abs_diff_eq(project.bookmarks[0].rotation, 1e-6));
+
+        // Sections can be left out
+        let minimal = Project::from_json("{\"meshes\": [\"a.obj\"]}", dir).unwrap();
+        assert_eq!(minimal.meshes, vec![PathBuf::from("/levels/a.obj")]);
+        assert_eq!(minimal.bake_config, BakeConfig::default());
+        let old = Project::from_json("{\"walkable_slope_angle\": 30}", dir).unwrap();
+        assert_eq!(old.bake_config.walkable_slope_angle, 30.0);
+
+        assert!(Project::from_json("{\"meshes\": [1]}", dir).is_err());
+        assert!(Project::from_json("{\"meshes\": [\"a.obj\"]", dir).is_err());
+        assert!(
+            Project::from_json("{\"off_mesh_connections\": [{\"start\": [0, 0]}]}", dir).is_err()
+        );
+    }
+}
@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+
+use glam::Vec3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct GridItem {
+    id: usize,
+    pos: Vec3,
+    radius: f32,
+}
+
+// Spatial hash of agent circles on the XZ plane, cleared and refilled each
+// crowd tick so neighbour lookups only look at nearby cells. Like Detour's
+// dtProximityGrid.
+#[derive(Debug, Clone)]
+pub struct ProximityGrid {
+    cell_size: f32,
+    items: Vec<GridItem>,
+    cells: HashMap<(i32, i32), Vec<usize>>,
+}
+
+impl ProximityGrid {
+    // `cell_size` is best around the largest agent diameter
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size: cell_size.max(f32::EPSILON),
+            items: Vec::new(),
+            cells: HashMap::new(),
+        }
+    }
+
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+
+    // Forget every item, keeping the allocations for the next tick
+    pub fn clear(&mut self) {
+        self.items.clear();
+        for cell in self.cells.values_mut() {
+            cell.clear();
+        }
+    }
+
+    pub fn item_count(&self) -> usize {
+        self.items.len()
+    }
+
+    // Register `id` as a circle of `radius` at `pos`, in every cell the
+    // circle's bounds touch
+    pub fn add_item(&mut self, id: usize, pos: Vec3, radius: f32) {
+        let index = self.items.len();
+        self.items.push(GridItem { id, pos, radius });
+        let (x0, z0, x1, z1) = self.cell_range(pos, radius);
+        for z in z0..=z1 {
+            for x in x0..=x1 {
+                self.cells.entry((x, z)).or_default().push(index);
+            }
+        }
+    }
+
+    // Ids of the items in the cells overlapping the XZ box from `bmin` to
+    // `bmax`. Items near the box but outside it may be included.
+    pub fn query_items(&self, bmin: Vec3, bmax: Vec3) -> Vec<usize> {
+        self.query_indices(bmin, bmax)
+            .into_iter()
+            .map(|i| self.items[i].id)
+            .collect()
+    }
+
+    // Ids of the items whose circle comes within `radius` of `pos` on the
+    // XZ plane, nearest first
+    pub fn query_radius(&self, pos: Vec3, radius: f32) -> Vec<usize> {
+        let extent = Vec3::new(radius, 0.0, radius);
+        let mut found: Vec<(f32, usize)> = self
+            .query_indices(pos - extent, pos + extent)
+            .into_iter()
+            .filter_map(|i| {
+                let item = &self.items[i];
+                let (dx, dz) = (item.pos.x - pos.x, item.pos.z - pos.z);
+                let dist = (dx * dx + dz * dz).sqrt();
+                (dist <= radius + item.radius).then_some((dist, item.id))
+            })
+            .collect();
+        found.sort_by(|a, b| a.0.total_cmp(&b.0));
+        found.into_iter().map(|(_, id)| id).collect()
+    }
+
+    // Number of items registered in the cell containing `pos`, for debug
+    // drawing
+    pub fn item_count_at(&self, pos: Vec3) -> usize {
+        self.cells.get(&self.cell_of(pos)).map_or(0, Vec::len)
+    }
+
+    fn cell_of(&self, pos: Vec3) -> (i32, i32) {
+        (
+            (pos.x / self.cell_size).floor() as i32,
+            (pos.z / self.cell_size).floor() as i32,
+        )
+    }
+
+    fn cell_range(&self, pos: Vec3, radius: f32) -> (i32, i32, i32, i32) {
+        let extent = Vec3::new(radius, 0.0, radius);
+        let (x0, z0) = self.cell_of(pos - extent);
+        let (x1, z1) = self.cell_of(pos + extent);
+        (x0, z0, x1, z1)
+    }
+
+    // Item indices in the cells overlapping the box, each once
+    fn query_indices(&self, bmin: Vec3, bmax: Vec3) -> Vec<usize> {
+        let (x0, z0) = self.cell_of(bmin);
+        let (x1, z1) = self.cell_of(bmax);
+        let mut indices = Vec::new();
+        for z in z0..=z1 {
+            for x in x0..=x1 {
+                if let Some(cell) = self.cells.get(&(x, z)) {
+                    indices.extend_from_slice(cell);
+                }
+            }
+        }
+        indices.sort_unstable();
+        indices.dedup();
+        indices
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_radius_finds_nearby_agents() {
+        let mut grid = ProximityGrid::new(1.2);
+        grid.add_item(7, Vec3::new(0.0, 0.0, 0.0), 0.6);
+        grid.add_item(3, Vec3::new(1.5, 0.0, 0.0), 0.6);
+        grid.add_item(9, Vec3::new(-1.0, 2.0, 0.5), 0.6);
+        grid.add_item(4, Vec3::new(10.0, 0.0, 10.0), 0.6);
+
+        assert_eq!(grid.query_radius(Vec3::ZERO, 1.0), vec![7, 9, 3]);
+        assert!(grid.query_radius(Vec3::new(10.0, 0.0, 8.0), 1.0).is_empty());
+        assert_eq!(
+            grid.query_items(Vec3::splat(9.0), Vec3::splat(11.0)),
+            vec![4]
+        );
+        assert_eq!(grid.item_count_at(Vec3::new(0.1, 0.0, 0.1)), 2);
+
+        grid.clear();
+        assert_eq!(grid.item_count(), 0);
+        assert!(grid.query_radius(Vec3::ZERO, 5.0).is_empty());
+    }
+}
@@ -0,0 +1,117 @@
+use glam::Vec3;
+
+use crate::debug_draw::AREA_COUNT;
+use crate::nav_mesh::{MeshTile, Poly, PolyRef};
+
+// Decides which polygons a query may visit and what crossing them costs
+pub trait PolyFilter {
+    fn pass_filter(&self, _poly_ref: PolyRef, _tile: &MeshTile, _poly: &Poly) -> bool {
+        true
+    }
+
+    // Cost of moving from `pa` to `pb` across `cur_poly`
+    fn cost(&self, pa: Vec3, pb: Vec3, _cur_poly: &Poly) -> f32 {
+        pa.distance(pb)
+    }
+}
+
+// Accepts every polygon, costing plain distance
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultFilter;
+
+impl PolyFilter for DefaultFilter {}
+
+// Filter on polygon flags with a cost multiplier per area id, like
+// Detour's dtQueryFilter. A polygon passes when it has at least one of the
+// include flags and none of the exclude flags.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryFilter {
+    area_cost: [f32; AREA_COUNT],
+    include_flags: u16,
+    exclude_flags: u16,
+}
+
+impl Default for QueryFilter {
+    fn default() -> Self {
+        Self {
+            area_cost: [1.0; AREA_COUNT],
+            include_flags: 0xffff,
+            exclude_flags: 0,
+        }
+    }
+}
+
+impl QueryFilter {
+    pub fn area_cost(&self, area: u8) -> f32 {
+        self.area_cost[area as usize % AREA_COUNT]
+    }
+
+    // Multiplier on the distance travelled across polygons of `area`
+    pub fn set_area_cost(&mut self, area: u8, cost: f32) {
+        self.area_cost[area as usize % AREA_COUNT] = cost;
+    }
+
+    pub fn include_flags(&self) -> u16 {
+        self.include_flags
+    }
+
+    pub fn set_include_flags(&mut self, flags: u16) {
+        self.include_flags = flags;
+    }
+
+    pub fn exclude_flags(&self) -> u16 {
+        self.exclude_flags
+    }
+
+    pub fn set_exclude_flags(&mut self, flags: u16) {
+        self.exclude_flags = flags;
+    }
+}
+
+impl PolyFilter for QueryFilter {
+    fn pass_filter(&self, _poly_ref: PolyRef, _tile: &MeshTile, poly: &Poly) -> bool {
+        poly.flags & self.include_flags != 0 && poly.flags & self.exclude_flags == 0
+    }
+
+    fn cost(&self, pa: Vec3, pb: Vec3, cur_poly: &Poly) -> f32 {
+        pa.distance(pb) * self.area_cost(cur_poly.area)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nav_mesh::{NavMeshData, PolyType, DT_NULL_LINK};
+
+    fn poly(flags: u16, area: u8) -> Poly {
+        Poly {
+            first_link: DT_NULL_LINK,
+            verts: [0; 6],
+            neis: [0; 6],
+            flags,
+            vert_count: 3,
+            area,
+            poly_type: PolyType::Ground,
+        }
+    }
+
+    #[test]
+    fn test_flags_and_area_costs() {
+        let tile = MeshTile::new(1, NavMeshData::default());
+        let mut filter = QueryFilter::default();
+        assert!(filter.pass_filter(1, &tile, &poly(0x01, 0)));
+        // Polygons without flags never pass
+        assert!(!filter.pass_filter(1, &tile, &poly(0, 0)));
+
+        filter.set_include_flags(0x01 | 0x02);
+        filter.set_exclude_flags(0x10);
+        assert!(filter.pass_filter(1, &tile, &poly(0x02, 0)));
+        assert!(!filter.pass_filter(1, &tile, &poly(0x04, 0)));
+        assert!(!filter.pass_filter(1, &tile, &poly(0x01 | 0x10, 0)));
+
+        filter.set_area_cost(5, 3.0);
+        let (a, b) = (Vec3::ZERO, Vec3::new(2.0, 0.0, 0.0));
+        assert_eq!(filter.cost(a, b, &poly(1, 5)), 6.0);
+        assert_eq!(filter.cost(a, b, &poly(1, 4)), 2.0);
+    }
+}
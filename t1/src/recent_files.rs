@@ -0,0 +1,97 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// How many files the list remembers
+pub const MAX_RECENT_FILES: usize = 10;
+
+// Meshes and projects opened lately, most recent first. Stored as one path
+// per line.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RecentFiles {
+    paths: Vec<PathBuf>,
+}
+
+impl RecentFiles {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Location of the persisted list, next to the executable
+    pub fn default_path() -> Option<PathBuf> {
+        std::env::current_exe()
+            .ok()
+            .map(|exe| exe.with_file_name("recent_files.txt"))
+    }
+
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    // Move `path` to the front, dropping the oldest entry when full
+    pub fn push(&mut self, path: &Path) {
+        self.paths.retain(|p| p != path);
+        self.paths.insert(0, path.to_path_buf());
+        self.paths.truncate(MAX_RECENT_FILES);
+    }
+
+    pub fn remove(&mut self, path: &Path) {
+        self.paths.retain(|p| p != path);
+    }
+
+    pub fn to_text(&self) -> String {
+        self.paths
+            .iter()
+            .map(|p| format!("{}\n", p.display()))
+            .collect()
+    }
+
+    pub fn from_text(text: &str) -> Self {
+        let mut recent = Self::new();
+        for line in text.lines().map(str::trim).filter(|l| !l.is_empty()).rev() {
+            recent.push(Path::new(line));
+        }
+        recent
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_text())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self::from_text(&fs::read_to_string(path)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_orders_and_caps() {
+        let mut recent = RecentFiles::new();
+        for i in 0..MAX_RECENT_FILES + 2 {
+            recent.push(Path::new(&format!("mesh{}.obj", i)));
+        }
+        assert_eq!(recent.paths().len(), MAX_RECENT_FILES);
+        assert_eq!(recent.paths()[0], PathBuf::from("mesh11.obj"));
+
+        // Opening a file again moves it to the front without duplicating it
+        recent.push(Path::new("mesh5.obj"));
+        assert_eq!(recent.paths()[0], PathBuf::from("mesh5.obj"));
+        assert_eq!(recent.paths().len(), MAX_RECENT_FILES);
+        assert_eq!(
+            recent
+                .paths()
+                .iter()
+                .filter(|p| p.ends_with("mesh5.obj"))
+                .count(),
+            1
+        );
+
+        let loaded = RecentFiles::from_text(&recent.to_text());
+        assert_eq!(loaded, recent);
+        recent.remove(Path::new("mesh5.obj"));
+        assert_eq!(recent.paths()[0], PathBuf::from("mesh11.obj"));
+    }
+}
@@ -0,0 +1,1001 @@
+use crate::compact_heightfield::CompactHeightfield;
+use crate::debug_draw::RC_NULL_AREA;
+
+// Set on region ids painted into the tile border; those spans are never
+// part of the navmesh
+pub const RC_BORDER_REG: u16 = 0x8000;
+
+// How walkable spans are grouped into regions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PartitionType {
+    // Best quality, slowest; regions follow the shape of open areas
+    #[default]
+    Watershed,
+    // Fastest; long thin polygons, but never holes or overlaps
+    Monotone,
+}
+
+impl PartitionType {
+    pub const ALL: [PartitionType; 2] = [PartitionType::Watershed, PartitionType::Monotone];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PartitionType::Watershed => "watershed",
+            PartitionType::Monotone => "monotone",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|p| p.name() == name)
+    }
+}
+
+// Partition `chf` with the chosen algorithm
+pub fn build_regions(
+    chf: &mut CompactHeightfield,
+    partition: PartitionType,
+    border_size: i32,
+    min_region_area: i32,
+    merge_region_area: i32,
+) {
+    match partition {
+        PartitionType::Watershed => {
+            build_regions_watershed(chf, border_size, min_region_area, merge_region_area)
+        }
+        PartitionType::Monotone => {
+            build_regions_monotone(chf, border_size, min_region_area, merge_region_area)
+        }
+    }
+}
+
+// Distance to the nearest boundary for every span, in units of half a cell
+// (2 per straight step, 3 per diagonal), smoothed with a box blur
+pub fn build_distance_field(chf: &mut CompactHeightfield) {
+    let (dist, max_distance) = calculate_distance_field(chf);
+    chf.dist = box_blur(chf, 1, &dist);
+    chf.max_distance = max_distance;
+}
+
+fn calculate_distance_field(chf: &CompactHeightfield) -> (Vec<u16>, u16) {
+    let mut dist = vec![u16::MAX; chf.span_count()];
+
+    // Spans missing a neighbour of the same area are on the boundary
+    for z in 0..chf.height {
+        for x in 0..chf.width {
+            for i in chf.span_range(x, z) {
+                let same_area = (0..4)
+                    .filter_map(|dir| chf.neighbour(x, z, i, dir))
+                    .filter(|&(_, _, ai)| chf.areas[ai] == chf.areas[i])
+                    .count();
+                if same_area != 4 {
+                    dist[i] = 0;
+                }
+            }
+        }
+    }
+
+    // Chamfer distance: a forward pass looking back along -x and -z, then a
+    // backward pass looking along +x and +z. The diagonal of each straight
+    // step is the next direction counter-clockwise, e.g. (-1,0) then (0,-1).
+    let relax = |dist: &mut [u16], x: i32, z: i32, i: usize, dirs: [usize; 2]| {
+        for dir in dirs {
+            let Some((ax, az, ai)) = chf.neighbour(x, z, i, dir) else {
+                continue;
+            };
+            dist[i] = dist[i].min(dist[ai].saturating_add(2));
+            if let Some((_, _, aai)) = chf.neighbour(ax, az, ai, (dir + 3) & 3) {
+                dist[i] = dist[i].min(dist[aai].saturating_add(3));
+            }
+        }
+    };
+    for z in 0..chf.height {
+        for x in 0..chf.width {
+            for i in chf.span_range(x, z) {
+                relax(&mut dist, x, z, i, [0, 3]);
+            }
+        }
+    }
+    for z in (0..chf.height).rev() {
+        for x in (0..chf.width).rev() {
+            for i in chf.span_range(x, z) {
+                relax(&mut dist, x, z, i, [2, 1]);
+            }
+        }
+    }
+
+    let max_distance = dist.iter().copied().max().unwrap_or(0);
+    (dist, max_distance)
+}
+
+// Average each span with its 8 neighbours, leaving spans within `threshold`
+// cells of the boundary alone so thin corridors keep their shape
+fn box_blur(chf: &CompactHeightfield, threshold: u16, src: &[u16]) -> Vec<u16> {
+    let threshold = threshold * 2;
+    let mut dst = vec![0; src.len()];
+
+    for z in 0..chf.height {
+        for x in 0..chf.width {
+            for i in chf.span_range(x, z) {
+                let cd = src[i] as u32;
+                if cd <= threshold as u32 {
+                    dst[i] = cd as u16;
+                    continue;
+                }
+
+                // Missing neighbours count as the centre value
+                let mut d = cd;
+                for dir in 0..4 {
+                    match chf.neighbour(x, z, i, dir) {
+                        Some((ax, az, ai)) => {
+                            d += src[ai] as u32;
+                            d += chf
+                                .neighbour(ax, az, ai, (dir + 1) & 3)
+                                .map_or(cd, |(_, _, aai)| src[aai] as u32);
+                        }
+                        None => d += cd * 2,
+                    }
+                }
+                dst[i] = ((d + 5) / 9) as u16;
+            }
+        }
+    }
+    dst
+}
+
+// Partition the walkable spans into regions by flooding the distance field
+// from its peaks downwards, like water filling a basin. Produces regions
+// without holes or overlaps, the best input for contour tracing.
+//
+// `border_size` cells around the edge are reserved for tiling. Islands of
+// fewer than `min_region_area` spans are dropped, and regions smaller than
+// `merge_region_area` are merged into a neighbour where possible.
+pub fn build_regions_watershed(
+    chf: &mut CompactHeightfield,
+    border_size: i32,
+    min_region_area: i32,
+    merge_region_area: i32,
+) {
+    build_distance_field(chf);
+
+    let span_count = chf.span_count();
+    let mut src_reg = vec![0u16; span_count];
+    let mut src_dist = vec![0u16; span_count];
+    let mut region_id: u16 = 1;
+
+    if border_size > 0 {
+        region_id = paint_border_regions(chf, border_size, &mut src_reg, region_id);
+    }
+    chf.border_size = border_size;
+
+    const EXPAND_ITERS: usize = 8;
+    let mut level = (chf.max_distance + 1) & !1;
+    let mut stack = Vec::new();
+    while level > 0 {
+        level = level.saturating_sub(2);
+
+        // Grow the existing regions into the cells the new level uncovers,
+        // then seed new regions in whatever is left
+        expand_regions(
+            chf,
+            EXPAND_ITERS,
+            level,
+            &mut src_reg,
+            &mut src_dist,
+            &mut stack,
+        );
+
+        for z in 0..chf.height {
+            for x in 0..chf.width {
+                for i in chf.span_range(x, z) {
+                    if chf.dist[i] < level || src_reg[i] != 0 || chf.areas[i] == RC_NULL_AREA {
+                        continue;
+                    }
+                    if flood_region(chf, x, z, i, level, region_id, &mut src_reg, &mut src_dist) {
+                        region_id += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    // Fill whatever the levels didn't reach
+    expand_regions(
+        chf,
+        EXPAND_ITERS * 8,
+        0,
+        &mut src_reg,
+        &mut src_dist,
+        &mut stack,
+    );
+
+    let max_region_id = merge_and_filter_regions(
+        chf,
+        min_region_area,
+        merge_region_area,
+        region_id,
+        &mut src_reg,
+    );
+    chf.max_regions = max_region_id;
+
+    for (span, reg) in chf.spans.iter_mut().zip(src_reg) {
+        span.reg = reg;
+    }
+}
+
+// Neighbour marker for a row segment touching more than one region above it
+const RC_NULL_NEI: u16 = 0xffff;
+
+#[derive(Clone, Copy, Default)]
+struct SweepSpan {
+    // Final region id for the segment
+    id: u16,
+    // Spans connected to `nei` in the previous row
+    ns: u16,
+    // Region of the previous row this segment touches
+    nei: u16,
+}
+
+// Partition by sweeping the grid row by row: each row is cut into runs of
+// connected spans, and a run continues the region above it when the two
+// are each other's only connection. Needs no distance field, so it's much
+// faster than watershed, at the cost of long thin regions.
+pub fn build_regions_monotone(
+    chf: &mut CompactHeightfield,
+    border_size: i32,
+    min_region_area: i32,
+    merge_region_area: i32,
+) {
+    let (w, h) = (chf.width, chf.height);
+    let mut src_reg = vec![0u16; chf.span_count()];
+    let mut id: u16 = 1;
+
+    if border_size > 0 {
+        id = paint_border_regions(chf, border_size, &mut src_reg, id);
+    }
+    chf.border_size = border_size;
+
+    let mut sweeps: Vec<SweepSpan> = Vec::new();
+    let mut prev: Vec<u16> = Vec::new();
+
+    for z in border_size..h - border_size {
+        prev.clear();
+        prev.resize(id as usize + 1, 0);
+        sweeps.clear();
+        sweeps.push(SweepSpan::default());
+        let mut rid: u16 = 1;
+
+        for x in border_size..w - border_size {
+            for i in chf.span_range(x, z) {
+                if chf.areas[i] == RC_NULL_AREA {
+                    continue;
+                }
+
+                // Continue the run from -x, or start a new one
+                let same_area =
+                    |ai: usize| src_reg[ai] & RC_BORDER_REG == 0 && chf.areas[ai] == chf.areas[i];
+                let mut previd = chf.neighbour(x, z, i, 0).map_or(0, |(_, _, ai)| {
+                    if same_area(ai) {
+                        src_reg[ai]
+                    } else {
+                        0
+                    }
+                });
+                if previd == 0 {
+                    previd = rid;
+                    rid += 1;
+                    sweeps.push(SweepSpan::default());
+                }
+
+                // Track which region of the previous row the run touches
+                if let Some((_, _, ai)) = chf.neighbour(x, z, i, 3) {
+                    let nr = src_reg[ai];
+                    if nr != 0 && same_area(ai) {
+                        let sweep = &mut sweeps[previd as usize];
+                        if sweep.nei == 0 || sweep.nei == nr {
+                            sweep.nei = nr;
+                            sweep.ns += 1;
+                            prev[nr as usize] += 1;
+                        } else {
+                            sweep.nei = RC_NULL_NEI;
+                        }
+                    }
+                }
+
+                src_reg[i] = previd;
+            }
+        }
+
+        // A run joins the region above only when every span of that region
+        // in the previous row connects to this run
+        for sweep in &mut sweeps[1..] {
+            if sweep.nei != RC_NULL_NEI && sweep.nei != 0 && prev[sweep.nei as usize] == sweep.ns {
+                sweep.id = sweep.nei;
+            } else {
+                sweep.id = id;
+                id += 1;
+            }
+        }
+
+        for x in border_size..w - border_size {
+            for i in chf.span_range(x, z) {
+                if src_reg[i] > 0 && src_reg[i] < rid {
+                    src_reg[i] = sweeps[src_reg[i] as usize].id;
+                }
+            }
+        }
+    }
+
+    chf.max_regions =
+        merge_and_filter_regions(chf, min_region_area, merge_region_area, id, &mut src_reg);
+
+    for (span, reg) in chf.spans.iter_mut().zip(src_reg) {
+        span.reg = reg;
+    }
+}
+
+// Give each side of the border its own region id, returning the next free id
+fn paint_border_regions(
+    chf: &CompactHeightfield,
+    border_size: i32,
+    src_reg: &mut [u16],
+    mut region_id: u16,
+) -> u16 {
+    let (w, h) = (chf.width, chf.height);
+    let bw = w.min(border_size);
+    let bh = h.min(border_size);
+    for (min_x, max_x, min_z, max_z) in [
+        (0, bw, 0, h),
+        (w - bw, w, 0, h),
+        (0, w, 0, bh),
+        (0, w, h - bh, h),
+    ] {
+        for z in min_z..max_z {
+            for x in min_x..max_x {
+                for i in chf.span_range(x, z) {
+                    if chf.areas[i] != RC_NULL_AREA {
+                        src_reg[i] = region_id | RC_BORDER_REG;
+                    }
+                }
+            }
+        }
+        region_id += 1;
+    }
+    region_id
+}
+
+// Spans waiting to be claimed by a region; `None` once one has been
+type LevelStack = Vec<(i32, i32, Option<usize>)>;
+
+fn expand_regions(
+    chf: &CompactHeightfield,
+    max_iter: usize,
+    level: u16,
+    src_reg: &mut Vec<u16>,
+    src_dist: &mut Vec<u16>,
+    stack: &mut LevelStack,
+) {
+    // Find the unassigned cells at or above the level
+    stack.clear();
+    for z in 0..chf.height {
+        for x in 0..chf.width {
+            for i in chf.span_range(x, z) {
+                if chf.dist[i] >= level && src_reg[i] == 0 && chf.areas[i] != RC_NULL_AREA {
+                    stack.push((x, z, Some(i)));
+                }
+            }
+        }
+    }
+
+    let mut iter = 0;
+    while !stack.is_empty() {
+        let mut failed = 0;
+        // Assignments made in this pass must not be seen until the next one
+        let mut dst_reg = src_reg.clone();
+        let mut dst_dist = src_dist.clone();
+
+        for entry in stack.iter_mut() {
+            let (x, z, Some(i)) = *entry else {
+                failed += 1;
+                continue;
+            };
+
+            // Take the region of the neighbour closest to a region centre
+            let mut r = 0;
+            let mut d2 = u16::MAX;
+            for dir in 0..4 {
+                let Some((_, _, ai)) = chf.neighbour(x, z, i, dir) else {
+                    continue;
+                };
+                if chf.areas[ai] != chf.areas[i] {
+                    continue;
+                }
+                if src_reg[ai] > 0
+                    && src_reg[ai] & RC_BORDER_REG == 0
+                    && src_dist[ai].saturating_add(2) < d2
+                {
+                    r = src_reg[ai];
+                    d2 = src_dist[ai] + 2;
+                }
+            }
+            if r != 0 {
+                entry.2 = None;
+                dst_reg[i] = r;
+                dst_dist[i] = d2;
+            } else {
+                failed += 1;
+            }
+        }
+
+        *src_reg = dst_reg;
+        *src_dist = dst_dist;
+
+        if failed == stack.len() {
+            break;
+        }
+        if level > 0 {
+            iter += 1;
+            if iter >= max_iter {
+                break;
+            }
+        }
+    }
+}
+
+// Flood a new region `r` from span `i` over connected spans at or above
+// `level`, stopping where another region is adjacent. Returns whether any
+// span was claimed.
+#[allow(clippy::too_many_arguments)]
+fn flood_region(
+    chf: &CompactHeightfield,
+    x: i32,
+    z: i32,
+    i: usize,
+    level: u16,
+    r: u16,
+    src_reg: &mut [u16],
+    src_dist: &mut [u16],
+) -> bool {
+    let area = chf.areas[i];
+    let mut stack = vec![(x, z, i)];
+    src_reg[i] = r;
+    src_dist[i] = 0;
+
+    let lev = level.saturating_sub(2);
+    let mut count = 0;
+
+    while let Some((cx, cz, ci)) = stack.pop() {
+        // Give up the span if any 8-connected neighbour already belongs to
+        // another region
+        let mut other = false;
+        for dir in 0..4 {
+            let Some((ax, az, ai)) = chf.neighbour(cx, cz, ci, dir) else {
+                continue;
+            };
+            if chf.areas[ai] != area {
+                continue;
+            }
+            let nr = src_reg[ai];
+            if nr & RC_BORDER_REG != 0 {
+                continue;
+            }
+            if nr != 0 && nr != r {
+                other = true;
+                break;
+            }
+
+            if let Some((_, _, ai2)) = chf.neighbour(ax, az, ai, (dir + 1) & 3) {
+                if chf.areas[ai2] != area {
+                    continue;
+                }
+                let nr2 = src_reg[ai2];
+                if nr2 != 0 && nr2 != r {
+                    other = true;
+                    break;
+                }
+            }
+        }
+        if other {
+            src_reg[ci] = 0;
+            continue;
+        }
+
+        count += 1;
+
+        for dir in 0..4 {
+            let Some((ax, az, ai)) = chf.neighbour(cx, cz, ci, dir) else {
+                continue;
+            };
+            if chf.areas[ai] != area {
+                continue;
+            }
+            if chf.dist[ai] >= lev && src_reg[ai] == 0 {
+                src_reg[ai] = r;
+                src_dist[ai] = 0;
+                stack.push((ax, az, ai));
+            }
+        }
+    }
+
+    count > 0
+}
+
+#[derive(Clone, Debug, Default)]
+struct Region {
+    span_count: i32,
+    id: u16,
+    area_type: u8,
+    remap: bool,
+    visited: bool,
+    // Spans of this region appear above or below each other in a column
+    overlap: bool,
+    // Neighbouring region ids in walking order around the contour
+    connections: Vec<u16>,
+    // Regions sharing a column with this one
+    floors: Vec<u16>,
+}
+
+impl Region {
+    fn new(id: u16) -> Self {
+        Self {
+            id,
+            ..Default::default()
+        }
+    }
+
+    fn add_unique_floor(&mut self, id: u16) {
+        if !self.floors.contains(&id) {
+            self.floors.push(id);
+        }
+    }
+
+    fn remove_adjacent_neighbours(&mut self) {
+        let mut i = 0;
+        while i < self.connections.len() && self.connections.len() > 1 {
+            let ni = (i + 1) % self.connections.len();
+            if self.connections[i] == self.connections[ni] {
+                self.connections.remove(i);
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    fn replace_neighbour(&mut self, old_id: u16, new_id: u16) {
+        let mut changed = false;
+        for c in &mut self.connections {
+            if *c == old_id {
+                *c = new_id;
+                changed = true;
+            }
+        }
+        for f in &mut self.floors {
+            if *f == old_id {
+                *f = new_id;
+            }
+        }
+        if changed {
+            self.remove_adjacent_neighbours();
+        }
+    }
+
+    fn can_merge_with(&self, other: &Region) -> bool {
+        if self.area_type != other.area_type {
+            return false;
+        }
+        // Touching along more than one stretch would leave a hole
+        let shared = self.connections.iter().filter(|&&c| c == other.id).count();
+        shared <= 1 && !self.floors.contains(&other.id)
+    }
+
+    fn is_connected_to_border(&self) -> bool {
+        // Region 0 is the unwalkable space around the mesh
+        self.connections.contains(&0)
+    }
+}
+
+// Splice `b`'s contour connections into `a` where the two touch
+fn merge_regions(regions: &mut [Region], a: usize, b: usize) -> bool {
+    let (aid, bid) = (regions[a].id, regions[b].id);
+    let acon = regions[a].connections.clone();
+    let bcon = regions[b].connections.clone();
+
+    let Some(insa) = acon.iter().position(|&c| c == bid) else {
+        return false;
+    };
+    let Some(insb) = bcon.iter().position(|&c| c == aid) else {
+        return false;
+    };
+
+    let mut connections = Vec::with_capacity(acon.len() + bcon.len());
+    for i in 0..acon.len() - 1 {
+        connections.push(acon[(insa + 1 + i) % acon.len()]);
+    }
+    for i in 0..bcon.len() - 1 {
+        connections.push(bcon[(insb + 1 + i) % bcon.len()]);
+    }
+
+    let floors = std::mem::take(&mut regions[b].floors);
+    let span_count = regions[b].span_count;
+    regions[b].span_count = 0;
+    regions[b].connections.clear();
+
+    let rega = &mut regions[a];
+    rega.connections = connections;
+    rega.remove_adjacent_neighbours();
+    for f in floors {
+        rega.add_unique_floor(f);
+    }
+    rega.span_count += span_count;
+    true
+}
+
+fn is_solid_edge(
+    chf: &CompactHeightfield,
+    src_reg: &[u16],
+    x: i32,
+    z: i32,
+    i: usize,
+    dir: usize,
+) -> bool {
+    let r = chf
+        .neighbour(x, z, i, dir)
+        .map_or(0, |(_, _, ai)| src_reg[ai]);
+    r != src_reg[i]
+}
+
+// Walk the region boundary clockwise from span `i`, recording the region on
+// the other side of each stretch of edge
+fn walk_contour(
+    chf: &CompactHeightfield,
+    src_reg: &[u16],
+    mut x: i32,
+    mut z: i32,
+    mut i: usize,
+    mut dir: usize,
+) -> Vec<u16> {
+    let start_dir = dir;
+    let start_i = i;
+
+    let mut cur_reg = chf
+        .neighbour(x, z, i, dir)
+        .map_or(0, |(_, _, ai)| src_reg[ai]);
+    let mut cont = vec![cur_reg];
+
+    for _ in 1..40000 {
+        if is_solid_edge(chf, src_reg, x, z, i, dir) {
+            let r = chf
+                .neighbour(x, z, i, dir)
+                .map_or(0, |(_, _, ai)| src_reg[ai]);
+            if r != cur_reg {
+                cur_reg = r;
+                cont.push(cur_reg);
+            }
+            // Rotate clockwise
+            dir = (dir + 1) & 3;
+        } else {
+            let Some((nx, nz, ni)) = chf.neighbour(x, z, i, dir) else {
+                // Not a solid edge, so the neighbour must exist
+                return cont;
+            };
+            x = nx;
+            z = nz;
+            i = ni;
+            // Rotate counter-clockwise
+            dir = (dir + 3) & 3;
+        }
+
+        if start_i == i && start_dir == dir {
+            break;
+        }
+    }
+
+    // Remove adjacent duplicates
+    if cont.len() > 1 {
+        let mut j = 0;
+        while j < cont.len() {
+            let nj = (j + 1) % cont.len();
+            if cont[j] == cont[nj] {
+                cont.remove(j);
+            } else {
+                j += 1;
+            }
+        }
+    }
+    cont
+}
+
+// Drop islands smaller than `min_region_area`, merge regions smaller than
+// `merge_region_size` into their smallest neighbour and compact the ids.
+// Returns the highest region id in use.
+fn merge_and_filter_regions(
+    chf: &CompactHeightfield,
+    min_region_area: i32,
+    merge_region_size: i32,
+    max_region_id: u16,
+    src_reg: &mut [u16],
+) -> u16 {
+    let nreg = max_region_id as usize + 1;
+    let mut regions: Vec<Region> = (0..nreg).map(|i| Region::new(i as u16)).collect();
+
+    // Count spans and trace the neighbours around each region's contour
+    for z in 0..chf.height {
+        for x in 0..chf.width {
+            let range = chf.span_range(x, z);
+            for i in range.clone() {
+                let r = src_reg[i] as usize;
+                if r == 0 || r >= nreg {
+                    continue;
+                }
+                regions[r].span_count += 1;
+
+                for j in range.clone() {
+                    if i == j {
+                        continue;
+                    }
+                    let floor_id = src_reg[j] as usize;
+                    if floor_id == 0 || floor_id >= nreg {
+                        continue;
+                    }
+                    if floor_id == r {
+                        regions[r].overlap = true;
+                    }
+                    regions[r].add_unique_floor(floor_id as u16);
+                }
+
+                // Only the first span found on the boundary starts a walk
+                if !regions[r].connections.is_empty() {
+                    continue;
+                }
+                regions[r].area_type = chf.areas[i];
+
+                if let Some(dir) = (0..4).find(|&dir| is_solid_edge(chf, src_reg, x, z, i, dir)) {
+                    regions[r].connections = walk_contour(chf, src_reg, x, z, i, dir);
+                }
+            }
+        }
+    }
+
+    // Remove islands that are too small as a whole. Islands touching the
+    // tile border are kept, since their full size isn't known.
+    let mut stack = Vec::new();
+    let mut trace = Vec::new();
+    for i in 0..nreg {
+        let reg = &regions[i];
+        if reg.id == 0 || reg.id & RC_BORDER_REG != 0 || reg.span_count == 0 || reg.visited {
+            continue;
+        }
+
+        let mut connects_to_border = false;
+        let mut span_count = 0;
+        stack.clear();
+        trace.clear();
+        regions[i].visited = true;
+        stack.push(i);
+
+        while let Some(ri) = stack.pop() {
+            span_count += regions[ri].span_count;
+            trace.push(ri);
+
+            for j in 0..regions[ri].connections.len() {
+                let c = regions[ri].connections[j];
+                if c & RC_BORDER_REG != 0 {
+                    connects_to_border = true;
+                    continue;
+                }
+                let nei = &mut regions[c as usize];
+                if nei.visited || nei.id == 0 || nei.id & RC_BORDER_REG != 0 {
+                    continue;
+                }
+                nei.visited = true;
+                stack.push(nei.id as usize);
+            }
+        }
+
+        if span_count < min_region_area && !connects_to_border {
+            for &t in &trace {
+                regions[t].span_count = 0;
+                regions[t].id = 0;
+            }
+        }
+    }
+
+    // Merge small regions into neighbours until nothing changes
+    loop {
+        let mut merge_count = 0;
+        for i in 0..nreg {
+            let reg = &regions[i];
+            if reg.id == 0 || reg.id & RC_BORDER_REG != 0 || reg.overlap || reg.span_count == 0 {
+                continue;
+            }
+            // Big enough regions at the mesh edge stay as they are
+            if reg.span_count > merge_region_size && reg.is_connected_to_border() {
+                continue;
+            }
+
+            // Find the smallest neighbour this one can merge with
+            let mut smallest = i32::MAX;
+            let mut merge_id = reg.id;
+            for &c in &reg.connections {
+                if c & RC_BORDER_REG != 0 {
+                    continue;
+                }
+                let mreg = &regions[c as usize];
+                if mreg.id == 0 || mreg.id & RC_BORDER_REG != 0 || mreg.overlap {
+                    continue;
+                }
+                if mreg.span_count < smallest
+                    && reg.can_merge_with(mreg)
+                    && mreg.can_merge_with(reg)
+                {
+                    smallest = mreg.span_count;
+                    merge_id = mreg.id;
+                }
+            }
+
+            if merge_id != reg.id {
+                let old_id = reg.id;
+                if merge_regions(&mut regions, merge_id as usize, i) {
+                    // Point everything that referred to this region at the
+                    // one it merged into
+                    for reg in regions.iter_mut() {
+                        if reg.id == 0 || reg.id & RC_BORDER_REG != 0 {
+                            continue;
+                        }
+                        if reg.id == old_id {
+                            reg.id = merge_id;
+                        }
+                        reg.replace_neighbour(old_id, merge_id);
+                    }
+                    merge_count += 1;
+                }
+            }
+        }
+        if merge_count == 0 {
+            break;
+        }
+    }
+
+    // Compress the region ids
+    for reg in regions.iter_mut() {
+        reg.remap = reg.id != 0 && reg.id & RC_BORDER_REG == 0;
+    }
+    let mut next_id = 0;
+    for i in 0..nreg {
+        if !regions[i].remap {
+            continue;
+        }
+        let old_id = regions[i].id;
+        next_id += 1;
+        for reg in &mut regions[i..] {
+            if reg.id == old_id {
+                reg.id = next_id;
+                reg.remap = false;
+            }
+        }
+    }
+
+    for reg in src_reg.iter_mut() {
+        if *reg & RC_BORDER_REG == 0 {
+            *reg = regions[*reg as usize].id;
+        }
+    }
+    next_id
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debug_draw::RC_WALKABLE_AREA;
+    use crate::heightfield::Heightfield;
+    use glam::Vec3;
+
+    // A compact heightfield from a height map; None leaves the column empty
+    fn chf_from_heights(rows: &[&[Option<u16>]]) -> CompactHeightfield {
+        let (w, h) = (rows[0].len() as f32, rows.len() as f32);
+        let mut hf = Heightfield::new(Vec3::ZERO, Vec3::new(w, 10.0, h), 1.0, 0.1);
+        for (z, row) in rows.iter().enumerate() {
+            for (x, &height) in row.iter().enumerate() {
+                if let Some(y) = height {
+                    hf.add_span(x as i32, z as i32, 0, y, RC_WALKABLE_AREA, 1);
+                }
+            }
+        }
+        CompactHeightfield::build(&hf, 10, 2)
+    }
+
+    fn regions_of(chf: &CompactHeightfield) -> Vec<u16> {
+        let mut ids: Vec<u16> = chf.spans.iter().map(|s| s.reg).collect();
+        ids.sort_unstable();
+        ids.dedup();
+        ids
+    }
+
+    #[test]
+    fn test_distance_field() {
+        let floor: &[Option<u16>] = &[Some(1); 7];
+        let chf = &mut chf_from_heights(&[floor; 7]);
+        build_distance_field(chf);
+
+        // Edges are 0 and the distance grows by 2 per cell towards the
+        // centre, where blurring flattens the peak of 6
+        let at = |x, z| chf.dist[chf.span_range(x, z).start];
+        assert_eq!(at(0, 3), 0);
+        assert_eq!(at(1, 3), 2);
+        assert_eq!(at(3, 3), 4);
+        assert_eq!(chf.max_distance, 6);
+    }
+
+    #[test]
+    fn test_watershed_separate_floors() {
+        // Two floors separated by a gap, plus a single-cell island
+        let row: &[Option<u16>] = &[Some(1), Some(1), Some(1), None, Some(1), Some(1), Some(1)];
+        let island: &[Option<u16>] = &[None, None, None, None, None, None, Some(1)];
+        let mut rows = vec![row; 6];
+        rows.push(&[None; 7]);
+        rows.push(island);
+        let mut chf = chf_from_heights(&rows);
+
+        build_regions_watershed(&mut chf, 0, 4, 20);
+        assert_eq!(regions_of(&chf), vec![0, 1, 2]);
+        assert!(chf.max_regions >= 2);
+
+        // Each floor is a single region, and the island was dropped
+        let left = chf.spans[chf.span_range(0, 0).start].reg;
+        let right = chf.spans[chf.span_range(6, 0).start].reg;
+        assert_ne!(left, right);
+        for z in 0..6 {
+            assert_eq!(chf.spans[chf.span_range(2, z).start].reg, left);
+            assert_eq!(chf.spans[chf.span_range(4, z).start].reg, right);
+        }
+        assert_eq!(chf.spans[chf.span_range(6, 7).start].reg, 0);
+    }
+
+    #[test]
+    fn test_monotone_matches_watershed_on_simple_floors() {
+        // An L-shaped floor and a separate strip
+        let l: &[Option<u16>] = &[Some(1), Some(1), None, Some(1)];
+        let foot: &[Option<u16>] = &[Some(1), Some(1), Some(1), None];
+        let rows = [l, l, l, foot, foot];
+
+        let mut monotone = chf_from_heights(&rows);
+        build_regions(&mut monotone, PartitionType::Monotone, 0, 1, 20);
+        assert!(monotone.spans.iter().all(|s| s.reg != 0));
+
+        // Spans in the same connected piece share a region once small
+        // regions have been merged, and the two pieces stay apart
+        let at = |chf: &CompactHeightfield, x, z| chf.spans[chf.span_range(x, z).start].reg;
+        assert_eq!(at(&monotone, 0, 0), at(&monotone, 2, 4));
+        assert_ne!(at(&monotone, 0, 0), at(&monotone, 3, 0));
+
+        let mut watershed = chf_from_heights(&rows);
+        build_regions(&mut watershed, PartitionType::Watershed, 0, 1, 20);
+        assert_eq!(regions_of(&monotone), regions_of(&watershed));
+    }
+
+    #[test]
+    fn test_partition_names() {
+        for partition in PartitionType::ALL {
+            assert_eq!(PartitionType::from_name(partition.name()), Some(partition));
+        }
+        assert_eq!(PartitionType::from_name("layers"), None);
+    }
+
+    #[test]
+    fn test_watershed_border() {
+        let floor: &[Option<u16>] = &[Some(1); 8];
+        let mut chf = chf_from_heights(&[floor; 8]);
+        build_regions_watershed(&mut chf, 2, 1, 20);
+
+        let corner = chf.spans[chf.span_range(0, 0).start].reg;
+        assert_ne!(corner & RC_BORDER_REG, 0);
+        let inner: Vec<u16> = (2..6)
+            .flat_map(|z| (2..6).map(move |x| (x, z)))
+            .map(|(x, z)| chf.spans[chf.span_range(x, z).start].reg)
+            .collect();
+        assert!(inner
+            .iter()
+            .all(|&r| r == inner[0] && r & RC_BORDER_REG == 0));
+        assert_eq!(chf.border_size, 2);
+    }
+}
@@ -0,0 +1,198 @@
+use glam::{Mat4, Vec3};
+use std::sync::Arc;
+
+use crate::debug_draw::RC_NULL_AREA;
+use crate::debug_draw_b::{bounds_of, InputMesh};
+use crate::heightfield::{rasterize_triangles_transformed, Heightfield};
+use crate::navmesh_build::mark_walkable_triangles_transformed;
+
+// One placement of a mesh in a scene. Instances of the same mesh share its
+// data; the transform is only applied while rasterizing.
+#[derive(Clone)]
+pub struct MeshInstance {
+    mesh: Arc<InputMesh>,
+    transform: Mat4,
+    // Area of the instance's walkable triangles; None keeps the mesh's own
+    area: Option<u8>,
+    // World-space bounds, for skipping instances outside a tile
+    bmin: Vec3,
+    bmax: Vec3,
+}
+
+impl MeshInstance {
+    pub fn new(mesh: Arc<InputMesh>, transform: Mat4, area: Option<u8>) -> Self {
+        let (bmin, bmax) = bounds_of(mesh.verts.iter().map(|&v| transform.transform_point3(v)));
+        Self {
+            mesh,
+            transform,
+            area,
+            bmin,
+            bmax,
+        }
+    }
+
+    pub fn mesh(&self) -> &InputMesh {
+        &self.mesh
+    }
+
+    pub fn transform(&self) -> Mat4 {
+        self.transform
+    }
+
+    pub fn area(&self) -> Option<u8> {
+        self.area
+    }
+
+    pub fn bounds(&self) -> (Vec3, Vec3) {
+        (self.bmin, self.bmax)
+    }
+
+    // Area id of each triangle once placed: RC_NULL_AREA when too steep,
+    // otherwise the instance's area or the mesh's own
+    pub fn walkable_areas(&self, walkable_slope_angle: f32) -> Vec<u8> {
+        let mut areas =
+            mark_walkable_triangles_transformed(walkable_slope_angle, &self.mesh, self.transform);
+        if let Some(area) = self.area {
+            for a in areas.iter_mut().filter(|a| **a != RC_NULL_AREA) {
+                *a = area;
+            }
+        }
+        areas
+    }
+}
+
+// Input geometry as a list of placed meshes, for building a navmesh
+// without merging everything into one mesh first
+#[derive(Clone, Default)]
+pub struct Scene {
+    instances: Vec<MeshInstance>,
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, mesh: Arc<InputMesh>, transform: Mat4, area: Option<u8>) {
+        self.instances
+            .push(MeshInstance::new(mesh, transform, area));
+    }
+
+    pub fn instances(&self) -> &[MeshInstance] {
+        &self.instances
+    }
+
+    pub fn tri_count(&self) -> usize {
+        self.instances.iter().map(|i| i.mesh.tri_count()).sum()
+    }
+
+    // World-space bounds of every instance with geometry
+    pub fn bounds(&self) -> (Vec3, Vec3) {
+        bounds_of(
+            self.instances
+                .iter()
+                .filter(|i| !i.mesh.verts.is_empty())
+                .flat_map(|i| [i.bmin, i.bmax]),
+        )
+    }
+
+    // `MeshInstance::walkable_areas` of every instance, in order
+    pub fn walkable_areas(&self, walkable_slope_angle: f32) -> Vec<Vec<u8>> {
+        self.instances
+            .iter()
+            .map(|i| i.walkable_areas(walkable_slope_angle))
+            .collect()
+    }
+
+    // Voxelize every instance reaching into `hf`, with the areas from
+    // `walkable_areas`
+    pub fn rasterize(&self, hf: &mut Heightfield, areas: &[Vec<u8>], flag_merge_threshold: i32) {
+        for (instance, areas) in self.instances.iter().zip(areas) {
+            let outside = instance.bmax.x < hf.bmin.x
+                || instance.bmax.z < hf.bmin.z
+                || instance.bmin.x > hf.bmax.x
+                || instance.bmin.z > hf.bmax.z;
+            if !outside {
+                rasterize_triangles_transformed(
+                    hf,
+                    &instance.mesh,
+                    instance.transform,
+                    areas,
+                    flag_merge_threshold,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_context::BuildContext;
+    use crate::navmesh_build::{build_navmesh, build_scene_navmesh, NavMeshBuildConfig};
+    use glam::Quat;
+
+    // A 6 x 6 wall standing on the X axis, facing -Z
+    fn wall() -> InputMesh {
+        let mut mesh = InputMesh::new();
+        mesh.verts = vec![
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 6.0, 0.0),
+            Vec3::new(6.0, 6.0, 0.0),
+            Vec3::new(6.0, 0.0, 0.0),
+        ];
+        mesh.tris = vec![0, 1, 2, 0, 2, 3];
+        mesh.compute_normals();
+        mesh
+    }
+
+    #[test]
+    fn test_instances_match_prebaked_transforms() {
+        let cfg = NavMeshBuildConfig::builder()
+            .min_region_area(8)
+            .build()
+            .unwrap();
+        let wall = Arc::new(wall());
+        // Laid flat it becomes a floor, once as is and once mirrored
+        let lay_flat = Mat4::from_rotation_x(std::f32::consts::FRAC_PI_2);
+        let transforms = [
+            Mat4::from_translation(Vec3::new(0.0, 1.0, 0.0)) * lay_flat,
+            Mat4::from_scale_rotation_translation(
+                Vec3::new(-1.0, 1.0, 1.0),
+                Quat::IDENTITY,
+                Vec3::new(20.0, 1.0, 0.0),
+            ) * lay_flat,
+        ];
+
+        let mut scene = Scene::new();
+        let mut prebaked = InputMesh::new();
+        for (i, &transform) in transforms.iter().enumerate() {
+            scene.add(wall.clone(), transform, Some(5));
+            let base = prebaked.verts.len() as i32;
+            prebaked
+                .verts
+                .extend(wall.verts.iter().map(|&v| transform.transform_point3(v)));
+            // Mirroring flips the winding, which the pre-baked copy undoes
+            let tris = if i == 1 {
+                [0, 2, 1, 0, 3, 2]
+            } else {
+                [0, 1, 2, 0, 2, 3]
+            };
+            prebaked.tris.extend(tris.map(|t| t + base));
+        }
+        prebaked.areas = vec![5; 4];
+        assert_eq!(scene.walkable_areas(45.0), vec![vec![5, 5], vec![5, 5]]);
+
+        let from_scene = build_scene_navmesh(&scene, &cfg, &[], &mut BuildContext::new()).unwrap();
+        let expected = build_navmesh(&prebaked, &cfg).unwrap();
+        assert!(!from_scene.poly_mesh.polys.is_empty());
+        assert_eq!(from_scene.poly_mesh.verts, expected.poly_mesh.verts);
+        assert_eq!(from_scene.poly_mesh.areas, expected.poly_mesh.areas);
+        assert!(from_scene.poly_mesh.areas.iter().all(|&a| a == 5));
+
+        // Standing upright the instance has nothing to walk on
+        let mut upright = Scene::new();
+        upright.add(wall, Mat4::IDENTITY, None);
+        assert_eq!(upright.walkable_areas(45.0), vec![vec![RC_NULL_AREA; 2]]);
+    }
+}
@@ -0,0 +1,165 @@
+use glam::Vec3;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use crate::debug_draw_b::InputMesh;
+
+// Binary STL: 80 byte header, triangle count, then 50 bytes per triangle
+const STL_HEADER_SIZE: usize = 84;
+const STL_TRIANGLE_SIZE: usize = 50;
+
+#[derive(Debug)]
+pub enum StlLoadError {
+    IoError(io::Error),
+    ParseError(String),
+}
+
+impl fmt::Display for StlLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StlLoadError::IoError(err) => write!(f, "{}", err),
+            StlLoadError::ParseError(message) => write!(f, "STL: {}", message),
+        }
+    }
+}
+
+impl From<io::Error> for StlLoadError {
+    fn from(error: io::Error) -> Self {
+        StlLoadError::IoError(error)
+    }
+}
+
+// Load a binary or ASCII STL file. STL stores every triangle with its own
+// corners, so corners with identical coordinates are merged into one vertex.
+pub fn load_stl<P: AsRef<Path>>(path: P) -> Result<InputMesh, StlLoadError> {
+    parse_stl(&std::fs::read(path)?)
+}
+
+pub fn parse_stl(bytes: &[u8]) -> Result<InputMesh, StlLoadError> {
+    let corners = if is_binary_stl(bytes) {
+        read_binary_corners(bytes)
+    } else {
+        let text = std::str::from_utf8(bytes)
+            .map_err(|_| StlLoadError::ParseError("neither binary nor ASCII STL".to_string()))?;
+        read_ascii_corners(text)?
+    };
+
+    let mut mesh = InputMesh::new();
+    let mut index_of: HashMap<[u32; 3], i32> = HashMap::new();
+    for corner in corners {
+        let key = corner.to_array().map(f32::to_bits);
+        let index = *index_of.entry(key).or_insert_with(|| {
+            mesh.verts.push(corner);
+            mesh.verts.len() as i32 - 1
+        });
+        mesh.tris.push(index);
+    }
+    mesh.compute_normals();
+    Ok(mesh)
+}
+
+// Many binary exporters start the header with "solid" too, so trust the
+// size implied by the triangle count over the first word
+fn is_binary_stl(bytes: &[u8]) -> bool {
+    if bytes.len() < STL_HEADER_SIZE {
+        return false;
+    }
+    let count = u32::from_le_bytes(bytes[80..84].try_into().unwrap()) as usize;
+    let expected = count
+        .checked_mul(STL_TRIANGLE_SIZE)
+        .and_then(|n| n.checked_add(STL_HEADER_SIZE));
+    expected == Some(bytes.len()) || !bytes.trim_ascii_start().starts_with(b"solid")
+}
+
+fn read_binary_corners(bytes: &[u8]) -> Vec<Vec3> {
+    // A truncated file keeps the triangles that are complete
+    let vec3 = |b: &[u8]| {
+        let f = |i: usize| f32::from_le_bytes(b[i..i + 4].try_into().unwrap());
+        Vec3::new(f(0), f(4), f(8))
+    };
+    bytes
+        .get(STL_HEADER_SIZE..)
+        .unwrap_or_default()
+        .chunks_exact(STL_TRIANGLE_SIZE)
+        .flat_map(|tri| (0..3).map(move |k| vec3(&tri[12 + k * 12..])))
+        .collect()
+}
+
+// Only the `vertex` lines matter; the facet normals are recomputed
+fn read_ascii_corners(text: &str) -> Result<Vec<Vec3>, StlLoadError> {
+    let mut corners = Vec::new();
+    for (line_no, line) in text.lines().enumerate() {
+        let mut tokens = line.split_whitespace();
+        if tokens.next() != Some("vertex") {
+            continue;
+        }
+        let mut coord = || {
+            tokens
+                .next()
+                .and_then(|t| t.parse::<f32>().ok())
+                .ok_or_else(|| {
+                    StlLoadError::ParseError(format!("line {}: bad vertex", line_no + 1))
+                })
+        };
+        corners.push(Vec3::new(coord()?, coord()?, coord()?));
+    }
+    if corners.len() % 3 != 0 {
+        return Err(StlLoadError::ParseError(format!(
+            "{} vertices do not make whole triangles",
+            corners.len()
+        )));
+    }
+    Ok(corners)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ascii_and_binary_stl_match() {
+        let ascii = "solid quad
+facet normal 0 1 0
+  outer loop
+    vertex 0 0 0
+    vertex 0 0 1
+    vertex 1 0 1
+  endloop
+endfacet
+facet normal 0 1 0
+  outer loop
+    vertex 0 0 0
+    vertex 1 0 1
+    vertex 1 0 0
+  endloop
+endfacet
+endsolid quad
+";
+        let mesh = parse_stl(ascii.as_bytes()).unwrap();
+        assert_eq!(mesh.verts.len(), 4);
+        assert_eq!(mesh.tris, vec![0, 1, 2, 0, 2, 3]);
+        assert!(mesh.validate().is_ok());
+
+        // The same quad in binary, with a header that misleadingly starts
+        // with "solid"
+        let mut binary = b"solid binary".to_vec();
+        binary.resize(80, 0);
+        binary.extend(2u32.to_le_bytes());
+        for tri in mesh.tris.chunks(3) {
+            binary.extend([0u8; 12]);
+            for &i in tri {
+                for c in mesh.verts[i as usize].to_array() {
+                    binary.extend(c.to_le_bytes());
+                }
+            }
+            binary.extend([0u8; 2]);
+        }
+        let from_binary = parse_stl(&binary).unwrap();
+        assert_eq!(from_binary.verts, mesh.verts);
+        assert_eq!(from_binary.tris, mesh.tris);
+
+        assert!(parse_stl(b"solid x\nvertex 0 0 0\n").is_err());
+    }
+}
@@ -0,0 +1,316 @@
+use glam::Vec3;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::debug_draw_b::InputMesh;
+
+// Procedural level pieces for probing the bake without OBJ assets: planes
+// and floors with holes for the basics, stairs and ramps for the agent's
+// climb and slope, bridges for overlapping floors, corridors for the agent
+// radius and buildings for all of them at once. Everything starts at the origin, extends along +x and +z and has
+// its walkable surfaces facing up.
+
+// Triangles collected as quads and boxes; `finish` fills in the normals
+#[derive(Default)]
+struct Geometry {
+    mesh: InputMesh,
+}
+
+impl Geometry {
+    // Two triangles a-b-c and a-c-d. Corners seen from above in the order
+    // -x-z, -x+z, +x+z, +x-z face up.
+    fn quad(&mut self, a: Vec3, b: Vec3, c: Vec3, d: Vec3) {
+        let base = self.mesh.verts.len() as i32;
+        self.mesh.verts.extend([a, b, c, d]);
+        self.mesh.tris.extend([0, 1, 2, 0, 2, 3].map(|i| base + i));
+    }
+
+    // A closed box, every face pointing out
+    fn cuboid(&mut self, min: Vec3, max: Vec3) {
+        let p = |x: bool, y: bool, z: bool| {
+            Vec3::new(
+                if x { max.x } else { min.x },
+                if y { max.y } else { min.y },
+                if z { max.z } else { min.z },
+            )
+        };
+        let (f, t) = (false, true);
+        self.quad(p(f, t, f), p(f, t, t), p(t, t, t), p(t, t, f)); // top
+        self.quad(p(f, f, f), p(t, f, f), p(t, f, t), p(f, f, t)); // bottom
+        self.quad(p(f, f, f), p(f, f, t), p(f, t, t), p(f, t, f)); // -x
+        self.quad(p(t, f, f), p(t, t, f), p(t, t, t), p(t, f, t)); // +x
+        self.quad(p(f, f, f), p(f, t, f), p(t, t, f), p(t, f, f)); // -z
+        self.quad(p(f, f, t), p(t, f, t), p(t, t, t), p(f, t, t)); // +z
+    }
+
+    // A quad wound so that it faces up, whichever way round the corners
+    // were given
+    fn surface(&mut self, a: Vec3, b: Vec3, c: Vec3, d: Vec3) {
+        if (b - a).cross(c - a).y >= 0.0 {
+            self.quad(a, b, c, d);
+        } else {
+            self.quad(d, c, b, a);
+        }
+    }
+
+    // A flat floor at height `y`
+    fn floor(&mut self, min_x: f32, min_z: f32, max_x: f32, max_z: f32, y: f32) {
+        self.quad(
+            Vec3::new(min_x, y, min_z),
+            Vec3::new(min_x, y, max_z),
+            Vec3::new(max_x, y, max_z),
+            Vec3::new(max_x, y, min_z),
+        );
+    }
+
+    fn finish(mut self) -> InputMesh {
+        self.mesh.compute_normals();
+        self.mesh
+    }
+}
+
+// A square of `size` as two triangles, tilted by `slope` degrees around
+// the z axis so that it rises along +x
+pub fn plane(size: f32, slope: f32) -> InputMesh {
+    let (sin, cos) = slope.to_radians().sin_cos();
+    let at = |x: f32, z: f32| Vec3::new(x * cos, x * sin, z);
+    let mut g = Geometry::default();
+    g.quad(at(0.0, 0.0), at(0.0, size), at(size, size), at(size, 0.0));
+    g.finish()
+}
+
+// A flat `size` x `size` grid of unit quads, leaving out the quads whose
+// corner satisfies `hole`
+pub fn holed_floor(size: usize, hole: impl Fn(usize, usize) -> bool) -> InputMesh {
+    let mut g = Geometry::default();
+    for z in 0..size {
+        for x in 0..size {
+            if !hole(x, z) {
+                let (x, z) = (x as f32, z as f32);
+                g.floor(x, z, x + 1.0, z + 1.0, 0.0);
+            }
+        }
+    }
+    g.finish()
+}
+
+// Length of the flat ground before stairs and ramps and of the landing
+// after them, enough for an agent to stand on at the usual cell sizes
+const LANDING: f32 = 2.0;
+
+// `steps` solid steps rising along +x, with flat ground before them and a
+// landing at the top. Walkable when `step_height` is within the agent's
+// climb.
+pub fn stairs(steps: usize, step_height: f32, step_depth: f32, width: f32) -> InputMesh {
+    let mut g = Geometry::default();
+    g.floor(-LANDING, 0.0, 0.0, width, 0.0);
+    for i in 0..steps {
+        let x = i as f32 * step_depth;
+        g.cuboid(
+            Vec3::new(x, 0.0, 0.0),
+            Vec3::new(x + step_depth, (i + 1) as f32 * step_height, width),
+        );
+    }
+    let top = steps as f32 * step_height;
+    let x = steps as f32 * step_depth;
+    g.cuboid(Vec3::new(x, 0.0, 0.0), Vec3::new(x + LANDING, top, width));
+    g.finish()
+}
+
+// A slope rising `rise` over `length` along +x between flat ground and a
+// landing. Walkable when atan(rise / length) is within the agent's slope.
+pub fn ramp(length: f32, rise: f32, width: f32) -> InputMesh {
+    let mut g = Geometry::default();
+    g.floor(-LANDING, 0.0, 0.0, width, 0.0);
+    g.quad(
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, width),
+        Vec3::new(length, rise, width),
+        Vec3::new(length, rise, 0.0),
+    );
+    g.cuboid(
+        Vec3::new(length, 0.0, 0.0),
+        Vec3::new(length + LANDING, rise, width),
+    );
+    g.finish()
+}
+
+// Two platforms `height` high joined by a deck spanning `span` along +x,
+// over ground running under it across the whole width. The ground and the
+// deck overlap, so both need to come out as separate layers.
+pub fn bridge(span: f32, width: f32, height: f32, deck_thickness: f32) -> InputMesh {
+    let mut g = Geometry::default();
+    let length = span + 2.0 * LANDING;
+    // The ground reaches past the bridge on both sides
+    g.floor(0.0, -LANDING, length, width + LANDING, 0.0);
+    g.cuboid(Vec3::ZERO, Vec3::new(LANDING, height, width));
+    g.cuboid(
+        Vec3::new(LANDING, height - deck_thickness, 0.0),
+        Vec3::new(LANDING + span, height, width),
+    );
+    g.cuboid(
+        Vec3::new(LANDING + span, 0.0, 0.0),
+        Vec3::new(length, height, width),
+    );
+    g.finish()
+}
+
+// A floor `length` long along +x between two walls `width` apart, for
+// checking which corridors the agent radius closes
+pub fn corridor(length: f32, width: f32, wall_height: f32) -> InputMesh {
+    const WALL_THICKNESS: f32 = 0.5;
+    let mut g = Geometry::default();
+    g.floor(0.0, 0.0, length, width, 0.0);
+    g.cuboid(
+        Vec3::new(0.0, 0.0, -WALL_THICKNESS),
+        Vec3::new(length, wall_height, 0.0),
+    );
+    g.cuboid(
+        Vec3::new(0.0, 0.0, width),
+        Vec3::new(length, wall_height, width + WALL_THICKNESS),
+    );
+    g.finish()
+}
+
+// `floors` square slabs of `size`, one every `floor_height`. Each floor
+// climbs to the next over a switchback outside the slabs: a ramp out from
+// the slab edge to a landing halfway up, then a ramp beside it back to the
+// slab above. Switchbacks alternate between the +x and -x sides, so each
+// one runs a full floor above the one two floors below it and never
+// crosses its neighbours.
+pub fn building(floors: usize, floor_height: f32, size: f32, ramp_width: f32) -> InputMesh {
+    const SLAB_THICKNESS: f32 = 0.2;
+    let mut g = Geometry::default();
+    for i in 0..floors {
+        let y = i as f32 * floor_height;
+        g.cuboid(
+            Vec3::new(0.0, y - SLAB_THICKNESS, 0.0),
+            Vec3::new(size, y, size),
+        );
+    }
+
+    // Each ramp rises half a floor over the length of a floor
+    let run = floor_height;
+    let w = ramp_width;
+    for i in 0..floors.saturating_sub(1) {
+        let y = i as f32 * floor_height;
+        let mid = y + floor_height * 0.5;
+        // `out` is the distance from the slab edge
+        let at = |out: f32, y: f32, z: f32| {
+            let x = if i % 2 == 0 { size + out } else { -out };
+            Vec3::new(x, y, z)
+        };
+        g.surface(
+            at(0.0, y, 0.0),
+            at(0.0, y, w),
+            at(run, mid, w),
+            at(run, mid, 0.0),
+        );
+        let (a, b) = (
+            at(run, mid - SLAB_THICKNESS, 0.0),
+            at(run + w, mid, 2.0 * w),
+        );
+        g.cuboid(a.min(b), a.max(b));
+        let top = y + floor_height;
+        g.surface(
+            at(0.0, top, w),
+            at(0.0, top, 2.0 * w),
+            at(run, mid, 2.0 * w),
+            at(run, mid, w),
+        );
+    }
+    g.finish()
+}
+
+// The mesh as Wavefront OBJ text, so generated pieces can be opened in the
+// viewer or other tools
+pub fn to_obj(mesh: &InputMesh) -> String {
+    let mut text = String::new();
+    for v in &mesh.verts {
+        text.push_str(&format!("v {} {} {}\n", v.x, v.y, v.z));
+    }
+    for tri in mesh.tris.chunks_exact(3) {
+        text.push_str(&format!("f {} {} {}\n", tri[0] + 1, tri[1] + 1, tri[2] + 1));
+    }
+    text
+}
+
+pub fn save_obj<P: AsRef<Path>>(mesh: &InputMesh, path: P) -> io::Result<()> {
+    fs::write(path, to_obj(mesh))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::navmesh_build::{build_navmesh, NavMeshBuildConfig};
+
+    fn config() -> NavMeshBuildConfig {
+        NavMeshBuildConfig::builder()
+            .cell_size(0.2)
+            .cell_height(0.1)
+            .agent_radius(0.3)
+            .agent_height(1.8)
+            .agent_max_climb(0.4)
+            .min_region_area(2)
+            .build()
+            .unwrap()
+    }
+
+    // Islands of the baked navmesh, and the height of its highest polygon
+    fn bake(mesh: &InputMesh) -> (usize, f32) {
+        let baked = build_navmesh(mesh, &config()).unwrap();
+        let top = (0..baked.poly_mesh.polys.len())
+            .flat_map(|ip| baked.poly_mesh.poly_world_verts(ip))
+            .map(|v| v.y)
+            .fold(f32::MIN, f32::max);
+        (baked.poly_mesh.islands().len(), top)
+    }
+
+    #[test]
+    fn test_generated_meshes_are_valid() {
+        for mesh in [
+            plane(4.0, 20.0),
+            holed_floor(4, |x, z| x == z),
+            stairs(5, 0.25, 0.4, 2.0),
+            ramp(4.0, 1.0, 2.0),
+            bridge(4.0, 2.0, 2.5, 0.3),
+            corridor(6.0, 1.0, 2.0),
+            building(3, 3.0, 6.0, 2.0),
+        ] {
+            assert!(mesh.validate().is_ok());
+            // Floors, ramps and box tops face up; nothing is degenerate
+            assert!(mesh.normals.iter().any(|n| n.y > 0.9));
+            assert!(mesh.normals.iter().all(|n| n.is_normalized()));
+        }
+
+        let obj = to_obj(&corridor(6.0, 1.0, 2.0));
+        assert!(obj.starts_with("v 0 0 0\n"));
+        assert_eq!(
+            obj.lines().filter(|l| l.starts_with("f ")).count(),
+            2 + 2 * 12
+        );
+    }
+
+    #[test]
+    fn test_pieces_probe_agent_limits() {
+        // Steps within the climb join the top landing to the ground
+        let (islands, top) = bake(&stairs(4, 0.25, 0.6, 2.0));
+        assert_eq!(islands, 1);
+        assert!(top > 0.9);
+        // Steps twice the climb cut every step off from the next
+        let (islands, _) = bake(&stairs(4, 0.8, 1.2, 2.0));
+        assert!(islands > 1);
+
+        // A corridor narrower than the agent is closed by the erosion
+        let (_, wide) = bake(&corridor(6.0, 1.5, 2.0));
+        assert!(wide > f32::MIN);
+        let narrow = build_navmesh(&corridor(6.0, 0.4, 2.0), &config()).unwrap();
+        assert!(narrow.poly_mesh.polys.is_empty());
+
+        // Floors joined by ramps walk as one piece up to the top slab
+        let (islands, top) = bake(&building(3, 3.0, 6.0, 2.0));
+        assert_eq!(islands, 1);
+        assert!(top > 5.9);
+    }
+}
@@ -0,0 +1,503 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+use glam::Vec3;
+
+use crate::build_context::BuildContext;
+use crate::chunky_tri_mesh::{ChunkyTriMesh, DEFAULT_TRIS_PER_CHUNK};
+use crate::compact_heightfield::CompactHeightfield;
+use crate::convex_volume::{
+    mark_box_area, mark_cylinder_area, mark_rotated_box_area, ConvexVolume,
+};
+use crate::debug_draw::RC_NULL_AREA;
+use crate::debug_draw_b::InputMesh;
+use crate::heightfield_layers::{build_heightfield_layers, HeightfieldLayer};
+use crate::nav_mesh::{NavMesh, NavMeshParams};
+use crate::navmesh_build::{
+    bake_compact, eroded_compact, mark_walkable_triangles, rasterize_filtered, tile_data,
+    NavMeshBuildConfig, NavMeshBuildError, TileGrid, MAX_TILE_POLYS,
+};
+use crate::offmesh::OffMeshConnection;
+use crate::tile_compressor::{NoCompression, TileCompressor};
+
+pub type ObstacleRef = u32;
+
+// Temporary obstacle cut out of the navmesh until removed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ObstacleShape {
+    // Upright cylinder standing on `pos`
+    Cylinder {
+        pos: Vec3,
+        radius: f32,
+        height: f32,
+    },
+    Box {
+        bmin: Vec3,
+        bmax: Vec3,
+    },
+    // Box turned by `y_rotation` radians around the Y axis
+    OrientedBox {
+        center: Vec3,
+        half_extents: Vec3,
+        y_rotation: f32,
+    },
+}
+
+impl ObstacleShape {
+    pub fn bounds(&self) -> (Vec3, Vec3) {
+        match *self {
+            ObstacleShape::Cylinder {
+                pos,
+                radius,
+                height,
+            } => (
+                pos - Vec3::new(radius, 0.0, radius),
+                pos + Vec3::new(radius, height, radius),
+            ),
+            ObstacleShape::Box { bmin, bmax } => (bmin, bmax),
+            ObstacleShape::OrientedBox {
+                center,
+                half_extents,
+                y_rotation,
+            } => {
+                let (sin, cos) = y_rotation.sin_cos();
+                let reach = Vec3::new(
+                    half_extents.x * cos.abs() + half_extents.z * sin.abs(),
+                    half_extents.y,
+                    half_extents.x * sin.abs() + half_extents.z * cos.abs(),
+                );
+                (center - reach, center + reach)
+            }
+        }
+    }
+
+    fn is_valid(&self) -> bool {
+        let (bmin, bmax) = self.bounds();
+        bmin.is_finite() && bmax.is_finite() && bmin.cmple(bmax).all()
+    }
+
+    // Clear the shape grown by `radius` on the XZ plane. The cached layers
+    // are already eroded, so this keeps agents clear of the obstacle.
+    fn mark(&self, chf: &mut CompactHeightfield, radius: f32) {
+        let grow = Vec3::new(radius, 0.0, radius);
+        match *self {
+            ObstacleShape::Cylinder {
+                pos,
+                radius: r,
+                height,
+            } => mark_cylinder_area(chf, pos, r + radius, height, RC_NULL_AREA),
+            ObstacleShape::Box { bmin, bmax } => {
+                mark_box_area(chf, bmin - grow, bmax + grow, RC_NULL_AREA)
+            }
+            ObstacleShape::OrientedBox {
+                center,
+                half_extents,
+                y_rotation,
+            } => mark_rotated_box_area(chf, center, half_extents + grow, y_rotation, RC_NULL_AREA),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TileCacheError {
+    // Non-finite or inside-out obstacle bounds
+    InvalidObstacle,
+    NoObstacle(ObstacleRef),
+}
+
+impl fmt::Display for TileCacheError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TileCacheError::InvalidObstacle => write!(f, "obstacle bounds are not valid"),
+            TileCacheError::NoObstacle(r) => write!(f, "no obstacle {}", r),
+        }
+    }
+}
+
+// Keeps the heightfield layers of every tile of a tiled build, so tiles
+// can be re-baked from them when temporary obstacles come and go without
+// rasterizing the input mesh again. Like Detour's dtTileCache, each layer
+// becomes a navmesh tile of its own.
+pub struct TileCache {
+    cfg: NavMeshBuildConfig,
+    grid: TileGrid,
+    // Packed and compressed layers of each tile, border included
+    tiles: HashMap<(i32, i32), Vec<Vec<u8>>>,
+    compressor: Box<dyn TileCompressor>,
+    volumes: Vec<ConvexVolume>,
+    off_mesh_connections: Vec<OffMeshConnection>,
+    obstacles: HashMap<ObstacleRef, ObstacleShape>,
+    next_obstacle: ObstacleRef,
+    // Tiles to re-bake on the next update
+    pending: BTreeSet<(i32, i32)>,
+}
+
+impl TileCache {
+    // Rasterize every tile of `mesh` into the cache and bake the navmesh
+    // the cache then keeps up to date
+    pub fn build(
+        mesh: &InputMesh,
+        cfg: &NavMeshBuildConfig,
+        volumes: &[ConvexVolume],
+        off_mesh_connections: &[OffMeshConnection],
+    ) -> Result<(Self, NavMesh), NavMeshBuildError> {
+        Self::build_with_compressor(
+            mesh,
+            cfg,
+            volumes,
+            off_mesh_connections,
+            Box::new(NoCompression),
+        )
+    }
+
+    // `build`, keeping the cached layers packed with `compressor`
+    pub fn build_with_compressor(
+        mesh: &InputMesh,
+        cfg: &NavMeshBuildConfig,
+        volumes: &[ConvexVolume],
+        off_mesh_connections: &[OffMeshConnection],
+        compressor: Box<dyn TileCompressor>,
+    ) -> Result<(Self, NavMesh), NavMeshBuildError> {
+        cfg.validate()?;
+        if mesh.tri_count() == 0 {
+            return Err(NavMeshBuildError::EmptyMesh);
+        }
+
+        let grid = TileGrid::new(mesh, cfg);
+        let areas = mark_walkable_triangles(cfg.agent_max_slope, mesh);
+        let chunky = ChunkyTriMesh::build(mesh, DEFAULT_TRIS_PER_CHUNK);
+        let mut cache = TileCache {
+            cfg: cfg.clone(),
+            grid,
+            tiles: HashMap::new(),
+            compressor,
+            volumes: volumes.to_vec(),
+            off_mesh_connections: off_mesh_connections.to_vec(),
+            obstacles: HashMap::new(),
+            next_obstacle: 1,
+            pending: BTreeSet::new(),
+        };
+        let mut ctx = BuildContext::new();
+        for (tx, ty) in cache.grid.coords() {
+            let mut hf = cache.grid.heightfield(tx, ty, cfg);
+            rasterize_filtered(&mut hf, mesh, Some(&chunky), &areas, cfg, &mut ctx);
+            let chf = eroded_compact(&hf, cfg, &mut ctx);
+            let layers =
+                build_heightfield_layers(&chf, cache.grid.border_size, cfg.walkable_height());
+            if !layers.is_empty() {
+                let packed = layers
+                    .iter()
+                    .map(|layer| cache.compressor.compress(&pack_layer(layer)))
+                    .collect::<Result<_, _>>()?;
+                cache.tiles.insert((tx, ty), packed);
+                cache.pending.insert((tx, ty));
+            }
+        }
+
+        let layer_count = cache.tiles.values().map(Vec::len).sum::<usize>();
+        let mut nav_mesh = NavMesh::new(NavMeshParams {
+            max_tiles: layer_count.max(1),
+            ..cache.grid.nav_mesh_params(MAX_TILE_POLYS)
+        })?;
+        cache.update(&mut nav_mesh)?;
+        Ok((cache, nav_mesh))
+    }
+
+    pub fn grid(&self) -> &TileGrid {
+        &self.grid
+    }
+
+    pub fn obstacles(&self) -> impl Iterator<Item = (ObstacleRef, &ObstacleShape)> {
+        self.obstacles.iter().map(|(&r, shape)| (r, shape))
+    }
+
+    // Bytes taken by the cached layers
+    pub fn stored_size(&self) -> usize {
+        self.tiles.values().flatten().map(Vec::len).sum()
+    }
+
+    // Tiles waiting for `update`
+    pub fn pending_tiles(&self) -> impl Iterator<Item = &(i32, i32)> {
+        self.pending.iter()
+    }
+
+    // Cut `shape` out of the navmesh from the next `update` on
+    pub fn add_obstacle(&mut self, shape: ObstacleShape) -> Result<ObstacleRef, TileCacheError> {
+        if !shape.is_valid() {
+            return Err(TileCacheError::InvalidObstacle);
+        }
+        let r = self.next_obstacle;
+        self.next_obstacle += 1;
+        self.obstacles.insert(r, shape);
+        self.touch(shape);
+        Ok(r)
+    }
+
+    pub fn remove_obstacle(&mut self, r: ObstacleRef) -> Result<(), TileCacheError> {
+        let shape = self
+            .obstacles
+            .remove(&r)
+            .ok_or(TileCacheError::NoObstacle(r))?;
+        self.touch(shape);
+        Ok(())
+    }
+
+    // Re-bake the tiles touched by obstacle changes and swap them into
+    // `nav_mesh`. Returns the number of tiles rebuilt. A tile that fails
+    // goes back on the queue along with the ones not reached yet, so the
+    // next call rebakes it whole.
+    pub fn update(&mut self, nav_mesh: &mut NavMesh) -> Result<usize, NavMeshBuildError> {
+        let mut rebuilt = 0;
+        while let Some(tile) = self.pending.pop_first() {
+            match self.rebake_tile(tile, nav_mesh) {
+                Ok(true) => rebuilt += 1,
+                Ok(false) => {}
+                Err(err) => {
+                    self.pending.insert(tile);
+                    return Err(err);
+                }
+            }
+        }
+        Ok(rebuilt)
+    }
+
+    // Re-bake every layer of a tile into `nav_mesh`, or nothing when the
+    // tile has no layers
+    fn rebake_tile(
+        &self,
+        (tx, ty): (i32, i32),
+        nav_mesh: &mut NavMesh,
+    ) -> Result<bool, NavMeshBuildError> {
+        let Some(packed) = self.tiles.get(&(tx, ty)) else {
+            return Ok(false);
+        };
+
+        // Obstacles reaching into the tile or its border
+        let (tile_min, tile_max) = self.padded_bounds(tx, ty);
+        let obstacles: Vec<&ObstacleShape> = self
+            .obstacles
+            .values()
+            .filter(|shape| {
+                let (bmin, bmax) = shape.bounds();
+                bmin.x <= tile_max.x
+                    && bmax.x >= tile_min.x
+                    && bmin.z <= tile_max.z
+                    && bmax.z >= tile_min.z
+            })
+            .collect();
+        let radius = self.cfg.walkable_radius() as f32 * self.cfg.cell_size;
+        for (li, packed) in packed.iter().enumerate() {
+            let layer = unpack_layer(&self.compressor.decompress(packed)?, &self.cfg);
+            let mut chf =
+                layer.to_compact_heightfield(self.cfg.walkable_height(), self.cfg.walkable_climb());
+            for shape in &obstacles {
+                shape.mark(&mut chf, radius);
+            }
+            let baked = bake_compact(
+                chf,
+                &self.cfg,
+                &self.volumes,
+                self.grid.border_size,
+                &mut BuildContext::new(),
+            );
+            let data = tile_data(
+                &baked,
+                &self.cfg,
+                tx,
+                ty,
+                li as i32,
+                &self.off_mesh_connections,
+            )?;
+
+            // The layer may not be in the navmesh yet, or have lost all
+            // its polygons to obstacles last time
+            let _ = nav_mesh.remove_tile(tx, ty, li as i32);
+            if let Some(data) = data {
+                nav_mesh.add_tile(data)?;
+            }
+        }
+        Ok(true)
+    }
+
+    // Queue every cached tile whose padded bounds `shape` overlaps
+    fn touch(&mut self, shape: ObstacleShape) {
+        let (bmin, bmax) = shape.bounds();
+        let pad = self.grid.border_size as f32 * self.cfg.cell_size;
+        for tile in self.grid.tiles_overlapping(bmin, bmax, pad) {
+            if self.tiles.contains_key(&tile) {
+                self.pending.insert(tile);
+            }
+        }
+    }
+
+    fn padded_bounds(&self, tx: i32, ty: i32) -> (Vec3, Vec3) {
+        let (bmin, bmax) = self.grid.tile_bounds(tx, ty);
+        let pad = self.grid.border_size as f32 * self.cfg.cell_size;
+        let pad = Vec3::new(pad, 0.0, pad);
+        (bmin - pad, bmax + pad)
+    }
+}
+
+// Size, bounds and then the heights, areas and connections of every cell,
+// little-endian
+fn pack_layer(layer: &HeightfieldLayer) -> Vec<u8> {
+    let mut out = Vec::with_capacity(28 + layer.heights.len() * 3);
+    out.extend_from_slice(&(layer.width as u16).to_le_bytes());
+    out.extend_from_slice(&(layer.height as u16).to_le_bytes());
+    for v in [layer.bmin, layer.bmax] {
+        for c in v.to_array() {
+            out.extend_from_slice(&c.to_le_bytes());
+        }
+    }
+    out.extend_from_slice(&layer.heights);
+    out.extend_from_slice(&layer.areas);
+    out.extend_from_slice(&layer.cons);
+    out
+}
+
+// Inverse of `pack_layer`; cell sizes aren't stored and come from `cfg`
+fn unpack_layer(packed: &[u8], cfg: &NavMeshBuildConfig) -> HeightfieldLayer {
+    let u16_at = |at: usize| u16::from_le_bytes([packed[at], packed[at + 1]]) as i32;
+    let f32_at = |at: usize| f32::from_le_bytes(packed[at..at + 4].try_into().unwrap());
+    let vec_at = |at: usize| Vec3::new(f32_at(at), f32_at(at + 4), f32_at(at + 8));
+    let (width, height) = (u16_at(0), u16_at(2));
+    let cells = (width * height) as usize;
+    let grid = &packed[28..];
+    HeightfieldLayer {
+        bmin: vec_at(4),
+        bmax: vec_at(16),
+        cs: cfg.cell_size,
+        ch: cfg.cell_height,
+        width,
+        height,
+        heights: grid[..cells].to_vec(),
+        areas: grid[cells..cells * 2].to_vec(),
+        cons: grid[cells * 2..cells * 3].to_vec(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nav_mesh::MeshTile;
+    use crate::testgen::plane;
+
+    // Whether any polygon of the navmesh lies over `pos`
+    fn walkable_at(nav_mesh: &NavMesh, pos: Vec3) -> bool {
+        nav_mesh.tiles().any(|tile: &MeshTile| {
+            (0..tile.data.header.off_mesh_base).any(|ip| tile.poly_height(ip, pos).is_some())
+        })
+    }
+
+    #[test]
+    fn test_pack_round_trip() {
+        let cfg = NavMeshBuildConfig::default();
+        let layer = HeightfieldLayer {
+            bmin: Vec3::new(-1.0, 0.5, 2.0),
+            bmax: Vec3::new(1.0, 3.5, 2.6),
+            cs: cfg.cell_size,
+            ch: cfg.cell_height,
+            width: 3,
+            height: 2,
+            heights: vec![0, 4, 0xff, 7, 2, 1],
+            areas: vec![63, 63, 0, 1, 63, 63],
+            cons: vec![0x04, 0x15, 0, 0x82, 0x0f, 0x01],
+        };
+        assert_eq!(unpack_layer(&pack_layer(&layer), &cfg), layer);
+    }
+
+    #[test]
+    fn test_obstacles_cut_and_restore_tiles() {
+        let cfg = NavMeshBuildConfig::builder()
+            .min_region_area(8)
+            .merge_region_area(20)
+            .tile_size(16)
+            .build()
+            .unwrap();
+        let (mut cache, mut nav_mesh) =
+            TileCache::build(&plane(12.0, 0.0), &cfg, &[], &[]).unwrap();
+        assert_eq!(nav_mesh.tiles().count(), 9);
+        assert_eq!(cache.pending_tiles().count(), 0);
+
+        // A pillar in the middle of the centre tile only touches that tile
+        let (tile_min, tile_max) = cache.grid().tile_bounds(1, 1);
+        let centre = (tile_min + tile_max) * 0.5 * Vec3::new(1.0, 0.0, 1.0);
+        assert!(walkable_at(&nav_mesh, centre));
+        let pillar = cache
+            .add_obstacle(ObstacleShape::Cylinder {
+                pos: centre - Vec3::Y,
+                radius: 0.5,
+                height: 3.0,
+            })
+            .unwrap();
+        assert_eq!(
+            cache.pending_tiles().copied().collect::<Vec<_>>(),
+            vec![(1, 1)]
+        );
+        assert_eq!(cache.update(&mut nav_mesh).unwrap(), 1);
+        assert!(!walkable_at(&nav_mesh, centre));
+        // The agent radius is kept clear around it
+        assert!(!walkable_at(&nav_mesh, centre + Vec3::new(0.9, 0.0, 0.0)));
+        assert!(walkable_at(&nav_mesh, centre + Vec3::new(2.0, 0.0, 0.0)));
+        assert_eq!(nav_mesh.tiles().count(), 9);
+
+        cache.remove_obstacle(pillar).unwrap();
+        assert_eq!(
+            cache.remove_obstacle(pillar).unwrap_err(),
+            TileCacheError::NoObstacle(pillar)
+        );
+        cache.update(&mut nav_mesh).unwrap();
+        assert!(walkable_at(&nav_mesh, centre));
+
+        let inside_out = ObstacleShape::Box {
+            bmin: Vec3::ONE,
+            bmax: Vec3::ZERO,
+        };
+        assert_eq!(
+            cache.add_obstacle(inside_out).unwrap_err(),
+            TileCacheError::InvalidObstacle
+        );
+    }
+
+    #[test]
+    fn test_failed_update_keeps_tiles_pending() {
+        use crate::tile_compressor::CompressionError;
+
+        struct Corrupt;
+        impl TileCompressor for Corrupt {
+            fn compress(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+                Ok(data.to_vec())
+            }
+            fn decompress(&self, _: &[u8]) -> Result<Vec<u8>, CompressionError> {
+                Err(CompressionError::Decompress("corrupt".into()))
+            }
+        }
+
+        let cfg = NavMeshBuildConfig::builder().tile_size(16).build().unwrap();
+        let (mut cache, mut nav_mesh) =
+            TileCache::build(&plane(12.0, 0.0), &cfg, &[], &[]).unwrap();
+        cache.compressor = Box::new(Corrupt);
+        cache.pending = cache.tiles.keys().copied().collect();
+        assert!(cache.update(&mut nav_mesh).is_err());
+        assert_eq!(cache.pending_tiles().count(), cache.tiles.len());
+    }
+
+    #[cfg(feature = "lz4")]
+    #[test]
+    fn test_compressed_cache() {
+        use crate::tile_compressor::Lz4Compressor;
+
+        let cfg = NavMeshBuildConfig::builder().tile_size(16).build().unwrap();
+        let (plain, plain_mesh) = TileCache::build(&plane(12.0, 0.0), &cfg, &[], &[]).unwrap();
+        let (packed, packed_mesh) = TileCache::build_with_compressor(
+            &plane(12.0, 0.0),
+            &cfg,
+            &[],
+            &[],
+            Box::new(Lz4Compressor),
+        )
+        .unwrap();
+        assert!(packed.stored_size() < plain.stored_size() / 2);
+        assert_eq!(packed_mesh.tiles().count(), plain_mesh.tiles().count());
+    }
+}
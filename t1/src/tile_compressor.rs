@@ -0,0 +1,121 @@
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CompressionError {
+    Compress(String),
+    // The data is corrupt or wasn't written by this compressor
+    Decompress(String),
+}
+
+impl fmt::Display for CompressionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompressionError::Compress(msg) => write!(f, "compression failed: {}", msg),
+            CompressionError::Decompress(msg) => write!(f, "decompression failed: {}", msg),
+        }
+    }
+}
+
+// Packs the tile data kept around by the tile cache, like Detour's
+// dtTileCacheCompressor
+pub trait TileCompressor: Send + Sync {
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError>;
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError>;
+}
+
+// Stores tiles as they are
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NoCompression;
+
+impl TileCompressor for NoCompression {
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        Ok(data.to_vec())
+    }
+
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        Ok(data.to_vec())
+    }
+}
+
+// Fast LZ4 block compression, the same trade-off as Detour's FastLZ
+#[cfg(feature = "lz4")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Lz4Compressor;
+
+#[cfg(feature = "lz4")]
+impl TileCompressor for Lz4Compressor {
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        Ok(lz4_flex::compress_prepend_size(data))
+    }
+
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        lz4_flex::decompress_size_prepended(data)
+            .map_err(|e| CompressionError::Decompress(e.to_string()))
+    }
+}
+
+// Slower but tighter zstd compression at `level`, 1 to 22
+#[cfg(feature = "zstd")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ZstdCompressor {
+    pub level: i32,
+}
+
+#[cfg(feature = "zstd")]
+impl Default for ZstdCompressor {
+    fn default() -> Self {
+        Self {
+            level: zstd::DEFAULT_COMPRESSION_LEVEL,
+        }
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl TileCompressor for ZstdCompressor {
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        zstd::bulk::compress(data, self.level)
+            .map_err(|e| CompressionError::Compress(e.to_string()))
+    }
+
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        zstd::stream::decode_all(data).map_err(|e| CompressionError::Decompress(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Mostly empty cells, like a sparse heightfield layer
+    fn sample() -> Vec<u8> {
+        (0..4096u32)
+            .map(|i| if i % 64 < 8 { (i / 64) as u8 } else { 0xff })
+            .collect()
+    }
+
+    fn round_trip(compressor: &dyn TileCompressor) -> usize {
+        let data = sample();
+        let packed = compressor.compress(&data).unwrap();
+        assert_eq!(compressor.decompress(&packed).unwrap(), data);
+        packed.len()
+    }
+
+    #[test]
+    fn test_no_compression_round_trip() {
+        assert_eq!(round_trip(&NoCompression), 4096);
+    }
+
+    #[cfg(feature = "lz4")]
+    #[test]
+    fn test_lz4_round_trip() {
+        assert!(round_trip(&Lz4Compressor) < 4096 / 4);
+        assert!(Lz4Compressor.decompress(&[0xff; 8]).is_err());
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_round_trip() {
+        assert!(round_trip(&ZstdCompressor::default()) < 4096 / 4);
+        assert!(ZstdCompressor::default().decompress(&[1, 2, 3]).is_err());
+    }
+}
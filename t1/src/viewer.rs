@@ -1,19 +1,74 @@
-use eframe::egui::{self, Color32, ViewportBuilder};
+use eframe::egui::{self, Color32};
 use egui::{Pos2, Vec2};
-use glam::{Mat4, Vec3, Vec4};
-use std::path::PathBuf;
+use glam::{Mat4, Quat, Vec3, Vec4};
+use std::collections::VecDeque;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+#[cfg(not(target_arch = "wasm32"))]
+use std::thread;
 
 // Import the debug draw implementation and obj loader
+use crate::bake_config::BakeConfig;
+use crate::build_context::{format_bytes, BuildContext, BuildStage};
+use crate::build_handle::BuildHandle;
+use crate::bvh::MeshBvh;
+use crate::chunky_tri_mesh::{ChunkyTriMesh, DEFAULT_TRIS_PER_CHUNK};
+use crate::compact_heightfield::CompactHeightfield;
+use crate::contours::ContourSet;
+use crate::convex_volume::{
+    load_volumes, volumes_from_text, volumes_path, volumes_to_text, ConvexVolume,
+};
+use crate::crowd::{Crowd, CrowdAgentParams};
+use crate::debug_draw::*;
 use crate::debug_draw_b::*;
-use crate::obj_loader::{self, ObjData};
+use crate::file_dialog::{self, FileDialogs, FilePurpose, PickedFile};
+use crate::frustum::Frustum;
+use crate::heightfield::Heightfield;
+use crate::islands::Island;
+use crate::jump_links::{build_jump_links, JumpKind, JumpLinkCandidate, JumpLinkConfig};
+use crate::mesh_import::{self, MeshImportError, IMPORTERS};
+use crate::mesh_stats::MeshStats;
+use crate::nav_mesh::{NavMesh, NavMeshCreateParams, NavMeshData, NavMeshError};
+use crate::nav_mesh_query::{NavMeshQuery, PointToPoint};
+use crate::navmesh_build::{
+    build_heightfield, eroded_compact, mesh_contours, partition_compact, trace_contours,
+    BakedNavMesh, NavMeshBuildError, MAX_VERTS_PER_POLY,
+};
+use crate::obj_loader::ObjLoadError;
+use crate::obstacle_avoidance::AvoidanceQuality;
+use crate::offmesh::OffMeshConnection;
+use crate::png;
+use crate::poly_mesh::RC_MESH_PORTAL_FLAG;
+use crate::project::{CameraBookmark, Project, PROJECT_EXTENSION};
+use crate::query_filter::QueryFilter;
+use crate::recent_files::RecentFiles;
+use crate::regions::{build_distance_field, PartitionType};
+#[cfg(feature = "wgpu")]
+use crate::wgpu_draw::{self, WgpuDebugDraw};
+
+// Corners, fill color, per-corner UVs and whether texturing was on
+type DrawTri = (Vec3, Vec3, Vec3, Color32, Vec2, Vec2, Vec2, bool);
+
+// Middle of a light square of the checker texture, which is pure white
+const CHECKER_WHITE_UV: Pos2 = Pos2::new(0.25, 0.25);
+
+// Ends, color and width in pixels
+type DrawLine = (Vec3, Vec3, Color32, f32);
+// Position, color and diameter in pixels
+type DrawPoint = (Vec3, Color32, f32);
 
 struct EguiDebugDraw {
-    lines: Vec<(Vec3, Vec3, Color32)>,
-    points: Vec<(Vec3, Color32)>,
-    tris: Vec<(Vec3, Vec3, Vec3, Color32, Vec2, Vec2, Vec2)>,
-    current_mode: i32,
-    texture_enabled: bool,
-    vertex_count: usize,
+    lines: Vec<DrawLine>,
+    points: Vec<DrawPoint>,
+    tris: Vec<DrawTri>,
+    batcher: PrimitiveBatcher,
+    palette: AreaPalette,
+    colors: DebugPalette,
+    // Multiplied into every vertex color while set
+    tint: Option<Vec4>,
 }
 
 impl EguiDebugDraw {
@@ -22,9 +77,10 @@ impl EguiDebugDraw {
             lines: Vec::new(),
             points: Vec::new(),
             tris: Vec::new(),
-            current_mode: 0,
-            texture_enabled: false,
-            vertex_count: 0,
+            batcher: PrimitiveBatcher::new(),
+            palette: AreaPalette::default(),
+            colors: DebugPalette::default(),
+            tint: None,
         }
     }
 
@@ -32,115 +88,291 @@ impl EguiDebugDraw {
         self.lines.clear();
         self.points.clear();
         self.tris.clear();
-        self.vertex_count = 0;
     }
-}
 
-impl DebugDraw for EguiDebugDraw {
-    fn begin(&mut self, prim: i32, _size: f32) {
-        self.current_mode = prim;
-        self.vertex_count = 0;
+    // Order the triangles far to near by the view-space depth of their
+    // centroids, so painting them in order puts near geometry on top. The
+    // sort is stable: overlays like the navmesh fill, drawn after the
+    // surface under them, stay on top where the centroids tie.
+    fn sort_back_to_front(&mut self, view: Mat4) {
+        let mut keyed: Vec<(f32, DrawTri)> = self
+            .tris
+            .drain(..)
+            .map(|tri| {
+                let centroid = (tri.0 + tri.1 + tri.2) / 3.0;
+                (view.transform_point3(centroid).z, tri)
+            })
+            .collect();
+        // Looking down -Z, the most negative depth is the furthest
+        keyed.sort_by(|a, b| a.0.total_cmp(&b.0));
+        self.tris.extend(keyed.into_iter().map(|(_, tri)| tri));
     }
+}
 
-    fn vertex(&mut self, pos: Vec3, color: Vec4) {
-        self.vertex_uv(pos, color, Vec2::new(0.0, 0.0));
+impl BatchedDebugDraw for EguiDebugDraw {
+    fn batcher(&mut self) -> &mut PrimitiveBatcher {
+        &mut self.batcher
     }
 
-    fn vertex_uv(&mut self, pos: Vec3, color: Vec4, uv: Vec2) {
-        let col = Color32::from_rgba_premultiplied(
-            (color.x * 255.0) as u8,
-            (color.y * 255.0) as u8,
-            (color.z * 255.0) as u8,
-            (color.w * 255.0) as u8,
-        );
+    fn flush(&mut self, batch: PrimitiveBatch) {
+        let tint = self.tint;
+        let col = |v: &BatchVertex| to_color32(tint.map_or(v.color, |tint| v.color * tint));
 
-        if self.current_mode == DU_DRAW_TRIS {
-            if self.vertex_count % 3 == 0 {
-                self.tris.push((pos, pos, pos, col, uv, uv, uv));
-            } else {
-                let tri = self.tris.last_mut().unwrap();
-                match self.vertex_count % 3 {
-                    1 => {
-                        tri.1 = pos;
-                        tri.5 = uv;
-                    }
-                    2 => {
-                        tri.2 = pos;
-                        tri.6 = uv;
-                    }
-                    _ => {}
-                }
+        match batch.prim {
+            DebugDrawPrimitive::Points => self
+                .points
+                .extend(batch.vertices.iter().map(|v| (v.pos, col(v), batch.size))),
+            DebugDrawPrimitive::Lines => self.lines.extend(
+                batch
+                    .vertices
+                    .chunks_exact(2)
+                    .map(|line| (line[0].pos, line[1].pos, col(&line[0]), batch.size)),
+            ),
+            DebugDrawPrimitive::Tris | DebugDrawPrimitive::Quads => {
+                self.tris.extend(batch.vertices.chunks_exact(3).map(|tri| {
+                    (
+                        tri[0].pos,
+                        tri[1].pos,
+                        tri[2].pos,
+                        col(&tri[0]),
+                        tri[0].uv,
+                        tri[1].uv,
+                        tri[2].uv,
+                        batch.textured,
+                    )
+                }))
             }
-            self.vertex_count += 1;
         }
     }
 
-    fn texture(&mut self, state: bool) {
-        self.texture_enabled = state;
+    fn area_colors(&self) -> &AreaPalette {
+        &self.palette
+    }
+
+    fn debug_palette(&self) -> &DebugPalette {
+        &self.colors
     }
+}
+
+// Debug draw colors are straight alpha, e.g. the translucent navmesh fill
+fn to_color32(color: Vec4) -> Color32 {
+    Color32::from_rgba_unmultiplied(
+        (color.x * 255.0) as u8,
+        (color.y * 255.0) as u8,
+        (color.z * 255.0) as u8,
+        (color.w * 255.0) as u8,
+    )
+}
 
-    fn end(&mut self) {}
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ProjectionMode {
+    Perspective,
+    // Half-height of the view volume in world units
+    Orthographic { scale: f32 },
+    // Looking straight down -Y; zoom is world units per screen height
+    TopDown { zoom: f32 },
 }
 
+// Fly-camera state saved while the top-down view is active
+#[derive(Debug, Clone, Copy)]
+struct SavedView {
+    position: Vec3,
+    rotation: Quat,
+    projection: ProjectionMode,
+}
+
+// How mouse and keyboard move the camera
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CameraMode {
+    // WASD to move, right-drag to look around
+    Fly,
+    // Turntable around a focus point: middle-drag orbits, shift+middle-drag
+    // pans and scroll zooms
+    Orbit,
+}
+
+// Distance from the eye to the near clip plane
+const NEAR_PLANE: f32 = 0.1;
+// Focus distance ahead of the camera when none is known
+const DEFAULT_FOCUS_DISTANCE: f32 = 5.0;
+
 struct Camera {
     position: Vec3,
-    yaw: f32,   // Horizontal rotation
-    pitch: f32, // Vertical rotation
+    // Orientation relative to looking down -Z with +Y up
+    rotation: Quat,
     fov: f32,
     aspect: f32,
+    projection: ProjectionMode,
+    saved_view: Option<SavedView>,
+    mode: CameraMode,
+    // Point orbited around in orbit mode
+    focus: Vec3,
 }
 
 impl Camera {
     fn new() -> Self {
         Self {
             position: Vec3::new(0.0, 2.0, 5.0),
-            yaw: -90.0_f32.to_radians(),
-            pitch: 0.0,
+            rotation: Quat::IDENTITY,
             fov: 60.0_f32.to_radians(),
             aspect: 1.0,
+            projection: ProjectionMode::Perspective,
+            saved_view: None,
+            mode: CameraMode::Fly,
+            focus: Vec3::new(0.0, 2.0, 5.0 - DEFAULT_FOCUS_DISTANCE),
         }
     }
 
-    fn view_matrix(&self) -> Mat4 {
-        let forward = Vec3::new(
-            self.yaw.cos() * self.pitch.cos(),
-            self.pitch.sin(),
-            self.yaw.sin() * self.pitch.cos(),
+    // Position camera at a good starting point relative to a model
+    fn frame_bounds(&mut self, min: Vec3, max: Vec3) {
+        let center = (min + max) * 0.5;
+        self.position = center + Vec3::new(0.0, 2.0, 5.0);
+        self.rotation = Quat::IDENTITY;
+        self.focus = self.position + self.forward() * DEFAULT_FOCUS_DISTANCE;
+    }
+
+    // Center the box in the view, backing off along the view direction
+    // until all of it fits
+    fn focus_on(&mut self, min: Vec3, max: Vec3) {
+        let center = (min + max) * 0.5;
+        let radius = ((max - min).length() * 0.5).max(0.5);
+        match &mut self.projection {
+            ProjectionMode::TopDown { zoom } => {
+                self.position = Vec3::new(center.x, max.y + 1.0, center.z);
+                *zoom = (max.x - min.x).max(max.z - min.z).max(1.0) * 1.1;
+            }
+            ProjectionMode::Orthographic { scale } => {
+                *scale = radius;
+                self.position = center - self.forward() * radius * 2.0;
+            }
+            ProjectionMode::Perspective => {
+                // The bounding sphere touches the narrower of the two view angles
+                let half_width = ((self.fov * 0.5).tan() * self.aspect).atan();
+                let half_fov = (self.fov * 0.5).min(half_width);
+                self.position = center - self.forward() * (radius / half_fov.sin());
+            }
+        }
+        self.focus = center;
+    }
+
+    fn set_mode(&mut self, mode: CameraMode) {
+        if mode == CameraMode::Orbit && self.mode != CameraMode::Orbit {
+            // Orbit around whatever is straight ahead
+            self.focus = self.position + self.forward() * DEFAULT_FOCUS_DISTANCE;
+        }
+        self.mode = mode;
+    }
+
+    fn forward(&self) -> Vec3 {
+        self.rotation.mul_vec3(Vec3::NEG_Z)
+    }
+
+    fn right(&self) -> Vec3 {
+        self.rotation.mul_vec3(Vec3::X)
+    }
+
+    fn up(&self) -> Vec3 {
+        self.rotation.mul_vec3(Vec3::Y)
+    }
+
+    fn is_orthographic(&self) -> bool {
+        matches!(
+            self.projection,
+            ProjectionMode::Orthographic { .. } | ProjectionMode::TopDown { .. }
         )
-        .normalize();
-        let right = forward.cross(Vec3::Y).normalize();
-        let up = right.cross(forward);
+    }
+
+    fn is_top_down(&self) -> bool {
+        matches!(self.projection, ProjectionMode::TopDown { .. })
+    }
+
+    fn toggle_projection(&mut self) {
+        match self.projection {
+            ProjectionMode::Perspective => {
+                self.projection = ProjectionMode::Orthographic { scale: 10.0 }
+            }
+            ProjectionMode::Orthographic { .. } => self.projection = ProjectionMode::Perspective,
+            ProjectionMode::TopDown { .. } => self.exit_top_down(),
+        }
+    }
 
-        // Create view matrix that properly handles w coordinate
-        let mut view = Mat4::IDENTITY;
+    // Look straight down on the given bounds, remembering the fly camera
+    fn enter_top_down(&mut self, min: Vec3, max: Vec3) {
+        if !self.is_top_down() {
+            self.saved_view = Some(SavedView {
+                position: self.position,
+                rotation: self.rotation,
+                projection: self.projection,
+            });
+        }
 
-        // Set rotation part
-        view.col_mut(0)[0] = right.x;
-        view.col_mut(0)[1] = right.y;
-        view.col_mut(0)[2] = right.z;
+        let center = (min + max) * 0.5;
+        let extent = (max.x - min.x).max(max.z - min.z).max(1.0);
+        self.position = Vec3::new(center.x, max.y + 1.0, center.z);
+        // Forward becomes -Y with -Z at the top of the screen
+        self.rotation = Quat::from_rotation_x(-90.0_f32.to_radians());
+        self.projection = ProjectionMode::TopDown { zoom: extent * 1.1 };
+    }
 
-        view.col_mut(1)[0] = up.x;
-        view.col_mut(1)[1] = up.y;
-        view.col_mut(1)[2] = up.z;
+    fn exit_top_down(&mut self) {
+        match self.saved_view.take() {
+            Some(saved) => {
+                self.position = saved.position;
+                self.rotation = saved.rotation;
+                self.projection = saved.projection;
+            }
+            None => self.projection = ProjectionMode::Perspective,
+        }
+    }
 
-        view.col_mut(2)[0] = -forward.x;
-        view.col_mut(2)[1] = -forward.y;
-        view.col_mut(2)[2] = -forward.z;
+    fn bookmark(&self, name: String) -> CameraBookmark {
+        CameraBookmark {
+            name,
+            position: self.position,
+            rotation: self.rotation,
+            focus: self.focus,
+        }
+    }
 
-        // Set translation part
-        view.col_mut(3)[0] = -right.dot(self.position);
-        view.col_mut(3)[1] = -up.dot(self.position);
-        view.col_mut(3)[2] = forward.dot(self.position);
-        view.col_mut(3)[3] = 1.0;
+    fn go_to(&mut self, bookmark: &CameraBookmark) {
+        if self.is_top_down() {
+            self.exit_top_down();
+        }
+        self.position = bookmark.position;
+        self.rotation = bookmark.rotation;
+        self.focus = bookmark.focus;
+    }
 
-        view
+    fn view_matrix(&self) -> Mat4 {
+        // Rows of the view rotation are the camera basis vectors
+        Mat4::look_to_rh(self.position, self.forward(), self.up())
     }
 
     fn projection_matrix(&self) -> Mat4 {
-        let f = 1.0 / (self.fov / 2.0).tan();
-        let near = 0.1;
+        let near = NEAR_PLANE;
         let far = 100.0;
 
+        let ortho_half_height = match self.projection {
+            ProjectionMode::Perspective => None,
+            ProjectionMode::Orthographic { scale } => Some(scale),
+            ProjectionMode::TopDown { zoom } => Some(zoom * 0.5),
+        };
+        if let Some(scale) = ortho_half_height {
+            // Standard OpenGL orthographic matrix with symmetric half-widths
+            let right = scale * self.aspect;
+            let top = scale;
+
+            let mut proj = Mat4::IDENTITY;
+            proj.col_mut(0)[0] = 1.0 / right;
+            proj.col_mut(1)[1] = 1.0 / top;
+            proj.col_mut(2)[2] = -2.0 / (far - near);
+            proj.col_mut(3)[2] = -(far + near) / (far - near);
+
+            return proj;
+        }
+
+        let f = 1.0 / (self.fov / 2.0).tan();
+
         // Create perspective projection matrix with proper w coordinate handling
         let mut proj = Mat4::ZERO;
         proj.col_mut(0)[0] = f / self.aspect;
@@ -152,21 +384,115 @@ impl Camera {
         proj
     }
 
-    fn update(&mut self, ui: &egui::Ui) {
-        let delta_time = ui.input(|i| i.unstable_dt) as f32;
+    // `cursor_depth` is the distance along the cursor ray to the geometry
+    // under the pointer, if any
+    fn update(&mut self, ui: &egui::Ui, rect: egui::Rect, cursor_depth: Option<f32>) {
+        let delta_time = ui.input(|i| i.unstable_dt);
         let move_speed = 5.0 * delta_time;
         let rotate_speed = 1.0 * delta_time;
 
+        // Scrolling only applies while the pointer is over the viewport
+        let cursor = ui
+            .input(|i| i.pointer.hover_pos())
+            .filter(|pos| rect.contains(*pos));
+        let scroll = match cursor {
+            Some(_) => ui.input(|i| i.raw_scroll_delta.y),
+            None => 0.0,
+        };
+
+        if let ProjectionMode::TopDown { zoom } = &mut self.projection {
+            *zoom = (*zoom * (-scroll * 0.002).exp()).max(0.1);
+
+            // Pan in the XZ plane at a speed relative to the visible area
+            let pan_speed = *zoom * 0.5 * delta_time;
+            ui.input(|i| {
+                if i.key_down(egui::Key::W) {
+                    self.position.z -= pan_speed;
+                }
+                if i.key_down(egui::Key::S) {
+                    self.position.z += pan_speed;
+                }
+                if i.key_down(egui::Key::A) {
+                    self.position.x -= pan_speed;
+                }
+                if i.key_down(egui::Key::D) {
+                    self.position.x += pan_speed;
+                }
+            });
+            return;
+        }
+
+        if self.mode == CameraMode::Orbit {
+            self.update_orbit(ui, rect, scroll);
+            return;
+        }
+
         if ui.input(|i| i.pointer.secondary_down()) {
             let delta = ui.input(|i| i.pointer.delta());
-            self.yaw += delta.x * 0.005;
-            self.pitch =
-                (self.pitch - delta.y * 0.005).clamp(-89.0_f32.to_radians(), 89.0_f32.to_radians());
+            const SENSITIVITY: f32 = 0.005;
+            // Pitch around the camera's right axis, yaw around world up
+            let delta_rotation = Quat::from_axis_angle(self.right(), -delta.y * SENSITIVITY)
+                * Quat::from_axis_angle(Vec3::Y, -delta.x * SENSITIVITY);
+            self.rotation = (delta_rotation * self.rotation).normalize();
+        }
+
+        // Q/E roll around the view direction; with shift they move down/up instead
+        let (roll_left, roll_right) = ui.input(|i| {
+            (
+                !i.modifiers.shift && i.key_down(egui::Key::Q),
+                !i.modifiers.shift && i.key_down(egui::Key::E),
+            )
+        });
+        if roll_left || roll_right {
+            let angle = if roll_left {
+                -rotate_speed
+            } else {
+                rotate_speed
+            };
+            self.rotation =
+                (Quat::from_axis_angle(self.forward(), angle) * self.rotation).normalize();
+        }
+
+        if let ProjectionMode::Orthographic { scale } = &mut self.projection {
+            // Scroll zooms the view volume instead of moving the camera
+            *scale = (*scale * (-scroll * 0.002).exp()).max(0.1);
+
+            // Pan in the screen plane
+            let right = self.right();
+            let up = self.up();
+
+            ui.input(|i| {
+                if i.key_down(egui::Key::W) {
+                    self.position += up * move_speed;
+                }
+                if i.key_down(egui::Key::S) {
+                    self.position -= up * move_speed;
+                }
+                if i.key_down(egui::Key::A) {
+                    self.position -= right * move_speed;
+                }
+                if i.key_down(egui::Key::D) {
+                    self.position += right * move_speed;
+                }
+            });
+            return;
+        }
+
+        if let Some(cursor) = cursor.filter(|_| scroll != 0.0) {
+            self.dolly_to_cursor(cursor, rect, scroll, cursor_depth);
         }
 
-        let forward = Vec3::new(self.yaw.cos(), 0.0, self.yaw.sin()).normalize();
+        // Walk in the horizontal plane; when looking straight up or down
+        // the camera's up vector gives the heading instead
+        let forward = self.forward();
+        let forward = Vec3::new(forward.x, 0.0, forward.z)
+            .try_normalize()
+            .unwrap_or_else(|| {
+                let up = self.up();
+                Vec3::new(up.x, 0.0, up.z).normalize_or_zero() * forward.y.signum()
+            });
 
-        let right = forward.cross(Vec3::Y).normalize();
+        let right = forward.cross(Vec3::Y).normalize_or_zero();
 
         ui.input(|i| {
             if i.key_down(egui::Key::W) {
@@ -181,311 +507,3620 @@ impl Camera {
             if i.key_down(egui::Key::D) {
                 self.position += right * move_speed;
             }
-            if i.key_down(egui::Key::E) {
+            if i.modifiers.shift && i.key_down(egui::Key::E) {
                 self.position.y += move_speed;
             }
-            if i.key_down(egui::Key::Q) {
+            if i.modifiers.shift && i.key_down(egui::Key::Q) {
                 self.position.y -= move_speed;
             }
         });
     }
-}
-
-pub struct MeshViewerApp {
-    mesh: InputMesh,
-    debug_draw: EguiDebugDraw,
-    camera: Camera,
-    walkable_slope_angle: f32,
-    obj_path: Option<PathBuf>,
-}
-
-fn obj_to_input_mesh(obj: &ObjData) -> InputMesh {
-    let mut mesh = InputMesh::new();
 
-    // Convert vertices
-    mesh.verts = obj
-        .vertices
-        .iter()
-        .skip(1)
-        .map(|v| Vec3::new(v.x, v.y, v.z))
-        .collect();
+    fn update_orbit(&mut self, ui: &egui::Ui, rect: egui::Rect, scroll: f32) {
+        let (dragging, delta, shift) = ui.input(|i| {
+            (
+                i.pointer.middle_down(),
+                i.pointer.delta(),
+                i.modifiers.shift,
+            )
+        });
 
-    // Triangulate faces and add indices
-    let triangles = obj.triangulate();
-    mesh.tris = triangles
-        .iter()
-        .flat_map(|tri| {
-            // Adjust indices to be 0-based
-            vec![tri[0] - 1, tri[1] - 1, tri[2] - 1].into_iter()
-        })
-        .map(|i| i as i32)
-        .collect();
+        if dragging && shift {
+            // Pan so the point under the cursor at the focus depth follows it
+            let half_height = match self.projection {
+                ProjectionMode::Orthographic { scale } => scale,
+                _ => self.position.distance(self.focus) * (self.fov * 0.5).tan(),
+            };
+            let units_per_pixel = 2.0 * half_height / rect.height().max(1.0);
+            let offset = (self.up() * delta.y - self.right() * delta.x) * units_per_pixel;
+            self.position += offset;
+            self.focus += offset;
+        } else if dragging {
+            self.orbit(delta.x, delta.y);
+        }
 
-    // Calculate normals for each vertex in each triangle
-    mesh.normals = Vec::new();
-    for chunk in mesh.tris.chunks(3) {
-        if chunk.len() == 3 {
-            let v0 = mesh.verts[chunk[0] as usize];
-            let v1 = mesh.verts[chunk[1] as usize];
-            let v2 = mesh.verts[chunk[2] as usize];
-            let normal = (v1 - v0).cross(v2 - v0).normalize();
-            // Add the same normal for all three vertices of this triangle
-            mesh.normals.push(normal);
-            mesh.normals.push(normal);
-            mesh.normals.push(normal);
+        if let ProjectionMode::Orthographic { scale } = &mut self.projection {
+            *scale = (*scale * (-scroll * 0.002).exp()).max(0.1);
+        } else if scroll != 0.0 {
+            let distance = self.position.distance(self.focus) * (-scroll * 0.002).exp();
+            self.position = self.focus - self.forward() * distance.max(NEAR_PLANE * 2.0);
         }
     }
 
-    mesh
-}
+    // Turn around the focus by a mouse movement in pixels: yaw around world
+    // up, pitch around the camera's right axis short of the poles
+    fn orbit(&mut self, dx: f32, dy: f32) {
+        const SENSITIVITY: f32 = 0.005;
+        let distance = self.position.distance(self.focus);
 
-impl MeshViewerApp {
-    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
-        // Create default mesh in case loading fails
-        let mut default_mesh = InputMesh::new();
-        default_mesh.verts = vec![
-            Vec3::new(-1.0, 0.0, -1.0),
-            Vec3::new(1.0, 0.0, -1.0),
-            Vec3::new(1.0, 1.0, 1.0),
-            Vec3::new(-1.0, 1.0, 1.0),
-        ];
-        default_mesh.tris = vec![0, 1, 2, 0, 2, 3];
-        let normal1 = (default_mesh.verts[1] - default_mesh.verts[0])
-            .cross(default_mesh.verts[2] - default_mesh.verts[0])
-            .normalize();
-        let normal2 = (default_mesh.verts[2] - default_mesh.verts[0])
-            .cross(default_mesh.verts[3] - default_mesh.verts[0])
-            .normalize();
-        default_mesh.normals = vec![normal1, normal1, normal1, normal2, normal2, normal2];
-
-        // Try to load dungeon.obj
-        let dungeon_path = PathBuf::from("./dungeon.obj");
-        let (mesh, obj_path) = if let Ok(obj_data) = obj_loader::load_obj(&dungeon_path) {
-            println!("Successfully loaded dungeon.obj");
-            (obj_to_input_mesh(&obj_data), Some(dungeon_path))
+        let yawed = (Quat::from_axis_angle(Vec3::Y, -dx * SENSITIVITY) * self.rotation).normalize();
+        let pitched =
+            (Quat::from_axis_angle(yawed.mul_vec3(Vec3::X), -dy * SENSITIVITY) * yawed).normalize();
+        // Pitching over the top would flip the view upside down
+        self.rotation = if pitched.mul_vec3(Vec3::NEG_Z).y.abs() < 0.99 {
+            pitched
         } else {
-            println!("Failed to load dungeon.obj, using default mesh");
-            (default_mesh, None)
+            yawed
         };
+        self.position = self.focus - self.forward() * distance;
+    }
 
-        // Create initial camera position
-        let mut camera = Camera::new();
-        if let Some(path) = &obj_path {
-            // If we loaded the obj, adjust camera to fit the model
-            if let Ok(obj_data) = obj_loader::load_obj(path) {
-                let (min, max) = obj_data.get_bounds();
-                let center = Vec3::new(
-                    (min.x + max.x) * 0.5,
-                    (min.y + max.y) * 0.5,
-                    (min.z + max.z) * 0.5,
-                );
-                camera.position = center + Vec3::new(0.0, 2.0, 5.0);
-                camera.yaw = -90.0_f32.to_radians();
-                camera.pitch = 0.0;
-            }
-        }
+    // Move along the ray under the cursor by a fraction of the distance to
+    // whatever is there, so zooming slows on approach and never reaches the
+    // near plane. Without geometry under the cursor a fixed depth is used.
+    fn dolly_to_cursor(&mut self, cursor: Pos2, rect: egui::Rect, scroll: f32, depth: Option<f32>) {
+        const DEFAULT_DEPTH: f32 = 5.0;
+        const SCROLL_SPEED: f32 = 0.002;
 
-        Self {
-            mesh,
-            debug_draw: EguiDebugDraw::new(),
-            camera,
-            walkable_slope_angle: 45.0,
-            obj_path,
+        let (origin, dir) = screen_ray(cursor, self, rect);
+        // screen_ray starts on the near plane; measure from the eye instead
+        let depth = depth.unwrap_or(DEFAULT_DEPTH) + origin.distance(self.position);
+
+        // 1 - e^-x maps any scroll to less than the full distance going in
+        // and grows smoothly going out
+        let step = depth * (1.0 - (-scroll * SCROLL_SPEED).exp());
+        let step = step.min(depth - NEAR_PLANE * 2.0);
+        if scroll > 0.0 && step <= 0.0 {
+            // Already as close as allowed; don't let zooming in back away
+            return;
         }
+        self.position += dir * step;
     }
+}
 
-    fn load_obj(&mut self, path: PathBuf) {
-        if let Ok(obj_data) = obj_loader::load_obj(&path) {
-            self.mesh = obj_to_input_mesh(&obj_data);
-            self.obj_path = Some(path);
+// The mesh and its BVH plus a description of every line that had to be skipped
+type LoadResult = Result<(InputMesh, MeshBvh, Vec<String>), MeshImportError>;
 
-            // Adjust camera to fit the model
-            let (min, max) = obj_data.get_bounds();
-            let center = Vec3::new(
-                (min.x + max.x) * 0.5,
-                (min.y + max.y) * 0.5,
-                (min.z + max.z) * 0.5,
-            );
+enum LoadMessage {
+    Progress(f32),
+    Finished(Box<LoadResult>),
+}
+
+// A mesh file being parsed on a background thread
+struct LoadJob {
+    path: PathBuf,
+    progress: f32,
+    cancel: Arc<AtomicBool>,
+    receiver: Receiver<LoadMessage>,
+}
+
+impl LoadJob {
+    fn spawn(ctx: &egui::Context, file: PickedFile) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
 
-            // Position camera at a good starting point relative to the model
-            self.camera.position = center + Vec3::new(0.0, 2.0, 5.0);
-            self.camera.yaw = -90.0_f32.to_radians();
-            self.camera.pitch = 0.0;
+        let path = file.path.clone();
+        let worker_cancel = Arc::clone(&cancel);
+        let worker_ctx = ctx.clone();
+        let work = move || {
+            let progress_sender = sender.clone();
+            let progress_ctx = worker_ctx.clone();
+            let progress = move |fraction| {
+                let _ = progress_sender.send(LoadMessage::Progress(fraction));
+                progress_ctx.request_repaint();
+            };
+
+            // Build the BVH here too so large meshes don't stall the UI
+            let imported = match &file.contents {
+                Some(contents) => {
+                    mesh_import::import_mesh_bytes(&file.path, contents, &progress, &worker_cancel)
+                }
+                None => mesh_import::import_mesh(&file.path, &progress, &worker_cancel),
+            };
+            let result = imported.map(|(mesh, warnings)| {
+                let bvh = MeshBvh::build(&mesh);
+                (mesh, bvh, warnings)
+            });
+
+            // The receiver is gone if the job was abandoned
+            let _ = sender.send(LoadMessage::Finished(Box::new(result)));
+            worker_ctx.request_repaint();
+        };
+        // The browser has no threads, so there the mesh is loaded before
+        // the next frame
+        #[cfg(not(target_arch = "wasm32"))]
+        thread::spawn(work);
+        #[cfg(target_arch = "wasm32")]
+        work();
+
+        Self {
+            path,
+            progress: 0.0,
+            cancel,
+            receiver,
         }
     }
+}
 
-    fn draw_mesh(&mut self) {
-        self.debug_draw.clear();
-        du_debug_draw_tri_mesh_slope(
-            &mut self.debug_draw,
-            &self.mesh,
-            self.walkable_slope_angle,
-            1.0,
-        );
+impl Drop for LoadJob {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
     }
 }
 
-fn pos_to_screen(pos: Vec3, camera: &Camera, rect: egui::Rect) -> Option<Pos2> {
-    let view_proj = camera.projection_matrix() * camera.view_matrix();
-    // Convert Vec3 to Vec4 for clip space
-    let clip_pos = view_proj.project_point3(pos);
-    let clip_pos = Vec4::new(clip_pos.x, clip_pos.y, clip_pos.z, 1.0);
+// Input triangles drawn per frame unless changed in the View panel
+const DEFAULT_TRIANGLE_BUDGET: usize = 500_000;
 
-    // Handle near plane clipping - if point is behind or very close to camera
-    if clip_pos.z <= 0.001 {
-        return None;
-    }
+// Frames of a turntable capture unless changed in the Capture menu
+const DEFAULT_TURNTABLE_FRAMES: usize = 36;
 
-    // Perspective divide
-    let w = clip_pos.w;
-    let ndc = Vec3::new(clip_pos.x / w, clip_pos.y / w, clip_pos.z / w);
+// A capture waiting on screenshots of the window
+enum Capture {
+    Screenshot(PathBuf),
+    // One frame per step of a full turn around the camera focus, starting
+    // from and returning to the view the capture began at
+    Turntable {
+        dir: PathBuf,
+        frame: usize,
+        frames: usize,
+        start_position: Vec3,
+        start_rotation: Quat,
+    },
+}
 
-    // More lenient frustum culling - allow points slightly outside the frustum
-    // This helps prevent lines from disappearing near screen edges
-    const MARGIN: f32 = 0.2; // 20% margin
-    if ndc.x < -1.0 - MARGIN
-        || ndc.x > 1.0 + MARGIN
-        || ndc.y < -1.0 - MARGIN
-        || ndc.y > 1.0 + MARGIN
-        || ndc.z > 1.0 + MARGIN
-    {
-        return None;
+// The part of a window screenshot covering `rect`, in points
+fn crop_to(image: &egui::ColorImage, rect: egui::Rect, pixels_per_point: f32) -> egui::ColorImage {
+    let [width, height] = image.size;
+    let full = egui::Rect::from_min_size(
+        Pos2::ZERO,
+        egui::vec2(width as f32, height as f32) / pixels_per_point,
+    );
+    let rect = rect.intersect(full);
+    if !rect.is_positive() {
+        return image.clone();
     }
+    image.region(&rect, Some(pixels_per_point))
+}
 
-    // Clamp coordinates to screen bounds
-    let x = (ndc.x * 0.5 + 0.5).clamp(0.0, 1.0) * rect.width() + rect.min.x;
-    let y = (1.0 - (ndc.y * 0.5 + 0.5)).clamp(0.0, 1.0) * rect.height() + rect.min.y;
+// Everything a background bake produces, handed to the UI in one piece
+struct BuiltNavMesh {
+    hf: Heightfield,
+    chf: CompactHeightfield,
+    cset: ContourSet,
+    baked: BakedNavMesh,
+    nav_mesh: Result<NavMesh, NavMeshError>,
+    build_ctx: BuildContext,
+    agent_radius: f32,
+    agent_height: f32,
+    pruned_polys: usize,
+}
 
-    Some(Pos2::new(x, y))
+type BuildJob = BuildHandle<Result<Box<BuiltNavMesh>, NavMeshBuildError>>;
+
+// Authoring state for off-mesh connections
+struct OffMeshTool {
+    enabled: bool,
+    radius: f32,
+    bidirectional: bool,
+    area: u8,
+    // First click of a connection being placed
+    pending_start: Option<Vec3>,
+    selected: Option<usize>,
+    // Connection and end (0 start, 1 end) following the pointer
+    dragging: Option<(usize, usize)>,
+    // Draw the connections as linked into the runtime navmesh too
+    show_baked: bool,
 }
 
-impl eframe::App for MeshViewerApp {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        egui::CentralPanel::default().show(ctx, |ui| {
-            // Add file picker
-            ui.horizontal(|ui| {
-                if ui.button("Load OBJ").clicked() {
-                    if let Some(path) = rfd::FileDialog::new()
-                        .add_filter("OBJ files", &["obj"])
-                        .pick_file()
-                    {
-                        self.load_obj(path);
-                    }
-                }
+impl OffMeshTool {
+    fn new() -> Self {
+        Self {
+            enabled: false,
+            radius: 0.6,
+            bidirectional: true,
+            area: 63,
+            pending_start: None,
+            selected: None,
+            dragging: None,
+            show_baked: true,
+        }
+    }
+}
 
-                if let Some(path) = &self.obj_path {
-                    ui.label(format!("Loaded: {}", path.display()));
-                }
+// Authoring state for convex volumes
+struct ConvexVolumeTool {
+    enabled: bool,
+    area: u8,
+    // Prism extent above and below the lowest clicked point
+    height: f32,
+    descent: f32,
+    // Points of the volume being placed; the hull is taken when closed
+    points: Vec<Vec3>,
+    selected: Option<usize>,
+}
 
-                ui.separator();
+impl ConvexVolumeTool {
+    fn new() -> Self {
+        Self {
+            enabled: false,
+            area: 2,
+            height: 6.0,
+            descent: 1.0,
+            points: Vec::new(),
+            selected: None,
+        }
+    }
+}
 
-                ui.label("Walkable Slope Angle:");
-                ui.add(egui::Slider::new(&mut self.walkable_slope_angle, 0.0..=90.0));
-            });
+// What a click does in the crowd tool
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CrowdClick {
+    // Add an agent, or select the one clicked
+    PlaceAgent,
+    // Send every agent to the clicked point
+    SetTarget,
+}
 
-            // Update camera before drawing
-            self.camera.update(ui);
+// Crowd simulation state, reset whenever the navmesh is rebuilt
+struct CrowdTool {
+    enabled: bool,
+    click: CrowdClick,
+    running: bool,
+    params: CrowdAgentParams,
+    draw: CrowdDrawOptions,
+    // The shared target, given to agents placed after it was set too
+    target: Option<Vec3>,
+    selected: Option<usize>,
+}
 
-            self.draw_mesh();
+impl CrowdTool {
+    fn new() -> Self {
+        Self {
+            enabled: false,
+            click: CrowdClick::PlaceAgent,
+            running: true,
+            params: CrowdAgentParams::default(),
+            draw: CrowdDrawOptions::default(),
+            target: None,
+            selected: None,
+        }
+    }
+}
 
-            let (rect, _response) = ui.allocate_exact_size(ui.available_size(), egui::Sense::drag());
-            self.camera.aspect = rect.width() / rect.height();
+// Cap on the agents the crowd tool can place
+const MAX_CROWD_AGENTS: usize = 128;
 
-            // Reset camera position when R is pressed
-            if ui.input(|i| i.key_pressed(egui::Key::R)) {
-                self.camera = Camera::new();
-            }
+// Start and end points of the navmesh test tool, with the queries between
+// them rerun whenever either point or the navmesh changes
+struct NavMeshTestTool {
+    enabled: bool,
+    start: Option<Vec3>,
+    end: Option<Vec3>,
+    result: PointToPoint,
+    error: Option<String>,
+}
 
-            let painter = ui.painter();
+impl NavMeshTestTool {
+    // Box searched for the nearest polygon around each point, as in the
+    // Recast demo
+    const HALF_EXTENTS: Vec3 = Vec3::new(2.0, 4.0, 2.0);
+    const MAX_NODES: usize = 2048;
 
-            // Draw all triangle
-            for tri in &self.debug_draw.tris {
-                let points = vec![
-                    pos_to_screen(tri.0, &self.camera, rect),
-                    pos_to_screen(tri.1, &self.camera, rect),
-                    pos_to_screen(tri.2, &self.camera, rect),
-                ];
-            
-                // Convert Vec<Option<Pos2>> to Vec<Pos2> by filtering out None values
-                let valid_points: Vec<Pos2> = points.into_iter()
-                    .filter_map(|p| p)  // Removes None values and unwraps Some values
-                    .collect();
-            
-                // Only draw if we have all three points (no points were culled)
-                if valid_points.len() == 3 {
-                    painter.add(egui::Shape::convex_polygon(
-                        valid_points,
-                        tri.3,  // Fill color
-                        (1.0, tri.3),  // Stroke width and color
-                    ));
-                }
-            }
+    fn new() -> Self {
+        Self {
+            enabled: false,
+            start: None,
+            end: None,
+            result: PointToPoint::default(),
+            error: None,
+        }
+    }
 
-            // Add control instructions
-            ui.painter().text(
-                rect.min + egui::vec2(10.0, 10.0),
-                egui::Align2::LEFT_TOP,
-                "Controls:\nWASD - Move\nQ/E - Up/Down\nRight Click + Drag - Look\nR - Reset Camera",
-                egui::FontId::proportional(14.0),
-                Color32::WHITE,
-            );
-        });
+    fn update(&mut self, nav_mesh: Option<&NavMesh>) {
+        self.result = PointToPoint::default();
+        self.error = None;
+        let Some(nav_mesh) = nav_mesh else {
+            return;
+        };
+        let mut query = NavMeshQuery::new(nav_mesh, Self::MAX_NODES);
+        let filter = QueryFilter::default();
+        let result = match (self.start, self.end) {
+            (Some(start), Some(end)) => {
+                query.query_point_to_point(start, end, Self::HALF_EXTENTS, &filter)
+            }
+            (Some(start), None) => query
+                .find_nearest_poly(start, Self::HALF_EXTENTS, &filter)
+                .map(|start| PointToPoint {
+                    start,
+                    ..Default::default()
+                }),
+            (None, Some(end)) => query
+                .find_nearest_poly(end, Self::HALF_EXTENTS, &filter)
+                .map(|end| PointToPoint {
+                    end,
+                    ..Default::default()
+                }),
+            (None, None) => Ok(PointToPoint::default()),
+        };
+        match result {
+            Ok(result) => self.result = result,
+            Err(err) => self.error = Some(err.to_string()),
+        }
     }
 }
 
-pub fn run() -> Result<(), eframe::Error> {
-    let options = eframe::NativeOptions {
-        viewport: ViewportBuilder::default().with_inner_size([800.0, 600.0]),
-        ..Default::default()
-    };
-    eframe::run_native(
-        "Mesh Viewer",
-        options,
-        Box::new(|cc| Box::new(MeshViewerApp::new(cc))),
-    )
+// Errors or warnings from the last load, shown until dismissed
+struct LoadReport {
+    title: String,
+    messages: Vec<String>,
 }
 
-// Create a minimal test mesh that should trigger the rendering artifacts
-fn create_test_mesh() -> InputMesh {
-    let mut mesh = InputMesh::new();
-
-    // Create just two triangles that share an edge
-    mesh.verts = vec![
-        Vec3::new(0.0, 0.0, 0.0),  // Bottom vertex
-        Vec3::new(-1.0, 2.0, 0.0), // Top left
-        Vec3::new(1.0, 2.0, 0.0),  // Top right
-        Vec3::new(0.0, 1.0, 1.0),  // Back middle
-    ];
+// Default cap on the number of meshes loaded at once
+const MAX_SCENE_MESHES: usize = 16;
 
-    // Create two triangles
-    mesh.tris = vec![
-        0, 1, 2, // Front triangle
-        0, 2, 3, // Back triangle with steep slope
-    ];
+// One loaded geometry file in the scene
+struct SceneMesh {
+    mesh: InputMesh,
+    // Rebuilt whenever `mesh` is replaced; used for picking
+    bvh: MeshBvh,
+    // Chunks of `mesh`, for picking triangles with the mouse and culling
+    // the ones outside the view
+    chunky: ChunkyTriMesh,
+    // Empty for the built-in placeholder quad, which the first load replaces
+    path: PathBuf,
+    visible: bool,
+    // Tint multiplied into the slope shading
+    color_override: Option<Vec4>,
+}
 
-    // Calculate normals
-    let v0 = mesh.verts[0];
-    let v1 = mesh.verts[1];
-    let v2 = mesh.verts[2];
-    let v3 = mesh.verts[3];
+impl SceneMesh {
+    fn new(mesh: InputMesh, path: PathBuf) -> Self {
+        let bvh = MeshBvh::build(&mesh);
+        Self::with_bvh(mesh, bvh, path)
+    }
 
-    // Calculate normals for both triangles
-    let normal1 = (v1 - v0).cross(v2 - v0).normalize();
-    let normal2 = (v2 - v0).cross(v3 - v0).normalize();
+    fn with_bvh(mesh: InputMesh, bvh: MeshBvh, path: PathBuf) -> Self {
+        Self {
+            chunky: ChunkyTriMesh::build(&mesh, DEFAULT_TRIS_PER_CHUNK),
+            mesh,
+            bvh,
+            path,
+            visible: true,
+            color_override: None,
+        }
+    }
 
-    mesh.normals = vec![
-        normal1, normal1, normal1, // First triangle
-        normal2, normal2, normal2, // Second triangle
-    ];
+    fn name(&self) -> String {
+        if self.path.as_os_str().is_empty() {
+            return "Default quad".to_string();
+        }
+        self.path.file_name().map_or_else(
+            || self.path.display().to_string(),
+            |name| name.to_string_lossy().into_owned(),
+        )
+    }
+}
 
-    mesh
+// What a click in the viewport selected, shown in the inspector
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Picked {
+    InputTri { mesh: usize, tri: usize },
+    NavMeshPoly { poly: usize },
 }
 
-// Test function to load and render the minimal test case
-fn test_rendering(app: &mut MeshViewerApp) {
-    app.mesh = create_test_mesh();
-    app.camera.position = Vec3::new(0.0, 1.0, 3.0);
-    app.camera.yaw = -90.0_f32.to_radians();
-    app.camera.pitch = 0.0;
-    app.walkable_slope_angle = 45.0;
+// Longest ray cast when picking, matching the far clip plane
+const PICK_DISTANCE: f32 = 100.0;
+
+// Per-frame numbers shown in the stats overlay
+#[derive(Debug, Clone, Copy, Default)]
+struct FrameStats {
+    frame_ms: f32,
+    tris_submitted: usize,
+    // Triangles left after dropping those with a vertex outside the view
+    tris_drawn: usize,
+    lines: usize,
+    points: usize,
+    // Input mesh chunks outside the view, and triangles of chunks in view
+    // left out by the triangle budget
+    chunks_culled: usize,
+    tris_over_budget: usize,
+}
+
+// Which geometry the viewport shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NavMeshView {
+    Input,
+    NavMesh,
+    Both,
+}
+
+// Intermediate build data drawn over the scene
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DebugLayer {
+    None,
+    VoxelsSolid,
+    VoxelsWalkable,
+    Compact,
+    CompactDistance,
+    CompactRegions,
+    RawContours,
+    Contours,
+}
+
+impl DebugLayer {
+    const ALL: [DebugLayer; 8] = [
+        DebugLayer::None,
+        DebugLayer::VoxelsSolid,
+        DebugLayer::VoxelsWalkable,
+        DebugLayer::Compact,
+        DebugLayer::CompactDistance,
+        DebugLayer::CompactRegions,
+        DebugLayer::RawContours,
+        DebugLayer::Contours,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            DebugLayer::None => "None",
+            DebugLayer::VoxelsSolid => "Voxels",
+            DebugLayer::VoxelsWalkable => "Walkable voxels",
+            DebugLayer::Compact => "Compact",
+            DebugLayer::CompactDistance => "Compact distance",
+            DebugLayer::CompactRegions => "Compact regions",
+            DebugLayer::RawContours => "Raw contours",
+            DebugLayer::Contours => "Contours",
+        }
+    }
+}
+
+pub struct MeshViewerApp {
+    meshes: Vec<SceneMesh>,
+    max_meshes: usize,
+    // Mesh framed by the F key
+    selected_mesh: Option<usize>,
+    picked: Option<Picked>,
+    debug_draw: EguiDebugDraw,
+    // GPU backend the recorded scene is replayed into, when eframe runs on wgpu
+    #[cfg(feature = "wgpu")]
+    gpu_draw: Option<WgpuDebugDraw>,
+    camera: Camera,
+    load_job: Option<LoadJob>,
+    mesh_stats: MeshStats,
+    off_mesh_connections: Vec<OffMeshConnection>,
+    off_mesh_tool: OffMeshTool,
+    convex_volumes: Vec<ConvexVolume>,
+    convex_volume_tool: ConvexVolumeTool,
+    jump_link_config: JumpLinkConfig,
+    // Proposed by the last annotation pass, not yet accepted
+    jump_link_candidates: Vec<JumpLinkCandidate>,
+    // Agents on the runtime navmesh, None until it's built
+    crowd: Option<Crowd>,
+    crowd_tool: CrowdTool,
+    test_tool: NavMeshTestTool,
+    show_grid: bool,
+    grid_size: f32,
+    grid_divisions: u32,
+    show_axes: bool,
+    frustum_culling: bool,
+    // Most input triangles drawn per frame; the furthest chunks go first
+    triangle_budget: usize,
+    // Wire boxes around the input meshes
+    show_bounds: bool,
+    navmesh: Option<PolyMesh>,
+    // Islands of `navmesh`, found once per build for the islands panel
+    navmesh_islands: Vec<Island>,
+    // Height detail of the navmesh, shown in place of its flat polygons
+    navmesh_detail: Option<PolyMeshDetail>,
+    // Filtered voxels of the last build, for the debug layers
+    heightfield: Option<Heightfield>,
+    compact: Option<CompactHeightfield>,
+    contours: Option<ContourSet>,
+    debug_layer: DebugLayer,
+    // Linked runtime form of the navmesh, with the off-mesh connections
+    runtime_navmesh: Option<NavMesh>,
+    show_detail_mesh: bool,
+    navmesh_view: NavMeshView,
+    navmesh_status: Option<String>,
+    // Stage timings of the last successful build
+    build_stats: Option<BuildContext>,
+    build_job: Option<BuildJob>,
+    bake_config: BakeConfig,
+    // Set when the bake config changed since the last build
+    navmesh_dirty: bool,
+    auto_rebuild: bool,
+    // Islands smaller than this, in square world units, are left out of
+    // the next build; zero keeps them all
+    min_island_area: f32,
+    // Only the island under this point is kept when set
+    keep_island_at: Option<Vec3>,
+    show_frame_stats: bool,
+    frame_stats: FrameStats,
+    show_texture: bool,
+    // Sort triangles back to front before painting them
+    depth_sort: bool,
+    tex_scale: f32,
+    checker_texture: Option<egui::TextureHandle>,
+    load_report: Option<LoadReport>,
+    // Project file the scene was last saved to or loaded from
+    project_path: Option<PathBuf>,
+    // Meshes of a project still to load, one job at a time
+    load_queue: VecDeque<PickedFile>,
+    bookmarks: Vec<CameraBookmark>,
+    // Name for the next bookmark
+    bookmark_name: String,
+    recent_files: RecentFiles,
+    file_dialogs: FileDialogs,
+    capture: Option<Capture>,
+    // Set once the screenshot for the current capture step was requested
+    capture_requested: bool,
+    turntable_frames: usize,
+    // Where the 3D view was drawn last frame, for cropping screenshots
+    viewport_rect: egui::Rect,
+}
+
+impl MeshViewerApp {
+    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
+        // Create default mesh in case loading fails
+        let mut default_mesh = InputMesh::new();
+        default_mesh.verts = vec![
+            Vec3::new(-1.0, 0.0, -1.0),
+            Vec3::new(1.0, 0.0, -1.0),
+            Vec3::new(1.0, 1.0, 1.0),
+            Vec3::new(-1.0, 1.0, 1.0),
+        ];
+        default_mesh.tris = vec![0, 1, 2, 0, 2, 3];
+        default_mesh.compute_normals();
+
+        let mut app = Self {
+            mesh_stats: default_mesh.compute_stats(45.0),
+            meshes: vec![SceneMesh::new(default_mesh, PathBuf::new())],
+            max_meshes: MAX_SCENE_MESHES,
+            selected_mesh: None,
+            picked: None,
+            debug_draw: EguiDebugDraw::new(),
+            #[cfg(feature = "wgpu")]
+            gpu_draw: cc.wgpu_render_state.as_ref().map(|render_state| {
+                wgpu_draw::init(render_state);
+                WgpuDebugDraw::new()
+            }),
+            camera: Camera::new(),
+            load_job: None,
+            off_mesh_connections: Vec::new(),
+            off_mesh_tool: OffMeshTool::new(),
+            convex_volumes: Vec::new(),
+            convex_volume_tool: ConvexVolumeTool::new(),
+            jump_link_config: JumpLinkConfig::default(),
+            jump_link_candidates: Vec::new(),
+            crowd: None,
+            crowd_tool: CrowdTool::new(),
+            test_tool: NavMeshTestTool::new(),
+            show_grid: true,
+            grid_size: 20.0,
+            grid_divisions: 20,
+            show_axes: true,
+            frustum_culling: true,
+            triangle_budget: DEFAULT_TRIANGLE_BUDGET,
+            show_bounds: false,
+            navmesh: None,
+            navmesh_islands: Vec::new(),
+            navmesh_detail: None,
+            heightfield: None,
+            compact: None,
+            contours: None,
+            debug_layer: DebugLayer::None,
+            runtime_navmesh: None,
+            show_detail_mesh: true,
+            navmesh_view: NavMeshView::Input,
+            navmesh_status: None,
+            build_stats: None,
+            build_job: None,
+            bake_config: BakeConfig::default_path()
+                .and_then(|path| BakeConfig::load(path).ok())
+                .unwrap_or_default(),
+            navmesh_dirty: true,
+            auto_rebuild: false,
+            min_island_area: 0.0,
+            keep_island_at: None,
+            show_frame_stats: false,
+            frame_stats: FrameStats::default(),
+            show_texture: true,
+            depth_sort: true,
+            tex_scale: 1.0,
+            checker_texture: None,
+            load_report: None,
+            project_path: None,
+            load_queue: VecDeque::new(),
+            bookmarks: Vec::new(),
+            bookmark_name: String::new(),
+            recent_files: RecentFiles::default_path()
+                .and_then(|path| RecentFiles::load(path).ok())
+                .unwrap_or_default(),
+            file_dialogs: FileDialogs::new(),
+            capture: None,
+            capture_requested: false,
+            turntable_frames: DEFAULT_TURNTABLE_FRAMES,
+            viewport_rect: egui::Rect::NOTHING,
+        };
+
+        // Try to load dungeon.obj in the background
+        let dungeon_path = PathBuf::from("./dungeon.obj");
+        if dungeon_path.exists() {
+            app.load_mesh(&cc.egui_ctx, PickedFile::on_disk(dungeon_path));
+        } else {
+            println!("Failed to load dungeon.obj, using default mesh");
+        }
+
+        app
+    }
+
+    // Start loading a mesh file in any supported format, abandoning any
+    // load still in progress. The result is appended to the scene.
+    fn load_mesh(&mut self, ctx: &egui::Context, file: PickedFile) {
+        self.load_job = Some(LoadJob::spawn(ctx, file));
+    }
+
+    // Open a mesh or project picked by the user, remembering it in the
+    // recent files. Meshes queue up behind any load in progress.
+    fn open_file(&mut self, file: PickedFile) {
+        let path = &file.path;
+        let is_project = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case(PROJECT_EXTENSION));
+        if is_project {
+            self.open_project(&file);
+        } else if mesh_import::importer_for(path).is_none() {
+            self.report_load_failure(
+                format!("Can't open {}", path.display()),
+                vec!["Unsupported file type".to_string()],
+            );
+        } else if !self.can_add_mesh() {
+            self.report_load_failure(
+                format!("Not adding {}", path.display()),
+                vec![format!(
+                    "The scene is limited to {} meshes",
+                    self.max_meshes
+                )],
+            );
+        } else {
+            // Forgotten again if the load fails
+            if file.contents.is_none() {
+                self.remember_file(&file.path);
+            }
+            self.load_queue.push_back(file);
+        }
+    }
+
+    fn remember_file(&mut self, path: &Path) {
+        self.recent_files.push(path);
+        self.save_recent_files();
+    }
+
+    fn forget_file(&mut self, path: &Path) {
+        self.recent_files.remove(path);
+        self.save_recent_files();
+    }
+
+    fn save_recent_files(&self) {
+        if let Some(path) = RecentFiles::default_path() {
+            if let Err(err) = self.recent_files.save(&path) {
+                println!("Failed to save {}: {}", path.display(), err);
+            }
+        }
+    }
+
+    fn capture_menu(&mut self, ui: &mut egui::Ui) {
+        ui.menu_button("Capture", |ui| {
+            if ui.button("Screenshot...").clicked() {
+                ui.close_menu();
+                let filters = [("PNG", &["png"][..])];
+                if let Some(path) = file_dialog::save_path(&filters, Path::new("navmesh.png")) {
+                    self.start_capture(Capture::Screenshot(path));
+                }
+            }
+            // Frames go to a folder, which the browser has no way to pick
+            #[cfg(not(target_arch = "wasm32"))]
+            ui.horizontal(|ui| {
+                if ui.button("Turntable...").clicked() {
+                    ui.close_menu();
+                    if let Some(dir) = file_dialog::pick_folder() {
+                        self.start_capture(Capture::Turntable {
+                            dir,
+                            frame: 0,
+                            frames: self.turntable_frames,
+                            start_position: self.camera.position,
+                            start_rotation: self.camera.rotation,
+                        });
+                    }
+                }
+                ui.add(
+                    egui::DragValue::new(&mut self.turntable_frames)
+                        .clamp_range(2..=720)
+                        .suffix(" frames"),
+                );
+            });
+        });
+    }
+
+    fn start_capture(&mut self, capture: Capture) {
+        self.capture = Some(capture);
+        self.capture_requested = false;
+    }
+
+    // Save the screenshot asked for last frame, then set up the camera for
+    // the next one and ask for it. Screenshots come back the frame after
+    // the request.
+    fn update_capture(&mut self, ctx: &egui::Context) {
+        let Some(capture) = &mut self.capture else {
+            return;
+        };
+
+        if self.capture_requested {
+            let image = ctx.input(|i| {
+                i.raw.events.iter().find_map(|event| match event {
+                    egui::Event::Screenshot { image, .. } => Some(image.clone()),
+                    _ => None,
+                })
+            });
+            let Some(image) = image else {
+                // Still waiting on the window
+                ctx.request_repaint();
+                return;
+            };
+            self.capture_requested = false;
+
+            let path = match capture {
+                Capture::Screenshot(path) => path.clone(),
+                Capture::Turntable { dir, frame, .. } => {
+                    dir.join(format!("turntable_{:04}.png", frame))
+                }
+            };
+            let image = crop_to(&image, self.viewport_rect, ctx.pixels_per_point());
+            let rgba: Vec<u8> = image
+                .pixels
+                .iter()
+                .flat_map(|c| c.to_srgba_unmultiplied())
+                .collect();
+            let png = png::encode_png(image.size[0] as u32, image.size[1] as u32, &rgba);
+            let saved = file_dialog::write_file(&path, &png);
+            if let Err(err) = &saved {
+                println!("Failed to save {}: {}", path.display(), err);
+            }
+
+            match capture {
+                Capture::Turntable { frame, frames, .. }
+                    if saved.is_ok() && *frame + 1 < *frames =>
+                {
+                    *frame += 1
+                }
+                _ => {
+                    self.finish_capture();
+                    return;
+                }
+            }
+        }
+
+        if let Some(Capture::Turntable {
+            frame,
+            frames,
+            start_position,
+            start_rotation,
+            ..
+        }) = &self.capture
+        {
+            let turn =
+                Quat::from_rotation_y(std::f32::consts::TAU * *frame as f32 / *frames as f32);
+            let focus = self.camera.focus;
+            self.camera.position = focus + turn * (*start_position - focus);
+            self.camera.rotation = (turn * *start_rotation).normalize();
+        }
+        ctx.send_viewport_cmd(egui::ViewportCommand::Screenshot);
+        self.capture_requested = true;
+    }
+
+    fn finish_capture(&mut self) {
+        if let Some(Capture::Turntable {
+            start_position,
+            start_rotation,
+            ..
+        }) = self.capture.take()
+        {
+            self.camera.position = start_position;
+            self.camera.rotation = start_rotation;
+        }
+    }
+
+    // Dim the window while files are dragged over it
+    fn file_drop_overlay(&self, ctx: &egui::Context) {
+        if ctx.input(|i| i.raw.hovered_files.is_empty()) {
+            return;
+        }
+        let painter = ctx.layer_painter(egui::LayerId::new(
+            egui::Order::Foreground,
+            egui::Id::new("file_drop_overlay"),
+        ));
+        let rect = ctx.screen_rect();
+        painter.rect_filled(rect, 0.0, egui::Color32::from_black_alpha(160));
+        painter.text(
+            rect.center(),
+            egui::Align2::CENTER_CENTER,
+            "Drop meshes or a project to load",
+            egui::FontId::proportional(24.0),
+            egui::Color32::WHITE,
+        );
+    }
+
+    fn to_project(&self) -> Project {
+        Project {
+            meshes: self
+                .meshes
+                .iter()
+                .filter(|entry| !entry.path.as_os_str().is_empty())
+                .map(|entry| entry.path.clone())
+                .collect(),
+            bake_config: self.bake_config.clone(),
+            convex_volumes: self.convex_volumes.clone(),
+            off_mesh_connections: self.off_mesh_connections.clone(),
+            bookmarks: self.bookmarks.clone(),
+        }
+    }
+
+    fn save_project(&mut self, path: PathBuf) {
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let json = self.to_project().to_json(dir);
+        match file_dialog::write_file(&path, json.as_bytes()) {
+            Ok(()) => {
+                self.remember_file(&path);
+                self.project_path = Some(path);
+            }
+            Err(err) => println!("Failed to save {}: {}", path.display(), err),
+        }
+    }
+
+    // Replace the scene with the project's; its meshes load in the background
+    fn open_project(&mut self, file: &PickedFile) {
+        let path = file.path.as_path();
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let loaded = file.read_to_string().and_then(|text| {
+            Project::from_json(&text, dir)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        });
+        let project = match loaded {
+            Ok(project) => project,
+            Err(err) => {
+                println!("Failed to load {}: {}", path.display(), err);
+                self.load_report = Some(LoadReport {
+                    title: format!("Failed to load {}", path.display()),
+                    messages: vec![err.to_string()],
+                });
+                self.forget_file(path);
+                return;
+            }
+        };
+
+        // A project read in the browser names its meshes by paths on disk,
+        // which can't be read there; refuse it before the scene is cleared
+        if file.contents.is_some() && !project.meshes.is_empty() {
+            let mut messages =
+                vec!["Its meshes are files on disk, which can't be read here:".to_string()];
+            messages.extend(project.meshes.iter().map(|m| m.display().to_string()));
+            self.report_load_failure(format!("Can't open {}", path.display()), messages);
+            return;
+        }
+
+        self.load_job = None;
+        self.meshes.clear();
+        self.selected_mesh = None;
+        self.load_queue = project
+            .meshes
+            .into_iter()
+            .map(PickedFile::on_disk)
+            .collect();
+        self.bake_config = project.bake_config;
+        self.convex_volumes = project.convex_volumes;
+        self.convex_volume_tool.selected = None;
+        self.convex_volume_tool.points.clear();
+        self.off_mesh_connections = project.off_mesh_connections;
+        self.off_mesh_tool.selected = None;
+        self.off_mesh_tool.pending_start = None;
+        self.off_mesh_tool.dragging = None;
+        self.bookmarks = project.bookmarks;
+        if file.contents.is_none() {
+            self.remember_file(path);
+        }
+        self.project_path = Some(path.to_path_buf());
+        self.scene_changed();
+    }
+
+    // Act on the files picked in open dialogs, which in the browser arrive
+    // some frames after the dialog was opened
+    fn open_picked_files(&mut self) {
+        for (purpose, file) in self.file_dialogs.take_picked() {
+            let path = file.path.display().to_string();
+            match purpose {
+                FilePurpose::Scene => self.open_file(file),
+                FilePurpose::Palette => {
+                    let loaded = file.read_to_string().and_then(|text| {
+                        AreaPalette::from_text(&text)
+                            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+                    });
+                    match loaded {
+                        Ok(palette) => self.debug_draw.palette = palette,
+                        Err(err) => self.report_load_failure(
+                            format!("Failed to load {}", path),
+                            vec![err.to_string()],
+                        ),
+                    }
+                }
+                FilePurpose::Volumes => {
+                    let loaded = file.read_to_string().and_then(|text| {
+                        volumes_from_text(&text)
+                            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+                    });
+                    match loaded {
+                        Ok(volumes) => {
+                            self.convex_volumes = volumes;
+                            self.convex_volume_tool.selected = None;
+                            self.navmesh_dirty = true;
+                        }
+                        Err(err) => self.report_load_failure(
+                            format!("Failed to load {}", path),
+                            vec![err.to_string()],
+                        ),
+                    }
+                }
+            }
+        }
+    }
+
+    // Print a file that couldn't be opened and show why in the load report
+    fn report_load_failure(&mut self, title: String, messages: Vec<String>) {
+        println!("{}: {}", title, messages.join(" "));
+        self.load_report = Some(LoadReport { title, messages });
+    }
+
+    fn can_add_mesh(&self) -> bool {
+        self.meshes.len() < self.max_meshes || self.only_placeholder()
+    }
+
+    fn only_placeholder(&self) -> bool {
+        matches!(&self.meshes[..], [only] if only.path.as_os_str().is_empty())
+    }
+
+    // Called whenever the set of visible geometry changes
+    fn scene_changed(&mut self) {
+        self.refresh_stats();
+        self.picked = None;
+        self.navmesh = None;
+        self.navmesh_islands.clear();
+        self.navmesh_detail = None;
+        self.heightfield = None;
+        self.compact = None;
+        self.contours = None;
+        self.navmesh_status = None;
+        self.navmesh_dirty = true;
+        // A running build is for the old geometry
+        self.build_job = None;
+    }
+
+    // What F frames: the selected mesh, else the navmesh tile nearest the
+    // camera focus while the navmesh is shown, else the whole scene
+    fn focus_bounds(&self) -> (Vec3, Vec3) {
+        let selected = self.selected_mesh.and_then(|i| self.meshes.get(i));
+        if let Some(entry) = selected.filter(|entry| !entry.mesh.verts.is_empty()) {
+            return entry.mesh.bounds();
+        }
+        let nav_mesh = self
+            .runtime_navmesh
+            .as_ref()
+            .filter(|_| self.navmesh_view != NavMeshView::Input);
+        if let Some(nav_mesh) = nav_mesh {
+            let focus = self.camera.focus;
+            let nearest = nav_mesh
+                .tiles()
+                .map(|tile| (tile.data.header.bmin, tile.data.header.bmax))
+                .min_by(|a, b| {
+                    let da = ((a.0 + a.1) * 0.5).distance_squared(focus);
+                    let db = ((b.0 + b.1) * 0.5).distance_squared(focus);
+                    da.total_cmp(&db)
+                });
+            if let Some(bounds) = nearest {
+                return bounds;
+            }
+        }
+        self.scene_bounds()
+    }
+
+    // Union of the bounds of all loaded meshes
+    fn scene_bounds(&self) -> (Vec3, Vec3) {
+        let bounds: Vec<_> = self
+            .meshes
+            .iter()
+            .filter(|entry| !entry.mesh.verts.is_empty())
+            .map(|entry| entry.mesh.bounds())
+            .collect();
+        if bounds.is_empty() {
+            return (Vec3::ZERO, Vec3::ZERO);
+        }
+        bounds.iter().fold(
+            (Vec3::INFINITY, Vec3::NEG_INFINITY),
+            |(min, max), &(lo, hi)| (min.min(lo), max.max(hi)),
+        )
+    }
+
+    // Visible meshes merged into one, for stats and baking
+    fn combined_mesh(&self) -> InputMesh {
+        let visible: Vec<&InputMesh> = self
+            .meshes
+            .iter()
+            .filter(|entry| entry.visible)
+            .map(|entry| &entry.mesh)
+            .collect();
+        let with_areas = visible.iter().any(|mesh| !mesh.areas.is_empty());
+
+        let mut combined = InputMesh::new();
+        for mesh in visible {
+            let offset = combined.verts.len() as i32;
+            combined.verts.extend_from_slice(&mesh.verts);
+            combined.tris.extend(mesh.tris.iter().map(|&i| i + offset));
+            combined.normals.extend_from_slice(&mesh.normals);
+            if with_areas {
+                if mesh.areas.is_empty() {
+                    let len = combined.areas.len() + mesh.tri_count();
+                    combined.areas.resize(len, RC_WALKABLE_AREA);
+                } else {
+                    combined.areas.extend_from_slice(&mesh.areas);
+                }
+            }
+        }
+        combined
+    }
+
+    fn poll_load_job(&mut self) {
+        let Some(job) = &mut self.load_job else {
+            return;
+        };
+
+        let result = loop {
+            match job.receiver.try_recv() {
+                Ok(LoadMessage::Progress(fraction)) => job.progress = fraction,
+                Ok(LoadMessage::Finished(result)) => break Some(*result),
+                Err(TryRecvError::Empty) => break None,
+                Err(TryRecvError::Disconnected) => {
+                    break Some(Err(MeshImportError::Obj(ObjLoadError::IoError(
+                        std::io::Error::other("Loader thread exited unexpectedly"),
+                    ))))
+                }
+            }
+        };
+
+        let Some(result) = result else {
+            return;
+        };
+        let path = self.load_job.take().unwrap().path.clone();
+
+        match result {
+            Ok((mesh, bvh, warnings)) => {
+                if !warnings.is_empty() {
+                    self.load_report = Some(LoadReport {
+                        title: format!(
+                            "{}: {} problem(s) while loading",
+                            path.display(),
+                            warnings.len()
+                        ),
+                        messages: warnings,
+                    });
+                }
+                if !self.can_add_mesh() {
+                    println!(
+                        "Not adding {}: the scene is limited to {} meshes",
+                        path.display(),
+                        self.max_meshes
+                    );
+                    return;
+                }
+                println!("Successfully loaded {}", path.display());
+                if self.only_placeholder() {
+                    self.meshes.clear();
+                    self.selected_mesh = None;
+                }
+                // Volumes saved next to the mesh come back with it, unless
+                // a project holds them
+                let saved_volumes = volumes_path(&path);
+                if self.project_path.is_none() && saved_volumes.exists() {
+                    match load_volumes(&saved_volumes) {
+                        Ok(volumes) => self.convex_volumes.extend(volumes),
+                        Err(err) => {
+                            println!("Failed to load {}: {}", saved_volumes.display(), err)
+                        }
+                    }
+                }
+                self.meshes.push(SceneMesh::with_bvh(mesh, bvh, path));
+                self.scene_changed();
+
+                // Adjust camera to fit the whole scene
+                let (min, max) = self.scene_bounds();
+                self.camera.frame_bounds(min, max);
+            }
+            Err(MeshImportError::Cancelled) => {}
+            Err(err) => {
+                // The scene keeps whatever was loaded before
+                println!("Failed to load {}: {}", path.display(), err);
+                self.forget_file(&path);
+                self.load_report = Some(LoadReport {
+                    title: format!("Failed to load {}", path.display()),
+                    messages: vec![err.to_string()],
+                });
+            }
+        }
+    }
+
+    // Stats are cached and only recomputed when the mesh or slope changes
+    fn refresh_stats(&mut self) {
+        self.mesh_stats = self
+            .combined_mesh()
+            .compute_stats(self.bake_config.walkable_slope_angle);
+    }
+
+    // Reference grid on the XZ plane at y=0, centered on the origin
+    // Outline and fill the picked triangle or polygon
+    fn draw_picked(&mut self) {
+        let triangles: Vec<[Vec3; 3]> = match self.picked {
+            Some(Picked::InputTri { mesh, tri }) => {
+                let Some(entry) = self.meshes.get(mesh) else {
+                    return;
+                };
+                let mesh = &entry.mesh;
+                vec![[0, 1, 2].map(|k| mesh.verts[mesh.tris[tri * 3 + k] as usize])]
+            }
+            Some(Picked::NavMeshPoly { poly }) => {
+                let Some(detail) = &self.navmesh_detail else {
+                    return;
+                };
+                let Some(&[vert_base, _, tri_base, tri_count]) = detail.meshes.get(poly) else {
+                    return;
+                };
+                detail.tris[tri_base as usize..(tri_base + tri_count) as usize]
+                    .iter()
+                    .map(|t| [0, 1, 2].map(|k| detail.verts[vert_base as usize + t[k] as usize]))
+                    .collect()
+            }
+            None => return,
+        };
+
+        // Lifted a little so the highlight isn't hidden in the surface
+        let lift = Vec3::Y * 0.02;
+        let dd = &mut self.debug_draw;
+        let highlight = dd.colors.highlight;
+        dd.begin(DebugDrawPrimitive::Tris, 1.0);
+        for tri in &triangles {
+            for v in tri {
+                dd.vertex(*v + lift, highlight.truncate().extend(0.5));
+            }
+        }
+        dd.end();
+        dd.begin(DebugDrawPrimitive::Lines, 2.0);
+        for tri in &triangles {
+            for k in 0..3 {
+                dd.vertex(tri[k] + lift, highlight);
+                dd.vertex(tri[(k + 1) % 3] + lift, highlight);
+            }
+        }
+        dd.end();
+    }
+
+    fn draw_grid(&mut self) {
+        let half = self.grid_size * 0.5;
+        let step = self.grid_size / self.grid_divisions as f32;
+        let color = self.debug_draw.colors.grid;
+        du_debug_draw_grid_xz(
+            &mut self.debug_draw,
+            Vec3::new(-half, 0.0, -half),
+            self.grid_divisions,
+            self.grid_divisions,
+            step,
+            color,
+            1.0,
+        );
+    }
+
+    // Bounds of each visible input mesh, the selected one highlighted
+    fn draw_mesh_bounds(&mut self) {
+        for (i, entry) in self.meshes.iter().enumerate() {
+            if !entry.visible || entry.mesh.verts.is_empty() {
+                continue;
+            }
+            let (min, max) = entry.mesh.bounds();
+            let (color, width) = if self.selected_mesh == Some(i) {
+                (self.debug_draw.colors.highlight, 2.0)
+            } else {
+                (Vec4::new(0.8, 0.8, 0.8, 0.6), 1.0)
+            };
+            du_debug_draw_box_wire(&mut self.debug_draw, min, max, color, width);
+        }
+    }
+
+    fn view_panel(&mut self, ui: &mut egui::Ui) {
+        let colors = &mut self.debug_draw.colors;
+        egui::ComboBox::from_label("Colors")
+            .selected_text(colors.name)
+            .show_ui(ui, |ui| {
+                for preset in DebugPalette::PRESETS {
+                    ui.selectable_value(colors, preset, preset.name);
+                }
+            });
+        ui.checkbox(&mut self.show_grid, "Grid");
+        ui.add_enabled_ui(self.show_grid, |ui| {
+            ui.horizontal(|ui| {
+                ui.add(
+                    egui::DragValue::new(&mut self.grid_size)
+                        .speed(0.5)
+                        .clamp_range(1.0..=1000.0)
+                        .prefix("size "),
+                );
+                ui.add(
+                    egui::DragValue::new(&mut self.grid_divisions)
+                        .clamp_range(1..=200)
+                        .prefix("cells "),
+                );
+            });
+            ui.label(format!(
+                "{:.2} units per cell",
+                self.grid_size / self.grid_divisions as f32
+            ));
+        });
+        ui.checkbox(&mut self.show_axes, "World axes")
+            .on_hover_text("X red, Y green, Z blue");
+        ui.checkbox(&mut self.show_bounds, "Mesh bounds");
+        ui.checkbox(&mut self.frustum_culling, "Frustum culling");
+        ui.horizontal(|ui| {
+            ui.label("Triangle budget:");
+            ui.add(
+                egui::DragValue::new(&mut self.triangle_budget)
+                    .speed(1000.0)
+                    .clamp_range(1000..=10_000_000),
+            )
+            .on_hover_text("Input triangles drawn per frame, nearest chunks first");
+        });
+
+        if self.meshes.iter().any(|entry| !entry.mesh.verts.is_empty()) {
+            let (min, max) = self.scene_bounds();
+            let size = max - min;
+            ui.label(format!(
+                "Scene size: {:.2} x {:.2} x {:.2}",
+                size.x, size.y, size.z
+            ));
+        }
+    }
+
+    // Slope-shaded input meshes, leaving out chunks outside the view and,
+    // past the triangle budget, the chunks furthest from the camera.
+    // Returns how many chunks were culled and how many triangles the
+    // budget skipped.
+    fn draw_input_meshes(&mut self) -> (usize, usize) {
+        let frustum =
+            Frustum::from_view_proj(self.camera.projection_matrix() * self.camera.view_matrix());
+        let eye = self.camera.position;
+
+        // (distance, mesh, triangles) of every chunk in view
+        let mut visible = Vec::new();
+        let mut chunks_culled = 0;
+        for (i, entry) in self.meshes.iter().enumerate() {
+            if !entry.visible {
+                continue;
+            }
+            let chunks = if self.frustum_culling {
+                entry
+                    .chunky
+                    .chunks_passing(|bmin, bmax| frustum.intersects_aabb(bmin, bmax))
+            } else {
+                entry.chunky.chunks_passing(|_, _| true)
+            };
+            chunks_culled += entry.chunky.chunk_count() - chunks.len();
+            visible.extend(
+                chunks
+                    .into_iter()
+                    .map(|c| (eye.clamp(c.bmin, c.bmax).distance(eye), i, c.tris)),
+            );
+        }
+
+        // Nearest chunks first until the budget runs out
+        visible.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mut tris = vec![Vec::new(); self.meshes.len()];
+        let mut budget = self.triangle_budget;
+        let mut tris_over_budget = 0;
+        for (_, i, chunk_tris) in visible {
+            if chunk_tris.len() <= budget {
+                budget -= chunk_tris.len();
+                tris[i].extend_from_slice(chunk_tris);
+            } else {
+                tris_over_budget += chunk_tris.len();
+            }
+        }
+
+        for (entry, mut tris) in self.meshes.iter().zip(tris) {
+            if tris.is_empty() {
+                continue;
+            }
+            // Mesh order, so the draw doesn't flicker as chunks come and go
+            tris.sort_unstable();
+            self.debug_draw.tint = entry.color_override;
+            du_debug_draw_tri_mesh_slope_tris(
+                &mut self.debug_draw,
+                &entry.mesh,
+                tris,
+                self.bake_config.walkable_slope_angle,
+                self.tex_scale,
+            );
+        }
+        self.debug_draw.tint = None;
+        (chunks_culled, tris_over_budget)
+    }
+
+    // Record this frame's geometry. Returns `draw_input_meshes`' counts.
+    fn draw_mesh(&mut self) -> (usize, usize) {
+        self.debug_draw.clear();
+        if self.show_grid {
+            self.draw_grid();
+        }
+        if self.show_axes {
+            du_debug_draw_axes(&mut self.debug_draw, Vec3::ZERO, self.grid_size * 0.5, 2.0);
+        }
+        if self.show_bounds {
+            self.draw_mesh_bounds();
+        }
+        let mut culled = (0, 0);
+        if self.navmesh_view != NavMeshView::NavMesh {
+            culled = self.draw_input_meshes();
+        }
+        match (self.debug_layer, &self.heightfield, &self.compact) {
+            (DebugLayer::VoxelsSolid, Some(hf), _) => {
+                du_debug_draw_heightfield_solid(&mut self.debug_draw, hf)
+            }
+            (DebugLayer::VoxelsWalkable, Some(hf), _) => {
+                du_debug_draw_heightfield_walkable(&mut self.debug_draw, hf)
+            }
+            (DebugLayer::Compact, _, Some(chf)) => {
+                du_debug_draw_compact_heightfield_solid(&mut self.debug_draw, chf)
+            }
+            (DebugLayer::CompactDistance, _, Some(chf)) => {
+                du_debug_draw_compact_heightfield_distance(&mut self.debug_draw, chf)
+            }
+            (DebugLayer::CompactRegions, _, Some(chf)) => {
+                du_debug_draw_compact_heightfield_regions(&mut self.debug_draw, chf)
+            }
+            _ => {}
+        }
+        if let Some(cset) = &self.contours {
+            match self.debug_layer {
+                DebugLayer::RawContours => {
+                    du_debug_draw_raw_contours(&mut self.debug_draw, cset, 1.0)
+                }
+                DebugLayer::Contours => du_debug_draw_contours(&mut self.debug_draw, cset, 1.0),
+                _ => {}
+            }
+        }
+        // The navmesh fill is translucent, so drawing it last overlays the input mesh
+        if self.navmesh_view != NavMeshView::Input {
+            match (&self.navmesh_detail, &self.navmesh) {
+                (Some(detail), _) if self.show_detail_mesh => {
+                    du_debug_draw_poly_mesh_detail(&mut self.debug_draw, detail)
+                }
+                (_, Some(navmesh)) => du_debug_draw_poly_mesh(&mut self.debug_draw, navmesh),
+                _ => {}
+            }
+        }
+        du_debug_draw_off_mesh_connections(&mut self.debug_draw, &self.off_mesh_connections);
+        if let Some(nav_mesh) = self
+            .runtime_navmesh
+            .as_ref()
+            .filter(|_| self.off_mesh_tool.show_baked && self.navmesh_view != NavMeshView::Input)
+        {
+            du_debug_draw_nav_mesh_off_mesh_connections(&mut self.debug_draw, nav_mesh);
+        }
+        // Candidates are drawn faded until accepted
+        let candidates: Vec<OffMeshConnection> = self
+            .jump_link_candidates
+            .iter()
+            .map(|c| c.connection.clone())
+            .collect();
+        self.debug_draw.tint = Some(Vec4::new(1.0, 1.0, 1.0, 0.4));
+        du_debug_draw_off_mesh_connections(&mut self.debug_draw, &candidates);
+        self.debug_draw.tint = None;
+        du_debug_draw_convex_volumes(&mut self.debug_draw, &self.convex_volumes);
+        self.draw_picked();
+        if let Some(nav_mesh) = self
+            .runtime_navmesh
+            .as_ref()
+            .filter(|_| self.test_tool.enabled)
+        {
+            let tool = &self.test_tool;
+            du_debug_draw_point_to_point(
+                &mut self.debug_draw,
+                nav_mesh,
+                tool.start,
+                tool.end,
+                &tool.result,
+            );
+        }
+        if let (Some(crowd), Some(nav_mesh)) = (&self.crowd, &self.runtime_navmesh) {
+            let tool = &self.crowd_tool;
+            du_debug_draw_crowd(
+                &mut self.debug_draw,
+                nav_mesh,
+                crowd,
+                tool.selected,
+                tool.draw,
+            );
+            if let Some(target) = tool.target {
+                let color = Vec4::new(1.0, 0.25, 0.25, 1.0);
+                let r = tool.params.radius;
+                self.debug_draw.begin(DU_DRAW_LINES, 2.0);
+                for d in [Vec3::X * r, Vec3::Z * r, Vec3::Y * tool.params.height] {
+                    self.debug_draw
+                        .vertex(target - Vec3::new(d.x, 0.0, d.z), color);
+                    self.debug_draw.vertex(target + d, color);
+                }
+                self.debug_draw.end();
+            }
+        }
+
+        // Outline of the volume being placed
+        let points = &self.convex_volume_tool.points;
+        let color = self.debug_draw.area_to_col(self.convex_volume_tool.area);
+        self.debug_draw.begin(DU_DRAW_POINTS, 6.0);
+        for &p in points {
+            self.debug_draw.vertex(p, color);
+        }
+        self.debug_draw.end();
+        self.debug_draw.begin(DU_DRAW_LINES, 2.0);
+        for pair in points.windows(2) {
+            self.debug_draw.vertex(pair[0], color);
+            self.debug_draw.vertex(pair[1], color);
+        }
+        self.debug_draw.end();
+        culled
+    }
+
+    // Start the navmesh bake pipeline on the current input mesh. The bake
+    // runs in the background and replaces the navmesh once it finishes.
+    fn build_navmesh(&mut self) {
+        self.navmesh_dirty = false;
+        let config = match self.bake_config.to_build_config() {
+            Ok(config) => config,
+            Err(err) => {
+                self.navmesh_status = Some(format!("Invalid bake settings: {}", err));
+                return;
+            }
+        };
+
+        let mesh = self.combined_mesh();
+        let volumes = self.convex_volumes.clone();
+        let off_mesh_connections = self.off_mesh_connections.clone();
+        let (min_island_area, keep_island_at) = (self.min_island_area, self.keep_island_at);
+        // Replacing a running build cancels it
+        self.build_job = Some(BuildHandle::spawn(move |task| {
+            let steps = 5.0;
+            let mut build_ctx = BuildContext::new();
+            let hf = build_heightfield(&mesh, &config, &mut build_ctx)?;
+            task.set_progress(1.0 / steps);
+            task.check()?;
+            // Same steps as `bake_filtered`, keeping each stage for the debug layers
+            let mut chf = eroded_compact(&hf, &config, &mut build_ctx);
+            task.set_progress(2.0 / steps);
+            task.check()?;
+            partition_compact(&mut chf, &config, &volumes, 0, &mut build_ctx);
+            task.set_progress(3.0 / steps);
+            task.check()?;
+            let cset = trace_contours(&chf, &config, &mut build_ctx);
+            task.set_progress(4.0 / steps);
+            task.check()?;
+            let mut baked = mesh_contours(&cset, &chf, &config, &mut build_ctx);
+            let mut pruned_polys =
+                baked.prune_islands_smaller_than(min_island_area, &off_mesh_connections);
+            if let Some(point) = keep_island_at {
+                pruned_polys += baked
+                    .keep_island_containing(point, &off_mesh_connections)
+                    .unwrap_or(0);
+            }
+            // Only watershed partitioning needs the distance field
+            if chf.dist.is_empty() {
+                build_distance_field(&mut chf);
+            }
+            task.check()?;
+            let params = NavMeshCreateParams {
+                off_mesh_connections: &off_mesh_connections,
+                ..NavMeshCreateParams::new(
+                    &baked.poly_mesh,
+                    Some(&baked.detail_mesh),
+                    config.agent_height,
+                    config.agent_radius,
+                    config.agent_max_climb,
+                )
+            };
+            let nav_mesh = NavMeshData::build(&params).and_then(NavMesh::single_tile);
+            task.set_progress(1.0);
+            Ok(Box::new(BuiltNavMesh {
+                hf,
+                chf,
+                cset,
+                baked,
+                nav_mesh,
+                build_ctx,
+                agent_radius: config.agent_radius,
+                agent_height: config.agent_height,
+                pruned_polys,
+            }))
+        }));
+        self.navmesh_status = Some("Building...".to_string());
+    }
+
+    fn poll_build_job(&mut self) {
+        let Some(job) = &mut self.build_job else {
+            return;
+        };
+        let Some(result) = job.try_take() else {
+            return;
+        };
+        self.build_job = None;
+
+        let built = match result {
+            Ok(built) => built,
+            Err(NavMeshBuildError::Cancelled) => {
+                // The previous navmesh stays
+                self.navmesh_status = Some("Build cancelled".to_string());
+                return;
+            }
+            Err(NavMeshBuildError::EmptyMesh) => {
+                self.clear_navmesh();
+                self.navmesh_status = Some("Nothing to bake: no visible meshes".to_string());
+                return;
+            }
+            Err(err) => {
+                self.clear_navmesh();
+                self.navmesh_status = Some(format!("Bake failed: {}", err));
+                return;
+            }
+        };
+
+        self.clear_navmesh();
+        let BuiltNavMesh {
+            hf,
+            chf,
+            cset,
+            baked,
+            nav_mesh,
+            build_ctx,
+            agent_radius,
+            agent_height,
+            pruned_polys,
+        } = *built;
+        self.heightfield = Some(hf);
+        self.compact = Some(chf);
+        self.contours = Some(cset);
+        self.build_stats = Some(build_ctx);
+        self.navmesh_status = Some(if pruned_polys > 0 {
+            format!(
+                "Built {} polygons, pruned {} in islands",
+                baked.poly_mesh.polys.len(),
+                pruned_polys
+            )
+        } else {
+            format!("Built {} polygons", baked.poly_mesh.polys.len())
+        });
+        match nav_mesh {
+            Ok(nav_mesh) => {
+                self.runtime_navmesh = Some(nav_mesh);
+                self.crowd = Some(Crowd::new(MAX_CROWD_AGENTS, agent_radius));
+                self.crowd_tool.params = CrowdAgentParams::new(agent_radius, agent_height);
+            }
+            Err(NavMeshError::EmptyMesh) => {}
+            Err(err) => self.navmesh_status = Some(format!("Navmesh tile failed: {}", err)),
+        }
+        self.test_tool.update(self.runtime_navmesh.as_ref());
+        self.navmesh_islands = baked.poly_mesh.islands();
+        self.navmesh = Some(baked.poly_mesh);
+        self.navmesh_detail = Some(baked.detail_mesh);
+        // Candidates were found on the previous navmesh
+        self.jump_link_candidates.clear();
+    }
+
+    // Drop the navmesh and everything built on it
+    fn clear_navmesh(&mut self) {
+        self.navmesh = None;
+        self.navmesh_islands.clear();
+        self.navmesh_detail = None;
+        self.heightfield = None;
+        self.compact = None;
+        self.contours = None;
+        self.runtime_navmesh = None;
+        // Agents stand on polygons of the old navmesh
+        self.crowd = None;
+        self.crowd_tool.selected = None;
+        self.test_tool.result = PointToPoint::default();
+        if matches!(self.picked, Some(Picked::NavMeshPoly { .. })) {
+            self.picked = None;
+        }
+    }
+
+    // Replay the triangles and lines recorded this frame into the GPU
+    // backend, which depth-tests them and draws each kind in one call. The
+    // egui backend stays the recorder since picking and the frame stats read
+    // from it. Line widths are not carried over, and points are painted by
+    // egui on top afterwards. False when eframe isn't running on wgpu.
+    #[cfg(feature = "wgpu")]
+    fn paint_scene_gpu(&mut self, painter: &egui::Painter, rect: egui::Rect) -> bool {
+        let Some(gpu) = &mut self.gpu_draw else {
+            return false;
+        };
+        let color = |c: Color32| {
+            let [r, g, b, a] = c.to_srgba_unmultiplied();
+            Vec4::new(r as f32, g as f32, b as f32, a as f32) / 255.0
+        };
+        gpu.clear();
+        // A new batch whenever texturing turns on or off
+        let mut textured = None;
+        for tri in &self.debug_draw.tris {
+            let tri_textured = tri.7 && self.show_texture;
+            if textured != Some(tri_textured) {
+                gpu.end();
+                gpu.texture(tri_textured);
+                gpu.begin(DU_DRAW_TRIS, 1.0);
+                textured = Some(tri_textured);
+            }
+            for (pos, uv) in [(tri.0, tri.4), (tri.1, tri.5), (tri.2, tri.6)] {
+                gpu.vertex_uv(pos, color(tri.3), uv);
+            }
+        }
+        gpu.end();
+        gpu.texture(false);
+        gpu.begin(DU_DRAW_LINES, 2.0);
+        for line in &self.debug_draw.lines {
+            gpu.vertex(line.0, color(line.2));
+            gpu.vertex(line.1, color(line.2));
+        }
+        gpu.end();
+
+        // The GPU clips against the near plane instead of dropping triangles
+        self.frame_stats.tris_drawn = self.debug_draw.tris.len();
+        let view_proj = self.camera.projection_matrix() * self.camera.view_matrix();
+        painter.add(gpu.paint_callback(rect, view_proj));
+        true
+    }
+
+    // Recorded points as dots of their size, skipping those behind the camera
+    fn paint_points(&self, painter: &egui::Painter, rect: egui::Rect) {
+        let view_proj = self.camera.projection_matrix() * self.camera.view_matrix();
+        let clip_near = !self.camera.is_orthographic();
+        for &(pos, color, size) in &self.debug_draw.points {
+            let clip = view_proj * pos.extend(1.0);
+            if clip_near && near_distance(clip) < 0.0 {
+                continue;
+            }
+            painter.circle_filled(clip_to_screen(clip, rect), size * 0.5, color);
+        }
+    }
+
+    // Gray checker modulated by the vertex colors, like Recast's debug texture
+    fn checker_texture(&mut self, ctx: &egui::Context) -> &egui::TextureHandle {
+        self.checker_texture.get_or_insert_with(|| {
+            let image = egui::ColorImage {
+                size: [CHECKER_TEXTURE_SIZE; 2],
+                pixels: checker_texture_pixels()
+                    .into_iter()
+                    .map(Color32::from_gray)
+                    .collect(),
+            };
+            ctx.load_texture("checker", image, egui::TextureOptions::LINEAR_REPEAT)
+        })
+    }
+
+    // Swatch and polygon count for each area id in the navmesh, in the
+    // bottom-left corner of the viewport
+    fn paint_area_legend(&self, painter: &egui::Painter, rect: egui::Rect) {
+        let Some(navmesh) = &self.navmesh else {
+            return;
+        };
+        let counts = area_counts(navmesh);
+        if counts.is_empty() {
+            return;
+        }
+
+        const ROW: f32 = 18.0;
+        let font = egui::FontId::monospace(12.0);
+        let height = counts.len() as f32 * ROW + 8.0;
+        let panel = egui::Rect::from_min_size(
+            egui::pos2(rect.min.x + 8.0, rect.max.y - height - 8.0),
+            egui::vec2(150.0, height),
+        );
+        painter.rect_filled(panel, 4.0, Color32::from_black_alpha(160));
+
+        for (i, (area, count)) in counts.into_iter().enumerate() {
+            let top = panel.min + egui::vec2(6.0, 4.0 + i as f32 * ROW);
+            let swatch =
+                egui::Rect::from_min_size(top + egui::vec2(0.0, 2.0), egui::vec2(12.0, 12.0));
+            let c = poly_area_color(&self.debug_draw, area);
+            // Draw the swatch opaque so translucent fills stay readable
+            painter.rect_filled(
+                swatch,
+                2.0,
+                Color32::from_rgb(
+                    (c.x * 255.0) as u8,
+                    (c.y * 255.0) as u8,
+                    (c.z * 255.0) as u8,
+                ),
+            );
+            painter.text(
+                top + egui::vec2(18.0, 0.0),
+                egui::Align2::LEFT_TOP,
+                format!("area {:>2}: {}", area, count),
+                font.clone(),
+                Color32::WHITE,
+            );
+        }
+    }
+
+    // Semi-transparent panel in the top-right corner of the viewport
+    fn paint_frame_stats(&self, painter: &egui::Painter, rect: egui::Rect) {
+        let stats = &self.frame_stats;
+        let forward = self.camera.forward();
+        let yaw = forward.z.atan2(forward.x).to_degrees();
+        let pitch = forward.y.clamp(-1.0, 1.0).asin().to_degrees();
+        let pos = self.camera.position;
+
+        let text = format!(
+            "Frame: {:.2} ms\nTriangles: {} ({} drawn)\nCulled: {} chunks, {} tris over budget\nLines: {}\nPoints: {}\nCamera: ({:.2}, {:.2}, {:.2})\nYaw: {:.1}°  Pitch: {:.1}°",
+            stats.frame_ms,
+            stats.tris_submitted,
+            stats.tris_drawn,
+            stats.chunks_culled,
+            stats.tris_over_budget,
+            stats.lines,
+            stats.points,
+            pos.x,
+            pos.y,
+            pos.z,
+            yaw,
+            pitch,
+        );
+
+        let galley = painter.layout_no_wrap(text, egui::FontId::monospace(12.0), Color32::WHITE);
+        let padding = egui::vec2(8.0, 6.0);
+        let size = galley.size() + padding * 2.0;
+        let panel = egui::Rect::from_min_size(
+            egui::pos2(rect.max.x - size.x - 10.0, rect.min.y + 10.0),
+            size,
+        );
+
+        painter.rect_filled(panel, 4.0, Color32::from_black_alpha(160));
+        painter.galley(panel.min + padding, galley, Color32::WHITE);
+    }
+
+    // Edit colors of the areas used by the current navmesh; changes show up
+    // on the next frame since the mesh is redrawn every frame
+    fn area_palette_panel(&mut self, ui: &mut egui::Ui) {
+        let palette = &mut self.debug_draw.palette;
+
+        ui.horizontal(|ui| {
+            let filters = [("Palette", &["txt"][..])];
+            if ui.button("Load palette").clicked() {
+                self.file_dialogs
+                    .open(ui.ctx(), FilePurpose::Palette, &filters);
+            }
+            if ui.button("Save palette").clicked() {
+                if let Some(path) = file_dialog::save_path(&filters, Path::new("palette.txt")) {
+                    if let Err(err) = file_dialog::write_file(&path, palette.to_text().as_bytes()) {
+                        println!("Failed to save {}: {}", path.display(), err);
+                    }
+                }
+            }
+        });
+
+        let Some(navmesh) = &self.navmesh else {
+            ui.label("No navmesh built");
+            return;
+        };
+
+        for (area, count) in area_counts(navmesh) {
+            ui.horizontal(|ui| {
+                let mut rgba = palette.color(area).to_array();
+                if ui.color_edit_button_rgba_unmultiplied(&mut rgba).changed() {
+                    palette.set_color(area, Vec4::from_array(rgba));
+                }
+                ui.label(format!("Area {} ({} polys)", area, count));
+                if palette.is_overridden(area) && ui.small_button("Reset").clicked() {
+                    palette.reset_color(area);
+                }
+            });
+        }
+    }
+
+    // Time and largest output of each pipeline stage in the last build
+    fn build_stats_panel(&mut self, ui: &mut egui::Ui) {
+        let Some(stats) = &self.build_stats else {
+            ui.label("Build the navmesh to see its stats");
+            return;
+        };
+        let ms = |d: std::time::Duration| format!("{:.2} ms", d.as_secs_f64() * 1000.0);
+        egui::Grid::new("build_stats").striped(true).show(ui, |ui| {
+            ui.strong("Stage");
+            ui.strong("Time");
+            ui.strong("Peak memory");
+            ui.end_row();
+            for stage in BuildStage::ALL {
+                ui.label(stage.name());
+                ui.label(ms(stats.stage_time(stage)));
+                ui.label(format_bytes(stats.peak_memory(stage)));
+                ui.end_row();
+            }
+            ui.strong("total");
+            ui.strong(ms(stats.total_time()));
+            ui.strong(format_bytes(stats.peak_memory_overall()));
+            ui.end_row();
+        });
+        for warning in stats.warnings() {
+            ui.colored_label(Color32::YELLOW, warning);
+        }
+    }
+
+    fn bake_settings_panel(&mut self, ui: &mut egui::Ui) {
+        let config = &mut self.bake_config;
+        let mut changed = false;
+        // Saved once an edit is finished, not on every frame of a drag
+        let mut finished = false;
+        let mut track = |response: egui::Response| {
+            changed |= response.changed();
+            finished |= response.drag_stopped() || response.lost_focus() || response.clicked();
+        };
+
+        egui::Grid::new("bake_settings").show(ui, |ui| {
+            ui.label("Cell size:");
+            track(ui.add(egui::Slider::new(&mut config.cell_size, 0.05..=2.0)));
+            ui.end_row();
+            ui.label("Cell height:");
+            track(ui.add(egui::Slider::new(&mut config.cell_height, 0.05..=2.0)));
+            ui.end_row();
+            ui.label("Walkable height:");
+            track(ui.add(egui::Slider::new(&mut config.walkable_height, 0.1..=5.0)));
+            ui.end_row();
+            ui.label("Walkable climb:");
+            track(ui.add(egui::Slider::new(&mut config.walkable_climb, 0.0..=5.0)));
+            ui.end_row();
+            ui.label("Agent radius:");
+            track(ui.add(egui::Slider::new(&mut config.agent_radius, 0.0..=5.0)));
+            ui.end_row();
+            ui.label("Min region area:");
+            track(ui.add(egui::Slider::new(&mut config.min_region_area, 0..=150)));
+            ui.end_row();
+            ui.label("Edge max error:");
+            track(ui.add(egui::Slider::new(&mut config.edge_max_error, 0.1..=3.0)));
+            ui.end_row();
+            ui.label("Verts per poly:");
+            track(ui.add(egui::Slider::new(
+                &mut config.verts_per_poly,
+                3..=MAX_VERTS_PER_POLY,
+            )));
+            ui.end_row();
+            ui.label("Partitioning:");
+            egui::ComboBox::from_id_source("partition")
+                .selected_text(config.partition.name())
+                .show_ui(ui, |ui| {
+                    for partition in PartitionType::ALL {
+                        track(ui.selectable_value(
+                            &mut config.partition,
+                            partition,
+                            partition.name(),
+                        ));
+                    }
+                });
+            ui.end_row();
+        });
+        ui.checkbox(&mut self.auto_rebuild, "Rebuild automatically");
+
+        if changed {
+            self.navmesh_dirty = true;
+        }
+        if finished {
+            if let Some(path) = BakeConfig::default_path() {
+                if let Err(err) = self.bake_config.save(&path) {
+                    println!("Failed to save {}: {}", path.display(), err);
+                }
+            }
+        }
+    }
+
+    // Disconnected parts of the navmesh, and which of them the next build
+    // leaves out
+    fn islands_panel(&mut self, ui: &mut egui::Ui) {
+        const LISTED: usize = 10;
+        let mut changed = false;
+
+        ui.horizontal(|ui| {
+            ui.label("Prune smaller than:");
+            changed |= ui
+                .add(
+                    egui::DragValue::new(&mut self.min_island_area)
+                        .speed(0.1)
+                        .clamp_range(0.0..=10_000.0)
+                        .suffix(" m²"),
+                )
+                .on_hover_text(
+                    "Islands reaching the tile border are always kept, and islands \
+                     joined by off-mesh connections are measured together",
+                )
+                .changed();
+        });
+        ui.horizontal(|ui| {
+            match self.keep_island_at {
+                Some(p) => ui.label(format!(
+                    "Keeping island at ({:.1}, {:.1}, {:.1})",
+                    p.x, p.y, p.z
+                )),
+                None => ui.label("Keeping all islands"),
+            };
+            if ui
+                .add_enabled(
+                    self.test_tool.start.is_some(),
+                    egui::Button::new("Use test start"),
+                )
+                .on_hover_text("Keep only the island under the test tool's start point")
+                .clicked()
+            {
+                self.keep_island_at = self.test_tool.start;
+                changed = true;
+            }
+            if self.keep_island_at.is_some() && ui.small_button("Clear").clicked() {
+                self.keep_island_at = None;
+                changed = true;
+            }
+        });
+        if changed {
+            self.navmesh_dirty = true;
+        }
+
+        if self.navmesh.is_none() {
+            ui.label("No navmesh built");
+            return;
+        }
+        let islands = &self.navmesh_islands;
+        ui.label(format!("{} islands", islands.len()));
+        egui::Grid::new("islands").striped(true).show(ui, |ui| {
+            ui.label("Polys");
+            ui.label("Area");
+            ui.end_row();
+            for island in islands.iter().take(LISTED) {
+                ui.label(island.polys.len().to_string());
+                ui.label(format!("{:.1} m²", island.area));
+                ui.end_row();
+            }
+        });
+        if islands.len() > LISTED {
+            ui.label(format!("and {} smaller", islands.len() - LISTED));
+        }
+    }
+
+    fn navmesh_toolbar(&mut self, ui: &mut egui::Ui) {
+        let build_label = if self.navmesh_dirty {
+            "Build NavMesh*"
+        } else {
+            "Build NavMesh"
+        };
+        if ui.button(build_label).clicked() {
+            self.build_navmesh();
+        }
+        if let Some(job) = &self.build_job {
+            ui.add(
+                egui::ProgressBar::new(job.progress())
+                    .desired_width(150.0)
+                    .show_percentage(),
+            );
+            if ui.button("Cancel").clicked() {
+                job.cancel();
+            }
+        }
+
+        ui.label("Show:");
+        ui.selectable_value(&mut self.navmesh_view, NavMeshView::Input, "Input");
+        ui.selectable_value(&mut self.navmesh_view, NavMeshView::NavMesh, "NavMesh");
+        ui.selectable_value(&mut self.navmesh_view, NavMeshView::Both, "Both");
+        ui.checkbox(&mut self.show_detail_mesh, "Detail");
+        egui::ComboBox::from_id_source("debug_layer")
+            .selected_text(self.debug_layer.name())
+            .show_ui(ui, |ui| {
+                for layer in DebugLayer::ALL {
+                    ui.selectable_value(&mut self.debug_layer, layer, layer.name());
+                }
+            });
+
+        if let Some(status) = &self.navmesh_status {
+            ui.separator();
+            ui.label(status);
+        }
+    }
+
+    // Nearest hit distance along the ray against all visible meshes
+    fn scene_raycast(&self, origin: Vec3, dir: Vec3) -> Option<f32> {
+        self.meshes
+            .iter()
+            .filter(|entry| entry.visible)
+            .filter_map(|entry| entry.bvh.raycast(origin, dir))
+            .map(|hit| hit.t)
+            .min_by(f32::total_cmp)
+    }
+
+    // Select the input triangle or navmesh polygon under the pointer,
+    // whichever is nearer among those on show
+    fn pick(&mut self, pointer: Pos2, rect: egui::Rect) {
+        let (origin, dir) = screen_ray(pointer, &self.camera, rect);
+        let mut best: Option<(Picked, f32)> = None;
+
+        if self.navmesh_view != NavMeshView::NavMesh {
+            let end = origin + dir * PICK_DISTANCE;
+            for (mesh, entry) in self.meshes.iter().enumerate() {
+                if !entry.visible {
+                    continue;
+                }
+                if let Some((tri, t)) = entry.chunky.raycast(&entry.mesh, origin, end) {
+                    let distance = t * PICK_DISTANCE;
+                    if best.is_none_or(|(_, best_distance)| distance < best_distance) {
+                        best = Some((Picked::InputTri { mesh, tri }, distance));
+                    }
+                }
+            }
+        }
+        if self.navmesh_view != NavMeshView::Input {
+            // The navmesh lies on the input it was built from, so it wins
+            // when the two are about as near
+            const TIE_DISTANCE: f32 = 0.05;
+            let hit = self
+                .navmesh_detail
+                .as_ref()
+                .and_then(|detail| detail.raycast(origin, dir))
+                .filter(|&(_, distance)| distance <= PICK_DISTANCE);
+            if let Some((poly, distance)) = hit {
+                if best.is_none_or(|(_, best_distance)| distance <= best_distance + TIE_DISTANCE) {
+                    best = Some((Picked::NavMeshPoly { poly }, distance));
+                }
+            }
+        }
+
+        self.picked = best.map(|(picked, _)| picked);
+    }
+
+    // Connection and end (0 start, 1 end) drawn under the pointer
+    fn off_mesh_endpoint_at(&self, pointer: Pos2, rect: egui::Rect) -> Option<(usize, usize)> {
+        const PICK_RADIUS: f32 = 10.0;
+        self.off_mesh_connections
+            .iter()
+            .enumerate()
+            .find_map(|(i, con)| {
+                [con.start, con.end]
+                    .iter()
+                    .position(|&p| {
+                        pos_to_screen(p, &self.camera, rect)
+                            .is_some_and(|screen| screen.distance(pointer) < PICK_RADIUS)
+                    })
+                    .map(|end| (i, end))
+            })
+    }
+
+    // Move the dragged end of a connection to the surface under the pointer
+    fn drag_off_mesh_endpoint(&mut self, pointer: Pos2, rect: egui::Rect) {
+        let Some((i, end)) = self.off_mesh_tool.dragging else {
+            return;
+        };
+        let (origin, dir) = screen_ray(pointer, &self.camera, rect);
+        let Some(t) = self.scene_raycast(origin, dir) else {
+            return;
+        };
+        if let Some(con) = self.off_mesh_connections.get_mut(i) {
+            let point = if end == 0 {
+                &mut con.start
+            } else {
+                &mut con.end
+            };
+            *point = origin + dir * t;
+        }
+    }
+
+    // Place connection endpoints, or select an existing connection by
+    // clicking near one of its endpoints when not authoring
+    fn handle_off_mesh_click(&mut self, pointer: Pos2, rect: egui::Rect) {
+        if !self.off_mesh_tool.enabled {
+            self.off_mesh_tool.selected = self.off_mesh_endpoint_at(pointer, rect).map(|(i, _)| i);
+            return;
+        }
+
+        let (origin, dir) = screen_ray(pointer, &self.camera, rect);
+        let Some(t) = self.scene_raycast(origin, dir) else {
+            return;
+        };
+        let hit = origin + dir * t;
+
+        let tool = &mut self.off_mesh_tool;
+        match tool.pending_start.take() {
+            None => tool.pending_start = Some(hit),
+            Some(start) => {
+                self.off_mesh_connections.push(OffMeshConnection {
+                    start,
+                    end: hit,
+                    radius: tool.radius,
+                    bidirectional: tool.bidirectional,
+                    area: tool.area,
+                });
+                tool.selected = Some(self.off_mesh_connections.len() - 1);
+                self.navmesh_dirty = true;
+            }
+        }
+    }
+
+    // Add a point of the volume being placed; clicking the first point
+    // again closes it
+    fn handle_convex_volume_click(&mut self, pointer: Pos2, rect: egui::Rect) {
+        const CLOSE_RADIUS: f32 = 10.0;
+        let tool = &self.convex_volume_tool;
+        let closes = tool.points.len() >= 3
+            && pos_to_screen(tool.points[0], &self.camera, rect)
+                .is_some_and(|screen| screen.distance(pointer) < CLOSE_RADIUS);
+        if closes {
+            self.close_convex_volume();
+            return;
+        }
+
+        let (origin, dir) = screen_ray(pointer, &self.camera, rect);
+        if let Some(t) = self.scene_raycast(origin, dir) {
+            self.convex_volume_tool.points.push(origin + dir * t);
+        }
+    }
+
+    fn handle_crowd_click(&mut self, pointer: Pos2, rect: egui::Rect) {
+        let (origin, dir) = screen_ray(pointer, &self.camera, rect);
+        let Some(t) = self.scene_raycast(origin, dir) else {
+            return;
+        };
+        let hit = origin + dir * t;
+        let (Some(crowd), Some(nav_mesh)) = (&mut self.crowd, &self.runtime_navmesh) else {
+            return;
+        };
+        let tool = &mut self.crowd_tool;
+
+        match tool.click {
+            CrowdClick::PlaceAgent => {
+                let clicked = crowd.agents().find_map(|(id, agent)| {
+                    let mut d = agent.pos() - hit;
+                    d.y = 0.0;
+                    (d.length() < agent.params.radius).then_some(id)
+                });
+                if let Some(id) = clicked {
+                    tool.selected = Some(id);
+                    return;
+                }
+                match crowd.add_agent(nav_mesh, hit, tool.params) {
+                    Ok(Some(id)) => {
+                        if let Some(target) = tool.target {
+                            crowd.request_move_target(id, target);
+                        }
+                        tool.selected = Some(id);
+                    }
+                    Ok(None) => {}
+                    Err(err) => self.navmesh_status = Some(format!("Adding agent failed: {}", err)),
+                }
+            }
+            CrowdClick::SetTarget => {
+                tool.target = Some(hit);
+                let ids: Vec<usize> = crowd.agents().map(|(id, _)| id).collect();
+                for id in ids {
+                    crowd.request_move_target(id, hit);
+                }
+            }
+        }
+    }
+
+    // Left click moves the start point, shift-click the end point
+    fn handle_test_click(&mut self, pointer: Pos2, rect: egui::Rect, shift: bool) {
+        let (origin, dir) = screen_ray(pointer, &self.camera, rect);
+        let Some(t) = self.scene_raycast(origin, dir) else {
+            return;
+        };
+        let hit = origin + dir * t;
+        let tool = &mut self.test_tool;
+        if shift {
+            tool.end = Some(hit);
+        } else {
+            tool.start = Some(hit);
+        }
+        tool.update(self.runtime_navmesh.as_ref());
+    }
+
+    // Advance the crowd by one frame of `dt` seconds
+    fn step_crowd(&mut self, dt: f32) {
+        let (Some(crowd), Some(nav_mesh)) = (&mut self.crowd, &self.runtime_navmesh) else {
+            return;
+        };
+        // Long frames, e.g. while dragging the window, would tunnel agents
+        if let Err(err) = crowd.update(nav_mesh, dt.min(0.1), self.crowd_tool.selected) {
+            self.navmesh_status = Some(format!("Crowd update failed: {}", err));
+            self.crowd_tool.running = false;
+        }
+    }
+
+    fn close_convex_volume(&mut self) {
+        let tool = &mut self.convex_volume_tool;
+        let points = std::mem::take(&mut tool.points);
+        if let Some(vol) = ConvexVolume::from_points(&points, tool.height, tool.descent, tool.area)
+        {
+            self.convex_volumes.push(vol);
+            tool.selected = Some(self.convex_volumes.len() - 1);
+            self.navmesh_dirty = true;
+        }
+    }
+
+    fn load_report_window(&mut self, ctx: &egui::Context) {
+        const MAX_SHOWN: usize = 10;
+        let Some(report) = &self.load_report else {
+            return;
+        };
+
+        let mut open = true;
+        let mut dismissed = false;
+        egui::Window::new("Load problems")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.strong(&report.title);
+                for message in report.messages.iter().take(MAX_SHOWN) {
+                    ui.label(message);
+                }
+                if report.messages.len() > MAX_SHOWN {
+                    ui.label(format!(
+                        "... and {} more",
+                        report.messages.len() - MAX_SHOWN
+                    ));
+                }
+                dismissed = ui.button("Close").clicked();
+            });
+        if !open || dismissed {
+            self.load_report = None;
+        }
+    }
+
+    fn scene_panel(&mut self, ui: &mut egui::Ui) {
+        let mut changed = false;
+        let mut remove = None;
+
+        for (i, entry) in self.meshes.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                let name = entry.name();
+                changed |= ui.checkbox(&mut entry.visible, "").changed();
+                let selected = self.selected_mesh == Some(i);
+                if ui
+                    .selectable_label(selected, name)
+                    .on_hover_text("Select to frame with F")
+                    .clicked()
+                {
+                    self.selected_mesh = (!selected).then_some(i);
+                }
+                ui.label(format!("{} tris", entry.mesh.tri_count()))
+                    .on_hover_text(entry.path.display().to_string());
+
+                let mut tinted = entry.color_override.is_some();
+                if ui.checkbox(&mut tinted, "Tint").changed() {
+                    entry.color_override = tinted.then_some(Vec4::new(0.4, 0.8, 1.0, 1.0));
+                }
+                if let Some(color) = &mut entry.color_override {
+                    let mut rgba = color.to_array();
+                    if ui.color_edit_button_rgba_unmultiplied(&mut rgba).changed() {
+                        *color = Vec4::from_array(rgba);
+                    }
+                }
+                if ui.small_button("Remove").clicked() {
+                    remove = Some(i);
+                }
+            });
+        }
+
+        if let Some(i) = remove {
+            self.meshes.remove(i);
+            self.selected_mesh = match self.selected_mesh {
+                Some(selected) if selected == i => None,
+                Some(selected) if selected > i => Some(selected - 1),
+                selected => selected,
+            };
+            changed = true;
+        }
+        if changed {
+            self.scene_changed();
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Max meshes:");
+            ui.add(egui::DragValue::new(&mut self.max_meshes).clamp_range(1..=256));
+        });
+    }
+
+    fn off_mesh_panel(&mut self, ui: &mut egui::Ui) {
+        let tool = &mut self.off_mesh_tool;
+
+        if ui
+            .checkbox(&mut tool.enabled, "Place connections")
+            .changed()
+        {
+            tool.pending_start = None;
+            if tool.enabled {
+                self.convex_volume_tool.enabled = false;
+                self.convex_volume_tool.points.clear();
+                self.crowd_tool.enabled = false;
+                self.test_tool.enabled = false;
+            }
+        }
+        ui.horizontal(|ui| {
+            ui.label("Radius:");
+            ui.add(
+                egui::DragValue::new(&mut tool.radius)
+                    .speed(0.05)
+                    .clamp_range(0.05..=10.0),
+            );
+            ui.checkbox(&mut tool.bidirectional, "Bidirectional");
+            ui.label("Area:");
+            ui.add(egui::DragValue::new(&mut tool.area).clamp_range(0..=63));
+        });
+        if tool.enabled {
+            ui.label(if tool.pending_start.is_some() {
+                "Click the end point"
+            } else {
+                "Click the start point"
+            });
+        }
+
+        for (i, con) in self.off_mesh_connections.iter().enumerate() {
+            let text = format!(
+                "#{} area {} {}",
+                i,
+                con.area,
+                if con.bidirectional { "<->" } else { "->" }
+            );
+            if ui
+                .selectable_label(tool.selected == Some(i), text)
+                .clicked()
+            {
+                tool.selected = Some(i);
+            }
+        }
+        if let Some(con) = tool
+            .selected
+            .and_then(|i| self.off_mesh_connections.get_mut(i))
+        {
+            let mut changed = false;
+            ui.horizontal(|ui| {
+                ui.label("Radius:");
+                changed |= ui
+                    .add(
+                        egui::DragValue::new(&mut con.radius)
+                            .speed(0.05)
+                            .clamp_range(0.05..=10.0),
+                    )
+                    .changed();
+                changed |= ui
+                    .checkbox(&mut con.bidirectional, "Bidirectional")
+                    .changed();
+                ui.label("Area:");
+                changed |= ui
+                    .add(egui::DragValue::new(&mut con.area).clamp_range(0..=63))
+                    .changed();
+            });
+            let delete = ui
+                .horizontal(|ui| {
+                    if ui.button("Reverse").clicked() {
+                        std::mem::swap(&mut con.start, &mut con.end);
+                        changed = true;
+                    }
+                    ui.button("Delete selected").clicked()
+                })
+                .inner;
+            if delete {
+                self.off_mesh_connections
+                    .remove(tool.selected.take().unwrap());
+                changed = true;
+            }
+            if changed {
+                self.navmesh_dirty = true;
+            }
+        }
+        ui.label("Drag the ends of a connection to move them");
+        ui.checkbox(&mut tool.show_baked, "Show baked connections")
+            .on_hover_text("Arcs as linked into the navmesh; unlinked ends are red");
+    }
+
+    fn jump_link_panel(&mut self, ui: &mut egui::Ui) {
+        let config = &mut self.jump_link_config;
+        ui.horizontal(|ui| {
+            ui.label("Max drop:");
+            ui.add(
+                egui::DragValue::new(&mut config.max_drop)
+                    .speed(0.1)
+                    .clamp_range(0.1..=50.0),
+            );
+            ui.label("Max gap:");
+            ui.add(
+                egui::DragValue::new(&mut config.max_gap)
+                    .speed(0.1)
+                    .clamp_range(0.1..=50.0),
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.label("Spacing:");
+            ui.add(
+                egui::DragValue::new(&mut config.sample_spacing)
+                    .speed(0.05)
+                    .clamp_range(0.1..=10.0),
+            );
+            ui.label("Area:");
+            ui.add(egui::DragValue::new(&mut config.area).clamp_range(0..=63));
+        });
+
+        let find = ui.add_enabled(
+            self.runtime_navmesh.is_some(),
+            egui::Button::new("Find jump links"),
+        );
+        if find.clicked() {
+            self.find_jump_links();
+        }
+
+        let mut accepted = None;
+        for (i, candidate) in self.jump_link_candidates.iter().enumerate() {
+            ui.horizontal(|ui| {
+                let kind = match candidate.kind {
+                    JumpKind::Down => "down",
+                    JumpKind::Across => "across",
+                };
+                let con = &candidate.connection;
+                ui.label(format!(
+                    "#{} {} {:.1}m",
+                    i,
+                    kind,
+                    con.start.distance(con.end)
+                ));
+                if ui.small_button("Accept").clicked() {
+                    accepted = Some(i);
+                }
+            });
+        }
+        if let Some(i) = accepted {
+            let candidate = self.jump_link_candidates.remove(i);
+            self.off_mesh_connections.push(candidate.connection);
+            self.navmesh_dirty = true;
+        }
+        if !self.jump_link_candidates.is_empty() {
+            ui.horizontal(|ui| {
+                if ui.button("Accept all").clicked() {
+                    self.off_mesh_connections.extend(
+                        self.jump_link_candidates
+                            .drain(..)
+                            .map(|candidate| candidate.connection),
+                    );
+                    self.navmesh_dirty = true;
+                }
+                if ui.button("Discard").clicked() {
+                    self.jump_link_candidates.clear();
+                }
+            });
+        }
+    }
+
+    // Run the annotation pass over the runtime navmesh, testing jump
+    // trajectories against the visible scene
+    fn find_jump_links(&mut self) {
+        let Some(nav_mesh) = &self.runtime_navmesh else {
+            return;
+        };
+        // Agent dimensions come from the bake
+        if let Some(tile) = nav_mesh.tiles().next() {
+            let header = &tile.data.header;
+            self.jump_link_config.agent_radius = header.walkable_radius;
+            self.jump_link_config.agent_height = header.walkable_height;
+            self.jump_link_config.agent_climb = header.walkable_climb;
+        }
+        self.jump_link_candidates = build_jump_links(nav_mesh, &self.jump_link_config, |a, b| {
+            self.scene_raycast(a, b - a).is_none_or(|t| t >= 1.0)
+        });
+        self.navmesh_status = Some(format!(
+            "Found {} jump link candidates",
+            self.jump_link_candidates.len()
+        ));
+    }
+
+    fn convex_volume_panel(&mut self, ui: &mut egui::Ui) {
+        let tool = &mut self.convex_volume_tool;
+
+        if ui.checkbox(&mut tool.enabled, "Place volumes").changed() {
+            tool.points.clear();
+            if tool.enabled {
+                self.off_mesh_tool.enabled = false;
+                self.off_mesh_tool.pending_start = None;
+                self.crowd_tool.enabled = false;
+                self.test_tool.enabled = false;
+            }
+        }
+        ui.horizontal(|ui| {
+            ui.label("Height:");
+            ui.add(
+                egui::DragValue::new(&mut tool.height)
+                    .speed(0.1)
+                    .clamp_range(0.1..=100.0),
+            );
+            ui.label("Descent:");
+            ui.add(
+                egui::DragValue::new(&mut tool.descent)
+                    .speed(0.1)
+                    .clamp_range(0.0..=100.0),
+            );
+            ui.label("Area:");
+            ui.add(egui::DragValue::new(&mut tool.area).clamp_range(0..=63));
+        });
+        if tool.enabled {
+            ui.label(format!(
+                "Click points on the mesh, then the first one again to close ({} placed)",
+                tool.points.len()
+            ));
+            let can_close = tool.points.len() >= 3;
+            let (close, clear) = ui
+                .horizontal(|ui| {
+                    let close = ui
+                        .add_enabled(can_close, egui::Button::new("Close volume"))
+                        .clicked();
+                    (close, ui.button("Clear points").clicked())
+                })
+                .inner;
+            if close {
+                self.close_convex_volume();
+            }
+            if clear {
+                self.convex_volume_tool.points.clear();
+            }
+        }
+
+        ui.horizontal(|ui| {
+            let filters = [("Volumes", &["volumes"][..])];
+            if ui.button("Load volumes").clicked() {
+                self.file_dialogs
+                    .open(ui.ctx(), FilePurpose::Volumes, &filters);
+            }
+            if ui.button("Save volumes").clicked() {
+                // Next to the first mesh, where loading it picks them up
+                let default = self
+                    .meshes
+                    .iter()
+                    .find(|m| !m.path.as_os_str().is_empty())
+                    .map_or_else(|| PathBuf::from("scene.volumes"), |m| volumes_path(&m.path));
+                if let Some(path) = file_dialog::save_path(&filters, &default) {
+                    let text = volumes_to_text(&self.convex_volumes);
+                    if let Err(err) = file_dialog::write_file(&path, text.as_bytes()) {
+                        println!("Failed to save {}: {}", path.display(), err);
+                    }
+                }
+            }
+        });
+
+        let tool = &mut self.convex_volume_tool;
+        for (i, vol) in self.convex_volumes.iter().enumerate() {
+            let text = format!("#{} area {} ({} verts)", i, vol.area, vol.verts.len());
+            if ui
+                .selectable_label(tool.selected == Some(i), text)
+                .clicked()
+            {
+                tool.selected = Some(i);
+            }
+        }
+        if let Some(vol) = tool.selected.and_then(|i| self.convex_volumes.get_mut(i)) {
+            let mut changed = false;
+            ui.horizontal(|ui| {
+                ui.label("Min:");
+                changed |= ui
+                    .add(egui::DragValue::new(&mut vol.hmin).speed(0.1))
+                    .changed();
+                ui.label("Max:");
+                changed |= ui
+                    .add(egui::DragValue::new(&mut vol.hmax).speed(0.1))
+                    .changed();
+                ui.label("Area:");
+                changed |= ui
+                    .add(egui::DragValue::new(&mut vol.area).clamp_range(0..=63))
+                    .changed();
+            });
+            vol.hmax = vol.hmax.max(vol.hmin);
+            if ui.button("Delete selected").clicked() {
+                self.convex_volumes.remove(tool.selected.take().unwrap());
+                changed = true;
+            }
+            if changed {
+                self.navmesh_dirty = true;
+            }
+        }
+    }
+
+    fn crowd_panel(&mut self, ui: &mut egui::Ui) {
+        let Some(crowd) = &mut self.crowd else {
+            ui.label("Build the navmesh to add agents");
+            return;
+        };
+        let tool = &mut self.crowd_tool;
+
+        if ui.checkbox(&mut tool.enabled, "Edit crowd").changed() && tool.enabled {
+            self.off_mesh_tool.enabled = false;
+            self.off_mesh_tool.pending_start = None;
+            self.convex_volume_tool.enabled = false;
+            self.convex_volume_tool.points.clear();
+            self.test_tool.enabled = false;
+        }
+        ui.horizontal(|ui| {
+            ui.label("Click:");
+            ui.selectable_value(&mut tool.click, CrowdClick::PlaceAgent, "Place agent");
+            ui.selectable_value(&mut tool.click, CrowdClick::SetTarget, "Set target");
+        });
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut tool.running, "Run");
+            ui.label(format!("{} agent(s)", crowd.agent_count()));
+            if ui.button("Clear").clicked() {
+                let ids: Vec<usize> = crowd.agents().map(|(id, _)| id).collect();
+                for id in ids {
+                    crowd.remove_agent(id);
+                }
+                tool.selected = None;
+                tool.target = None;
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Max speed:");
+            ui.add(
+                egui::DragValue::new(&mut tool.params.max_speed)
+                    .speed(0.05)
+                    .clamp_range(0.1..=20.0),
+            );
+            ui.label("Separation:");
+            ui.add(
+                egui::DragValue::new(&mut tool.params.separation_weight)
+                    .speed(0.05)
+                    .clamp_range(0.0..=20.0),
+            );
+        });
+        egui::ComboBox::from_label("Avoidance")
+            .selected_text(tool.params.avoidance.name())
+            .show_ui(ui, |ui| {
+                for quality in AvoidanceQuality::ALL {
+                    ui.selectable_value(&mut tool.params.avoidance, quality, quality.name());
+                }
+            });
+        ui.label("Settings apply to agents placed afterwards");
+        ui.horizontal(|ui| {
+            ui.label("Draw:");
+            ui.checkbox(&mut tool.draw.corridors, "Corridors");
+            ui.checkbox(&mut tool.draw.velocities, "Velocities");
+            ui.checkbox(&mut tool.draw.boundaries, "Walls");
+            ui.checkbox(&mut tool.draw.avoidance_samples, "Samples");
+        });
+        if let Some(selected) = tool.selected {
+            if ui.button("Remove selected").clicked() {
+                crowd.remove_agent(selected);
+                tool.selected = None;
+            }
+        }
+    }
+
+    fn bookmarks_panel(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.bookmark_name).desired_width(120.0));
+            if ui.button("Add view").clicked() {
+                let name = match self.bookmark_name.trim() {
+                    "" => format!("View {}", self.bookmarks.len() + 1),
+                    name => name.to_string(),
+                };
+                self.bookmarks.push(self.camera.bookmark(name));
+                self.bookmark_name.clear();
+            }
+        });
+
+        let mut remove = None;
+        for (i, bookmark) in self.bookmarks.iter().enumerate() {
+            ui.horizontal(|ui| {
+                if ui.button(&bookmark.name).clicked() {
+                    self.camera.go_to(bookmark);
+                }
+                if ui.small_button("Remove").clicked() {
+                    remove = Some(i);
+                }
+            });
+        }
+        if let Some(i) = remove {
+            self.bookmarks.remove(i);
+        }
+    }
+
+    fn inspector_panel(&mut self, ui: &mut egui::Ui) {
+        match self.picked {
+            None => {
+                ui.label("Click a triangle or navmesh polygon to inspect it");
+            }
+            Some(Picked::InputTri { mesh, tri }) => {
+                let Some(entry) = self.meshes.get(mesh) else {
+                    return;
+                };
+                ui.label(format!("{}, triangle {}", entry.name(), tri));
+                let normal = entry.mesh.normals.get(tri).copied().unwrap_or(Vec3::ZERO);
+                let slope = normal.y.clamp(-1.0, 1.0).acos().to_degrees();
+                ui.label(format!("Slope: {:.1}°", slope));
+                let area = match entry.mesh.areas.get(tri) {
+                    Some(&area) => area.to_string(),
+                    None if slope <= self.bake_config.walkable_slope_angle => {
+                        "walkable".to_string()
+                    }
+                    None => "too steep".to_string(),
+                };
+                ui.label(format!("Area: {}", area));
+                for k in 0..3 {
+                    let index = entry.mesh.tris[tri * 3 + k];
+                    let v = entry.mesh.verts[index as usize];
+                    ui.label(format!(
+                        "Vertex {}: ({:.2}, {:.2}, {:.2})",
+                        index, v.x, v.y, v.z
+                    ));
+                }
+            }
+            Some(Picked::NavMeshPoly { poly }) => {
+                let Some(navmesh) = &self.navmesh else {
+                    return;
+                };
+                let verts = &navmesh.polys[poly];
+                let nv = verts
+                    .iter()
+                    .position(|&v| v == RC_MESH_NULL_IDX)
+                    .unwrap_or(verts.len());
+                ui.label(format!("Polygon {}, {} vertices", poly, nv));
+                ui.label(format!("Area: {}", navmesh.areas[poly]));
+                ui.label(format!("Region: {}", navmesh.regs[poly]));
+                ui.label(format!("Flags: {:#06x}", navmesh.flags[poly]));
+                let neighbours: Vec<String> = navmesh.neighbours[poly][..nv]
+                    .iter()
+                    .map(|&n| match n {
+                        RC_MESH_NULL_IDX => "-".to_string(),
+                        n if n & RC_MESH_PORTAL_FLAG != 0 => "portal".to_string(),
+                        n => n.to_string(),
+                    })
+                    .collect();
+                ui.label(format!("Neighbours: {}", neighbours.join(", ")));
+            }
+        }
+        if self.picked.is_some() && ui.button("Clear selection").clicked() {
+            self.picked = None;
+        }
+    }
+
+    fn nav_mesh_test_panel(&mut self, ui: &mut egui::Ui) {
+        if self.runtime_navmesh.is_none() {
+            ui.label("Build the navmesh to run queries");
+            return;
+        }
+        let tool = &mut self.test_tool;
+
+        if ui.checkbox(&mut tool.enabled, "Test navmesh").changed() && tool.enabled {
+            self.off_mesh_tool.enabled = false;
+            self.off_mesh_tool.pending_start = None;
+            self.convex_volume_tool.enabled = false;
+            self.convex_volume_tool.points.clear();
+            self.crowd_tool.enabled = false;
+        }
+        ui.label("Click sets the start, shift-click the end");
+        if ui.button("Clear").clicked() {
+            tool.start = None;
+            tool.end = None;
+            tool.update(self.runtime_navmesh.as_ref());
+        }
+
+        if let Some(err) = &tool.error {
+            ui.label(format!("Query failed: {}", err));
+            return;
+        }
+        let result = &tool.result;
+        for (label, point, nearest) in [
+            ("Start", tool.start, result.start),
+            ("End", tool.end, result.end),
+        ] {
+            let text = match (point, nearest) {
+                (None, _) => "not set".to_string(),
+                (Some(_), None) => "no polygon nearby".to_string(),
+                (Some(_), Some((r, p))) => {
+                    format!("poly {:#x} at ({:.2}, {:.2}, {:.2})", r, p.x, p.y, p.z)
+                }
+            };
+            ui.label(format!("{}: {}", label, text));
+        }
+        if result.start.is_some() && result.end.is_some() {
+            let partial = if result.path.partial {
+                " (partial)"
+            } else {
+                ""
+            };
+            ui.label(format!(
+                "Path: {} polygon(s){}, {} corner(s)",
+                result.path.polys.len(),
+                partial,
+                result.straight_path.len()
+            ));
+            if let Some(hit) = &result.raycast {
+                if hit.t >= 1.0 {
+                    ui.label("Raycast: reached the end");
+                } else {
+                    ui.label(format!("Raycast: hit a wall at t = {:.2}", hit.t));
+                }
+            }
+        }
+    }
+}
+
+// Polygon count per area id, ordered by id
+fn area_counts(navmesh: &PolyMesh) -> Vec<(u8, usize)> {
+    let mut counts = [0usize; AREA_COUNT];
+    for &area in &navmesh.areas {
+        counts[area as usize % AREA_COUNT] += 1;
+    }
+    counts
+        .iter()
+        .enumerate()
+        .filter(|(_, &count)| count > 0)
+        .map(|(area, &count)| (area as u8, count))
+        .collect()
+}
+
+// World-space ray through a screen position as (origin, normalized direction)
+fn screen_ray(pos: Pos2, camera: &Camera, rect: egui::Rect) -> (Vec3, Vec3) {
+    let inv_view_proj = (camera.projection_matrix() * camera.view_matrix()).inverse();
+    let ndc_x = (pos.x - rect.min.x) / rect.width() * 2.0 - 1.0;
+    let ndc_y = 1.0 - (pos.y - rect.min.y) / rect.height() * 2.0;
+
+    let near = inv_view_proj.project_point3(Vec3::new(ndc_x, ndc_y, -1.0));
+    let far = inv_view_proj.project_point3(Vec3::new(ndc_x, ndc_y, 1.0));
+    (near, (far - near).normalize())
+}
+
+// Clip-space vertex and the texture coordinate interpolated along with it
+type ClipVertex = (Vec4, Vec2);
+
+// Distance inside the near plane, z >= -w in OpenGL clip space
+fn near_distance(clip: Vec4) -> f32 {
+    clip.z + clip.w
+}
+
+// The part of a convex polygon in front of the near plane (Sutherland-Hodgman)
+fn clip_polygon_near(poly: &[ClipVertex]) -> Vec<ClipVertex> {
+    let mut clipped = Vec::with_capacity(poly.len() + 1);
+    for (i, &(a, uv_a)) in poly.iter().enumerate() {
+        let (b, uv_b) = poly[(i + 1) % poly.len()];
+        let (da, db) = (near_distance(a), near_distance(b));
+        if da >= 0.0 {
+            clipped.push((a, uv_a));
+        }
+        if (da >= 0.0) != (db >= 0.0) {
+            let t = da / (da - db);
+            clipped.push((a.lerp(b, t), uv_a + (uv_b - uv_a) * t));
+        }
+    }
+    clipped
+}
+
+// The part of a segment in front of the near plane
+fn clip_segment_near(a: Vec4, b: Vec4) -> Option<(Vec4, Vec4)> {
+    let (da, db) = (near_distance(a), near_distance(b));
+    let cut = || a.lerp(b, da / (da - db));
+    match (da >= 0.0, db >= 0.0) {
+        (true, true) => Some((a, b)),
+        (true, false) => Some((a, cut())),
+        (false, true) => Some((cut(), b)),
+        (false, false) => None,
+    }
+}
+
+// Screen position of a clip-space point in front of the camera. Points
+// off the view land outside `rect`, where the painter clips them.
+fn clip_to_screen(clip: Vec4, rect: egui::Rect) -> Pos2 {
+    let ndc = clip.truncate() / clip.w;
+    Pos2::new(
+        (ndc.x * 0.5 + 0.5) * rect.width() + rect.min.x,
+        (0.5 - ndc.y * 0.5) * rect.height() + rect.min.y,
+    )
+}
+
+fn pos_to_screen(pos: Vec3, camera: &Camera, rect: egui::Rect) -> Option<Pos2> {
+    let view_proj = camera.projection_matrix() * camera.view_matrix();
+    // Convert Vec3 to Vec4 for clip space
+    let clip_pos = view_proj.project_point3(pos);
+    let clip_pos = Vec4::new(clip_pos.x, clip_pos.y, clip_pos.z, 1.0);
+
+    // Handle near plane clipping - if point is behind or very close to camera.
+    // Orthographic projection keeps w at 1, so there is nothing to guard against.
+    if !camera.is_orthographic() && clip_pos.z <= 0.001 {
+        return None;
+    }
+
+    // Perspective divide
+    let w = clip_pos.w;
+    let ndc = Vec3::new(clip_pos.x / w, clip_pos.y / w, clip_pos.z / w);
+
+    // More lenient frustum culling - allow points slightly outside the frustum
+    // This helps prevent lines from disappearing near screen edges
+    const MARGIN: f32 = 0.2; // 20% margin
+    if ndc.x < -1.0 - MARGIN
+        || ndc.x > 1.0 + MARGIN
+        || ndc.y < -1.0 - MARGIN
+        || ndc.y > 1.0 + MARGIN
+        || ndc.z > 1.0 + MARGIN
+    {
+        return None;
+    }
+
+    // Clamp coordinates to screen bounds
+    let x = (ndc.x * 0.5 + 0.5).clamp(0.0, 1.0) * rect.width() + rect.min.x;
+    let y = (1.0 - (ndc.y * 0.5 + 0.5)).clamp(0.0, 1.0) * rect.height() + rect.min.y;
+
+    Some(Pos2::new(x, y))
+}
+
+impl eframe::App for MeshViewerApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.poll_load_job();
+        self.poll_build_job();
+        self.update_capture(ctx);
+        if self.build_job.is_some() {
+            // Nothing else wakes the UI while the bake runs
+            ctx.request_repaint();
+        }
+        // Files dropped on the window open as if picked in a dialog. The
+        // browser drops their contents rather than a path.
+        let dropped: Vec<PickedFile> = ctx.input(|i| {
+            i.raw
+                .dropped_files
+                .iter()
+                .filter_map(|file| match (&file.path, &file.bytes) {
+                    (Some(path), _) => Some(PickedFile::on_disk(path.clone())),
+                    (None, Some(bytes)) => Some(PickedFile::in_memory(&file.name, bytes.to_vec())),
+                    (None, None) => None,
+                })
+                .collect()
+        });
+        for file in dropped {
+            self.open_file(file);
+        }
+        self.open_picked_files();
+        if self.load_job.is_none() {
+            if let Some(file) = self.load_queue.pop_front() {
+                self.load_mesh(ctx, file);
+            }
+        }
+        self.load_report_window(ctx);
+        self.file_drop_overlay(ctx);
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            // Add file picker
+            ui.horizontal(|ui| {
+                let can_add = self.can_add_mesh();
+                if ui
+                    .add_enabled(can_add, egui::Button::new("Load mesh"))
+                    .on_disabled_hover_text("Scene is full; remove a mesh first")
+                    .clicked()
+                {
+                    let supported = mesh_import::supported_extensions();
+                    let mut filters = vec![("All supported", &supported[..])];
+                    filters.extend(IMPORTERS.iter().map(|i| (i.name(), i.extensions())));
+                    self.file_dialogs
+                        .open(ui.ctx(), FilePurpose::Scene, &filters);
+                }
+                if ui.button("Load project").clicked() {
+                    let filters = [("Project", &[PROJECT_EXTENSION][..])];
+                    self.file_dialogs
+                        .open(ui.ctx(), FilePurpose::Scene, &filters);
+                }
+                let mut reopen = None;
+                ui.menu_button("Recent", |ui| {
+                    if self.recent_files.paths().is_empty() {
+                        ui.label("No recent files");
+                    }
+                    for path in self.recent_files.paths() {
+                        let name = path
+                            .file_name()
+                            .map_or_else(|| path.display().to_string(), |n| {
+                                n.to_string_lossy().into_owned()
+                            });
+                        if ui
+                            .button(name)
+                            .on_hover_text(path.display().to_string())
+                            .clicked()
+                        {
+                            reopen = Some(path.clone());
+                            ui.close_menu();
+                        }
+                    }
+                });
+                if let Some(path) = reopen {
+                    self.open_file(PickedFile::on_disk(path));
+                }
+                if ui.button("Save project").clicked() {
+                    let filters = [("Project", &[PROJECT_EXTENSION][..])];
+                    let name = self
+                        .project_path
+                        .as_ref()
+                        .and_then(|p| p.file_name())
+                        .map_or_else(|| format!("scene.{}", PROJECT_EXTENSION).into(), PathBuf::from);
+                    if let Some(path) = file_dialog::save_path(&filters, &name) {
+                        self.save_project(path);
+                    }
+                }
+
+                if let Some(job) = &self.load_job {
+                    ui.label(format!("Loading: {}", job.path.display()));
+                    ui.add(
+                        egui::ProgressBar::new(job.progress)
+                            .desired_width(150.0)
+                            .show_percentage(),
+                    );
+                } else {
+                    ui.label(format!("{} mesh(es) loaded", self.meshes.len()));
+                }
+
+                ui.separator();
+
+                if self.camera.is_top_down() {
+                    if ui.button("Exit top view").clicked() {
+                        self.camera.exit_top_down();
+                    }
+                } else if ui.button("Top view").clicked() {
+                    let (min, max) = self.scene_bounds();
+                    self.camera.enter_top_down(min, max);
+                }
+
+                let projection_label = if self.camera.is_orthographic() {
+                    "Perspective"
+                } else {
+                    "Orthographic"
+                };
+                if ui.button(projection_label).clicked() {
+                    self.camera.toggle_projection();
+                }
+
+                let mut mode = self.camera.mode;
+                ui.selectable_value(&mut mode, CameraMode::Fly, "Fly");
+                ui.selectable_value(&mut mode, CameraMode::Orbit, "Orbit");
+                self.camera.set_mode(mode);
+
+                ui.separator();
+
+                self.capture_menu(ui);
+                ui.checkbox(&mut self.show_frame_stats, "Frame stats");
+                ui.checkbox(&mut self.depth_sort, "Depth sort");
+                ui.checkbox(&mut self.show_texture, "Show texture");
+                if self.show_texture {
+                    ui.add(
+                        egui::DragValue::new(&mut self.tex_scale)
+                            .speed(0.01)
+                            .clamp_range(0.01..=10.0)
+                            .prefix("scale "),
+                    );
+                }
+
+                ui.separator();
+
+                ui.label("Walkable Slope Angle:");
+                if ui
+                    .add(egui::Slider::new(
+                        &mut self.bake_config.walkable_slope_angle,
+                        0.0..=90.0,
+                    ))
+                    .changed()
+                {
+                    self.refresh_stats();
+                    self.navmesh_dirty = true;
+                }
+            });
+
+            ui.horizontal(|ui| self.navmesh_toolbar(ui));
+
+            egui::CollapsingHeader::new("View").show(ui, |ui| {
+                self.view_panel(ui);
+            });
+            egui::CollapsingHeader::new("Scene").show(ui, |ui| {
+                self.scene_panel(ui);
+            });
+
+            egui::CollapsingHeader::new("Stats").show(ui, |ui| {
+                let stats = &self.mesh_stats;
+                egui::Grid::new("mesh_stats").show(ui, |ui| {
+                    ui.label("Vertices:");
+                    ui.label(stats.vertex_count.to_string());
+                    ui.end_row();
+                    ui.label("Triangles:");
+                    ui.label(stats.triangle_count.to_string());
+                    ui.end_row();
+                    ui.label("Bounds:");
+                    let extents = stats.bounds_max - stats.bounds_min;
+                    ui.label(format!(
+                        "{:.2} x {:.2} x {:.2}",
+                        extents.x, extents.y, extents.z
+                    ));
+                    ui.end_row();
+                    ui.label("Total area:");
+                    ui.label(format!("{:.2}", stats.total_area));
+                    ui.end_row();
+                    ui.label("Walkable area:");
+                    ui.label(format!(
+                        "{:.2} ({:.1}%)",
+                        stats.walkable_area,
+                        stats.walkable_percentage()
+                    ));
+                    ui.end_row();
+                    ui.label("Triangle area:");
+                    ui.label(format!("{:.4} .. {:.4}", stats.min_area, stats.max_area));
+                    ui.end_row();
+                    ui.label("Min angle:");
+                    ui.label(format!("{:.1}°", stats.min_angle_deg));
+                    ui.end_row();
+                    ui.label("Mean aspect ratio:");
+                    ui.label(format!("{:.2}", stats.mean_aspect_ratio));
+                    ui.end_row();
+                    ui.label("Degenerate:");
+                    ui.label(stats.degenerate_count.to_string());
+                    ui.end_row();
+                });
+            });
+
+            egui::CollapsingHeader::new("Build stats").show(ui, |ui| {
+                self.build_stats_panel(ui);
+            });
+
+            egui::CollapsingHeader::new("Bake settings").show(ui, |ui| {
+                self.bake_settings_panel(ui);
+            });
+
+            egui::CollapsingHeader::new("Islands").show(ui, |ui| {
+                self.islands_panel(ui);
+            });
+
+            egui::CollapsingHeader::new("Area colors").show(ui, |ui| {
+                self.area_palette_panel(ui);
+            });
+
+            egui::CollapsingHeader::new("Off-mesh connections").show(ui, |ui| {
+                self.off_mesh_panel(ui);
+            });
+
+            egui::CollapsingHeader::new("Convex volumes").show(ui, |ui| {
+                self.convex_volume_panel(ui);
+            });
+
+            egui::CollapsingHeader::new("Jump links").show(ui, |ui| {
+                self.jump_link_panel(ui);
+            });
+
+            egui::CollapsingHeader::new("Crowd").show(ui, |ui| {
+                self.crowd_panel(ui);
+            });
+
+            egui::CollapsingHeader::new("Test navmesh").show(ui, |ui| {
+                self.nav_mesh_test_panel(ui);
+            });
+
+            egui::CollapsingHeader::new("Camera bookmarks").show(ui, |ui| {
+                self.bookmarks_panel(ui);
+            });
+
+            egui::CollapsingHeader::new("Inspector")
+                .default_open(true)
+                .show(ui, |ui| {
+                    self.inspector_panel(ui);
+                });
+
+            if self.crowd_tool.running && self.crowd.as_ref().is_some_and(|c| c.agent_count() > 0) {
+                self.step_crowd(ui.input(|i| i.stable_dt));
+                ctx.request_repaint();
+            }
+
+            let (chunks_culled, tris_over_budget) = self.draw_mesh();
+
+            self.frame_stats = FrameStats {
+                frame_ms: ui.input(|i| i.unstable_dt) * 1000.0,
+                tris_submitted: self.debug_draw.tris.len(),
+                tris_drawn: 0,
+                lines: self.debug_draw.lines.len(),
+                points: self.debug_draw.points.len(),
+                chunks_culled,
+                tris_over_budget,
+            };
+
+            let (rect, response) =
+                ui.allocate_exact_size(ui.available_size(), egui::Sense::click_and_drag());
+            self.camera.aspect = rect.width() / rect.height();
+            self.viewport_rect = rect;
+
+            // Only pay for a raycast when the wheel is actually turning
+            let cursor_depth = response
+                .hover_pos()
+                .filter(|_| ui.input(|i| i.raw_scroll_delta.y != 0.0))
+                .and_then(|pos| {
+                    let (origin, dir) = screen_ray(pos, &self.camera, rect);
+                    self.scene_raycast(origin, dir)
+                });
+            // Update camera before projecting anything
+            self.camera.update(ui, rect, cursor_depth);
+
+            // Connection ends can be dragged unless another tool owns clicks
+            let other_tool = self.convex_volume_tool.enabled
+                || self.crowd_tool.enabled
+                || self.test_tool.enabled;
+            if !other_tool && response.drag_started_by(egui::PointerButton::Primary) {
+                if let Some(origin) = ui.input(|i| i.pointer.press_origin()) {
+                    self.off_mesh_tool.dragging = self.off_mesh_endpoint_at(origin, rect);
+                    if let Some((i, _)) = self.off_mesh_tool.dragging {
+                        self.off_mesh_tool.selected = Some(i);
+                    }
+                }
+            }
+            if self.off_mesh_tool.dragging.is_some() {
+                if let Some(pointer) = response.interact_pointer_pos() {
+                    self.drag_off_mesh_endpoint(pointer, rect);
+                }
+                if response.drag_stopped() {
+                    self.off_mesh_tool.dragging = None;
+                    self.navmesh_dirty = true;
+                }
+            }
+
+            if response.clicked() {
+                if let Some(pointer) = response.interact_pointer_pos() {
+                    if self.convex_volume_tool.enabled {
+                        self.handle_convex_volume_click(pointer, rect);
+                    } else if self.crowd_tool.enabled {
+                        self.handle_crowd_click(pointer, rect);
+                    } else if self.test_tool.enabled {
+                        let shift = ui.input(|i| i.modifiers.shift);
+                        self.handle_test_click(pointer, rect, shift);
+                    } else {
+                        self.handle_off_mesh_click(pointer, rect);
+                        // Without a tool, clicks away from connections pick
+                        if !self.off_mesh_tool.enabled && self.off_mesh_tool.selected.is_none() {
+                            self.pick(pointer, rect);
+                        }
+                    }
+                }
+            }
+            // Delete in a text field edits the text, not the scene
+            if !ctx.wants_keyboard_input() && ui.input(|i| i.key_pressed(egui::Key::Delete)) {
+                if let Some(selected) = self.off_mesh_tool.selected.take() {
+                    self.off_mesh_connections.remove(selected);
+                }
+            }
+
+            // Reset camera position when R is pressed
+            if ui.input(|i| i.key_pressed(egui::Key::R)) {
+                let mode = self.camera.mode;
+                self.camera = Camera::new();
+                self.camera.set_mode(mode);
+            }
+
+            if ui.input(|i| i.key_pressed(egui::Key::F)) {
+                let (min, max) = self.focus_bounds();
+                self.camera.focus_on(min, max);
+            }
+
+            if ui.input(|i| i.key_pressed(egui::Key::Backtick)) {
+                self.show_frame_stats = !self.show_frame_stats;
+            }
+
+            // Clipped to the view, as clipped geometry can reach far outside it
+            let painter = &ui.painter_at(rect);
+            painter.rect_filled(rect, 0.0, to_color32(self.debug_draw.colors.background));
+
+            #[cfg(feature = "wgpu")]
+            let painted_on_gpu = self.paint_scene_gpu(painter, rect);
+            #[cfg(not(feature = "wgpu"))]
+            let painted_on_gpu = false;
+            if !painted_on_gpu {
+                if self.depth_sort {
+                    self.debug_draw
+                        .sort_back_to_front(self.camera.view_matrix());
+                }
+                // One mesh for everything, so the order above is the order
+                // drawn; untextured triangles sample a plain white texel
+                let checker = self.checker_texture(ctx).id();
+                let mut mesh = egui::Mesh::with_texture(checker);
+                let view_proj = self.camera.projection_matrix() * self.camera.view_matrix();
+                // Orthographic views keep everything in front of the camera
+                let clip_near = !self.camera.is_orthographic();
+                for tri in &self.debug_draw.tris {
+                    let corners = [(tri.0, tri.4), (tri.1, tri.5), (tri.2, tri.6)]
+                        .map(|(pos, uv)| (view_proj * pos.extend(1.0), uv));
+                    let poly = if clip_near {
+                        clip_polygon_near(&corners)
+                    } else {
+                        corners.to_vec()
+                    };
+                    if poly.len() < 3 {
+                        // Entirely behind the camera
+                        continue;
+                    }
+                    self.frame_stats.tris_drawn += 1;
+
+                    let textured = tri.7 && self.show_texture;
+                    let base = mesh.vertices.len() as u32;
+                    for &(clip, uv) in &poly {
+                        mesh.vertices.push(egui::epaint::Vertex {
+                            pos: clip_to_screen(clip, rect),
+                            uv: if textured { uv.to_pos2() } else { CHECKER_WHITE_UV },
+                            color: tri.3,
+                        });
+                    }
+                    for k in 2..poly.len() as u32 {
+                        mesh.add_triangle(base, base + k - 1, base + k);
+                    }
+                }
+                painter.add(egui::Shape::mesh(mesh));
+
+                for line in &self.debug_draw.lines {
+                    let (a, b) = (view_proj * line.0.extend(1.0), view_proj * line.1.extend(1.0));
+                    let segment = if clip_near {
+                        clip_segment_near(a, b)
+                    } else {
+                        Some((a, b))
+                    };
+                    if let Some((a, b)) = segment {
+                        painter.line_segment(
+                            [clip_to_screen(a, rect), clip_to_screen(b, rect)],
+                            (line.3, line.2),
+                        );
+                    }
+                }
+            }
+            // The GPU backend has no point pipeline, so points are always
+            // painted here, on top of the scene
+            self.paint_points(painter, rect);
+
+            if self.show_frame_stats {
+                self.paint_frame_stats(painter, rect);
+            }
+            if self.navmesh_view != NavMeshView::Input {
+                self.paint_area_legend(painter, rect);
+            }
+
+            // Add control instructions
+            ui.painter().text(
+                rect.min + egui::vec2(10.0, 10.0),
+                egui::Align2::LEFT_TOP,
+                "Controls:\nWASD - Move\nShift+Q/E - Down/Up\nQ/E - Roll\nRight Click + Drag - Look\nMiddle Drag - Orbit (orbit mode)\nShift+Middle Drag - Pan (orbit mode)\nScroll - Zoom\nF - Frame selection\nClick - Select triangle/polygon\nR - Reset Camera\n~ - Frame stats\nDelete - Remove selected connection",
+                egui::FontId::proportional(14.0),
+                Color32::WHITE,
+            );
+        });
+
+        // Settings edited in any panel this frame, open or collapsed, are
+        // rebuilt here once the UI is done with them
+        if self.navmesh_dirty && self.auto_rebuild {
+            self.build_navmesh();
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn run() -> Result<(), eframe::Error> {
+    let options = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default().with_inner_size([800.0, 600.0]),
+        #[cfg(feature = "wgpu")]
+        renderer: eframe::Renderer::Wgpu,
+        #[cfg(feature = "wgpu")]
+        depth_buffer: wgpu_draw::DEPTH_BUFFER_BITS,
+        ..Default::default()
+    };
+    eframe::run_native(
+        "Mesh Viewer",
+        options,
+        Box::new(|cc| Box::new(MeshViewerApp::new(cc))),
+    )
+}
+
+// Start the viewer in the browser, drawing into the canvas with id
+// `canvas_id`. Errors go to the browser console since there's no terminal.
+#[cfg(target_arch = "wasm32")]
+pub fn run_web(canvas_id: &str) {
+    let options = eframe::WebOptions {
+        #[cfg(feature = "wgpu")]
+        depth_buffer: wgpu_draw::DEPTH_BUFFER_BITS,
+        ..Default::default()
+    };
+    let canvas_id = canvas_id.to_owned();
+    wasm_bindgen_futures::spawn_local(async move {
+        let started = eframe::WebRunner::new()
+            .start(
+                &canvas_id,
+                options,
+                Box::new(|cc| Box::new(MeshViewerApp::new(cc))),
+            )
+            .await;
+        if let Err(err) = started {
+            web_sys::console::error_2(&"Failed to start the viewer:".into(), &err);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Two triangles sharing an edge, one upright and one leaning back
+    fn create_test_mesh() -> InputMesh {
+        let mut mesh = InputMesh::new();
+
+        // Create just two triangles that share an edge
+        mesh.verts = vec![
+            Vec3::new(0.0, 0.0, 0.0),  // Bottom vertex
+            Vec3::new(-1.0, 2.0, 0.0), // Top left
+            Vec3::new(1.0, 2.0, 0.0),  // Top right
+            Vec3::new(0.0, 1.0, 1.0),  // Back middle
+        ];
+
+        // Create two triangles
+        mesh.tris = vec![
+            0, 1, 2, // Front triangle
+            0, 2, 3, // Back triangle with steep slope
+        ];
+
+        // Calculate normals for both triangles
+        mesh.compute_normals();
+
+        mesh
+    }
+
+    #[test]
+    fn test_normals_are_per_triangle() {
+        let mesh = create_test_mesh();
+        assert!(mesh.validate().is_ok());
+        assert_eq!(mesh.normals.len(), 2);
+        assert!(mesh.normals[0].abs_diff_eq(Vec3::NEG_Z, 1e-6));
+        let back = Vec3::new(2.0, -1.0, 1.0).normalize();
+        assert!(mesh.normals[1].abs_diff_eq(back, 1e-6));
+    }
+
+    #[test]
+    fn test_view_matrix_follows_rotation() {
+        let mut camera = Camera::new();
+        camera.rotation = Quat::from_axis_angle(Vec3::Y, 0.7) * Quat::from_axis_angle(Vec3::X, 0.3);
+
+        // A point straight ahead of the camera lands on the view -Z axis
+        let ahead = camera.position + camera.forward() * 4.0;
+        let view_pos = camera.view_matrix().transform_point3(ahead);
+        assert!(view_pos.abs_diff_eq(Vec3::new(0.0, 0.0, -4.0), 1e-5));
+
+        let above = camera.position + camera.up();
+        let view_pos = camera.view_matrix().transform_point3(above);
+        assert!(view_pos.abs_diff_eq(Vec3::Y, 1e-5));
+    }
+
+    #[test]
+    fn test_triangles_sort_back_to_front() {
+        let camera = Camera::new();
+        let mut dd = EguiDebugDraw::new();
+        dd.begin(DU_DRAW_TRIS, 1.0);
+        // A near triangle, a far one, then one level with the near one
+        for (distance, color) in [(2.0, Vec4::X), (10.0, Vec4::Y), (2.0, Vec4::Z)] {
+            let centre = camera.position + camera.forward() * distance;
+            for offset in [Vec3::ZERO, camera.up(), camera.up() + camera.right()] {
+                dd.vertex(centre + offset * 0.1, color.truncate().extend(1.0));
+            }
+        }
+        dd.end();
+
+        dd.sort_back_to_front(camera.view_matrix());
+        let colors: Vec<Color32> = dd.tris.iter().map(|tri| tri.3).collect();
+        assert_eq!(colors, vec![Color32::GREEN, Color32::RED, Color32::BLUE]);
+    }
+
+    #[test]
+    fn test_lines_and_points_keep_their_size() {
+        let mut dd = EguiDebugDraw::new();
+        dd.begin(DU_DRAW_LINES, 3.0);
+        dd.vertex(Vec3::ZERO, Vec4::ONE);
+        dd.vertex(Vec3::X, Vec4::ONE);
+        dd.vertex(Vec3::Y, Vec4::ONE);
+        dd.vertex(Vec3::Z, Vec4::ONE);
+        dd.end();
+        dd.begin(DU_DRAW_POINTS, 6.0);
+        dd.vertex(Vec3::ONE, Vec4::new(1.0, 0.0, 0.0, 1.0));
+        dd.end();
+
+        assert_eq!(
+            dd.lines,
+            vec![
+                (Vec3::ZERO, Vec3::X, Color32::WHITE, 3.0),
+                (Vec3::Y, Vec3::Z, Color32::WHITE, 3.0),
+            ]
+        );
+        assert_eq!(dd.points, vec![(Vec3::ONE, Color32::RED, 6.0)]);
+        assert!(dd.tris.is_empty());
+    }
+
+    #[test]
+    fn test_near_plane_clipping() {
+        // Clip-space points in front of (z > -w) and behind the near plane
+        let front = Vec4::new(0.0, 0.0, 0.0, 1.0);
+        let behind = Vec4::new(0.0, 0.0, -3.0, 1.0);
+        let side = Vec4::new(1.0, 0.0, 0.0, 1.0);
+
+        // One corner behind turns the triangle into a quad, not nothing
+        let tri = [(front, Vec2::ZERO), (side, Vec2::X), (behind, Vec2::Y)];
+        let clipped = clip_polygon_near(&tri);
+        assert_eq!(clipped.len(), 4);
+        for (clip, _) in &clipped {
+            assert!(near_distance(*clip) >= -1e-6);
+        }
+        // The edge back to the front corner is cut on the plane, with the
+        // uv interpolated to match
+        let (clip, uv) = clipped[3];
+        assert!(clip.abs_diff_eq(Vec4::new(0.0, 0.0, -1.0, 1.0), 1e-6));
+        assert!((uv.y - 1.0 / 3.0).abs() < 1e-6);
+
+        assert!(clip_polygon_near(&[(behind, Vec2::ZERO); 3]).is_empty());
+
+        assert_eq!(
+            clip_segment_near(behind, front),
+            Some((Vec4::new(0.0, 0.0, -1.0, 1.0), front))
+        );
+        assert_eq!(clip_segment_near(behind, behind), None);
+    }
+
+    #[test]
+    fn test_orbit_keeps_focus() {
+        let mut camera = Camera::new();
+        camera.focus_on(Vec3::new(-1.0, 0.0, -1.0), Vec3::new(1.0, 2.0, 1.0));
+        let focus = Vec3::new(0.0, 1.0, 0.0);
+        assert_eq!(camera.focus, focus);
+        let distance = camera.position.distance(focus);
+        assert!(distance > 3.0_f32.sqrt(), "{}", distance);
+
+        // Orbiting circles the focus at the same distance, still facing it
+        for (dx, dy) in [(120.0, 0.0), (0.0, -80.0), (-300.0, 40.0)] {
+            camera.orbit(dx, dy);
+            assert!((camera.position.distance(focus) - distance).abs() < 1e-4);
+            let to_focus = (focus - camera.position).normalize();
+            assert!(to_focus.abs_diff_eq(camera.forward(), 1e-5));
+        }
+
+        // Pitch stops short of looking straight down
+        camera.orbit(0.0, 1000.0);
+        assert!(camera.forward().y.abs() < 0.99);
+    }
+
+    #[test]
+    fn test_dolly_stops_before_surface() {
+        let mut camera = Camera::new();
+        let rect = egui::Rect::from_min_size(Pos2::ZERO, egui::vec2(800.0, 600.0));
+        let start = camera.position;
+
+        // Scrolling in hard, many times, approaches but never crosses a
+        // surface 3 units ahead of the view centre
+        for _ in 0..100 {
+            let depth = 3.0 - camera.position.distance(start);
+            camera.dolly_to_cursor(rect.center(), rect, 1000.0, Some(depth));
+        }
+        let travelled = camera.position.distance(start);
+        assert!(travelled < 3.0 && travelled > 2.5, "{}", travelled);
+        assert!(camera.position.z < start.z);
+
+        // Scrolling out moves back along the same ray
+        camera.dolly_to_cursor(rect.center(), rect, -200.0, None);
+        assert!(camera.position.distance(start) < travelled);
+    }
 }
@@ -0,0 +1,412 @@
+use std::sync::Arc;
+
+use eframe::egui_wgpu::{self, CallbackResources, CallbackTrait, ScreenDescriptor};
+use eframe::wgpu;
+use eframe::wgpu::util::DeviceExt;
+use glam::{Mat4, Vec3, Vec4};
+
+use crate::debug_draw::{BatchedDebugDraw, DebugDrawPrimitive, PrimitiveBatch, PrimitiveBatcher};
+
+// Depth buffer eframe has to be asked for, in bits; the pipelines below
+// are built against the matching format
+pub const DEPTH_BUFFER_BITS: u8 = 32;
+const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+// Position, straight-alpha color, checker UV and 1 where textured, as
+// passed to the shader
+const VERTEX_FLOATS: usize = 10;
+
+const SHADER: &str = r#"
+struct Uniforms {
+    view_proj: mat4x4<f32>,
+};
+@group(0) @binding(0) var<uniform> uniforms: Uniforms;
+
+struct VertexOut {
+    @builtin(position) pos: vec4<f32>,
+    @location(0) color: vec4<f32>,
+    @location(1) uv: vec2<f32>,
+    @location(2) textured: f32,
+};
+
+@vertex
+fn vs_main(
+    @location(0) pos: vec3<f32>,
+    @location(1) color: vec4<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(3) textured: f32,
+) -> VertexOut {
+    var out: VertexOut;
+    out.pos = uniforms.view_proj * vec4<f32>(pos, 1.0);
+    out.color = color;
+    out.uv = uv;
+    out.textured = textured;
+    return out;
+}
+
+// Same pattern as `checker_shade`
+@fragment
+fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
+    if in.textured < 0.5 {
+        return in.color;
+    }
+    let f = fract(in.uv);
+    let shade = select(215.0 / 255.0, 1.0, (f.x < 0.5) == (f.y < 0.5));
+    return vec4<f32>(in.color.rgb * shade, in.color.a);
+}
+"#;
+
+// The camera matrices map depth to -1..1 like OpenGL; wgpu clips to 0..1
+const GL_TO_WGPU_DEPTH: Mat4 = Mat4::from_cols_array(&[
+    1.0, 0.0, 0.0, 0.0, //
+    0.0, 1.0, 0.0, 0.0, //
+    0.0, 0.0, 0.5, 0.0, //
+    0.0, 0.0, 0.5, 1.0,
+]);
+
+// Vertices and indices of one primitive type, collected over a frame and
+// drawn with a single indexed call
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DrawBatch {
+    vertices: Vec<f32>,
+    indices: Vec<u32>,
+}
+
+impl DrawBatch {
+    pub fn vertex_count(&self) -> usize {
+        self.vertices.len() / VERTEX_FLOATS
+    }
+
+    pub fn index_count(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    fn clear(&mut self) {
+        self.vertices.clear();
+        self.indices.clear();
+    }
+
+    fn push_vertex(&mut self, pos: Vec3, color: Vec4, uv: [f32; 2], textured: bool) -> u32 {
+        let index = self.vertex_count() as u32;
+        self.vertices.extend_from_slice(&pos.to_array());
+        self.vertices.extend_from_slice(&color.to_array());
+        self.vertices.extend_from_slice(&uv);
+        self.vertices.push(if textured { 1.0 } else { 0.0 });
+        index
+    }
+
+    fn vertex_bytes(&self) -> Vec<u8> {
+        self.vertices.iter().flat_map(|f| f.to_ne_bytes()).collect()
+    }
+
+    fn index_bytes(&self) -> Vec<u8> {
+        self.indices.iter().flat_map(|i| i.to_ne_bytes()).collect()
+    }
+}
+
+// DebugDraw backend that batches triangles and lines for the GPU. Quads
+// arrive as triangles from the batcher. Textured triangles get the checker
+// pattern in the shader. Points aren't supported and are dropped.
+#[derive(Debug, Clone, Default)]
+pub struct WgpuDebugDraw {
+    tris: DrawBatch,
+    lines: DrawBatch,
+    batcher: PrimitiveBatcher,
+    // Multiplied into every vertex color while set
+    pub tint: Option<Vec4>,
+}
+
+impl WgpuDebugDraw {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.tris.clear();
+        self.lines.clear();
+    }
+
+    pub fn tris(&self) -> &DrawBatch {
+        &self.tris
+    }
+
+    pub fn lines(&self) -> &DrawBatch {
+        &self.lines
+    }
+
+    // Paint callback drawing this frame's batches into `rect` of the egui
+    // window. The batches are copied, so drawing can go on for the next
+    // frame.
+    pub fn paint_callback(&self, rect: egui::Rect, view_proj: Mat4) -> egui::PaintCallback {
+        egui_wgpu::Callback::new_paint_callback(
+            rect,
+            DebugDrawCallback {
+                view_proj: GL_TO_WGPU_DEPTH * view_proj,
+                tris: Arc::new(self.tris.clone()),
+                lines: Arc::new(self.lines.clone()),
+            },
+        )
+    }
+}
+
+impl BatchedDebugDraw for WgpuDebugDraw {
+    fn batcher(&mut self) -> &mut PrimitiveBatcher {
+        &mut self.batcher
+    }
+
+    fn flush(&mut self, batch: PrimitiveBatch) {
+        let target = match batch.prim {
+            DebugDrawPrimitive::Tris | DebugDrawPrimitive::Quads => &mut self.tris,
+            DebugDrawPrimitive::Lines => &mut self.lines,
+            DebugDrawPrimitive::Points => return,
+        };
+        for v in &batch.vertices {
+            let color = self.tint.map_or(v.color, |tint| v.color * tint);
+            let index = target.push_vertex(v.pos, color, [v.uv.x, v.uv.y], batch.textured);
+            target.indices.push(index);
+        }
+    }
+}
+
+// GPU objects shared by every frame, kept in egui-wgpu's callback resources
+struct DebugDrawResources {
+    tri_pipeline: wgpu::RenderPipeline,
+    line_pipeline: wgpu::RenderPipeline,
+    uniforms: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    // (vertex buffer, index buffer) per batch, grown as needed
+    tri_buffers: Option<(wgpu::Buffer, wgpu::Buffer)>,
+    line_buffers: Option<(wgpu::Buffer, wgpu::Buffer)>,
+}
+
+// Create the pipelines for the window's render state. Call once at startup;
+// without it the paint callbacks draw nothing.
+pub fn init(render_state: &egui_wgpu::RenderState) {
+    let device = &render_state.device;
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("debug_draw"),
+        source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+    });
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("debug_draw"),
+        entries: &[wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::VERTEX,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }],
+    });
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("debug_draw"),
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+    });
+
+    let pipeline = |topology: wgpu::PrimitiveTopology, depth_write: bool| {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("debug_draw"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: (VERTEX_FLOATS * 4) as u64,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &wgpu::vertex_attr_array![
+                        0 => Float32x3,
+                        1 => Float32x4,
+                        2 => Float32x2,
+                        3 => Float32,
+                    ],
+                }],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: render_state.target_format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology,
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: depth_write,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        })
+    };
+
+    let uniforms = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("debug_draw_uniforms"),
+        size: 64,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("debug_draw"),
+        layout: &bind_group_layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: uniforms.as_entire_binding(),
+        }],
+    });
+
+    render_state
+        .renderer
+        .write()
+        .callback_resources
+        .insert(DebugDrawResources {
+            tri_pipeline: pipeline(wgpu::PrimitiveTopology::TriangleList, true),
+            // Lines lying on a surface would lose the depth test against
+            // it if they wrote depth as well
+            line_pipeline: pipeline(wgpu::PrimitiveTopology::LineList, false),
+            uniforms,
+            bind_group,
+            tri_buffers: None,
+            line_buffers: None,
+        });
+}
+
+struct DebugDrawCallback {
+    view_proj: Mat4,
+    tris: Arc<DrawBatch>,
+    lines: Arc<DrawBatch>,
+}
+
+// Write `batch` into `buffers`, replacing them when they are too small
+fn upload(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    buffers: &mut Option<(wgpu::Buffer, wgpu::Buffer)>,
+    batch: &DrawBatch,
+) {
+    if batch.is_empty() {
+        return;
+    }
+    let (vertices, indices) = (batch.vertex_bytes(), batch.index_bytes());
+    if let Some((vb, ib)) = buffers {
+        if vb.size() >= vertices.len() as u64 && ib.size() >= indices.len() as u64 {
+            queue.write_buffer(vb, 0, &vertices);
+            queue.write_buffer(ib, 0, &indices);
+            return;
+        }
+    }
+    let buffer = |contents: &[u8], usage: wgpu::BufferUsages| {
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("debug_draw_batch"),
+            contents,
+            usage: usage | wgpu::BufferUsages::COPY_DST,
+        })
+    };
+    *buffers = Some((
+        buffer(&vertices, wgpu::BufferUsages::VERTEX),
+        buffer(&indices, wgpu::BufferUsages::INDEX),
+    ));
+}
+
+impl CallbackTrait for DebugDrawCallback {
+    fn prepare(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        _screen_descriptor: &ScreenDescriptor,
+        _egui_encoder: &mut wgpu::CommandEncoder,
+        callback_resources: &mut CallbackResources,
+    ) -> Vec<wgpu::CommandBuffer> {
+        let Some(res) = callback_resources.get_mut::<DebugDrawResources>() else {
+            return Vec::new();
+        };
+        let matrix: Vec<u8> = self
+            .view_proj
+            .to_cols_array()
+            .iter()
+            .flat_map(|f| f.to_ne_bytes())
+            .collect();
+        queue.write_buffer(&res.uniforms, 0, &matrix);
+        upload(device, queue, &mut res.tri_buffers, &self.tris);
+        upload(device, queue, &mut res.line_buffers, &self.lines);
+        Vec::new()
+    }
+
+    fn paint<'a>(
+        &'a self,
+        _info: egui::PaintCallbackInfo,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        callback_resources: &'a CallbackResources,
+    ) {
+        let Some(res) = callback_resources.get::<DebugDrawResources>() else {
+            return;
+        };
+        render_pass.set_bind_group(0, &res.bind_group, &[]);
+        for (pipeline, buffers, batch) in [
+            (&res.tri_pipeline, &res.tri_buffers, &self.tris),
+            (&res.line_pipeline, &res.line_buffers, &self.lines),
+        ] {
+            let Some((vb, ib)) = buffers.as_ref().filter(|_| !batch.is_empty()) else {
+                continue;
+            };
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_vertex_buffer(0, vb.slice(..));
+            render_pass.set_index_buffer(ib.slice(..), wgpu::IndexFormat::Uint32);
+            render_pass.draw_indexed(0..batch.index_count() as u32, 0, 0..1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debug_draw::{
+        DebugDraw, DU_DRAW_LINES, DU_DRAW_POINTS, DU_DRAW_QUADS, DU_DRAW_TRIS,
+    };
+
+    #[test]
+    fn test_batches_index_primitives() {
+        let mut dd = WgpuDebugDraw::new();
+        dd.begin(DU_DRAW_TRIS, 1.0);
+        for x in 0..6 {
+            dd.vertex(Vec3::new(x as f32, 0.0, 0.0), Vec4::ONE);
+        }
+        dd.end();
+        dd.begin(DU_DRAW_QUADS, 1.0);
+        for x in 0..4 {
+            dd.vertex(Vec3::new(x as f32, 1.0, 0.0), Vec4::ONE);
+        }
+        dd.end();
+        dd.begin(DU_DRAW_LINES, 1.0);
+        // The last vertex has no partner and isn't drawn
+        for x in 0..3 {
+            dd.vertex(Vec3::new(x as f32, 2.0, 0.0), Vec4::ONE);
+        }
+        dd.end();
+        dd.begin(DU_DRAW_POINTS, 1.0);
+        dd.vertex(Vec3::ZERO, Vec4::ONE);
+        dd.end();
+
+        // The quad comes through as two triangles
+        assert_eq!(dd.tris().vertex_count(), 12);
+        assert_eq!(dd.tris().indices, (0..12).collect::<Vec<_>>());
+        assert_eq!(dd.lines().vertex_count(), 2);
+        assert_eq!(dd.lines().indices, vec![0, 1]);
+
+        dd.clear();
+        assert!(dd.tris().is_empty() && dd.lines().is_empty());
+    }
+}